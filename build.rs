@@ -7,27 +7,376 @@
 //! Cargo re-run the build script whenever `memory.x` is changed,
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
+//!
+//! It also minifies the web pages served by the firmware into `$OUT_DIR`
+//! (with the `gzip-assets` feature, next to a gzip copy served to the
+//! clients that accept it), generating the constants included by
+//! `src/assets.rs`, together with the table of the files in `static/`.
+//! The source pages stay readable.
+//!
+//! Finally it reads the network secrets (`WIFI_SSID`, `WIFI_PASSWORD`,
+//! `PICO_IP` and the optional backup networks) from the environment or from
+//! the git-ignored `.env` file and passes them to `src/configuration.rs`,
+//! so they are never committed. With the `https` feature it also copies
+//! the certificate and private key named by `HTTPS_CERT` and `HTTPS_KEY`
+//! (same sources) into `$OUT_DIR`, where `src/tls.rs` includes them.
 
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Web pages embedded in the firmware, relative to the crate root
+const ASSETS: [&str; 20] = [
+    "index.html",
+    "index.en.html",
+    "form.html",
+    "form.en.html",
+    "time.html",
+    "time.en.html",
+    "provision.html",
+    "provision.en.html",
+    "settings.html",
+    "settings.en.html",
+    "login.html",
+    "login.en.html",
+    "temperature.html",
+    "temperature.en.html",
+    "environment.html",
+    "environment.en.html",
+    "leds.html",
+    "leds.en.html",
+    "uart.html",
+    "uart.en.html",
+];
+
+/// Directory of the files served under `/static/`, relative to the crate root
+const STATIC_DIR: &str = "static";
+
+/// Secrets read from the environment or from `.env`: name of the variable,
+/// name of the `env!` constant used by `src/configuration.rs` and whether
+/// it is required (the optional ones default to empty)
+const SECRETS: [(&str, &str, bool); 7] = [
+    ("WIFI_SSID", "PICOWEB_WIFI_SSID", true),
+    ("WIFI_PASSWORD", "PICOWEB_WIFI_PASSWORD", true),
+    ("PICO_IP", "PICOWEB_IP_ADDRESS", true),
+    ("WIFI_SSID_2", "PICOWEB_WIFI_SSID_2", false),
+    ("WIFI_PASSWORD_2", "PICOWEB_WIFI_PASSWORD_2", false),
+    ("WIFI_SSID_3", "PICOWEB_WIFI_SSID_3", false),
+    ("WIFI_PASSWORD_3", "PICOWEB_WIFI_PASSWORD_3", false),
+];
+
+/// Git-ignored file with the secrets, relative to the crate root
+const DOTENV: &str = ".env";
+
+/// PEM files of the HTTPS server (feature `https`): variable with the path
+/// of the file and name of the copy in `$OUT_DIR` included by `src/tls.rs`
+const HTTPS_FILES: [(&str, &str); 2] = [
+    ("HTTPS_CERT", "https_cert.pem"),
+    ("HTTPS_KEY", "https_key.pem"),
+];
+
+/// Removes every `open ... close` block (HTML and CSS comments)
+fn strip_blocks(src: &str, open: &str, close: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut rest = src;
+    while let Some(start) = rest.find(open) {
+        out.push_str(&rest[..start]);
+        match rest[start + open.len()..].find(close) {
+            Some(end) => rest = &rest[start + open.len() + end + close.len()..],
+            None => {
+                rest = "";
+                break;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Appends the non-blank lines of `src` to `out` without indentation,
+/// joining the lines between tags
+fn push_lines(out: &mut String, src: &str) {
+    for line in src.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if !out.is_empty() && !(out.ends_with('>') && line.starts_with('<')) {
+            out.push('\n');
+        }
+        out.push_str(line);
+    }
+}
+
+/// Splits `src` at the first `<script>` or `<style>` element. Returns the
+/// markup up to its opening tag included, the body of the element, the
+/// name of the tag and the rest of the page from the closing tag on.
+fn split_element(src: &str) -> Option<(&str, &str, &str, &str)> {
+    let (start, tag) = ["script", "style"]
+        .into_iter()
+        .filter_map(|tag| src.find(&format!("<{tag}")).map(|start| (start, tag)))
+        .min()?;
+    let body_start = start + src[start..].find('>')? + 1;
+    let body_end = body_start + src[body_start..].find(&format!("</{tag}>"))?;
+    Some((
+        &src[..body_start],
+        &src[body_start..body_end],
+        tag,
+        &src[body_end..],
+    ))
+}
+
+/// Conservative minification: drops comments, indentation and blank
+/// lines, and joins lines between tags. Comments are removed only where
+/// they are comments: `<!-- -->` in the markup and `/* */` inside
+/// `<style>`. Scripts keep both, since they may appear in JavaScript
+/// strings, and their line breaks, so JavaScript without semicolons keeps
+/// working.
+fn minify(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut rest = src;
+    while let Some((markup, body, tag, tail)) = split_element(rest) {
+        push_lines(&mut out, &strip_blocks(markup, "<!--", "-->"));
+        match tag {
+            "style" => push_lines(&mut out, &strip_blocks(body, "/*", "*/")),
+            _ => push_lines(&mut out, body),
+        }
+        rest = tail;
+    }
+    push_lines(&mut out, &strip_blocks(rest, "<!--", "-->"));
+    out
+}
+
+#[cfg(feature = "gzip-assets")]
+fn compress(body: &[u8]) -> Vec<u8> {
+    use flate2::{Compression, write::GzEncoder};
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(body).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Writes `body` to `path` and, with `gzip-assets` and `compressible`,
+/// a gzip copy to `path.gz`. Returns the Rust expression of the `Asset`
+/// and the size of the gzip copy; the copy is dropped when it is not
+/// smaller than the original.
+fn write_asset(path: &Path, body: &[u8], compressible: bool) -> (String, Option<usize>) {
+    fs::write(path, body).unwrap();
+    #[cfg(feature = "gzip-assets")]
+    let compressed = Some(compress(body)).filter(|gz| compressible && gz.len() < body.len());
+    #[cfg(not(feature = "gzip-assets"))]
+    let compressed: Option<Vec<u8>> = {
+        let _ = compressible;
+        None
+    };
+    let gzip = match &compressed {
+        Some(gz) => {
+            let gz_path = path.with_file_name(format!(
+                "{}.gz",
+                path.file_name().unwrap().to_string_lossy()
+            ));
+            fs::write(&gz_path, gz).unwrap();
+            format!("Some(include_bytes!({gz_path:?}))")
+        }
+        None => String::from("None"),
+    };
+    let asset = format!("Asset {{ body: include_bytes!({path:?}), gzip: {gzip} }}");
+    (asset, compressed.map(|gz| gz.len()))
+}
+
+/// Content type of a static file, from its extension
+fn content_type(name: &str) -> &'static str {
+    match name.rsplit('.').next().unwrap_or("") {
+        "html" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "ico" => "image/x-icon",
+        "txt" => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// FNV-1a hash of a file, used as its ETag
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Writes the files of `STATIC_DIR` to `out/static` and returns the
+/// generated `STATIC_FILES` table. HTML, CSS and SVG files are minified;
+/// text files get a gzip copy like the pages. The ETag is computed on the
+/// uncompressed content, shared by both encodings.
+fn build_static(out: &Path) -> String {
+    println!("cargo:rerun-if-changed={STATIC_DIR}");
+    let mut names: Vec<String> = fs::read_dir(STATIC_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_file())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    fs::create_dir_all(out.join(STATIC_DIR)).unwrap();
+
+    let mut table = String::from("pub static STATIC_FILES: &[StaticFile] = &[\n");
+    for name in names {
+        let source = fs::read(Path::new(STATIC_DIR).join(&name)).unwrap();
+        let extension = name.rsplit('.').next().unwrap_or("");
+        let body = match (extension, String::from_utf8(source.clone())) {
+            ("html" | "css" | "svg", Ok(text)) => minify(&text).into_bytes(),
+            _ => source,
+        };
+        let text = matches!(extension, "html" | "css" | "js" | "json" | "svg" | "txt");
+        let path = out.join(STATIC_DIR).join(&name);
+        let (asset, _) = write_asset(&path, &body, text);
+        let etag = format!("\"{:016x}\"", fnv1a(&body));
+        let content_type = content_type(&name);
+        table.push_str(&format!(
+            "    StaticFile {{ name: {name:?}, content_type: {content_type:?}, etag: {etag:?}, \
+             asset: {asset} }},\n"
+        ));
+    }
+    table.push_str("];\n");
+    table
+}
+
+/// Writes the processed pages to `out` and generates `assets.rs`
+fn build_assets(out: &Path) {
+    println!("cargo:rerun-if-env-changed=PICOWEB_ASSET_SIZES");
+    let mut generated = String::new();
+    for name in ASSETS {
+        println!("cargo:rerun-if-changed={name}");
+        let source = fs::read_to_string(name).unwrap();
+        let minified = minify(&source);
+        let (asset, gzip_len) = write_asset(&out.join(name), minified.as_bytes(), true);
+        // Sizes of the pages, on request: `PICOWEB_ASSET_SIZES=1 cargo build`
+        if env::var_os("PICOWEB_ASSET_SIZES").is_some() {
+            let gzip = gzip_len
+                .map(|len| format!(" ({len} gzip)"))
+                .unwrap_or_default();
+            println!(
+                "cargo:warning={name}: {} -> {} bytes{gzip}",
+                source.len(),
+                minified.len()
+            );
+        }
+        let constant = name.replace('.', "_").to_uppercase();
+        generated.push_str(&format!("pub const {constant}: Asset = {asset};\n"));
+    }
+    generated.push_str(&build_static(out));
+    fs::write(out.join("assets.rs"), generated).unwrap();
+}
+
+/// Reads a `KEY=value` entry of `DOTENV`, skipping comments and blank
+/// lines; the value may be quoted
+fn dotenv_value(dotenv: &str, key: &str) -> Option<String> {
+    dotenv
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .find(|(name, _)| name.trim() == key)
+        .map(|(_, value)| {
+            let value = value.trim();
+            value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value)
+                .to_string()
+        })
+}
+
+/// Converts `PICO_IP` (`192.168.1.115`) to the format of `IP_ADDRESS` in
+/// `CONFIG` (`192, 168, 1, 115`)
+fn config_ipv4(value: &str) -> Option<String> {
+    let octets: Vec<u8> = value
+        .split('.')
+        .map(|octet| octet.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    (octets.len() == 4).then(|| {
+        octets
+            .iter()
+            .map(u8::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    })
+}
+
+/// Passes the secrets to the compiler as `PICOWEB_*` variables. The
+/// environment wins over `.env`; a missing required secret is reported by
+/// the `env!` that reads it in `src/configuration.rs`.
+fn export_secrets() {
+    println!("cargo:rerun-if-changed={DOTENV}");
+    let dotenv = fs::read_to_string(DOTENV).unwrap_or_default();
+    for (key, constant, required) in SECRETS {
+        println!("cargo:rerun-if-env-changed={key}");
+        let value = env::var(key).ok().or_else(|| dotenv_value(&dotenv, key));
+        let Some(value) = value.or_else(|| (!required).then(String::new)) else {
+            continue;
+        };
+        if value.contains(['\n', '\r']) {
+            panic!("{key} must be a single line");
+        }
+        let value = if key == "PICO_IP" {
+            config_ipv4(&value)
+                .unwrap_or_else(|| panic!("PICO_IP is not an IPv4 address: {value:?}"))
+        } else {
+            value
+        };
+        println!("cargo:rustc-env={constant}={value}");
+    }
+}
+
+/// Copies the HTTPS certificate and key into `out`. The paths come from
+/// the environment or from `.env`, relative to the crate root; both are
+/// required with the `https` feature.
+fn export_https_files(out: &Path) {
+    let dotenv = fs::read_to_string(DOTENV).unwrap_or_default();
+    for (key, file) in HTTPS_FILES {
+        println!("cargo:rerun-if-env-changed={key}");
+        let path = env::var(key)
+            .ok()
+            .or_else(|| dotenv_value(&dotenv, key))
+            .unwrap_or_else(|| panic!("the https feature requires {key} (path of a PEM file)"));
+        println!("cargo:rerun-if-changed={path}");
+        let pem = fs::read(&path).unwrap_or_else(|e| panic!("{key}: cannot read {path}: {e}"));
+        if !pem.starts_with(b"-----BEGIN ") {
+            panic!("{key}: {path} is not a PEM file");
+        }
+        fs::write(out.join(file), pem).unwrap();
+    }
+}
 
 fn main() {
     // Put `memory.x` in our output directory and ensure it's
-    // on the linker search path.
+    // on the linker search path. The Pico W (feature `rp2040`) has
+    // its own memory layout in `memory-rp2040.x`.
+    let memory: &[u8] = if env::var_os("CARGO_FEATURE_RP2040").is_some() {
+        include_bytes!("memory-rp2040.x")
+    } else {
+        include_bytes!("memory.x")
+    };
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
     File::create(out.join("memory.x"))
         .unwrap()
-        .write_all(include_bytes!("memory.x"))
+        .write_all(memory)
         .unwrap();
     println!("cargo:rustc-link-search={}", out.display());
 
+    build_assets(out);
+    export_secrets();
+    if env::var_os("CARGO_FEATURE_HTTPS").is_some() {
+        export_https_files(out);
+    }
+
     // By default, Cargo will re-run a build script whenever
-    // any file in the project changes. By specifying `memory.x`
-    // here, we ensure the build script is only re-run when
-    // `memory.x` is changed.
+    // any file in the project changes. By specifying the memory
+    // layouts here, we ensure the build script is only re-run when
+    // one of them is changed.
     println!("cargo:rerun-if-changed=memory.x");
+    println!("cargo:rerun-if-changed=memory-rp2040.x");
 
     println!("cargo:rustc-link-arg-bins=--nmagic");
     println!("cargo:rustc-link-arg-bins=-Tlink.x");
@@ -0,0 +1,32 @@
+MEMORY {
+    /*
+     * Pico W (RP2040, feature rp2040): 2 MiB of external flash, with the
+     * second stage bootloader in the first 256 bytes. The program stops at
+     * 1536K, leaving room for the CYW43 blobs loaded with the
+     * firmware-external feature (see src/cyw43_firmware.rs) and for the
+     * record stores at the top of the flash (see src/flash_store.rs).
+     */
+    BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
+    FLASH : ORIGIN = 0x10000100, LENGTH = 1536K - 0x100
+    /*
+     * 256 KiB of striped RAM; the two 4 KiB banks are left unused.
+     */
+    RAM : ORIGIN = 0x20000000, LENGTH = 256K
+    PANDUMP : ORIGIN = 0x20000000 + 200K, LENGTH = 1K
+}
+
+_panic_dump_start = ORIGIN(PANDUMP);
+_panic_dump_end   = ORIGIN(PANDUMP) + LENGTH(PANDUMP);
+
+EXTERN(BOOT2_FIRMWARE)
+
+SECTIONS {
+    /* ### Boot loader
+     *
+     * The second stage bootloader must be the first thing in flash.
+     */
+    .boot2 ORIGIN(BOOT2) :
+    {
+        KEEP(*(.boot2));
+    } > BOOT2
+} INSERT BEFORE .text;
@@ -2,9 +2,11 @@ MEMORY {
     /*
      * The RP2350 has either external or internal flash.
      *
-     * 2 MiB is a safe default here, although a Pico 2 has 4 MiB.
+     * The firmware must fit in one A/B slot of partition_table.json
+     * (1528 KiB, see src/ota.rs); the top of the 4 MiB flash holds
+     * the record stores (see src/flash_store.rs).
      */
-    FLASH : ORIGIN = 0x10000000, LENGTH = 2048K
+    FLASH : ORIGIN = 0x10000000, LENGTH = 1528K
     /*
      * RAM consists of 8 banks, SRAM0-SRAM7, with a striped mapping.
      * This is usually good for performance, as it distributes load on
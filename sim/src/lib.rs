@@ -0,0 +1,296 @@
+//! picoweb-sim - Web server della pico simulato sul PC.
+//!
+//! Espone con picoserve su tokio le stesse rotte del firmware che non
+//! dipendono dall'hardware, costruite sul crate `sudoku` e sulle pagine
+//! HTML della root del progetto:
+//! * `/` - pagina principale (le API di diagnostica non sono simulate)
+//! * `/upload` - form dello schema (GET) e risoluzione (POST)
+//! * `/api/command` - comando `solve <schema>` della console (POST)
+//! * `/api/bench` - benchmark dei risolutori
+//!
+//! Le risposte hanno lo stesso formato di quelle del firmware, così il
+//! browser e i client HTTP si possono provare senza la pico; i test in
+//! `tests/` avviano il server su una porta libera e lo interrogano.
+
+use picoserve::extract::Form;
+use picoserve::response::{File, Json};
+use picoserve::routing::{PathRouter, get};
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use sudoku::{BENCH_SCHEMAS, Backend, Sudoku, SudokuError};
+use tokio::net::TcpListener;
+
+const INDEX_HTML: &str = include_str!("../../index.html");
+const FORM_HTML: &str = include_str!("../../form.html");
+
+const HTML_HEAD: &str =
+    "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Sudoku Result</title>";
+const HTML_FOOTER: &str = "</body></html>";
+
+/// Stile della tabella della soluzione, come `SOLVED_STYLE` del firmware
+const SOLVED_STYLE: &str = "table{border-collapse:collapse;border:3px solid}\
+    td{width:2em;height:2em;text-align:center;border:1px solid #999}\
+    td.clue{font-weight:bold;color:#06c}\
+    td:nth-child(3n){border-right:3px solid #000}\
+    tr:nth-child(3n) td{border-bottom:3px solid #000}";
+
+/// Iterazioni massime di un solve, come `solve::MAX_ITERATIONS` del firmware
+const MAX_ITERATIONS: u32 = 1_000_000;
+
+/// Form dello schema, come `FormValue` del firmware: le 81 celle riga per
+/// riga (vedi `Sudoku::parse_grid81`)
+#[derive(serde::Deserialize)]
+pub struct FormValue {
+    pub grid: String,
+}
+
+/// Riga di comando inviata a `POST /api/command`
+#[derive(serde::Deserialize)]
+pub struct CommandForm {
+    pub line: String,
+}
+
+/// Tempo di un algoritmo su uno schema, come `BenchResult` del firmware
+#[derive(serde::Serialize)]
+pub struct BenchResult {
+    pub schema: &'static str,
+    pub backend: &'static str,
+    pub solved: bool,
+    pub timeout: bool,
+    pub elapsed_us: u64,
+}
+
+/// Pagina HTML generata
+pub struct HtmlPage(String);
+
+impl picoserve::response::Content for HtmlPage {
+    fn content_type(&self) -> &'static str {
+        "text/html"
+    }
+
+    fn content_length(&self) -> usize {
+        self.0.len()
+    }
+
+    async fn write_content<W: picoserve::io::Write>(self, mut writer: W) -> Result<(), W::Error> {
+        writer.write_all(self.0.as_bytes()).await
+    }
+}
+
+/// Errore di uno schema, con il messaggio da mostrare
+pub enum SolveFailure {
+    Parse(SudokuError),
+    Solve(SudokuError),
+}
+
+/// Risolve uno schema in formato compatto o nel formato a righe, come
+/// `commands::solve` del firmware
+///
+/// # Argomenti
+/// * `schema` - Schema da risolvere
+///
+/// # Ritorna
+/// * Result<Sudoku, SolveFailure> - Schema risolto oppure errore
+pub fn solve(schema: &str) -> Result<Sudoku, SolveFailure> {
+    let mut sudoku = parse(schema).map_err(SolveFailure::Parse)?;
+    sudoku
+        .solve_with_budget(MAX_ITERATIONS)
+        .map_err(SolveFailure::Solve)?;
+    Ok(sudoku)
+}
+
+/// Legge uno schema in formato compatto o nel formato a righe
+fn parse(schema: &str) -> Result<Sudoku, SudokuError> {
+    let mut sudoku = Sudoku::default();
+    if schema.len() == 81 && !schema.contains(',') {
+        sudoku.parse_compact(schema)?;
+    } else {
+        sudoku.parse(schema)?;
+    }
+    Ok(sudoku)
+}
+
+/// Genera la pagina di risposta al form, come `generate_html` del firmware
+///
+/// # Argomenti
+/// * `form` - Schema inviato dalla griglia
+///
+/// # Ritorna
+/// * HtmlPage - Tabella della soluzione oppure pagina di errore
+pub fn generate_html(form: &FormValue) -> HtmlPage {
+    let mut sudoku = Sudoku::default();
+    let parsed = sudoku.parse_grid81(&form.grid).map_err(SolveFailure::Parse);
+    let puzzle = sudoku.grid;
+    // Come nel firmware, uno schema con più soluzioni è un errore
+    let multiple = parsed.is_ok() && sudoku.count_solutions(2) > 1;
+    let start = std::time::Instant::now();
+    let result = parsed.and_then(|()| {
+        sudoku
+            .solve_with_budget(MAX_ITERATIONS)
+            .map_err(SolveFailure::Solve)?;
+        Ok(sudoku)
+    });
+    let micros = start.elapsed().as_micros();
+    let mut page = String::from(HTML_HEAD);
+    if result.is_ok() && !multiple {
+        let _ = write!(page, "<style>{SOLVED_STYLE}</style>");
+    }
+    page.push_str("</head><body>");
+    match result {
+        Ok(_) if multiple => {
+            let e = SudokuError::MultipleSolutions;
+            let _ = write!(
+                page,
+                "<h1>Errore nella risoluzione dello schema: {e:?}</h1>"
+            );
+        }
+        Ok(sudoku) => {
+            page.push_str("<h1>Sudoku risolto</h1><table>");
+            for (given, row) in puzzle.iter().zip(sudoku.grid.iter()) {
+                page.push_str("<tr>");
+                for (&g, cell) in given.iter().zip(row) {
+                    let _ = match g {
+                        0 => write!(page, "<td>{cell}</td>"),
+                        _ => write!(page, "<td class=\"clue\">{cell}</td>"),
+                    };
+                }
+                page.push_str("</tr>");
+            }
+            let _ = write!(page, "</table><p>Tempo di risoluzione: {micros} µs</p>");
+        }
+        Err(SolveFailure::Parse(e)) => {
+            let _ = write!(page, "<h1>Errore nella lettura dello schema: {e:?}</h1>");
+        }
+        Err(SolveFailure::Solve(e)) => {
+            let _ = write!(
+                page,
+                "<h1>Errore nella risoluzione dello schema: {e:?}</h1>"
+            );
+        }
+    }
+    page.push_str(HTML_FOOTER);
+    HtmlPage(page)
+}
+
+/// Risposta testuale di `POST /api/command`
+pub struct CommandOutput(String);
+
+impl picoserve::response::Content for CommandOutput {
+    fn content_type(&self) -> &'static str {
+        "text/plain; charset=utf-8"
+    }
+
+    fn content_length(&self) -> usize {
+        self.0.len()
+    }
+
+    async fn write_content<W: picoserve::io::Write>(self, mut writer: W) -> Result<(), W::Error> {
+        writer.write_all(self.0.as_bytes()).await
+    }
+}
+
+/// Esegue un comando della console; è simulato solo `solve`
+///
+/// # Argomenti
+/// * `line` - Riga di comando
+///
+/// # Ritorna
+/// * CommandOutput - Output del comando, una riga per risposta
+pub fn execute(line: &str) -> CommandOutput {
+    let mut out = String::new();
+    match line.trim().split_once(' ') {
+        Some(("solve", schema)) => match solve(schema.trim()) {
+            Ok(sudoku) => {
+                for row in sudoku.grid.iter() {
+                    let row = row.map(|cell| cell.to_string()).join(" ");
+                    let _ = writeln!(out, "{row}");
+                }
+            }
+            Err(SolveFailure::Parse(e) | SolveFailure::Solve(e)) => {
+                let _ = writeln!(out, "errore: {e:?}");
+            }
+        },
+        _ => out.push_str("comando non disponibile nella simulazione\n"),
+    }
+    CommandOutput(out)
+}
+
+/// Esegue il benchmark dei risolutori, misurando i tempi con l'orologio del PC
+///
+/// # Ritorna
+/// * Vec<BenchResult> - Un risultato per ogni coppia schema/algoritmo
+pub fn bench() -> Vec<BenchResult> {
+    let mut report = Vec::new();
+    for (name, schema) in BENCH_SCHEMAS {
+        let mut parsed = Sudoku::default();
+        if parsed.parse_compact(schema).is_err() {
+            continue;
+        }
+        for backend in Backend::ALL {
+            let mut sudoku = parsed.clone();
+            let start = Instant::now();
+            let result = sudoku.solve_within(backend, MAX_ITERATIONS);
+            report.push(BenchResult {
+                schema: name,
+                backend: backend.name(),
+                solved: result.is_ok(),
+                timeout: matches!(result, Err(SudokuError::Timeout)),
+                elapsed_us: start.elapsed().as_micros() as u64,
+            });
+        }
+    }
+    report
+}
+
+/// Costruisce il router della simulazione
+///
+/// # Ritorna
+/// * picoserve::Router<impl PathRouter> - Rotte simulate
+pub fn app() -> picoserve::Router<impl PathRouter> {
+    picoserve::Router::new()
+        .route("/", get(|| async { File::html(INDEX_HTML) }))
+        .route(
+            "/upload",
+            get(|| async { File::html(FORM_HTML) })
+                .post(|Form(form): Form<FormValue>| async move { generate_html(&form) }),
+        )
+        .route(
+            "/api/command",
+            picoserve::routing::post(
+                |Form(form): Form<CommandForm>| async move { execute(&form.line) },
+            ),
+        )
+        .route("/api/bench", get(|| async { Json(bench()) }))
+}
+
+/// Serve le connessioni in arrivo, una task locale per connessione: da
+/// eseguire dentro un `tokio::task::LocalSet`
+///
+/// # Argomenti
+/// * `listener` - Socket in ascolto
+///
+/// # Ritorna
+/// * std::io::Result<()> - Errore di accept, altrimenti non ritorna
+pub async fn serve(listener: TcpListener) -> std::io::Result<()> {
+    let app = Rc::new(app());
+    // Gli stessi timeout del firmware (vedi src/main.rs)
+    let config = Rc::new(
+        picoserve::Config::new(picoserve::Timeouts {
+            start_read_request: Some(Duration::from_secs(5)),
+            persistent_start_read_request: Some(Duration::from_secs(1)),
+            read_request: Some(Duration::from_secs(1)),
+            write: Some(Duration::from_secs(1)),
+        })
+        .keep_connection_alive(),
+    );
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let (app, config) = (app.clone(), config.clone());
+        tokio::task::spawn_local(async move {
+            if let Err(e) = picoserve::serve(&app, &config, &mut [0; 2048], stream).await {
+                eprintln!("{remote}: {e:?}");
+            }
+        });
+    }
+}
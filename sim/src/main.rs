@@ -0,0 +1,18 @@
+//! Avvia il web server simulato, di default su http://127.0.0.1:8080
+//! (la porta si cambia con la variabile d'ambiente `PICOWEB_SIM_PORT`).
+
+use std::net::Ipv4Addr;
+use tokio::net::TcpListener;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> std::io::Result<()> {
+    let port = std::env::var("PICOWEB_SIM_PORT")
+        .ok()
+        .and_then(|port| port.parse::<u16>().ok())
+        .unwrap_or(8080);
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+    println!("picoweb-sim su http://{}", listener.local_addr()?);
+    tokio::task::LocalSet::new()
+        .run_until(picoweb_sim::serve(listener))
+        .await
+}
@@ -0,0 +1,131 @@
+//! Test delle API HTTP sul server simulato: ogni test avvia il server su
+//! una porta libera e invia richieste HTTP/1.1 con `Connection: close`.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Schema di esempio nel formato del form
+const ROWS: [&str; 9] = [
+    "5,3,_,_,7,_,_,_,_",
+    "6,_,_,1,9,5,_,_,_",
+    "_,9,8,_,_,_,_,6,_",
+    "8,_,_,_,6,_,_,_,3",
+    "4,_,_,8,_,3,_,_,1",
+    "7,_,_,_,2,_,_,_,6",
+    "_,6,_,_,_,_,2,8,_",
+    "_,_,_,4,1,9,_,_,5",
+    "_,_,_,_,8,_,_,7,9",
+];
+
+/// Avvia il server e invia una richiesta
+///
+/// # Argomenti
+/// * `method` - Metodo HTTP
+/// * `path` - Percorso
+/// * `form` - Corpo `application/x-www-form-urlencoded`, se presente
+///
+/// # Ritorna
+/// * (u16, String) - Stato e corpo della risposta
+async fn request(method: &str, path: &str, form: Option<&str>) -> (u16, String) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let address: SocketAddr = listener.local_addr().unwrap();
+    let local = tokio::task::LocalSet::new();
+    local.spawn_local(picoweb_sim::serve(listener));
+    local
+        .run_until(async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let body = form.unwrap_or("");
+            let request = format!(
+                "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+                 Content-Type: application/x-www-form-urlencoded\r\n\
+                 Content-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            let (head, body) = response.split_once("\r\n\r\n").unwrap();
+            let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+            (status, body.to_string())
+        })
+        .await
+}
+
+/// Codifica lo schema come il form della griglia: 81 celle, '.' per le vuote
+fn form_grid() -> String {
+    let cells: String = ROWS.concat().replace(',', "").replace('_', ".");
+    format!("grid={cells}")
+}
+
+#[tokio::test]
+async fn index_page_is_served() {
+    let (status, body) = request("GET", "/", None).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("Sudoku Webserver"));
+}
+
+#[tokio::test]
+async fn upload_solves_form_schema() {
+    let (status, body) = request("POST", "/upload", Some(&form_grid())).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("Sudoku risolto"));
+    assert!(body.contains("Tempo di risoluzione"));
+    assert!(body.starts_with("<!DOCTYPE html>"));
+    // Prima riga della soluzione, con i numeri dello schema evidenziati
+    assert!(body.contains(concat!(
+        "<tr><td class=\"clue\">5</td><td class=\"clue\">3</td><td>4</td><td>6</td>",
+        "<td class=\"clue\">7</td><td>8</td><td>9</td><td>1</td><td>2</td></tr>"
+    )));
+    assert!(body.contains("td.clue{font-weight:bold"));
+}
+
+#[tokio::test]
+async fn upload_ignores_grid_separators() {
+    // Schema incollato nel campo di testo: righe a capo e riquadri separati
+    let rows: Vec<String> = ROWS.iter().map(|row| row.replace(',', "")).collect();
+    let form = format!("grid={}", rows.join("%7C%0D%0A"));
+    let (status, body) = request("POST", "/upload", Some(&form)).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("Sudoku risolto"));
+}
+
+#[tokio::test]
+async fn upload_reports_invalid_schema() {
+    let form = form_grid().replacen("53", "x3", 1);
+    let (status, body) = request("POST", "/upload", Some(&form)).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("Errore nella lettura dello schema"));
+}
+
+#[tokio::test]
+async fn upload_reports_multiple_solutions() {
+    // Solo la prima riga: lo schema ha molte soluzioni
+    let form = format!("grid={}{}", ROWS[0].replace(',', ""), ".".repeat(72));
+    let (status, body) = request("POST", "/upload", Some(&form)).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("Puzzle has multiple solutions"));
+}
+
+#[tokio::test]
+async fn command_solves_compact_schema() {
+    let schema =
+        "53..7....6..195....98....6.8...6...34..8.3..17...2...6.6....28....419..5....8..79";
+    let (status, body) = request(
+        "POST",
+        "/api/command",
+        Some(&format!("line=solve+{schema}")),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body.lines().next(), Some("5 3 4 6 7 8 9 1 2"));
+    assert_eq!(body.lines().count(), 9);
+}
+
+#[tokio::test]
+async fn bench_reports_every_backend() {
+    let (status, body) = request("GET", "/api/bench", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(body.matches("\"solved\":true").count(), 9);
+    assert_eq!(body.matches("\"timeout\":false").count(), 9);
+}
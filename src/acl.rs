@@ -0,0 +1,232 @@
+//! acl.rs - Liste di accesso per indirizzo IP.
+//!
+//! Due liste di reti in notazione CIDR (es. `192.168.1.0/24`, separate da
+//! virgole) in configuration.rs:
+//! * `ACL_DENY` - i client di queste reti vengono respinti dal worker appena
+//!   accettata la connessione, prima di leggere la richiesta;
+//! * `ACL_ADMIN_ALLOW` - solo i client di queste reti possono usare le rotte
+//!   di amministrazione ([`ADMIN_PREFIXES`]: comandi, benchmark, impostazioni,
+//!   aggiornamento del firmware, LED, GPIO del CYW43, PWM, terminale UART
+//!   e scritture sui GPIO del connettore); vuota = tutti.
+//!
+//! Il worker classifica il client all'accept (vedi `web_worker` in main.rs)
+//! e salva l'esito nello stato dell'applicazione, da cui [`AclLayer`] lo
+//! legge prima del routing: così la pico si può esporre su una rete più
+//! ampia senza esporre riavvio e OTA. Il percorso è confrontato dopo la
+//! decodifica (vedi request_path.rs), quindi `/api/%6Fta` resta una rotta
+//! di amministrazione. Le liste sono rilette a ogni
+//! connessione, quindi modificabili a runtime con `config set`.
+
+use crate::app_state::AppState;
+use crate::i18n::Lang;
+use crate::request_path::{self, DecodedPath};
+use crate::utility::{ConfigValue, get_acl_admin_allow, get_acl_deny};
+use embassy_net::IpAddress;
+use picoserve::ResponseSent;
+use picoserve::io::Read;
+use picoserve::request::RequestParts;
+use picoserve::response::{IntoResponse, ResponseWriter, StatusCode};
+use picoserve::routing::{Layer, Next};
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Rotte di amministrazione, consentite solo alle reti di ACL_ADMIN_ALLOW
+pub const ADMIN_PREFIXES: [&str; 10] = [
+    "/api/command",
+    "/api/bench",
+    "/settings",
+    "/api/settings",
+    "/api/ota",
+    "/api/led",
+    "/api/cyw43/",
+    "/api/pwm",
+    "/uart",
+    "/api/uart/",
+];
+
+/// Prefisso delle rotte dei GPIO del connettore: le POST sono di amministrazione
+const GPIO_PIN_PREFIX: &str = "/api/gpio/";
+
+/// Connessioni respinte perché il client è in ACL_DENY
+static DENIED: AtomicU32 = AtomicU32::new(0);
+
+/// Richieste di amministrazione rifiutate perché il client non è in ACL_ADMIN_ALLOW
+static ADMIN_REFUSED: AtomicU32 = AtomicU32::new(0);
+
+/// Accesso consentito a un client
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    /// Connessione da respingere
+    Denied,
+    /// Solo le rotte pubbliche
+    Public,
+    /// Tutte le rotte
+    Admin,
+}
+
+/// Rete in notazione CIDR
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cidr {
+    network: u32,
+    mask: u32,
+}
+
+impl Cidr {
+    /// Legge una rete, es. `192.168.1.0/24`; senza prefisso è un solo indirizzo
+    ///
+    /// # Argomenti
+    /// * `text` - Rete da leggere
+    ///
+    /// # Ritorna
+    /// * Option<Cidr> - Rete, None se il testo non è valido
+    pub fn parse(text: &str) -> Option<Self> {
+        let (address, prefix) = match text.trim().split_once('/') {
+            Some((address, prefix)) => (address, prefix.trim().parse::<u32>().ok()?),
+            None => (text.trim(), 32),
+        };
+        if prefix > 32 {
+            return None;
+        }
+        let address: core::net::Ipv4Addr = address.trim().parse().ok()?;
+        let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+        Some(Cidr {
+            network: u32::from(address) & mask,
+            mask,
+        })
+    }
+
+    /// Ritorna true se l'indirizzo appartiene alla rete
+    pub fn contains(&self, address: [u8; 4]) -> bool {
+        u32::from_be_bytes(address) & self.mask == self.network
+    }
+}
+
+/// Cerca un indirizzo in una lista di reti; le voci non valide vengono
+/// ignorate (con un avviso nel log)
+///
+/// # Argomenti
+/// * `list` - Reti separate da virgole
+/// * `address` - Indirizzo da cercare
+///
+/// # Ritorna
+/// * bool - true se almeno una rete contiene l'indirizzo
+fn list_contains(list: &str, address: [u8; 4]) -> bool {
+    list.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .any(|entry| match Cidr::parse(entry) {
+            Some(cidr) => cidr.contains(address),
+            None => {
+                log::warn!("ACL: rete non valida '{}'", entry.trim());
+                false
+            }
+        })
+}
+
+/// Classifica un client secondo ACL_DENY e ACL_ADMIN_ALLOW
+///
+/// # Argomenti
+/// * `client` - Indirizzo del client, None se sconosciuto
+///
+/// # Ritorna
+/// * Access - Accesso consentito
+pub fn classify(client: Option<IpAddress>) -> Access {
+    let deny = get_acl_deny();
+    let admin = get_acl_admin_allow();
+    let Some(IpAddress::Ipv4(address)) = client else {
+        // Senza indirizzo le liste non si possono applicare: solo le rotte pubbliche
+        return if deny.trim().is_empty() {
+            Access::Public
+        } else {
+            Access::Denied
+        };
+    };
+    let address = address.octets();
+    if list_contains(&deny, address) {
+        DENIED.fetch_add(1, Ordering::Relaxed);
+        return Access::Denied;
+    }
+    if admin.trim().is_empty() || list_contains(&admin, address) {
+        Access::Admin
+    } else {
+        Access::Public
+    }
+}
+
+/// Verifica se una richiesta usa una rotta di amministrazione
+///
+/// # Argomenti
+/// * `method` - Metodo HTTP
+/// * `path` - Percorso decodificato della richiesta (vedi request_path.rs)
+///
+/// # Ritorna
+/// * bool - true per ADMIN_PREFIXES e per le POST su /api/gpio/{pin}
+fn is_admin(method: &str, path: &DecodedPath) -> bool {
+    let gpio_write = method == "POST"
+        && path
+            .strip_prefix(GPIO_PIN_PREFIX)
+            .is_some_and(|pin| pin.first().is_some_and(u8::is_ascii_digit));
+    gpio_write || ADMIN_PREFIXES.iter().any(|p| path.starts_with(p))
+}
+
+/// Stato delle liste, restituito da /api/acl
+#[derive(serde::Serialize)]
+pub struct AclReport {
+    deny: ConfigValue,
+    admin_allow: ConfigValue,
+    denied: u32,
+    admin_refused: u32,
+    /// Richieste rifiutate per credenziali HTTP assenti o errate (vedi auth.rs)
+    auth_refused: u32,
+    /// Connessioni rifiutate con 429 (vedi rate_limit.rs)
+    rate_limited: u32,
+}
+
+/// Ritorna le liste configurate e i rifiuti dall'avvio
+///
+/// # Ritorna
+/// * AclReport - Liste e contatori
+pub fn report() -> AclReport {
+    AclReport {
+        deny: get_acl_deny(),
+        admin_allow: get_acl_admin_allow(),
+        denied: DENIED.load(Ordering::Relaxed),
+        admin_refused: ADMIN_REFUSED.load(Ordering::Relaxed),
+        auth_refused: crate::auth::refused(),
+        rate_limited: crate::rate_limit::limited(),
+    }
+}
+
+/// Layer che consente le rotte di amministrazione solo ai client di ACL_ADMIN_ALLOW
+pub struct AclLayer;
+
+impl<PathParameters> Layer<AppState, PathParameters> for AclLayer {
+    type NextState = AppState;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &AppState,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let path = request_parts.path().encoded();
+        let decoded = request_path::decoded(path);
+        if is_admin(request_parts.method(), &decoded) && state.access.get() != Access::Admin {
+            ADMIN_REFUSED.fetch_add(1, Ordering::Relaxed);
+            log::warn!("ACL: {} rifiutata, client fuori da ACL_ADMIN_ALLOW", path);
+            return (
+                StatusCode::FORBIDDEN,
+                Lang::negotiate(&request_parts).strings().acl_forbidden,
+            )
+                .write_to(next.into_connection(), response_writer)
+                .await;
+        }
+        next.run(state, path_parameters, response_writer).await
+    }
+}
@@ -1,39 +1,98 @@
+use crate::acl;
 use crate::app_state::AppState;
-use crate::form_value::FormValue;
+use crate::auth;
+use crate::bench::BenchRoutes;
+use crate::body_limit;
+use crate::clock;
+use crate::commands::AdminRoutes;
+use crate::crash_log;
+use crate::events::GpioRoutes;
+use crate::files::FileRoutes;
+use crate::form_value::SudokuRoutes;
+use crate::gpio::GpioPinRoutes;
+use crate::i2c::EnvironmentRoutes;
+use crate::led::LedRoutes;
+use crate::led_strip::LedStripRoutes;
+use crate::live::LiveRoutes;
+use crate::metrics::MetricsRoutes;
+#[cfg(feature = "rp235x")]
+use crate::ota::OtaRoutes;
+use crate::provisioning::{self, ProvisionRoutes};
+use crate::pwm::PwmRoutes;
+use crate::request_id;
+use crate::request_log::{self, RequestLogRoutes};
+use crate::safe_mode;
+use crate::sensors::SensorRoutes;
+use crate::session::SessionRoutes;
+use crate::settings::SettingsRoutes;
+use crate::solve_ws::SolveSocketRoutes;
+use crate::status::StatusRoutes;
+use crate::uart_bridge::UartRoutes;
+use crate::wifi_scan::WifiScanRoutes;
 use picoserve::AppWithStateBuilder;
-use picoserve::routing::{PathRouter, get_service};
+use picoserve::routing::PathRouter;
 
 pub struct AppProps;
 
+/// Gruppo di rotte fornito da un modulo: ogni funzionalità registra le
+/// proprie rotte con questo trait invece di aggiungerle in build_app.
+pub trait Routes {
+    /// Aggiunge le rotte del modulo al router
+    ///
+    /// # Argomenti
+    /// * `router` - Router a cui aggiungere le rotte
+    ///
+    /// # Ritorna
+    /// * picoserve::Router<impl PathRouter<AppState>, AppState> - Router con le rotte del modulo
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState>;
+}
+
 // Costruisce il router dell'applicazione con le rotte definite
 impl AppWithStateBuilder for AppProps {
     type State = AppState;
     type PathRouter = impl PathRouter<AppState>;
 
-    /// Costruisce il router dell'applicazione con gli endpoint.
+    /// Costruisce il router dell'applicazione con le rotte di ogni modulo.
+    /// I layer valgono per tutte le rotte.
     ///
     /// # Ritorna
     /// * picoserve::Router<Self::PathRouter, Self::State>
     fn build_app(self) -> picoserve::Router<Self::PathRouter, Self::State> {
-        picoserve::Router::new()
-            .route(
-                "/",
-                get_service(picoserve::response::File::html(include_str!(
-                    "../index.html"
-                ))),
-            )
-            .route(
-                "/upload",
-                get_service(picoserve::response::File::html(include_str!(
-                    "../form.html"
-                )))
-                .post(
-                    |picoserve::extract::Form(form_value): picoserve::extract::Form<FormValue>| {
-                        async move {
-                            form_value
-                        }
-                    },
-                ),
-            )
+        let router = picoserve::Router::new();
+        let router = FileRoutes::register(router);
+        let router = SudokuRoutes::register(router);
+        let router = SolveSocketRoutes::register(router);
+        let router = GpioRoutes::register(router);
+        let router = GpioPinRoutes::register(router);
+        let router = LedRoutes::register(router);
+        let router = LedStripRoutes::register(router);
+        let router = PwmRoutes::register(router);
+        let router = MetricsRoutes::register(router);
+        let router = StatusRoutes::register(router);
+        let router = SensorRoutes::register(router);
+        let router = EnvironmentRoutes::register(router);
+        let router = UartRoutes::register(router);
+        let router = LiveRoutes::register(router);
+        let router = BenchRoutes::register(router);
+        let router = AdminRoutes::register(router);
+        let router = ProvisionRoutes::register(router);
+        let router = SettingsRoutes::register(router);
+        let router = SessionRoutes::register(router);
+        let router = WifiScanRoutes::register(router);
+        let router = RequestLogRoutes::register(router);
+        #[cfg(feature = "rp235x")]
+        let router = OtaRoutes::register(router);
+        router
+            .layer(body_limit::BodyLimitLayer)
+            .layer(auth::AuthLayer)
+            .layer(acl::AclLayer)
+            .layer(provisioning::ProvisionLayer)
+            .layer(safe_mode::SafeModeLayer)
+            .layer(crash_log::CrashContextLayer)
+            .layer(request_log::RequestLogLayer)
+            .layer(request_id::RequestIdLayer)
+            .layer(clock::DateLayer)
     }
 }
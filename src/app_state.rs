@@ -1,8 +1,48 @@
 use crate::SharedControl;
+use crate::acl::Access;
+use crate::gpio::GpioManager;
+use crate::request_log::RequestLog;
+use crate::settings::Settings;
+use crate::uart_bridge::UartBridge;
+use core::cell::Cell;
 
 /// Stato dell'applicazione condifiviso tra i task embassy
 pub struct AppState {
     pub shared_control: SharedControl,
+    /// Accesso del client della connessione in corso sul worker (vedi acl.rs)
+    pub access: Cell<Access>,
+    /// Impostazioni di rete modificabili da /settings (vedi settings.rs)
+    pub settings: Settings,
+    /// GPIO del connettore gestiti da /api/gpio/{pin} (vedi gpio.rs)
+    pub gpio: GpioManager,
+    /// Terminale sulla UART1 di /uart (vedi uart_bridge.rs)
+    pub uart: UartBridge,
+    /// Ultime richieste servite da tutti i worker (vedi request_log.rs)
+    pub request_log: RequestLog,
+    /// Corpo più grande che sta nel buffer HTTP del worker (vedi body_limit.rs)
+    pub max_body: usize,
+}
+
+impl AppState {
+    /// Crea lo stato di un worker del web server
+    ///
+    /// # Argomenti
+    /// * `shared_control` - Controller condiviso
+    /// * `max_body` - Corpo più grande accettato dal worker
+    ///
+    /// # Ritorna
+    /// * Self - Stato del worker
+    pub fn new(shared_control: SharedControl, max_body: usize) -> Self {
+        Self {
+            shared_control,
+            access: Cell::new(Access::Public),
+            settings: Settings,
+            gpio: GpioManager,
+            uart: UartBridge,
+            request_log: RequestLog,
+            max_body,
+        }
+    }
 }
 
 // Permette di estrarre il controller condiviso dallo stato dell'applicazione
@@ -18,3 +58,59 @@ impl picoserve::extract::FromRef<AppState> for SharedControl {
         state.shared_control
     }
 }
+
+// Permette di estrarre le impostazioni di rete dallo stato dell'applicazione
+impl picoserve::extract::FromRef<AppState> for Settings {
+    /// Ritorna le impostazioni di rete
+    ///
+    /// # Argomenti
+    /// * `state` - Riferimento allo stato dell'applicazione
+    ///
+    /// # Ritorna
+    /// * Self - Impostazioni di rete
+    fn from_ref(state: &AppState) -> Self {
+        state.settings
+    }
+}
+
+// Permette di estrarre i GPIO gestiti dallo stato dell'applicazione
+impl picoserve::extract::FromRef<AppState> for GpioManager {
+    /// Ritorna i GPIO gestiti
+    ///
+    /// # Argomenti
+    /// * `state` - Riferimento allo stato dell'applicazione
+    ///
+    /// # Ritorna
+    /// * Self - GPIO gestiti
+    fn from_ref(state: &AppState) -> Self {
+        state.gpio
+    }
+}
+
+// Permette di estrarre il terminale sulla UART1 dallo stato dell'applicazione
+impl picoserve::extract::FromRef<AppState> for UartBridge {
+    /// Ritorna il terminale sulla UART1
+    ///
+    /// # Argomenti
+    /// * `state` - Riferimento allo stato dell'applicazione
+    ///
+    /// # Ritorna
+    /// * Self - Terminale sulla UART1
+    fn from_ref(state: &AppState) -> Self {
+        state.uart
+    }
+}
+
+// Permette di estrarre il buffer delle ultime richieste dallo stato dell'applicazione
+impl picoserve::extract::FromRef<AppState> for RequestLog {
+    /// Ritorna il buffer delle ultime richieste
+    ///
+    /// # Argomenti
+    /// * `state` - Riferimento allo stato dell'applicazione
+    ///
+    /// # Ritorna
+    /// * Self - Buffer delle ultime richieste
+    fn from_ref(state: &AppState) -> Self {
+        state.request_log
+    }
+}
@@ -0,0 +1,181 @@
+//! assets.rs - Pagine web incluse nel firmware.
+//!
+//! Le pagine sorgente (`index.html`, `form.html` e le versioni inglesi)
+//! vengono minimizzate da build.rs; qui sono incluse le costanti generate.
+//! Con la feature `gzip-assets` ogni pagina ha anche una copia compressa con
+//! gzip, inviata con `Content-Encoding: gzip` ai client che la accettano
+//! (intestazione `Accept-Encoding`, vedi [`AcceptGzip`]); gli altri ricevono
+//! la pagina in chiaro. Le pagine compresse occupano meno segmenti sui
+//! buffer TCP da 1 KB, al prezzo di più flash.
+//! Allo stesso modo i file della directory `static/` formano la tabella
+//! [`STATIC_FILES`], servita da files.rs sotto `/static/`.
+
+use core::convert::Infallible;
+use picoserve::extract::FromRequestParts;
+use picoserve::request::RequestParts;
+use picoserve::response::File;
+
+/// Tipo delle pagine HTML
+const HTML: &str = "text/html; charset=utf-8";
+
+/// Pagina generata da build.rs
+#[derive(Clone, Copy)]
+pub struct Asset {
+    /// Contenuto in chiaro
+    pub body: &'static [u8],
+    /// Copia compressa con gzip, solo con la feature `gzip-assets`
+    pub gzip: Option<&'static [u8]>,
+}
+
+impl Asset {
+    /// Sceglie la codifica del contenuto
+    ///
+    /// # Argomenti
+    /// * `accept` - Codifiche accettate dal client
+    ///
+    /// # Ritorna
+    /// * Option<&[u8]> - Copia compressa se esiste e il client la accetta,
+    ///   None per il contenuto in chiaro
+    pub fn gzip_for(self, accept: AcceptGzip) -> Option<&'static [u8]> {
+        self.gzip.filter(|_| accept.0)
+    }
+
+    /// Risposta HTTP con la pagina
+    ///
+    /// # Argomenti
+    /// * `accept` - Codifiche accettate dal client
+    ///
+    /// # Ritorna
+    /// * File - Pagina HTML, con `Content-Encoding: gzip` se compressa
+    pub fn html(self, accept: AcceptGzip) -> File {
+        match self.gzip_for(accept) {
+            Some(body) => File::with_content_type_and_headers(
+                HTML,
+                body,
+                &[("Content-Encoding", "gzip"), ("Vary", "Accept-Encoding")],
+            ),
+            // Le cache devono distinguere le due versioni della stessa pagina
+            None if self.gzip.is_some() => {
+                File::with_content_type_and_headers(HTML, self.body, &[("Vary", "Accept-Encoding")])
+            }
+            None => File::with_content_type(HTML, self.body),
+        }
+    }
+}
+
+/// true se il client accetta contenuti compressi con gzip, estraibile negli
+/// handler
+#[derive(Clone, Copy)]
+pub struct AcceptGzip(pub bool);
+
+impl AcceptGzip {
+    /// Legge l'intestazione `Accept-Encoding`
+    ///
+    /// # Argomenti
+    /// * `header` - Valore dell'intestazione, es. `gzip, deflate, br`
+    ///
+    /// # Ritorna
+    /// * AcceptGzip - true se gzip è elencato senza `q=0`
+    pub fn parse(header: &str) -> Self {
+        let accepted = header.split(',').any(|entry| {
+            let mut params = entry.split(';').map(str::trim);
+            let coding = params.next().unwrap_or_default();
+            let refused = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .is_some_and(|q| q.parse::<f32>() == Ok(0.0))
+            });
+            coding.eq_ignore_ascii_case("gzip") && !refused
+        });
+        Self(accepted)
+    }
+}
+
+impl<'r, State> FromRequestParts<'r, State> for AcceptGzip {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        _state: &'r State,
+        request_parts: &RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        let header = request_parts.headers().get("Accept-Encoding");
+        let accept = header
+            .as_ref()
+            .and_then(|value| core::str::from_utf8(value.as_raw()).ok())
+            .map_or(Self(false), Self::parse);
+        Ok(accept)
+    }
+}
+
+/// File servito sotto `/static/` (vedi files.rs), generato da build.rs a
+/// partire dalla directory `static/`
+pub struct StaticFile {
+    /// Nome del file, es. `favicon.svg`
+    pub name: &'static str,
+    pub content_type: &'static str,
+    /// ETag tra virgolette, calcolato sul contenuto in chiaro e uguale
+    /// per le due codifiche
+    pub etag: &'static str,
+    pub asset: Asset,
+}
+
+impl StaticFile {
+    /// Cerca un file statico per nome
+    ///
+    /// # Argomenti
+    /// * `name` - Nome del file, senza `/static/`
+    ///
+    /// # Ritorna
+    /// * Option<&StaticFile> - File, None se non esiste
+    pub fn find(name: &str) -> Option<&'static StaticFile> {
+        STATIC_FILES.iter().find(|file| file.name == name)
+    }
+
+    /// Ritorna true se una delle versioni in cache del client (intestazione
+    /// `If-None-Match`) coincide con quella inclusa nel firmware
+    ///
+    /// # Argomenti
+    /// * `if_none_match` - Valore dell'intestazione, es. `"3789b308", W/"1a2b"`
+    pub fn matches(&self, if_none_match: &str) -> bool {
+        if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == self.etag)
+    }
+}
+
+/// Contenuto di un file statico nella codifica scelta
+pub struct StaticBody {
+    content_type: &'static str,
+    body: &'static [u8],
+}
+
+impl StaticBody {
+    /// Contenuto di un file
+    ///
+    /// # Argomenti
+    /// * `file` - File statico
+    /// * `body` - Contenuto in chiaro o copia compressa
+    pub fn new(file: &StaticFile, body: &'static [u8]) -> Self {
+        Self {
+            content_type: file.content_type,
+            body,
+        }
+    }
+}
+
+impl picoserve::response::Content for StaticBody {
+    fn content_type(&self) -> &'static str {
+        self.content_type
+    }
+
+    fn content_length(&self) -> usize {
+        self.body.len()
+    }
+
+    async fn write_content<W: picoserve::io::Write>(self, mut writer: W) -> Result<(), W::Error> {
+        writer.write_all(self.body).await
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
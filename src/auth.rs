@@ -0,0 +1,212 @@
+//! auth.rs - Autenticazione HTTP Basic per i form e le API.
+//!
+//! Se `HTTP_AUTH_USER` (configuration.rs) non è vuoto, le rotte di
+//! [`PROTECTED_PREFIXES`] (form del Sudoku, impostazioni, configurazione e
+//! scansione WiFi, terminale UART, LED, log, eventi, ultime richieste e
+//! tutte le API) richiedono l'intestazione `Authorization: Basic ...` con
+//! utente e password `HTTP_AUTH_PASSWORD`; senza credenziali valide la
+//! risposta è `401` con `WWW-Authenticate`. Una sessione aperta dal form di
+//! login vale come le credenziali, e i browser senza sessione vengono
+//! reindirizzati al form (vedi session.rs). Le credenziali sono rilette a
+//! ogni richiesta, quindi modificabili a runtime con `config set`.
+//!
+//! Restano aperte la pagina principale, il login e il logout, i file di
+//! `/static/` e le pagine di sola lettura di ora, temperatura e ambiente,
+//! che non modificano nulla e leggono i dati dalle API protette. Il
+//! percorso è confrontato dopo la decodifica (vedi request_path.rs), come
+//! fa il router: `/%61pi/...` è protetto come `/api/...`.
+//!
+//! [`credentials_required`] e [`check_credentials`] sono il controllo
+//! comune a tutta la superficie dei comandi: li usano il layer HTTP (Basic
+//! Auth), il form di login (session.rs) e la shell telnet (telnet.rs), così
+//! le stesse credenziali proteggono `/api/command` e telnet.
+//!
+//! Il Basic Auth non cifra la password: su una rete non fidata va usato
+//! insieme alle liste di accesso di acl.rs.
+//!
+//! Esempio:
+//! ```text
+//! curl -u admin:segreta http://<ip>/api/status
+//! ```
+
+use crate::i18n::Lang;
+use crate::request_path;
+use crate::session;
+use crate::utility::get_http_auth;
+use picoserve::ResponseSent;
+use picoserve::io::Read;
+use picoserve::request::RequestParts;
+use picoserve::response::{IntoResponse, Response, ResponseWriter, StatusCode};
+use picoserve::routing::{Layer, Next};
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Rotte protette dalla password
+pub const PROTECTED_PREFIXES: [&str; 10] = [
+    "/upload",
+    "/settings",
+    "/provision",
+    "/wifi/",
+    "/uart",
+    "/leds",
+    "/logs",
+    "/events",
+    "/requests",
+    "/api/",
+];
+
+/// Intestazione della risposta 401
+const CHALLENGE: &str = "Basic realm=\"picoweb\", charset=\"UTF-8\"";
+
+/// Richieste rifiutate per credenziali assenti o errate
+static REFUSED: AtomicU32 = AtomicU32::new(0);
+
+/// Ritorna il numero di richieste rifiutate dall'avvio
+pub fn refused() -> u32 {
+    REFUSED.load(Ordering::Relaxed)
+}
+
+/// Ritorna true se sono configurate le credenziali (HTTP_AUTH_USER non vuoto)
+pub fn credentials_required() -> bool {
+    get_http_auth().is_some()
+}
+
+/// Verifica utente e password con HTTP_AUTH_USER e HTTP_AUTH_PASSWORD
+///
+/// # Argomenti
+/// * `user` - Utente inviato dal client
+/// * `password` - Password inviata dal client
+///
+/// # Ritorna
+/// * bool - true se le credenziali sono configurate e coincidono
+pub fn check_credentials(user: &[u8], password: &[u8]) -> bool {
+    let Some((expected_user, expected_password)) = get_http_auth() else {
+        return false;
+    };
+    // `&` e non `&&`: entrambi i confronti vengono sempre eseguiti
+    same_bytes(user, expected_user.as_bytes()) & same_bytes(password, expected_password.as_bytes())
+}
+
+/// Decodifica il base64 delle credenziali di un'intestazione Basic
+///
+/// # Argomenti
+/// * `encoded` - Testo dopo `Basic `
+///
+/// # Ritorna
+/// * Option<heapless::Vec<u8, 130>> - `utente:password`, None se il testo non
+///   è base64 o è troppo lungo
+fn decode_basic(encoded: &[u8]) -> Option<heapless::Vec<u8, 130>> {
+    let mut out = heapless::Vec::new();
+    let (mut acc, mut bits) = (0u32, 0);
+    for &c in encoded.iter().take_while(|&&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8).ok()?;
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// Verifica le credenziali inviate dal client con il Basic Auth
+///
+/// # Argomenti
+/// * `header` - Valore dell'intestazione `Authorization`, se presente
+///
+/// # Ritorna
+/// * bool - true se le credenziali coincidono
+fn authorized(header: Option<&[u8]>) -> bool {
+    let Some(credentials) = header
+        .and_then(|h| h.strip_prefix(b"Basic "))
+        .and_then(|encoded| decode_basic(encoded.trim_ascii()))
+    else {
+        return false;
+    };
+    let Some(colon) = credentials.iter().position(|&b| b == b':') else {
+        return false;
+    };
+    check_credentials(&credentials[..colon], &credentials[colon + 1..])
+}
+
+/// Confronta due segreti in tempo costante: la durata non rivela quanti
+/// byte coincidono
+///
+/// # Argomenti
+/// * `a` - Valore ricevuto
+/// * `b` - Valore atteso
+///
+/// # Ritorna
+/// * bool - true se i due valori sono uguali
+pub fn same_bytes(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Ritorna true se la richiesta viene da un browser, che preferisce il form
+/// di login alla finestra del Basic Auth
+fn is_browser(request_parts: &RequestParts<'_>) -> bool {
+    let accept = request_parts.headers().get("Accept");
+    accept
+        .as_ref()
+        .is_some_and(|value| value.as_raw().windows(9).any(|w| w == b"text/html"))
+}
+
+/// Layer che richiede le credenziali HTTP_AUTH_USER e HTTP_AUTH_PASSWORD
+/// sulle rotte di PROTECTED_PREFIXES
+pub struct AuthLayer;
+
+impl<State, PathParameters> Layer<State, PathParameters> for AuthLayer {
+    type NextState = State;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &State,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let path = request_parts.path().encoded();
+        let decoded = request_path::decoded(path);
+        let protected = PROTECTED_PREFIXES.iter().any(|p| decoded.starts_with(p));
+        if protected && credentials_required() {
+            let cookie = request_parts.headers().get("Cookie");
+            let token = cookie
+                .as_ref()
+                .and_then(|value| session::cookie_token(value.as_raw()));
+            let header = request_parts.headers().get("Authorization");
+            let raw = header.as_ref().map(|value| value.as_raw());
+            if !token.is_some_and(session::is_valid) && !authorized(raw) {
+                REFUSED.fetch_add(1, Ordering::Relaxed);
+                if raw.is_some() {
+                    log::warn!("Auth: credenziali errate per {}", path);
+                }
+                if raw.is_none() && is_browser(&request_parts) {
+                    return session::redirect_to_login(path)
+                        .write_to(next.into_connection(), response_writer)
+                        .await;
+                }
+                let message = Lang::negotiate(&request_parts).strings().auth_required;
+                return Response::new(StatusCode::UNAUTHORIZED, message)
+                    .with_header("WWW-Authenticate", CHALLENGE)
+                    .write_to(next.into_connection(), response_writer)
+                    .await;
+            }
+        }
+        next.run(state, path_parameters, response_writer).await
+    }
+}
@@ -0,0 +1,77 @@
+//! bench.rs - Benchmark dei risolutori sul dispositivo.
+//!
+//! Risolve gli schemi difficili di `sudoku::BENCH_SCHEMAS` con ogni
+//! algoritmo del crate `sudoku` (vedi `sudoku::Backend`) e misura i tempi
+//! con il servizio di timing (timer PIO, oppure timer di embassy se
+//! occupato). I risultati sono su `/api/bench`, rotta di amministrazione
+//! (vedi acl.rs), e con il comando `bench` della console.
+//!
+//! Per misurare l'algoritmo da solo ogni risoluzione gira sull'executor del
+//! core 0 senza cedere il controllo: finché non termina la rete e gli altri
+//! task restano fermi. Ogni risoluzione si ferma dopo `solve::MAX_ITERATIONS`
+//! numeri provati (risultato `timeout`) e tra l'una e l'altra il task cede
+//! il controllo all'executor.
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::solve::MAX_ITERATIONS;
+use crate::timing;
+use picoserve::response::Json;
+use picoserve::routing::{PathRouter, get};
+use sudoku::{BENCH_SCHEMAS, Backend, Sudoku, SudokuError};
+
+/// Risultati di un benchmark completo
+pub type BenchReport = heapless::Vec<BenchResult, { BENCH_SCHEMAS.len() * Backend::ALL.len() }>;
+
+/// Tempo di un algoritmo su uno schema
+#[derive(serde::Serialize)]
+pub struct BenchResult {
+    pub schema: &'static str,
+    pub backend: &'static str,
+    pub solved: bool,
+    /// true se la ricerca si è fermata dopo MAX_ITERATIONS numeri provati
+    pub timeout: bool,
+    pub elapsed_us: u64,
+}
+
+/// Esegue il benchmark
+///
+/// # Ritorna
+/// * BenchReport - Un risultato per ogni coppia schema/algoritmo
+pub async fn run() -> BenchReport {
+    let mut report = BenchReport::new();
+    for (name, schema) in BENCH_SCHEMAS {
+        let mut parsed = Sudoku::default();
+        // Gli schemi sono verificati dai test del crate sudoku
+        if let Err(e) = parsed.parse_compact(schema) {
+            log::error!("Benchmark: schema {} non valido: {:?}", name, e);
+            continue;
+        }
+        for backend in Backend::ALL {
+            let mut sudoku = parsed.clone();
+            let (result, elapsed) =
+                timing::measure(async { sudoku.solve_within(backend, MAX_ITERATIONS) }).await;
+            let _ = report.push(BenchResult {
+                schema: name,
+                backend: backend.name(),
+                solved: result.is_ok(),
+                timeout: matches!(result, Err(SudokuError::Timeout)),
+                elapsed_us: elapsed.as_micros(),
+            });
+            embassy_futures::yield_now().await;
+        }
+    }
+    log::info!("Benchmark dei risolutori completato");
+    report
+}
+
+/// Rotta del benchmark: `/api/bench`
+pub struct BenchRoutes;
+
+impl Routes for BenchRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router.route("/api/bench", get(|| async move { Json(run().await) }))
+    }
+}
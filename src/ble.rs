@@ -0,0 +1,247 @@
+//! ble.rs - Servizio GATT Bluetooth LE per risolvere schemi di Sudoku
+//! senza collegarsi alla rete WiFi.
+//!
+//! Il lato Bluetooth del CYW43439 viene avviato con il firmware
+//! `43439A0_btfw.bin` e lo stack host è `trouble-host`.
+//! Il servizio espone tre caratteristiche:
+//! * `puzzle_in` (write): 81 byte, uno per cella, riga per riga, 0 = cella vuota
+//! * `solution_out` (read/notify): 81 byte con lo schema risolto
+//! * `status` (read/notify): stato dell'ultima richiesta, vedi [`BleStatus`]
+//!
+//! Il modulo è compilato solo con la feature `ble`.
+
+use crate::bus::{self, Event, SolveSource};
+use bt_hci::controller::ExternalController;
+use embassy_futures::join::join;
+use embassy_rp::clocks::RoscRng;
+use sudoku::{Sudoku, SudokuError};
+use trouble_host::prelude::*;
+
+/// Nome con cui la pico si annuncia via Bluetooth
+const BLE_NAME: &str = "picoweb-sudoku";
+
+/// Numero massimo di connessioni contemporanee
+const CONNECTIONS_MAX: usize = 1;
+
+/// Numero massimo di canali L2CAP (segnalazione + ATT)
+const L2CAP_CHANNELS_MAX: usize = 2;
+
+/// Numero di comandi HCI in coda verso il controller
+const HCI_SLOTS: usize = 10;
+
+/// Numero di celle dello schema
+const CELLS: usize = 81;
+
+/// Stato dell'ultima richiesta di risoluzione
+#[repr(u8)]
+#[derive(Clone, Copy)]
+pub enum BleStatus {
+    Idle = 0,
+    Solved = 1,
+    InvalidPuzzle = 2,
+    NoSolution = 3,
+}
+
+/// Server GATT con il solo servizio Sudoku
+#[gatt_server]
+struct Server {
+    sudoku: SudokuService,
+}
+
+/// Servizio GATT Sudoku
+#[gatt_service(uuid = "5d0c0001-8f3a-4c6e-9a4b-7069636f7765")]
+struct SudokuService {
+    /// Schema da risolvere (81 celle, 0 = vuota)
+    #[characteristic(uuid = "5d0c0002-8f3a-4c6e-9a4b-7069636f7765", write)]
+    puzzle_in: [u8; CELLS],
+    /// Schema risolto (81 celle)
+    #[characteristic(uuid = "5d0c0003-8f3a-4c6e-9a4b-7069636f7765", read, notify)]
+    solution_out: [u8; CELLS],
+    /// Stato dell'ultima richiesta (vedi BleStatus)
+    #[characteristic(uuid = "5d0c0004-8f3a-4c6e-9a4b-7069636f7765", read, notify)]
+    status: u8,
+}
+
+#[embassy_executor::task]
+/// Task Bluetooth: avvia lo stack host e gestisce advertising e connessioni
+///
+/// # Argomenti
+/// * `bt_device` - Driver HCI del CYW43
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn ble_task(bt_device: cyw43::bluetooth::BtDriver<'static>) {
+    let controller: ExternalController<_, HCI_SLOTS> = ExternalController::new(bt_device);
+
+    // Indirizzo statico random: i due bit più significativi devono valere 1
+    let mut addr = [0u8; 6];
+    RoscRng.fill_bytes(&mut addr);
+    addr[5] |= 0xc0;
+    let address = Address::random(addr);
+
+    let mut resources: HostResources<DefaultPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX> =
+        HostResources::new();
+    let stack = trouble_host::new(controller, &mut resources).set_random_address(address);
+    let Host {
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+
+    let server = match Server::new_with_config(GapConfig::Peripheral(PeripheralConfig {
+        name: BLE_NAME,
+        appearance: &appearance::UNKNOWN,
+    })) {
+        Ok(server) => server,
+        Err(e) => {
+            log::error!("BLE: impossibile creare il server GATT: {:?}", e);
+            return;
+        }
+    };
+
+    let _ = join(
+        async {
+            loop {
+                if let Err(e) = runner.run().await {
+                    log::error!("BLE: errore nello stack host: {:?}", e);
+                }
+            }
+        },
+        async {
+            loop {
+                crate::tasks::touch("ble");
+                match advertise(&mut peripheral, &server).await {
+                    Ok(conn) => {
+                        log::info!("BLE: client connesso");
+                        gatt_events(&server, &conn).await;
+                        log::info!("BLE: client disconnesso");
+                    }
+                    Err(e) => {
+                        log::warn!("BLE: advertising fallito: {:?}", e);
+                        embassy_time::Timer::after_secs(1).await;
+                    }
+                }
+            }
+        },
+    )
+    .await;
+}
+
+/// Avvia l'advertising e attende la connessione di un client
+///
+/// # Argomenti
+/// * `peripheral` - Ruolo periferica dello stack
+/// * `server` - Server GATT da associare alla connessione
+///
+/// # Ritorna
+/// * Result<GattConnection, BleHostError> - Connessione GATT oppure errore
+async fn advertise<'values, 'server, C: Controller>(
+    peripheral: &mut Peripheral<'values, C, DefaultPacketPool>,
+    server: &'server Server<'values>,
+) -> Result<GattConnection<'values, 'server, DefaultPacketPool>, BleHostError<C::Error>> {
+    let mut adv_data = [0; 31];
+    let len = AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::CompleteLocalName(BLE_NAME.as_bytes()),
+        ],
+        &mut adv_data[..],
+    )?;
+    let advertiser = peripheral
+        .advertise(
+            &Default::default(),
+            Advertisement::ConnectableScannableUndirected {
+                adv_data: &adv_data[..len],
+                scan_data: &[],
+            },
+        )
+        .await?;
+    let conn = advertiser.accept().await?.with_attribute_server(server)?;
+    Ok(conn)
+}
+
+/// Gestisce gli eventi GATT di una connessione fino alla disconnessione
+///
+/// # Argomenti
+/// * `server` - Server GATT
+/// * `conn` - Connessione GATT attiva
+async fn gatt_events(server: &Server<'_>, conn: &GattConnection<'_, '_, DefaultPacketPool>) {
+    let puzzle_in = &server.sudoku.puzzle_in;
+    let _ = server.set(&server.sudoku.status, &(BleStatus::Idle as u8));
+    loop {
+        match conn.next().await {
+            GattConnectionEvent::Disconnected { reason } => {
+                log::info!("BLE: disconnessione: {:?}", reason);
+                break;
+            }
+            GattConnectionEvent::Gatt { event } => {
+                crate::tasks::touch("ble");
+                let puzzle = match &event {
+                    GattEvent::Write(write) if write.handle() == puzzle_in.handle => {
+                        let mut cells = [0u8; CELLS];
+                        let data = write.data();
+                        let len = data.len().min(CELLS);
+                        cells[..len].copy_from_slice(&data[..len]);
+                        Some((cells, data.len() == CELLS))
+                    }
+                    _ => None,
+                };
+
+                match event.accept() {
+                    Ok(reply) => reply.send().await,
+                    Err(e) => log::warn!("BLE: errore nella risposta GATT: {:?}", e),
+                }
+
+                if let Some((cells, complete)) = puzzle {
+                    let status = solve_cells(&cells, complete, server, conn).await;
+                    let _ = server.sudoku.status.notify(conn, &(status as u8)).await;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Risolve lo schema ricevuto e pubblica la soluzione
+///
+/// # Argomenti
+/// * `cells` - Celle ricevute (0 = vuota)
+/// * `complete` - true se sono state ricevute esattamente 81 celle
+/// * `server` - Server GATT
+/// * `conn` - Connessione su cui notificare la soluzione
+///
+/// # Ritorna
+/// * BleStatus - Esito della risoluzione
+async fn solve_cells(
+    cells: &[u8; CELLS],
+    complete: bool,
+    server: &Server<'_>,
+    conn: &GattConnection<'_, '_, DefaultPacketPool>,
+) -> BleStatus {
+    if !complete || cells.iter().any(|&c| c > 9) {
+        return BleStatus::InvalidPuzzle;
+    }
+
+    let mut sudoku = Sudoku::default();
+    for (i, &cell) in cells.iter().enumerate() {
+        sudoku.grid[i / 9][i % 9] = cell;
+    }
+
+    match crate::solve::solve(&mut sudoku).await {
+        Ok(()) => {
+            let mut solution = [0u8; CELLS];
+            for (i, cell) in solution.iter_mut().enumerate() {
+                *cell = sudoku.grid[i / 9][i % 9];
+            }
+            if let Err(e) = server.sudoku.solution_out.notify(conn, &solution).await {
+                log::warn!("BLE: notifica della soluzione fallita: {:?}", e);
+            }
+            bus::publish(Event::PuzzleSolved {
+                source: SolveSource::Ble,
+            });
+            BleStatus::Solved
+        }
+        Err(SudokuError::NoSolution) => BleStatus::NoSolution,
+        Err(_) => BleStatus::InvalidPuzzle,
+    }
+}
@@ -0,0 +1,56 @@
+//! board.rs - Differenze tra le schede supportate.
+//!
+//! La scheda si sceglie con una feature di Cargo: `pico2_w` (Raspberry Pi
+//! Pico 2 W, RP2350, default) oppure `pico_w` (Pico W originale, RP2040).
+//! Ognuna abilita la feature del chip, `rp235x` o `rp2040`, che seleziona
+//! l'inizializzazione di embassy-rp, il layout della memoria (vedi build.rs)
+//! e i moduli legati alla ROM dell'RP2350 (es. ota.rs e system.rs).
+//!
+//! Qui stanno invece i valori che dipendono dalla scheda: nome, dimensione
+//! della flash (da cui gli indirizzi dei blob del CYW43 in
+//! cyw43_firmware.rs e dell'archivio di flash_store.rs), divisore del clock
+//! dello SPI del CYW43 e pin del chip WiFi.
+//!
+//! Mappa dei pin, uguale sulle due schede:
+//! * GP23 - alimentazione del CYW43 (WL_ON)
+//! * GP24 - dati SPI del CYW43 (DIO), condiviso con VBUS sense
+//! * GP25 - chip select del CYW43
+//! * GP29 - clock SPI del CYW43, condiviso con VSYS/3 (vedi brownout.rs)
+
+/// Nome della scheda, riportato da `/api/status`
+#[cfg(feature = "rp235x")]
+pub const NAME: &str = "Raspberry Pi Pico 2 W";
+
+/// Nome della scheda, riportato da `/api/status`
+#[cfg(feature = "rp2040")]
+pub const NAME: &str = "Raspberry Pi Pico W";
+
+/// Dimensione della flash della Pico 2 W
+#[cfg(feature = "rp235x")]
+pub const FLASH_SIZE: usize = 4 * 1024 * 1024;
+
+/// Dimensione della flash della Pico W
+#[cfg(feature = "rp2040")]
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+
+// SPI communication won't work if the speed is too high, so on the RP2350 we use a divider
+// larger than `DEFAULT_CLOCK_DIVIDER`. See: https://github.com/embassy-rs/embassy/issues/3960.
+// The RP2040 runs at 125 MHz and works with the default divider.
+#[cfg(feature = "rp235x")]
+pub const SPI_CLOCK_DIVIDER: fixed::FixedU32<fixed::types::extra::U8> =
+    cyw43_pio::RM2_CLOCK_DIVIDER;
+#[cfg(feature = "rp2040")]
+pub const SPI_CLOCK_DIVIDER: fixed::FixedU32<fixed::types::extra::U8> =
+    cyw43_pio::DEFAULT_CLOCK_DIVIDER;
+
+/// Pin del CYW43 presi dalle periferiche di embassy-rp, nell'ordine
+/// `(pwr, cs, dio, clk)`
+///
+/// # Argomenti
+/// * `$p` - Periferiche restituite da `embassy_rp::init`
+macro_rules! cyw43_pins {
+    ($p:ident) => {
+        ($p.PIN_23, $p.PIN_25, $p.PIN_24, $p.PIN_29)
+    };
+}
+pub(crate) use cyw43_pins;
@@ -0,0 +1,89 @@
+//! body_limit.rs - Risposta 413 alle richieste troppo grandi.
+//!
+//! picoserve legge intestazioni e corpo di una richiesta nel buffer HTTP
+//! del worker (vedi `WorkerPool` in configuration.rs): un corpo che non ci
+//! sta farebbe fallire l'estrazione del form a metà lettura. Il layer
+//! [`BodyLimitLayer`] confronta prima dell'handler la `Content-Length` con
+//! lo spazio del worker (`WorkerPool::max_body`, salvato in AppState) e
+//! risponde `413 Payload Too Large` con una pagina di errore che riporta il
+//! limite. Le rotte che leggono il corpo a pezzi senza bufferizzarlo (es.
+//! l'upload del firmware) sono escluse.
+//!
+//! I worker dei due gruppi hanno buffer diversi: il limite garantito è
+//! quello del gruppo GET, il più piccolo.
+
+use crate::app_state::AppState;
+use crate::errors;
+use crate::i18n::Lang;
+use crate::request_id;
+use crate::request_path;
+use crate::utility::HtmlPage;
+use picoserve::ResponseSent;
+use picoserve::io::Read;
+use picoserve::request::RequestParts;
+use picoserve::response::{IntoResponse, Response, ResponseWriter, StatusCode};
+use picoserve::routing::{Layer, Next};
+
+/// Rotte che leggono il corpo a pezzi, senza limite di dimensione
+const STREAMED_PATHS: [&str; 1] = ["/api/ota"];
+
+/// Ritorna la `Content-Length` dichiarata dalla richiesta
+///
+/// # Argomenti
+/// * `request_parts` - Intestazioni della richiesta
+///
+/// # Ritorna
+/// * Option<usize> - Lunghezza del corpo, None se assente o non valida
+fn content_length(request_parts: &RequestParts<'_>) -> Option<usize> {
+    let header = request_parts.headers().get("Content-Length");
+    header
+        .as_ref()
+        .and_then(|value| core::str::from_utf8(value.as_raw()).ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Layer che risponde 413 se il corpo non sta nel buffer del worker
+pub struct BodyLimitLayer;
+
+impl<PathParameters> Layer<AppState, PathParameters> for BodyLimitLayer {
+    type NextState = AppState;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &AppState,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let path = request_parts.path().encoded();
+        let decoded = request_path::decoded(path);
+        let streamed = STREAMED_PATHS.iter().any(|streamed| decoded.is(streamed));
+        let length = content_length(&request_parts).filter(|_| !streamed);
+        if let Some(length) = length.filter(|&length| length > state.max_body) {
+            log::warn!(
+                "{}: corpo di {} byte oltre il limite di {}",
+                path,
+                length,
+                state.max_body
+            );
+            let lang = Lang::negotiate(&request_parts);
+            let page = HtmlPage::Error {
+                msg: lang.strings().payload_too_large,
+                err: errors::fit(format_args!("{} > {} byte", length, state.max_body)),
+                request_id: request_id::current(),
+                lang,
+            };
+            return Response::new(StatusCode::PAYLOAD_TOO_LARGE, page)
+                .write_to(next.into_connection(), response_writer)
+                .await;
+        }
+        next.run(state, path_parameters, response_writer).await
+    }
+}
@@ -0,0 +1,144 @@
+//! brownout.rs - Salvataggio dei log in flash prima di uno spegnimento.
+//!
+//! Il ring buffer dei log (log_buffer.rs) vive in RAM: dopo un'interruzione
+//! dell'alimentazione i messaggi che l'hanno preceduta, i più utili per
+//! capire cosa è successo, vanno persi. [`brownout_task`] campiona la
+//! tensione VSYS con l'ADC e, quando scende sotto la soglia configurata
+//! (`BROWNOUT_VSYS_MV`), salva in flash la coda del ring buffer e i
+//! contatori persistenti, finché i condensatori tengono in vita la pico.
+//!
+//! L'ADC è condiviso con il sensore di temperatura (vedi sensors.rs).
+//!
+//! Sulla Pico W il GPIO29 (VSYS/3) è il clock dell'SPI del CYW43: la
+//! tensione va quindi portata sul GPIO26 (ADC0) con un partitore esterno
+//! 1:3, come quello della scheda. Senza partitore l'ingresso è flottante:
+//! per questo la soglia di default è 0 (disabilitato).
+//!
+//! Al riavvio [`restore`] rimette i record salvati dall'esecuzione
+//! precedente nel log, quindi in `/logs`, con il prefisso
+//! "prima dello spegnimento".
+
+use crate::clock::Timestamp;
+use crate::flash_store::RecordStore;
+use crate::log_buffer;
+use crate::utility::get_brownout_vsys_mv;
+use embassy_rp::adc::Channel;
+use embassy_time::{Duration, Ticker};
+
+/// Dimensione di uno slot dell'archivio: un salvataggio per slot
+const SLOT_SIZE: usize = 1024;
+
+/// Archivio delle code di log, sotto lo stato dell'aggiornamento del firmware
+static BROWNOUT_STORE: RecordStore<SLOT_SIZE, 0> = RecordStore::new("Brownout", 6, 2);
+
+/// Spazio per le righe di log in un salvataggio
+const PAYLOAD_SIZE: usize = RecordStore::<SLOT_SIZE, 0>::PAYLOAD_CAPACITY;
+
+/// Intervallo tra due campionamenti di VSYS
+const SAMPLE_PERIOD: Duration = Duration::from_millis(10);
+
+/// Isteresi: dopo un salvataggio la tensione deve risalire di tanto
+/// sopra la soglia prima di poterne fare un altro
+const HYSTERESIS_MV: u32 = 200;
+
+/// Rapporto del partitore su VSYS
+const VSYS_DIVIDER: u32 = 3;
+
+/// Tensione di riferimento dell'ADC (mV)
+const ADC_REF_MV: u32 = 3300;
+
+/// Converte una lettura dell'ADC (12 bit) nella tensione VSYS
+fn vsys_mv(raw: u16) -> u32 {
+    raw as u32 * ADC_REF_MV * VSYS_DIVIDER / 4096
+}
+
+/// Salva in flash la coda del ring buffer dei log: più record recenti
+/// possibile, una riga ciascuno, nell'ordine in cui sono stati scritti
+///
+/// # Argomenti
+/// * `boot` - Numero dell'avvio corrente, chiave del record
+fn save_log_tail(boot: u32) {
+    let mut payload = [0u8; PAYLOAD_SIZE];
+    // Le righe vengono scritte dalla fine, dalla più recente, finché c'è spazio
+    let mut start = PAYLOAD_SIZE;
+    let (first, count) = log_buffer::window();
+    for i in (0..count).rev() {
+        let Some(record) = log_buffer::get(first.wrapping_add(i as u32)) else {
+            continue;
+        };
+        let line: heapless::String<{ log_buffer::LOG_LINE_WIDTH }> =
+            crate::errors::fit(format_args!(
+                "{} {:<5} {}: {}\n",
+                Timestamp(record.timestamp_ms),
+                record.level,
+                record.target,
+                record.message
+            ));
+        if line.len() > start {
+            break;
+        }
+        start -= line.len();
+        payload[start..start + line.len()].copy_from_slice(line.as_bytes());
+    }
+    match BROWNOUT_STORE.append(boot as u16, &payload[start..]) {
+        Ok(seq) => log::info!("Brownout: record {} salvato", seq),
+        Err(e) => log::error!("Brownout: scrittura fallita: {:?}", e),
+    }
+}
+
+/// Rimette nel log le righe salvate dall'esecuzione precedente prima dello
+/// spegnimento: da chiamare una sola volta all'avvio, dopo counters::boot
+pub fn restore() {
+    let boots = crate::counters::report().boots;
+    let previous = boots.wrapping_sub(1) as u16;
+    let Some(record) = BROWNOUT_STORE.latest(previous) else {
+        return;
+    };
+    let Ok(text) = core::str::from_utf8(record.payload) else {
+        log::warn!("Brownout: record {} non leggibile", record.seq);
+        return;
+    };
+    log::warn!(
+        "Brownout: l'avvio {} si è concluso con un calo di tensione, ultime righe di log:",
+        previous
+    );
+    for line in text.lines() {
+        log::warn!("prima dello spegnimento: {}", line);
+    }
+}
+
+#[embassy_executor::task]
+/// Task che campiona VSYS e salva log e contatori quando scende sotto la
+/// soglia (avviato solo se `BROWNOUT_VSYS_MV` è configurata)
+///
+/// # Argomenti
+/// * `vsys` - Canale collegato a VSYS tramite il partitore, letto con l'ADC condiviso
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn brownout_task(mut vsys: Channel<'static>) {
+    let mut ticker = Ticker::every(SAMPLE_PERIOD);
+    let mut armed = true;
+    loop {
+        ticker.next().await;
+        crate::tasks::touch("brownout");
+        let Some(raw) = crate::sensors::read(&mut vsys) else {
+            continue;
+        };
+        let mv = vsys_mv(raw);
+        // La soglia è riletta a ogni campione: modificabile con `config set`
+        let threshold = get_brownout_vsys_mv();
+        if threshold == 0 {
+            continue;
+        }
+        if armed && mv < threshold {
+            armed = false;
+            log::error!("Brownout: VSYS a {} mV, salvataggio dei log", mv);
+            crate::counters::flush();
+            save_log_tail(crate::counters::report().boots);
+        } else if !armed && mv > threshold + HYSTERESIS_MV {
+            armed = true;
+            log::warn!("Brownout: VSYS tornata a {} mV", mv);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! bus.rs - Bus degli eventi tra task.
+//!
+//! I moduli pubblicano eventi tipizzati con [`publish`] e chi è interessato
+//! (LED, display, MQTT, webhook, notifiche URC, ...) li riceve con
+//! [`subscribe`], senza che ogni nuova funzionalità debba toccare main.rs o
+//! il modulo che genera l'evento. Il bus è un `PubSubChannel` di embassy:
+//! se un sottoscrittore è lento perde gli eventi più vecchi.
+
+use crate::fault::LedMode;
+use crate::wifi::WifiState;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pubsub::{PubSubChannel, Subscriber};
+
+/// Numero di eventi in coda per ogni sottoscrittore
+const BUS_QUEUE: usize = 8;
+
+/// Numero massimo di sottoscrittori contemporanei
+const BUS_SUBSCRIBERS: usize = 8;
+
+/// Origine di una richiesta di risoluzione
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SolveSource {
+    Web,
+    Serial,
+    Console,
+    Telnet,
+    Ble,
+}
+
+impl SolveSource {
+    /// Nome dell'origine, es. per le notifiche URC
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SolveSource::Web => "web",
+            SolveSource::Serial => "serial",
+            SolveSource::Console => "console",
+            SolveSource::Telnet => "telnet",
+            SolveSource::Ble => "ble",
+        }
+    }
+}
+
+/// Evento di sistema
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// Transizione della connessione WiFi (vedi wifi.rs)
+    WifiStateChanged { state: WifiState },
+    /// Join riuscito alla rete indicata
+    WifiJoined { ssid: heapless::String<32> },
+    /// Risoluzione di uno schema avviata (vedi solve.rs)
+    SolveStarted,
+    /// Schema risolto con successo
+    PuzzleSolved { source: SolveSource },
+    /// Risoluzione fallita: schema impossibile o interrotto per timeout
+    SolveFailed { timeout: bool },
+    /// Modalità del LED cambiata (vedi fault.rs)
+    LedModeChanged { mode: LedMode },
+    /// Pulsante premuto (fronte di discesa su un ingresso con pull-up)
+    ButtonPressed { pin: u8 },
+}
+
+/// Sottoscrittore del bus
+pub type EventSubscriber =
+    Subscriber<'static, CriticalSectionRawMutex, Event, BUS_QUEUE, BUS_SUBSCRIBERS, 1>;
+
+static BUS: PubSubChannel<CriticalSectionRawMutex, Event, BUS_QUEUE, BUS_SUBSCRIBERS, 1> =
+    PubSubChannel::new();
+
+/// Pubblica un evento a tutti i sottoscrittori
+///
+/// # Argomenti
+/// * `event` - Evento da pubblicare
+pub fn publish(event: Event) {
+    log::debug!("evento {:?}", event);
+    BUS.immediate_publisher().publish_immediate(event);
+}
+
+/// Sottoscrive gli eventi del bus
+///
+/// # Ritorna
+/// * Option<EventSubscriber> - None se tutti i posti sono già occupati
+pub fn subscribe() -> Option<EventSubscriber> {
+    BUS.subscriber().ok()
+}
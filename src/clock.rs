@@ -0,0 +1,307 @@
+//! clock.rs - Orologio di sistema (tempo reale).
+//!
+//! Conserva l'istante Unix corrispondente all'avvio, impostato dalla
+//! sincronizzazione (SNTP, RTC o a mano dalla console con `time <secondi>`).
+//! Finché l'orologio non è sincronizzato i timestamp ricadono sull'uptime.
+//! Tutte le destinazioni dei log usano [`Timestamp`], quindi i record sono
+//! confrontabili con i log del router una volta sincronizzato l'orologio.
+//! L'ora locale ([`LocalTime`]) applica lo scostamento `UTC_OFFSET_MINUTES`
+//! della configurazione. Una volta sincronizzato l'orologio, [`DateLayer`]
+//! aggiunge l'intestazione `Date` alle risposte HTTP.
+
+use crate::errors;
+use crate::utility::get_utc_offset_minutes;
+use core::cell::Cell;
+use core::fmt;
+use critical_section::Mutex as CsMutex;
+use embassy_time::Instant;
+use picoserve::ResponseSent;
+use picoserve::io::Read;
+use picoserve::request::RequestParts;
+use picoserve::response::{Body, Connection, HeadersIter, Response, ResponseWriter};
+use picoserve::routing::{Layer, Next};
+
+/// Istante Unix (ms) corrispondente all'avvio, None se non sincronizzato
+static BOOT_UNIX_MS: CsMutex<Cell<Option<u64>>> = CsMutex::new(Cell::new(None));
+
+/// Uptime (ms) e origine dell'ultima sincronizzazione
+static LAST_SYNC: CsMutex<Cell<Option<(u64, SyncSource)>>> = CsMutex::new(Cell::new(None));
+
+/// Millisecondi in un giorno
+const MS_PER_DAY: u64 = 86_400_000;
+
+/// Origine di una sincronizzazione dell'orologio
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SyncSource {
+    /// Comando `time <secondi>` della console
+    Console,
+    /// Server NTP (vedi sntp.rs)
+    Sntp,
+}
+
+impl SyncSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SyncSource::Console => "console",
+            SyncSource::Sntp => "sntp",
+        }
+    }
+}
+
+/// Imposta l'ora corrente
+///
+/// # Argomenti
+/// * `unix_ms` - Millisecondi dal 1970-01-01T00:00:00Z
+/// * `source` - Origine dell'ora
+pub fn set_unix_time_ms(unix_ms: u64, source: SyncSource) {
+    let uptime = Instant::now().as_millis();
+    let boot = unix_ms.saturating_sub(uptime);
+    let previous = critical_section::with(|cs| {
+        LAST_SYNC.borrow(cs).set(Some((uptime, source)));
+        BOOT_UNIX_MS.borrow(cs).replace(Some(boot))
+    });
+    if previous.is_none() {
+        log::info!(
+            "Orologio sincronizzato ({}): {}",
+            source.as_str(),
+            Timestamp::now()
+        );
+    }
+}
+
+/// Ritorna l'istante Unix corrispondente all'avvio, se sincronizzato
+fn boot_unix_ms() -> Option<u64> {
+    critical_section::with(|cs| BOOT_UNIX_MS.borrow(cs).get())
+}
+
+/// Ritorna true se l'orologio è sincronizzato
+pub fn is_synced() -> bool {
+    boot_unix_ms().is_some()
+}
+
+/// Ritorna l'ora corrente in millisecondi Unix, se sincronizzato
+pub fn now_unix_ms() -> Option<u64> {
+    boot_unix_ms().map(|boot| boot + Instant::now().as_millis())
+}
+
+/// Converte un numero di giorni dal 1970-01-01 in (anno, mese, giorno)
+/// (algoritmo "civil_from_days" di Howard Hinnant)
+///
+/// # Argomenti
+/// * `days` - Giorni dal 1970-01-01
+///
+/// # Ritorna
+/// * (u64, u64, u64) - Anno, mese (1-12) e giorno (1-31)
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Scrive un istante Unix in formato ISO 8601
+///
+/// # Argomenti
+/// * `f` - Destinazione
+/// * `unix_ms` - Millisecondi dal 1970-01-01T00:00:00Z
+/// * `offset_minutes` - Scostamento da UTC del fuso in cui scrivere l'ora
+fn write_iso8601(f: &mut fmt::Formatter<'_>, unix_ms: u64, offset_minutes: i32) -> fmt::Result {
+    let local_ms = unix_ms.saturating_add_signed(offset_minutes as i64 * 60_000);
+    let (year, month, day) = civil_from_days(local_ms / MS_PER_DAY);
+    let ms_of_day = local_ms % MS_PER_DAY;
+    write!(
+        f,
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        ms_of_day / 3_600_000,
+        ms_of_day / 60_000 % 60,
+        ms_of_day / 1000 % 60,
+        ms_of_day % 1000
+    )?;
+    if offset_minutes == 0 {
+        return f.write_str("Z");
+    }
+    let sign = if offset_minutes < 0 { '-' } else { '+' };
+    let offset = offset_minutes.unsigned_abs();
+    write!(f, "{}{:02}:{:02}", sign, offset / 60, offset % 60)
+}
+
+/// Timestamp di un evento, espresso come uptime in millisecondi.
+/// Viene visualizzato in formato ISO 8601 UTC se l'orologio è
+/// sincronizzato, altrimenti come uptime.
+#[derive(Clone, Copy)]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+    /// Timestamp dell'istante corrente
+    pub fn now() -> Self {
+        Timestamp(Instant::now().as_millis())
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(boot) = boot_unix_ms() else {
+            return write!(f, "{:>10}ms", self.0);
+        };
+        write_iso8601(f, boot + self.0, 0)
+    }
+}
+
+/// Istante Unix in millisecondi, visualizzato in formato ISO 8601 nell'ora
+/// locale (scostamento `UTC_OFFSET_MINUTES`)
+#[derive(Clone, Copy)]
+pub struct LocalTime(pub u64);
+
+impl fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_iso8601(f, self.0, get_utc_offset_minutes())
+    }
+}
+
+/// Istante Unix in millisecondi, visualizzato nel formato delle date HTTP
+/// (RFC 9110, es. `Sun, 06 Nov 1994 08:49:37 GMT`)
+#[derive(Clone, Copy)]
+pub struct HttpDate(pub u64);
+
+impl fmt::Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        let days = self.0 / MS_PER_DAY;
+        let (year, month, day) = civil_from_days(days);
+        let secs_of_day = self.0 % MS_PER_DAY / 1000;
+        write!(
+            f,
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            // Il 1970-01-01 era un giovedì
+            WEEKDAYS[(days % 7) as usize],
+            day,
+            MONTHS[(month - 1) as usize],
+            year,
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60
+        )
+    }
+}
+
+/// Writer che aggiunge l'intestazione `Date` alla risposta
+struct DateWriter<W> {
+    inner: W,
+    unix_ms: u64,
+}
+
+impl<W: ResponseWriter> ResponseWriter for DateWriter<W> {
+    type Error = W::Error;
+
+    async fn write_response<R: Read<Error = Self::Error>, H: HeadersIter, B: Body>(
+        self,
+        connection: Connection<'_, R>,
+        response: Response<H, B>,
+    ) -> Result<ResponseSent, Self::Error> {
+        let response = response.with_header("Date", HttpDate(self.unix_ms));
+        self.inner.write_response(connection, response).await
+    }
+}
+
+/// Layer che aggiunge l'intestazione `Date` alle risposte quando l'orologio
+/// è sincronizzato (RFC 9110 la vieta a chi non ha un orologio affidabile)
+pub struct DateLayer;
+
+impl<State, PathParameters> Layer<State, PathParameters> for DateLayer {
+    type NextState = State;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &State,
+        path_parameters: PathParameters,
+        _request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        match now_unix_ms() {
+            Some(unix_ms) => {
+                let writer = DateWriter {
+                    inner: response_writer,
+                    unix_ms,
+                };
+                next.run(state, path_parameters, writer).await
+            }
+            None => next.run(state, path_parameters, response_writer).await,
+        }
+    }
+}
+
+/// Attività programmata elencata da /api/time
+#[derive(serde::Serialize)]
+pub struct ScheduledJob {
+    name: &'static str,
+    next_unix_ms: u64,
+    /// Prossima esecuzione nell'ora locale
+    next_local: heapless::String<32>,
+}
+
+/// Stato dell'orologio restituito da /api/time
+#[derive(serde::Serialize)]
+pub struct ClockStatus {
+    synced: bool,
+    uptime_ms: u64,
+    unix_ms: Option<u64>,
+    /// Ora corrente (ISO 8601) o uptime se non sincronizzato
+    now: heapless::String<32>,
+    /// Ora locale corrente, se sincronizzato
+    local: Option<heapless::String<32>>,
+    utc_offset_minutes: i32,
+    /// Origine dell'ultima sincronizzazione
+    sync_source: Option<&'static str>,
+    /// Secondi trascorsi dall'ultima sincronizzazione
+    last_sync_secs: Option<u64>,
+    /// Prossime esecuzioni delle attività programmate
+    scheduled: heapless::Vec<ScheduledJob, 4>,
+}
+
+/// Ritorna lo stato dell'orologio
+///
+/// # Ritorna
+/// * ClockStatus - Stato corrente
+pub fn status() -> ClockStatus {
+    let timestamp = Timestamp::now();
+    let unix_ms = now_unix_ms();
+    let last_sync = critical_section::with(|cs| LAST_SYNC.borrow(cs).get());
+    let mut scheduled = heapless::Vec::new();
+    if let Some(next) = crate::maintenance::next_reboot_unix_ms() {
+        let _ = scheduled.push(ScheduledJob {
+            name: "maintenance-reboot",
+            next_unix_ms: next,
+            next_local: errors::fit(format_args!("{}", LocalTime(next))),
+        });
+    }
+    ClockStatus {
+        synced: unix_ms.is_some(),
+        uptime_ms: timestamp.0,
+        unix_ms,
+        now: errors::fit(format_args!("{}", timestamp)),
+        local: unix_ms.map(|ms| errors::fit(format_args!("{}", LocalTime(ms)))),
+        utc_offset_minutes: get_utc_offset_minutes(),
+        sync_source: last_sync.map(|(_, source)| source.as_str()),
+        last_sync_secs: last_sync.map(|(at, _)| timestamp.0.saturating_sub(at) / 1000),
+        scheduled,
+    }
+}
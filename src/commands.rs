@@ -0,0 +1,544 @@
+//! commands.rs - Comandi di gestione condivisi da tutti i trasporti.
+//!
+//! La console su UART0, la UART1, la USB CDC, la shell telnet (telnet.rs) e
+//! l'API di amministrazione (`POST /api/command`) passano ogni riga ricevuta
+//! a [`handle_line`]: le righe che iniziano con `AT` vanno al protocollo
+//! `serial_proto`, le altre vengono interpretate con [`parse`] ed eseguite
+//! con [`execute`]. Le risposte sono scritte riga per riga su un
+//! [`LineSink`], così ogni trasporto espone gli stessi comandi e si limita
+//! a inoltrarne l'output.
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::bench;
+use crate::bus::{self, Event, SolveSource};
+use crate::clock;
+use crate::config_store;
+use crate::configuration::{self, LOG_LEVEL_DEFAULT};
+use crate::counters;
+use crate::drain::{self, DrainAction};
+use crate::errors;
+use crate::fault::{self, LedMode};
+use crate::i18n::Lang;
+use crate::log_buffer;
+use crate::logging;
+use crate::resources;
+use crate::safe_mode;
+use crate::serial_proto::{self, LineSink};
+use crate::system;
+use crate::tasks;
+use crate::utility::{
+    self, ConfigError, get_gateway_address, get_ip_address, get_ssid, get_subnet_mask,
+};
+use crate::wifi;
+use crate::wifi_stats;
+use embassy_time::Instant;
+use picoserve::routing::{PathRouter, post};
+use sudoku::{Sudoku, SudokuError};
+
+/// Lunghezza massima di una riga di comando
+pub const LINE_CAPACITY: usize = 200;
+
+/// Numero di record mostrati di default dal comando `log`
+const DEFAULT_LOG_LINES: usize = 10;
+
+/// Dimensione massima della risposta di `POST /api/command`
+const HTTP_OUTPUT_CAPACITY: usize = 1536;
+
+/// Comando di gestione riconosciuto
+pub enum Command<'a> {
+    Help,
+    Status,
+    Solve(&'a str),
+    Config,
+    Ip,
+    ConfigGet(&'a str),
+    ConfigSet {
+        key: &'a str,
+        value: &'a str,
+    },
+    Led(LedMode),
+    Log(usize),
+    LogLevel,
+    /// Livello per tutti i target, None per tornare a `LOG_LEVELS`
+    SetLogLevel(Option<log::LevelFilter>),
+    Time(Option<u64>),
+    Ps,
+    Bench,
+    Reboot,
+    Bootsel,
+    Drain(DrainAction),
+}
+
+/// Errori di parsing dei comandi
+#[derive(Debug)]
+pub enum CommandError {
+    UnknownCommand,
+    InvalidArguments,
+}
+
+/// Interpreta una riga come comando di gestione
+///
+/// # Argomenti
+/// * `line` - Riga ricevuta (senza terminatore)
+///
+/// # Ritorna
+/// * Result<Command, CommandError> - Comando riconosciuto oppure errore
+pub fn parse(line: &str) -> Result<Command<'_>, CommandError> {
+    let line = line.trim();
+    let (cmd, args) = line.split_once(' ').unwrap_or((line, ""));
+    let args = args.trim();
+    match cmd {
+        "help" => Ok(Command::Help),
+        "status" => Ok(Command::Status),
+        "solve" if !args.is_empty() => Ok(Command::Solve(args)),
+        "config" if args.is_empty() => Ok(Command::Config),
+        "config" => {
+            let (action, rest) = args.split_once(' ').unwrap_or((args, ""));
+            let rest = rest.trim();
+            match action {
+                "get" if !rest.is_empty() => Ok(Command::ConfigGet(rest)),
+                "set" => {
+                    let (key, value) = rest.split_once(' ').unwrap_or((rest, ""));
+                    if key.is_empty() {
+                        return Err(CommandError::InvalidArguments);
+                    }
+                    Ok(Command::ConfigSet {
+                        key,
+                        value: value.trim(),
+                    })
+                }
+                _ => Err(CommandError::InvalidArguments),
+            }
+        }
+        "ip" => Ok(Command::Ip),
+        "led" => match args {
+            "on" => Ok(Command::Led(LedMode::On)),
+            "off" => Ok(Command::Led(LedMode::Off)),
+            "auto" => Ok(Command::Led(LedMode::Auto)),
+            "blink" => Ok(Command::Led(LedMode::Blink)),
+            _ => Err(CommandError::InvalidArguments),
+        },
+        "log" if args.is_empty() => Ok(Command::Log(DEFAULT_LOG_LINES)),
+        "log" => args
+            .parse()
+            .map(Command::Log)
+            .map_err(|_| CommandError::InvalidArguments),
+        "loglevel" if args.is_empty() => Ok(Command::LogLevel),
+        "loglevel" if args == "default" => Ok(Command::SetLogLevel(None)),
+        "loglevel" => args
+            .parse()
+            .map(|level| Command::SetLogLevel(Some(level)))
+            .map_err(|_| CommandError::InvalidArguments),
+        "time" if args.is_empty() => Ok(Command::Time(None)),
+        "time" => args
+            .parse()
+            .map(|secs| Command::Time(Some(secs)))
+            .map_err(|_| CommandError::InvalidArguments),
+        "ps" => Ok(Command::Ps),
+        "bench" => Ok(Command::Bench),
+        "reboot" => Ok(Command::Reboot),
+        "bootsel" => Ok(Command::Bootsel),
+        "drain" => match args {
+            "reboot" => Ok(Command::Drain(DrainAction::Reboot)),
+            "bootsel" => Ok(Command::Drain(DrainAction::Bootsel)),
+            _ => Err(CommandError::InvalidArguments),
+        },
+        "solve" => Err(CommandError::InvalidArguments),
+        _ => Err(CommandError::UnknownCommand),
+    }
+}
+
+/// Interpreta ed esegue una riga ricevuta da un trasporto
+///
+/// # Argomenti
+/// * `line` - Riga ricevuta
+/// * `source` - Origine della riga (per gli eventi pubblicati sul bus)
+/// * `sink` - Destinazione della risposta
+pub async fn handle_line<S: LineSink>(line: &str, source: SolveSource, sink: &mut S) {
+    let line = line.trim();
+    if line.is_empty() {
+        return;
+    }
+    if serial_proto::is_at_command(line) {
+        serial_proto::handle_line(line, sink).await;
+        return;
+    }
+    match parse(line) {
+        Ok(cmd) => execute(cmd, source, sink).await,
+        Err(CommandError::UnknownCommand) => {
+            sink.write_line("comando sconosciuto, digitare 'help'")
+                .await
+        }
+        Err(CommandError::InvalidArguments) => {
+            sink.write_line("argomenti non validi, digitare 'help'")
+                .await
+        }
+    }
+}
+
+/// Esegue un comando di gestione
+///
+/// # Argomenti
+/// * `cmd` - Comando da eseguire
+/// * `source` - Origine del comando
+/// * `sink` - Destinazione della risposta
+pub async fn execute<S: LineSink>(cmd: Command<'_>, source: SolveSource, sink: &mut S) {
+    match cmd {
+        Command::Help => {
+            for line in Lang::configured().strings().help.lines() {
+                sink.write_line(line).await;
+            }
+        }
+        Command::Status => status(sink).await,
+        Command::Solve(schema) => match solve(schema, source).await {
+            Ok(sudoku) => {
+                for row in sudoku.grid.iter() {
+                    let mut line = heapless::String::<18>::new();
+                    for cell in row.iter() {
+                        let _ = line.push((b'0' + cell) as char);
+                        let _ = line.push(' ');
+                    }
+                    sink.write_line(line.trim_end()).await;
+                }
+            }
+            Err(e) => {
+                let out: heapless::String<64> = errors::fit(format_args!("errore: {:?}", e));
+                sink.write_line(&out).await;
+            }
+        },
+        Command::Config => {
+            let ip = get_ip_address();
+            let gw = get_gateway_address();
+            let out: heapless::String<96> = errors::fit(format_args!("ssid: {}", get_ssid()));
+            sink.write_line(&out).await;
+            let out: heapless::String<64> = errors::fit(format_args!(
+                "ip: {}.{}.{}.{}/{}",
+                ip[0],
+                ip[1],
+                ip[2],
+                ip[3],
+                get_subnet_mask()
+            ));
+            sink.write_line(&out).await;
+            let out: heapless::String<64> = errors::fit(format_args!(
+                "gateway: {}.{}.{}.{}",
+                gw[0], gw[1], gw[2], gw[3]
+            ));
+            sink.write_line(&out).await;
+            let out: heapless::String<64> =
+                errors::fit(format_args!("indirizzo: {}", wifi::addressing()));
+            sink.write_line(&out).await;
+        }
+        Command::Ip => ip(sink).await,
+        Command::ConfigGet(key) => match utility::get_config(key) {
+            Some(_) if configuration::is_secret(key) => {
+                let out: heapless::String<64> = errors::fit(format_args!("{}=***", key));
+                sink.write_line(&out).await;
+            }
+            Some(value) => {
+                let out: heapless::String<96> = errors::fit(format_args!("{}={}", key, value));
+                sink.write_line(&out).await;
+            }
+            None => sink.write_line("errore: chiave sconosciuta").await,
+        },
+        Command::ConfigSet { key, value } => match config_store::save(key, value) {
+            Ok(()) => sink.write_line("ok, salvato in flash").await,
+            Err(ConfigError::UnknownKey) => sink.write_line("errore: chiave sconosciuta").await,
+            Err(e) => {
+                let out: heapless::String<64> = errors::fit(format_args!("errore: {:?}", e));
+                sink.write_line(&out).await;
+            }
+        },
+        Command::Led(mode) => {
+            fault::set_led_mode(mode);
+            sink.write_line("ok").await;
+        }
+        Command::Log(max) => {
+            let (first, count) = log_buffer::window();
+            let skip = count.saturating_sub(max);
+            for i in skip..count {
+                let record = log_buffer::get(first.wrapping_add(i as u32));
+                let line = log_buffer::render_line(record.as_ref());
+                sink.write_line(line.trim_end()).await;
+            }
+        }
+        Command::LogLevel => {
+            let out: heapless::String<64> = match logging::level_override() {
+                Some(level) => errors::fit(format_args!("livello: {} (tutti i moduli)", level)),
+                None => errors::fit(format_args!(
+                    "livello: {} (LOG_LEVELS di configuration.rs)",
+                    LOG_LEVEL_DEFAULT
+                )),
+            };
+            sink.write_line(&out).await;
+        }
+        Command::SetLogLevel(level) => {
+            logging::set_level_override(level);
+            sink.write_line("ok").await;
+        }
+        Command::Time(secs) => {
+            if let Some(secs) = secs {
+                clock::set_unix_time_ms(secs * 1000, clock::SyncSource::Console);
+            }
+            let synced = if clock::is_synced() {
+                ""
+            } else {
+                " (uptime, orologio non sincronizzato)"
+            };
+            let out: heapless::String<96> =
+                errors::fit(format_args!("{}{}", clock::Timestamp::now(), synced));
+            sink.write_line(&out).await;
+        }
+        Command::Ps => {
+            let now = Instant::now().as_millis() as u32;
+            sink.write_line("task         ist.  avvio (s)  inattivo (ms)")
+                .await;
+            for task in tasks::list() {
+                let out: heapless::String<64> = errors::fit(format_args!(
+                    "{:<12} {:>4}  {:>9}  {:>13}",
+                    task.name,
+                    task.instances,
+                    task.spawned_at_ms / 1000,
+                    now.wrapping_sub(task.last_activity_ms)
+                ));
+                sink.write_line(&out).await;
+            }
+        }
+        Command::Bench => {
+            sink.write_line("schema          algoritmo      risolto  tempo (us)")
+                .await;
+            for result in bench::run().await {
+                let out: heapless::String<64> = errors::fit(format_args!(
+                    "{:<15} {:<14} {:<7}  {:>10}",
+                    result.schema,
+                    result.backend,
+                    match (result.solved, result.timeout) {
+                        (true, _) => "si",
+                        (false, true) => "timeout",
+                        (false, false) => "no",
+                    },
+                    result.elapsed_us
+                ));
+                sink.write_line(&out).await;
+            }
+        }
+        // Da /api/command il riavvio passa dallo svuotamento (vedi drain.rs):
+        // il reset avviene dopo l'invio della risposta, non dentro l'handler
+        Command::Reboot if source == SolveSource::Web => {
+            request_drain(DrainAction::Reboot, sink).await;
+        }
+        Command::Bootsel if source == SolveSource::Web => {
+            request_drain(DrainAction::Bootsel, sink).await;
+        }
+        Command::Reboot => {
+            sink.write_line("riavvio...").await;
+            system::reboot().await;
+        }
+        Command::Bootsel => {
+            sink.write_line("riavvio in BOOTSEL...").await;
+            system::enter_bootsel().await;
+        }
+        Command::Drain(action) => request_drain(action, sink).await,
+    }
+}
+
+/// Chiede lo svuotamento del web server seguito da un riavvio
+///
+/// # Argomenti
+/// * `action` - Riavvio da eseguire a svuotamento concluso
+/// * `sink` - Destinazione della risposta
+async fn request_drain<S: LineSink>(action: DrainAction, sink: &mut S) {
+    if drain::request(action) {
+        let out: heapless::String<96> = errors::fit(format_args!(
+            "svuotamento del web server ({} connessioni in corso), poi riavvio",
+            drain::in_flight()
+        ));
+        sink.write_line(&out).await;
+    } else {
+        sink.write_line("errore: svuotamento già in corso").await;
+    }
+}
+
+/// Scrive l'indirizzo in uso, il gateway e i server DNS
+///
+/// # Argomenti
+/// * `sink` - Destinazione della risposta
+async fn ip<S: LineSink>(sink: &mut S) {
+    let stack = resources::get::<embassy_net::Stack<'static>>();
+    let Some(config) = stack.and_then(|s| s.config_v4()) else {
+        sink.write_line("ip: nessun indirizzo").await;
+        return;
+    };
+    let out: heapless::String<64> = errors::fit(format_args!(
+        "ip: {} ({})",
+        config.address,
+        wifi::addressing()
+    ));
+    sink.write_line(&out).await;
+    if let Some(gateway) = config.gateway {
+        let out: heapless::String<48> = errors::fit(format_args!("gateway: {}", gateway));
+        sink.write_line(&out).await;
+    }
+    for dns in config.dns_servers.iter() {
+        let out: heapless::String<48> = errors::fit(format_args!("dns: {}", dns));
+        sink.write_line(&out).await;
+    }
+}
+
+/// Scrive lo stato del sistema
+///
+/// # Argomenti
+/// * `sink` - Destinazione della risposta
+async fn status<S: LineSink>(sink: &mut S) {
+    let stack = resources::get::<embassy_net::Stack<'static>>();
+    let link = if stack.is_some_and(|s| s.is_link_up()) {
+        "up"
+    } else {
+        "down"
+    };
+    let out: heapless::String<32> =
+        errors::fit(format_args!("uptime: {}s", Instant::now().as_secs()));
+    sink.write_line(&out).await;
+    let out: heapless::String<32> = errors::fit(format_args!("link: {}", link));
+    sink.write_line(&out).await;
+    let out: heapless::String<32> =
+        errors::fit(format_args!("versione: {}", env!("CARGO_PKG_VERSION")));
+    sink.write_line(&out).await;
+    if let Some(config) = stack.and_then(|s| s.config_v4()) {
+        let out: heapless::String<48> = errors::fit(format_args!("ip: {}", config.address));
+        sink.write_line(&out).await;
+    }
+    let wifi = wifi_stats::report();
+    let out: heapless::String<128> = errors::fit(format_args!(
+        "wifi: {}, {} join ({} falliti), {} disconnessioni, associato in {} ms",
+        wifi.state,
+        wifi.join_attempts,
+        wifi.join_failures,
+        wifi.disconnects,
+        wifi.time_to_associate_ms.unwrap_or_default()
+    ));
+    sink.write_line(&out).await;
+    let totals = counters::report();
+    let out: heapless::String<96> = errors::fit(format_args!(
+        "avvii: {}, schemi risolti: {}, uptime totale: {}s",
+        totals.boots, totals.lifetime_solves, totals.total_uptime_secs
+    ));
+    sink.write_line(&out).await;
+    let handled = errors::counts();
+    let out: heapless::String<96> = errors::fit(format_args!(
+        "errori: uart rx {}, uart tx {}, testi troncati {}",
+        handled.uart_rx, handled.uart_tx, handled.truncated
+    ));
+    sink.write_line(&out).await;
+    if drain::is_draining() {
+        let out: heapless::String<64> = errors::fit(format_args!(
+            "manutenzione: svuotamento, {} connessioni in corso",
+            drain::in_flight()
+        ));
+        sink.write_line(&out).await;
+    }
+    if safe_mode::is_active() {
+        sink.write_line("modalità provvisoria: attiva ('reboot' per uscire)")
+            .await;
+    }
+}
+
+/// Legge uno schema in formato compatto (81 caratteri, '0', '.' o '_' per
+/// le celle vuote) oppure nel formato a righe
+///
+/// # Argomenti
+/// * `schema` - Schema da leggere
+///
+/// # Ritorna
+/// * Result<Sudoku, SudokuError> - Schema letto oppure errore
+pub fn parse_schema(schema: &str) -> Result<Sudoku, SudokuError> {
+    let mut sudoku = Sudoku::default();
+    if schema.len() == 81 && !schema.contains(',') {
+        sudoku.parse_compact(schema)?;
+    } else {
+        sudoku.parse(schema)?;
+    }
+    Ok(sudoku)
+}
+
+/// Risolve uno schema in uno dei formati di [`parse_schema`], pubblicando
+/// l'evento `PuzzleSolved` in caso di successo
+///
+/// # Argomenti
+/// * `schema` - Schema da risolvere
+/// * `source` - Origine della richiesta
+///
+/// # Ritorna
+/// * Result<Sudoku, SudokuError> - Schema risolto oppure errore
+pub async fn solve(schema: &str, source: SolveSource) -> Result<Sudoku, SudokuError> {
+    let mut sudoku = parse_schema(schema)?;
+    crate::solve::solve(&mut sudoku).await?;
+    bus::publish(Event::PuzzleSolved { source });
+    Ok(sudoku)
+}
+
+/// Riga di comando inviata a `POST /api/command` (form con il campo `line`)
+#[derive(serde::Deserialize)]
+pub struct CommandForm {
+    pub line: heapless::String<LINE_CAPACITY>,
+}
+
+/// Risposta testuale di un comando eseguito via HTTP
+#[derive(Default)]
+pub struct CommandOutput(heapless::String<HTTP_OUTPUT_CAPACITY>);
+
+impl LineSink for CommandOutput {
+    async fn write_line(&mut self, line: &str) {
+        // Le righe che non entrano nella risposta vengono scartate
+        if self.0.len() + line.len() < HTTP_OUTPUT_CAPACITY {
+            let _ = self.0.push_str(line);
+            let _ = self.0.push('\n');
+        }
+    }
+}
+
+impl picoserve::response::Content for CommandOutput {
+    fn content_type(&self) -> &'static str {
+        "text/plain; charset=utf-8"
+    }
+
+    fn content_length(&self) -> usize {
+        self.0.len()
+    }
+
+    async fn write_content<W: picoserve::io::Write>(self, mut writer: W) -> Result<(), W::Error> {
+        writer.write_all(self.0.as_bytes()).await
+    }
+}
+
+/// Esegue un comando ricevuto dall'API di amministrazione
+///
+/// # Argomenti
+/// * `line` - Riga di comando
+///
+/// # Ritorna
+/// * CommandOutput - Output del comando, una riga per risposta
+pub async fn run(line: &str) -> CommandOutput {
+    let mut output = CommandOutput::default();
+    handle_line(line, SolveSource::Web, &mut output).await;
+    output
+}
+
+/// Rotte di amministrazione: `POST /api/command`
+pub struct AdminRoutes;
+
+impl Routes for AdminRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router.route(
+            "/api/command",
+            post(
+                |picoserve::extract::Form(form): picoserve::extract::Form<CommandForm>| async move {
+                    run(&form.line).await
+                },
+            ),
+        )
+    }
+}
@@ -0,0 +1,180 @@
+//! config_store.rs - Configurazione salvata in flash.
+//!
+//! La configurazione non è più fissata nel firmware: `config set`,
+//! `AT+WIFI=`, `POST /settings` e il form di `/provision` salvano i valori
+//! con [`save`] in un archivio di flash_store.rs, e [`load`] li riapplica a
+//! ogni avvio, prima di qualunque lettura della configurazione. La stringa
+//! CONFIG di configuration.rs resta solo l'elenco delle chiavi e dei loro
+//! valori di default. I getter di utility.rs (es. `get_ssid`,
+//! `get_ip_address`) leggono quindi i valori salvati; per le impostazioni di
+//! rete ci sono qui i setter tipizzati corrispondenti.
+//!
+//! Ogni valore è un record `CHIAVE=valore`; il numero del record è un hash
+//! del nome, così la compattazione dell'archivio conserva l'ultimo valore
+//! di ogni chiave anche se CONFIG cambia tra un firmware e l'altro (il nome
+//! nel record viene comunque verificato alla lettura).
+//!
+//! L'archivio conserva al più [`PERSISTED_KEYS`] chiavi diverse: salvare una
+//! chiave nuova oltre questo numero fallisce con `ConfigError::StoreFull`,
+//! senza modificare la configurazione in uso. Le chiavi già salvate si
+//! possono sempre riscrivere.
+
+use crate::flash_store::{RecordStore, StoreError};
+use crate::utility::{ConfigError, config_keys, set_config};
+
+/// Numero massimo di chiavi salvate, come le modifiche in RAM (vedi
+/// utility.rs); deve restare sotto gli slot di un settore (vedi flash_store.rs)
+pub const PERSISTED_KEYS: usize = 16;
+
+/// Dimensione di uno slot: nome della chiave, '=' e valore
+const SLOT_SIZE: usize = 128;
+
+/// Archivio della configurazione, sotto i log salvati prima di uno spegnimento
+static CONFIG_STORE: RecordStore<SLOT_SIZE, PERSISTED_KEYS> =
+    RecordStore::new("Configurazione", 8, 2);
+
+/// Numero del record di una chiave (FNV-1a ridotto a 16 bit)
+fn record_key(key: &str) -> u16 {
+    let hash = key.bytes().fold(0x811C_9DC5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+    (hash ^ (hash >> 16)) as u16
+}
+
+/// Modifica un valore della configurazione e lo salva in flash, così
+/// resta valido anche dopo il riavvio (i valori letti solo all'avvio, es.
+/// l'indirizzo IP, hanno effetto dal riavvio successivo)
+///
+/// # Argomenti
+/// * `key` - Nome di una chiave presente in CONFIG
+/// * `value` - Nuovo valore
+///
+/// # Ritorna
+/// * Result<(), ConfigError> - Errore se la chiave non esiste, il valore è troppo
+///   lungo, l'archivio è pieno o la scrittura in flash fallisce
+pub fn save(key: &str, value: &str) -> Result<(), ConfigError> {
+    // Le chiavi sconosciute sono rifiutate da set_config
+    if config_keys().any(|k| k == key) && !CONFIG_STORE.accepts(record_key(key)) {
+        log::warn!("Configurazione: {} non salvato, archivio pieno", key);
+        return Err(ConfigError::StoreFull);
+    }
+    set_config(key, value)?;
+    let record: heapless::String<{ RecordStore::<SLOT_SIZE, PERSISTED_KEYS>::PAYLOAD_CAPACITY }> =
+        crate::errors::fit(format_args!("{}={}", key, value));
+    match CONFIG_STORE.append(record_key(key), record.as_bytes()) {
+        Ok(seq) => {
+            log::info!("Configurazione: {} salvato in flash (record {})", key, seq);
+            Ok(())
+        }
+        Err(StoreError::Full) => Err(ConfigError::StoreFull),
+        Err(e) => {
+            log::error!("Configurazione: salvataggio di {} fallito: {:?}", key, e);
+            Err(ConfigError::Storage)
+        }
+    }
+}
+
+/// Applica i valori salvati in flash: da chiamare una sola volta all'avvio,
+/// dopo flash_store::init e prima di leggere la configurazione
+pub fn load() {
+    for key in config_keys() {
+        let Some(record) = CONFIG_STORE.latest(record_key(key)) else {
+            continue;
+        };
+        let value = core::str::from_utf8(record.payload)
+            .ok()
+            .and_then(|text| text.strip_prefix(key)?.strip_prefix('='));
+        match value.map(|value| set_config(key, value)) {
+            Some(Ok(())) => {}
+            Some(Err(e)) => log::warn!("Configurazione: {} salvato ma non applicato: {:?}", key, e),
+            // Collisione dell'hash con un'altra chiave, o record illeggibile
+            None => {}
+        }
+    }
+}
+
+/// Scrive un indirizzo nel formato di CONFIG, es. "192, 168, 1, 115"
+fn ipv4_value(address: [u8; 4]) -> heapless::String<20> {
+    let [a, b, c, d] = address;
+    crate::errors::fit(format_args!("{}, {}, {}, {}", a, b, c, d))
+}
+
+/// Salva l'SSID della rete WiFi (`WIFI_NETWORK`)
+///
+/// # Argomenti
+/// * `ssid` - Nome della rete
+///
+/// # Ritorna
+/// * Result<(), ConfigError> - Errore se il valore è troppo lungo o la scrittura fallisce
+pub fn set_ssid(ssid: &str) -> Result<(), ConfigError> {
+    save("WIFI_NETWORK", ssid)
+}
+
+/// Salva la password della rete WiFi (`WIFI_PASSWORD`)
+///
+/// # Argomenti
+/// * `password` - Password della rete, vuota per una rete aperta
+///
+/// # Ritorna
+/// * Result<(), ConfigError> - Errore se il valore è troppo lungo o la scrittura fallisce
+pub fn set_wifi_password(password: &str) -> Result<(), ConfigError> {
+    save("WIFI_PASSWORD", password)
+}
+
+/// Salva l'indirizzo IP statico (`IP_ADDRESS`)
+///
+/// # Argomenti
+/// * `address` - Indirizzo della pico
+///
+/// # Ritorna
+/// * Result<(), ConfigError> - Errore se la scrittura fallisce
+pub fn set_ip_address(address: [u8; 4]) -> Result<(), ConfigError> {
+    save("IP_ADDRESS", &ipv4_value(address))
+}
+
+/// Salva l'indirizzo del gateway (`GATEWAY_ADDRESS`)
+///
+/// # Argomenti
+/// * `address` - Indirizzo del gateway
+///
+/// # Ritorna
+/// * Result<(), ConfigError> - Errore se la scrittura fallisce
+pub fn set_gateway_address(address: [u8; 4]) -> Result<(), ConfigError> {
+    save("GATEWAY_ADDRESS", &ipv4_value(address))
+}
+
+/// Salva il nome annunciato con mDNS (`HOSTNAME`): lettere, cifre e
+/// trattini, senza `.local`
+///
+/// # Argomenti
+/// * `hostname` - Nome della pico, vuoto per disabilitare mDNS
+///
+/// # Ritorna
+/// * Result<(), ConfigError> - Errore se il nome non è valido o la scrittura fallisce
+pub fn set_hostname(hostname: &str) -> Result<(), ConfigError> {
+    let valid = hostname.len() <= 63
+        && !hostname.starts_with('-')
+        && !hostname.ends_with('-')
+        && hostname
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-');
+    if !valid {
+        return Err(ConfigError::InvalidValue);
+    }
+    save("HOSTNAME", hostname)
+}
+
+/// Salva la lunghezza del prefisso della rete (`SUBNET_MASK`)
+///
+/// # Argomenti
+/// * `prefix` - Bit della maschera, da 0 a 32
+///
+/// # Ritorna
+/// * Result<(), ConfigError> - Errore se il prefisso non è valido o la scrittura fallisce
+pub fn set_subnet_mask(prefix: u8) -> Result<(), ConfigError> {
+    if prefix > 32 {
+        return Err(ConfigError::InvalidValue);
+    }
+    let value: heapless::String<4> = crate::errors::fit(format_args!("{}", prefix));
+    save("SUBNET_MASK", &value)
+}
@@ -1,11 +1,244 @@
 //! File di configurazione di varibili amnbiente
 //! da modificare a seconda del proprio amnbiente.
-//! TODO: cambirare ip, gateway, ssid e password.
+//! TODO: cambiare il gateway; ssid, password e ip vengono da `.env` (vedi CONFIG).
 
-pub const CONFIG: &str = r#"
-IP_ADDRESS=192, 168, 1, 115
+/// Numero di avvii consecutivi dopo un panic oltre il quale
+/// la pico si avvia in modalità provvisoria (vedi safe_mode.rs)
+pub const SAFE_MODE_PANIC_THRESHOLD: u8 = 3;
+
+/// Numero di record di log conservati in memoria (vedi log_buffer.rs)
+pub const LOG_RING_CAPACITY: usize = 64;
+
+/// Numero di LED della striscia WS2812 su GP22 (vedi led_strip.rs)
+pub const LED_STRIP_LENGTH: usize = 16;
+
+/// Livello di log di default (vedi logging.rs)
+pub const LOG_LEVEL_DEFAULT: log::LevelFilter = log::LevelFilter::Info;
+
+/// Livelli di log per modulo: il prefisso del target più lungo vince
+pub const LOG_LEVELS: &[(&str, log::LevelFilter)] = &[
+    ("cyw43", log::LevelFilter::Warn),
+    ("picoserve", log::LevelFilter::Warn),
+    ("pico_webserver::log_tcp", log::LevelFilter::Info),
+    ("pico_webserver::syslog", log::LevelFilter::Info),
+];
+
+/// Dimensione dello heap in byte (solo con la feature `heap`, vedi heap.rs)
+#[cfg(all(feature = "heap", not(feature = "https")))]
+pub const HEAP_SIZE: usize = 32 * 1024;
+
+/// Con la feature `https` lo heap ospita anche la sessione TLS del worker
+/// HTTPS, con il buffer di un record da 16 KB (vedi tls.rs)
+#[cfg(feature = "https")]
+pub const HEAP_SIZE: usize = 96 * 1024;
+
+/// Dimensione dello heap quando è in PSRAM (feature `psram`, vedi psram.rs):
+/// il resto della PSRAM resta disponibile per `psram::alloc`
+#[cfg(feature = "psram")]
+pub const PSRAM_HEAP_SIZE: usize = 1024 * 1024;
+
+/// Parte del buffer HTTP riservata alla riga di richiesta e alle intestazioni
+pub const HTTP_HEAD_RESERVE: usize = 512;
+
+/// Gruppo di worker del web server con buffer della stessa dimensione
+pub struct WorkerPool {
+    /// Numero di worker (almeno 1)
+    pub workers: usize,
+    /// Buffer di ricezione del socket TCP
+    pub tcp_rx: usize,
+    /// Buffer di trasmissione del socket TCP
+    pub tcp_tx: usize,
+    /// Buffer di picoserve per intestazioni e corpo della richiesta
+    pub http: usize,
+}
+
+impl WorkerPool {
+    /// RAM occupata dai buffer di tutti i worker del gruppo
+    pub const fn ram_bytes(&self) -> usize {
+        self.workers * (self.tcp_rx + self.tcp_tx + self.http)
+    }
+
+    /// Corpo più grande accettato dai worker del gruppo: oltre questa
+    /// dimensione la richiesta riceve un 413 (vedi body_limit.rs)
+    pub const fn max_body(&self) -> usize {
+        self.http.saturating_sub(HTTP_HEAD_RESERVE)
+    }
+}
+
+/// Socket di riserva che rispondono con un 503 quando tutti i worker sono
+/// occupati (vedi overload.rs)
+pub struct OverflowQueue {
+    /// Numero di socket
+    pub sockets: usize,
+    /// Buffer di ricezione del socket TCP
+    pub tcp_rx: usize,
+    /// Buffer di trasmissione del socket TCP
+    pub tcp_tx: usize,
+}
+
+/// Risorse del web server: forma dei gruppi di worker e socket dello stack
+/// di rete. I buffer stanno nei task, quindi la RAM è allocata staticamente
+/// e il totale è calcolato a compile time (loggato all'avvio).
+pub struct ServerResources;
+
+impl ServerResources {
+    /// Pochi worker con buffer di ricezione grandi, per gli upload (es. /api/ota)
+    pub const UPLOAD: WorkerPool = WorkerPool {
+        workers: 2,
+        tcp_rx: 4096,
+        tcp_tx: 1024,
+        http: 2048,
+    };
+
+    /// Molti worker con buffer piccoli, per le GET
+    pub const GET: WorkerPool = WorkerPool {
+        workers: 6,
+        tcp_rx: 1024,
+        tcp_tx: 1024,
+        http: 1024,
+    };
+
+    /// Worker HTTPS sulla porta 443, solo con la feature `https` (vedi
+    /// tls.rs): i record TLS stanno nello heap, qui ci sono i dati in chiaro
+    pub const HTTPS: WorkerPool = WorkerPool {
+        workers: if cfg!(feature = "https") { 1 } else { 0 },
+        tcp_rx: 2048,
+        tcp_tx: 2048,
+        http: 2048,
+    };
+
+    /// Coda di socket di riserva, con buffer minimi: leggono solo l'inizio
+    /// della richiesta e inviano una risposta breve
+    pub const OVERFLOW: OverflowQueue = OverflowQueue {
+        sockets: 2,
+        tcp_rx: 256,
+        tcp_tx: 256,
+    };
+
+    /// Socket usati da altri servizi (telnet, log TCP, syslog, SNTP, MQTT, lettura HTTP,
+    /// avviso SMTP, DNS, mDNS, server DHCP e DNS dell'access point di configurazione)
+    pub const EXTRA_SOCKETS: usize = 11;
+
+    /// Worker del web server
+    pub const WORKERS: usize = Self::UPLOAD.workers + Self::GET.workers;
+
+    /// Socket dello stack di rete
+    pub const SOCKETS: usize =
+        Self::WORKERS + Self::HTTPS.workers + Self::OVERFLOW.sockets + Self::EXTRA_SOCKETS;
+
+    /// RAM occupata dai buffer dei worker
+    pub const RAM_BYTES: usize =
+        Self::UPLOAD.ram_bytes() + Self::GET.ram_bytes() + Self::HTTPS.ram_bytes();
+}
+
+/// Configurazione di default, una chiave `NOME=valore` per riga. Rete WiFi,
+/// password e indirizzo IP non stanno nel sorgente: build.rs li legge dalle
+/// variabili d'ambiente `WIFI_SSID`, `WIFI_PASSWORD` e `PICO_IP` (es.
+/// `192.168.1.115`) o dal file `.env`, escluso da git (vedi `.env.example`).
+/// Allo stesso modo le reti di riserva facoltative `WIFI_SSID_2`/`_3` e
+/// `WIFI_PASSWORD_2`/`_3`, vuote se assenti (vedi wifi.rs).
+pub const CONFIG: &str = concat!(
+    r#"
+USE_DHCP=false
+HOSTNAME=picoweb
+IP_ADDRESS="#,
+    env!(
+        "PICOWEB_IP_ADDRESS",
+        "PICO_IP mancante: impostarla nell'ambiente o in .env (vedi .env.example)"
+    ),
+    r#"
 GATEWAY_ADDRESS=192, 168, 1, 1
-WIFI_NETWORK=Vodafone-A53164087
-WIFI_PASSWORD=dl6uud8f9wmv5tlt
+DNS_SERVER=192, 168, 1, 1
+WIFI_NETWORK="#,
+    env!(
+        "PICOWEB_WIFI_SSID",
+        "WIFI_SSID mancante: impostarla nell'ambiente o in .env (vedi .env.example)"
+    ),
+    r#"
+WIFI_PASSWORD="#,
+    env!(
+        "PICOWEB_WIFI_PASSWORD",
+        "WIFI_PASSWORD mancante: impostarla nell'ambiente o in .env (vuota per una rete aperta)"
+    ),
+    r#"
+WIFI_NETWORK_2="#,
+    env!("PICOWEB_WIFI_SSID_2"),
+    r#"
+WIFI_PASSWORD_2="#,
+    env!("PICOWEB_WIFI_PASSWORD_2"),
+    r#"
+WIFI_NETWORK_3="#,
+    env!("PICOWEB_WIFI_SSID_3"),
+    r#"
+WIFI_PASSWORD_3="#,
+    env!("PICOWEB_WIFI_PASSWORD_3"),
+    r#"
+PROVISION_AFTER_FAILURES=5
+PROVISION_AP_PASSWORD=
 SUBNET_MASK=24
-"#;
+UART_BREAK_ACTION=reboot
+LOG_COLLECTOR_ADDRESS=
+LOG_COLLECTOR_PORT=5140
+SYSLOG_ADDRESS=
+SYSLOG_PORT=514
+WATCHDOG_ENABLED=false
+TELNET_ENABLED=false
+ACL_DENY=
+ACL_ADMIN_ALLOW=
+RATE_LIMIT_CONNECTIONS=4
+RATE_LIMIT_PER_MINUTE=120
+HTTP_AUTH_USER=
+HTTP_AUTH_PASSWORD=
+BROWNOUT_VSYS_MV=0
+GPIO_OUTPUTS=6,7
+GPIO_INPUTS=8,9
+I2C_PINS=20,21
+MAINTENANCE_REBOOT=
+UTC_OFFSET_MINUTES=60
+NTP_SERVER=pool.ntp.org
+NTP_INTERVAL_MINUTES=60
+LANGUAGE=auto
+FETCH_URL=
+FETCH_KEYS=temperature,humidity
+FETCH_INTERVAL_SECS=300
+MQTT_BROKER=
+MQTT_PORT=1883
+MQTT_TOPIC=picoweb
+MQTT_USER=
+MQTT_PASSWORD=
+MQTT_INTERVAL_SECS=60
+SMTP_SERVER=
+SMTP_PORT=25
+SMTP_STARTTLS=false
+SMTP_FROM=picoweb@localhost
+SMTP_TO=
+SMTP_USER=
+SMTP_PASSWORD=
+"#
+);
+
+/// Chiavi di [`CONFIG`] il cui valore non viene mai mostrato (es. `config
+/// get`). Una nuova chiave segreta va aggiunta qui, accanto a CONFIG
+pub const SECRET_KEYS: &[&str] = &[
+    "WIFI_PASSWORD",
+    "WIFI_PASSWORD_2",
+    "WIFI_PASSWORD_3",
+    "PROVISION_AP_PASSWORD",
+    "HTTP_AUTH_PASSWORD",
+    "MQTT_PASSWORD",
+    "SMTP_PASSWORD",
+];
+
+/// Indica se il valore di una chiave va nascosto: quelle di [`SECRET_KEYS`]
+/// e, per non dimenticarne nessuna, qualunque chiave che contiene `PASSWORD`
+///
+/// # Argomenti
+///
+/// * `key` - nome della chiave di configurazione
+///
+/// # Ritorna
+///
+/// `true` se il valore è segreto
+pub fn is_secret(key: &str) -> bool {
+    SECRET_KEYS.contains(&key) || key.contains("PASSWORD")
+}
@@ -0,0 +1,154 @@
+//! console.rs - Console di gestione interattiva su UART0 (GP0 TX, GP1 RX).
+//!
+//! La console è separata dall'I/O seriale applicativo (UART1) e offre
+//! un editing di riga minimale (backspace, Ctrl-C); le righe inserite
+//! vengono eseguite come comandi di gestione (vedi commands.rs).
+
+use crate::bus::SolveSource;
+use crate::commands::{self, LINE_CAPACITY};
+#[cfg(feature = "log-uart")]
+use crate::logging;
+use crate::tasks;
+#[cfg(feature = "log-uart")]
+use embassy_futures::select::{Either, select};
+use embassy_rp::uart::{Async, UartRx, UartTx};
+
+const PROMPT: &str = "picoweb> ";
+
+/// Evento prodotto dall'editor di riga
+enum LineEvent<'a> {
+    /// Carattere accettato: va mostrato all'utente
+    Echo(u8),
+    /// Ultimo carattere cancellato
+    Erase,
+    /// Riga completata con invio
+    Submit(&'a str),
+    /// Riga annullata con Ctrl-C
+    Cancel,
+    /// Nessuna azione visibile
+    None,
+}
+
+/// Editor di riga minimale per terminali seriali
+struct LineEditor {
+    line: heapless::String<LINE_CAPACITY>,
+    submitted: bool,
+    /// true se l'ultimo byte ricevuto è `\r`
+    after_cr: bool,
+}
+
+impl LineEditor {
+    /// Crea un editor vuoto
+    fn new() -> Self {
+        Self {
+            line: heapless::String::new(),
+            submitted: false,
+            after_cr: false,
+        }
+    }
+
+    /// Elabora un byte ricevuto dalla seriale
+    ///
+    /// # Argomenti
+    /// * `byte` - Byte ricevuto
+    ///
+    /// # Ritorna
+    /// * LineEvent - Azione da riflettere sul terminale
+    fn feed(&mut self, byte: u8) -> LineEvent<'_> {
+        if self.submitted {
+            self.line.clear();
+            self.submitted = false;
+        }
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        match byte {
+            // I terminali che inviano CRLF: il \n completa la riga già inviata con \r
+            b'\n' if after_cr => LineEvent::None,
+            b'\r' | b'\n' => {
+                self.submitted = true;
+                LineEvent::Submit(self.line.trim())
+            }
+            0x08 | 0x7f => match self.line.pop() {
+                Some(_) => LineEvent::Erase,
+                None => LineEvent::None,
+            },
+            0x03 => {
+                self.line.clear();
+                LineEvent::Cancel
+            }
+            0x20..=0x7e => match self.line.push(byte as char) {
+                Ok(()) => LineEvent::Echo(byte),
+                Err(()) => LineEvent::None,
+            },
+            _ => LineEvent::None,
+        }
+    }
+}
+
+#[embassy_executor::task]
+/// Task della console di gestione su UART0
+///
+/// # Argomenti
+/// * `tx` - Trasmettitore UART0
+/// * `rx` - Ricevitore UART0
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn console_task(mut tx: UartTx<'static, Async>, mut rx: UartRx<'static, Async>) {
+    let mut editor = LineEditor::new();
+    write_str(&mut tx, "\r\npicoweb console - digitare 'help'\r\n").await;
+    write_str(&mut tx, PROMPT).await;
+
+    loop {
+        let mut byte = [0u8; 1];
+        #[cfg(feature = "log-uart")]
+        {
+            // Le righe di log vengono scritte tra un carattere e l'altro
+            let mut log_buf = [0u8; 64];
+            match select(rx.read(&mut byte), logging::read_uart(&mut log_buf)).await {
+                Either::First(result) => {
+                    if let Err(e) = result {
+                        log::warn!("console: errore di ricezione {:?}", e);
+                        continue;
+                    }
+                }
+                Either::Second(len) => {
+                    write_bytes(&mut tx, &log_buf[..len]).await;
+                    continue;
+                }
+            }
+        }
+        #[cfg(not(feature = "log-uart"))]
+        if let Err(e) = rx.read(&mut byte).await {
+            log::warn!("console: errore di ricezione {:?}", e);
+            continue;
+        }
+
+        tasks::touch("console");
+        match editor.feed(byte[0]) {
+            LineEvent::Echo(c) => write_bytes(&mut tx, &[c]).await,
+            LineEvent::Erase => write_str(&mut tx, "\x08 \x08").await,
+            LineEvent::Cancel => {
+                write_str(&mut tx, "^C\r\n").await;
+                write_str(&mut tx, PROMPT).await;
+            }
+            LineEvent::Submit(line) => {
+                write_str(&mut tx, "\r\n").await;
+                commands::handle_line(line, SolveSource::Console, &mut tx).await;
+                write_str(&mut tx, PROMPT).await;
+            }
+            LineEvent::None => {}
+        }
+    }
+}
+
+/// Scrive una stringa sulla console ignorando gli errori di trasmissione
+async fn write_str(tx: &mut UartTx<'static, Async>, s: &str) {
+    write_bytes(tx, s.as_bytes()).await;
+}
+
+/// Scrive dei byte sulla console ignorando gli errori di trasmissione
+async fn write_bytes(tx: &mut UartTx<'static, Async>, bytes: &[u8]) {
+    if let Err(e) = tx.write(bytes).await {
+        log::warn!("console: errore di trasmissione {:?}", e);
+    }
+}
@@ -0,0 +1,128 @@
+//! counters.rs - Contatori persistenti in flash.
+//!
+//! Numero di avvii, schemi risolti in totale e uptime complessivo
+//! sopravvivono al riavvio e allo spegnimento. I valori vivono in RAM e
+//! vengono salvati a blocchi ogni [`FLUSH_PERIOD_SECS`] secondi, così la
+//! flash viene scritta poche volte all'ora: dopo uno spegnimento si
+//! perdono al massimo gli ultimi minuti di conteggio.
+//!
+//! Ogni salvataggio aggiunge un record all'archivio dei contatori (vedi
+//! flash_store.rs), due settori sotto lo storico dei crash: l'ultimo
+//! record valido resta sempre in flash. I contatori sono disponibili su
+//! `/api/counters`, sulla pagina principale e con il comando `status`.
+
+use crate::bus::{self, Event};
+use crate::flash_store::RecordStore;
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Instant, Ticker};
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Archivio dei contatori: record da 32 byte, un'unica chiave
+static COUNTERS_STORE: RecordStore<32, 1> = RecordStore::new("Contatori", 2, 2);
+
+/// Chiave del record dei contatori
+const COUNTERS_KEY: u16 = 0;
+
+/// Periodo dei salvataggi in flash
+pub const FLUSH_PERIOD_SECS: u64 = 600;
+
+static BOOTS: AtomicU32 = AtomicU32::new(0);
+static LIFETIME_SOLVES: AtomicU32 = AtomicU32::new(0);
+/// Uptime complessivo delle esecuzioni precedenti (secondi)
+static PREVIOUS_UPTIME_SECS: AtomicU32 = AtomicU32::new(0);
+
+/// Contatori restituiti da /api/counters
+#[derive(serde::Serialize)]
+pub struct Counters {
+    pub boots: u32,
+    pub lifetime_solves: u32,
+    /// Uptime complessivo, esecuzione corrente compresa
+    pub total_uptime_secs: u32,
+}
+
+/// Uptime complessivo, esecuzione corrente compresa
+fn total_uptime_secs() -> u32 {
+    PREVIOUS_UPTIME_SECS
+        .load(Ordering::Relaxed)
+        .wrapping_add(Instant::now().as_secs() as u32)
+}
+
+/// Salva i contatori in flash (anche prima di un riavvio, vedi system.rs)
+pub fn flush() {
+    let mut record = [0u8; 12];
+    let values = [
+        BOOTS.load(Ordering::Relaxed),
+        LIFETIME_SOLVES.load(Ordering::Relaxed),
+        total_uptime_secs(),
+    ];
+    for (i, value) in values.iter().enumerate() {
+        record[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    match COUNTERS_STORE.append(COUNTERS_KEY, &record) {
+        Ok(seq) => log::debug!("Contatori: record {} salvato", seq),
+        Err(e) => log::error!("Contatori: scrittura fallita: {:?}", e),
+    }
+}
+
+/// Carica i contatori dalla flash e registra l'avvio corrente:
+/// da chiamare una sola volta all'inizio del main, dopo flash_store::init
+pub fn boot() {
+    let last = COUNTERS_STORE
+        .latest(COUNTERS_KEY)
+        .filter(|record| record.payload.len() == 12);
+    if let Some(record) = last {
+        let value = |i: usize| {
+            let bytes = &record.payload[i * 4..i * 4 + 4];
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        };
+        BOOTS.store(value(0), Ordering::Relaxed);
+        LIFETIME_SOLVES.store(value(1), Ordering::Relaxed);
+        PREVIOUS_UPTIME_SECS.store(value(2), Ordering::Relaxed);
+    }
+    let boots = BOOTS.fetch_add(1, Ordering::Relaxed) + 1;
+    flush();
+    log::info!("Avvio numero {}", boots);
+}
+
+/// Ritorna i contatori correnti
+///
+/// # Ritorna
+/// * Counters - Valori correnti, anche se non ancora salvati
+pub fn report() -> Counters {
+    Counters {
+        boots: BOOTS.load(Ordering::Relaxed),
+        lifetime_solves: LIFETIME_SOLVES.load(Ordering::Relaxed),
+        total_uptime_secs: total_uptime_secs(),
+    }
+}
+
+#[embassy_executor::task]
+/// Task che conta gli schemi risolti (dagli eventi del bus) e salva
+/// periodicamente i contatori in flash
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn counters_task() {
+    let mut events = bus::subscribe();
+    let mut ticker = Ticker::every(Duration::from_secs(FLUSH_PERIOD_SECS));
+    loop {
+        let solved = match events.as_mut() {
+            Some(events) => match select(ticker.next(), events.next_message_pure()).await {
+                Either::First(()) => false,
+                Either::Second(event) => matches!(event, Event::PuzzleSolved { .. }),
+            },
+            None => {
+                ticker.next().await;
+                false
+            }
+        };
+        crate::tasks::touch("counters");
+        if solved {
+            // Le soluzioni si accumulano in RAM fino al prossimo salvataggio
+            LIFETIME_SOLVES.fetch_add(1, Ordering::Relaxed);
+        } else {
+            // L'uptime cresce sempre, quindi a ogni periodo c'è qualcosa da salvare
+            flush();
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! crash_log.rs - Storico dei crash salvato in flash.
+//!
+//! `panic_persist` conserva il messaggio di panic in RAM solo fino al
+//! riavvio successivo. Questo modulo, all'avvio, trasforma il panic (o il
+//! riavvio causato dal watchdog) in un record salvato in due settori
+//! dedicati in cima alla flash, con messaggio, uptime, versione del
+//! firmware, ultimo percorso HTTP richiesto e causa del reset.
+//!
+//! I record sono conservati in un archivio di flash_store.rs (due settori
+//! in cima alla flash): quando è pieno, i crash più vecchi vengono persi.
+//!
+//! Uptime e ultimo percorso vengono aggiornati durante l'esecuzione in una
+//! zona di RAM `.uninit`, che sopravvive al riavvio software.
+//! Lo storico è consultabile su `/api/crashes`.
+
+use crate::flash_store::RecordStore;
+use core::mem::MaybeUninit;
+use embassy_rp::flash::ERASE_SIZE;
+use picoserve::ResponseSent;
+use picoserve::io::Read;
+use picoserve::request::RequestParts;
+use picoserve::response::ResponseWriter;
+use picoserve::routing::{Layer, Next};
+
+/// Dimensione di uno slot dell'archivio
+const SLOT_SIZE: usize = 256;
+
+/// Archivio dei crash: gli ultimi due settori della flash
+static CRASH_STORE: RecordStore<SLOT_SIZE, 0> = RecordStore::new("Crash log", 0, 2);
+
+/// Numero totale di record conservati
+pub const CRASH_SLOTS: usize = 2 * (ERASE_SIZE / SLOT_SIZE);
+
+/// Marcatore del contesto in RAM valido
+const CONTEXT_MAGIC: u32 = 0x5458_4343;
+
+/// Dimensione del contenuto di un record
+const PAYLOAD_SIZE: usize = RecordStore::<SLOT_SIZE, 0>::PAYLOAD_CAPACITY;
+
+// Layout del contenuto: uptime_ms, reason, lunghezze, poi i testi
+const VERSION_FIELD: core::ops::Range<usize> = 8..24;
+const PATH_FIELD: core::ops::Range<usize> = 24..88;
+const MESSAGE_FIELD: core::ops::Range<usize> = 88..PAYLOAD_SIZE;
+
+/// Lunghezza massima del percorso conservato
+const PATH_CAPACITY: usize = PATH_FIELD.end - PATH_FIELD.start;
+
+/// Causa del reset che ha preceduto l'avvio
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ResetReason {
+    /// Accensione, pulsante di reset o riavvio software (anche dopo un panic)
+    PowerOn,
+    /// Watchdog scaduto
+    Watchdog,
+    /// Reset forzato tramite watchdog (es. riavvio dalla ROM)
+    Forced,
+}
+
+impl ResetReason {
+    /// Legge la causa dell'ultimo reset dal watchdog
+    ///
+    /// # Ritorna
+    /// * ResetReason - Causa del reset
+    pub fn read() -> Self {
+        let reason = embassy_rp::pac::WATCHDOG.reason().read();
+        if reason.timer() {
+            ResetReason::Watchdog
+        } else if reason.force() {
+            ResetReason::Forced
+        } else {
+            ResetReason::PowerOn
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            ResetReason::PowerOn => "power-on/software",
+            ResetReason::Watchdog => "watchdog",
+            ResetReason::Forced => "forced",
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => ResetReason::Watchdog,
+            2 => ResetReason::Forced,
+            _ => ResetReason::PowerOn,
+        }
+    }
+}
+
+/// Contesto dell'esecuzione corrente, in RAM non inizializzata
+#[derive(Clone, Copy)]
+pub struct CrashContext {
+    magic: u32,
+    uptime_ms: u32,
+    panic_boots: u8,
+    path_len: u8,
+    path: [u8; PATH_CAPACITY],
+}
+
+#[unsafe(link_section = ".uninit.CRASH_CONTEXT")]
+static mut CRASH_CONTEXT: MaybeUninit<CrashContext> = MaybeUninit::uninit();
+
+/// Puntatore al contesto in RAM non inizializzata
+fn context_ptr() -> *mut CrashContext {
+    core::ptr::addr_of_mut!(CRASH_CONTEXT).cast()
+}
+
+/// Recupera il contesto dell'esecuzione precedente e lo azzera per quella
+/// corrente: da chiamare una sola volta all'inizio del main
+///
+/// # Ritorna
+/// * Option<CrashContext> - Contesto precedente, se valido
+pub fn take_context() -> Option<CrashContext> {
+    // SAFETY: la zona .uninit è sempre leggibile; il contenuto viene usato
+    // solo se il marcatore è valido
+    let previous = unsafe { core::ptr::read_volatile(context_ptr()) };
+    let fresh = CrashContext {
+        magic: CONTEXT_MAGIC,
+        uptime_ms: 0,
+        panic_boots: 0,
+        path_len: 0,
+        path: [0; PATH_CAPACITY],
+    };
+    unsafe { core::ptr::write_volatile(context_ptr(), fresh) };
+    (previous.magic == CONTEXT_MAGIC).then_some(previous)
+}
+
+impl CrashContext {
+    /// Numero di avvii consecutivi dopo un panic registrato in questo contesto
+    pub fn panic_boots(&self) -> u8 {
+        self.panic_boots
+    }
+}
+
+/// Registra nel contesto corrente il numero di avvii consecutivi dopo un
+/// panic (vedi safe_mode.rs)
+///
+/// # Argomenti
+/// * `count` - Numero di avvii consecutivi dopo un panic
+pub fn set_panic_boots(count: u8) {
+    // SAFETY: il contesto è stato inizializzato da take_context
+    unsafe { core::ptr::addr_of_mut!((*context_ptr()).panic_boots).write_volatile(count) };
+}
+
+/// Aggiorna l'uptime nel contesto (chiamata periodicamente dal supervisore)
+pub fn update_uptime() {
+    let uptime_ms = embassy_time::Instant::now().as_millis() as u32;
+    // SAFETY: il contesto è stato inizializzato da take_context
+    unsafe { core::ptr::addr_of_mut!((*context_ptr()).uptime_ms).write_volatile(uptime_ms) };
+}
+
+/// Registra l'ultimo percorso HTTP richiesto
+///
+/// # Argomenti
+/// * `path` - Percorso della richiesta (troncato se troppo lungo)
+fn set_last_path(path: &str) {
+    let len = path.len().min(PATH_CAPACITY);
+    critical_section::with(|_| {
+        // SAFETY: il contesto è stato inizializzato da take_context;
+        // l'accesso avviene in sezione critica
+        unsafe {
+            let context = &mut *context_ptr();
+            context.path[..len].copy_from_slice(&path.as_bytes()[..len]);
+            context.path_len = len as u8;
+        }
+    });
+}
+
+/// Layer che registra il percorso di ogni richiesta nel contesto dei crash
+pub struct CrashContextLayer;
+
+impl<State, PathParameters> Layer<State, PathParameters> for CrashContextLayer {
+    type NextState = State;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &State,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        set_last_path(request_parts.path().encoded());
+        next.run(state, path_parameters, response_writer).await
+    }
+}
+
+/// Record letto dalla flash
+#[derive(serde::Serialize)]
+pub struct CrashRecord {
+    seq: u32,
+    uptime_ms: u32,
+    reset_reason: &'static str,
+    version: &'static str,
+    last_path: &'static str,
+    message: &'static str,
+}
+
+/// Estrae un campo di testo di lunghezza `len`
+fn text(bytes: &'static [u8], field: core::ops::Range<usize>, len: u8) -> &'static str {
+    let end = (field.start + len as usize).min(field.end);
+    core::str::from_utf8(&bytes[field.start..end]).unwrap_or("?")
+}
+
+/// Decodifica un record dell'archivio
+///
+/// # Argomenti
+/// * `record` - Record letto dalla flash
+///
+/// # Ritorna
+/// * Option<CrashRecord> - Record, se il contenuto ha la dimensione attesa
+fn decode(record: crate::flash_store::Record) -> Option<CrashRecord> {
+    let bytes = record.payload;
+    if bytes.len() != PAYLOAD_SIZE {
+        return None;
+    }
+    Some(CrashRecord {
+        seq: record.seq,
+        uptime_ms: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        reset_reason: ResetReason::from_u8(bytes[4]).as_str(),
+        version: text(bytes, VERSION_FIELD, bytes[5]),
+        last_path: text(bytes, PATH_FIELD, bytes[6]),
+        message: text(bytes, MESSAGE_FIELD, bytes[7]),
+    })
+}
+
+/// Ritorna lo storico dei crash, dal più vecchio al più recente
+///
+/// # Ritorna
+/// * heapless::Vec<CrashRecord, CRASH_SLOTS> - Record presenti in flash
+pub fn list() -> heapless::Vec<CrashRecord, CRASH_SLOTS> {
+    let mut records: heapless::Vec<CrashRecord, CRASH_SLOTS> =
+        CRASH_STORE.records().filter_map(decode).collect();
+    records.sort_unstable_by_key(|record| record.seq);
+    records
+}
+
+/// Ritorna il record più recente dello storico
+///
+/// # Ritorna
+/// * Option<CrashRecord> - Ultimo record, None se lo storico è vuoto
+pub fn latest() -> Option<CrashRecord> {
+    CRASH_STORE
+        .records()
+        .filter_map(decode)
+        .max_by_key(|record| record.seq)
+}
+
+// Testo del record, una voce per riga (es. per l'avviso via email, vedi smtp.rs)
+impl core::fmt::Display for CrashRecord {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "record: {}", self.seq)?;
+        writeln!(f, "reset: {}", self.reset_reason)?;
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "uptime_ms: {}", self.uptime_ms)?;
+        writeln!(f, "last_path: {}", self.last_path)?;
+        writeln!(f, "message: {}", self.message)
+    }
+}
+
+/// Scrive un campo di testo troncandolo alla sua capacità
+///
+/// # Ritorna
+/// * u8 - Lunghezza scritta
+fn put_text(record: &mut [u8; PAYLOAD_SIZE], field: core::ops::Range<usize>, text: &str) -> u8 {
+    // Il troncamento non deve spezzare un carattere UTF-8
+    let mut len = text.len().min(field.end - field.start);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    record[field.start..field.start + len].copy_from_slice(&text.as_bytes()[..len]);
+    len as u8
+}
+
+/// Salva un record di crash nell'archivio, perdendo il più vecchio quando
+/// è pieno
+///
+/// # Argomenti
+/// * `message` - Messaggio di panic (o descrizione del crash)
+/// * `reason` - Causa del reset
+/// * `context` - Contesto dell'esecuzione terminata, se disponibile
+pub fn record(message: &str, reason: ResetReason, context: Option<&CrashContext>) {
+    let mut record = [0u8; PAYLOAD_SIZE];
+    let uptime_ms = context.map_or(0, |c| c.uptime_ms);
+    record[0..4].copy_from_slice(&uptime_ms.to_le_bytes());
+    record[4] = reason as u8;
+    record[5] = put_text(&mut record, VERSION_FIELD, env!("CARGO_PKG_VERSION"));
+    let path = context
+        .map(|c| &c.path[..(c.path_len as usize).min(PATH_CAPACITY)])
+        .and_then(|p| core::str::from_utf8(p).ok())
+        .unwrap_or("");
+    record[6] = put_text(&mut record, PATH_FIELD, path);
+    record[7] = put_text(&mut record, MESSAGE_FIELD, message);
+
+    match CRASH_STORE.append(0, &record) {
+        Ok(seq) => log::info!("Crash log: record {} salvato", seq),
+        Err(e) => log::error!("Crash log: scrittura fallita: {:?}", e),
+    }
+}
@@ -0,0 +1,131 @@
+//! cyw43_firmware.rs - Firmware e CLM del chip WiFi CYW43439.
+//!
+//! Di default i blob vengono inclusi nel programma con `include_bytes!`.
+//! Con la feature `firmware-external` vengono invece letti da indirizzi
+//! fissi della flash, dove vanno caricati una sola volta con picotool:
+//! ```text
+//! picotool load -t bin -o 0x10300000 cyw43-firmware/43439A0.bin
+//! picotool load -t bin -o 0x10340000 cyw43-firmware/43439A0_clm.bin
+//! picotool load -t bin -o 0x10341000 cyw43-firmware/43439A0_btfw.bin
+//! ```
+//! così ogni caricamento del firmware durante lo sviluppo è più piccolo di
+//! circa 240 KB. Gli indirizzi sono nella zona libera tra lo slot B (vedi
+//! ota.rs) e gli archivi in cima alla flash (vedi flash_store.rs); sulla
+//! Pico W (2 MB di flash) sono 0x10180000, 0x101C0000 e 0x101C1000.
+//! Lunghezza e byte iniziali e finali dei blob in flash vengono confrontati
+//! con i file di `cyw43-firmware/`: un blob mancante o di un'altra
+//! versione ferma l'avvio con un messaggio chiaro invece di bloccare il chip.
+
+/// Blob caricato separatamente in flash
+#[cfg(feature = "firmware-external")]
+struct ExternalBlob {
+    name: &'static str,
+    /// Offset in flash
+    offset: usize,
+    len: usize,
+    head: [u8; 4],
+    tail: [u8; 4],
+}
+
+#[cfg(feature = "firmware-external")]
+impl ExternalBlob {
+    /// Descrive un blob a partire dal file di riferimento, letto solo in
+    /// fase di compilazione
+    const fn new(name: &'static str, offset: usize, reference: &[u8]) -> Self {
+        let n = reference.len();
+        Self {
+            name,
+            offset,
+            len: n,
+            head: [reference[0], reference[1], reference[2], reference[3]],
+            tail: [
+                reference[n - 4],
+                reference[n - 3],
+                reference[n - 2],
+                reference[n - 1],
+            ],
+        }
+    }
+
+    /// Ritorna il blob presente in flash, se corrisponde al riferimento
+    ///
+    /// # Ritorna
+    /// * &'static [u8] - Contenuto del blob
+    fn load(&self) -> &'static [u8] {
+        // La zona è fuori dagli slot del firmware: si legge senza traduzione degli indirizzi
+        let address = crate::flash_store::XIP_BASE + self.offset;
+        // SAFETY: la flash è mappata in memoria e la zona dei blob è riservata
+        let bytes = unsafe { core::slice::from_raw_parts(address as *const u8, self.len) };
+        if bytes[..4] != self.head || bytes[self.len - 4..] != self.tail {
+            core::panic!(
+                "blob CYW43 '{}' assente o diverso a 0x{:08x}: caricarlo con picotool (vedi cyw43_firmware.rs)",
+                self.name,
+                0x1000_0000 + self.offset
+            );
+        }
+        bytes
+    }
+}
+
+/// Offset in flash dei blob: firmware WiFi, CLM e firmware Bluetooth
+#[cfg(all(feature = "firmware-external", feature = "rp235x"))]
+const OFFSETS: [usize; 3] = [0x0030_0000, 0x0034_0000, 0x0034_1000];
+#[cfg(all(feature = "firmware-external", feature = "rp2040"))]
+const OFFSETS: [usize; 3] = [0x0018_0000, 0x001C_0000, 0x001C_1000];
+
+#[cfg(feature = "firmware-external")]
+static WIFI: ExternalBlob = ExternalBlob::new(
+    "43439A0.bin",
+    OFFSETS[0],
+    include_bytes!("../cyw43-firmware/43439A0.bin"),
+);
+
+#[cfg(feature = "firmware-external")]
+static CLM: ExternalBlob = ExternalBlob::new(
+    "43439A0_clm.bin",
+    OFFSETS[1],
+    include_bytes!("../cyw43-firmware/43439A0_clm.bin"),
+);
+
+#[cfg(all(feature = "firmware-external", feature = "ble"))]
+static BLUETOOTH: ExternalBlob = ExternalBlob::new(
+    "43439A0_btfw.bin",
+    OFFSETS[2],
+    include_bytes!("../cyw43-firmware/43439A0_btfw.bin"),
+);
+
+/// Firmware WiFi
+#[cfg(feature = "firmware-external")]
+pub fn wifi() -> &'static [u8] {
+    WIFI.load()
+}
+
+/// CLM (regolamentazione radio)
+#[cfg(feature = "firmware-external")]
+pub fn clm() -> &'static [u8] {
+    CLM.load()
+}
+
+/// Firmware Bluetooth
+#[cfg(all(feature = "firmware-external", feature = "ble"))]
+pub fn bluetooth() -> &'static [u8] {
+    BLUETOOTH.load()
+}
+
+/// Firmware WiFi
+#[cfg(not(feature = "firmware-external"))]
+pub fn wifi() -> &'static [u8] {
+    include_bytes!("../cyw43-firmware/43439A0.bin")
+}
+
+/// CLM (regolamentazione radio)
+#[cfg(not(feature = "firmware-external"))]
+pub fn clm() -> &'static [u8] {
+    include_bytes!("../cyw43-firmware/43439A0_clm.bin")
+}
+
+/// Firmware Bluetooth
+#[cfg(all(not(feature = "firmware-external"), feature = "ble"))]
+pub fn bluetooth() -> &'static [u8] {
+    include_bytes!("../cyw43-firmware/43439A0_btfw.bin")
+}
@@ -0,0 +1,160 @@
+//! drain.rs - Modalità manutenzione con svuotamento del web server.
+//!
+//! Le operazioni che riavviano la pico (riavvio, BOOTSEL, riavvio di
+//! manutenzione, nuovo firmware dopo `/api/ota`) non vengono eseguite nel
+//! mezzo di una richiesta: [`request`] le passa a [`drain_task`], che
+//! * smette di accettare nuove connessioni su tutti i worker tranne uno,
+//!   riservato, che risponde a ogni nuovo client con la pagina di
+//!   manutenzione (503 con `Retry-After`);
+//! * attende che le connessioni in corso siano concluse, al massimo per
+//!   [`DRAIN_TIMEOUT`];
+//! * esegue l'operazione richiesta.
+//!
+//! Lo svuotamento non si annulla: una volta iniziato la pico si riavvia.
+
+use crate::configuration::ServerResources;
+use crate::i18n::Lang;
+use crate::overload;
+use embassy_net::tcp::TcpSocket;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+use portable_atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Attesa massima della fine delle connessioni in corso
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Intervallo del controllo delle connessioni in corso
+const DRAIN_POLL: Duration = Duration::from_millis(100);
+
+/// Secondi suggeriti ai client prima di riprovare
+const RETRY_AFTER_SECS: u32 = 30;
+
+/// Worker riservato alla pagina di manutenzione: l'ultimo dei worker GET
+pub const MAINTENANCE_WORKER: usize = ServerResources::WORKERS - 1;
+
+/// Operazione da eseguire a svuotamento concluso
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrainAction {
+    Reboot,
+    Bootsel,
+    /// Riavvio di manutenzione (vedi maintenance.rs)
+    MaintenanceReboot,
+    /// Riavvio nella partizione aggiornata all'offset indicato (vedi ota.rs)
+    #[cfg(feature = "rp235x")]
+    FlashUpdate(u32),
+}
+
+/// Svuotamento iniziato
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// Connessioni HTTP in corso
+static IN_FLIGHT: AtomicU32 = AtomicU32::new(0);
+
+/// Operazione richiesta, consegnata a drain_task
+static REQUESTED: Signal<CriticalSectionRawMutex, DrainAction> = Signal::new();
+
+/// Ritorna true se lo svuotamento è iniziato
+pub fn is_draining() -> bool {
+    DRAINING.load(Ordering::Relaxed)
+}
+
+/// Ritorna il numero di connessioni HTTP in corso
+pub fn in_flight() -> u32 {
+    IN_FLIGHT.load(Ordering::Relaxed)
+}
+
+/// Chiede di svuotare il web server e poi eseguire un'operazione
+///
+/// # Argomenti
+/// * `action` - Operazione da eseguire
+///
+/// # Ritorna
+/// * bool - false se uno svuotamento era già in corso (la richiesta è ignorata)
+pub fn request(action: DrainAction) -> bool {
+    if DRAINING.swap(true, Ordering::Relaxed) {
+        return false;
+    }
+    log::warn!(
+        "Manutenzione: svuotamento del web server, poi {:?} ({} connessioni in corso)",
+        action,
+        in_flight()
+    );
+    REQUESTED.signal(action);
+    true
+}
+
+/// Connessione servita da un worker: conta tra quelle in corso finché esiste
+pub struct InFlight;
+
+impl InFlight {
+    /// Segna l'inizio di una connessione
+    pub fn enter() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+        InFlight
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Attende l'inizio dello svuotamento: un worker non riservato in attesa di
+/// una connessione smette di accettarne
+pub async fn started() {
+    while !is_draining() {
+        Timer::after(DRAIN_POLL).await;
+    }
+}
+
+/// Risponde a un nuovo client con la pagina di manutenzione e chiude la
+/// connessione
+///
+/// # Argomenti
+/// * `socket` - Connessione appena accettata
+pub async fn serve_maintenance_page(socket: &mut TcpSocket<'_>) {
+    let page = Lang::configured().strings().maintenance_page;
+    overload::reject(
+        socket,
+        overload::SERVICE_UNAVAILABLE,
+        RETRY_AFTER_SECS,
+        "text/html; charset=utf-8",
+        page,
+    )
+    .await;
+}
+
+#[embassy_executor::task]
+/// Task che esegue lo svuotamento richiesto e poi l'operazione
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn drain_task() {
+    let action = REQUESTED.wait().await;
+    crate::tasks::touch("drain");
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    while in_flight() > 0 && Instant::now() < deadline {
+        Timer::after(DRAIN_POLL).await;
+    }
+    match in_flight() {
+        0 => log::warn!(
+            "Manutenzione: web server svuotato, esecuzione di {:?}",
+            action
+        ),
+        n => log::warn!(
+            "Manutenzione: {} connessioni ancora aperte dopo {} s, esecuzione di {:?}",
+            n,
+            DRAIN_TIMEOUT.as_secs(),
+            action
+        ),
+    }
+    match action {
+        DrainAction::Reboot => crate::system::reboot().await,
+        DrainAction::Bootsel => crate::system::enter_bootsel().await,
+        DrainAction::MaintenanceReboot => crate::system::maintenance_reboot().await,
+        #[cfg(feature = "rp235x")]
+        DrainAction::FlashUpdate(offset) => crate::system::reboot_flash_update(offset).await,
+    }
+}
@@ -0,0 +1,176 @@
+//! errors.rs - Politica di gestione degli errori dei task a regime.
+//!
+//! I task che restano attivi per tutta la vita del dispositivo (UART,
+//! blink, web server, console) non devono mai andare in panic per un
+//! errore transitorio. Gli errori vengono trattati così:
+//! * riprova - le operazioni di I/O brevi (es. la trasmissione sulla UART)
+//!   vengono ripetute qualche volta prima di arrendersi (vedi [`retry`])
+//! * degrado - se l'errore persiste l'operazione viene abbandonata (riga
+//!   scartata, testo troncato, lettura sospesa per un attimo) e il task
+//!   prosegue con la successiva
+//! * diagnostica - ogni errore viene contato per classe (vedi [`report`]);
+//!   i contatori sono disponibili su `/api/errors` e con il comando `status`
+//!
+//! I testi formattati nelle stringhe a capacità fissa usano [`fit`], che
+//! tronca il testo invece di sostituirlo con una stringa vuota.
+
+use core::fmt::Write;
+use embassy_time::{Duration, Timer};
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Tentativi di un'operazione di I/O prima di arrendersi
+const RETRY_ATTEMPTS: usize = 3;
+
+/// Pausa tra due tentativi
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// Marcatore aggiunto ai testi troncati
+const ELLIPSIS: &str = "...";
+
+/// Classi di errore conteggiate
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    /// Errore di ricezione sulla UART (overrun, framing, parità)
+    UartRx = 0,
+    /// Trasmissione sulla UART fallita anche dopo i tentativi
+    UartTx = 1,
+    /// Testo troncato perché più lungo della stringa di destinazione
+    Truncated = 2,
+}
+
+const KINDS: usize = 3;
+
+static COUNTS: [AtomicU32; KINDS] = [const { AtomicU32::new(0) }; KINDS];
+
+/// Contatori restituiti da /api/errors
+#[derive(serde::Serialize)]
+pub struct ErrorCounts {
+    pub uart_rx: u32,
+    pub uart_tx: u32,
+    pub truncated: u32,
+}
+
+/// Registra un errore gestito
+///
+/// # Argomenti
+/// * `kind` - Classe dell'errore
+///
+/// # Ritorna
+/// * u32 - Errori della stessa classe dall'avvio
+pub fn report(kind: ErrorKind) -> u32 {
+    COUNTS[kind as usize].fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Ritorna i contatori degli errori gestiti dall'avvio
+///
+/// # Ritorna
+/// * ErrorCounts - Contatori per classe
+pub fn counts() -> ErrorCounts {
+    let count = |kind: ErrorKind| COUNTS[kind as usize].load(Ordering::Relaxed);
+    ErrorCounts {
+        uart_rx: count(ErrorKind::UartRx),
+        uart_tx: count(ErrorKind::UartTx),
+        truncated: count(ErrorKind::Truncated),
+    }
+}
+
+/// Esegue un'operazione di I/O ripetendola in caso di errore
+///
+/// # Argomenti
+/// * `kind` - Classe registrata se tutti i tentativi falliscono
+/// * `op` - Operazione da eseguire
+///
+/// # Ritorna
+/// * Result<T, E> - Risultato del primo tentativo riuscito, oppure l'ultimo errore
+pub async fn retry<T, E>(
+    kind: ErrorKind,
+    mut op: impl AsyncFnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(_) if attempt < RETRY_ATTEMPTS => {
+                attempt += 1;
+                Timer::after(RETRY_DELAY).await;
+            }
+            Err(e) => {
+                report(kind);
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Writer che tronca il testo alla capacità della stringa
+struct Truncating<'a, const N: usize> {
+    out: &'a mut heapless::String<N>,
+    truncated: bool,
+}
+
+impl<const N: usize> Write for Truncating<'_, N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if self.truncated {
+            return Ok(());
+        }
+        if self.out.push_str(s).is_err() {
+            // Copia fino all'ultimo carattere intero che ci sta
+            let room = N - self.out.len();
+            let end = (0..=room)
+                .rev()
+                .find(|&i| s.is_char_boundary(i))
+                .unwrap_or(0);
+            let _ = self.out.push_str(&s[..end]);
+            self.truncated = true;
+        }
+        Ok(())
+    }
+}
+
+/// Formatta un testo in una stringa a capacità fissa, troncandolo se non ci sta
+///
+/// Il testo troncato termina con "..." ed è conteggiato come
+/// [`ErrorKind::Truncated`].
+///
+/// # Argomenti
+/// * `args` - Testo da formattare (con `format_args!`)
+///
+/// # Ritorna
+/// * heapless::String<N> - Testo, eventualmente troncato
+pub fn fit<const N: usize>(args: core::fmt::Arguments) -> heapless::String<N> {
+    let mut out = heapless::String::new();
+    let mut writer = Truncating {
+        out: &mut out,
+        truncated: false,
+    };
+    let _ = writer.write_fmt(args);
+    if writer.truncated {
+        report(ErrorKind::Truncated);
+        if N >= ELLIPSIS.len() {
+            let mut end = N - ELLIPSIS.len();
+            while !out.is_char_boundary(end) {
+                end -= 1;
+            }
+            out.truncate(end);
+            let _ = out.push_str(ELLIPSIS);
+        }
+    }
+    out
+}
+
+/// Aggiunge un testo in coda a una stringa a capacità fissa, troncandolo se non ci sta
+///
+/// # Argomenti
+/// * `out` - Stringa di destinazione
+/// * `s` - Testo da aggiungere
+pub fn push_fit<const N: usize>(out: &mut heapless::String<N>, s: &str) {
+    let mut writer = Truncating {
+        out,
+        truncated: false,
+    };
+    let _ = writer.write_str(s);
+    if writer.truncated {
+        report(ErrorKind::Truncated);
+    }
+}
@@ -0,0 +1,161 @@
+//! events.rs - Eventi sui fronti dei GPIO configurati come ingressi.
+//!
+//! Ogni fronte viene marcato temporalmente (microsecondi dall'avvio),
+//! conteggiato e pubblicato su un canale broadcast da cui il web server
+//! legge per inviare gli eventi al browser via Server-Sent Events.
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::bus::{self, Event};
+use crate::errors;
+use embassy_futures::select::{Either, select};
+use embassy_rp::gpio::{Input, Level};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::pubsub::PubSubChannel;
+use embassy_time::{Instant, Timer};
+use picoserve::response::EventStream;
+use picoserve::response::Json;
+use picoserve::response::sse::{EventSource, EventWriter};
+use picoserve::routing::{PathRouter, get};
+use portable_atomic::{AtomicU32, Ordering};
+
+/// GPIO monitorati (con pull-up interno)
+pub const EVENT_PINS: [u8; 2] = [14, 15];
+
+/// Numero di eventi in coda per ogni client
+const EVENT_QUEUE: usize = 16;
+
+/// Numero massimo di client SSE contemporanei
+const EVENT_SUBSCRIBERS: usize = 2;
+
+/// Intervallo dei keep-alive SSE in assenza di eventi
+const KEEPALIVE_SECS: u64 = 15;
+
+/// Evento su un fronte di un GPIO
+#[derive(Clone, Copy)]
+pub struct GpioEvent {
+    pub pin: u8,
+    pub rising: bool,
+    pub timestamp_us: u64,
+}
+
+/// Canale broadcast degli eventi GPIO
+static EVENTS: PubSubChannel<
+    CriticalSectionRawMutex,
+    GpioEvent,
+    EVENT_QUEUE,
+    EVENT_SUBSCRIBERS,
+    { EVENT_PINS.len() },
+> = PubSubChannel::new();
+
+/// Contatori dei fronti per ciascun pin di EVENT_PINS
+static EDGE_COUNTS: [AtomicU32; EVENT_PINS.len()] = [const { AtomicU32::new(0) }; EVENT_PINS.len()];
+
+/// Conteggio dei fronti di un pin monitorato
+#[derive(serde::Serialize)]
+pub struct EdgeCount {
+    pub pin: u8,
+    pub edges: u32,
+}
+
+/// Ritorna i contatori dei fronti per ciascun pin monitorato
+///
+/// # Ritorna
+/// * heapless::Vec<EdgeCount, N> - Un contatore per pin
+pub fn edge_counts() -> heapless::Vec<EdgeCount, { EVENT_PINS.len() }> {
+    EVENT_PINS
+        .iter()
+        .zip(EDGE_COUNTS.iter())
+        .map(|(&pin, count)| EdgeCount {
+            pin,
+            edges: count.load(Ordering::Relaxed),
+        })
+        .collect()
+}
+
+#[embassy_executor::task(pool_size = EVENT_PINS.len())]
+/// Task che attende i fronti di un GPIO e li pubblica
+///
+/// # Argomenti
+/// * `index` - Indice del pin in EVENT_PINS
+/// * `input` - Ingresso da monitorare
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn gpio_event_task(index: usize, mut input: Input<'static>) {
+    let publisher = EVENTS.immediate_publisher();
+    let pin = EVENT_PINS[index];
+    loop {
+        input.wait_for_any_edge().await;
+        crate::tasks::touch("gpio");
+        let event = GpioEvent {
+            pin,
+            rising: input.get_level() == Level::High,
+            timestamp_us: Instant::now().as_micros(),
+        };
+        EDGE_COUNTS[index].fetch_add(1, Ordering::Relaxed);
+        publisher.publish_immediate(event);
+        // Gli ingressi hanno il pull-up: il fronte di discesa è la pressione
+        if !event.rising {
+            bus::publish(Event::ButtonPressed { pin });
+        }
+    }
+}
+
+/// Sorgente SSE degli eventi GPIO
+pub struct GpioEventStream;
+
+impl EventSource for GpioEventStream {
+    /// Invia gli eventi GPIO al client finché la connessione resta aperta
+    ///
+    /// # Argomenti
+    /// * `writer` - Writer degli eventi SSE
+    ///
+    /// # Ritorna
+    /// * Result<(), W::Error> - Errore di scrittura (client disconnesso)
+    async fn write_events<W: picoserve::io::Write>(
+        self,
+        mut writer: EventWriter<W>,
+    ) -> Result<(), W::Error> {
+        let Ok(mut subscriber) = EVENTS.subscriber() else {
+            return writer.write_event("error", "troppi client connessi").await;
+        };
+
+        loop {
+            match select(
+                subscriber.next_message_pure(),
+                Timer::after_secs(KEEPALIVE_SECS),
+            )
+            .await
+            {
+                Either::First(event) => {
+                    let data: heapless::String<80> = errors::fit(format_args!(
+                        "{{\"pin\":{},\"rising\":{},\"timestamp_us\":{}}}",
+                        event.pin, event.rising, event.timestamp_us
+                    ));
+                    writer.write_event("gpio", data.as_str()).await?;
+                }
+                Either::Second(()) => writer.write_keepalive().await?,
+            }
+        }
+    }
+}
+
+/// Rotte degli eventi GPIO: `/api/gpio/events` (SSE) e `/api/gpio/counts`
+pub struct GpioRoutes;
+
+impl Routes for GpioRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                "/api/gpio/events",
+                get(|| async move { EventStream(GpioEventStream) }),
+            )
+            .route(
+                "/api/gpio/counts",
+                get(|| async move { Json(edge_counts()) }),
+            )
+    }
+}
@@ -0,0 +1,235 @@
+//! fault.rs - Segnalazione dei guasti tramite il LED del CYW43.
+//!
+//! Ogni classe di guasto ha un codice: il LED emette tante brevi
+//! accensioni quante il codice, seguite da una pausa, così che una pico
+//! senza console indichi comunque il motivo del malfunzionamento:
+//! * 2 lampeggi - join WiFi fallito
+//! * 3 lampeggi - configurazione dello stack di rete non arrivata in tempo
+//! * SOS (3 brevi, 3 lunghi, 3 brevi) - panic salvato dall'esecuzione precedente
+//!
+//! In assenza di guasti il LED mostra lo stato del sistema (vedi
+//! status_led.rs): connessione in corso, rete attiva, connessione
+//! degradata o risoluzione in corso.
+//! Con il comando `led` o con `/api/led` (vedi [`set_led_mode`]) resta invece
+//! acceso, spento o lampeggia con un periodo scelto.
+
+use crate::bus::{self, Event};
+use cyw43::Control;
+use embassy_time::{Duration, Timer};
+use portable_atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering};
+
+/// Durata di un'accensione (e dello spegnimento tra due accensioni)
+const PULSE: Duration = Duration::from_millis(200);
+
+/// Durata di un'accensione lunga (linea dell'SOS)
+const LONG_PULSE: Duration = Duration::from_millis(600);
+
+/// Pausa tra due ripetizioni del codice
+const PAUSE: Duration = Duration::from_millis(1500);
+
+/// Semiperiodo del lampeggio lento (connessione degradata)
+pub const SLOW_BLINK: Duration = Duration::from_millis(250);
+
+/// Classi di guasto, in ordine di priorità
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fault {
+    WifiJoinFailed = 2,
+    StackConfigTimeout = 3,
+    StoredPanic = 4,
+}
+
+/// Tutte le classi di guasto, in ordine di priorità
+const FAULTS: [Fault; 3] = [
+    Fault::WifiJoinFailed,
+    Fault::StackConfigTimeout,
+    Fault::StoredPanic,
+];
+
+/// Guasti attivi (un bit per codice)
+static ACTIVE: AtomicU8 = AtomicU8::new(0);
+
+/// Comportamento del LED in assenza di guasti
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LedMode {
+    /// Segue lo stato del sistema (vedi status_led.rs)
+    Auto = 0,
+    On = 1,
+    Off = 2,
+    /// Lampeggio regolare con il semiperiodo di [`blink_period`]
+    Blink = 3,
+}
+
+impl LedMode {
+    /// Nome della modalità, es. per /api/led
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LedMode::Auto => "auto",
+            LedMode::On => "on",
+            LedMode::Off => "off",
+            LedMode::Blink => "blink",
+        }
+    }
+}
+
+/// Modalità del LED corrente
+static LED_MODE: AtomicU8 = AtomicU8::new(LedMode::Auto as u8);
+
+/// Semiperiodo del lampeggio in modalità `Blink` (ms)
+static BLINK_PERIOD_MS: AtomicU32 = AtomicU32::new(SLOW_BLINK.as_millis() as u32);
+
+/// Ultimo stato impostato sul LED
+static LED_LIT: AtomicBool = AtomicBool::new(false);
+
+impl Fault {
+    /// Numero di lampeggi del codice (il panic salvato è segnalato con l'SOS)
+    pub fn blinks(self) -> u8 {
+        self as u8
+    }
+
+    fn mask(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+/// Segnala un guasto
+///
+/// # Argomenti
+/// * `fault` - Guasto da segnalare
+pub fn raise(fault: Fault) {
+    if ACTIVE.fetch_or(fault.mask(), Ordering::Relaxed) & fault.mask() == 0 {
+        log::warn!(
+            "Guasto segnalato: {:?} ({} lampeggi)",
+            fault,
+            fault.blinks()
+        );
+    }
+}
+
+/// Rimuove la segnalazione di un guasto risolto
+///
+/// # Argomenti
+/// * `fault` - Guasto risolto
+pub fn clear(fault: Fault) {
+    ACTIVE.fetch_and(!fault.mask(), Ordering::Relaxed);
+}
+
+/// Ritorna il guasto attivo con priorità più alta
+///
+/// # Ritorna
+/// * Option<Fault> - Guasto da mostrare, se presente
+pub fn current() -> Option<Fault> {
+    let active = ACTIVE.load(Ordering::Relaxed);
+    FAULTS.into_iter().find(|f| active & f.mask() != 0)
+}
+
+/// Imposta il comportamento del LED in assenza di guasti
+///
+/// # Argomenti
+/// * `mode` - Nuova modalità
+pub fn set_led_mode(mode: LedMode) {
+    if LED_MODE.swap(mode as u8, Ordering::Relaxed) != mode as u8 {
+        bus::publish(Event::LedModeChanged { mode });
+    }
+}
+
+/// Ritorna il comportamento del LED in assenza di guasti
+///
+/// # Ritorna
+/// * LedMode - Modalità corrente
+pub fn led_mode() -> LedMode {
+    match LED_MODE.load(Ordering::Relaxed) {
+        1 => LedMode::On,
+        2 => LedMode::Off,
+        3 => LedMode::Blink,
+        _ => LedMode::Auto,
+    }
+}
+
+/// Imposta il semiperiodo del lampeggio in modalità `Blink`
+///
+/// # Argomenti
+/// * `period` - Semiperiodo (limitato tra 50 ms e 5 s)
+pub fn set_blink_period(period: Duration) {
+    let ms = period.as_millis().clamp(50, 5000) as u32;
+    BLINK_PERIOD_MS.store(ms, Ordering::Relaxed);
+}
+
+/// Ritorna il semiperiodo del lampeggio in modalità `Blink`
+///
+/// # Ritorna
+/// * Duration - Semiperiodo
+pub fn blink_period() -> Duration {
+    Duration::from_millis(BLINK_PERIOD_MS.load(Ordering::Relaxed) as u64)
+}
+
+/// Ritorna l'ultimo stato impostato sul LED
+///
+/// # Ritorna
+/// * bool - true se il LED è acceso
+pub fn led_lit() -> bool {
+    LED_LIT.load(Ordering::Relaxed)
+}
+
+/// Accende o spegne il LED
+pub trait Led {
+    async fn set_led(&mut self, on: bool);
+}
+
+impl Led for Control<'_> {
+    async fn set_led(&mut self, on: bool) {
+        self.gpio_set(0, on).await;
+        LED_LIT.store(on, Ordering::Relaxed);
+    }
+}
+
+impl Led for crate::SharedControl {
+    async fn set_led(&mut self, on: bool) {
+        self.0.lock().await.gpio_set(0, on).await;
+        LED_LIT.store(on, Ordering::Relaxed);
+    }
+}
+
+/// Accende il LED per la durata indicata, seguita da uno spegnimento breve
+async fn pulse(led: &mut impl Led, duration: Duration) {
+    led.set_led(true).await;
+    Timer::after(duration).await;
+    led.set_led(false).await;
+    Timer::after(PULSE).await;
+}
+
+/// Mostra una volta il codice di un guasto sul LED, pausa compresa
+///
+/// # Argomenti
+/// * `led` - LED da usare
+/// * `fault` - Guasto da mostrare
+pub async fn blink_code(led: &mut impl Led, fault: Fault) {
+    if fault == Fault::StoredPanic {
+        for duration in [PULSE, LONG_PULSE, PULSE] {
+            for _ in 0..3 {
+                pulse(led, duration).await;
+            }
+        }
+    } else {
+        for _ in 0..fault.blinks() {
+            pulse(led, PULSE).await;
+        }
+    }
+    Timer::after(PAUSE).await;
+}
+
+/// Mostra il codice di un guasto finché il future non viene interrotto
+/// (da usare in `select` con l'attesa della risoluzione del guasto)
+///
+/// # Argomenti
+/// * `led` - LED da usare
+/// * `fault` - Guasto da mostrare
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn indicate(led: &mut impl Led, fault: Fault) -> ! {
+    loop {
+        blink_code(led, fault).await;
+    }
+}
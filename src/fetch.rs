@@ -0,0 +1,348 @@
+//! fetch.rs - Lettura periodica di un documento JSON da un servizio HTTP.
+//!
+//! Con `FETCH_URL` in configurazione (es. `http://meteo.lan/now.json`) il
+//! task [`fetch_task`] scarica il documento ogni `FETCH_INTERVAL_SECS`
+//! secondi, con una richiesta HTTP/1.0 (`Connection: close`, quindi senza
+//! chunked encoding). Il nome dell'host viene risolto con il DNS dello stack
+//! (server `DNS_SERVER`); un indirizzo IPv4 scritto per esteso viene usato
+//! direttamente. Sono supportati solo `http://` e risposte fino a
+//! [`RESPONSE_CAPACITY`] byte.
+//!
+//! Del documento vengono conservati i valori dei campi elencati in
+//! `FETCH_KEYS` (separati da virgole, cercati a qualunque livello), mostrati
+//! sulla dashboard e su `/api/fetch` insieme all'esito delle letture.
+
+use crate::errors;
+use crate::utility::{get_fetch_interval_secs, get_fetch_keys, get_fetch_url};
+use core::cell::RefCell;
+use critical_section::Mutex as CsMutex;
+use embassy_net::dns::DnsQueryType;
+use embassy_net::tcp::TcpSocket;
+use embassy_net::{IpAddress, IpEndpoint, Ipv4Address, Stack};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_io_async::Write;
+
+/// Dimensione massima della risposta (header compresi)
+pub const RESPONSE_CAPACITY: usize = 1024;
+
+/// Numero massimo di campi conservati
+const MAX_VALUES: usize = 4;
+
+/// Timeout delle operazioni sul socket
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Indirizzo del documento, scritto come `http://host[:porta][/percorso]`
+#[derive(Debug)]
+pub struct FetchUrl<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub path: &'a str,
+}
+
+impl<'a> FetchUrl<'a> {
+    /// Interpreta l'indirizzo del documento
+    ///
+    /// # Argomenti
+    /// * `url` - Valore di configurazione
+    ///
+    /// # Ritorna
+    /// * Option<FetchUrl> - Indirizzo, None se non valido o non `http://`
+    pub fn parse(url: &'a str) -> Option<Self> {
+        let rest = url.trim().strip_prefix("http://")?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.split_once(':') {
+            Some((host, port)) => (host, port.parse::<u16>().ok()?),
+            None => (authority, 80),
+        };
+        (!host.is_empty()).then_some(Self { host, port, path })
+    }
+}
+
+/// Errori di una lettura
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FetchError {
+    /// `FETCH_URL` non valido
+    InvalidUrl,
+    /// Nome dell'host non risolto
+    Dns,
+    /// Connessione rifiutata o scaduta
+    Connect,
+    /// Errore di invio o ricezione
+    Io,
+    /// Risposta più lunga di RESPONSE_CAPACITY
+    TooLarge,
+    /// Risposta non HTTP o non UTF-8
+    Malformed,
+    /// Stato HTTP diverso da 2xx
+    Status,
+}
+
+impl FetchError {
+    fn as_str(self) -> &'static str {
+        match self {
+            FetchError::InvalidUrl => "invalid_url",
+            FetchError::Dns => "dns",
+            FetchError::Connect => "connect",
+            FetchError::Io => "io",
+            FetchError::TooLarge => "too_large",
+            FetchError::Malformed => "malformed",
+            FetchError::Status => "status",
+        }
+    }
+}
+
+/// Valore di un campo del documento
+#[derive(Clone, serde::Serialize)]
+pub struct FetchedValue {
+    key: heapless::String<16>,
+    /// Valore letto, None se il campo non c'è
+    value: Option<heapless::String<24>>,
+}
+
+/// Esito delle letture
+struct FetchState {
+    fetches: u32,
+    failures: u32,
+    /// Uptime (ms) dell'ultima lettura riuscita
+    last_success_ms: Option<u64>,
+    last_error: Option<FetchError>,
+    /// Ultimo stato HTTP ricevuto
+    last_status: Option<u16>,
+    values: heapless::Vec<FetchedValue, MAX_VALUES>,
+}
+
+static STATE: CsMutex<RefCell<FetchState>> = CsMutex::new(RefCell::new(FetchState {
+    fetches: 0,
+    failures: 0,
+    last_success_ms: None,
+    last_error: None,
+    last_status: None,
+    values: heapless::Vec::new(),
+}));
+
+/// Stato restituito da /api/fetch
+#[derive(serde::Serialize)]
+pub struct FetchReport {
+    enabled: bool,
+    fetches: u32,
+    failures: u32,
+    /// Secondi trascorsi dall'ultima lettura riuscita
+    last_success_secs: Option<u64>,
+    /// Errore dell'ultima lettura, None se è riuscita
+    last_error: Option<&'static str>,
+    last_status: Option<u16>,
+    values: heapless::Vec<FetchedValue, MAX_VALUES>,
+}
+
+/// Ritorna l'esito delle letture
+///
+/// # Ritorna
+/// * FetchReport - Contatori, ultimo errore e valori letti
+pub fn report() -> FetchReport {
+    let now = Instant::now().as_millis();
+    critical_section::with(|cs| {
+        let state = STATE.borrow_ref(cs);
+        FetchReport {
+            enabled: get_fetch_url().is_some(),
+            fetches: state.fetches,
+            failures: state.failures,
+            last_success_secs: state.last_success_ms.map(|at| (now - at) / 1000),
+            last_error: state.last_error.map(FetchError::as_str),
+            last_status: state.last_status,
+            values: state.values.clone(),
+        }
+    })
+}
+
+/// Ritorna il valore di un campo di un documento JSON
+///
+/// Il campo è cercato a qualunque livello; sono riconosciuti solo i valori
+/// scalari (stringhe, numeri, booleani e null) e le stringhe senza escape.
+///
+/// # Argomenti
+/// * `document` - Documento JSON
+/// * `key` - Nome del campo
+///
+/// # Ritorna
+/// * Option<&str> - Valore (senza virgolette), None se il campo non c'è
+fn json_value<'a>(document: &'a str, key: &str) -> Option<&'a str> {
+    let mut rest = document;
+    loop {
+        // Le stringhe si alternano tra apertura e chiusura: un nome seguito
+        // da ':' è il nome di un campo
+        let (_, after_open) = rest.split_once('"')?;
+        let (name, after_close) = after_open.split_once('"')?;
+        rest = after_close;
+        let Some(value) = after_close.trim_start().strip_prefix(':') else {
+            continue;
+        };
+        if name != key {
+            continue;
+        }
+        let value = value.trim_start();
+        return match value.strip_prefix('"') {
+            Some(string) => string.split_once('"').map(|(string, _)| string),
+            None => {
+                let end = value
+                    .find(|c: char| matches!(c, ',' | '}' | ']') || c.is_whitespace())
+                    .unwrap_or(value.len());
+                let scalar = &value[..end];
+                (!scalar.is_empty() && !scalar.starts_with(['{', '['])).then_some(scalar)
+            }
+        };
+    }
+}
+
+/// Risolve il nome di un host con il DNS dello stack (server `DNS_SERVER`)
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+/// * `host` - Nome dell'host o indirizzo IPv4 scritto per esteso
+///
+/// # Ritorna
+/// * Option<IpAddress> - Indirizzo, None se il nome non è stato risolto
+pub async fn resolve(stack: Stack<'static>, host: &str) -> Option<IpAddress> {
+    match host.parse::<Ipv4Address>() {
+        Ok(address) => Some(IpAddress::Ipv4(address)),
+        Err(_) => stack
+            .dns_query(host, DnsQueryType::A)
+            .await
+            .ok()
+            .and_then(|addresses| addresses.first().copied()),
+    }
+}
+
+/// Scarica il documento
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+/// * `url` - Indirizzo del documento
+/// * `response` - Buffer della risposta
+///
+/// # Ritorna
+/// * Result<(u16, &str), FetchError> - Stato HTTP e corpo della risposta
+async fn fetch_once<'b>(
+    stack: Stack<'static>,
+    url: &FetchUrl<'_>,
+    response: &'b mut [u8],
+) -> Result<(u16, &'b str), FetchError> {
+    let address = resolve(stack, url.host).await.ok_or(FetchError::Dns)?;
+
+    let mut rx_buffer = [0; 512];
+    let mut tx_buffer = [0; 256];
+    let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+    socket.set_timeout(Some(IO_TIMEOUT));
+    socket
+        .connect(IpEndpoint::new(address, url.port))
+        .await
+        .map_err(|_| FetchError::Connect)?;
+
+    for part in [
+        "GET ",
+        url.path,
+        " HTTP/1.0\r\nHost: ",
+        url.host,
+        "\r\nAccept: application/json\r\nConnection: close\r\n\r\n",
+    ] {
+        socket
+            .write_all(part.as_bytes())
+            .await
+            .map_err(|_| FetchError::Io)?;
+    }
+
+    // Con HTTP/1.0 il server chiude la connessione al termine della risposta
+    let mut len = 0;
+    loop {
+        if len == response.len() {
+            socket.abort();
+            return Err(FetchError::TooLarge);
+        }
+        match socket.read(&mut response[len..]).await {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(_) => return Err(FetchError::Io),
+        }
+    }
+    socket.close();
+
+    let text = core::str::from_utf8(&response[..len]).map_err(|_| FetchError::Malformed)?;
+    let (head, body) = text.split_once("\r\n\r\n").ok_or(FetchError::Malformed)?;
+    let status = head
+        .split(' ')
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or(FetchError::Malformed)?;
+    Ok((status, body))
+}
+
+/// Registra l'esito di una lettura
+///
+/// # Argomenti
+/// * `result` - Stato HTTP e corpo della risposta, oppure errore
+fn record(result: Result<(u16, &str), FetchError>) {
+    let keys = get_fetch_keys();
+    let result = match result {
+        Ok((status, _)) if !(200..300).contains(&status) => Err((FetchError::Status, Some(status))),
+        Ok((status, body)) => Ok((status, body)),
+        Err(e) => Err((e, None)),
+    };
+    critical_section::with(|cs| {
+        let mut state = STATE.borrow_ref_mut(cs);
+        state.fetches += 1;
+        match result {
+            Ok((status, body)) => {
+                state.last_success_ms = Some(Instant::now().as_millis());
+                state.last_error = None;
+                state.last_status = Some(status);
+                state.values.clear();
+                for key in keys.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+                    let value = FetchedValue {
+                        key: errors::fit(format_args!("{}", key)),
+                        value: json_value(body, key)
+                            .map(|value| errors::fit(format_args!("{}", value))),
+                    };
+                    if state.values.push(value).is_err() {
+                        break;
+                    }
+                }
+            }
+            Err((e, status)) => {
+                state.failures += 1;
+                state.last_error = Some(e);
+                state.last_status = status;
+            }
+        }
+    });
+    match result {
+        Ok(_) => log::debug!("fetch: documento letto"),
+        Err((e, _)) => log::warn!("fetch: lettura fallita ({})", e.as_str()),
+    }
+}
+
+#[embassy_executor::task]
+/// Task che legge periodicamente il documento configurato
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn fetch_task(stack: Stack<'static>) {
+    let mut response = [0; RESPONSE_CAPACITY];
+    loop {
+        crate::tasks::touch("fetch");
+        // La configurazione è riletta a ogni lettura: modificabile a runtime
+        if let Some(url) = get_fetch_url() {
+            stack.wait_config_up().await;
+            let result = match FetchUrl::parse(&url) {
+                Some(url) => fetch_once(stack, &url, &mut response).await,
+                None => Err(FetchError::InvalidUrl),
+            };
+            record(result);
+        }
+        Timer::after_secs(get_fetch_interval_secs()).await;
+    }
+}
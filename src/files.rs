@@ -0,0 +1,131 @@
+//! files.rs - Pagine statiche incluse nel firmware (vedi assets.rs), nella
+//! lingua della richiesta (vedi i18n.rs), e file della directory `static/`.
+//!
+//! I file sotto `/static/` hanno un `ETag` calcolato da build.rs e
+//! `Cache-Control: public, max-age=3600`: scaduta la cache il browser li
+//! riconvalida con `If-None-Match` e riceve `304 Not Modified` se il
+//! firmware non è cambiato. Come le pagine, i file di testo sono inviati
+//! compressi ai client che accettano gzip.
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::assets::{AcceptGzip, StaticBody, StaticFile};
+use crate::i18n::Lang;
+use core::convert::Infallible;
+use picoserve::extract::FromRequestParts;
+use picoserve::request::RequestParts;
+use picoserve::response::{IntoResponse, Response, StatusCode};
+use picoserve::routing::{PathRouter, get, parse_path_segment};
+
+/// Durata della cache dei file statici nel browser
+const STATIC_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// Versioni di un file già in cache nel browser (intestazione `If-None-Match`)
+pub struct IfNoneMatch(Option<heapless::String<128>>);
+
+impl<'r, State> FromRequestParts<'r, State> for IfNoneMatch {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        _state: &'r State,
+        request_parts: &RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        let header = request_parts.headers().get("If-None-Match");
+        let tags = header
+            .as_ref()
+            .and_then(|value| core::str::from_utf8(value.as_raw()).ok())
+            .and_then(|value| heapless::String::try_from(value).ok());
+        Ok(Self(tags))
+    }
+}
+
+/// Risposta `200` con un file statico
+///
+/// # Argomenti
+/// * `file` - File da inviare
+/// * `accept` - Codifiche accettate dal client
+///
+/// # Ritorna
+/// * Result<impl IntoResponse, impl IntoResponse> - File in chiaro (Ok) o
+///   compresso con `Content-Encoding: gzip` (Err): l'intestazione in più
+///   cambia il tipo della risposta, quindi le due varianti stanno nei due
+///   lati del Result
+fn file_response(
+    file: &'static StaticFile,
+    accept: AcceptGzip,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let gzip = file.asset.gzip_for(accept);
+    let body = StaticBody::new(file, gzip.unwrap_or(file.asset.body));
+    let response = Response::new(StatusCode::OK, body)
+        .with_header("ETag", file.etag)
+        .with_header("Cache-Control", STATIC_CACHE_CONTROL)
+        .with_header("Vary", "Accept-Encoding");
+    match gzip {
+        Some(_) => Err(response.with_header("Content-Encoding", "gzip")),
+        None => Ok(response),
+    }
+}
+
+/// Risponde a `GET /static/<name>`
+///
+/// # Argomenti
+/// * `name` - Nome del file
+/// * `cached` - Versioni in cache nel browser
+/// * `accept` - Codifiche accettate dal client
+/// * `lang` - Lingua del messaggio di errore
+///
+/// # Ritorna
+/// * Result<impl IntoResponse, impl IntoResponse> - File, oppure `304` se la
+///   versione in cache è ancora valida o `404` se il file non esiste
+fn serve_static(
+    name: &str,
+    cached: &IfNoneMatch,
+    accept: AcceptGzip,
+    lang: Lang,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let Some(file) = StaticFile::find(name) else {
+        return Err(
+            Response::new(StatusCode::NOT_FOUND, lang.strings().static_not_found)
+                .with_header("Cache-Control", "no-store"),
+        );
+    };
+    if cached.0.as_deref().is_some_and(|tags| file.matches(tags)) {
+        return Err(Response::new(StatusCode::NOT_MODIFIED, "").with_header("ETag", file.etag));
+    }
+    Ok(file_response(file, accept))
+}
+
+/// Rotte delle pagine statiche: `/` (dashboard), `/time` (ora e attività
+/// programmate) e `/static/<name>` (file della directory `static/`)
+pub struct FileRoutes;
+
+impl Routes for FileRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                "/",
+                get(|lang: Lang, accept: AcceptGzip| async move {
+                    lang.strings().index_page.html(accept)
+                }),
+            )
+            .route(
+                "/time",
+                get(|lang: Lang, accept: AcceptGzip| async move {
+                    lang.strings().time_page.html(accept)
+                }),
+            )
+            .route(
+                ("/static", parse_path_segment::<heapless::String<32>>()),
+                get(
+                    |name: heapless::String<32>,
+                     cached: IfNoneMatch,
+                     accept: AcceptGzip,
+                     lang: Lang| async move {
+                        serve_static(&name, &cached, accept, lang)
+                    },
+                ),
+            )
+    }
+}
@@ -0,0 +1,407 @@
+//! flash_store.rs - Archivio di record in flash condiviso tra i moduli.
+//!
+//! Ogni archivio occupa alcuni settori dedicati in cima alla flash, fuori
+//! dalla zona del firmware (vedi memory.x), ed è un log circolare di slot
+//! di dimensione fissa. Ogni record ha un'intestazione con marcatore,
+//! numero di sequenza, chiave, lunghezza e CRC-32: un record scritto a
+//! metà (es. per uno spegnimento) viene semplicemente ignorato.
+//!
+//! I record vengono aggiunti in coda; quando la coda raggiunge un settore
+//! già usato, il settore più vecchio viene cancellato. La scrittura ruota
+//! quindi su tutti i settori dell'archivio e ognuno viene cancellato una
+//! volta per giro (wear leveling). Con `KEYS > 0` l'archivio conserva
+//! l'ultimo valore di ogni chiave: prima di cancellare un settore i valori
+//! ancora validi che contiene vengono ricopiati in coda (compattazione).
+//! Una chiave nuova oltre le `KEYS` già salvate viene rifiutata con
+//! [`StoreError::Full`], invece di perdere valori alla compattazione.
+//! Con `KEYS = 0` è uno storico e i record più vecchi vengono persi.
+//!
+//! Il driver della flash è condiviso tramite [`with_flash`] tra gli
+//! archivi e l'aggiornamento del firmware (ota.rs). Le operazioni non
+//! girano in una sezione critica: durante una cancellazione o una scrittura
+//! la flash non è leggibile, quindi embassy-rp mette in pausa il core 1 e
+//! disabilita gli interrupt solo per la durata della chiamata alla ROM;
+//! [`with_rom_flash`] fa lo stesso per le funzioni della ROM chiamate
+//! direttamente.
+//!
+//! Occupazione della flash (dall'alto):
+//! * 2 settori - storico dei crash (crash_log.rs)
+//! * 2 settori - contatori persistenti (counters.rs)
+//! * 2 settori - stato dell'aggiornamento del firmware (ota.rs)
+//! * 2 settori - log salvati prima di uno spegnimento (brownout.rs)
+//! * 2 settori - configurazione salvata (config_store.rs)
+
+use crate::board::FLASH_SIZE;
+use core::cell::RefCell;
+use critical_section::Mutex as CsMutex;
+use embassy_rp::flash::{Blocking, ERASE_SIZE, Flash};
+use embassy_rp::peripherals::FLASH;
+
+/// Flash con la dimensione della scheda (vedi FLASH_SIZE)
+pub type StoreFlash = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
+
+/// Indirizzo XIP della flash senza traduzione degli indirizzi, per leggere
+/// i record senza driver: con le partizioni A/B la ROM mappa 0x10000000
+/// sullo slot in esecuzione, mentre gli archivi usano offset fisici
+#[cfg(feature = "rp235x")]
+pub const XIP_BASE: usize = 0x1C00_0000;
+
+/// Indirizzo XIP della flash senza cache (l'RP2040 non traduce gli indirizzi)
+#[cfg(feature = "rp2040")]
+pub const XIP_BASE: usize = 0x1300_0000;
+
+/// Driver della flash, ceduto dal main con [`init`]
+static FLASH_CELL: CsMutex<RefCell<Option<StoreFlash>>> = CsMutex::new(RefCell::new(None));
+
+/// Dimensione dell'intestazione: magic, seq, chiave, lunghezza, crc
+pub const HEADER_SIZE: usize = 16;
+
+/// Marcatore di un record valido ("RECD")
+const RECORD_MAGIC: u32 = 0x4443_4552;
+
+/// Errori dell'archivio
+#[derive(Debug)]
+pub enum StoreError {
+    /// Il contenuto non entra in uno slot
+    TooLarge,
+    /// Driver della flash non ancora ceduto con init, o in uso
+    NoFlash,
+    /// Sono già salvate `KEYS` chiavi diverse: la compattazione non
+    /// conserverebbe quella nuova
+    Full,
+    Flash(embassy_rp::flash::Error),
+}
+
+/// Record letto dalla flash
+#[derive(Clone, Copy)]
+pub struct Record {
+    pub seq: u32,
+    pub key: u16,
+    pub payload: &'static [u8],
+}
+
+/// Archivio di record su settori dedicati della flash
+///
+/// * `SLOT` - Dimensione di uno slot (intestazione compresa), divisore di ERASE_SIZE
+/// * `KEYS` - Numero massimo di chiavi conservate dalla compattazione (0 = storico)
+pub struct RecordStore<const SLOT: usize, const KEYS: usize> {
+    name: &'static str,
+    offset: u32,
+    sectors: usize,
+}
+
+/// Cede il driver della flash agli archivi (da chiamare solo dal main una sola volta)
+///
+/// # Argomenti
+/// * `flash` - Driver della flash
+pub fn init(flash: StoreFlash) {
+    critical_section::with(|cs| FLASH_CELL.borrow(cs).replace(Some(flash)));
+}
+
+/// Esegue un'operazione con il driver della flash
+///
+/// # Argomenti
+/// * `f` - Operazione da eseguire
+///
+/// # Ritorna
+/// * Result<R, StoreError> - Risultato dell'operazione, NoFlash se il driver non è disponibile
+pub fn with_flash<R>(
+    f: impl FnOnce(&mut StoreFlash) -> Result<R, StoreError>,
+) -> Result<R, StoreError> {
+    // Il driver resta fuori dalla cella per la durata dell'operazione: la
+    // sezione critica serve solo a prenderlo e a restituirlo
+    let Some(mut flash) = critical_section::with(|cs| FLASH_CELL.borrow(cs).take()) else {
+        return Err(StoreError::NoFlash);
+    };
+    let result = f(&mut flash);
+    critical_section::with(|cs| FLASH_CELL.borrow(cs).replace(Some(flash)));
+    result
+}
+
+/// Esegue una funzione della ROM che cancella o scrive la flash, con il
+/// core 1 in pausa e gli interrupt disabilitati solo per la sua durata
+///
+/// # Argomenti
+/// * `f` - Chiamata alla ROM
+///
+/// # Ritorna
+/// * R - Risultato della chiamata
+#[cfg(feature = "rp235x")]
+pub fn with_rom_flash<R>(f: impl FnOnce() -> R) -> R {
+    // Il core 1 va fermato prima della sezione critica: la pausa aspetta la
+    // risposta del suo interrupt, che non arriverebbe se il core 1 fosse in
+    // attesa della stessa sezione critica
+    embassy_rp::multicore::pause_core1();
+    let result = critical_section::with(|_| f());
+    embassy_rp::multicore::resume_core1();
+    result
+}
+
+/// CRC-32 (IEEE, riflesso)
+///
+/// # Argomenti
+/// * `crc` - CRC parziale (0 all'inizio)
+/// * `bytes` - Dati da aggiungere
+///
+/// # Ritorna
+/// * u32 - CRC aggiornato
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+impl<const SLOT: usize, const KEYS: usize> RecordStore<SLOT, KEYS> {
+    /// Spazio disponibile per il contenuto di un record
+    pub const PAYLOAD_CAPACITY: usize = SLOT - HEADER_SIZE;
+
+    const SLOTS_PER_SECTOR: usize = ERASE_SIZE / SLOT;
+
+    /// Crea un archivio sui `sectors` settori che terminano `top_sectors`
+    /// settori sotto la fine della flash
+    ///
+    /// # Argomenti
+    /// * `name` - Nome per i log
+    /// * `top_sectors` - Settori occupati dagli archivi sopra questo (vedi la tabella in cima al file)
+    /// * `sectors` - Settori dell'archivio (almeno 2)
+    ///
+    /// # Ritorna
+    /// * RecordStore - Archivio
+    pub const fn new(name: &'static str, top_sectors: usize, sectors: usize) -> Self {
+        assert!(sectors >= 2 && ERASE_SIZE % SLOT == 0 && SLOT > HEADER_SIZE);
+        // Dopo la compattazione il settore deve contenere le KEYS chiavi
+        // ricopiate e il record nuovo
+        assert!(KEYS < Self::SLOTS_PER_SECTOR);
+        Self {
+            name,
+            offset: (FLASH_SIZE - (top_sectors + sectors) * ERASE_SIZE) as u32,
+            sectors,
+        }
+    }
+
+    fn slot_count(&self) -> usize {
+        self.sectors * Self::SLOTS_PER_SECTOR
+    }
+
+    /// Ritorna i byte di uno slot tramite la mappatura XIP della flash
+    fn slot(&self, index: usize) -> &'static [u8; SLOT] {
+        let address = XIP_BASE + self.offset as usize + index * SLOT;
+        // SAFETY: la flash è mappata in memoria e la zona dell'archivio è riservata
+        unsafe { &*(address as *const [u8; SLOT]) }
+    }
+
+    /// Decodifica uno slot
+    ///
+    /// # Argomenti
+    /// * `index` - Indice dello slot
+    ///
+    /// # Ritorna
+    /// * Option<Record> - Record, se lo slot ne contiene uno integro
+    fn decode(&self, index: usize) -> Option<Record> {
+        let bytes: &'static [u8] = self.slot(index);
+        let len = read_u16(bytes, 10) as usize;
+        if read_u32(bytes, 0) != RECORD_MAGIC || len > Self::PAYLOAD_CAPACITY {
+            return None;
+        }
+        let payload = &bytes[HEADER_SIZE..HEADER_SIZE + len];
+        let crc = crc32(crc32(0, &bytes[4..12]), payload);
+        (crc == read_u32(bytes, 12)).then(|| Record {
+            seq: read_u32(bytes, 4),
+            key: read_u16(bytes, 8),
+            payload,
+        })
+    }
+
+    /// Record integri presenti in flash, in ordine di slot
+    pub fn records(&self) -> impl Iterator<Item = Record> + '_ {
+        (0..self.slot_count()).filter_map(|i| self.decode(i))
+    }
+
+    /// Ultimo valore salvato per una chiave
+    ///
+    /// # Argomenti
+    /// * `key` - Chiave cercata
+    ///
+    /// # Ritorna
+    /// * Option<Record> - Record con il numero di sequenza più alto
+    pub fn latest(&self, key: u16) -> Option<Record> {
+        self.records()
+            .filter(|r| r.key == key)
+            .max_by_key(|r| r.seq)
+    }
+
+    /// Indica se un record con questa chiave può essere aggiunto: negli
+    /// storici sempre, altrimenti se la chiave è già salvata o se le chiavi
+    /// diverse sono meno di KEYS
+    ///
+    /// # Argomenti
+    /// * `key` - Chiave del record
+    ///
+    /// # Ritorna
+    /// * bool - false se l'archivio è pieno
+    pub fn accepts(&self, key: u16) -> bool {
+        if KEYS == 0 {
+            return true;
+        }
+        let mut keys: heapless::Vec<u16, KEYS> = heapless::Vec::new();
+        for record in self.records() {
+            if record.key == key {
+                return true;
+            }
+            if !keys.contains(&record.key) && keys.push(record.key).is_err() {
+                return false;
+            }
+        }
+        // Chiave nuova: serve un posto libero
+        !keys.is_full()
+    }
+
+    /// Slot e numero di sequenza dell'ultimo record scritto
+    fn head(&self) -> Option<(usize, u32)> {
+        (0..self.slot_count())
+            .filter_map(|i| self.decode(i).map(|r| (i, r.seq)))
+            .max_by_key(|&(_, seq)| seq)
+    }
+
+    /// Scrive un record in uno slot vergine
+    fn write(
+        &self,
+        flash: &mut StoreFlash,
+        index: usize,
+        seq: u32,
+        key: u16,
+        payload: &[u8],
+    ) -> Result<(), StoreError> {
+        let mut slot = [0xFFu8; SLOT];
+        slot[0..4].copy_from_slice(&RECORD_MAGIC.to_le_bytes());
+        slot[4..8].copy_from_slice(&seq.to_le_bytes());
+        slot[8..10].copy_from_slice(&key.to_le_bytes());
+        slot[10..12].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+        let crc = crc32(crc32(0, &slot[4..12]), payload);
+        slot[12..16].copy_from_slice(&crc.to_le_bytes());
+        slot[HEADER_SIZE..HEADER_SIZE + payload.len()].copy_from_slice(payload);
+        flash
+            .blocking_write(self.offset + (index * SLOT) as u32, &slot)
+            .map_err(StoreError::Flash)
+    }
+
+    /// Cancella un settore, ricopiando prima in RAM gli ultimi valori delle
+    /// chiavi che vi si trovano (solo con KEYS > 0)
+    ///
+    /// # Argomenti
+    /// * `flash` - Driver della flash
+    /// * `sector` - Indice del settore nell'archivio
+    /// * `skip_key` - Chiave che sta per essere riscritta, da non ricopiare
+    ///
+    /// # Ritorna
+    /// * Result<heapless::Vec<..>, StoreError> - Record da riscrivere in testa al settore
+    #[allow(clippy::type_complexity)]
+    fn erase_sector(
+        &self,
+        flash: &mut StoreFlash,
+        sector: usize,
+        skip_key: u16,
+    ) -> Result<heapless::Vec<(u16, heapless::Vec<u8, SLOT>), KEYS>, StoreError> {
+        let slots = sector * Self::SLOTS_PER_SECTOR..(sector + 1) * Self::SLOTS_PER_SECTOR;
+        let mut live: heapless::Vec<(u16, heapless::Vec<u8, SLOT>), KEYS> = heapless::Vec::new();
+        if KEYS > 0 {
+            // Record più recente di ogni chiave presente nel settore...
+            let mut candidates: heapless::Vec<Record, KEYS> = heapless::Vec::new();
+            for record in slots.clone().filter_map(|i| self.decode(i)) {
+                if record.key == skip_key {
+                    continue;
+                }
+                match candidates.iter_mut().find(|c| c.key == record.key) {
+                    Some(c) if c.seq < record.seq => *c = record,
+                    Some(_) => {}
+                    // Non succede se le chiavi passano da accepts: meglio
+                    // fallire che cancellare il settore perdendo valori
+                    None => {
+                        if candidates.push(record).is_err() {
+                            log::error!("{}: troppe chiavi, settore non compattato", self.name);
+                            return Err(StoreError::Full);
+                        }
+                    }
+                }
+            }
+            // ...da conservare se non è stato sostituito in un altro settore
+            for record in candidates {
+                if self.latest(record.key).is_some_and(|r| r.seq == record.seq) {
+                    let payload = heapless::Vec::from_slice(record.payload).unwrap_or_default();
+                    let _ = live.push((record.key, payload));
+                }
+            }
+        }
+        let start = self.offset + (slots.start * SLOT) as u32;
+        flash
+            .blocking_erase(start, start + ERASE_SIZE as u32)
+            .map_err(StoreError::Flash)?;
+        Ok(live)
+    }
+
+    /// Aggiunge un record in coda
+    ///
+    /// # Argomenti
+    /// * `key` - Chiave del record (libera per gli storici)
+    /// * `payload` - Contenuto, al massimo PAYLOAD_CAPACITY byte
+    ///
+    /// # Ritorna
+    /// * Result<u32, StoreError> - Numero di sequenza assegnato, Full se la
+    ///   chiave è nuova e l'archivio ne contiene già KEYS
+    pub fn append(&self, key: u16, payload: &[u8]) -> Result<u32, StoreError> {
+        if payload.len() > Self::PAYLOAD_CAPACITY {
+            return Err(StoreError::TooLarge);
+        }
+        if !self.accepts(key) {
+            return Err(StoreError::Full);
+        }
+        with_flash(|flash| self.append_with(flash, key, payload))
+    }
+
+    fn append_with(
+        &self,
+        flash: &mut StoreFlash,
+        key: u16,
+        payload: &[u8],
+    ) -> Result<u32, StoreError> {
+        let (mut index, mut seq) = match self.head() {
+            Some((i, seq)) => ((i + 1) % self.slot_count(), seq.wrapping_add(1)),
+            None => (0, 0),
+        };
+
+        // Gli slot non vergini dopo la coda sono scritture interrotte: si saltano
+        while index % Self::SLOTS_PER_SECTOR != 0 && !self.slot(index).iter().all(|&b| b == 0xFF) {
+            index = (index + 1) % self.slot_count();
+        }
+
+        // Inizio di un settore: va cancellato, conservando le chiavi ancora valide
+        if index % Self::SLOTS_PER_SECTOR == 0 {
+            let live = self.erase_sector(flash, index / Self::SLOTS_PER_SECTOR, key)?;
+            for (live_key, live_payload) in live.iter() {
+                self.write(flash, index, seq, *live_key, live_payload)?;
+                index += 1;
+                seq = seq.wrapping_add(1);
+            }
+        }
+
+        self.write(flash, index, seq, key, payload)?;
+        Ok(seq)
+    }
+}
@@ -1,99 +1,149 @@
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::assets::AcceptGzip;
+use crate::i18n::Lang;
+use crate::timing;
 use crate::utility::generate_html;
-use crate::SharedSm2;
-use core::cell::RefCell;
+use picoserve::extract::Json as JsonBody;
+use picoserve::response::{Json, StatusCode};
+use picoserve::routing::{PathRouter, get, post};
+use sudoku::{Sudoku, SudokuError};
 
-/// Guard RAII per sm2: garantisce che sm2 venga sempre disattivata,
-/// anche in caso di panic durante la generazione dell'HTML.
-/// sm2 è usata solo per debug/monitoring - non critica.
-struct Sm2Guard {
-    shared_sm2: SharedSm2,
+/// Numero massimo di caratteri dello schema ricevuto dal form: 81 celle
+/// più spazi e separatori, se lo schema è incollato nel campo di testo
+const GRID_CAPACITY: usize = 200;
+
+/// Form data structure (per la HTTP POST) con lo schema di Sudoku 9x9.
+/// La griglia della pagina invia le 81 celle riga per riga in un solo campo,
+/// es. `53..7....6..195...` e così via, con '.' per le celle vuote (vedi
+/// `Sudoku::parse_grid81`).
+#[derive(serde::Deserialize)]
+pub struct FormValue {
+    pub grid: heapless::String<GRID_CAPACITY>,
 }
 
-impl Sm2Guard {
-    /// Crea un nuovo guard e attiva sm2
-    /// Ritorna None se sm2 non è disponibile (non critico - solo debug)
-    fn new(shared_sm2: SharedSm2) -> Option<Self> {
-        match shared_sm2.0.try_lock() {
-            Ok(mut sm) => {
-                sm.set_enable(true);
-                log::info!("sm2 attivata per debug");
-                Some(Self { shared_sm2 })
-            }
-            Err(_) => {
-                log::debug!("sm2 non disponibile - continua senza");
-                None
-            }
-        }
-    }
+/// Schema inviato a `POST /api/solve`, in uno dei due formati:
+/// * `{"cells": [5, 3, 0, ...]}` - 81 numeri riga per riga, 0 per le celle vuote
+/// * `{"rows": ["53..7....", ...]}` - 9 righe nel formato compatto
+#[derive(serde::Deserialize)]
+pub struct SolveRequest {
+    cells: Option<heapless::Vec<u8, 81>>,
+    rows: Option<heapless::Vec<heapless::String<9>, 9>>,
 }
 
-impl Drop for Sm2Guard {
-    /// Disattiva sm2 quando il guard viene distrutto (anche in caso di panic)
-    /// Se fallisce, logga warning ma NON fa panic (sm2 è solo per debug, non critica)
-    fn drop(&mut self) {
-        if let Ok(mut sm) = self.shared_sm2.0.try_lock() {
-            sm.set_enable(false);
-            log::info!("sm2 disattivata");
-        } else {
-            log::warn!("impossibile disattivare sm2 - non critico (solo debug)");
-        }
-    }
+/// Soluzione restituita da `POST /api/solve`
+#[derive(serde::Serialize)]
+pub struct SolveResponse {
+    grid: [[u8; 9]; 9],
+    elapsed_us: u64,
 }
 
-/// Form data structure (per la HTTP POST) per inserire le 9 righe
-/// dello schema di Sudoku 9x9.
-/// L'inserimento avviene ad esempio con: 5,3,_,_,7,_,_,_,_ e così via
-/// per le 9 righe.
-#[derive(serde::Deserialize)]
-pub struct FormValue {
-    pub row_1: heapless::String<20>,
-    pub row_2: heapless::String<20>,
-    pub row_3: heapless::String<20>,
-    pub row_4: heapless::String<20>,
-    pub row_5: heapless::String<20>,
-    pub row_6: heapless::String<20>,
-    pub row_7: heapless::String<20>,
-    pub row_8: heapless::String<20>,
-    pub row_9: heapless::String<20>,
-    #[serde(skip)]
-    pub message: RefCell<heapless::String<1024>>,
+/// Errore restituito da `POST /api/solve`
+#[derive(serde::Serialize)]
+pub struct SolveError {
+    error: heapless::String<32>,
 }
 
-impl picoserve::response::Content for FormValue {
-    /// Specifica il tipo di contenuto della risposta HTTP (HTML)
-    fn content_type(&self) -> &'static str {
-        "text/html"
+impl SolveError {
+    fn response(status: StatusCode, err: SudokuError) -> (StatusCode, Json<Self>) {
+        let error = crate::errors::fit(format_args!("{err:?}"));
+        (status, Json(SolveError { error }))
     }
+}
 
-    /// Specifica la lunghezza del contenuto della risposta HTTP
-    /// (utile per l'header Content-Length).
-    /// Genera l'HTML attivando sm2 per la durata della generazione (se disponibile).
+impl SolveRequest {
+    /// Carica lo schema inviato
     ///
     /// # Ritorna
-    /// * usize - Lunghezza del contenuto
-    fn content_length(&self) -> usize {
-        // Crea guard RAII: sm2 attivata qui, disattivata automaticamente a fine scope
-        let _guard = crate::get_shared_sm2().and_then(Sm2Guard::new);
-
-        // Genera HTML (con sm2 attiva se disponibile)
-        let html = generate_html(self);
+    /// * Result<Sudoku, SudokuError> - Schema da risolvere, oppure errore se
+    ///   nessuno dei due formati è presente o completo
+    fn sudoku(&self) -> Result<Sudoku, SudokuError> {
+        let mut sudoku = Sudoku::default();
+        match (&self.cells, &self.rows) {
+            (Some(cells), None) if cells.len() == 81 => {
+                for (i, &cell) in cells.iter().enumerate() {
+                    sudoku.grid[i / 9][i % 9] = cell;
+                }
+            }
+            (None, Some(rows)) if rows.len() == 9 => {
+                let mut schema: heapless::String<81> = heapless::String::new();
+                for row in rows.iter() {
+                    if row.len() != 9 {
+                        return Err(SudokuError::InvalidFormat);
+                    }
+                    let _ = schema.push_str(row);
+                }
+                sudoku.parse_compact(&schema)?;
+            }
+            (None, None) | (Some(_), Some(_)) => return Err(SudokuError::InvalidFormat),
+            _ => return Err(SudokuError::NotEnoughArguments),
+        }
+        Ok(sudoku)
+    }
+}
 
-        // _guard droppato qui -> sm2 disattivata automaticamente
-        html.as_bytes().content_length()
+/// Risolve lo schema inviato come JSON
+///
+/// # Argomenti
+/// * `request` - Schema inviato
+///
+/// # Ritorna
+/// * Result<Json<SolveResponse>, (StatusCode, Json<SolveError>)> - Soluzione, oppure
+///   400 se lo schema non è valido e 422 se non ha soluzione
+async fn solve_json(
+    request: &SolveRequest,
+) -> Result<Json<SolveResponse>, (StatusCode, Json<SolveError>)> {
+    let mut sudoku = request
+        .sudoku()
+        .map_err(|e| SolveError::response(StatusCode::BAD_REQUEST, e))?;
+    // Risoluzione cooperativa (vedi solve.rs): gli altri worker restano serviti
+    let (result, elapsed) = timing::measure(crate::solve::solve(&mut sudoku)).await;
+    match result {
+        Ok(()) => {
+            crate::bus::publish(crate::bus::Event::PuzzleSolved {
+                source: crate::bus::SolveSource::Web,
+            });
+            Ok(Json(SolveResponse {
+                grid: sudoku.grid,
+                elapsed_us: elapsed.as_micros(),
+            }))
+        }
+        Err(e @ (SudokuError::NoSolution | SudokuError::Timeout)) => {
+            Err(SolveError::response(StatusCode::UNPROCESSABLE_ENTITY, e))
+        }
+        Err(e) => Err(SolveError::response(StatusCode::BAD_REQUEST, e)),
     }
+}
 
-    /// Ridefinisce il metodo per scrivere il contenuto della risposta HTTP in modo dinamico
-    /// in base ai dati ricevuti nel form.
-    /// Scrive il contenuto già generato in content_length (evita rigenerazione).
-    ///
-    /// # Argomenti
-    /// * `writer` - Writer per scrivere il contenuto della risposta HTTP
-    ///
-    /// # Ritorna
-    /// * Result<(), W::Error> - Risultato dell'operazione di scrittura
-    async fn write_content<W: picoserve::io::Write>(self, mut writer: W) -> Result<(), W::Error> {
-        // Usa l'HTML già generato in content_length
-        let content = self.message.borrow().clone();
-        writer.write_all(content.as_str().as_bytes()).await
+/// Rotte del Sudoku: `/upload` mostra il form (GET) e risolve lo schema inviato
+/// (POST), `/api/solve` risolve uno schema inviato come JSON
+pub struct SudokuRoutes;
+
+impl Routes for SudokuRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                "/api/solve",
+                post(|JsonBody(request): JsonBody<SolveRequest, 0>| async move {
+                    solve_json(&request).await
+                }),
+            )
+            .route(
+                "/upload",
+                get(|lang: Lang, accept: AcceptGzip| async move {
+                    lang.strings().form_page.html(accept)
+                })
+                .post(
+                    |lang: Lang,
+                     picoserve::extract::Form(form_value): picoserve::extract::Form<FormValue>| {
+                        // Risolve lo schema misurando il tempo impiegato (mostrato
+                        // nella pagina); la pagina viene generata un pezzo alla
+                        // volta durante l'invio
+                        async move { generate_html(&form_value, lang).await }
+                    },
+                ),
+            )
     }
 }
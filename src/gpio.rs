@@ -0,0 +1,254 @@
+//! gpio.rs - Lettura e scrittura via HTTP dei GPIO liberi del connettore.
+//!
+//! I pin di [`MANAGED_PINS`] non sono usati da UART, eventi GPIO e CYW43:
+//! il main li cede tutti a [`init`], che configura come uscite quelli
+//! elencati in `GPIO_OUTPUTS` e come ingressi con pull-up quelli in
+//! `GPIO_INPUTS` (configuration.rs); gli altri restano scollegati.
+//! * `GET /api/gpio/{pin}` - direzione e livello del pin;
+//! * `POST /api/gpio/{pin}` - form con `level` = `0`, `1` o `toggle`,
+//!   solo sulle uscite.
+//!
+//! Le rotte passano da [`GpioManager`], nello stato dell'applicazione. Le
+//! scritture sono rotte di amministrazione (vedi acl.rs).
+//!
+//! Esempio:
+//! ```text
+//! curl -d 'level=toggle' http://<ip>/api/gpio/6
+//! ```
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::i18n::Lang;
+use crate::utility::get_gpio_pins;
+use core::cell::RefCell;
+use critical_section::Mutex as CsMutex;
+use embassy_rp::gpio::{Flex, Level, Pull};
+use picoserve::extract::{Form, State};
+use picoserve::response::{Json, StatusCode};
+use picoserve::routing::{PathRouter, get, parse_path_segment};
+
+/// GPIO del connettore gestibili via HTTP
+pub const MANAGED_PINS: [u8; 8] = [6, 7, 8, 9, 10, 11, 12, 13];
+
+/// Direzione di un GPIO gestito
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Output,
+    Input,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Output => "output",
+            Direction::Input => "input",
+        }
+    }
+}
+
+/// Errori delle operazioni sui GPIO gestiti
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GpioError {
+    /// Pin fuori da GPIO_OUTPUTS e GPIO_INPUTS
+    Unmanaged,
+    /// Scrittura su un ingresso
+    ReadOnly,
+}
+
+/// Pin configurato da init
+struct ManagedPin {
+    pin: u8,
+    direction: Direction,
+    flex: Flex<'static>,
+}
+
+/// Pin configurati, nell'ordine di MANAGED_PINS
+static PINS: CsMutex<RefCell<heapless::Vec<ManagedPin, { MANAGED_PINS.len() }>>> =
+    CsMutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Stato di un pin restituito da /api/gpio/{pin}
+#[derive(serde::Serialize)]
+pub struct PinState {
+    pub pin: u8,
+    pub direction: &'static str,
+    /// Livello letto sull'ingresso o impostato sull'uscita
+    pub level: bool,
+}
+
+/// Comando inviato a `POST /api/gpio/{pin}`
+#[derive(serde::Deserialize)]
+pub struct PinForm {
+    pub level: heapless::String<8>,
+}
+
+/// Configura i pin di MANAGED_PINS secondo GPIO_OUTPUTS e GPIO_INPUTS: da
+/// chiamare una sola volta all'avvio. Le uscite partono a livello basso.
+///
+/// # Argomenti
+/// * `pins` - Pin di MANAGED_PINS, nello stesso ordine
+pub fn init(pins: [Flex<'static>; MANAGED_PINS.len()]) {
+    let outputs = get_gpio_pins("GPIO_OUTPUTS");
+    let inputs = get_gpio_pins("GPIO_INPUTS");
+    critical_section::with(|cs| {
+        let mut managed = PINS.borrow(cs).borrow_mut();
+        for (&pin, mut flex) in MANAGED_PINS.iter().zip(pins) {
+            let direction = if outputs.contains(&pin) {
+                flex.set_low();
+                flex.set_as_output();
+                Direction::Output
+            } else if inputs.contains(&pin) {
+                flex.set_pull(Pull::Up);
+                flex.set_as_input();
+                Direction::Input
+            } else {
+                continue;
+            };
+            // MANAGED_PINS e PINS hanno la stessa capacità
+            let _ = managed.push(ManagedPin {
+                pin,
+                direction,
+                flex,
+            });
+        }
+    });
+    for pin in outputs.iter().chain(inputs.iter()) {
+        if !MANAGED_PINS.contains(pin) {
+            log::warn!("GPIO: GP{} non è tra i pin gestibili, ignorato", pin);
+        }
+    }
+}
+
+/// Ritorna lo stato di un pin configurato
+fn state(managed: &ManagedPin) -> PinState {
+    let level = match managed.direction {
+        Direction::Output => managed.flex.is_set_high(),
+        Direction::Input => managed.flex.is_high(),
+    };
+    PinState {
+        pin: managed.pin,
+        direction: managed.direction.as_str(),
+        level,
+    }
+}
+
+/// GPIO del connettore configurati all'avvio, condivisi dai worker del web
+/// server tramite lo stato dell'applicazione
+#[derive(Clone, Copy)]
+pub struct GpioManager;
+
+impl GpioManager {
+    /// Legge un pin
+    ///
+    /// # Argomenti
+    /// * `pin` - Numero del GPIO
+    ///
+    /// # Ritorna
+    /// * Result<PinState, GpioError> - Stato del pin, Unmanaged se non è configurato
+    pub fn read(&self, pin: u8) -> Result<PinState, GpioError> {
+        critical_section::with(|cs| {
+            let managed = PINS.borrow(cs).borrow();
+            managed
+                .iter()
+                .find(|managed| managed.pin == pin)
+                .map(state)
+                .ok_or(GpioError::Unmanaged)
+        })
+    }
+
+    /// Imposta un'uscita
+    ///
+    /// # Argomenti
+    /// * `pin` - Numero del GPIO
+    /// * `level` - Nuovo livello, None per invertire quello attuale
+    ///
+    /// # Ritorna
+    /// * Result<PinState, GpioError> - Nuovo stato, Unmanaged se il pin non è
+    ///   configurato, ReadOnly se è un ingresso
+    pub fn write(&self, pin: u8, level: Option<bool>) -> Result<PinState, GpioError> {
+        critical_section::with(|cs| {
+            let mut managed = PINS.borrow(cs).borrow_mut();
+            let managed = managed
+                .iter_mut()
+                .find(|managed| managed.pin == pin)
+                .ok_or(GpioError::Unmanaged)?;
+            if managed.direction != Direction::Output {
+                return Err(GpioError::ReadOnly);
+            }
+            let high = level.unwrap_or(!managed.flex.is_set_high());
+            managed.flex.set_level(Level::from(high));
+            Ok(state(managed))
+        })
+    }
+}
+
+/// Converte un errore in una risposta per l'utente
+///
+/// # Argomenti
+/// * `error` - Errore dell'operazione
+/// * `lang` - Lingua della risposta
+///
+/// # Ritorna
+/// * (StatusCode, &'static str) - Stato HTTP e messaggio
+fn error_response(error: GpioError, lang: Lang) -> (StatusCode, &'static str) {
+    match error {
+        GpioError::Unmanaged => (StatusCode::NOT_FOUND, lang.strings().gpio_unmanaged),
+        GpioError::ReadOnly => (StatusCode::CONFLICT, lang.strings().gpio_read_only),
+    }
+}
+
+/// Applica un comando a un'uscita
+///
+/// # Argomenti
+/// * `gpio` - GPIO dallo stato dell'applicazione
+/// * `pin` - Numero del GPIO
+/// * `form` - Comando ricevuto
+/// * `lang` - Lingua della richiesta
+///
+/// # Ritorna
+/// * Result<PinState, (StatusCode, &str)> - Nuovo stato, oppure errore
+fn apply(
+    gpio: GpioManager,
+    pin: u8,
+    form: &PinForm,
+    lang: Lang,
+) -> Result<PinState, (StatusCode, &'static str)> {
+    let level = match form.level.as_str() {
+        "1" => Some(true),
+        "0" => Some(false),
+        "toggle" => None,
+        _ => return Err((StatusCode::BAD_REQUEST, lang.strings().gpio_invalid_level)),
+    };
+    let state = gpio
+        .write(pin, level)
+        .map_err(|e| error_response(e, lang))?;
+    log::info!("GPIO: GP{} = {} da HTTP", pin, state.level as u8);
+    Ok(state)
+}
+
+/// Rotte dei GPIO del connettore: `/api/gpio/{pin}`
+pub struct GpioPinRoutes;
+
+impl Routes for GpioPinRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router.route(
+            ("/api/gpio", parse_path_segment::<u8>()),
+            get(
+                |pin: u8, State(gpio): State<GpioManager>, lang: Lang| async move {
+                    gpio.read(pin)
+                        .map(Json)
+                        .map_err(|e| error_response(e, lang))
+                },
+            )
+            .post(
+                |pin: u8,
+                 State(gpio): State<GpioManager>,
+                 lang: Lang,
+                 Form(form): Form<PinForm>| async move {
+                    apply(gpio, pin, &form, lang).map(Json)
+                },
+            ),
+        )
+    }
+}
@@ -0,0 +1,95 @@
+//! heap.rs - Allocatore globale opzionale (`embedded-alloc`).
+//!
+//! Con la feature `heap` le strutture grandi e usate di rado (nodi DLX,
+//! buffer di cattura, risposte di grandi dimensioni) possono essere
+//! allocate con `alloc::boxed::Box`/`alloc::vec::Vec` invece di occupare
+//! memoria statica per tutta la vita del programma.
+//! La dimensione dello heap è `HEAP_SIZE` in `configuration.rs`; con la
+//! feature `psram` e una PSRAM rilevata lo heap occupa invece
+//! `PSRAM_HEAP_SIZE` byte della PSRAM (vedi psram.rs). Le statistiche sono
+//! riportate da `/api/memory`.
+
+use crate::configuration::HEAP_SIZE;
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::MaybeUninit;
+use embedded_alloc::LlffHeap as Heap;
+
+#[global_allocator]
+static HEAP: Heap = Heap::empty();
+
+/// Inizializza lo heap: da chiamare una sola volta all'inizio del main,
+/// prima di qualsiasi allocazione e, con la feature `psram`, dopo
+/// `psram::init`
+pub fn init() {
+    #[cfg(feature = "psram")]
+    if let Some(region) = crate::psram::alloc(crate::configuration::PSRAM_HEAP_SIZE) {
+        // SAFETY: chiamata una sola volta, prima di qualsiasi allocazione;
+        // la regione è riservata allo heap per tutta la vita del programma
+        unsafe { HEAP.init(region.as_mut_ptr() as usize, region.len()) }
+        return;
+    }
+    static mut HEAP_MEM: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
+    // SAFETY: chiamata una sola volta, prima di qualsiasi allocazione
+    unsafe { HEAP.init(core::ptr::addr_of_mut!(HEAP_MEM) as usize, HEAP_SIZE) }
+}
+
+/// Statistiche dello heap
+#[derive(serde::Serialize)]
+pub struct HeapStats {
+    size: usize,
+    used: usize,
+    free: usize,
+    /// Blocco più grande allocabile in questo momento
+    largest_free_block: usize,
+    /// Frammentazione in percentuale: 0 se tutta la memoria libera è contigua
+    fragmentation_percent: u8,
+}
+
+/// Cerca (per bisezione) il blocco più grande allocabile
+///
+/// # Argomenti
+/// * `free` - Memoria libera totale, limite superiore della ricerca
+///
+/// # Ritorna
+/// * usize - Dimensione del blocco più grande allocabile
+fn largest_free_block(free: usize) -> usize {
+    let (mut low, mut high) = (0, free);
+    while low < high {
+        let size = (low + high).div_ceil(2);
+        let Ok(layout) = Layout::from_size_align(size, 4) else {
+            break;
+        };
+        // SAFETY: il blocco viene liberato subito con lo stesso layout
+        let ptr = unsafe { HEAP.alloc(layout) };
+        if ptr.is_null() {
+            high = size - 1;
+        } else {
+            unsafe { HEAP.dealloc(ptr, layout) };
+            low = size;
+        }
+    }
+    low
+}
+
+/// Ritorna le statistiche dello heap
+///
+/// # Ritorna
+/// * HeapStats - Statistiche correnti
+pub fn stats() -> HeapStats {
+    // Le misure e la ricerca del blocco più grande devono essere coerenti
+    critical_section::with(|_| {
+        let free = HEAP.free();
+        let largest_free_block = largest_free_block(free);
+        let fragmentation_percent = match free {
+            0 => 0,
+            _ => (100 - largest_free_block * 100 / free) as u8,
+        };
+        HeapStats {
+            size: HEAP.used() + free,
+            used: HEAP.used(),
+            free,
+            largest_free_block,
+            fragmentation_percent,
+        }
+    })
+}
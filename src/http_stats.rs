@@ -0,0 +1,317 @@
+//! http_stats.rs - Statistiche per connessione del web server.
+//!
+//! Ogni worker (vedi `web_worker` in main.rs) accetta le connessioni TCP e
+//! le passa a picoserve attraverso un [`CountingSocket`], che conta i byte
+//! ricevuti e inviati. Alla chiusura della connessione i totali (richieste
+//! servite sulla connessione keep-alive, byte, durata, IP del client)
+//! vengono aggregati in istogrammi a bucket fissi, utili a dimensionare il
+//! pool di worker (vedi `ServerResources` in configuration.rs): se le
+//! connessioni restano aperte a lungo e il picco di connessioni attive
+//! raggiunge il numero di worker, i nuovi client ricevono un 503 dalla coda
+//! di riserva (vedi overload.rs, contati in `overloaded`). I dati sono
+//! disponibili su `/api/http`.
+
+use core::cell::{Cell, RefCell};
+use critical_section::Mutex as CsMutex;
+use embassy_net::IpAddress;
+use embassy_net::tcp::{TcpReader, TcpSocket, TcpWriter};
+use embassy_time::Instant;
+use picoserve::io::{ErrorType, Read, Socket, Write};
+use portable_atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Limiti superiori dei bucket delle richieste per connessione
+const REQUEST_BOUNDS: [u32; 4] = [1, 2, 4, 8];
+
+/// Limiti superiori (byte) dei bucket del traffico per connessione
+const BYTE_BOUNDS: [u32; 4] = [1024, 4096, 16 * 1024, 64 * 1024];
+
+/// Limiti superiori (ms) dei bucket della durata delle connessioni
+const DURATION_BOUNDS_MS: [u32; 4] = [100, 1000, 10_000, 60_000];
+
+/// Bucket di ogni istogramma: uno per limite più quello oltre l'ultimo
+const BUCKETS: usize = 5;
+
+/// Numero massimo di client distinti conteggiati
+const CLIENT_SLOTS: usize = 8;
+
+/// Istogramma a bucket fissi
+#[derive(Clone, Copy, serde::Serialize)]
+pub struct Histogram {
+    /// Limiti superiori (inclusi) dei bucket; l'ultimo bucket non ha limite
+    bounds: [u32; BUCKETS - 1],
+    counts: [u32; BUCKETS],
+}
+
+impl Histogram {
+    const fn new(bounds: [u32; BUCKETS - 1]) -> Self {
+        Self {
+            bounds,
+            counts: [0; BUCKETS],
+        }
+    }
+
+    fn record(&mut self, value: u32) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|&bound| value <= bound)
+            .unwrap_or(BUCKETS - 1);
+        self.counts[bucket] += 1;
+    }
+}
+
+/// Connessioni di un client
+#[derive(Clone, Copy, serde::Serialize)]
+pub struct ClientCount {
+    #[serde(serialize_with = "serialize_ip")]
+    ip: IpAddress,
+    connections: u32,
+    requests: u32,
+}
+
+fn serialize_ip<S: serde::Serializer>(ip: &IpAddress, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(ip)
+}
+
+/// Istogrammi delle connessioni chiuse
+struct Histograms {
+    requests: Histogram,
+    bytes: Histogram,
+    duration_ms: Histogram,
+    clients: heapless::Vec<ClientCount, CLIENT_SLOTS>,
+}
+
+static HISTOGRAMS: CsMutex<RefCell<Histograms>> = CsMutex::new(RefCell::new(Histograms {
+    requests: Histogram::new(REQUEST_BOUNDS),
+    bytes: Histogram::new(BYTE_BOUNDS),
+    duration_ms: Histogram::new(DURATION_BOUNDS_MS),
+    clients: heapless::Vec::new(),
+}));
+
+static CONNECTIONS: AtomicU32 = AtomicU32::new(0);
+static CONNECTION_ERRORS: AtomicU32 = AtomicU32::new(0);
+static ACTIVE: AtomicU32 = AtomicU32::new(0);
+static PEAK_ACTIVE: AtomicU32 = AtomicU32::new(0);
+static OVERLOADED: AtomicU32 = AtomicU32::new(0);
+
+/// true quando almeno un worker è in ascolto sulla porta 80
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+/// Statistiche restituite da /api/http
+#[derive(serde::Serialize)]
+pub struct HttpStats {
+    /// Connessioni chiuse dall'avvio
+    pub connections: u32,
+    /// Connessioni terminate con un errore
+    pub errors: u32,
+    /// Connessioni aperte in questo momento
+    pub active: u32,
+    /// Massimo di connessioni aperte contemporaneamente
+    pub peak_active: u32,
+    /// Connessioni rifiutate con un 503 perché tutti i worker erano occupati
+    pub overloaded: u32,
+    /// Web server in ascolto
+    pub listening: bool,
+    pub requests: Histogram,
+    pub bytes: Histogram,
+    pub duration_ms: Histogram,
+    /// Client con più connessioni (i primi CLIENT_SLOTS visti)
+    pub clients: heapless::Vec<ClientCount, CLIENT_SLOTS>,
+}
+
+/// Connessione in corso su un worker
+pub struct Connection {
+    started: Instant,
+    client: Option<IpAddress>,
+    rx_bytes: Cell<u32>,
+    tx_bytes: Cell<u32>,
+}
+
+impl Connection {
+    /// Registra l'apertura di una connessione
+    ///
+    /// # Argomenti
+    /// * `socket` - Socket della connessione accettata
+    ///
+    /// # Ritorna
+    /// * Connection - Connessione da passare a [`CountingSocket::new`] e poi a [`Connection::close`]
+    pub fn open(socket: &TcpSocket<'_>) -> Self {
+        let active = ACTIVE.fetch_add(1, Ordering::Relaxed) + 1;
+        PEAK_ACTIVE.fetch_max(active, Ordering::Relaxed);
+        Self {
+            started: Instant::now(),
+            client: socket.remote_endpoint().map(|endpoint| endpoint.addr),
+            rx_bytes: Cell::new(0),
+            tx_bytes: Cell::new(0),
+        }
+    }
+
+    /// Registra la chiusura della connessione e la aggrega negli istogrammi
+    ///
+    /// # Argomenti
+    /// * `requests` - Richieste servite, None se la connessione è terminata con un errore
+    pub fn close(self, requests: Option<u64>) {
+        ACTIVE.fetch_sub(1, Ordering::Relaxed);
+        CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+        if requests.is_none() {
+            CONNECTION_ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+        let requests = requests.unwrap_or(0).min(u32::MAX as u64) as u32;
+        let bytes = self.rx_bytes.get().saturating_add(self.tx_bytes.get());
+        let duration = self.started.elapsed();
+        critical_section::with(|cs| {
+            let mut histograms = HISTOGRAMS.borrow(cs).borrow_mut();
+            histograms.requests.record(requests);
+            histograms.bytes.record(bytes);
+            histograms
+                .duration_ms
+                .record(duration.as_millis().min(u32::MAX as u64) as u32);
+            if let Some(ip) = self.client {
+                match histograms.clients.iter_mut().find(|c| c.ip == ip) {
+                    Some(client) => {
+                        client.connections += 1;
+                        client.requests += requests;
+                    }
+                    // Oltre CLIENT_SLOTS client distinti conta solo negli istogrammi
+                    None => {
+                        let _ = histograms.clients.push(ClientCount {
+                            ip,
+                            connections: 1,
+                            requests,
+                        });
+                    }
+                }
+            }
+        });
+        log::debug!(
+            "HTTP: connessione da {:?} chiusa dopo {} ms, {} richieste, {} byte",
+            self.client,
+            duration.as_millis(),
+            requests,
+            bytes
+        );
+    }
+}
+
+/// Conta una connessione rifiutata perché tutti i worker erano occupati
+/// (vedi overload.rs)
+pub fn record_overload() {
+    OVERLOADED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Registra che un worker è in ascolto: il web server è avviato
+pub fn mark_listening() {
+    LISTENING.store(true, Ordering::Relaxed);
+}
+
+/// Ritorna true se il web server è avviato (vedi ota.rs)
+pub fn is_listening() -> bool {
+    LISTENING.load(Ordering::Relaxed)
+}
+
+/// Ritorna le statistiche delle connessioni
+///
+/// # Ritorna
+/// * HttpStats - Contatori e istogrammi
+pub fn report() -> HttpStats {
+    let (requests, bytes, duration_ms, clients) = critical_section::with(|cs| {
+        let histograms = HISTOGRAMS.borrow(cs).borrow();
+        (
+            histograms.requests,
+            histograms.bytes,
+            histograms.duration_ms,
+            histograms.clients.clone(),
+        )
+    });
+    HttpStats {
+        connections: CONNECTIONS.load(Ordering::Relaxed),
+        errors: CONNECTION_ERRORS.load(Ordering::Relaxed),
+        active: ACTIVE.load(Ordering::Relaxed),
+        peak_active: PEAK_ACTIVE.load(Ordering::Relaxed),
+        overloaded: OVERLOADED.load(Ordering::Relaxed),
+        listening: is_listening(),
+        requests,
+        bytes,
+        duration_ms,
+        clients,
+    }
+}
+
+/// Socket TCP che conta i byte ricevuti e inviati sulla connessione
+pub struct CountingSocket<'s, 'c> {
+    socket: TcpSocket<'s>,
+    connection: &'c Connection,
+}
+
+impl<'s, 'c> CountingSocket<'s, 'c> {
+    /// # Argomenti
+    /// * `socket` - Socket della connessione accettata
+    /// * `connection` - Connessione su cui accumulare i byte
+    pub fn new(socket: TcpSocket<'s>, connection: &'c Connection) -> Self {
+        Self { socket, connection }
+    }
+}
+
+/// Metà di un socket che accumula i byte trasferiti in un contatore
+pub struct Counting<'c, T> {
+    inner: T,
+    bytes: &'c Cell<u32>,
+}
+
+impl<T: ErrorType> ErrorType for Counting<'_, T> {
+    type Error = T::Error;
+}
+
+impl<T: Read> Read for Counting<'_, T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let n = self.inner.read(buf).await?;
+        self.bytes.set(self.bytes.get().saturating_add(n as u32));
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Counting<'_, T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let n = self.inner.write(buf).await?;
+        self.bytes.set(self.bytes.get().saturating_add(n as u32));
+        Ok(n)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().await
+    }
+}
+
+impl<'s, 'c> Socket for CountingSocket<'s, 'c> {
+    type Error = embassy_net::tcp::Error;
+    type ReadHalf<'a>
+        = Counting<'c, TcpReader<'a>>
+    where
+        Self: 'a;
+    type WriteHalf<'a>
+        = Counting<'c, TcpWriter<'a>>
+    where
+        Self: 'a;
+
+    fn split(&mut self) -> (Self::ReadHalf<'_>, Self::WriteHalf<'_>) {
+        let (reader, writer) = self.socket.split();
+        (
+            Counting {
+                inner: reader,
+                bytes: &self.connection.rx_bytes,
+            },
+            Counting {
+                inner: writer,
+                bytes: &self.connection.tx_bytes,
+            },
+        )
+    }
+
+    async fn shutdown<T: picoserve::Timer>(
+        self,
+        timeouts: &picoserve::Timeouts<T::Duration>,
+        timer: &mut T,
+    ) -> Result<(), picoserve::Error<Self::Error>> {
+        Socket::shutdown(self.socket, timeouts, timer).await
+    }
+}
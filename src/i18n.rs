@@ -0,0 +1,364 @@
+//! i18n.rs - Lingua dell'interfaccia web e dei messaggi per l'utente.
+//!
+//! Le pagine e i messaggi mostrati all'utente (pagine statiche, risultato
+//! del Sudoku, errori HTTP, aiuto della console) sono raccolti in una
+//! tabella di stringhe per lingua ([`Strings`]). La lingua di una richiesta
+//! HTTP è scelta dal valore `LANGUAGE` della configurazione (`it` o `en`);
+//! con `auto` viene scelta la prima lingua supportata nell'header
+//! `Accept-Language`, con l'italiano come ripiego. La console, senza
+//! header, usa la lingua configurata (italiano con `auto`).
+//!
+//! I log restano in italiano: sono destinati a chi sviluppa il firmware.
+
+use crate::assets::{self, Asset};
+use crate::utility::get_config;
+use core::convert::Infallible;
+use picoserve::extract::FromRequestParts;
+use picoserve::request::RequestParts;
+
+/// Lingue supportate
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lang {
+    It,
+    En,
+}
+
+/// Testi mostrati all'utente in una lingua
+pub struct Strings {
+    /// Pagina principale (dashboard)
+    pub index_page: Asset,
+    /// Form di inserimento dello schema
+    pub form_page: Asset,
+    /// Pagina dell'ora e delle attività programmate
+    pub time_page: Asset,
+    /// Form della rete WiFi sull'access point di configurazione
+    pub provision_page: Asset,
+    /// Pagina delle impostazioni di rete
+    pub settings_page: Asset,
+    /// Form di login delle sessioni (vedi session.rs)
+    pub login_page: Asset,
+    /// Pagina della temperatura del chip
+    pub temperature_page: Asset,
+    /// Pagina del sensore ambientale
+    pub environment_page: Asset,
+    /// Pagina del selettore di colore della striscia di LED
+    pub leds_page: Asset,
+    /// Pagina del terminale sulla UART1
+    pub uart_page: Asset,
+    /// Titolo della pagina con lo schema risolto
+    pub solved_title: &'static str,
+    /// Schema non risolvibile
+    pub solve_error: &'static str,
+    /// Schema non valido
+    pub parse_error: &'static str,
+    /// Risoluzione interrotta dopo troppe iterazioni (vedi solve.rs)
+    pub solve_timeout: &'static str,
+    /// Etichetta del tempo di risoluzione nella pagina del risultato
+    pub solve_time_label: &'static str,
+    /// Nota della pagina del risultato quando non si sa se la soluzione è
+    /// l'unica (vedi utility::generate_html)
+    pub uniqueness_unknown: &'static str,
+    /// Etichetta dell'ID della richiesta nelle pagine di errore
+    pub request_id_label: &'static str,
+    /// Tutti i worker del web server occupati (vedi overload.rs)
+    pub server_busy: &'static str,
+    /// Risposta 429 ai client oltre i limiti (vedi rate_limit.rs)
+    pub too_many_requests: &'static str,
+    /// Pagina 413 per un corpo oltre il buffer del worker (vedi body_limit.rs)
+    pub payload_too_large: &'static str,
+    /// Rotta di amministrazione chiesta da una rete fuori da ACL_ADMIN_ALLOW
+    pub acl_forbidden: &'static str,
+    /// Credenziali HTTP assenti o errate (vedi auth.rs)
+    pub auth_required: &'static str,
+    /// Credenziali errate nel form di login
+    pub login_failed: &'static str,
+    /// File inesistente sotto /static/
+    pub static_not_found: &'static str,
+    /// Rotta non disponibile in modalità provvisoria
+    pub safe_mode_unavailable: &'static str,
+    /// Azione sconosciuta su /api/led
+    pub led_invalid_action: &'static str,
+    /// GPIO inesistente su /api/cyw43/gpio
+    pub gpio_invalid: &'static str,
+    /// GPIO fuori da GPIO_OUTPUTS e GPIO_INPUTS su /api/gpio/{pin}
+    pub gpio_unmanaged: &'static str,
+    /// Scrittura su un GPIO configurato come ingresso
+    pub gpio_read_only: &'static str,
+    /// Livello non valido nel form di /api/gpio/{pin}
+    pub gpio_invalid_level: &'static str,
+    /// LED inesistente su /api/leds
+    pub leds_invalid_index: &'static str,
+    /// Dati del terminale web inviati sulla UART1
+    pub uart_sent: &'static str,
+    /// UART1 non disponibile o trasmissione fallita (vedi uart_bridge.rs)
+    pub uart_unavailable: &'static str,
+    /// Canale inesistente su /api/pwm/{channel}
+    pub pwm_unknown_channel: &'static str,
+    /// Frequenza o duty cycle fuori dai limiti (vedi pwm.rs)
+    pub pwm_invalid: &'static str,
+    /// Rete WiFi salvata dal form di /provision, prima del riavvio
+    pub provision_saved: &'static str,
+    /// Form di /provision inviato fuori dalla modalità di configurazione
+    pub provision_inactive: &'static str,
+    /// Rete WiFi non valida o non salvata
+    pub provision_failed: &'static str,
+    /// Titolo della pagina delle reti visibili (vedi wifi_scan.rs)
+    pub wifi_scan_title: &'static str,
+    /// Colonne della tabella delle reti: rete, canale, segnale, sicurezza
+    pub wifi_scan_columns: [&'static str; 4],
+    /// Scansione senza reti visibili
+    pub wifi_scan_empty: &'static str,
+    /// Rete senza password
+    pub wifi_open: &'static str,
+    /// Rete protetta da password
+    pub wifi_protected: &'static str,
+    /// Impostazioni salvate da POST /settings
+    pub settings_saved: &'static str,
+    /// Campo non valido nel form di /settings
+    pub settings_invalid: &'static str,
+    /// Scrittura in flash delle impostazioni fallita
+    pub settings_failed: &'static str,
+    /// Sensore di temperatura non disponibile (vedi sensors.rs)
+    pub sensor_unavailable: &'static str,
+    /// Nessuna lettura del sensore ambientale (vedi i2c.rs)
+    pub environment_unavailable: &'static str,
+    /// Aggiornamento del firmware ricevuto, seguito dal nome dello slot
+    pub ota_rebooting: &'static str,
+    /// Prefisso degli errori dell'aggiornamento del firmware
+    pub ota_error: &'static str,
+    /// Pagina mostrata ai nuovi client durante lo svuotamento (vedi drain.rs)
+    pub maintenance_page: &'static str,
+    /// Oggetto dell'email inviata dopo un crash
+    pub crash_alert_subject: &'static str,
+    /// Aiuto dei comandi della console
+    pub help: &'static str,
+}
+
+static IT: Strings = Strings {
+    index_page: assets::INDEX_HTML,
+    form_page: assets::FORM_HTML,
+    time_page: assets::TIME_HTML,
+    provision_page: assets::PROVISION_HTML,
+    settings_page: assets::SETTINGS_HTML,
+    login_page: assets::LOGIN_HTML,
+    temperature_page: assets::TEMPERATURE_HTML,
+    environment_page: assets::ENVIRONMENT_HTML,
+    leds_page: assets::LEDS_HTML,
+    uart_page: assets::UART_HTML,
+    solved_title: "Sudoku risolto",
+    solve_error: "Errore nella risoluzione dello schema",
+    parse_error: "Errore nella lettura dello schema",
+    solve_timeout: "Schema troppo difficile, risoluzione interrotta",
+    solve_time_label: "Tempo di risoluzione",
+    uniqueness_unknown: "Unicità non verificata: lo schema potrebbe avere altre soluzioni",
+    request_id_label: "ID della richiesta",
+    server_busy: "Errore: server occupato, riprovare tra poco\n",
+    too_many_requests: "Errore: troppe richieste da questo indirizzo, riprovare più tardi\n",
+    payload_too_large: "Richiesta troppo grande",
+    acl_forbidden: "Errore: rotta di amministrazione non consentita da questa rete\n",
+    auth_required: "Errore: autenticazione richiesta\n",
+    login_failed: "Errore: utente o password errati\n",
+    static_not_found: "Errore: file non trovato\n",
+    safe_mode_unavailable: "Modalita' provvisoria: servizio non disponibile\n",
+    led_invalid_action: "Errore: action deve essere on, off, toggle, blink o auto\n",
+    gpio_invalid: "Errore: GPIO del CYW43 inesistente\n",
+    gpio_unmanaged: "Errore: GPIO non configurato in GPIO_OUTPUTS o GPIO_INPUTS\n",
+    gpio_read_only: "Errore: il GPIO e' un ingresso\n",
+    gpio_invalid_level: "Errore: level deve essere 0, 1 o toggle\n",
+    leds_invalid_index: "Errore: LED della striscia inesistente\n",
+    uart_sent: "OK: dati inviati sulla UART1\n",
+    uart_unavailable: "Errore: UART1 non disponibile\n",
+    pwm_unknown_channel: "Errore: canale PWM inesistente\n",
+    pwm_invalid: "Errore: frequenza tra 10 Hz e 1 MHz, duty cycle tra 0 e 1000 per mille\n",
+    provision_saved: "OK: rete salvata, la pico si riavvia e si collega\n",
+    provision_inactive: "Errore: la pico non e' in modalita' di configurazione\n",
+    provision_failed: "Errore: rete non salvata\n",
+    wifi_scan_title: "Reti WiFi visibili",
+    wifi_scan_columns: ["Rete", "Canale", "Segnale", "Sicurezza"],
+    wifi_scan_empty: "Nessuna rete trovata",
+    wifi_open: "aperta",
+    wifi_protected: "protetta",
+    settings_saved: "OK: impostazioni salvate, valide dal prossimo riavvio\n",
+    settings_invalid: "Errore: impostazioni non valide, nulla e' stato salvato\n",
+    settings_failed: "Errore: impostazioni non salvate\n",
+    sensor_unavailable: "Errore: sensore di temperatura non disponibile\n",
+    environment_unavailable: "Errore: nessuna lettura del sensore ambientale\n",
+    ota_rebooting: "OK: riavvio nello slot",
+    ota_error: "errore",
+    maintenance_page: "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+                       <title>Manutenzione</title></head><body>\
+                       <h1>Manutenzione in corso</h1>\
+                       <p>La pico si sta riavviando: riprovare tra qualche istante.</p>\
+                       </body></html>",
+    crash_alert_subject: "picoweb: riavvio dopo un crash",
+    help: "Comandi disponibili:
+  help                     mostra questo messaggio
+  status                   stato del sistema
+  solve <schema>           risolve uno schema (81 caratteri o 9 righe separate da spazi)
+  config                   mostra la configurazione di rete
+  ip                       mostra indirizzo, gateway e DNS in uso
+  config get <chiave>      mostra un valore della configurazione
+  config set <chiave> <v>  modifica un valore e lo salva in flash
+  led on|off|blink|auto    LED fisso, lampeggio o stato del sistema
+  log [n]                  mostra gli ultimi n record di log (default 10)
+  loglevel [livello]       mostra o imposta il livello di log (error..trace, default)
+  time [secondi]           mostra l'ora o la imposta (secondi Unix, UTC)
+  ps                       elenca i task avviati e la loro attività
+  bench                    confronta i tempi degli algoritmi di risoluzione
+  reboot                   riavvia la pico
+  bootsel                  riavvia la pico in modalità BOOTSEL
+  drain reboot|bootsel     attende la fine delle richieste HTTP, poi riavvia
+  AT...                    comandi del protocollo AT (vedi README)",
+};
+
+static EN: Strings = Strings {
+    index_page: assets::INDEX_EN_HTML,
+    form_page: assets::FORM_EN_HTML,
+    time_page: assets::TIME_EN_HTML,
+    provision_page: assets::PROVISION_EN_HTML,
+    settings_page: assets::SETTINGS_EN_HTML,
+    login_page: assets::LOGIN_EN_HTML,
+    temperature_page: assets::TEMPERATURE_EN_HTML,
+    environment_page: assets::ENVIRONMENT_EN_HTML,
+    leds_page: assets::LEDS_EN_HTML,
+    uart_page: assets::UART_EN_HTML,
+    solved_title: "Solved Sudoku",
+    solve_error: "Error solving schema",
+    parse_error: "Error parsing schema",
+    solve_timeout: "Schema too hard, solving stopped",
+    solve_time_label: "Solve time",
+    uniqueness_unknown: "Uniqueness not verified: the schema may have other solutions",
+    request_id_label: "Request ID",
+    server_busy: "Error: server busy, please retry shortly\n",
+    too_many_requests: "Error: too many requests from this address, please retry later\n",
+    payload_too_large: "Request too large",
+    acl_forbidden: "Error: admin route not allowed from this network\n",
+    auth_required: "Error: authentication required\n",
+    login_failed: "Error: wrong user or password\n",
+    static_not_found: "Error: file not found\n",
+    safe_mode_unavailable: "Safe mode: service unavailable\n",
+    led_invalid_action: "Error: action must be on, off, toggle, blink or auto\n",
+    gpio_invalid: "Error: no such CYW43 GPIO\n",
+    gpio_unmanaged: "Error: GPIO not configured in GPIO_OUTPUTS or GPIO_INPUTS\n",
+    gpio_read_only: "Error: the GPIO is an input\n",
+    gpio_invalid_level: "Error: level must be 0, 1 or toggle\n",
+    leds_invalid_index: "Error: no such LED on the strip\n",
+    uart_sent: "OK: data sent on UART1\n",
+    uart_unavailable: "Error: UART1 unavailable\n",
+    pwm_unknown_channel: "Error: no such PWM channel\n",
+    pwm_invalid: "Error: frequency must be 10 Hz to 1 MHz, duty cycle 0 to 1000 per mille\n",
+    provision_saved: "OK: network saved, the pico restarts and joins it\n",
+    provision_inactive: "Error: the pico is not in setup mode\n",
+    provision_failed: "Error: network not saved\n",
+    wifi_scan_title: "Visible WiFi networks",
+    wifi_scan_columns: ["Network", "Channel", "Signal", "Security"],
+    wifi_scan_empty: "No networks found",
+    wifi_open: "open",
+    wifi_protected: "protected",
+    settings_saved: "OK: settings saved, effective from the next reboot\n",
+    settings_invalid: "Error: invalid settings, nothing was saved\n",
+    settings_failed: "Error: settings not saved\n",
+    sensor_unavailable: "Error: temperature sensor unavailable\n",
+    environment_unavailable: "Error: no reading from the environment sensor\n",
+    ota_rebooting: "OK: rebooting into slot",
+    ota_error: "error",
+    maintenance_page: "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+                       <title>Maintenance</title></head><body>\
+                       <h1>Maintenance in progress</h1>\
+                       <p>The pico is restarting: please retry in a few moments.</p>\
+                       </body></html>",
+    crash_alert_subject: "picoweb: restarted after a crash",
+    help: "Available commands:
+  help                     show this message
+  status                   system status
+  solve <schema>           solve a schema (81 characters or 9 space-separated rows)
+  config                   show the network configuration
+  ip                       show the address, gateway and DNS in use
+  config get <key>         show a configuration value
+  config set <key> <v>     change a value and save it to flash
+  led on|off|blink|auto    LED steady, blinking or system status
+  log [n]                  show the last n log records (default 10)
+  loglevel [level]         show or set the log level (error..trace, default)
+  time [seconds]           show the time or set it (Unix seconds, UTC)
+  ps                       list the spawned tasks and their activity
+  bench                    compare the timings of the solving algorithms
+  reboot                   reboot the pico
+  bootsel                  reboot the pico into BOOTSEL mode
+  drain reboot|bootsel     wait for the HTTP requests to finish, then reboot
+  AT...                    AT protocol commands (see README)",
+};
+
+impl Lang {
+    /// Tabella delle stringhe della lingua
+    pub fn strings(self) -> &'static Strings {
+        match self {
+            Lang::It => &IT,
+            Lang::En => &EN,
+        }
+    }
+
+    /// Interpreta un tag di lingua, es. "en-US"
+    fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.trim();
+        if primary.eq_ignore_ascii_case("it") {
+            Some(Lang::It)
+        } else if primary.eq_ignore_ascii_case("en") {
+            Some(Lang::En)
+        } else {
+            None
+        }
+    }
+
+    /// Ritorna la lingua forzata dalla configurazione
+    ///
+    /// # Ritorna
+    /// * Option<Lang> - Lingua configurata, None con `auto` (o valore non valido)
+    fn forced() -> Option<Self> {
+        get_config("LANGUAGE").and_then(|value| Self::from_tag(value.trim()))
+    }
+
+    /// Lingua dei messaggi senza una richiesta HTTP (es. la console)
+    ///
+    /// # Ritorna
+    /// * Lang - Lingua configurata, italiano con `auto`
+    pub fn configured() -> Self {
+        Self::forced().unwrap_or(Lang::It)
+    }
+
+    /// Sceglie la lingua di una richiesta HTTP
+    ///
+    /// # Argomenti
+    /// * `request_parts` - Richiesta ricevuta
+    ///
+    /// # Ritorna
+    /// * Lang - Lingua configurata, oppure la prima supportata in `Accept-Language`
+    pub fn negotiate(request_parts: &RequestParts<'_>) -> Self {
+        if let Some(lang) = Self::forced() {
+            return lang;
+        }
+        request_parts
+            .headers()
+            .get("Accept-Language")
+            .and_then(|value| core::str::from_utf8(value.as_raw()).ok())
+            .and_then(|value| {
+                // I browser elencano le lingue in ordine di preferenza
+                value
+                    .split(',')
+                    .filter_map(|entry| entry.split(';').next())
+                    .find_map(Self::from_tag)
+            })
+            .unwrap_or(Lang::It)
+    }
+}
+
+// Permette di estrarre la lingua della richiesta negli handler
+impl<'r, State> FromRequestParts<'r, State> for Lang {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        _state: &'r State,
+        request_parts: &RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(Self::negotiate(request_parts))
+    }
+}
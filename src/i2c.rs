@@ -0,0 +1,417 @@
+//! i2c.rs - Sensore ambientale (BME280 o SHT3x) su bus I2C.
+//!
+//! Il bus parte sui pin di `I2C_PINS` (configuration.rs): `20,21` per I2C0
+//! (SDA GP20, SCL GP21) oppure `2,3` per I2C1 (SDA GP2, SCL GP3); vuoto =
+//! disabilitato. Il task di campionamento cerca un BME280 (indirizzi 0x76
+//! e 0x77) o un SHT3x (0x44 e 0x45), lo legge ogni [`SAMPLE_PERIOD`] e
+//! conserva l'ultima lettura: gli handler HTTP restituiscono quella e non
+//! aspettano mai il bus.
+//! * `GET /api/environment` - ultima lettura in JSON (la pressione solo
+//!   con il BME280);
+//! * `GET /environment` - pagina con le letture aggiornate ogni 5 s.
+//!
+//! Se il sensore smette di rispondere la lettura viene scartata e il task
+//! torna a cercarlo ogni [`DETECT_PERIOD`].
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::assets::AcceptGzip;
+use crate::i18n::Lang;
+use core::cell::Cell;
+use critical_section::Mutex as CsMutex;
+use embassy_rp::i2c::{AbortReason, Async, Error, I2c, Instance};
+use embassy_rp::peripherals::{I2C0, I2C1};
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+use picoserve::response::{Json, StatusCode};
+use picoserve::routing::{PathRouter, get};
+
+/// Intervallo tra due letture
+const SAMPLE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Intervallo tra due ricerche del sensore
+const DETECT_PERIOD: Duration = Duration::from_secs(30);
+
+/// Attesa massima di una transazione sul bus
+const BUS_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Indirizzi del BME280 (SDO a massa o a VDD)
+const BME280_ADDRESSES: [u8; 2] = [0x76, 0x77];
+
+/// Identificativo restituito dal registro 0xD0 del BME280
+const BME280_CHIP_ID: u8 = 0x60;
+
+/// Indirizzi dell'SHT3x (ADDR a massa o a VDD)
+const SHT3X_ADDRESSES: [u8; 2] = [0x44, 0x45];
+
+/// Errore riportato per i timeout del bus e per i CRC errati
+const BUS_FAILURE: Error = Error::Abort(AbortReason::Other(0));
+
+/// Ultima lettura del sensore
+static READING: CsMutex<Cell<Option<Reading>>> = CsMutex::new(Cell::new(None));
+
+/// Lettura conservata dal task di campionamento
+#[derive(Clone, Copy)]
+struct Reading {
+    sensor: &'static str,
+    /// Temperatura in centesimi di grado
+    centi_celsius: i32,
+    /// Umidità relativa in millesimi di punto percentuale
+    milli_percent: u32,
+    /// Pressione in Pa, None con l'SHT3x
+    pressure_pa: Option<u32>,
+    at: Instant,
+}
+
+/// Lettura restituita da /api/environment
+#[derive(serde::Serialize)]
+pub struct Environment {
+    /// Modello del sensore (`bme280` o `sht3x`)
+    sensor: &'static str,
+    temperature_c: f32,
+    humidity_percent: f32,
+    pressure_hpa: Option<f32>,
+    /// Tempo trascorso dalla lettura
+    age_ms: u64,
+}
+
+/// Ritorna l'ultima lettura del sensore
+///
+/// # Ritorna
+/// * Option<Environment> - Lettura, None se il sensore non è disponibile
+pub fn environment() -> Option<Environment> {
+    let reading = critical_section::with(|cs| READING.borrow(cs).get())?;
+    Some(Environment {
+        sensor: reading.sensor,
+        temperature_c: reading.centi_celsius as f32 / 100.0,
+        humidity_percent: reading.milli_percent as f32 / 1000.0,
+        pressure_hpa: reading.pressure_pa.map(|pa| pa as f32 / 100.0),
+        age_ms: reading.at.elapsed().as_millis(),
+    })
+}
+
+/// Coefficienti di compensazione del BME280, letti dal chip
+struct Calibration {
+    t1: u16,
+    t2: i16,
+    t3: i16,
+    p: [i64; 9],
+    h1: u8,
+    h2: i16,
+    h3: u8,
+    h4: i16,
+    h5: i16,
+    h6: i8,
+}
+
+impl Calibration {
+    /// Decodifica i registri 0x88..0xA1 e 0xE1..0xE7
+    fn parse(tp: &[u8; 26], h: &[u8; 7]) -> Self {
+        let u16_at = |i: usize| u16::from_le_bytes([tp[i], tp[i + 1]]);
+        let i16_at = |i: usize| i16::from_le_bytes([tp[i], tp[i + 1]]);
+        let mut p = [u16_at(6) as i64; 9];
+        for (k, coefficient) in p.iter_mut().enumerate().skip(1) {
+            *coefficient = i16_at(6 + 2 * k) as i64;
+        }
+        Self {
+            t1: u16_at(0),
+            t2: i16_at(2),
+            t3: i16_at(4),
+            p,
+            h1: tp[25],
+            h2: i16::from_le_bytes([h[0], h[1]]),
+            h3: h[2],
+            h4: ((h[3] as i8 as i16) << 4) | (h[4] & 0x0F) as i16,
+            h5: ((h[5] as i8 as i16) << 4) | (h[4] >> 4) as i16,
+            h6: h[6] as i8,
+        }
+    }
+
+    /// Compensa una lettura grezza con le formule intere del datasheet
+    ///
+    /// # Argomenti
+    /// * `raw` - Registri 0xF7..0xFE (pressione, temperatura, umidità)
+    ///
+    /// # Ritorna
+    /// * (i32, u32, u32) - Temperatura in c°C, umidità in m%, pressione in Pa
+    fn compensate(&self, raw: &[u8; 8]) -> (i32, u32, u32) {
+        let adc_p = ((raw[0] as i32) << 12) | ((raw[1] as i32) << 4) | (raw[2] as i32 >> 4);
+        let adc_t = ((raw[3] as i32) << 12) | ((raw[4] as i32) << 4) | (raw[5] as i32 >> 4);
+        let adc_h = ((raw[6] as i32) << 8) | raw[7] as i32;
+
+        let t1 = self.t1 as i32;
+        let var1 = (((adc_t >> 3) - (t1 << 1)) * self.t2 as i32) >> 11;
+        let var2 = (((((adc_t >> 4) - t1) * ((adc_t >> 4) - t1)) >> 12) * self.t3 as i32) >> 14;
+        let t_fine = var1 + var2;
+        let centi_celsius = (t_fine * 5 + 128) >> 8;
+
+        let p = &self.p;
+        let mut var1 = t_fine as i64 - 128_000;
+        let mut var2 = var1 * var1 * p[5];
+        var2 += (var1 * p[4]) << 17;
+        var2 += p[3] << 35;
+        var1 = ((var1 * var1 * p[2]) >> 8) + ((var1 * p[1]) << 12);
+        var1 = (((1i64 << 47) + var1) * p[0]) >> 33;
+        let pressure_pa = if var1 == 0 {
+            0
+        } else {
+            let mut pressure = 1_048_576 - adc_p as i64;
+            pressure = (((pressure << 31) - var2) * 3125) / var1;
+            let var1 = (p[8] * (pressure >> 13) * (pressure >> 13)) >> 25;
+            let var2 = (p[7] * pressure) >> 19;
+            // Pa in formato Q24.8
+            ((((pressure + var1 + var2) >> 8) + (p[6] << 4)) >> 8) as u32
+        };
+
+        let v = t_fine - 76_800;
+        let v = ((((adc_h << 14) - ((self.h4 as i32) << 20) - (self.h5 as i32 * v)) + 16_384)
+            >> 15)
+            * (((((((v * self.h6 as i32) >> 10) * (((v * self.h3 as i32) >> 11) + 32_768))
+                >> 10)
+                + 2_097_152)
+                * self.h2 as i32
+                + 8192)
+                >> 14);
+        let v = v - (((((v >> 15) * (v >> 15)) >> 7) * self.h1 as i32) >> 4);
+        // %RH in formato Q22.10
+        let humidity = (v.clamp(0, 419_430_400) >> 12) as u32;
+
+        (centi_celsius, humidity * 1000 / 1024, pressure_pa)
+    }
+}
+
+/// Sensore trovato sul bus
+enum Sensor {
+    Bme280 {
+        address: u8,
+        calibration: Calibration,
+    },
+    Sht3x {
+        address: u8,
+    },
+}
+
+/// Scrive e poi legge sul bus, con un limite di tempo
+async fn write_read<T: Instance>(
+    i2c: &mut I2c<'static, T, Async>,
+    address: u8,
+    bytes: &[u8],
+    buffer: &mut [u8],
+) -> Result<(), Error> {
+    with_timeout(
+        BUS_TIMEOUT,
+        i2c.write_read_async(address, bytes.iter().copied(), buffer),
+    )
+    .await
+    .unwrap_or(Err(BUS_FAILURE))
+}
+
+/// Scrive sul bus, con un limite di tempo
+async fn write<T: Instance>(
+    i2c: &mut I2c<'static, T, Async>,
+    address: u8,
+    bytes: &[u8],
+) -> Result<(), Error> {
+    with_timeout(BUS_TIMEOUT, i2c.write_async(address, bytes.iter().copied()))
+        .await
+        .unwrap_or(Err(BUS_FAILURE))
+}
+
+/// Legge dal bus, con un limite di tempo
+async fn read<T: Instance>(
+    i2c: &mut I2c<'static, T, Async>,
+    address: u8,
+    buffer: &mut [u8],
+) -> Result<(), Error> {
+    with_timeout(BUS_TIMEOUT, i2c.read_async(address, buffer))
+        .await
+        .unwrap_or(Err(BUS_FAILURE))
+}
+
+/// CRC-8 dell'SHT3x (polinomio 0x31, valore iniziale 0xFF)
+fn sht3x_crc(data: &[u8]) -> u8 {
+    data.iter().fold(0xFF, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Cerca un sensore supportato sul bus
+///
+/// # Argomenti
+/// * `i2c` - Bus I2C
+///
+/// # Ritorna
+/// * Option<Sensor> - Primo sensore trovato, con la calibrazione se è un BME280
+async fn detect<T: Instance>(i2c: &mut I2c<'static, T, Async>) -> Option<Sensor> {
+    for address in BME280_ADDRESSES {
+        let mut id = [0u8; 1];
+        if write_read(i2c, address, &[0xD0], &mut id).await.is_err() || id[0] != BME280_CHIP_ID {
+            continue;
+        }
+        let mut tp = [0u8; 26];
+        let mut h = [0u8; 7];
+        write_read(i2c, address, &[0x88], &mut tp).await.ok()?;
+        write_read(i2c, address, &[0xE1], &mut h).await.ok()?;
+        // Sovracampionamento x1 dell'umidità (vale dalla scrittura di ctrl_meas)
+        write(i2c, address, &[0xF2, 0x01]).await.ok()?;
+        return Some(Sensor::Bme280 {
+            address,
+            calibration: Calibration::parse(&tp, &h),
+        });
+    }
+    for address in SHT3X_ADDRESSES {
+        // Lettura del registro di stato
+        let mut status = [0u8; 3];
+        if write_read(i2c, address, &[0xF3, 0x2D], &mut status)
+            .await
+            .is_ok()
+            && sht3x_crc(&status[..2]) == status[2]
+        {
+            return Some(Sensor::Sht3x { address });
+        }
+    }
+    None
+}
+
+/// Esegue una misura sul sensore
+///
+/// # Argomenti
+/// * `i2c` - Bus I2C
+/// * `sensor` - Sensore trovato da detect
+///
+/// # Ritorna
+/// * Result<Reading, Error> - Lettura, errore del bus (o CRC errato) se il sensore non risponde
+async fn measure<T: Instance>(
+    i2c: &mut I2c<'static, T, Async>,
+    sensor: &Sensor,
+) -> Result<Reading, Error> {
+    match sensor {
+        Sensor::Bme280 {
+            address,
+            calibration,
+        } => {
+            // Modo forced: una misura con sovracampionamento x1, poi sleep
+            write(i2c, *address, &[0xF4, 0x25]).await?;
+            Timer::after_millis(10).await;
+            let mut raw = [0u8; 8];
+            write_read(i2c, *address, &[0xF7], &mut raw).await?;
+            let (centi_celsius, milli_percent, pressure_pa) = calibration.compensate(&raw);
+            Ok(Reading {
+                sensor: "bme280",
+                centi_celsius,
+                milli_percent,
+                pressure_pa: Some(pressure_pa),
+                at: Instant::now(),
+            })
+        }
+        Sensor::Sht3x { address } => {
+            // Misura singola ad alta ripetibilità, senza clock stretching
+            write(i2c, *address, &[0x24, 0x00]).await?;
+            Timer::after_millis(16).await;
+            let mut raw = [0u8; 6];
+            read(i2c, *address, &mut raw).await?;
+            if sht3x_crc(&raw[0..2]) != raw[2] || sht3x_crc(&raw[3..5]) != raw[5] {
+                return Err(BUS_FAILURE);
+            }
+            let t = u16::from_be_bytes([raw[0], raw[1]]) as i32;
+            let h = u16::from_be_bytes([raw[3], raw[4]]);
+            Ok(Reading {
+                sensor: "sht3x",
+                centi_celsius: -4500 + 17_500 * t / 65_535,
+                milli_percent: (100_000 * h as u64 / 65_535) as u32,
+                pressure_pa: None,
+                at: Instant::now(),
+            })
+        }
+    }
+}
+
+/// Cerca il sensore e lo legge periodicamente, conservando l'ultima lettura
+///
+/// # Argomenti
+/// * `i2c` - Bus I2C
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+async fn sample<T: Instance>(mut i2c: I2c<'static, T, Async>) -> ! {
+    loop {
+        crate::tasks::touch("i2c");
+        let Some(sensor) = detect(&mut i2c).await else {
+            log::debug!("I2C: nessun sensore ambientale trovato");
+            Timer::after(DETECT_PERIOD).await;
+            continue;
+        };
+        match &sensor {
+            Sensor::Bme280 { address, .. } => log::info!("I2C: BME280 a 0x{:02x}", address),
+            Sensor::Sht3x { address } => log::info!("I2C: SHT3x a 0x{:02x}", address),
+        }
+        loop {
+            crate::tasks::touch("i2c");
+            match measure(&mut i2c, &sensor).await {
+                Ok(reading) => critical_section::with(|cs| READING.borrow(cs).set(Some(reading))),
+                Err(e) => {
+                    log::warn!("I2C: lettura del sensore fallita: {:?}", e);
+                    critical_section::with(|cs| READING.borrow(cs).set(None));
+                    break;
+                }
+            }
+            Timer::after(SAMPLE_PERIOD).await;
+        }
+        Timer::after(DETECT_PERIOD).await;
+    }
+}
+
+#[embassy_executor::task]
+/// Task di campionamento sul bus I2C0 (GP20 e GP21)
+///
+/// # Argomenti
+/// * `i2c` - Bus I2C0
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn i2c0_task(i2c: I2c<'static, I2C0, Async>) -> ! {
+    sample(i2c).await
+}
+
+#[embassy_executor::task]
+/// Task di campionamento sul bus I2C1 (GP2 e GP3)
+///
+/// # Argomenti
+/// * `i2c` - Bus I2C1
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn i2c1_task(i2c: I2c<'static, I2C1, Async>) -> ! {
+    sample(i2c).await
+}
+
+/// Rotte del sensore ambientale: `/environment` (pagina) e `/api/environment` (JSON)
+pub struct EnvironmentRoutes;
+
+impl Routes for EnvironmentRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                "/environment",
+                get(|lang: Lang, accept: AcceptGzip| async move {
+                    lang.strings().environment_page.html(accept)
+                }),
+            )
+            .route(
+                "/api/environment",
+                get(|lang: Lang| async move {
+                    environment().map(Json).ok_or((
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        lang.strings().environment_unavailable,
+                    ))
+                }),
+            )
+    }
+}
@@ -0,0 +1,212 @@
+//! led.rs - Controllo via HTTP del LED e dei GPIO del chip CYW43.
+//!
+//! Le rotte usano il controller condiviso (`SharedControl`) estratto dallo
+//! stato dell'applicazione:
+//! * `/api/led` - GET: modalità e stato del LED; POST (form con `action` =
+//!   `on`, `off`, `toggle`, `blink` o `auto` e, per `blink`, `period_ms`
+//!   facoltativo) cambia la modalità, come il comando `led` della console
+//! * `/api/cyw43/gpio` - GET: ultimo livello impostato su ogni GPIO del
+//!   CYW43; POST (form con `pin` e `level`) imposta un GPIO
+//!
+//! Il LED (GPIO 0) è gestito dal task di blink (vedi fault.rs): le rotte ne
+//! cambiano la modalità e aggiornano subito il LED, ma i codici dei guasti
+//! restano prioritari.
+
+use crate::SharedControl;
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::fault::{self, Led, LedMode};
+use crate::i18n::Lang;
+use embassy_time::Duration;
+use picoserve::extract::{Form, State};
+use picoserve::response::{Json, StatusCode};
+use picoserve::routing::{PathRouter, get};
+use portable_atomic::{AtomicU8, Ordering};
+
+/// Numero di GPIO del CYW43 (0 = LED, 1 = modalità dell'SMPS, 2 = VBUS)
+const CYW43_GPIOS: u8 = 3;
+
+/// GPIO del CYW43 collegato al LED
+const LED_GPIO: u8 = 0;
+
+/// Livelli impostati sui GPIO del CYW43 (bit n = GPIO n)
+static LEVELS: AtomicU8 = AtomicU8::new(0);
+
+/// GPIO impostati almeno una volta dall'avvio (bit n = GPIO n)
+static WRITTEN: AtomicU8 = AtomicU8::new(0);
+
+/// Stato restituito da /api/led
+#[derive(serde::Serialize)]
+pub struct LedStatus {
+    pub mode: &'static str,
+    pub lit: bool,
+    pub blink_period_ms: u64,
+    /// Guasto mostrato al posto della modalità, se presente
+    pub fault: Option<u8>,
+}
+
+/// Comando inviato a `POST /api/led`
+#[derive(serde::Deserialize)]
+pub struct LedForm {
+    pub action: heapless::String<8>,
+    pub period_ms: Option<u64>,
+}
+
+/// Livello di un GPIO del CYW43 restituito da /api/cyw43/gpio
+#[derive(serde::Serialize)]
+pub struct GpioLevel {
+    pub pin: u8,
+    /// Ultimo livello impostato, None se mai impostato
+    pub level: Option<bool>,
+}
+
+/// Comando inviato a `POST /api/cyw43/gpio`
+#[derive(serde::Deserialize)]
+pub struct GpioForm {
+    pub pin: u8,
+    pub level: u8,
+}
+
+/// Ritorna lo stato del LED
+///
+/// # Ritorna
+/// * LedStatus - Modalità, stato e guasto mostrato
+pub fn status() -> LedStatus {
+    LedStatus {
+        mode: fault::led_mode().as_str(),
+        lit: fault::led_lit(),
+        blink_period_ms: fault::blink_period().as_millis(),
+        fault: fault::current().map(|f| f.blinks()),
+    }
+}
+
+/// Ritorna l'ultimo livello impostato su ogni GPIO del CYW43
+///
+/// # Ritorna
+/// * heapless::Vec<GpioLevel, N> - Un livello per GPIO
+pub fn levels() -> heapless::Vec<GpioLevel, { CYW43_GPIOS as usize }> {
+    let levels = LEVELS.load(Ordering::Relaxed);
+    let written = WRITTEN.load(Ordering::Relaxed);
+    (0..CYW43_GPIOS)
+        .map(|pin| GpioLevel {
+            pin,
+            level: (written & (1 << pin) != 0).then_some(levels & (1 << pin) != 0),
+        })
+        .collect()
+}
+
+/// Traduce un'azione sul LED nella nuova modalità
+///
+/// # Argomenti
+/// * `action` - `on`, `off`, `toggle`, `blink` o `auto`
+///
+/// # Ritorna
+/// * Option<LedMode> - Modalità richiesta, None se l'azione non è valida
+pub fn mode_for_action(action: &str) -> Option<LedMode> {
+    match action {
+        "on" => Some(LedMode::On),
+        "off" => Some(LedMode::Off),
+        "toggle" if fault::led_lit() => Some(LedMode::Off),
+        "toggle" => Some(LedMode::On),
+        "blink" => Some(LedMode::Blink),
+        "auto" => Some(LedMode::Auto),
+        _ => None,
+    }
+}
+
+/// Applica un comando al LED
+///
+/// # Argomenti
+/// * `control` - Controller condiviso del CYW43
+/// * `form` - Comando ricevuto
+/// * `lang` - Lingua della richiesta
+///
+/// # Ritorna
+/// * Result<LedStatus, (StatusCode, &str)> - Nuovo stato, oppure errore se il comando non è valido
+async fn apply(
+    mut control: SharedControl,
+    form: &LedForm,
+    lang: Lang,
+) -> Result<LedStatus, (StatusCode, &'static str)> {
+    let Some(mode) = mode_for_action(&form.action) else {
+        return Err((StatusCode::BAD_REQUEST, lang.strings().led_invalid_action));
+    };
+    if let Some(ms) = form.period_ms {
+        fault::set_blink_period(Duration::from_millis(ms));
+    }
+    fault::set_led_mode(mode);
+    // Il task di blink applica la modalità al passo successivo: on e off
+    // vengono applicati subito, a meno che non si stia mostrando un guasto
+    if matches!(mode, LedMode::On | LedMode::Off) && fault::current().is_none() {
+        control.set_led(mode == LedMode::On).await;
+    }
+    log::info!("LED: modalità {} da HTTP", mode.as_str());
+    Ok(status())
+}
+
+/// Imposta un GPIO del CYW43
+///
+/// # Argomenti
+/// * `control` - Controller condiviso del CYW43
+/// * `form` - GPIO e livello richiesti
+/// * `lang` - Lingua della richiesta
+///
+/// # Ritorna
+/// * Result<Vec<GpioLevel>, (StatusCode, &str)> - Livelli aggiornati, oppure errore se il GPIO non esiste
+async fn set_gpio(
+    mut control: SharedControl,
+    form: &GpioForm,
+    lang: Lang,
+) -> Result<heapless::Vec<GpioLevel, { CYW43_GPIOS as usize }>, (StatusCode, &'static str)> {
+    if form.pin >= CYW43_GPIOS {
+        return Err((StatusCode::BAD_REQUEST, lang.strings().gpio_invalid));
+    }
+    let on = form.level != 0;
+    if form.pin == LED_GPIO {
+        // Il LED resta nello stato richiesto invece di essere ripreso dal task di blink
+        fault::set_led_mode(if on { LedMode::On } else { LedMode::Off });
+        control.set_led(on).await;
+    } else {
+        control.0.lock().await.gpio_set(form.pin, on).await;
+    }
+    let bit = 1 << form.pin;
+    WRITTEN.fetch_or(bit, Ordering::Relaxed);
+    if on {
+        LEVELS.fetch_or(bit, Ordering::Relaxed);
+    } else {
+        LEVELS.fetch_and(!bit, Ordering::Relaxed);
+    }
+    log::info!("CYW43: GPIO {} = {} da HTTP", form.pin, on as u8);
+    Ok(levels())
+}
+
+/// Rotte del LED e dei GPIO del CYW43: `/api/led` e `/api/cyw43/gpio`
+pub struct LedRoutes;
+
+impl Routes for LedRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                "/api/led",
+                get(|| async move { Json(status()) }).post(
+                    |State(control): State<SharedControl>,
+                     lang: Lang,
+                     Form(form): Form<LedForm>| async move {
+                        apply(control, &form, lang).await.map(Json)
+                    },
+                ),
+            )
+            .route(
+                "/api/cyw43/gpio",
+                get(|| async move { Json(levels()) }).post(
+                    |State(control): State<SharedControl>,
+                     lang: Lang,
+                     Form(form): Form<GpioForm>| async move {
+                        set_gpio(control, &form, lang).await.map(Json)
+                    },
+                ),
+            )
+    }
+}
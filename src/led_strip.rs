@@ -0,0 +1,180 @@
+//! led_strip.rs - Striscia di LED WS2812 (NeoPixel) comandata via HTTP.
+//!
+//! I colori stanno in un frame buffer di [`LED_STRIP_LENGTH`] LED: le rotte
+//! lo modificano e svegliano [`led_strip_task`], che lo invia alla striscia
+//! con il driver sulla state machine sm0 del PIO1 (vedi pio.rs).
+//! * `GET /leds` - pagina con un selettore di colore;
+//! * `GET /api/leds` - colori correnti;
+//! * `POST /api/leds` - form con `r`, `g` e `b` (0-255) e, facoltativo,
+//!   `index` per un solo LED; senza `index` cambia tutta la striscia.
+//!
+//! Le rotte `/api/leds` rientrano nel prefisso `/api/led` delle rotte di
+//! amministrazione (vedi acl.rs).
+//!
+//! Esempio:
+//! ```text
+//! curl -d 'r=255&g=80&b=0' http://<ip>/api/leds
+//! ```
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::assets::AcceptGzip;
+use crate::configuration::LED_STRIP_LENGTH;
+use crate::i18n::Lang;
+use crate::pio::PioWs2812;
+use core::cell::Cell;
+use critical_section::Mutex as CsMutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use picoserve::extract::Form;
+use picoserve::response::{Json, StatusCode};
+use picoserve::routing::{PathRouter, get};
+
+/// Colore di un LED
+#[derive(Clone, Copy, Default, serde::Serialize)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Parola per la TX FIFO: GRB nei 24 bit alti
+    fn word(self) -> u32 {
+        ((self.g as u32) << 24) | ((self.r as u32) << 16) | ((self.b as u32) << 8)
+    }
+}
+
+/// Frame buffer della striscia
+static FRAME: CsMutex<Cell<[Rgb; LED_STRIP_LENGTH]>> =
+    CsMutex::new(Cell::new([Rgb { r: 0, g: 0, b: 0 }; LED_STRIP_LENGTH]));
+
+/// Frame buffer modificato, da inviare alla striscia
+static UPDATED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Colori restituiti da /api/leds
+#[derive(serde::Serialize)]
+pub struct StripReport {
+    pub length: usize,
+    pub leds: heapless::Vec<Rgb, LED_STRIP_LENGTH>,
+}
+
+/// Comando inviato a `POST /api/leds`
+#[derive(serde::Deserialize)]
+pub struct LedsForm {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// LED da cambiare, tutti se assente
+    #[serde(default)]
+    pub index: Option<usize>,
+}
+
+/// Ritorna i colori correnti della striscia
+///
+/// # Ritorna
+/// * StripReport - Un colore per LED
+pub fn report() -> StripReport {
+    let frame = critical_section::with(|cs| FRAME.borrow(cs).get());
+    StripReport {
+        length: LED_STRIP_LENGTH,
+        leds: frame.into_iter().collect(),
+    }
+}
+
+/// Cambia il colore di un LED o di tutta la striscia
+///
+/// # Argomenti
+/// * `color` - Nuovo colore
+/// * `index` - LED da cambiare, None per tutti
+///
+/// # Ritorna
+/// * bool - false se il LED non esiste (il frame resta invariato)
+pub fn set(color: Rgb, index: Option<usize>) -> bool {
+    let changed = critical_section::with(|cs| {
+        let cell = FRAME.borrow(cs);
+        let mut frame = cell.get();
+        match index {
+            Some(index) => match frame.get_mut(index) {
+                Some(led) => *led = color,
+                None => return false,
+            },
+            None => frame = [color; LED_STRIP_LENGTH],
+        }
+        cell.set(frame);
+        true
+    });
+    if changed {
+        UPDATED.signal(());
+    }
+    changed
+}
+
+#[embassy_executor::task]
+/// Task che invia il frame buffer alla striscia a ogni modifica
+///
+/// # Argomenti
+/// * `driver` - Driver WS2812 sul PIO1
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn led_strip_task(mut driver: PioWs2812) -> ! {
+    loop {
+        let frame = critical_section::with(|cs| FRAME.borrow(cs).get());
+        driver.write(&frame.map(Rgb::word)).await;
+        crate::tasks::touch("leds");
+        UPDATED.wait().await;
+    }
+}
+
+/// Applica un comando alla striscia
+///
+/// # Argomenti
+/// * `form` - Comando ricevuto
+/// * `lang` - Lingua della richiesta
+///
+/// # Ritorna
+/// * Result<StripReport, (StatusCode, &str)> - Nuovi colori, oppure errore se il LED non esiste
+fn apply(form: &LedsForm, lang: Lang) -> Result<StripReport, (StatusCode, &'static str)> {
+    let color = Rgb {
+        r: form.r,
+        g: form.g,
+        b: form.b,
+    };
+    if !set(color, form.index) {
+        return Err((StatusCode::BAD_REQUEST, lang.strings().leds_invalid_index));
+    }
+    log::info!(
+        "LED strip: #{:02x}{:02x}{:02x} su {:?} da HTTP",
+        color.r,
+        color.g,
+        color.b,
+        form.index
+    );
+    Ok(report())
+}
+
+/// Rotte della striscia di LED: `/leds` (pagina) e `/api/leds`
+pub struct LedStripRoutes;
+
+impl Routes for LedStripRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                "/leds",
+                get(|lang: Lang, accept: AcceptGzip| async move {
+                    lang.strings().leds_page.html(accept)
+                }),
+            )
+            .route(
+                "/api/leds",
+                get(|| async move { Json(report()) }).post(
+                    |lang: Lang, Form(form): Form<LedsForm>| async move {
+                        apply(&form, lang).map(Json)
+                    },
+                ),
+            )
+    }
+}
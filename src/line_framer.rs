@@ -0,0 +1,69 @@
+//! line_framer.rs - Suddivide un flusso di byte seriali in righe
+//! terminate da '\n' (o "\r\n"), con gestione delle righe troppo lunghe.
+
+/// Risultato dell'inserimento di un byte nel framer
+pub enum Frame<'a> {
+    /// Riga completa (senza terminatore)
+    Line(&'a [u8]),
+    /// Riga scartata perché più lunga della capacità; contiene la lunghezza ricevuta
+    Overflow(usize),
+}
+
+/// Accumula byte fino al terminatore di riga.
+/// Se la riga supera `N` byte viene scartata fino al terminatore successivo.
+pub struct LineFramer<const N: usize> {
+    buf: heapless::Vec<u8, N>,
+    dropped: usize,
+    completed: bool,
+}
+
+impl<const N: usize> LineFramer<N> {
+    /// Crea un framer vuoto
+    pub const fn new() -> Self {
+        Self {
+            buf: heapless::Vec::new(),
+            dropped: 0,
+            completed: false,
+        }
+    }
+
+    /// Scarta la riga parziale (es. dopo un errore di ricezione)
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.dropped = 0;
+        self.completed = false;
+    }
+
+    /// Inserisce un byte ricevuto.
+    ///
+    /// # Argomenti
+    /// * `byte` - Byte ricevuto
+    ///
+    /// # Ritorna
+    /// * Option<Frame> - Riga completata o segnalazione di overflow
+    pub fn push(&mut self, byte: u8) -> Option<Frame<'_>> {
+        if self.completed {
+            self.buf.clear();
+            self.completed = false;
+        }
+        match byte {
+            b'\r' => None,
+            b'\n' => {
+                self.completed = true;
+                if self.dropped > 0 {
+                    let len = self.buf.len() + self.dropped;
+                    self.dropped = 0;
+                    Some(Frame::Overflow(len))
+                } else {
+                    Some(Frame::Line(&self.buf))
+                }
+            }
+            _ => {
+                if self.dropped > 0 || self.buf.push(byte).is_err() {
+                    self.dropped += 1;
+                }
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! live.rs - Log ed eventi di sistema in tempo reale via Server-Sent Events.
+//!
+//! `GET /events` tiene aperta la connessione e invia:
+//! * `log` - ogni nuovo record del ring buffer (vedi log_buffer.rs), nello
+//!   stesso formato di `/logs`;
+//! * `wifi`, `solve`, `led`, `button` - gli eventi del bus (vedi bus.rs) come
+//!   JSON, es. `{"status":"solved","source":"web"}`;
+//! * `gap` - numero di record o eventi persi perché il client è rimasto
+//!   indietro.
+//!
+//! I posti sono limitati ([`log_buffer::watch`] e [`bus::subscribe`]): oltre
+//! il limite il client riceve un evento `error` e la connessione si chiude.
+//! La rotta resta disponibile in modalità provvisoria.
+//!
+//! Esempio:
+//! ```text
+//! curl -N http://<ip>/events
+//! ```
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::bus::{self, Event};
+use crate::errors;
+use crate::log_buffer;
+use crate::uart_bridge;
+use core::fmt::Write;
+use embassy_futures::select::{Either3, select3};
+use embassy_sync::pubsub::WaitResult;
+use embassy_time::Timer;
+use picoserve::response::EventStream;
+use picoserve::response::sse::{EventSource, EventWriter};
+use picoserve::routing::{PathRouter, get};
+
+/// Intervallo dei keep-alive SSE in assenza di dati
+const KEEPALIVE_SECS: u64 = 15;
+
+/// Traduce un evento del bus in un evento SSE (usato anche per i topic MQTT,
+/// vedi mqtt.rs)
+///
+/// # Argomenti
+/// * `event` - Evento ricevuto dal bus
+///
+/// # Ritorna
+/// * (&'static str, heapless::String<96>) - Nome dell'evento SSE e dati JSON
+pub fn describe(event: &Event) -> (&'static str, heapless::String<96>) {
+    let mut data = heapless::String::new();
+    let name = match event {
+        Event::WifiStateChanged { state } => {
+            let _ = write!(data, "{{\"state\":\"{}\"}}", state.as_str());
+            "wifi"
+        }
+        Event::WifiJoined { ssid } => {
+            let _ = data.push_str("{\"joined\":");
+            let _ = uart_bridge::escape(ssid.as_bytes(), &mut data);
+            let _ = data.push('}');
+            "wifi"
+        }
+        Event::SolveStarted => {
+            let _ = data.push_str("{\"status\":\"started\"}");
+            "solve"
+        }
+        Event::PuzzleSolved { source } => {
+            let _ = write!(
+                data,
+                "{{\"status\":\"solved\",\"source\":\"{}\"}}",
+                source.as_str()
+            );
+            "solve"
+        }
+        Event::SolveFailed { timeout } => {
+            let _ = write!(data, "{{\"status\":\"failed\",\"timeout\":{}}}", timeout);
+            "solve"
+        }
+        Event::LedModeChanged { mode } => {
+            let _ = write!(data, "{{\"mode\":\"{}\"}}", mode.as_str());
+            "led"
+        }
+        Event::ButtonPressed { pin } => {
+            let _ = write!(data, "{{\"pin\":{}}}", pin);
+            "button"
+        }
+    };
+    (name, data)
+}
+
+/// Invia i record di log a partire da una sequenza
+///
+/// # Argomenti
+/// * `writer` - Writer degli eventi SSE
+/// * `seq` - Sequenza del primo record da inviare
+///
+/// # Ritorna
+/// * Result<u32, W::Error> - Sequenza del prossimo record da inviare, oppure
+///   errore di scrittura (client disconnesso)
+async fn write_records<W: picoserve::io::Write>(
+    writer: &mut EventWriter<W>,
+    seq: u32,
+) -> Result<u32, W::Error> {
+    let (first, count) = log_buffer::window();
+    let end = first.wrapping_add(count as u32);
+    // Se i record da inviare sono già stati scartati si riparte dal più vecchio
+    let mut seq = if seq.wrapping_sub(first) <= count as u32 {
+        seq
+    } else {
+        let lost: heapless::String<12> = errors::fit(format_args!("{}", first.wrapping_sub(seq)));
+        writer.write_event("gap", lost.as_str()).await?;
+        first
+    };
+    while seq != end {
+        if let Some(record) = log_buffer::get(seq) {
+            let line = log_buffer::render_line(Some(&record));
+            writer.write_event("log", line.trim_end()).await?;
+        }
+        seq = seq.wrapping_add(1);
+    }
+    Ok(seq)
+}
+
+/// Sorgente SSE dei log e degli eventi di sistema
+pub struct LiveEventStream;
+
+impl EventSource for LiveEventStream {
+    /// Invia i nuovi record di log e gli eventi del bus finché la connessione
+    /// resta aperta
+    ///
+    /// # Argomenti
+    /// * `writer` - Writer degli eventi SSE
+    ///
+    /// # Ritorna
+    /// * Result<(), W::Error> - Errore di scrittura (client disconnesso)
+    async fn write_events<W: picoserve::io::Write>(
+        self,
+        mut writer: EventWriter<W>,
+    ) -> Result<(), W::Error> {
+        let (Some(mut records), Some(mut events)) = (log_buffer::watch(), bus::subscribe()) else {
+            return writer.write_event("error", "troppi client connessi").await;
+        };
+
+        // Solo i record successivi alla connessione: quelli vecchi sono su /logs
+        let (first, count) = log_buffer::window();
+        let mut seq = first.wrapping_add(count as u32);
+        loop {
+            seq = write_records(&mut writer, seq).await?;
+            match select3(
+                records.changed(),
+                events.next_message(),
+                Timer::after_secs(KEEPALIVE_SECS),
+            )
+            .await
+            {
+                Either3::First(_) => {}
+                Either3::Second(WaitResult::Message(event)) => {
+                    let (name, data) = describe(&event);
+                    writer.write_event(name, data.as_str()).await?;
+                }
+                Either3::Second(WaitResult::Lagged(lost)) => {
+                    let lost: heapless::String<20> = errors::fit(format_args!("{}", lost));
+                    writer.write_event("gap", lost.as_str()).await?;
+                }
+                Either3::Third(()) => writer.write_keepalive().await?,
+            }
+        }
+    }
+}
+
+/// Rotte degli eventi in tempo reale: `/events`
+pub struct LiveRoutes;
+
+impl Routes for LiveRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router.route(
+            "/events",
+            get(|| async move { EventStream(LiveEventStream) }),
+        )
+    }
+}
@@ -0,0 +1,251 @@
+//! log_buffer.rs - Ring buffer in memoria dei record di log.
+//!
+//! Ogni record conserva numero di sequenza, timestamp (ms dall'avvio,
+//! mostrato come ora reale se l'orologio è sincronizzato, vedi clock.rs),
+//! livello, target e messaggio. I record vengono scritti dal logger di
+//! `logging.rs`, così restano consultabili da `/logs` e dal comando `log`
+//! della console anche senza cavo USB.
+//! La capacità è definita da `LOG_RING_CAPACITY` in `configuration.rs`.
+
+use crate::clock::Timestamp;
+use crate::configuration::LOG_RING_CAPACITY;
+use core::cell::RefCell;
+use core::fmt::Write;
+use critical_section::Mutex as CsMutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_sync::watch::{Receiver, Watch};
+use embassy_time::Instant;
+use heapless::Deque;
+
+/// Lunghezza massima del messaggio di un record
+pub const LOG_MESSAGE_CAPACITY: usize = 96;
+
+/// Lunghezza massima del target di un record
+const LOG_TARGET_CAPACITY: usize = 24;
+
+/// Larghezza fissa di una riga di log renderizzata (newline compreso)
+pub const LOG_LINE_WIDTH: usize = 160;
+
+/// Record di log strutturato
+#[derive(Clone)]
+pub struct LogRecord {
+    pub seq: u32,
+    pub timestamp_ms: u64,
+    pub level: log::Level,
+    pub target: heapless::String<LOG_TARGET_CAPACITY>,
+    pub message: heapless::String<LOG_MESSAGE_CAPACITY>,
+}
+
+/// Ring buffer con il numero di sequenza del prossimo record
+struct LogRing {
+    records: Deque<LogRecord, LOG_RING_CAPACITY>,
+    next_seq: u32,
+}
+
+static LOG_RING: CsMutex<RefCell<LogRing>> = CsMutex::new(RefCell::new(LogRing {
+    records: Deque::new(),
+    next_seq: 0,
+}));
+
+/// Segnalato a ogni nuovo record, per i consumatori in streaming
+static NEW_RECORD: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Numero massimo di consumatori di [`watch`]: due client di `/events`
+/// (vedi live.rs) e il task syslog
+const LOG_WATCHERS: usize = 3;
+
+/// Sequenza del prossimo record, pubblicata a ogni push per più consumatori
+static RECORDED: Watch<CriticalSectionRawMutex, u32, LOG_WATCHERS> = Watch::new();
+
+/// Ricevitore delle notifiche di nuovi record
+pub type RecordWatcher = Receiver<'static, CriticalSectionRawMutex, u32, LOG_WATCHERS>;
+
+/// Writer che tronca silenziosamente quando la stringa è piena
+/// (un messaggio di log troncato è preferibile a uno perso)
+struct Truncating<'a, const N: usize>(&'a mut heapless::String<N>);
+
+impl<const N: usize> Write for Truncating<'_, N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            if self.0.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Aggiunge un record al ring buffer, scartando il più vecchio se pieno
+///
+/// # Argomenti
+/// * `record` - Record del crate `log`
+#[cfg_attr(not(feature = "log-ring"), allow(dead_code))]
+pub fn push(record: &log::Record) {
+    let mut message = heapless::String::new();
+    let _ = write!(Truncating(&mut message), "{}", record.args());
+    let mut target = heapless::String::new();
+    let _ = Truncating(&mut target).write_str(record.target());
+    let timestamp_ms = Instant::now().as_millis();
+
+    let next_seq = critical_section::with(|cs| {
+        let mut ring = LOG_RING.borrow(cs).borrow_mut();
+        let entry = LogRecord {
+            seq: ring.next_seq,
+            timestamp_ms,
+            level: record.level(),
+            target,
+            message,
+        };
+        ring.next_seq = ring.next_seq.wrapping_add(1);
+        if ring.records.is_full() {
+            ring.records.pop_front();
+        }
+        let _ = ring.records.push_back(entry);
+        ring.next_seq
+    });
+    NEW_RECORD.signal(());
+    RECORDED.sender().send(next_seq);
+}
+
+/// Attende l'arrivo di un nuovo record nel ring buffer
+pub async fn wait_for_record() {
+    NEW_RECORD.wait().await;
+}
+
+/// Registra un consumatore delle notifiche di nuovi record; a differenza di
+/// [`wait_for_record`] ogni consumatore riceve tutte le notifiche
+///
+/// # Ritorna
+/// * Option<RecordWatcher> - None se tutti i posti sono già occupati
+pub fn watch() -> Option<RecordWatcher> {
+    RECORDED.receiver()
+}
+
+/// Ritorna il numero di sequenza del record più vecchio e il numero di record presenti
+///
+/// # Ritorna
+/// * (u32, usize) - Sequenza del primo record e numero di record
+pub fn window() -> (u32, usize) {
+    critical_section::with(|cs| {
+        let ring = LOG_RING.borrow(cs).borrow();
+        let first = ring.records.front().map_or(ring.next_seq, |r| r.seq);
+        (first, ring.records.len())
+    })
+}
+
+/// Ritorna una copia del record con il numero di sequenza indicato
+///
+/// # Argomenti
+/// * `seq` - Numero di sequenza
+///
+/// # Ritorna
+/// * Option<LogRecord> - None se il record è già stato scartato
+pub fn get(seq: u32) -> Option<LogRecord> {
+    critical_section::with(|cs| {
+        let ring = LOG_RING.borrow(cs).borrow();
+        let first = ring.records.front()?.seq;
+        let index = seq.wrapping_sub(first) as usize;
+        ring.records.iter().nth(index).cloned()
+    })
+}
+
+/// Renderizza un record del crate `log` su una riga (senza newline),
+/// con lo stesso formato del ring buffer ma senza numero di sequenza
+///
+/// # Argomenti
+/// * `record` - Record da renderizzare
+///
+/// # Ritorna
+/// * heapless::String<LOG_LINE_WIDTH> - Riga renderizzata (troncata se necessario)
+#[cfg_attr(not(any(feature = "log-uart", feature = "log-rtt")), allow(dead_code))]
+pub fn render_record(record: &log::Record) -> heapless::String<LOG_LINE_WIDTH> {
+    let mut line = heapless::String::new();
+    let _ = write!(
+        Truncating(&mut line),
+        "{} {:<5} {}: {}",
+        Timestamp::now(),
+        record.level(),
+        record.target(),
+        record.args()
+    );
+    line
+}
+
+/// Renderizza un record su una riga di larghezza fissa LOG_LINE_WIDTH
+/// (troncata o completata con spazi, terminata da newline).
+/// La larghezza fissa permette di calcolare la Content-Length in anticipo.
+///
+/// # Argomenti
+/// * `record` - Record da renderizzare, None se scartato nel frattempo
+///
+/// # Ritorna
+/// * heapless::String<LOG_LINE_WIDTH> - Riga renderizzata
+pub fn render_line(record: Option<&LogRecord>) -> heapless::String<LOG_LINE_WIDTH> {
+    let mut line = heapless::String::<LOG_LINE_WIDTH>::new();
+    {
+        let mut out = Truncating(&mut line);
+        let _ = match record {
+            Some(r) => write!(
+                out,
+                "{:>6} {} {:<5} {}: {}",
+                r.seq,
+                Timestamp(r.timestamp_ms),
+                r.level,
+                r.target,
+                r.message
+            ),
+            None => out.write_str("(record scartato)"),
+        };
+    }
+    while line.len() > LOG_LINE_WIDTH - 1 {
+        line.pop();
+    }
+    while line.len() < LOG_LINE_WIDTH - 1 {
+        let _ = line.push(' ');
+    }
+    let _ = line.push('\n');
+    line
+}
+
+/// Risposta HTTP con il contenuto del ring buffer in formato testo.
+/// La finestra di record viene fissata alla creazione; i record scartati
+/// durante l'invio vengono sostituiti da un segnaposto della stessa lunghezza.
+pub struct LogDump {
+    first: u32,
+    count: usize,
+}
+
+impl LogDump {
+    /// Crea una risposta con gli ultimi `max` record
+    ///
+    /// # Argomenti
+    /// * `max` - Numero massimo di record da includere
+    pub fn last(max: usize) -> Self {
+        let (first, count) = window();
+        let skip = count.saturating_sub(max);
+        Self {
+            first: first.wrapping_add(skip as u32),
+            count: count - skip,
+        }
+    }
+}
+
+impl picoserve::response::Content for LogDump {
+    fn content_type(&self) -> &'static str {
+        "text/plain; charset=utf-8"
+    }
+
+    fn content_length(&self) -> usize {
+        self.count * LOG_LINE_WIDTH
+    }
+
+    async fn write_content<W: picoserve::io::Write>(self, mut writer: W) -> Result<(), W::Error> {
+        for i in 0..self.count {
+            let record = get(self.first.wrapping_add(i as u32));
+            let line = render_line(record.as_ref());
+            writer.write_all(line.as_bytes()).await?;
+        }
+        Ok(())
+    }
+}
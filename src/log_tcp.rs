@@ -0,0 +1,96 @@
+//! log_tcp.rs - Invio dei log renderizzati a un collector TCP.
+//!
+//! Il task si collega all'indirizzo configurato con `LOG_COLLECTOR_ADDRESS`
+//! e `LOG_COLLECTOR_PORT` e invia un record per riga (terminata da '\n'),
+//! partendo dai record ancora presenti nel ring buffer. In caso di errore
+//! si ricollega con un ritardo crescente, riprendendo dal primo record non inviato.
+
+use crate::log_buffer;
+use embassy_net::tcp::TcpSocket;
+use embassy_net::{IpEndpoint, Ipv4Address};
+use embassy_time::{Duration, Timer};
+
+/// Ritardo iniziale e massimo tra due tentativi di connessione
+const RETRY_MIN_SECS: u64 = 2;
+const RETRY_MAX_SECS: u64 = 60;
+
+/// Timeout di scrittura sul socket
+const WRITE_TIMEOUT_SECS: u64 = 5;
+
+#[embassy_executor::task]
+/// Task che invia i log al collector TCP configurato
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+/// * `address` - Indirizzo IPv4 del collector
+/// * `port` - Porta TCP del collector
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn log_tcp_task(stack: embassy_net::Stack<'static>, address: [u8; 4], port: u16) {
+    let endpoint = IpEndpoint::new(
+        Ipv4Address::new(address[0], address[1], address[2], address[3]).into(),
+        port,
+    );
+    let mut rx_buffer = [0; 64];
+    let mut tx_buffer = [0; 1024];
+    let mut retry_secs = RETRY_MIN_SECS;
+    let mut next_seq: Option<u32> = None;
+
+    loop {
+        stack.wait_config_up().await;
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(WRITE_TIMEOUT_SECS)));
+
+        match socket.connect(endpoint).await {
+            Ok(()) => {
+                log::info!("log_tcp: collegato a {}", endpoint);
+                retry_secs = RETRY_MIN_SECS;
+                stream_records(&mut socket, &mut next_seq).await;
+                socket.abort();
+                let _ = socket.flush().await;
+            }
+            Err(e) => log::debug!("log_tcp: connessione fallita {:?}", e),
+        }
+
+        drop(socket);
+        Timer::after_secs(retry_secs).await;
+        retry_secs = (retry_secs * 2).min(RETRY_MAX_SECS);
+    }
+}
+
+/// Invia i record al collector finché la connessione resta valida
+///
+/// # Argomenti
+/// * `socket` - Socket connesso
+/// * `next_seq` - Prossimo record da inviare (None = dal più vecchio disponibile)
+async fn stream_records(socket: &mut TcpSocket<'_>, next_seq: &mut Option<u32>) {
+    loop {
+        let (first, count) = log_buffer::window();
+        let end = first.wrapping_add(count as u32);
+        // Se i record da inviare sono già stati scartati si riparte dal più vecchio
+        let mut seq = match *next_seq {
+            Some(seq) if seq.wrapping_sub(first) <= count as u32 => seq,
+            _ => first,
+        };
+
+        crate::tasks::touch("log_tcp");
+        while seq != end {
+            if let Some(record) = log_buffer::get(seq) {
+                let line = log_buffer::render_line(Some(&record));
+                let line = line.trim_end();
+                let sent = async {
+                    embedded_io_async::Write::write_all(socket, line.as_bytes()).await?;
+                    embedded_io_async::Write::write_all(socket, b"\n").await
+                };
+                if sent.await.is_err() {
+                    return;
+                }
+            }
+            seq = seq.wrapping_add(1);
+            *next_seq = Some(seq);
+        }
+
+        log_buffer::wait_for_record().await;
+    }
+}
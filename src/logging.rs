@@ -0,0 +1,195 @@
+//! logging.rs - Facciata unica per i log dell'applicazione.
+//!
+//! Tutto il codice usa esclusivamente le macro del crate `log`
+//! (`log::info!`, `log::warn!`, ...); questo modulo ne fornisce l'unica
+//! implementazione e smista ogni record verso i sink abilitati a compile time
+//! tramite le feature cargo:
+//! * `log-usb` - seriale USB (default)
+//! * `log-ring` - ring buffer in memoria, letto da `/logs`, dalla console e dal sink di rete (default)
+//! * `log-net` - invio al collector TCP (default, richiede `log-ring`)
+//! * `log-uart` - console UART0
+//! * `log-rtt` - RTT tramite defmt, per il debug con la sonda
+//!
+//! Il livello è configurabile per modulo con `LOG_LEVELS` in `configuration.rs`;
+//! il comando `loglevel` (vedi commands.rs) imposta a runtime un livello unico
+//! per tutti i target, fino al riavvio o a `loglevel default`.
+//! Ogni destinazione riporta il timestamp di `clock.rs` (ora reale se
+//! sincronizzata, altrimenti uptime). I record emessi durante una richiesta
+//! HTTP iniziano con il suo ID (vedi request_id.rs).
+
+#[cfg(feature = "log-usb")]
+use crate::clock::Timestamp;
+use crate::configuration::{LOG_LEVEL_DEFAULT, LOG_LEVELS};
+use crate::log_buffer;
+use crate::request_id;
+use crate::serial_proto::UsbAtHandler;
+use embassy_usb_logger::UsbLogger;
+use portable_atomic::{AtomicU8, Ordering};
+#[cfg(feature = "log-uart")]
+use {embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex, embassy_sync::pipe::Pipe};
+
+/// Dimensione del buffer del logger USB
+pub const USB_LOG_BUFFER: usize = 1024;
+
+/// Logger USB (che gestisce anche i comandi AT in arrivo dalla USB CDC)
+pub type UsbLog = UsbLogger<USB_LOG_BUFFER, UsbAtHandler>;
+
+/// Dimensione della pipe verso la console UART0
+#[cfg(feature = "log-uart")]
+const UART_LOG_PIPE: usize = 512;
+
+/// Righe di log in attesa di essere scritte sulla console UART0
+#[cfg(feature = "log-uart")]
+static UART_PIPE: Pipe<CriticalSectionRawMutex, UART_LOG_PIPE> = Pipe::new();
+
+/// Livello impostato a runtime per tutti i target: 0 se non impostato,
+/// altrimenti il `LevelFilter` più uno
+static LEVEL_OVERRIDE: AtomicU8 = AtomicU8::new(0);
+
+/// Ritorna il livello impostato a runtime
+///
+/// # Ritorna
+/// * Option<log::LevelFilter> - Livello, None se valgono i `LOG_LEVELS`
+pub fn level_override() -> Option<log::LevelFilter> {
+    match LEVEL_OVERRIDE.load(Ordering::Relaxed) {
+        1 => Some(log::LevelFilter::Off),
+        2 => Some(log::LevelFilter::Error),
+        3 => Some(log::LevelFilter::Warn),
+        4 => Some(log::LevelFilter::Info),
+        5 => Some(log::LevelFilter::Debug),
+        6 => Some(log::LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+/// Imposta a runtime il livello di tutti i target
+///
+/// # Argomenti
+/// * `level` - Nuovo livello, None per tornare ai `LOG_LEVELS`
+pub fn set_level_override(level: Option<log::LevelFilter>) {
+    LEVEL_OVERRIDE.store(level.map_or(0, |level| level as u8 + 1), Ordering::Relaxed);
+    log::set_max_level(max_level());
+}
+
+/// Ritorna il livello configurato per un target (il prefisso più lungo vince)
+///
+/// # Argomenti
+/// * `target` - Target del record, es. "picoserve::routing"
+///
+/// # Ritorna
+/// * log::LevelFilter - Livello massimo abilitato
+pub fn level_for(target: &str) -> log::LevelFilter {
+    if let Some(level) = level_override() {
+        return level;
+    }
+    LOG_LEVELS
+        .iter()
+        .filter(|(prefix, _)| target.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map_or(LOG_LEVEL_DEFAULT, |&(_, level)| level)
+}
+
+/// Livello massimo tra quello di default e quelli per modulo (o quello
+/// impostato a runtime), usato come filtro globale delle macro di `log`
+fn max_level() -> log::LevelFilter {
+    if let Some(level) = level_override() {
+        return level;
+    }
+    LOG_LEVELS
+        .iter()
+        .map(|&(_, level)| level)
+        .fold(LOG_LEVEL_DEFAULT, core::cmp::max)
+}
+
+/// Logger che smista i record verso i sink abilitati
+pub struct Logger {
+    #[cfg_attr(not(feature = "log-usb"), allow(dead_code))]
+    usb: &'static UsbLog,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= level_for(metadata.target())
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match request_id::current() {
+            Some(id) => self.dispatch(
+                &log::Record::builder()
+                    .args(format_args!("[{}] {}", id, record.args()))
+                    .level(record.level())
+                    .target(record.target())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            ),
+            None => self.dispatch(record),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl Logger {
+    /// Invia un record a tutti i sink abilitati
+    ///
+    /// # Argomenti
+    /// * `record` - Record da scrivere
+    fn dispatch(&self, record: &log::Record) {
+        #[cfg(feature = "log-ring")]
+        log_buffer::push(record);
+
+        // Sulla USB il timestamp viene aggiunto davanti al messaggio
+        #[cfg(feature = "log-usb")]
+        self.usb.log(
+            &log::Record::builder()
+                .args(format_args!("{} {}", Timestamp::now(), record.args()))
+                .level(record.level())
+                .target(record.target())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+
+        #[cfg(any(feature = "log-uart", feature = "log-rtt"))]
+        {
+            let line = log_buffer::render_record(record);
+            #[cfg(feature = "log-uart")]
+            {
+                // Se la console è lenta le righe in eccesso vengono scartate
+                let _ = UART_PIPE.try_write(line.as_bytes());
+                let _ = UART_PIPE.try_write(b"\r\n");
+            }
+            #[cfg(feature = "log-rtt")]
+            defmt::println!("{=str}", line.as_str());
+        }
+    }
+}
+
+/// Installa il logger globale
+///
+/// # Argomenti
+/// * `usb` - Logger USB a cui inoltrare i record
+pub fn init(usb: &'static UsbLog) {
+    let logger = picoserve::make_static!(Logger, Logger { usb });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level());
+    }
+}
+
+/// Legge le righe di log destinate alla console UART0
+///
+/// # Argomenti
+/// * `buf` - Buffer di destinazione
+///
+/// # Ritorna
+/// * usize - Numero di byte letti
+#[cfg(feature = "log-uart")]
+pub async fn read_uart(buf: &mut [u8]) -> usize {
+    UART_PIPE.read(buf).await
+}
@@ -1,4 +1,4 @@
-//! Questa applicazione per Raspberry Pico 2 W
+//! Questa applicazione per Raspberry Pico 2 W (e Pico W, vedi board.rs)
 //! realizza un web server che espone un servizio
 //! per l'inserimento di un form HTML per uno schema
 //! di Sudoku 9x9. Lo schema viene inviato via HTTP POST
@@ -12,51 +12,158 @@
 #![no_main]
 #![feature(impl_trait_in_assoc_type)]
 
-mod configuration;
-mod sudoku;
-#[macro_use]
-mod utility;
+#[cfg(all(feature = "rp235x", feature = "rp2040"))]
+compile_error!("selezionare una sola scheda: feature pico2_w (rp235x) oppure pico_w (rp2040)");
+#[cfg(not(any(feature = "rp235x", feature = "rp2040")))]
+compile_error!("selezionare la scheda: feature pico2_w (rp235x) oppure pico_w (rp2040)");
+
+#[cfg(feature = "heap")]
+extern crate alloc;
+
+mod acl;
 mod app_props;
 mod app_state;
+mod assets;
+mod auth;
+mod bench;
+#[cfg(feature = "ble")]
+mod ble;
+mod board;
+mod body_limit;
+mod brownout;
+mod bus;
+mod clock;
+mod commands;
+mod config_store;
+mod configuration;
+mod console;
+mod counters;
+mod crash_log;
+mod cyw43_firmware;
+mod drain;
+mod errors;
+mod events;
+mod fault;
+mod fetch;
+mod files;
+mod flash_store;
 mod form_value;
+mod gpio;
+#[cfg(feature = "heap")]
+mod heap;
+mod http_stats;
+mod i18n;
+mod i2c;
+mod led;
+mod led_strip;
+mod line_framer;
+mod live;
+mod log_buffer;
+#[cfg(feature = "log-net")]
+mod log_tcp;
+mod logging;
+mod maintenance;
+mod mdns;
+mod memory;
+mod metrics;
+mod mqtt;
+mod net_watch;
+#[cfg(feature = "rp235x")]
+mod ota;
+mod overload;
 mod pio;
+mod provisioning;
+#[cfg(feature = "psram")]
+mod psram;
+mod pwm;
+mod rate_limit;
+mod request_id;
+mod request_log;
+mod request_path;
+mod resources;
+mod safe_mode;
+mod sensors;
+mod serial_proto;
+mod session;
+mod settings;
+mod smtp;
+mod sntp;
+mod solve;
+mod solve_ws;
+mod status;
+mod status_led;
+mod supervisor;
+#[cfg(feature = "log-net")]
+mod syslog;
+mod system;
+mod tasks;
+mod telnet;
+mod timing;
+#[cfg(feature = "https")]
+mod tls;
+mod uart_bridge;
+mod utility;
+mod wifi;
+mod wifi_scan;
+mod wifi_stats;
 
 use crate::app_props::AppProps;
 use crate::app_state::AppState;
-use core::cell::RefCell;
-use critical_section::Mutex as CsMutex;
-use cyw43::{Control, JoinOptions};
-use cyw43_pio::{PioSpi, RM2_CLOCK_DIVIDER};
-use defmt::*;
+use crate::bus::SolveSource;
+use crate::configuration::{ServerResources, WorkerPool};
+use crate::errors::ErrorKind;
+use crate::fault::{Fault, Led, LedMode};
+use crate::line_framer::{Frame, LineFramer};
+use crate::resources::SharedUartTx;
+use crate::status_led::SystemState;
+use crate::supervisor::Task;
+use cyw43::Control;
+use cyw43_pio::PioSpi;
 use defmt_rtt as _;
 use embassy_executor::Spawner;
-use embassy_net::Ipv4Address;
+use embassy_futures::join::join;
+use embassy_futures::select::{Either, select};
+use embassy_net::tcp::TcpSocket;
+use embassy_rp::adc::{Adc, Channel as AdcChannel, Config as AdcConfig};
 use embassy_rp::bind_interrupts;
 use embassy_rp::clocks::RoscRng;
-use embassy_rp::gpio::{Level, Output};
-use embassy_rp::peripherals::{DMA_CH0, PIO0, PIO1, UART1, USB};
+use embassy_rp::gpio::{Flex, Input, Level, Output, Pull};
+use embassy_rp::i2c::{Config as I2cConfig, I2c, InterruptHandler as I2cInterruptHandler};
+use embassy_rp::peripherals::{DMA_CH0, I2C0, I2C1, PIO0, PIO1, UART0, UART1, USB};
 use embassy_rp::pio::{InterruptHandler, Pio};
-use embassy_rp::uart::{Async, Config, InterruptHandler as UartInterruptHandler, UartRx, UartTx};
+use embassy_rp::pwm::{Config as PwmConfig, Pwm};
+use embassy_rp::uart::{
+    BufferedInterruptHandler, BufferedUart, BufferedUartRx, BufferedUartTx, Config,
+    InterruptHandler as UartInterruptHandler, Uart,
+};
 use embassy_rp::usb::{Driver, InterruptHandler as UsbInterruptHandler};
+use embassy_rp::watchdog::Watchdog;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::{Duration, Ticker, Timer};
+use embassy_time::{Duration, Instant, Ticker, Timer};
+use embassy_usb_logger::{LoggerState, ReceiverHandler, UsbLogger};
 use panic_persist as _;
 use picoserve::{AppRouter, AppWithStateBuilder, make_static};
 use static_cell::StaticCell;
 use utility::*;
 
-const WEB_TASK_POOL_SIZE: usize = 10;
 const ELAPSED_SECS: u64 = 60;
+const UART_RX_BUFFER_SIZE: usize = 256;
+const UART_TX_BUFFER_SIZE: usize = 64;
+const UART_LINE_CAPACITY: usize = 128;
+/// Errori di ricezione consecutivi dopo i quali la lettura viene sospesa
+const UART_ERROR_BURST: u32 = 16;
+const UART_ERROR_BACKOFF: Duration = Duration::from_secs(1);
 
 // Program metadata for `picotool info`.
 // This isn't needed, but it's recommended to have these minimal entries.
+#[cfg(feature = "rp235x")]
 #[unsafe(link_section = ".bi_entries")]
 #[used]
 pub static PICOTOOL_ENTRIES: [embassy_rp::binary_info::EntryAddr; 4] = [
-    embassy_rp::binary_info::rp_program_name!(c"Blinky Example"),
+    embassy_rp::binary_info::rp_program_name!(c"picoweb"),
     embassy_rp::binary_info::rp_program_description!(
-        c"Questo programma gira su Pico 2 W e risolve uno schema di Sudoku 9x9."
+        c"Sudoku 9x9 web server for the Raspberry Pi Pico 2 W"
     ),
     embassy_rp::binary_info::rp_cargo_version!(),
     embassy_rp::binary_info::rp_program_build_attribute!(),
@@ -68,7 +175,13 @@ bind_interrupts!(struct Irqs {
 });
 
 bind_interrupts!(struct UartIrqs {
-    UART1_IRQ => UartInterruptHandler<UART1>;
+    UART0_IRQ => UartInterruptHandler<UART0>;
+    UART1_IRQ => BufferedInterruptHandler<UART1>;
+});
+
+bind_interrupts!(struct I2cIrqs {
+    I2C0_IRQ => I2cInterruptHandler<I2C0>;
+    I2C1_IRQ => I2cInterruptHandler<I2C1>;
 });
 
 bind_interrupts!(struct UsbIrqs {
@@ -79,160 +192,308 @@ bind_interrupts!(struct IrqPIO1 {
     PIO1_IRQ_0 => InterruptHandler<PIO1>;
 });
 
+#[cfg(feature = "https")]
+bind_interrupts!(struct TrngIrqs {
+    TRNG_IRQ => embassy_rp::trng::InterruptHandler<embassy_rp::peripherals::TRNG>;
+});
+
 /// Struttura per condividere il controller tra task embassy diversi
 #[derive(Clone, Copy)]
 pub struct SharedControl(&'static Mutex<CriticalSectionRawMutex, Control<'static>>);
 
 // Alias di tipo per semplificare la gestione di SM2
-type Sm2StateMachine = embassy_rp::pio::StateMachine<'static, PIO1, 2>;
-type Sm2Mutex = Mutex<CriticalSectionRawMutex, Sm2StateMachine>;
-type Sm2Cell = CsMutex<RefCell<Option<&'static Sm2Mutex>>>;
+type Sm2Mutex = Mutex<CriticalSectionRawMutex, pio::PioTimer>;
 
-/// Struttura per condividere la state machine sm2 tra task embassy diversi
+/// Struttura per condividere il timer sulla state machine sm2 tra task embassy diversi
+/// (registrata in resources.rs)
 #[derive(Clone, Copy)]
 pub struct SharedSm2(&'static Sm2Mutex);
 
-// Variabile statica globale per SharedSm2 usando critical_section::Mutex + RefCell
-// (thread-safe per embedded, no unsafe, no static mut)
-static SHARED_SM2_CELL: Sm2Cell = CsMutex::new(RefCell::new(None));
-
-/// Ottiene il riferimento a SharedSm2 globale in modo thread-safe
-pub fn get_shared_sm2() -> Option<SharedSm2> {
-    critical_section::with(|cs| {
-        SHARED_SM2_CELL.borrow(cs).borrow().as_ref().map(|ptr| SharedSm2(ptr))
-    })
-}
-
-/// Imposta il riferimento a SharedSm2 globale (da chiamare solo dal main una sola volta)
-/// Panic se viene chiamato più di una volta.
-fn set_shared_sm2(sm2: &'static Sm2Mutex) {
-    critical_section::with(|cs| {
-        let mut cell = SHARED_SM2_CELL.borrow(cs).borrow_mut();
-        if cell.is_some() {
-            core::panic!("SHARED_SM2 già inizializzato - set_shared_sm2() chiamato più volte");
-        }
-        *cell = Some(sm2);
-    });
-}
-
 /// Entry point principale secondo Embassy
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
+    // Pittura dello stack libero per misurarne l'uso (vedi memory.rs)
+    memory::paint_stack();
     let p = embassy_rp::init(Default::default());
 
+    // Contesto dell'esecuzione precedente, per lo storico dei crash (vedi crash_log.rs)
+    let crash_context = crash_log::take_context();
+    let reset_reason = crash_log::ResetReason::read();
+    // Il driver della flash è condiviso da archivi e aggiornamento (vedi flash_store.rs)
+    flash_store::init(flash_store::StoreFlash::new_blocking(p.FLASH));
+
+    // PSRAM esterna opzionale, che ospita lo heap se presente (vedi heap.rs)
+    #[cfg(feature = "psram")]
+    let psram_result = psram::init();
+    #[cfg(feature = "heap")]
+    heap::init();
+
     // Parte il logger su USB
     let driver = Driver::new(p.USB, UsbIrqs);
-    spawner.must_spawn(logger_task(driver)); //<---- 1
-    if let Some(panic_message) = panic_persist::get_panic_message_utf8() {
+    tasks::spawn(&spawner, "logger", logger_task(driver)); //<---- 1
+    let panic_message = panic_persist::get_panic_message_utf8();
+    let crashed = panic_message.is_some()
+        || (reset_reason == crash_log::ResetReason::Watchdog && crash_context.is_some());
+    status::set_stored_panic(panic_message);
+    if let Some(panic_message) = panic_message {
+        crash_log::record(panic_message, reset_reason, crash_context.as_ref());
         log::error!("{panic_message}");
-        loop {
-            embassy_time::Timer::after_secs(5).await;
-        }
+        fault::raise(Fault::StoredPanic);
+    } else if reset_reason == crash_log::ResetReason::Watchdog && crash_context.is_some() {
+        crash_log::record(
+            "watchdog scaduto: un task supervisionato non rispondeva",
+            reset_reason,
+            crash_context.as_ref(),
+        );
     }
 
-    // Firmware files for the CYW43xxx WiFi chip.
-    let fw = include_bytes!("../cyw43-firmware/43439A0.bin");
-    let clm = include_bytes!("../cyw43-firmware/43439A0_clm.bin");
+    // Valori di configurazione salvati in flash (es. rete scelta su /provision)
+    config_store::load();
 
-    // To make flashing faster for development, you may want to flash the firmwares independently
-    // at hardcoded addresses, instead of baking them into the program with `include_bytes!`:
-    //     probe-rs download ../../cyw43-firmware/43439A0.bin --binary-format bin --chip RP235x --base-address 0x10100000
-    //     probe-rs download ../../cyw43-firmware/43439A0_clm.bin --binary-format bin --chip RP235x --base-address 0x10140000
-    //let fw = unsafe { core::slice::from_raw_parts(0x10100000 as *const u8, 230321) };
-    //let clm = unsafe { core::slice::from_raw_parts(0x10140000 as *const u8, 4752) };
+    // Contatori persistenti, salvati periodicamente dal loro task
+    counters::boot();
+    tasks::spawn(&spawner, "counters", counters::counters_task());
+    brownout::restore();
 
-    let pwr = Output::new(p.PIN_23, Level::Low);
-    let cs = Output::new(p.PIN_25, Level::High);
+    // ADC condiviso tra il sensore di temperatura e il campionamento di VSYS
+    let adc = Adc::new_blocking(p.ADC, AdcConfig::default());
+    sensors::init(adc, AdcChannel::new_temp_sensor(p.ADC_TEMP_SENSOR));
+
+    // Salvataggio dei log in flash quando VSYS cala (partitore su GPIO26)
+    if get_brownout_vsys_mv() > 0 {
+        let vsys = AdcChannel::new_pin(p.PIN_26, Pull::None);
+        tasks::spawn(&spawner, "brownout", brownout::brownout_task(vsys));
+    }
+
+    // Avvio di prova dopo un aggiornamento A/B (vedi ota.rs)
+    #[cfg(feature = "rp235x")]
+    let ota_trial = ota::boot(panic_message.is_some());
+    #[cfg(feature = "rp2040")]
+    let ota_trial = false;
+    #[cfg(feature = "rp235x")]
+    if ota_trial {
+        tasks::spawn(&spawner, "ota", ota::ota_task());
+    }
+
+    // Dopo troppi panic consecutivi si avvia in modalità provvisoria (vedi safe_mode.rs)
+    let safe_mode = safe_mode::evaluate(crash_context.as_ref(), panic_message.is_some());
+
+    // Supervisore dei task, con il watchdog hardware se abilitato
+    // (sempre durante un avvio di prova, per tornare allo slot precedente in caso di blocco)
+    let watchdog = (get_watchdog_enabled() || ota_trial).then(|| Watchdog::new(p.WATCHDOG));
+    tasks::spawn(
+        &spawner,
+        "supervisor",
+        supervisor::supervisor_task(watchdog),
+    );
+    tasks::spawn(&spawner, "memory", memory::memory_task());
+
+    // Riavvio periodico di manutenzione, se configurato
+    tasks::spawn(&spawner, "maintenance", maintenance::maintenance_task());
+
+    // Svuotamento del web server prima dei riavvii richiesti
+    tasks::spawn(&spawner, "drain", drain::drain_task());
+
+    // Risolutore degli schemi sul secondo core (vedi solve.rs)
+    solve::start(p.CORE1);
+
+    #[cfg(feature = "psram")]
+    match psram_result {
+        Ok(size) => log::info!(
+            "PSRAM rilevata: {} KB, {} KB liberi dopo lo heap",
+            size / 1024,
+            psram::free() / 1024
+        ),
+        Err(e) => log::warn!("PSRAM non disponibile: {:?}", e),
+    }
+
+    // Firmware files for the CYW43xxx WiFi chip (vedi cyw43_firmware.rs).
+    let fw = cyw43_firmware::wifi();
+    let clm = cyw43_firmware::clm();
+    #[cfg(feature = "ble")]
+    let btfw = cyw43_firmware::bluetooth();
+
+    // Pin del chip WiFi della scheda (vedi board.rs)
+    let (pwr_pin, cs_pin, dio_pin, clk_pin) = board::cyw43_pins!(p);
+    let pwr = Output::new(pwr_pin, Level::Low);
+    let cs = Output::new(cs_pin, Level::High);
     let mut pio = Pio::new(p.PIO0, Irqs); // <---- PIO0 for SPI communication
     let spi = PioSpi::new(
         &mut pio.common,
         pio.sm0,
-        // SPI communication won't work if the speed is too high, so we use a divider larger than `DEFAULT_CLOCK_DIVIDER`.
-        // See: https://github.com/embassy-rs/embassy/issues/3960.
-        RM2_CLOCK_DIVIDER,
+        board::SPI_CLOCK_DIVIDER,
         pio.irq0,
         cs,
-        p.PIN_24,
-        p.PIN_29,
+        dio_pin,
+        clk_pin,
         p.DMA_CH0,
     );
 
     static STATE: StaticCell<cyw43::State> = StaticCell::new();
     let state = STATE.init(cyw43::State::new());
+    #[cfg(not(feature = "ble"))]
     let (net_device, mut control, runner) = cyw43::new(state, pwr, spi, fw).await;
+    #[cfg(feature = "ble")]
+    let (net_device, bt_device, mut control, runner) =
+        cyw43::new_with_bluetooth(state, pwr, spi, fw, btfw).await;
 
     // parte il task di gestione del chip WiFi
-    spawner.must_spawn(cyw43_task(runner)); //<---- 2
-    panic_led_loop!(control);
+    tasks::spawn(&spawner, "cyw43", cyw43_task(runner)); //<---- 2
 
-    // PIO1 per un timer di esempio ad altissima precisione che genera un interrupt
-    // e viene gestito dal PIO senza passare da CPU.
+    // PIO1 per un timer ad alta risoluzione che conta i microsecondi
+    // senza passare dalla CPU e per la striscia di LED (vedi pio.rs)
     let pio1 = p.PIO1;
     let Pio {
         // destrutturazione per prendere solo quello che serve
         mut common,
-        mut sm2,
+        sm0,
+        sm2,
         ..
     } = Pio::new(pio1, IrqPIO1);
 
-    pio::setup_pio_task_sm2(&mut common, &mut sm2);
+    // Registra il timer tra le risorse condivise, usato dalle misure dei tempi (vedi timing.rs)
+    let timer = pio::PioTimer::new(&mut common, sm2, p.DMA_CH3);
+    resources::register(SharedSm2(make_static!(Sm2Mutex, Mutex::new(timer))));
 
-    // Inizializza la variabile statica globale con sm2
-    // sm2 verrà attivata solo durante la generazione dell'HTML
-    let sm2_ref = make_static!(Sm2Mutex, Mutex::new(sm2));
-    set_shared_sm2(sm2_ref);
+    // Striscia di LED WS2812 su GP22, comandata da /api/leds (vedi led_strip.rs)
+    let strip = pio::PioWs2812::new(&mut common, sm0, p.DMA_CH4, p.PIN_22);
+    tasks::spawn(&spawner, "leds", led_strip::led_strip_task(strip));
 
     control.init(clm).await;
     control
         .set_power_management(cyw43::PowerManagementMode::PowerSave)
         .await;
 
-    let uart_tx: UartTx<'_, Async> = UartTx::new(p.UART0, p.PIN_0, p.DMA_CH1, Config::default());
-    let uart_rx = UartRx::new(p.UART1, p.PIN_5, UartIrqs, p.DMA_CH2, Config::default());
+    // Servizio GATT Sudoku via Bluetooth LE
+    #[cfg(feature = "ble")]
+    if !safe_mode {
+        tasks::spawn(&spawner, "ble", ble::ble_task(bt_device));
+    }
+
+    // UART0 (GP0 TX, GP1 RX): console di gestione
+    let (console_tx, console_rx) = Uart::new(
+        p.UART0,
+        p.PIN_0,
+        p.PIN_1,
+        UartIrqs,
+        p.DMA_CH1,
+        p.DMA_CH2,
+        Config::default(),
+    )
+    .split();
+
+    // UART1 (GP4 TX, GP5 RX): I/O seriale applicativo, ricezione bufferizzata
+    // a interrupt in un ring buffer così che nessun byte vada perso tra due letture
+    let (uart_tx, uart_rx) = BufferedUart::new(
+        p.UART1,
+        p.PIN_4,
+        p.PIN_5,
+        UartIrqs,
+        make_static!([u8; UART_TX_BUFFER_SIZE], [0; UART_TX_BUFFER_SIZE]),
+        make_static!([u8; UART_RX_BUFFER_SIZE], [0; UART_RX_BUFFER_SIZE]),
+        Config::default(),
+    )
+    .split();
+    let uart_tx = SharedUartTx(make_static!(
+        Mutex<CriticalSectionRawMutex, BufferedUartTx>,
+        Mutex::new(uart_tx)
+    ));
+    resources::register(uart_tx);
+
+    // I sottosistemi opzionali non partono in modalità provvisoria
+    if !safe_mode {
+        // Fa partire la UART (lettura)
+        tasks::spawn(&spawner, "uart", reader(uart_rx, uart_tx)); //<---- 3
+
+        // Eventi sui fronti dei GPIO monitorati (vedi events::EVENT_PINS)
+        let pin = Input::new(p.PIN_14, Pull::Up);
+        tasks::spawn(&spawner, "gpio", events::gpio_event_task(0, pin));
+        let pin = Input::new(p.PIN_15, Pull::Up);
+        tasks::spawn(&spawner, "gpio", events::gpio_event_task(1, pin));
+
+        // Sensore ambientale sul bus I2C scelto con I2C_PINS (vedi i2c.rs)
+        match get_i2c_pins() {
+            Some((20, 21)) => {
+                let bus = I2c::new_async(p.I2C0, p.PIN_21, p.PIN_20, I2cIrqs, I2cConfig::default());
+                tasks::spawn(&spawner, "i2c", i2c::i2c0_task(bus));
+            }
+            Some((2, 3)) => {
+                let bus = I2c::new_async(p.I2C1, p.PIN_3, p.PIN_2, I2cIrqs, I2cConfig::default());
+                tasks::spawn(&spawner, "i2c", i2c::i2c1_task(bus));
+            }
+            Some((sda, scl)) => {
+                log::warn!(
+                    "I2C: SDA {} e SCL {} non supportati (20,21 o 2,3)",
+                    sda,
+                    scl
+                )
+            }
+            None => {}
+        }
+    }
+
+    // GPIO liberi del connettore, comandati da /api/gpio/{pin} (vedi gpio.rs)
+    gpio::init([
+        Flex::new(p.PIN_6),
+        Flex::new(p.PIN_7),
+        Flex::new(p.PIN_8),
+        Flex::new(p.PIN_9),
+        Flex::new(p.PIN_10),
+        Flex::new(p.PIN_11),
+        Flex::new(p.PIN_12),
+        Flex::new(p.PIN_13),
+    ]);
 
-    // Fa partire la UART (lettura)
-    spawner.must_spawn(reader(uart_rx)); //<---- 3
-    panic_led_loop!(control);
+    // Uscite PWM comandate da /api/pwm/{channel} (vedi pwm.rs)
+    pwm::init([
+        Pwm::new_output_a(p.PWM_SLICE0, p.PIN_16, PwmConfig::default()),
+        Pwm::new_output_a(p.PWM_SLICE1, p.PIN_18, PwmConfig::default()),
+    ]);
 
     // Genera un random seed per il network stack
     let seed: u64 = RoscRng.next_u64();
     log::info!("Random seed value seeded to {}", seed);
+    request_id::init(seed as u32);
 
-    let ip = get_ip_address();
-    log::info!("IP address: {:?}", ip);
-    let gateway = get_gateway_address();
+    // Con USE_DHCP l'indirizzo statico resta il fallback (vedi wifi.rs)
+    let net_config = if get_use_dhcp() {
+        log::info!("IP address: DHCP");
+        embassy_net::Config::dhcpv4(Default::default())
+    } else {
+        let config = wifi::static_config();
+        log::info!("IP address: {}", config.address);
+        embassy_net::Config::ipv4_static(config)
+    };
     let (stack, runner) = embassy_net::new(
         net_device,
-        embassy_net::Config::ipv4_static(embassy_net::StaticConfigV4 {
-            address: embassy_net::Ipv4Cidr::new(
-                Ipv4Address::new(ip[0], ip[1], ip[2], ip[3]),
-                get_subnet_mask(),
-            ),
-            gateway: Some(Ipv4Address::new(
-                gateway[0], gateway[1], gateway[2], gateway[3],
-            )),
-            dns_servers: Default::default(),
-        }),
+        net_config,
         make_static!(
-            embassy_net::StackResources::<WEB_TASK_POOL_SIZE>,
+            embassy_net::StackResources::<{ ServerResources::SOCKETS }>,
             embassy_net::StackResources::new()
         ),
         seed,
     );
 
     // parte il task di gestione del network
-    spawner.must_spawn(net_task(runner)); //<---- 4
-    panic_led_loop!(control);
+    tasks::spawn(&spawner, "net", net_task(runner)); //<---- 4
+    resources::register(stack);
 
-    while let Err(err) = control
-        .join(get_ssid(), JoinOptions::new(get_wifi_password().as_bytes()))
-        .await
-    {
-        log::info!("join failed with status={}", err.status);
-        control.gpio_set(0, true).await;
-    }
+    // Definiamo un controllore comune da condividere tra i task
+    let shared_control = SharedControl(
+        make_static!(Mutex<CriticalSectionRawMutex, Control<'static>>, Mutex::new(control)),
+    );
+    resources::register(shared_control);
+
+    // Fa partire il blink del LED collegato al cyw43, che mostra anche
+    // i codici dei guasti durante la connessione
+    tasks::spawn(&spawner, "blink", blink_task_shared(shared_control)); //<---- 5
 
-    log::info!("waiting for DHCP...");
+    // La connessione (join, DHCP, riconnessioni) è gestita da wifi.rs
+    tasks::spawn(&spawner, "wifi", wifi::wifi_task(shared_control, stack));
+    // Verifica dei progressi dello stack di rete, per il watchdog (vedi net_watch.rs)
+    tasks::spawn(&spawner, "net_watch", net_watch::net_watch_task(stack));
+
+    log::info!("waiting for network...");
     stack.wait_config_up().await;
 
     // And now we can use it!
@@ -243,19 +504,76 @@ async fn main(spawner: Spawner) {
         log::info!("Assigned IP: {ip}");
     }
 
-    // Definiamo un controllore comune da condividere tra i task
-    let shared_control = SharedControl(
-        make_static!(Mutex<CriticalSectionRawMutex, Control<'static>>, Mutex::new(control)),
+    // Nome <HOSTNAME>.local e servizio HTTP annunciati con mDNS
+    let hostname = get_hostname();
+    if !hostname.trim().is_empty() {
+        tasks::spawn(
+            &spawner,
+            "mdns",
+            mdns::mdns_task(stack, shared_control, hostname),
+        );
+    }
+
+    // Invio dei log al collector TCP, se configurato
+    #[cfg(feature = "log-net")]
+    if let Some((address, port)) = get_log_collector().filter(|_| !safe_mode) {
+        tasks::spawn(
+            &spawner,
+            "log_tcp",
+            log_tcp::log_tcp_task(stack, address, port),
+        );
+    }
+
+    // Invio dei log al server syslog, se configurato
+    #[cfg(feature = "log-net")]
+    if let Some((address, port)) = get_syslog_server().filter(|_| !safe_mode) {
+        tasks::spawn(
+            &spawner,
+            "syslog",
+            syslog::syslog_task(stack, address, port, get_hostname()),
+        );
+    }
+
+    // Fa partire la console di gestione su UART0
+    tasks::spawn(
+        &spawner,
+        "console",
+        console::console_task(console_tx, console_rx),
     );
 
-    // Fa partire il blink del LED collegato al cyw43
-    spawner.must_spawn(blink_task_shared(shared_control, uart_tx)); //<---- 5
-    panic_led_loop_shared!(shared_control);
+    // Shell di gestione via telnet, se abilitata
+    if get_telnet_enabled() {
+        tasks::spawn(&spawner, "telnet", telnet::telnet_task(stack));
+    }
+
+    // Avviso via email del crash che ha preceduto l'avvio (vedi smtp.rs)
+    if crashed && get_smtp_config().is_some() {
+        tasks::spawn(&spawner, "smtp", smtp::smtp_alert_task(stack));
+    }
+
+    // Eventi e telemetria verso il broker MQTT, se configurato (vedi mqtt.rs)
+    if get_mqtt_config().is_some() && !safe_mode {
+        tasks::spawn(&spawner, "mqtt", mqtt::mqtt_task(stack));
+    }
+
+    // Lettura periodica del documento JSON configurato (vedi fetch.rs)
+    if get_fetch_url().is_some() && !safe_mode {
+        tasks::spawn(&spawner, "fetch", fetch::fetch_task(stack));
+    }
+
+    // Sincronizzazione dell'orologio con il server NTP (vedi sntp.rs),
+    // anche in modalità provvisoria: i log restano confrontabili
+    if let Some((server, interval_secs)) = get_ntp_server() {
+        tasks::spawn(
+            &spawner,
+            "sntp",
+            sntp::sntp_task(stack, server, interval_secs),
+        );
+    }
 
     // Fa partire un timer: per ora non serve a molto, se non a dimostrare
     // che il sistema è vivo.
-    spawner.must_spawn(ticker_task());
-    panic_led_loop_shared!(shared_control);
+    tasks::spawn(&spawner, "ticker", ticker_task());
 
     let app = make_static!(AppRouter<AppProps>, AppProps.build_app());
 
@@ -270,21 +588,70 @@ async fn main(spawner: Spawner) {
         .keep_connection_alive()
     );
 
-    // Fa partire i task del web server per rispondere a diverse richieste in parallelo,
-    for id in 0..WEB_TASK_POOL_SIZE - 2 {
-        unwrap!(spawner.spawn(web_task(
-            id,
-            stack,
-            app,
-            config2,
-            AppState { shared_control },
-        )));
-    }
-
+    // Fa partire i task del web server per rispondere a diverse richieste in parallelo:
+    // le connessioni vengono accettate dal primo worker libero, di qualunque gruppo
     log::info!(
-        "Web Server running on http://{}/",
-        stack.config_v4().unwrap().address.address()
+        "Web server: {} worker ({} upload, {} GET), {} byte di buffer",
+        ServerResources::WORKERS,
+        ServerResources::UPLOAD.workers,
+        ServerResources::GET.workers,
+        ServerResources::RAM_BYTES
     );
+    for id in 0..ServerResources::UPLOAD.workers {
+        tasks::spawn(
+            &spawner,
+            "web",
+            web_upload_task(
+                id,
+                stack,
+                app,
+                config2,
+                AppState::new(shared_control, ServerResources::UPLOAD.max_body()),
+            ),
+        );
+    }
+    for id in ServerResources::UPLOAD.workers..ServerResources::WORKERS {
+        tasks::spawn(
+            &spawner,
+            "web",
+            web_get_task(
+                id,
+                stack,
+                app,
+                config2,
+                AppState::new(shared_control, ServerResources::GET.max_body()),
+            ),
+        );
+    }
+    // Worker HTTPS: senza una configurazione TLS valida la porta 443 resta chiusa
+    #[cfg(feature = "https")]
+    match tls::init(embassy_rp::trng::Trng::new(
+        p.TRNG,
+        TrngIrqs,
+        embassy_rp::trng::Config::default(),
+    )) {
+        Ok(()) => tasks::spawn(
+            &spawner,
+            "web-https",
+            web_https_task(
+                ServerResources::WORKERS,
+                stack,
+                app,
+                config2,
+                AppState::new(shared_control, ServerResources::HTTPS.max_body()),
+            ),
+        ),
+        Err(e) => log::error!("HTTPS disattivato: configurazione TLS non valida: {:?}", e),
+    }
+    // Coda di riserva: 503 immediato quando tutti i worker sono occupati
+    for id in 0..ServerResources::OVERFLOW.sockets {
+        tasks::spawn(&spawner, "overflow", overload::overflow_task(id, stack));
+    }
+
+    // Il link può essere già caduto: l'indirizzo si logga solo se c'è
+    if let Some(config) = stack.config_v4() {
+        log::info!("Web Server running on http://{}/", config.address.address());
+    }
 }
 
 // Tasks that run in the background:
@@ -299,7 +666,9 @@ async fn main(spawner: Spawner) {
 async fn cyw43_task(
     runner: cyw43::Runner<'static, Output<'static>, PioSpi<'static, PIO0, 0, DMA_CH0>>,
 ) -> ! {
-    runner.run().await
+    join(runner.run(), supervisor::heartbeat(Task::Cyw43))
+        .await
+        .0
 }
 
 #[embassy_executor::task]
@@ -311,11 +680,13 @@ async fn cyw43_task(
 /// # Ritorna
 /// * ! - Non ritorna mai
 async fn net_task(mut runner: embassy_net::Runner<'static, cyw43::NetDriver<'static>>) -> ! {
-    runner.run().await
+    let run = join(runner.run(), supervisor::heartbeat(Task::Net));
+    memory::measured(Task::Net, run).await.0
 }
 
 #[embassy_executor::task]
-/// Logger task for USB
+/// Logger task for USB: installa il logger globale (vedi logging.rs)
+/// e gestisce la seriale USB.
 ///
 /// # Argomenti
 /// * `driver` - USB driver
@@ -323,7 +694,13 @@ async fn net_task(mut runner: embassy_net::Runner<'static, cyw43::NetDriver<'sta
 /// # Ritorna
 /// * ! - Non ritorna mai
 async fn logger_task(driver: Driver<'static, USB>) {
-    embassy_usb_logger::run!(1024, log::LevelFilter::Info, driver);
+    let usb = make_static!(logging::UsbLog, UsbLogger::new());
+    usb.with_handler(serial_proto::UsbCommandHandler::new());
+    let usb: &'static logging::UsbLog = usb;
+    logging::init(usb);
+
+    let mut state = LoggerState::new();
+    usb.run(&mut state, driver).await;
 }
 
 #[embassy_executor::task]
@@ -335,6 +712,7 @@ async fn ticker_task() {
     let mut ticker = Ticker::every(Duration::from_secs(ELAPSED_SECS));
     loop {
         ticker.next().await;
+        tasks::touch("ticker");
         // calcola un random number
         let random_number: u32 = RoscRng.next_u32();
 
@@ -343,49 +721,207 @@ async fn ticker_task() {
 }
 
 #[embassy_executor::task]
-/// UART reader task
+/// UART reader task: suddivide i byte ricevuti in righe terminate da newline
+/// e le esegue come comandi (vedi `commands`), inoltrando anche le
+/// notifiche URC ricavate dagli eventi del bus. Le righe troppo lunghe vengono scartate e gli errori di
+/// ricezione (overrun, framing) vengono loggati e conteggiati senza interrompere il task
+/// (vedi errors.rs); dopo una raffica di errori la lettura viene sospesa per un attimo.
+/// Un break esegue l'azione configurata con `UART_BREAK_ACTION`.
 ///
 /// # Argomenti
-/// * `rx` - UART receiver
+/// * `rx` - UART receiver bufferizzato
+/// * `tx` - UART transmitter bufferizzato, condiviso tramite il registro delle risorse
 ///
 /// # Ritorna
 /// * ! - Non ritorna mai
-async fn reader(mut rx: UartRx<'static, Async>) {
-    info!("Reading...");
-    loop {
-        // read a total of 4 transmissions (32 / 8) and then print the result
-        let mut buf = [0; 32];
-        rx.read(&mut buf).await.unwrap();
-        info!("RX {:?}", buf);
-    }
+async fn reader(mut rx: BufferedUartRx, tx: SharedUartTx) {
+    memory::measured(Task::Uart, async move {
+        log::info!("Reading...");
+        let mut framer = LineFramer::<UART_LINE_CAPACITY>::new();
+        let mut urc = bus::subscribe();
+        let mut rx_errors = 0;
+        loop {
+            supervisor::check_in(Task::Uart);
+            let mut buf = [0; 32];
+            // La lettura viene interrotta periodicamente per il check-in:
+            // i byte restano nel ring buffer della BufferedUart
+            let read = select(
+                embedded_io_async::Read::read(&mut rx, &mut buf),
+                Timer::after(supervisor::HEARTBEAT_PERIOD),
+            );
+            let read = async {
+                match read.await {
+                    Either::First(result) => Some(result),
+                    Either::Second(()) => None,
+                }
+            };
+            let n = match urc.as_mut() {
+                Some(urc) => match select(read, urc.next_message_pure()).await {
+                    Either::First(result) => result,
+                    Either::Second(event) => {
+                        if let Some(line) = serial_proto::urc(&event) {
+                            serial_proto::LineSink::write_line(&mut *tx.0.lock().await, &line)
+                                .await;
+                        }
+                        continue;
+                    }
+                },
+                None => read.await,
+            };
+            let Some(n) = n else {
+                continue;
+            };
+            let n = match n {
+                Ok(n) => n,
+                Err(embassy_rp::uart::Error::Break) => {
+                    log::warn!("UART1: break ricevuto");
+                    framer.reset();
+                    system::on_break(get_uart_break_action()).await;
+                    continue;
+                }
+                Err(e) => {
+                    let count = errors::report(ErrorKind::UartRx);
+                    log::warn!(
+                        "UART1: errore di ricezione {:?}, riga scartata ({} errori)",
+                        e,
+                        count
+                    );
+                    framer.reset();
+                    // Errori a raffica (es. linea scollegata o baud rate errato):
+                    // la lettura viene sospesa per non saturare log e CPU
+                    rx_errors += 1;
+                    if rx_errors >= UART_ERROR_BURST {
+                        log::warn!("UART1: {} errori consecutivi, lettura sospesa", rx_errors);
+                        rx_errors = 0;
+                        Timer::after(UART_ERROR_BACKOFF).await;
+                    }
+                    continue;
+                }
+            };
+            rx_errors = 0;
+            // Copia per il terminale web (vedi uart_bridge.rs)
+            uart_bridge::record(&buf[..n]);
+
+            for &byte in &buf[..n] {
+                match framer.push(byte) {
+                    Some(Frame::Line(line)) => match core::str::from_utf8(line) {
+                        Ok(text) => {
+                            let mut tx = tx.0.lock().await;
+                            commands::handle_line(text, SolveSource::Serial, &mut *tx).await
+                        }
+                        Err(_) => log::info!("RX: {line:?}"),
+                    },
+                    Some(Frame::Overflow(len)) => {
+                        log::warn!("UART1: riga di {len} byte scartata (max {UART_LINE_CAPACITY})")
+                    }
+                    None => {}
+                }
+            }
+        }
+    })
+    .await
 }
 
 #[embassy_executor::task]
-/// Blink task che toggla il LED collegato al chip WiFi CYW43
+/// Blink task che ripete sul LED collegato al chip WiFi CYW43 il pattern
+/// dello stato del sistema (vedi status_led.rs), oppure il codice del
+/// guasto attivo (vedi fault.rs). Con il comando `led on|off|blink` (o con
+/// `/api/led`) il LED resta fisso o lampeggia finché non torna in `auto`.
 ///
 /// # Argomenti
 /// * `shared_control` - Controller condiviso per il WiFi
-/// * `uart_tx` - UART transmitter
 ///
 /// # Ritorna
 /// * ! - Non ritorna mai
-async fn blink_task_shared(shared_control: SharedControl, mut uart_tx: UartTx<'static, Async>) {
-    let delay = Duration::from_millis(250);
+async fn blink_task_shared(mut shared_control: SharedControl) {
+    memory::measured(Task::Blink, async move {
+        let mut events = bus::subscribe();
+        loop {
+            // Il LED passa dal chip CYW43: il check-in verifica anche il suo runner
+            supervisor::check_in(Task::Blink);
+            let state = status_led::system_state();
+            if let SystemState::Error(fault) = state {
+                fault::blink_code(&mut shared_control, fault).await;
+                continue;
+            }
+            let mode = fault::led_mode();
+            let period = fault::blink_period();
+            let blink = [(true, period), (false, period)];
+            let steps: &[(bool, Duration)] = match mode {
+                LedMode::On => &[(true, fault::SLOW_BLINK)],
+                LedMode::Off => &[(false, fault::SLOW_BLINK)],
+                LedMode::Blink => &blink,
+                LedMode::Auto => state.pattern(),
+            };
+            for &(on, duration) in steps {
+                shared_control.set_led(on).await;
+                let deadline = Instant::now() + duration;
+                if led_changed(&mut events, state, mode, deadline).await {
+                    break;
+                }
+            }
+        }
+    })
+    .await
+}
+
+/// Attende fino alla scadenza indicata, interrompendo l'attesa se un evento
+/// del bus cambia lo stato del sistema o la modalità del LED
+///
+/// # Argomenti
+/// * `events` - Sottoscrizione al bus (se None si attende la scadenza)
+/// * `state` - Stato mostrato dal LED
+/// * `mode` - Modalità del LED in uso
+/// * `deadline` - Istante di scadenza
+///
+/// # Ritorna
+/// * bool - true se lo stato o la modalità sono cambiati
+async fn led_changed(
+    events: &mut Option<bus::EventSubscriber>,
+    state: SystemState,
+    mode: LedMode,
+    deadline: Instant,
+) -> bool {
+    let Some(events) = events.as_mut() else {
+        Timer::at(deadline).await;
+        return false;
+    };
     loop {
-        let msg = "Led on!\r\n".as_bytes();
-        shared_control.0.lock().await.gpio_set(0, true).await;
-        uart_tx.write(msg).await.unwrap();
-        Timer::after(delay).await;
-
-        let msg = "Led off!\r\n".as_bytes();
-        shared_control.0.lock().await.gpio_set(0, false).await;
-        uart_tx.write(msg).await.unwrap();
-        Timer::after(delay).await;
+        match select(Timer::at(deadline), events.next_message_pure()).await {
+            Either::First(()) => return false,
+            Either::Second(_) => {
+                if status_led::system_state() != state || fault::led_mode() != mode {
+                    return true;
+                }
+            }
+        }
     }
 }
 
-#[embassy_executor::task(pool_size = WEB_TASK_POOL_SIZE)]
-/// Web server task che risponde alle richieste HTTP
+#[embassy_executor::task(pool_size = ServerResources::UPLOAD.workers)]
+/// Web server task con i buffer grandi (vedi `ServerResources::UPLOAD`)
+///
+/// # Argomenti
+///
+/// * `id` - ID del task
+/// * `stack` - Stack di rete
+/// * `app` - Router dell'applicazione
+/// * `config` - Configurazione del server
+/// * `state` - Stato dell'applicazione
+async fn web_upload_task(
+    id: usize,
+    stack: embassy_net::Stack<'static>,
+    app: &'static AppRouter<AppProps>,
+    config: &'static picoserve::Config<Duration>,
+    state: AppState,
+) -> ! {
+    const POOL: WorkerPool = ServerResources::UPLOAD;
+    web_worker::<{ POOL.tcp_rx }, { POOL.tcp_tx }, { POOL.http }>(id, 80, stack, app, config, state)
+        .await
+}
+
+#[embassy_executor::task(pool_size = ServerResources::GET.workers)]
+/// Web server task con i buffer piccoli (vedi `ServerResources::GET`)
 ///
 /// # Argomenti
 ///
@@ -394,28 +930,189 @@ async fn blink_task_shared(shared_control: SharedControl, mut uart_tx: UartTx<'s
 /// * `app` - Router dell'applicazione
 /// * `config` - Configurazione del server
 /// * `state` - Stato dell'applicazione
-async fn web_task(
+async fn web_get_task(
     id: usize,
     stack: embassy_net::Stack<'static>,
     app: &'static AppRouter<AppProps>,
     config: &'static picoserve::Config<Duration>,
     state: AppState,
 ) -> ! {
-    let port = 80;
-    let mut tcp_rx_buffer = [0; 1024];
-    let mut tcp_tx_buffer = [0; 1024];
-    let mut http_buffer = [0; 2048];
+    const POOL: WorkerPool = ServerResources::GET;
+    web_worker::<{ POOL.tcp_rx }, { POOL.tcp_tx }, { POOL.http }>(id, 80, stack, app, config, state)
+        .await
+}
 
-    picoserve::listen_and_serve_with_state(
+#[cfg(feature = "https")]
+#[embassy_executor::task(pool_size = ServerResources::HTTPS.workers)]
+/// Web server task HTTPS sulla porta 443 (vedi `ServerResources::HTTPS` e tls.rs)
+///
+/// # Argomenti
+///
+/// * `id` - ID del task
+/// * `stack` - Stack di rete
+/// * `app` - Router dell'applicazione
+/// * `config` - Configurazione del server
+/// * `state` - Stato dell'applicazione
+async fn web_https_task(
+    id: usize,
+    stack: embassy_net::Stack<'static>,
+    app: &'static AppRouter<AppProps>,
+    config: &'static picoserve::Config<Duration>,
+    state: AppState,
+) -> ! {
+    const POOL: WorkerPool = ServerResources::HTTPS;
+    web_worker::<{ POOL.tcp_rx }, { POOL.tcp_tx }, { POOL.http }>(
         id,
+        tls::HTTPS_PORT,
+        stack,
         app,
         config,
-        stack,
-        port,
-        &mut tcp_rx_buffer,
-        &mut tcp_tx_buffer,
-        &mut http_buffer,
-        &state,
+        state,
     )
     .await
 }
+
+/// Worker del web server che risponde alle richieste HTTP
+///
+/// # Argomenti
+///
+/// * `id` - ID del task
+/// * `port` - Porta TCP: 80, oppure la porta HTTPS con la feature `https`
+/// * `stack` - Stack di rete
+/// * `app` - Router dell'applicazione
+/// * `config` - Configurazione del server
+/// * `state` - Stato dell'applicazione
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+async fn web_worker<const TCP_RX: usize, const TCP_TX: usize, const HTTP: usize>(
+    id: usize,
+    port: u16,
+    stack: embassy_net::Stack<'static>,
+    app: &'static AppRouter<AppProps>,
+    config: &'static picoserve::Config<Duration>,
+    state: AppState,
+) -> ! {
+    let mut tcp_rx_buffer = [0; TCP_RX];
+    let mut tcp_tx_buffer = [0; TCP_TX];
+    let mut http_buffer = [0; HTTP];
+
+    // Come picoserve::listen_and_serve_with_state, ma con le statistiche
+    // di ogni connessione (vedi http_stats.rs)
+    let serve = async {
+        loop {
+            // Durante lo svuotamento (vedi drain.rs) solo il worker riservato
+            // accetta nuove connessioni, per la pagina di manutenzione
+            if drain::is_draining() && id != drain::MAINTENANCE_WORKER {
+                core::future::pending::<()>().await;
+            }
+            let mut socket = TcpSocket::new(stack, &mut tcp_rx_buffer, &mut tcp_tx_buffer);
+            // Mentre il worker è in attesa la coda di riserva non accetta
+            // connessioni (vedi overload.rs); la coda serve solo la porta 80
+            let idle = (port == 80).then(overload::Idle::enter);
+            http_stats::mark_listening();
+            let accepted = if id == drain::MAINTENANCE_WORKER {
+                socket.accept(port).await
+            } else {
+                match select(socket.accept(port), drain::started()).await {
+                    Either::First(accepted) => accepted,
+                    Either::Second(()) => continue,
+                }
+            };
+            drop(idle);
+            if let Err(e) = accepted {
+                log::warn!("web {}: accept fallita: {:?}", id, e);
+                continue;
+            }
+            if drain::is_draining() {
+                drain::serve_maintenance_page(&mut socket).await;
+                continue;
+            }
+            // Liste di accesso: i client respinti non arrivano a picoserve
+            let remote = socket.remote_endpoint().map(|e| e.addr);
+            let access = acl::classify(remote);
+            if access == acl::Access::Denied {
+                log::warn!(
+                    "web {}: {:?} respinto (ACL_DENY)",
+                    id,
+                    socket.remote_endpoint()
+                );
+                socket.abort();
+                let _ = socket.flush().await;
+                continue;
+            }
+            // Limiti per client: oltre i limiti risponde 429 senza picoserve
+            let _admitted = match rate_limit::admit(remote) {
+                Ok(admitted) => admitted,
+                Err(limited) => {
+                    log::warn!("web {}: {:?} oltre i limiti, risposta 429", id, remote);
+                    overload::reject(
+                        &mut socket,
+                        "429 Too Many Requests",
+                        limited.retry_after_secs,
+                        "text/plain; charset=utf-8",
+                        i18n::Lang::configured().strings().too_many_requests,
+                    )
+                    .await;
+                    continue;
+                }
+            };
+            state.access.set(access);
+            let _in_flight = drain::InFlight::enter();
+            let connection = http_stats::Connection::open(&socket);
+            let socket = http_stats::CountingSocket::new(socket, &connection);
+            // Sulla porta HTTPS picoserve legge e scrive attraverso la sessione TLS
+            #[cfg(feature = "https")]
+            if port == tls::HTTPS_PORT {
+                let handshake = tls::TlsSocket::accept(socket);
+                let socket =
+                    match embassy_time::with_timeout(tls::HANDSHAKE_TIMEOUT, handshake).await {
+                        Ok(Ok(socket)) => socket,
+                        Ok(Err(e)) => {
+                            log::warn!("web {}: handshake TLS fallito: {:?}", id, e);
+                            connection.close(None);
+                            continue;
+                        }
+                        Err(_) => {
+                            log::warn!("web {}: handshake TLS scaduto", id);
+                            connection.close(None);
+                            continue;
+                        }
+                    };
+                let served = picoserve::serve_with_state(
+                    app,
+                    picoserve::EmbassyTimer,
+                    config,
+                    &mut http_buffer,
+                    socket,
+                    &state,
+                )
+                .await;
+                if let Err(e) = &served {
+                    log::warn!(
+                        "web {}: connessione HTTPS terminata con errore: {:?}",
+                        id,
+                        e
+                    );
+                }
+                connection.close(served.ok());
+                continue;
+            }
+            let served = picoserve::serve_with_state(
+                app,
+                picoserve::EmbassyTimer,
+                config,
+                &mut http_buffer,
+                socket,
+                &state,
+            )
+            .await;
+            if let Err(e) = &served {
+                log::warn!("web {}: connessione terminata con errore: {:?}", id, e);
+            }
+            connection.close(served.ok());
+        }
+    };
+    let run = join(serve, supervisor::heartbeat(Task::Web));
+    memory::measured(Task::Web, run).await.0
+}
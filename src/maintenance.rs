@@ -0,0 +1,136 @@
+//! maintenance.rs - Riavvio periodico di manutenzione.
+//!
+//! Con `MAINTENANCE_REBOOT` in configurazione (es. `sun 04:00` per ogni
+//! domenica alle 4, oppure `daily 04:00`) il task [`maintenance_task`]
+//! riavvia la pico all'ora indicata, nel fuso di `UTC_OFFSET_MINUTES`.
+//! L'orario viene controllato solo con l'orologio sincronizzato (vedi
+//! clock.rs) e mai nella prima ora dopo l'avvio, così un riavvio non si
+//! ripete nello stesso minuto.
+//!
+//! Prima del reset (forzato tramite il watchdog, vedi system.rs) il web
+//! server viene svuotato (vedi drain.rs), i contatori persistenti vengono
+//! salvati in flash e i log hanno il tempo di essere inviati alle loro
+//! destinazioni.
+
+use crate::clock;
+use crate::utility::{get_maintenance_reboot, get_utc_offset_minutes};
+use embassy_time::{Duration, Instant, Ticker};
+
+/// Periodo del controllo dell'orario
+const CHECK_PERIOD: Duration = Duration::from_secs(20);
+
+/// Uptime minimo prima di un riavvio di manutenzione
+const MIN_UPTIME: Duration = Duration::from_secs(3600);
+
+/// Minuti in un giorno
+const MINUTES_PER_DAY: u64 = 24 * 60;
+
+/// Nomi dei giorni accettati in configurazione (0 = lunedì)
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// Orario del riavvio di manutenzione
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RebootSchedule {
+    /// Giorno della settimana (0 = lunedì), None per tutti i giorni
+    pub weekday: Option<u8>,
+    /// Minuti dalla mezzanotte (ora locale)
+    pub minute_of_day: u16,
+}
+
+impl RebootSchedule {
+    /// Interpreta un orario scritto come "sun 04:00" o "daily 04:00"
+    ///
+    /// # Argomenti
+    /// * `value` - Valore di configurazione
+    ///
+    /// # Ritorna
+    /// * Option<RebootSchedule> - Orario, None se vuoto o non valido
+    pub fn parse(value: &str) -> Option<Self> {
+        let (day, time) = value.trim().split_once(' ')?;
+        let weekday = match day {
+            "daily" => None,
+            day => Some(WEEKDAYS.iter().position(|&d| d == day)? as u8),
+        };
+        let (hours, minutes) = time.trim().split_once(':')?;
+        let (hours, minutes) = (hours.parse::<u16>().ok()?, minutes.parse::<u16>().ok()?);
+        (hours < 24 && minutes < 60).then_some(Self {
+            weekday,
+            minute_of_day: hours * 60 + minutes,
+        })
+    }
+
+    /// Ritorna il primo minuto del riavvio successivo a quello indicato
+    ///
+    /// # Argomenti
+    /// * `after` - Minuti dal 1970-01-01T00:00 nell'ora locale
+    ///
+    /// # Ritorna
+    /// * Option<u64> - Minuto del riavvio nell'ora locale
+    fn next_after(self, after: u64) -> Option<u64> {
+        let first_day = after / MINUTES_PER_DAY;
+        // Entro otto giorni cade sempre il giorno della settimana cercato
+        (first_day..first_day + 8)
+            .map(|day| day * MINUTES_PER_DAY + self.minute_of_day as u64)
+            .find(|&minutes| minutes > after && self.matches(minutes))
+    }
+
+    /// Ritorna true se l'istante indicato cade nel minuto del riavvio
+    ///
+    /// # Argomenti
+    /// * `local_minutes` - Minuti dal 1970-01-01T00:00 nell'ora locale
+    fn matches(self, local_minutes: u64) -> bool {
+        let days = local_minutes / MINUTES_PER_DAY;
+        // Il 1970-01-01 era un giovedì
+        let weekday = ((days + 3) % 7) as u8;
+        local_minutes % MINUTES_PER_DAY == self.minute_of_day as u64
+            && self.weekday.is_none_or(|day| day == weekday)
+    }
+}
+
+/// Ritorna i minuti dal 1970-01-01T00:00 nell'ora locale, se l'orologio è sincronizzato
+fn local_minutes() -> Option<u64> {
+    let utc_minutes = clock::now_unix_ms()? / 60_000;
+    utc_minutes.checked_add_signed(get_utc_offset_minutes() as i64)
+}
+
+/// Ritorna l'istante del prossimo riavvio di manutenzione
+///
+/// # Ritorna
+/// * Option<u64> - Millisecondi Unix, None se non configurato o con l'orologio non sincronizzato
+pub fn next_reboot_unix_ms() -> Option<u64> {
+    let schedule = get_maintenance_reboot()?;
+    let now = local_minutes()?;
+    // Nella prima ora dopo l'avvio il riavvio non viene eseguito
+    let warmup = MIN_UPTIME
+        .as_millis()
+        .saturating_sub(Instant::now().as_millis());
+    let after = now + warmup.div_ceil(60_000).saturating_sub(1);
+    let local = schedule.next_after(after)?;
+    let utc = local.checked_add_signed(-(get_utc_offset_minutes() as i64))?;
+    Some(utc * 60_000)
+}
+
+#[embassy_executor::task]
+/// Task che esegue il riavvio di manutenzione all'orario configurato
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn maintenance_task() {
+    let mut ticker = Ticker::every(CHECK_PERIOD);
+    loop {
+        ticker.next().await;
+        crate::tasks::touch("maintenance");
+        // La configurazione è riletta a ogni controllo: modificabile a runtime
+        let Some(schedule) = get_maintenance_reboot() else {
+            continue;
+        };
+        if Instant::now().as_millis() < MIN_UPTIME.as_millis() {
+            continue;
+        }
+        if local_minutes().is_some_and(|minutes| schedule.matches(minutes)) {
+            log::warn!("Riavvio di manutenzione programmato ({:?})", schedule);
+            // Il riavvio avviene quando le richieste HTTP in corso sono concluse
+            crate::drain::request(crate::drain::DrainAction::MaintenanceReboot);
+        }
+    }
+}
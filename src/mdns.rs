@@ -0,0 +1,368 @@
+//! mdns.rs - Responder mDNS e DNS-SD.
+//!
+//! [`mdns_task`] ascolta sul gruppo multicast 224.0.0.251, porta 5353, e
+//! risponde alle domande sui nomi della pico, così la pagina del Sudoku è
+//! raggiungibile come `http://<HOSTNAME>.local` senza ricordare l'IP:
+//! * `<HOSTNAME>.local` (A) - indirizzo IPv4 corrente;
+//! * `_http._tcp.local` (PTR) - il servizio HTTP della pico, per i browser
+//!   di servizi (es. `avahi-browse -r _http._tcp`, `dns-sd -B _http._tcp`);
+//! * `<HOSTNAME>._http._tcp.local` (SRV, TXT) - porta 80 e percorso `/`;
+//! * `_services._dns-sd._udp.local` (PTR) - elenco dei tipi di servizio.
+//!
+//! All'avvio il task annuncia i record due volte senza attendere domande.
+//! Le risposte non usano la compressione dei nomi: per un solo host con un
+//! solo servizio entrano comunque in un pacchetto. Con `HOSTNAME` vuoto il
+//! task non parte.
+
+use crate::SharedControl;
+use crate::utility::ConfigValue;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{IpEndpoint, Ipv4Address, Stack};
+use embassy_time::Timer;
+
+/// Porta e gruppo multicast di mDNS
+const MDNS_PORT: u16 = 5353;
+const MDNS_GROUP: Ipv4Address = Ipv4Address::new(224, 0, 0, 251);
+
+/// Indirizzo MAC del gruppo multicast, da abilitare nel filtro del CYW43
+const MDNS_MAC: [u8; 6] = [0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb];
+
+/// Porta del servizio HTTP annunciato
+const HTTP_PORT: u16 = 80;
+
+/// Durata di validità dei record nelle cache dei client
+const TTL_SECS: u32 = 120;
+
+/// Dimensione massima di un pacchetto
+const PACKET_CAPACITY: usize = 512;
+
+/// Lunghezza massima di un nome in una domanda
+const NAME_CAPACITY: usize = 128;
+
+/// Tipo di servizio annunciato
+const SERVICE: &str = "_http._tcp.local";
+
+/// Nome della domanda che elenca i tipi di servizio
+const SERVICES: &str = "_services._dns-sd._udp.local";
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Bit di classe dei record unici: i client sostituiscono quelli in cache
+const CACHE_FLUSH: u16 = 0x8000;
+
+// Record da inserire in una risposta, come maschera di bit
+const ANSWER_A: u8 = 1;
+const ANSWER_PTR: u8 = 2;
+const ANSWER_SRV: u8 = 4;
+const ANSWER_TXT: u8 = 8;
+const ANSWER_SERVICES: u8 = 16;
+const ANSWER_ALL: u8 = ANSWER_A | ANSWER_PTR | ANSWER_SRV | ANSWER_TXT;
+
+/// Pacchetto DNS in costruzione; None dalle scritture se non c'è spazio
+struct Packet {
+    buf: [u8; PACKET_CAPACITY],
+    len: usize,
+}
+
+impl Packet {
+    fn new() -> Self {
+        Self {
+            buf: [0; PACKET_CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> Option<()> {
+        let end = self.len + bytes.len();
+        self.buf.get_mut(self.len..end)?.copy_from_slice(bytes);
+        self.len = end;
+        Some(())
+    }
+
+    fn put_u16(&mut self, value: u16) -> Option<()> {
+        self.put(&value.to_be_bytes())
+    }
+
+    /// Scrive un nome come sequenza di etichette, senza compressione
+    fn put_name(&mut self, labels: &[&str]) -> Option<()> {
+        for label in labels.iter().flat_map(|l| l.split('.')) {
+            self.put(&[u8::try_from(label.len()).ok().filter(|&n| n <= 63)?])?;
+            self.put(label.as_bytes())?;
+        }
+        self.put(&[0])
+    }
+
+    /// Scrive un record: nome, tipo, classe, TTL e dati scritti da `rdata`
+    fn put_record(
+        &mut self,
+        name: &[&str],
+        kind: u16,
+        class: u16,
+        rdata: impl FnOnce(&mut Self) -> Option<()>,
+    ) -> Option<()> {
+        self.put_name(name)?;
+        self.put_u16(kind)?;
+        self.put_u16(class)?;
+        self.put(&TTL_SECS.to_be_bytes())?;
+        // La lunghezza dei dati si conosce solo dopo averli scritti
+        let length_at = self.len;
+        self.put_u16(0)?;
+        rdata(self)?;
+        let length = (self.len - length_at - 2) as u16;
+        self.buf[length_at..length_at + 2].copy_from_slice(&length.to_be_bytes());
+        Some(())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// Legge un nome da un pacchetto, seguendo i puntatori di compressione
+///
+/// # Argomenti
+/// * `packet` - Pacchetto ricevuto
+/// * `pos` - Inizio del nome
+/// * `out` - Nome letto, con le etichette separate da punti
+///
+/// # Ritorna
+/// * Option<usize> - Posizione dopo il nome, None se il nome non è valido
+fn read_name(
+    packet: &[u8],
+    mut pos: usize,
+    out: &mut heapless::String<NAME_CAPACITY>,
+) -> Option<usize> {
+    out.clear();
+    let mut end = None;
+    // Limite ai salti, contro i puntatori circolari
+    for _ in 0..NAME_CAPACITY {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => return Some(end.unwrap_or(pos + 1)),
+            l if l & 0xC0 == 0xC0 => {
+                let target = ((l & 0x3F) << 8) | *packet.get(pos + 1)? as usize;
+                if end.is_none() {
+                    end = Some(pos + 2);
+                }
+                pos = target;
+            }
+            l if l <= 63 => {
+                let label = core::str::from_utf8(packet.get(pos + 1..pos + 1 + l)?).ok()?;
+                if !out.is_empty() {
+                    out.push('.').ok()?;
+                }
+                out.push_str(label).ok()?;
+                pos += 1 + l;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *packet.get(pos)?,
+        *packet.get(pos + 1)?,
+    ]))
+}
+
+/// Nomi della pico annunciati
+struct Names<'a> {
+    host: &'a str,
+}
+
+impl Names<'_> {
+    fn is_host(&self, name: &str) -> bool {
+        name.strip_suffix(".local")
+            .is_some_and(|host| host.eq_ignore_ascii_case(self.host))
+    }
+
+    fn is_instance(&self, name: &str) -> bool {
+        name.split_once('.').is_some_and(|(instance, service)| {
+            instance.eq_ignore_ascii_case(self.host) && service.eq_ignore_ascii_case(SERVICE)
+        })
+    }
+}
+
+/// Record da inviare in risposta alle domande di una query
+///
+/// # Argomenti
+/// * `query` - Pacchetto ricevuto
+/// * `names` - Nomi della pico
+///
+/// # Ritorna
+/// * u8 - Maschera dei record (ANSWER_*), 0 se nessuna domanda riguarda la pico
+fn answers(query: &[u8], names: &Names) -> u8 {
+    let (Some(flags), Some(questions)) = (read_u16(query, 2), read_u16(query, 4)) else {
+        return 0;
+    };
+    // Solo query standard (QR = 0, opcode 0)
+    if flags & 0xF800 != 0 {
+        return 0;
+    }
+    let mut name = heapless::String::new();
+    let mut pos = 12;
+    let mut mask = 0;
+    for _ in 0..questions {
+        let Some(next) = read_name(query, pos, &mut name) else {
+            break;
+        };
+        let Some(kind) = read_u16(query, next) else {
+            break;
+        };
+        pos = next + 4;
+        let any = kind == TYPE_ANY;
+        if names.is_host(&name) && (any || kind == TYPE_A) {
+            mask |= ANSWER_A;
+        } else if name.eq_ignore_ascii_case(SERVICE) && (any || kind == TYPE_PTR) {
+            // Il browser di servizi riceve subito anche porta e indirizzo
+            mask |= ANSWER_ALL;
+        } else if names.is_instance(&name) {
+            if any || kind == TYPE_SRV {
+                mask |= ANSWER_SRV | ANSWER_A;
+            }
+            if any || kind == TYPE_TXT {
+                mask |= ANSWER_TXT;
+            }
+        } else if name.eq_ignore_ascii_case(SERVICES) && (any || kind == TYPE_PTR) {
+            mask |= ANSWER_SERVICES;
+        }
+    }
+    mask
+}
+
+/// Costruisce una risposta
+///
+/// # Argomenti
+/// * `id` - ID della query (0 per le risposte multicast)
+/// * `mask` - Record da inserire (ANSWER_*)
+/// * `host` - Nome della pico, senza `.local`
+/// * `address` - Indirizzo IPv4 corrente
+///
+/// # Ritorna
+/// * Option<Packet> - Risposta, None se non entra nel pacchetto
+fn response(id: u16, mask: u8, host: &str, address: Ipv4Address) -> Option<Packet> {
+    let mut packet = Packet::new();
+    packet.put_u16(id)?;
+    // Risposta autorevole
+    packet.put_u16(0x8400)?;
+    packet.put_u16(0)?;
+    packet.put_u16(mask.count_ones() as u16)?;
+    packet.put_u16(0)?;
+    packet.put_u16(0)?;
+    let host_name = [host, "local"];
+    let instance = [host, SERVICE];
+    let unique = CLASS_IN | CACHE_FLUSH;
+    if mask & ANSWER_A != 0 {
+        packet.put_record(&host_name, TYPE_A, unique, |p| p.put(&address.octets()))?;
+    }
+    if mask & ANSWER_PTR != 0 {
+        packet.put_record(&[SERVICE], TYPE_PTR, CLASS_IN, |p| p.put_name(&instance))?;
+    }
+    if mask & ANSWER_SRV != 0 {
+        packet.put_record(&instance, TYPE_SRV, unique, |p| {
+            // Priorità e peso, poi porta e host
+            p.put_u16(0)?;
+            p.put_u16(0)?;
+            p.put_u16(HTTP_PORT)?;
+            p.put_name(&host_name)
+        })?;
+    }
+    if mask & ANSWER_TXT != 0 {
+        packet.put_record(&instance, TYPE_TXT, unique, |p| {
+            p.put(&[6])?;
+            p.put(b"path=/")
+        })?;
+    }
+    if mask & ANSWER_SERVICES != 0 {
+        packet.put_record(&[SERVICES], TYPE_PTR, CLASS_IN, |p| p.put_name(&[SERVICE]))?;
+    }
+    Some(packet)
+}
+
+#[embassy_executor::task]
+/// Task che annuncia la pico con mDNS e risponde alle domande sui suoi nomi
+///
+/// # Argomenti
+/// * `stack` - Stack di rete, già configurato
+/// * `control` - Controller del CYW43, per abilitare il gruppo multicast
+/// * `hostname` - Nome della pico, senza `.local`
+///
+/// # Ritorna
+/// * () - Solo se il socket non può essere aperto
+pub async fn mdns_task(stack: Stack<'static>, control: SharedControl, hostname: ConfigValue) {
+    // Il CYW43 scarta i pacchetti multicast non abilitati nel suo filtro
+    if let Err(e) = control.0.lock().await.add_multicast_address(MDNS_MAC).await {
+        log::warn!("mDNS: filtro multicast del CYW43 non aggiornato: {:?}", e);
+    }
+    if let Err(e) = stack.join_multicast_group(MDNS_GROUP) {
+        log::error!("mDNS: ingresso nel gruppo multicast fallito: {:?}", e);
+        return;
+    }
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; PACKET_CAPACITY];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0; PACKET_CAPACITY];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    if let Err(e) = socket.bind(MDNS_PORT) {
+        log::error!("mDNS: bind della porta {} fallito: {:?}", MDNS_PORT, e);
+        return;
+    }
+    let group = IpEndpoint::new(MDNS_GROUP.into(), MDNS_PORT);
+    let host = hostname.trim();
+    let names = Names { host };
+    let address = || stack.config_v4().map(|c| c.address.address());
+
+    // Annuncio iniziale, ripetuto come chiede la RFC 6762
+    for _ in 0..2 {
+        if let Some(packet) = address().and_then(|a| response(0, ANSWER_ALL, host, a)) {
+            let _ = socket.send_to(packet.as_bytes(), group).await;
+        }
+        Timer::after_secs(1).await;
+    }
+    log::info!("mDNS: annunciato come {}.local", host);
+
+    let mut query = [0; PACKET_CAPACITY];
+    loop {
+        let (len, meta) = match socket.recv_from(&mut query).await {
+            Ok(received) => received,
+            Err(e) => {
+                log::warn!("mDNS: ricezione fallita: {:?}", e);
+                continue;
+            }
+        };
+        crate::tasks::touch("mdns");
+        let query = &query[..len];
+        let mask = answers(query, &names);
+        let (Some(address), true) = (address(), mask != 0) else {
+            continue;
+        };
+        // Le query "legacy" (porta diversa da 5353) ricevono una risposta
+        // unicast con il loro ID
+        let (id, destination) = if meta.endpoint.port == MDNS_PORT {
+            (0, group)
+        } else {
+            (read_u16(query, 0).unwrap_or(0), meta.endpoint)
+        };
+        match response(id, mask, host, address) {
+            Some(packet) => {
+                if let Err(e) = socket.send_to(packet.as_bytes(), destination).await {
+                    log::warn!("mDNS: invio fallito: {:?}", e);
+                }
+            }
+            None => log::warn!("mDNS: risposta oltre {} byte", PACKET_CAPACITY),
+        }
+    }
+}
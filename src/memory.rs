@@ -0,0 +1,235 @@
+//! memory.rs - Misura dell'uso della RAM: stack e memoria statica.
+//!
+//! Con embassy tutti i task girano sullo stesso stack (MSP): lo stato di
+//! ogni task (compresi i buffer di `web_task`) vive nella memoria statica,
+//! mentre lo stack viene usato solo durante il poll. Per questo:
+//! * all'avvio la parte libera dello stack viene "dipinta" con un valore noto
+//!   e [`memory_task`] cerca periodicamente il punto più basso raggiunto;
+//! * i task supervisionati vengono avvolti in [`measured`], che dipinge una
+//!   finestra sotto lo stack pointer prima di ogni poll e misura quanta ne
+//!   viene usata, per stimare il picco di stack di ogni task.
+//!
+//! I risultati, insieme alle dimensioni delle sezioni `.data`, `.bss` e
+//! `.uninit` e alle statistiche dello heap (feature `heap`), sono
+//! disponibili su `/api/memory`.
+
+use crate::supervisor::{TASK_COUNT, TASKS, Task};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use embassy_time::{Duration, Ticker};
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Valore con cui viene dipinto lo stack libero
+const STACK_PAINT: u32 = 0xDEAD_BEEF;
+
+/// Margine lasciato sotto lo stack pointer durante la pittura iniziale
+const PAINT_MARGIN: usize = 64;
+
+/// Finestra dipinta sotto lo stack pointer prima di ogni poll di un task misurato.
+/// Un picco pari alla finestra indica che il task potrebbe usarne di più.
+const PROBE_WINDOW: usize = 4096;
+
+/// Sotto questo margine residuo di stack viene loggato un avviso
+const HEADROOM_WARNING: usize = 4096;
+
+/// Periodo della scansione dello stack
+const SCAN_PERIOD_SECS: u64 = 10;
+
+unsafe extern "C" {
+    // Simboli definiti dal linker script di cortex-m-rt e da memory.x
+    static __sdata: u32;
+    static __edata: u32;
+    static __sbss: u32;
+    static __ebss: u32;
+    static __suninit: u32;
+    static __euninit: u32;
+    static __sheap: u32;
+    static _stack_start: u32;
+    static _panic_dump_end: u32;
+}
+
+/// Picco di stack misurato per ogni task (byte)
+static TASK_PEAK: [AtomicU32; TASK_COUNT] = [const { AtomicU32::new(0) }; TASK_COUNT];
+
+/// Indirizzo di un simbolo del linker
+macro_rules! symbol_addr {
+    ($symbol:ident) => {
+        unsafe { core::ptr::addr_of!($symbol) as usize }
+    };
+}
+
+/// Limite inferiore dello stack. La regione del panic dump (vedi memory.x)
+/// cade all'interno della RAM e non va sovrascritta, per non perdere il
+/// messaggio salvato da panic_persist.
+fn stack_bottom() -> usize {
+    symbol_addr!(__sheap).max(symbol_addr!(_panic_dump_end))
+}
+
+/// Limite superiore (iniziale) dello stack
+fn stack_top() -> usize {
+    symbol_addr!(_stack_start)
+}
+
+/// Dipinge le parole tra `from` e `to` con STACK_PAINT
+///
+/// # Argomenti
+/// * `from` - Indirizzo iniziale (allineato a 4)
+/// * `to` - Indirizzo finale escluso
+fn paint(from: usize, to: usize) {
+    let mut addr = from;
+    while addr < to {
+        // SAFETY: la regione è sotto lo stack pointer corrente, quindi non
+        // contiene dati vivi
+        unsafe { core::ptr::write_volatile(addr as *mut u32, STACK_PAINT) };
+        addr += 4;
+    }
+}
+
+/// Ritorna il primo indirizzo tra `from` e `to` che non contiene più STACK_PAINT
+///
+/// # Argomenti
+/// * `from` - Indirizzo iniziale (allineato a 4)
+/// * `to` - Indirizzo finale escluso
+///
+/// # Ritorna
+/// * usize - Primo indirizzo usato (`to` se la regione è intatta)
+fn first_used(from: usize, to: usize) -> usize {
+    let mut addr = from;
+    // SAFETY: la regione appartiene allo stack ed è allineata
+    while addr < to && unsafe { core::ptr::read_volatile(addr as *const u32) } == STACK_PAINT {
+        addr += 4;
+    }
+    addr
+}
+
+/// Dipinge lo stack libero: da chiamare all'inizio del main
+pub fn paint_stack() {
+    let sp = cortex_m::register::msp::read() as usize;
+    paint(stack_bottom(), (sp - PAINT_MARGIN) & !3);
+}
+
+/// Cerca il punto più basso raggiunto dallo stack
+///
+/// # Ritorna
+/// * usize - Byte di stack usati al massimo dall'avvio
+fn scan_stack() -> usize {
+    stack_top() - first_used(stack_bottom(), stack_top())
+}
+
+/// Future che misura il picco di stack usato durante i poll di un task
+pub struct Measured<F> {
+    task: Task,
+    inner: F,
+}
+
+/// Avvolge il future di un task per misurarne il picco di stack
+///
+/// # Argomenti
+/// * `task` - Task a cui attribuire la misura
+/// * `inner` - Future del task
+///
+/// # Ritorna
+/// * Measured<F> - Future che si comporta come `inner`
+pub fn measured<F: Future>(task: Task, inner: F) -> Measured<F> {
+    Measured { task, inner }
+}
+
+impl<F: Future> Future for Measured<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `inner` non viene mai spostato
+        let this = unsafe { self.get_unchecked_mut() };
+        let sp = (cortex_m::register::msp::read() as usize - PAINT_MARGIN) & !3;
+        let window_bottom = sp.saturating_sub(PROBE_WINDOW).max(stack_bottom());
+        paint(window_bottom, sp);
+
+        // SAFETY: `this` è pinnato, quindi anche `inner` lo è
+        let result = unsafe { Pin::new_unchecked(&mut this.inner) }.poll(cx);
+
+        let used = sp - first_used(window_bottom, sp);
+        TASK_PEAK[this.task as usize].fetch_max(used as u32, Ordering::Relaxed);
+        crate::tasks::touch(TASKS[this.task as usize].0);
+        result
+    }
+}
+
+/// Picco di stack di un task
+#[derive(serde::Serialize)]
+pub struct TaskStack {
+    name: &'static str,
+    peak_bytes: u32,
+    /// true se il picco ha raggiunto la finestra di misura
+    saturated: bool,
+}
+
+/// Uso della memoria restituito da /api/memory
+#[derive(serde::Serialize)]
+pub struct MemoryReport {
+    data_bytes: usize,
+    bss_bytes: usize,
+    uninit_bytes: usize,
+    stack_size: usize,
+    stack_used: usize,
+    stack_headroom: usize,
+    tasks: heapless::Vec<TaskStack, TASK_COUNT>,
+    #[cfg(feature = "heap")]
+    heap: crate::heap::HeapStats,
+}
+
+/// Ritorna l'uso della memoria
+///
+/// # Ritorna
+/// * MemoryReport - Uso della memoria
+pub fn report() -> MemoryReport {
+    let stack_size = stack_top() - stack_bottom();
+    let stack_used = scan_stack();
+    let tasks = TASKS
+        .iter()
+        .zip(TASK_PEAK.iter())
+        .map(|(&(name, _), peak)| {
+            let peak_bytes = peak.load(Ordering::Relaxed);
+            TaskStack {
+                name,
+                peak_bytes,
+                saturated: peak_bytes as usize >= PROBE_WINDOW,
+            }
+        })
+        .collect();
+    MemoryReport {
+        data_bytes: symbol_addr!(__edata) - symbol_addr!(__sdata),
+        bss_bytes: symbol_addr!(__ebss) - symbol_addr!(__sbss),
+        uninit_bytes: symbol_addr!(__euninit) - symbol_addr!(__suninit),
+        stack_size,
+        stack_used,
+        stack_headroom: stack_size.saturating_sub(stack_used),
+        tasks,
+        #[cfg(feature = "heap")]
+        heap: crate::heap::stats(),
+    }
+}
+
+#[embassy_executor::task]
+/// Task che scansiona periodicamente lo stack e avvisa se il margine residuo è basso
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn memory_task() {
+    let mut ticker = Ticker::every(Duration::from_secs(SCAN_PERIOD_SECS));
+    let mut warned = false;
+    loop {
+        ticker.next().await;
+        crate::tasks::touch("memory");
+        let used = scan_stack();
+        let headroom = (stack_top() - stack_bottom()).saturating_sub(used);
+        if headroom < HEADROOM_WARNING && !warned {
+            log::warn!(
+                "Stack: restano solo {} byte liberi ({} usati)",
+                headroom,
+                used
+            );
+        }
+        warned = headroom < HEADROOM_WARNING;
+    }
+}
@@ -0,0 +1,82 @@
+//! metrics.rs - Rotte di diagnostica: log in memoria, supervisore, memoria,
+//! task, orologio, WiFi, storico dei crash, contatori persistenti, errori
+//! gestiti dai task, connessioni HTTP, lettura del documento JSON esterno e
+//! misure dei tempi.
+//!
+//! Le rotte si limitano a serializzare i report dei rispettivi moduli e
+//! restano disponibili anche in modalità provvisoria (vedi safe_mode.rs).
+
+use crate::acl;
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::clock;
+use crate::counters;
+use crate::crash_log;
+use crate::errors;
+use crate::fetch;
+use crate::http_stats;
+use crate::log_buffer::LogDump;
+use crate::memory;
+use crate::supervisor;
+use crate::tasks;
+use crate::timing;
+use crate::wifi_stats;
+use picoserve::extract::Query;
+use picoserve::response::Json;
+use picoserve::routing::{PathRouter, get};
+
+/// Numero massimo di record restituiti da /logs
+const LOGS_PAGE_RECORDS: usize = 64;
+
+/// Parametri di `GET /logs`, es. `/logs?lines=20`
+#[derive(serde::Deserialize)]
+pub struct LogsQuery {
+    /// Numero di record più recenti da mostrare (al massimo LOGS_PAGE_RECORDS)
+    #[serde(default)]
+    pub lines: Option<usize>,
+}
+
+/// Rotte di diagnostica: `/logs` e `/api/{supervisor,memory,tasks,time,wifi,crashes,counters,errors,http,fetch,acl,timing}`
+pub struct MetricsRoutes;
+
+impl Routes for MetricsRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                "/logs",
+                get(|Query(query): Query<LogsQuery>| async move {
+                    let lines = query.lines.unwrap_or(LOGS_PAGE_RECORDS);
+                    LogDump::last(lines.min(LOGS_PAGE_RECORDS))
+                }),
+            )
+            .route(
+                "/api/supervisor",
+                get(|| async move { Json(supervisor::report()) }),
+            )
+            .route("/api/memory", get(|| async move { Json(memory::report()) }))
+            .route("/api/tasks", get(|| async move { Json(tasks::list()) }))
+            .route("/api/time", get(|| async move { Json(clock::status()) }))
+            .route(
+                "/api/wifi",
+                get(|| async move { Json(wifi_stats::report()) }),
+            )
+            .route(
+                "/api/crashes",
+                get(|| async move { Json(crash_log::list()) }),
+            )
+            .route(
+                "/api/counters",
+                get(|| async move { Json(counters::report()) }),
+            )
+            .route("/api/errors", get(|| async move { Json(errors::counts()) }))
+            .route(
+                "/api/http",
+                get(|| async move { Json(http_stats::report()) }),
+            )
+            .route("/api/fetch", get(|| async move { Json(fetch::report()) }))
+            .route("/api/acl", get(|| async move { Json(acl::report()) }))
+            .route("/api/timing", get(|| async move { Json(timing::report()) }))
+    }
+}
@@ -0,0 +1,470 @@
+//! mqtt.rs - Client MQTT per gli eventi, la telemetria e il comando del LED.
+//!
+//! Se `MQTT_BROKER` (nome o indirizzo IPv4) è configurato, [`mqtt_task`] si
+//! collega al broker con un client MQTT 3.1.1 minimo (solo QoS 0) e, con il
+//! prefisso `MQTT_TOPIC` (es. `picoweb`), pubblica:
+//! * `<prefisso>/status` - `online` dopo la connessione, conservato dal
+//!   broker; se la pico sparisce il broker pubblica `offline` (last will);
+//! * `<prefisso>/solve`, `/wifi`, `/led` e `/button` - gli eventi del bus
+//!   come JSON, come su `/events` (vedi live.rs);
+//! * `<prefisso>/temperature` (gradi, es. `23.4`) e `<prefisso>/uptime`
+//!   (secondi) ogni `MQTT_INTERVAL_SECS` secondi.
+//!
+//! Dal topic `<prefisso>/led/set` riceve `on`, `off`, `toggle`, `blink` o
+//! `auto` e cambia la modalità del LED come il comando `led` della console.
+//! Con `MQTT_USER` il client si autentica con `MQTT_PASSWORD`, che viaggia in
+//! chiaro. Se la connessione cade il task si ricollega dopo [`RETRY_SECS`].
+//!
+//! Esempio:
+//! ```text
+//! mosquitto_sub -h <broker> -t 'picoweb/#' -v
+//! mosquitto_pub -h <broker> -t picoweb/led/set -m toggle
+//! ```
+
+use crate::bus::{self, EventSubscriber};
+use crate::errors;
+use crate::fault;
+use crate::fetch;
+use crate::led;
+use crate::live;
+use crate::sensors;
+use crate::utility::{ConfigValue, get_hostname, get_mqtt_config};
+use embassy_futures::select::{Either4, select4};
+use embassy_net::tcp::TcpSocket;
+use embassy_net::{IpEndpoint, Stack};
+use embassy_sync::pubsub::WaitResult;
+use embassy_time::{Duration, Instant, Ticker, Timer, with_timeout};
+use embedded_io_async::{Read, Write};
+
+/// Intervallo massimo tra due pacchetti dichiarato al broker
+const KEEPALIVE_SECS: u16 = 60;
+
+/// Intervallo dei PINGREQ, metà del keep-alive
+const PING_SECS: u64 = KEEPALIVE_SECS as u64 / 2;
+
+/// Attesa massima della risposta alla connessione e del resto di un pacchetto
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ritardo prima di ricollegarsi dopo un errore
+const RETRY_SECS: u64 = 30;
+
+/// Dimensione massima di un pacchetto inviato, senza l'intestazione fissa
+const PACKET_CAPACITY: usize = 256;
+
+/// Dimensione massima di un pacchetto ricevuto: i più lunghi vengono scartati
+const RECEIVE_CAPACITY: usize = 128;
+
+/// Tipi di pacchetto (primo byte dell'intestazione fissa)
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xc0;
+
+/// Flag di PUBLISH: il broker conserva l'ultimo messaggio del topic
+const RETAIN: u8 = 0x01;
+
+/// Flag di CONNECT
+const CLEAN_SESSION: u8 = 0x02;
+const WILL: u8 = 0x04;
+const WILL_RETAIN: u8 = 0x20;
+const PASSWORD: u8 = 0x40;
+const USER: u8 = 0x80;
+
+/// Configurazione del broker e dei topic
+pub struct MqttConfig {
+    pub broker: ConfigValue,
+    pub port: u16,
+    /// Prefisso dei topic, es. `picoweb`
+    pub topic: ConfigValue,
+    /// Utente, vuoto per non autenticarsi
+    pub user: ConfigValue,
+    pub password: ConfigValue,
+    /// Secondi tra due invii di temperatura e uptime
+    pub interval_secs: u64,
+}
+
+/// Errori di una connessione
+#[derive(Debug)]
+enum MqttError {
+    /// Nome del broker non risolto
+    Dns,
+    /// Connessione rifiutata o scaduta
+    Connect,
+    /// Errore di invio o ricezione, o connessione chiusa dal broker
+    Io,
+    /// Nessuna risposta entro IO_TIMEOUT
+    Timeout,
+    /// Connessione rifiutata dal broker (codice di CONNACK)
+    Refused(u8),
+    /// Pacchetto non valido
+    Malformed,
+    /// Pacchetto da inviare più lungo di PACKET_CAPACITY
+    TooLong,
+}
+
+/// Nome di un topic
+type Topic = heapless::String<64>;
+
+/// Costruisce il nome di un topic con il prefisso configurato
+fn topic(prefix: &str, name: &str) -> Topic {
+    errors::fit(format_args!("{}/{}", prefix, name))
+}
+
+/// Pacchetto da inviare
+struct Packet {
+    /// Primo byte dell'intestazione fissa: tipo e flag
+    kind: u8,
+    body: heapless::Vec<u8, PACKET_CAPACITY>,
+}
+
+impl Packet {
+    fn new(kind: u8) -> Self {
+        Self {
+            kind,
+            body: heapless::Vec::new(),
+        }
+    }
+
+    /// Aggiunge byte al corpo
+    fn bytes(&mut self, data: &[u8]) -> Result<(), MqttError> {
+        self.body
+            .extend_from_slice(data)
+            .map_err(|_| MqttError::TooLong)
+    }
+
+    /// Aggiunge un intero a 16 bit big-endian
+    fn u16(&mut self, value: u16) -> Result<(), MqttError> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    /// Aggiunge una stringa preceduta dalla lunghezza
+    fn string(&mut self, value: &[u8]) -> Result<(), MqttError> {
+        let len = u16::try_from(value.len()).map_err(|_| MqttError::TooLong)?;
+        self.u16(len)?;
+        self.bytes(value)
+    }
+
+    /// Invia il pacchetto con l'intestazione fissa
+    ///
+    /// # Argomenti
+    /// * `io` - Connessione con il broker
+    async fn send<T: Write>(&self, io: &mut T) -> Result<(), MqttError> {
+        // Lunghezza rimanente: 7 bit per byte, il bit alto annuncia il seguito
+        let mut header: heapless::Vec<u8, 5> = heapless::Vec::new();
+        let _ = header.push(self.kind);
+        let mut len = self.body.len();
+        loop {
+            let byte = (len % 128) as u8;
+            len /= 128;
+            let _ = header.push(if len > 0 { byte | 0x80 } else { byte });
+            if len == 0 {
+                break;
+            }
+        }
+        io.write_all(&header).await.map_err(|_| MqttError::Io)?;
+        io.write_all(&self.body).await.map_err(|_| MqttError::Io)?;
+        io.flush().await.map_err(|_| MqttError::Io)
+    }
+}
+
+/// Legge un byte
+async fn read_byte<T: Read>(io: &mut T) -> Result<u8, MqttError> {
+    let mut byte = [0];
+    match io.read(&mut byte).await {
+        Ok(1) => Ok(byte[0]),
+        _ => Err(MqttError::Io),
+    }
+}
+
+/// Legge il resto di un pacchetto, dopo il primo byte dell'intestazione
+///
+/// # Argomenti
+/// * `io` - Connessione con il broker
+/// * `body` - Buffer per il corpo del pacchetto
+///
+/// # Ritorna
+/// * Result<Option<usize>, MqttError> - Lunghezza del corpo, None se il
+///   pacchetto era più lungo del buffer ed è stato scartato
+async fn receive<T: Read>(
+    io: &mut T,
+    body: &mut [u8; RECEIVE_CAPACITY],
+) -> Result<Option<usize>, MqttError> {
+    let mut len = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = read_byte(io).await?;
+        len |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 21 {
+            return Err(MqttError::Malformed);
+        }
+    }
+    if len <= body.len() {
+        io.read_exact(&mut body[..len])
+            .await
+            .map_err(|_| MqttError::Io)?;
+        return Ok(Some(len));
+    }
+    let mut rest = len;
+    while rest > 0 {
+        let chunk = rest.min(body.len());
+        io.read_exact(&mut body[..chunk])
+            .await
+            .map_err(|_| MqttError::Io)?;
+        rest -= chunk;
+    }
+    Ok(None)
+}
+
+/// Pubblica un messaggio con QoS 0
+///
+/// # Argomenti
+/// * `io` - Connessione con il broker
+/// * `topic` - Topic del messaggio
+/// * `payload` - Contenuto
+/// * `retain` - true se il broker deve conservare il messaggio
+async fn publish<T: Write>(
+    io: &mut T,
+    topic: &str,
+    payload: &[u8],
+    retain: bool,
+) -> Result<(), MqttError> {
+    let mut packet = Packet::new(if retain { PUBLISH | RETAIN } else { PUBLISH });
+    packet.string(topic.as_bytes())?;
+    packet.bytes(payload)?;
+    packet.send(io).await
+}
+
+/// Apre la sessione MQTT e attende la conferma del broker
+///
+/// # Argomenti
+/// * `io` - Connessione TCP con il broker
+/// * `config` - Configurazione MQTT
+/// * `client_id` - Identificativo del client
+async fn connect<T: Read + Write>(
+    io: &mut T,
+    config: &MqttConfig,
+    client_id: &str,
+) -> Result<(), MqttError> {
+    let authenticated = !config.user.is_empty();
+    let mut flags = CLEAN_SESSION | WILL | WILL_RETAIN;
+    if authenticated {
+        flags |= USER | PASSWORD;
+    }
+    let mut packet = Packet::new(CONNECT);
+    packet.string(b"MQTT")?;
+    // Livello 4 = MQTT 3.1.1
+    packet.bytes(&[4, flags])?;
+    packet.u16(KEEPALIVE_SECS)?;
+    packet.string(client_id.as_bytes())?;
+    packet.string(topic(&config.topic, "status").as_bytes())?;
+    packet.string(b"offline")?;
+    if authenticated {
+        packet.string(config.user.as_bytes())?;
+        packet.string(config.password.as_bytes())?;
+    }
+    packet.send(io).await?;
+
+    let mut body = [0; RECEIVE_CAPACITY];
+    let reply = with_timeout(IO_TIMEOUT, async {
+        let kind = read_byte(io).await?;
+        Ok::<_, MqttError>((kind, receive(io, &mut body).await?))
+    })
+    .await
+    .map_err(|_| MqttError::Timeout)?;
+    match reply? {
+        (CONNACK, Some(2)) if body[1] == 0 => Ok(()),
+        (CONNACK, Some(2)) => Err(MqttError::Refused(body[1])),
+        _ => Err(MqttError::Malformed),
+    }
+}
+
+/// Pubblica temperatura del chip e uptime
+///
+/// # Argomenti
+/// * `io` - Connessione con il broker
+/// * `prefix` - Prefisso dei topic
+async fn publish_telemetry<T: Write>(io: &mut T, prefix: &str) -> Result<(), MqttError> {
+    if let Some(temperature) = sensors::temperature() {
+        let millicelsius = temperature.millicelsius();
+        let sign = if millicelsius < 0 { "-" } else { "" };
+        let tenths = (millicelsius / 100).unsigned_abs();
+        let payload: heapless::String<12> =
+            errors::fit(format_args!("{}{}.{}", sign, tenths / 10, tenths % 10));
+        publish(io, &topic(prefix, "temperature"), payload.as_bytes(), false).await?;
+    }
+    let uptime: heapless::String<20> = errors::fit(format_args!("{}", Instant::now().as_secs()));
+    publish(io, &topic(prefix, "uptime"), uptime.as_bytes(), false).await
+}
+
+/// Separa topic e contenuto di un PUBLISH ricevuto
+///
+/// # Argomenti
+/// * `header` - Primo byte dell'intestazione, con il QoS
+/// * `body` - Corpo del pacchetto
+///
+/// # Ritorna
+/// * Option<(&[u8], &[u8])> - Topic e contenuto, None se il pacchetto non è valido
+fn parse_publish(header: u8, body: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+    let topic = body.get(2..2 + len)?;
+    // Con QoS 1 e 2 il topic è seguito dall'identificativo del pacchetto
+    let qos = (header >> 1) & 0x03;
+    let start = 2 + len + if qos > 0 { 2 } else { 0 };
+    Some((topic, body.get(start..)?))
+}
+
+/// Esegue un comando ricevuto sul topic del LED
+///
+/// # Argomenti
+/// * `payload` - Azione: `on`, `off`, `toggle`, `blink` o `auto`
+fn on_led_command(payload: &[u8]) {
+    let action = core::str::from_utf8(payload).unwrap_or_default().trim();
+    match led::mode_for_action(action) {
+        Some(mode) => {
+            fault::set_led_mode(mode);
+            log::info!("LED: modalità {} da MQTT", mode.as_str());
+        }
+        None => log::warn!("MQTT: azione sul LED '{}' non valida", action),
+    }
+}
+
+/// Scambia messaggi con il broker finché la connessione resta attiva
+///
+/// # Argomenti
+/// * `socket` - Connessione TCP con il broker
+/// * `config` - Configurazione MQTT
+/// * `events` - Sottoscrizione al bus degli eventi
+///
+/// # Ritorna
+/// * Result<(), MqttError> - Errore che ha chiuso la sessione
+async fn session(
+    socket: &mut TcpSocket<'_>,
+    config: &MqttConfig,
+    events: &mut EventSubscriber,
+) -> Result<(), MqttError> {
+    let hostname = get_hostname();
+    let client_id = match hostname.trim() {
+        "" => "picoweb",
+        hostname => hostname,
+    };
+    connect(socket, config, client_id).await?;
+
+    let prefix = config.topic.trim();
+    let command_topic = topic(prefix, "led/set");
+    let mut packet = Packet::new(SUBSCRIBE);
+    // Identificativo del pacchetto, il SUBACK viene ignorato
+    packet.u16(1)?;
+    packet.string(command_topic.as_bytes())?;
+    packet.bytes(&[0])?;
+    packet.send(socket).await?;
+    publish(socket, &topic(prefix, "status"), b"online", true).await?;
+    log::info!("MQTT: collegato a {}", config.broker);
+
+    let mut telemetry = Ticker::every(Duration::from_secs(config.interval_secs));
+    let mut ping = Ticker::every(Duration::from_secs(PING_SECS));
+    let mut body = [0; RECEIVE_CAPACITY];
+    loop {
+        crate::tasks::touch("mqtt");
+        let mut header = [0];
+        match select4(
+            events.next_message(),
+            socket.read(&mut header),
+            telemetry.next(),
+            ping.next(),
+        )
+        .await
+        {
+            Either4::First(WaitResult::Message(event)) => {
+                let (name, data) = live::describe(&event);
+                publish(socket, &topic(prefix, name), data.as_bytes(), false).await?;
+            }
+            Either4::First(WaitResult::Lagged(lost)) => {
+                log::warn!("MQTT: {} eventi del bus persi", lost);
+            }
+            Either4::Second(Ok(1)) => {
+                let received = with_timeout(IO_TIMEOUT, receive(socket, &mut body))
+                    .await
+                    .map_err(|_| MqttError::Timeout)??;
+                // CONNACK, SUBACK e PINGRESP non richiedono azioni
+                if let (PUBLISH, Some(len)) = (header[0] & 0xf0, received) {
+                    match parse_publish(header[0], &body[..len]) {
+                        Some((topic, payload)) if topic == command_topic.as_bytes() => {
+                            on_led_command(payload)
+                        }
+                        Some(_) => {}
+                        None => return Err(MqttError::Malformed),
+                    }
+                }
+            }
+            Either4::Second(_) => return Err(MqttError::Io),
+            Either4::Third(()) => publish_telemetry(socket, prefix).await?,
+            Either4::Fourth(()) => Packet::new(PINGREQ).send(socket).await?,
+        }
+    }
+}
+
+/// Si collega al broker e gestisce una sessione
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+/// * `config` - Configurazione MQTT
+/// * `events` - Sottoscrizione al bus degli eventi
+///
+/// # Ritorna
+/// * Result<(), MqttError> - Errore che ha chiuso la connessione
+async fn run(
+    stack: Stack<'static>,
+    config: &MqttConfig,
+    events: &mut EventSubscriber,
+) -> Result<(), MqttError> {
+    let address = fetch::resolve(stack, config.broker.trim())
+        .await
+        .ok_or(MqttError::Dns)?;
+
+    let mut rx_buffer = [0; 512];
+    let mut tx_buffer = [0; 512];
+    let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+    // Il broker risponde almeno ai PINGREQ: un silenzio più lungo è un guasto
+    socket.set_timeout(Some(Duration::from_secs(2 * KEEPALIVE_SECS as u64)));
+    socket
+        .connect(IpEndpoint::new(address, config.port))
+        .await
+        .map_err(|_| MqttError::Connect)?;
+    let result = session(&mut socket, config, events).await;
+    socket.abort();
+    let _ = socket.flush().await;
+    result
+}
+
+#[embassy_executor::task]
+/// Task che mantiene la connessione con il broker MQTT
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn mqtt_task(stack: Stack<'static>) {
+    let Some(mut events) = bus::subscribe() else {
+        log::error!("MQTT: troppi sottoscrittori del bus");
+        return;
+    };
+    loop {
+        crate::tasks::touch("mqtt");
+        // La configurazione è riletta a ogni connessione: modificabile a runtime
+        let Some(config) = get_mqtt_config() else {
+            log::info!("MQTT: broker non configurato, client fermo");
+            return;
+        };
+        stack.wait_config_up().await;
+        if let Err(e) = run(stack, &config, &mut events).await {
+            log::warn!("MQTT: connessione a {} chiusa: {:?}", config.broker, e);
+        }
+        Timer::after_secs(RETRY_SECS).await;
+    }
+}
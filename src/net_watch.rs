@@ -0,0 +1,142 @@
+//! net_watch.rs - Verifica che lo stack di rete faccia progressi.
+//!
+//! Gli heartbeat dei task `net` e `web` (vedi supervisor.rs) dimostrano solo
+//! che l'executor gira: se il firmware del CYW43 si blocca, il runner resta
+//! in attesa di pacchetti che non arrivano e il web server non serve più
+//! nessuno, senza che nessuna scadenza venga mancata. [`net_watch_task`]
+//! manda quindi ogni [`PROBE_PERIOD`] un echo ICMP al gateway e fa check-in
+//! come `net_io` solo quando arriva la risposta: dopo la scadenza del
+//! supervisore senza risposte il watchdog hardware (`WATCHDOG_ENABLED=true`)
+//! riavvia la pico.
+//!
+//! Senza link, senza gateway o in modalità access point il task fa check-in
+//! comunque: la riconnessione è compito di wifi.rs. La verifica si arma alla
+//! prima risposta, così un gateway che non risponde al ping non causa
+//! riavvii a ripetizione.
+
+use crate::supervisor::{self, Task};
+use crate::wifi;
+use embassy_net::IpAddress;
+use embassy_net::icmp::{IcmpEndpoint, IcmpSocket, PacketMetadata};
+use embassy_time::{Duration, Ticker, with_timeout};
+
+/// Intervallo tra due echo
+const PROBE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Attesa massima della risposta
+const REPLY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Identificativo degli echo della pico
+const ECHO_IDENT: u16 = 0x5057;
+
+/// Lunghezza di un echo: intestazione ICMP e 8 byte di dati
+const ECHO_LEN: usize = 16;
+
+/// Costruisce un echo request ICMP
+///
+/// # Argomenti
+/// * `seq` - Numero di sequenza
+///
+/// # Ritorna
+/// * [u8; ECHO_LEN] - Pacchetto con il checksum calcolato
+fn echo_request(seq: u16) -> [u8; ECHO_LEN] {
+    let mut packet = [0u8; ECHO_LEN];
+    packet[0] = 8; // echo request
+    packet[4..6].copy_from_slice(&ECHO_IDENT.to_be_bytes());
+    packet[6..8].copy_from_slice(&seq.to_be_bytes());
+    packet[8..].copy_from_slice(b"picoweb!");
+    let sum = packet
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], word[1]]) as u32)
+        .sum::<u32>();
+    let sum = (sum & 0xFFFF) + (sum >> 16);
+    let checksum = !((sum & 0xFFFF) + (sum >> 16)) as u16;
+    packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    packet
+}
+
+/// Verifica se un pacchetto è la risposta a un echo
+///
+/// # Argomenti
+/// * `packet` - Pacchetto ricevuto
+/// * `seq` - Numero di sequenza atteso
+fn is_reply(packet: &[u8], seq: u16) -> bool {
+    packet.len() >= 8
+        && packet[0] == 0 // echo reply
+        && packet[4..6] == ECHO_IDENT.to_be_bytes()
+        && packet[6..8] == seq.to_be_bytes()
+}
+
+#[embassy_executor::task]
+/// Task che manda un echo ICMP al gateway e fa check-in alla risposta
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn net_watch_task(stack: embassy_net::Stack<'static>) -> ! {
+    let mut rx_meta = [PacketMetadata::EMPTY; 2];
+    let mut rx_buffer = [0u8; 64];
+    let mut tx_meta = [PacketMetadata::EMPTY; 2];
+    let mut tx_buffer = [0u8; 64];
+    let mut socket = IcmpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    if let Err(e) = socket.bind(IcmpEndpoint::Ident(ECHO_IDENT)) {
+        log::error!("Rete: bind del socket ICMP fallito: {:?}", e);
+    }
+
+    let mut armed = false;
+    let mut seq: u16 = 0;
+    let mut ticker = Ticker::every(PROBE_PERIOD);
+    loop {
+        crate::tasks::touch("net_watch");
+        let gateway = stack.config_v4().and_then(|config| config.gateway);
+        let Some(gateway) = gateway.filter(|_| wifi::is_up()) else {
+            supervisor::check_in(Task::NetIo);
+            ticker.next().await;
+            continue;
+        };
+        if !armed {
+            // Finché il gateway non ha mai risposto la verifica non conta
+            supervisor::check_in(Task::NetIo);
+        }
+
+        seq = seq.wrapping_add(1);
+        let target = IpAddress::Ipv4(gateway);
+        let replied = match socket.send_to(&echo_request(seq), target).await {
+            Ok(()) => with_timeout(REPLY_TIMEOUT, async {
+                let mut packet = [0u8; ECHO_LEN];
+                loop {
+                    match socket.recv_from(&mut packet).await {
+                        Ok((len, from)) if from == target && is_reply(&packet[..len], seq) => {
+                            break;
+                        }
+                        Ok(_) => {}
+                        Err(e) => log::warn!("Rete: ricezione ICMP fallita: {:?}", e),
+                    }
+                }
+            })
+            .await
+            .is_ok(),
+            Err(e) => {
+                log::warn!("Rete: invio dell'echo al gateway fallito: {:?}", e);
+                false
+            }
+        };
+
+        if replied {
+            if !armed {
+                log::info!("Rete: il gateway {} risponde al ping", gateway);
+                armed = true;
+            }
+            supervisor::check_in(Task::NetIo);
+        }
+        ticker.next().await;
+    }
+}
@@ -0,0 +1,610 @@
+//! ota.rs - Aggiornamento del firmware via HTTP su due slot A/B.
+//!
+//! La flash è divisa dalla tabella delle partizioni (partition_table.json,
+//! caricata con picotool) in due slot di firmware, A e B: il boot ROM
+//! dell'RP2350 avvia uno dei due mappandolo su 0x10000000.
+//!
+//! `POST /api/ota` riceve l'immagine binaria e la scrive nello slot non in
+//! esecuzione, marcandola "try before you buy" (TBYB); il primo settore,
+//! che contiene l'IMAGE_DEF, viene scritto per ultimo, così un caricamento
+//! interrotto non lascia un'immagine avviabile. Al termine la pico si
+//! riavvia nel nuovo slot come avvio di prova.
+//!
+//! Durante la prova il watchdog è sempre attivo e [`ota_task`] conferma
+//! l'immagine (`explicit_buy` della ROM) solo dopo [`CONFIRM_AFTER`] di
+//! funzionamento regolare: web server avviato, supervisore senza scadenze
+//! mancate e WiFi connesso. Se la conferma non arriva entro
+//! [`TRIAL_TIMEOUT`], o se il nuovo firmware va in panic o si blocca, al
+//! riavvio successivo il boot ROM ignora l'immagine non confermata e torna
+//! allo slot precedente.
+//!
+//! Lo stato è salvato in flash a ogni passaggio, con il numero di avvii
+//! dell'immagine in prova e, dopo un rollback, la causa: `panic` se il
+//! messaggio conservato da panic_persist mostra che la prova è finita con
+//! un panic, altrimenti `unconfirmed` (blocco, timeout o riavvio). È
+//! consultabile su `GET /api/ota`.
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::errors;
+use crate::flash_store::{self, RecordStore, StoreError};
+use crate::i18n::Lang;
+use crate::wifi;
+use embassy_rp::flash::ERASE_SIZE;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
+use picoserve::ResponseSent;
+use picoserve::io::Read;
+use picoserve::request::Request;
+use picoserve::response::{IntoResponse, Json, ResponseWriter, StatusCode};
+use picoserve::routing::{PathRouter, RequestHandlerService, get};
+use portable_atomic::{AtomicU8, Ordering};
+
+/// Offset e dimensione degli slot, come in partition_table.json
+const SLOT_A_OFFSET: u32 = 0x0000_2000;
+const SLOT_B_OFFSET: u32 = 0x0018_0000;
+pub const SLOT_SIZE: u32 = 0x0017_E000;
+
+/// Tempo di funzionamento regolare richiesto per confermare un'immagine
+pub const CONFIRM_AFTER: Duration = Duration::from_secs(30);
+
+/// Tempo massimo dell'avvio di prova prima del rollback
+pub const TRIAL_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Archivio dello stato dell'aggiornamento, sotto i contatori
+static OTA_STORE: RecordStore<32, 1> = RecordStore::new("OTA", 4, 2);
+
+/// Chiave del record di stato
+const OTA_KEY: u16 = 0;
+
+/// Record di stato: stato, slot, avvii di prova, causa del rollback (i
+/// record dei firmware precedenti hanno solo i primi due byte)
+const RECORD_LEN: usize = 4;
+
+// Blocco IMAGE_DEF (vedi il datasheet RP2350, "Block Loop"): marcatore di
+// inizio seguito dall'item IMAGE_TYPE, i cui 16 bit alti sono i flag
+const BLOCK_MARKER_START: u32 = 0xFFFF_DED3;
+const ITEM_IMAGE_TYPE: u8 = 0x42;
+const IMAGE_TYPE_EXE_TBYB: u32 = 0x8000 << 16;
+
+/// Slot di firmware
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    A = 0,
+    B = 1,
+}
+
+impl Slot {
+    fn offset(self) -> u32 {
+        match self {
+            Slot::A => SLOT_A_OFFSET,
+            Slot::B => SLOT_B_OFFSET,
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Slot::A => "A",
+            Slot::B => "B",
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Slot::A),
+            1 => Some(Slot::B),
+            _ => None,
+        }
+    }
+
+    /// Slot in esecuzione, ricavato dalla traduzione degli indirizzi XIP
+    /// impostata dal boot ROM
+    ///
+    /// # Ritorna
+    /// * Option<Slot> - None se la flash non ha la tabella delle partizioni A/B
+    pub fn running() -> Option<Self> {
+        let base = embassy_rp::pac::QMI.atrans(0).read().base() as u32 * ERASE_SIZE as u32;
+        [Slot::A, Slot::B]
+            .into_iter()
+            .find(|slot| slot.offset() == base)
+    }
+}
+
+/// Stato dell'aggiornamento
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OtaState {
+    /// Nessun aggiornamento in corso
+    Idle = 0,
+    /// Immagine scritta, riavvio nel nuovo slot
+    Pending = 1,
+    /// Avvio di prova del nuovo slot in corso
+    Trial = 2,
+    /// Ultima immagine confermata
+    Confirmed = 3,
+    /// Ultima immagine non confermata: tornati allo slot precedente
+    RolledBack = 4,
+}
+
+impl OtaState {
+    fn as_str(self) -> &'static str {
+        match self {
+            OtaState::Idle => "idle",
+            OtaState::Pending => "pending",
+            OtaState::Trial => "trial",
+            OtaState::Confirmed => "confirmed",
+            OtaState::RolledBack => "rolled_back",
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => OtaState::Pending,
+            2 => OtaState::Trial,
+            3 => OtaState::Confirmed,
+            4 => OtaState::RolledBack,
+            _ => OtaState::Idle,
+        }
+    }
+}
+
+/// Causa del ritorno allo slot precedente
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RollbackReason {
+    None = 0,
+    /// L'immagine in prova è andata in panic
+    Panic = 1,
+    /// L'immagine in prova si è riavviata senza conferma (blocco, timeout)
+    Unconfirmed = 2,
+}
+
+impl RollbackReason {
+    fn as_str(self) -> Option<&'static str> {
+        match self {
+            RollbackReason::None => None,
+            RollbackReason::Panic => Some("panic"),
+            RollbackReason::Unconfirmed => Some("unconfirmed"),
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => RollbackReason::Panic,
+            2 => RollbackReason::Unconfirmed,
+            _ => RollbackReason::None,
+        }
+    }
+}
+
+/// Stato salvato in flash
+#[derive(Clone, Copy)]
+struct OtaRecord {
+    state: OtaState,
+    slot: Option<Slot>,
+    /// Avvii dell'immagine in prova dall'aggiornamento
+    trial_boots: u8,
+    reason: RollbackReason,
+}
+
+/// Stato corrente
+static STATE: AtomicU8 = AtomicU8::new(OtaState::Idle as u8);
+
+/// Errori dell'aggiornamento
+#[derive(Debug)]
+pub enum OtaError {
+    /// La flash non ha la tabella delle partizioni A/B
+    NoPartitions,
+    /// Un altro aggiornamento è in corso
+    Busy,
+    /// L'immagine non entra in uno slot
+    TooLarge,
+    /// L'immagine non contiene un blocco IMAGE_DEF
+    NotImage,
+    /// La connessione si è chiusa prima della fine dell'immagine
+    Incomplete,
+    Flash(StoreError),
+}
+
+impl From<StoreError> for OtaError {
+    fn from(e: StoreError) -> Self {
+        OtaError::Flash(e)
+    }
+}
+
+/// Stato restituito da /api/ota
+#[derive(serde::Serialize)]
+pub struct OtaStatus {
+    running_slot: Option<&'static str>,
+    state: &'static str,
+    /// Slot dell'ultimo aggiornamento
+    target_slot: Option<&'static str>,
+    /// Avvii dell'ultima immagine in prova
+    trial_boots: u8,
+    /// Causa dell'ultimo rollback, None se l'ultima immagine non è tornata indietro
+    rollback_reason: Option<&'static str>,
+}
+
+/// Ritorna lo stato corrente
+pub fn state() -> OtaState {
+    OtaState::from_u8(STATE.load(Ordering::Relaxed))
+}
+
+/// Ritorna lo stato dell'aggiornamento per /api/ota
+///
+/// # Ritorna
+/// * OtaStatus - Slot in esecuzione, stato e slot dell'ultimo aggiornamento
+pub fn status() -> OtaStatus {
+    let saved = load();
+    OtaStatus {
+        running_slot: Slot::running().map(Slot::as_str),
+        state: state().as_str(),
+        target_slot: saved.and_then(|record| record.slot).map(Slot::as_str),
+        trial_boots: saved.map_or(0, |record| record.trial_boots),
+        rollback_reason: saved.and_then(|record| record.reason.as_str()),
+    }
+}
+
+/// Legge l'ultimo stato salvato
+fn load() -> Option<OtaRecord> {
+    let record = OTA_STORE.latest(OTA_KEY)?;
+    let payload = record.payload;
+    if payload.len() != 2 && payload.len() != RECORD_LEN {
+        return None;
+    }
+    Some(OtaRecord {
+        state: OtaState::from_u8(payload[0]),
+        slot: Slot::from_u8(payload[1]),
+        trial_boots: payload.get(2).copied().unwrap_or(0),
+        reason: RollbackReason::from_u8(payload.get(3).copied().unwrap_or(0)),
+    })
+}
+
+/// Aggiorna lo stato e lo salva in flash
+///
+/// # Argomenti
+/// * `to` - Nuovo stato
+/// * `slot` - Slot interessato
+/// * `trial_boots` - Avvii dell'immagine in prova
+/// * `reason` - Causa del rollback, None negli altri stati
+fn save(to: OtaState, slot: Slot, trial_boots: u8, reason: RollbackReason) {
+    STATE.store(to as u8, Ordering::Relaxed);
+    let record = [to as u8, slot as u8, trial_boots, reason as u8];
+    if let Err(e) = OTA_STORE.append(OTA_KEY, &record) {
+        log::error!("OTA: salvataggio dello stato fallito: {:?}", e);
+    }
+}
+
+/// Confronta lo stato salvato con lo slot avviato dal boot ROM: da chiamare
+/// una sola volta all'inizio del main, dopo flash_store::init
+///
+/// # Argomenti
+/// * `panicked` - true se panic_persist conserva il messaggio di un panic
+///   dell'esecuzione precedente
+///
+/// # Ritorna
+/// * bool - true se è in corso un avvio di prova
+pub fn boot(panicked: bool) -> bool {
+    let Some(saved) = load() else {
+        return false;
+    };
+    STATE.store(saved.state as u8, Ordering::Relaxed);
+    let (OtaState::Pending | OtaState::Trial, Some(target)) = (saved.state, saved.slot) else {
+        return false;
+    };
+    if Slot::running() == Some(target) {
+        let trial_boots = saved.trial_boots.saturating_add(1);
+        log::warn!(
+            "OTA: avvio di prova {} dello slot {}",
+            trial_boots,
+            target.as_str()
+        );
+        save(OtaState::Trial, target, trial_boots, RollbackReason::None);
+        true
+    } else {
+        // Il panic conservato è dell'immagine in prova solo se è stata avviata
+        let reason = if panicked && saved.state == OtaState::Trial {
+            RollbackReason::Panic
+        } else {
+            RollbackReason::Unconfirmed
+        };
+        log::error!(
+            "OTA: lo slot {} non è stato confermato ({}), rollback allo slot {}",
+            target.as_str(),
+            reason.as_str().unwrap_or_default(),
+            target.other().as_str()
+        );
+        save(OtaState::RolledBack, target, saved.trial_boots, reason);
+        false
+    }
+}
+
+#[embassy_executor::task]
+/// Task dell'avvio di prova: conferma l'immagine se il sistema funziona
+/// regolarmente, altrimenti riavvia per tornare allo slot precedente
+///
+/// # Ritorna
+/// * () - Ritorna dopo la conferma
+pub async fn ota_task() {
+    let started = Instant::now();
+    let mut healthy_since: Option<Instant> = None;
+    loop {
+        Timer::after_secs(1).await;
+        crate::tasks::touch("ota");
+        let healthy =
+            crate::http_stats::is_listening() && crate::supervisor::is_healthy() && wifi::is_up();
+        if !healthy {
+            healthy_since = None;
+        } else if healthy_since.get_or_insert_with(Instant::now).elapsed() >= CONFIRM_AFTER {
+            confirm();
+            return;
+        }
+        if started.elapsed() >= TRIAL_TIMEOUT {
+            log::error!("OTA: immagine non confermata entro il tempo massimo");
+            crate::system::reboot().await;
+        }
+    }
+}
+
+/// Conferma l'immagine in prova e invalida quella dell'altro slot, così il
+/// boot ROM avvierà sempre l'immagine confermata
+fn confirm() {
+    let Some(running) = Slot::running() else {
+        return;
+    };
+    let result = flash_store::with_flash(|flash| {
+        let mut workarea = [0u8; ERASE_SIZE];
+        // SAFETY: funzione della bootrom RP2350; riscrive il primo settore
+        // dell'immagine in esecuzione togliendo il flag TBYB. Il driver
+        // della flash è preso da with_flash, quindi nessun'altra operazione
+        // è in corso, e with_rom_flash ferma il core 1 e gli interrupt.
+        let rc = flash_store::with_rom_flash(|| unsafe {
+            embassy_rp::rom_data::explicit_buy(workarea.as_mut_ptr(), workarea.len() as u32)
+        });
+        if rc != 0 {
+            log::error!("OTA: explicit_buy fallita ({})", rc);
+            return Ok(false);
+        }
+        let other = running.other().offset();
+        flash
+            .blocking_erase(other, other + ERASE_SIZE as u32)
+            .map_err(StoreError::Flash)?;
+        Ok(true)
+    });
+    match result {
+        Ok(true) => {
+            log::info!("OTA: slot {} confermato", running.as_str());
+            let trial_boots = load().map_or(1, |record| record.trial_boots);
+            save(
+                OtaState::Confirmed,
+                running,
+                trial_boots,
+                RollbackReason::None,
+            );
+        }
+        Ok(false) => {}
+        Err(e) => log::error!("OTA: conferma fallita: {:?}", e),
+    }
+}
+
+/// Imposta il flag TBYB nell'IMAGE_DEF del primo settore dell'immagine
+///
+/// # Argomenti
+/// * `sector` - Primo settore dell'immagine
+///
+/// # Ritorna
+/// * bool - false se il settore non contiene un IMAGE_DEF
+fn mark_try_before_you_buy(sector: &mut [u8]) -> bool {
+    let read_u32 = |at: usize| {
+        u32::from_le_bytes([sector[at], sector[at + 1], sector[at + 2], sector[at + 3]])
+    };
+    let Some(item) = (0..sector.len() - 8)
+        .step_by(4)
+        .find(|&at| read_u32(at) == BLOCK_MARKER_START && sector[at + 4] == ITEM_IMAGE_TYPE)
+        .map(|at| at + 4)
+    else {
+        return false;
+    };
+    let flags = read_u32(item) | IMAGE_TYPE_EXE_TBYB;
+    sector[item..item + 4].copy_from_slice(&flags.to_le_bytes());
+    true
+}
+
+/// Immagine in scrittura: settore corrente e primo settore, scritto per ultimo
+struct Upload {
+    slot: Slot,
+    sector: [u8; ERASE_SIZE],
+    first: [u8; ERASE_SIZE],
+    /// Byte presenti nel settore corrente
+    fill: usize,
+    /// Settori completati
+    sectors: u32,
+}
+
+impl Upload {
+    const fn new() -> Self {
+        Self {
+            slot: Slot::A,
+            sector: [0xFF; ERASE_SIZE],
+            first: [0xFF; ERASE_SIZE],
+            fill: 0,
+            sectors: 0,
+        }
+    }
+
+    /// Cancella e scrive un settore dello slot
+    fn program(slot: Slot, index: u32, data: &[u8; ERASE_SIZE]) -> Result<(), StoreError> {
+        let offset = slot.offset() + index * ERASE_SIZE as u32;
+        flash_store::with_flash(|flash| {
+            flash
+                .blocking_erase(offset, offset + ERASE_SIZE as u32)
+                .and_then(|()| flash.blocking_write(offset, data))
+                .map_err(StoreError::Flash)
+        })
+    }
+
+    /// Completa il settore corrente
+    fn store_sector(&mut self) -> Result<(), StoreError> {
+        self.sector[self.fill..].fill(0xFF);
+        if self.sectors == 0 {
+            self.first = self.sector;
+        } else {
+            Self::program(self.slot, self.sectors, &self.sector)?;
+        }
+        self.sectors += 1;
+        self.fill = 0;
+        Ok(())
+    }
+
+    /// Aggiunge byte ricevuti all'immagine
+    fn push(&mut self, mut data: &[u8]) -> Result<(), StoreError> {
+        while !data.is_empty() {
+            let n = data.len().min(ERASE_SIZE - self.fill);
+            self.sector[self.fill..self.fill + n].copy_from_slice(&data[..n]);
+            self.fill += n;
+            data = &data[n..];
+            if self.fill == ERASE_SIZE {
+                self.store_sector()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Completa l'immagine scrivendo il primo settore con il flag TBYB
+    fn finish(&mut self) -> Result<(), OtaError> {
+        if self.fill > 0 {
+            self.store_sector()?;
+        }
+        if self.sectors == 0 || !mark_try_before_you_buy(&mut self.first) {
+            return Err(OtaError::NotImage);
+        }
+        Self::program(self.slot, 0, &self.first)?;
+        Ok(())
+    }
+}
+
+/// Buffer dell'immagine in scrittura. Il lock è tenuto da [`receive`] per
+/// tutto l'aggiornamento (uno alla volta) e rilasciato anche se la richiesta
+/// viene abbandonata; i settori vengono scritti senza sezioni critiche
+/// attorno al buffer, che restano solo attorno alle chiamate alla flash
+/// (vedi flash_store::with_flash)
+static UPLOAD: Mutex<CriticalSectionRawMutex, Upload> = Mutex::new(Upload::new());
+
+/// Riceve un'immagine e la scrive nello slot non in esecuzione
+///
+/// # Argomenti
+/// * `reader` - Corpo della richiesta
+/// * `len` - Lunghezza dichiarata dell'immagine
+///
+/// # Ritorna
+/// * Result<Slot, OtaError> - Slot scritto
+async fn receive<R: Read>(reader: &mut R, len: usize) -> Result<Slot, OtaError> {
+    let slot = Slot::running().ok_or(OtaError::NoPartitions)?.other();
+    if len > SLOT_SIZE as usize {
+        return Err(OtaError::TooLarge);
+    }
+    // Il guard libera l'aggiornamento quando la funzione termina, anche se il
+    // future viene abbandonato a metà (es. connessione scaduta)
+    let Ok(mut upload) = UPLOAD.try_lock() else {
+        return Err(OtaError::Busy);
+    };
+    upload.slot = slot;
+    upload.fill = 0;
+    upload.sectors = 0;
+
+    // L'immagine precedente nello slot non deve restare avviabile a metà scrittura
+    let offset = slot.offset();
+    flash_store::with_flash(|flash| {
+        flash
+            .blocking_erase(offset, offset + ERASE_SIZE as u32)
+            .map_err(StoreError::Flash)
+    })?;
+
+    let mut received = 0;
+    let mut chunk = [0u8; 256];
+    while received < len {
+        let n = match reader.read(&mut chunk).await {
+            Ok(0) | Err(_) => return Err(OtaError::Incomplete),
+            Ok(n) => n.min(len - received),
+        };
+        upload.push(&chunk[..n])?;
+        received += n;
+    }
+    upload.finish()?;
+    Ok(slot)
+}
+
+/// Servizio di `POST /api/ota`: il corpo è l'immagine binaria del firmware
+struct OtaService;
+
+impl RequestHandlerService<AppState> for OtaService {
+    async fn call_request_handler_service<R: Read, W: ResponseWriter<Error = R::Error>>(
+        &self,
+        _state: &AppState,
+        _path_parameters: (),
+        mut request: Request<'_, R>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let strings = Lang::negotiate(&request.parts).strings();
+        let len = request.body_connection.body().content_length();
+        log::info!("OTA: ricezione di un'immagine di {} byte", len);
+        let result = receive(&mut request.body_connection.body().reader(), len).await;
+        let connection = request.body_connection.finalize().await?;
+        let (status, message): (StatusCode, heapless::String<64>) = match &result {
+            Ok(slot) => {
+                save(OtaState::Pending, *slot, 0, RollbackReason::None);
+                (
+                    StatusCode::OK,
+                    errors::fit(format_args!(
+                        "{} {}\n",
+                        strings.ota_rebooting,
+                        slot.as_str()
+                    )),
+                )
+            }
+            Err(e) => {
+                log::error!("OTA: aggiornamento fallito: {:?}", e);
+                let status = match e {
+                    OtaError::Busy => StatusCode::CONFLICT,
+                    OtaError::Flash(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                (
+                    status,
+                    errors::fit(format_args!("{}: {:?}\n", strings.ota_error, e)),
+                )
+            }
+        };
+        let sent = (status, message.as_str())
+            .write_to(connection, response_writer)
+            .await;
+        // Il riavvio nel nuovo slot avviene dopo l'invio della risposta e la
+        // fine delle altre richieste in corso (vedi drain.rs)
+        if let Ok(slot) = result {
+            crate::drain::request(crate::drain::DrainAction::FlashUpdate(slot.offset()));
+        }
+        sent
+    }
+}
+
+/// Rotte dell'aggiornamento: `GET/POST /api/ota`
+pub struct OtaRoutes;
+
+impl Routes for OtaRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router.route(
+            "/api/ota",
+            get(|| async move { Json(status()) }).post_service(OtaService),
+        )
+    }
+}
@@ -0,0 +1,150 @@
+//! overload.rs - Risposta rapida quando tutti i worker sono occupati.
+//!
+//! Con embassy-net una connessione viene accettata solo se c'è un socket in
+//! ascolto: quando tutti i worker del web server sono occupati un nuovo
+//! client resta in attesa finché il SYN va in timeout. Una piccola coda di
+//! socket di riserva ([`ServerResources::OVERFLOW`]) si mette in ascolto solo
+//! quando nessun worker è libero, accetta la connessione e risponde subito
+//! con un 503 e `Retry-After`: sotto carico il client riceve una risposta
+//! certa invece di un'attesa indefinita. I rifiuti sono contati nel campo
+//! `overloaded` di `/api/http` (vedi http_stats.rs).
+//!
+//! [`reject`] è usata anche per la pagina di manutenzione (vedi drain.rs) e
+//! per i client oltre i limiti di rate_limit.rs.
+
+use crate::configuration::{OverflowQueue, ServerResources};
+use crate::drain;
+use crate::http_stats;
+use crate::i18n::Lang;
+use embassy_futures::select::{Either, select};
+use embassy_net::tcp::TcpSocket;
+use embassy_time::{Duration, Timer, with_timeout};
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Secondi suggeriti ai client prima di riprovare
+pub const RETRY_AFTER_SECS: u32 = 5;
+
+/// Riga di stato delle risposte con tutti i worker occupati
+pub const SERVICE_UNAVAILABLE: &str = "503 Service Unavailable";
+
+/// Attesa della richiesta del client prima della risposta
+const READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Intervallo del controllo dei worker liberi
+const POLL: Duration = Duration::from_millis(10);
+
+/// Worker in attesa di una connessione
+static IDLE_WORKERS: AtomicU32 = AtomicU32::new(0);
+
+/// Worker in attesa di una connessione: conta tra i liberi finché esiste
+pub struct Idle;
+
+impl Idle {
+    /// Segna l'inizio dell'attesa di una connessione
+    pub fn enter() -> Self {
+        IDLE_WORKERS.fetch_add(1, Ordering::Relaxed);
+        Idle
+    }
+}
+
+impl Drop for Idle {
+    fn drop(&mut self) {
+        IDLE_WORKERS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Ritorna true se almeno un worker è in attesa di una connessione
+fn worker_idle() -> bool {
+    IDLE_WORKERS.load(Ordering::Relaxed) > 0
+}
+
+/// Attende che un worker torni libero
+async fn worker_freed() {
+    while !worker_idle() {
+        Timer::after(POLL).await;
+    }
+}
+
+/// Risponde con un errore e `Retry-After`, poi chiude la connessione
+///
+/// # Argomenti
+/// * `socket` - Connessione appena accettata
+/// * `status` - Riga di stato, es. `503 Service Unavailable`
+/// * `retry_after_secs` - Secondi suggeriti prima di riprovare
+/// * `content_type` - Tipo del corpo della risposta
+/// * `body` - Corpo della risposta
+pub async fn reject(
+    socket: &mut TcpSocket<'_>,
+    status: &str,
+    retry_after_secs: u32,
+    content_type: &str,
+    body: &str,
+) {
+    // La richiesta viene letta (anche solo in parte) prima di rispondere,
+    // così il client non riceve un reset con dati non letti
+    let mut request = [0u8; 256];
+    let _ = with_timeout(READ_TIMEOUT, socket.read(&mut request)).await;
+    let head: heapless::String<192> = crate::errors::fit(format_args!(
+        "HTTP/1.1 {}\r\nRetry-After: {}\r\n\
+         Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        retry_after_secs,
+        content_type,
+        body.len()
+    ));
+    for part in [head.as_bytes(), body.as_bytes()] {
+        let mut sent = 0;
+        while sent < part.len() {
+            match socket.write(&part[sent..]).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => sent += n,
+            }
+        }
+    }
+    let _ = socket.flush().await;
+    socket.close();
+}
+
+#[embassy_executor::task(pool_size = ServerResources::OVERFLOW.sockets)]
+/// Socket di riserva: in ascolto solo quando tutti i worker sono occupati
+///
+/// # Argomenti
+/// * `id` - ID del socket di riserva
+/// * `stack` - Stack di rete
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn overflow_task(id: usize, stack: embassy_net::Stack<'static>) {
+    const QUEUE: OverflowQueue = ServerResources::OVERFLOW;
+    let mut tcp_rx_buffer = [0; QUEUE.tcp_rx];
+    let mut tcp_tx_buffer = [0; QUEUE.tcp_tx];
+    loop {
+        crate::tasks::touch("overflow");
+        // Finché c'è un worker libero le connessioni vanno a lui; durante lo
+        // svuotamento risponde il worker riservato (vedi drain.rs)
+        if worker_idle() || drain::is_draining() {
+            Timer::after(POLL).await;
+            continue;
+        }
+        let mut socket = TcpSocket::new(stack, &mut tcp_rx_buffer, &mut tcp_tx_buffer);
+        // Se un worker si libera prima di una connessione, il socket viene
+        // chiuso e le nuove connessioni tornano ai worker
+        match select(socket.accept(80), worker_freed()).await {
+            Either::First(Ok(())) => {
+                http_stats::record_overload();
+                log::warn!("overflow {}: worker tutti occupati, risposta 503", id);
+                let body = Lang::configured().strings().server_busy;
+                reject(
+                    &mut socket,
+                    SERVICE_UNAVAILABLE,
+                    RETRY_AFTER_SECS,
+                    "text/plain; charset=utf-8",
+                    body,
+                )
+                .await;
+            }
+            Either::First(Err(e)) => log::warn!("overflow {}: accept fallita: {:?}", id, e),
+            Either::Second(()) => {}
+        }
+    }
+}
@@ -1,32 +1,208 @@
-use embassy_rp::peripherals::PIO1;
+//! pio.rs - Programmi del PIO1: timer ad alta risoluzione (sm2) e driver
+//! delle strisce di LED WS2812 (sm0).
+//!
+//! La state machine sm2 conta i microsecondi tra un comando di avvio e uno di
+//! arresto scritti dalla CPU nella sua TX FIFO, e alla fine spinge il
+//! conteggio nella RX FIFO, da dove viene letto tramite DMA. La misura non
+//! dipende dalla latenza degli interrupt né dall'executor.
+//!
+//! Il ciclo di conteggio dura 3 istruzioni e il clock della state machine è
+//! diviso in modo che un ciclo duri esattamente 1 µs: la risoluzione è
+//! quindi di 1 µs (con il divisore frazionario dell'RP2040 il singolo ciclo
+//! oscilla di qualche ns, ma la media resta esatta) e la durata massima
+//! misurabile è di circa 71 minuti.
+//!
+//! La state machine sm0 genera il segnale a 800 kHz dei WS2812: ogni bit
+//! dura 10 cicli, alto per 2 (zero) o 7 (uno). I colori arrivano nella TX
+//! FIFO via DMA, 24 bit per LED in ordine GRB (vedi led_strip.rs).
+use embassy_rp::Peri;
+use embassy_rp::clocks::clk_sys_freq;
+use embassy_rp::peripherals::{DMA_CH3, DMA_CH4, PIO1};
 use embassy_rp::pio::program::pio_asm;
-use embassy_rp::pio::{Common, Config, Irq, StateMachine};
+use embassy_rp::pio::{
+    Common, Config, Direction, FifoJoin, Pin, PioPin, ShiftConfig, ShiftDirection, StateMachine,
+    StatusSource,
+};
+use embassy_time::{Duration, Timer, with_timeout};
 use fixed::traits::ToFixed;
-use fixed_macro::types::U56F8;
-pub fn setup_pio_task_sm2<'a>(pio: &mut Common<'a, PIO1>, sm: &mut StateMachine<'a, PIO1, 2>) {
-    // Setup sm2
-
-    // Repeatedly trigger IRQ 3
-    let prg = pio_asm!(
-        ".origin 0",
-        ".wrap_target",
-        "set x,10",
-        "delay:",
-        "jmp x-- delay [15]",
-        "irq 3 [15]",
-        ".wrap",
-    );
-    let mut cfg = Config::default();
-    cfg.use_program(&pio.load_program(&prg.program), &[]);
-    cfg.clock_divider = (U56F8!(125_000_000) / 2000).to_fixed();
-    sm.set_config(&cfg);
+use fixed::types::U56F8;
+
+/// Cicli di clock della state machine per ogni iterazione del conteggio
+const CYCLES_PER_TICK: u64 = 3;
+
+/// Risoluzione del timer in microsecondi
+pub const RESOLUTION_US: u32 = 1;
+
+/// Attesa massima del conteggio dopo il comando di arresto
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Cicli di clock della state machine per ogni bit dei WS2812
+const CYCLES_PER_BIT: u64 = 10;
+
+/// Frequenza dei bit dei WS2812
+const WS2812_BIT_HZ: u64 = 800_000;
+
+/// Attesa dopo il DMA che chiude un frame dei WS2812: la FIFO unita e l'OSR
+/// contengono ancora fino a 9 LED (270 µs), poi la linea resta bassa per
+/// almeno 50 µs
+const WS2812_RESET: Duration = Duration::from_micros(350);
+
+/// Timer sulla state machine sm2 del PIO1
+pub struct PioTimer {
+    sm: StateMachine<'static, PIO1, 2>,
+    dma: Peri<'static, DMA_CH3>,
+    origin: u8,
+    running: bool,
+}
+
+impl PioTimer {
+    /// Carica il programma di conteggio e configura sm2
+    ///
+    /// # Argomenti
+    /// * `pio` - PIO1
+    /// * `sm` - State machine sm2
+    /// * `dma` - Canale DMA per la lettura della RX FIFO
+    ///
+    /// # Ritorna
+    /// * PioTimer - Timer pronto, in attesa del comando di avvio
+    pub fn new(
+        pio: &mut Common<'static, PIO1>,
+        mut sm: StateMachine<'static, PIO1, 2>,
+        dma: Peri<'static, DMA_CH3>,
+    ) -> Self {
+        let prg = pio_asm!(
+            ".origin 0",
+            ".wrap_target",
+            // Avvio: attende un comando dalla CPU
+            "pull block",
+            "mov x, ~null",
+            // Conteggio: 3 cicli per iterazione, finché la TX FIFO resta vuota
+            "count:",
+            "mov y, status",
+            "jmp !y stop",
+            "jmp x-- count",
+            // Arresto (o overflow): consuma il comando e spinge i tick contati
+            "stop:",
+            "pull block",
+            "mov isr, ~x",
+            "push block",
+            ".wrap",
+        );
+        let program = pio.load_program(&prg.program);
+        let mut cfg = Config::default();
+        cfg.use_program(&program, &[]);
+        // status = tutti 1 se la TX FIFO contiene meno di 1 parola
+        cfg.status_sel = StatusSource::TxFifoLevel;
+        cfg.status_n = 1;
+        // Un'iterazione del conteggio ogni microsecondo
+        cfg.clock_divider =
+            (U56F8::from_num(clk_sys_freq()) / (CYCLES_PER_TICK * 1_000_000)).to_fixed();
+        sm.set_config(&cfg);
+        sm.set_enable(true);
+        Self {
+            sm,
+            dma,
+            origin: program.origin,
+            running: false,
+        }
+    }
+
+    /// Avvia una misura, annullando quella eventualmente in corso
+    pub fn start(&mut self) {
+        // Riporta la state machine all'inizio del programma con le FIFO vuote:
+        // una misura interrotta non lascia conteggi vecchi
+        self.sm.set_enable(false);
+        self.sm.clear_fifos();
+        self.sm.restart();
+        // SAFETY: salto all'inizio del programma caricato da new
+        unsafe { self.sm.exec_jmp(self.origin) };
+        self.sm.set_enable(true);
+        self.running = self.sm.tx().try_push(0);
+    }
+
+    /// Termina la misura in corso
+    ///
+    /// # Ritorna
+    /// * Option<u32> - Microsecondi trascorsi dall'avvio, None se nessuna misura era in corso
+    pub async fn stop(&mut self) -> Option<u32> {
+        if !core::mem::take(&mut self.running) || !self.sm.tx().try_push(0) {
+            return None;
+        }
+        let mut ticks = [0u32; 1];
+        let read = self
+            .sm
+            .rx()
+            .dma_pull(self.dma.reborrow(), &mut ticks, false);
+        with_timeout(READ_TIMEOUT, read).await.ok()?;
+        Some(ticks[0] * RESOLUTION_US)
+    }
 }
 
-#[embassy_executor::task]
-pub async fn pio_task_sm2(mut irq: Irq<'static, PIO1, 3>, shared_sm2: crate::SharedSm2) {
-    shared_sm2.0.lock().await.set_enable(true);
-    loop {
-        irq.wait().await;
-        log::info!("--> Solving...");
+/// Driver WS2812 sulla state machine sm0 del PIO1
+pub struct PioWs2812 {
+    sm: StateMachine<'static, PIO1, 0>,
+    dma: Peri<'static, DMA_CH4>,
+    /// Pin dei dati, tenuto per tutta la durata del driver
+    _pin: Pin<'static, PIO1>,
+}
+
+impl PioWs2812 {
+    /// Carica il programma dei WS2812 e configura sm0
+    ///
+    /// # Argomenti
+    /// * `pio` - PIO1
+    /// * `sm` - State machine sm0
+    /// * `dma` - Canale DMA per la scrittura della TX FIFO
+    /// * `pin` - GPIO della linea dati
+    ///
+    /// # Ritorna
+    /// * PioWs2812 - Driver pronto, con la linea bassa
+    pub fn new(
+        pio: &mut Common<'static, PIO1>,
+        mut sm: StateMachine<'static, PIO1, 0>,
+        dma: Peri<'static, DMA_CH4>,
+        pin: Peri<'static, impl PioPin>,
+    ) -> Self {
+        let prg = pio_asm!(
+            ".side_set 1",
+            ".wrap_target",
+            // 3 cicli bassi, poi 2 alti; il bit decide i 5 cicli restanti
+            "bitloop:",
+            "out x, 1 side 0 [2]",
+            "jmp !x do_zero side 1 [1]",
+            "jmp bitloop side 1 [4]",
+            "do_zero:",
+            "nop side 0 [4]",
+            ".wrap",
+        );
+        let program = pio.load_program(&prg.program);
+        let pin = pio.make_pio_pin(pin);
+        sm.set_pin_dirs(Direction::Out, &[&pin]);
+        let mut cfg = Config::default();
+        cfg.use_program(&program, &[&pin]);
+        // 24 bit per LED, dal più significativo, ricaricati automaticamente
+        cfg.shift_out = ShiftConfig {
+            auto_fill: true,
+            threshold: 24,
+            direction: ShiftDirection::Left,
+        };
+        cfg.fifo_join = FifoJoin::TxOnly;
+        cfg.clock_divider =
+            (U56F8::from_num(clk_sys_freq()) / (CYCLES_PER_BIT * WS2812_BIT_HZ)).to_fixed();
+        sm.set_config(&cfg);
+        sm.set_enable(true);
+        Self { sm, dma, _pin: pin }
+    }
+
+    /// Invia un frame e attende la pausa che lo rende visibile
+    ///
+    /// # Argomenti
+    /// * `words` - Un colore GRB per LED, nei 24 bit alti
+    pub async fn write(&mut self, words: &[u32]) {
+        self.sm
+            .tx()
+            .dma_push(self.dma.reborrow(), words, false)
+            .await;
+        Timer::after(WS2812_RESET).await;
     }
 }
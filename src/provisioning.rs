@@ -0,0 +1,486 @@
+//! provisioning.rs - Configurazione del WiFi da un access point della pico.
+//!
+//! Se il join alla rete configurata fallisce `PROVISION_AFTER_FAILURES`
+//! volte di fila (vedi wifi.rs), [`run`] lascia la modalità client e avvia
+//! il CYW43 come access point `<HOSTNAME>-setup` (aperto, o WPA2 con
+//! `PROVISION_AP_PASSWORD`), con indirizzo 192.168.4.1/24. Sull'access point
+//! girano:
+//! * un server DHCP minimo, che assegna ai client gli indirizzi da
+//!   192.168.4.2 in poi, con la pico come gateway e server DNS;
+//! * un server DNS "captive", che risponde a ogni domanda A con l'indirizzo
+//!   della pico, così i telefoni aprono da soli la pagina di accesso;
+//! * il web server, in cui [`ProvisionLayer`] reindirizza ogni richiesta
+//!   alla pagina `/provision`, tranne l'elenco delle reti visibili
+//!   (`/wifi/scan`, vedi wifi_scan.rs) e il form di `/login`, necessario se
+//!   le pagine sono protette da password (vedi auth.rs).
+//!
+//! Il form di `/provision` salva `WIFI_NETWORK` e `WIFI_PASSWORD` in flash
+//! (vedi config_store.rs) e riavvia la pico, che al boot successivo si
+//! collega alla nuova rete. Fuori da questa modalità `POST /provision`
+//! risponde 403.
+
+use crate::SharedControl;
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::assets::AcceptGzip;
+use crate::config_store;
+use crate::drain::{self, DrainAction};
+use crate::i18n::Lang;
+use crate::request_path;
+use crate::session;
+use crate::utility::{get_hostname, get_provision_ap_password};
+use crate::wifi_scan;
+use embassy_futures::join::join;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{ConfigV4, IpEndpoint, Ipv4Address, Ipv4Cidr, Stack, StaticConfigV4};
+use picoserve::ResponseSent;
+use picoserve::io::Read;
+use picoserve::request::RequestParts;
+use picoserve::response::{IntoResponse, Redirect, ResponseWriter, StatusCode};
+use picoserve::routing::{Layer, Next, PathRouter, get};
+use portable_atomic::{AtomicBool, Ordering};
+
+/// Indirizzo della pico sull'access point
+const AP_ADDRESS: [u8; 4] = [192, 168, 4, 1];
+
+/// Prefisso della rete dell'access point
+const AP_PREFIX: u8 = 24;
+
+/// Canale WiFi dell'access point
+const AP_CHANNEL: u8 = 6;
+
+/// Lunghezza minima di una passphrase WPA2
+const WPA2_MIN_PASSWORD: usize = 8;
+
+/// Client serviti dal DHCP: gli indirizzi vanno da 192.168.4.2 in poi
+const LEASES: usize = 8;
+
+/// Durata di un lease DHCP (secondi)
+const LEASE_SECS: u32 = 3600;
+
+/// Durata di validità delle risposte DNS nelle cache dei client
+const DNS_TTL_SECS: u32 = 60;
+
+/// Dimensione massima di un pacchetto DHCP o DNS
+const PACKET_CAPACITY: usize = 576;
+
+/// Dimensione di una risposta DHCP (minimo BOOTP di 300 byte)
+const DHCP_REPLY_SIZE: usize = 300;
+
+const DHCP_SERVER_PORT: u16 = 67;
+const DHCP_CLIENT_PORT: u16 = 68;
+const DNS_PORT: u16 = 53;
+
+/// Magic cookie delle opzioni DHCP
+const DHCP_MAGIC: [u8; 4] = [99, 130, 83, 99];
+/// Inizio delle opzioni, dopo l'intestazione BOOTP e il magic cookie
+const DHCP_OPTIONS: usize = 240;
+
+const DHCP_DISCOVER: u8 = 1;
+const DHCP_OFFER: u8 = 2;
+const DHCP_REQUEST: u8 = 3;
+const DHCP_ACK: u8 = 5;
+
+/// Pagina del form, l'unica raggiungibile durante la configurazione
+/// insieme alla scansione delle reti (vedi wifi_scan.rs) e al login
+const PROVISION_PATH: &str = "/provision";
+
+/// true mentre la pico è un access point di configurazione
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Ritorna true se la pico è in modalità di configurazione
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Nome dell'access point: `<HOSTNAME>-setup`
+///
+/// # Ritorna
+/// * heapless::String<40> - SSID dell'access point
+fn ap_ssid() -> heapless::String<40> {
+    let hostname = get_hostname();
+    let host = match hostname.trim() {
+        "" => "picoweb",
+        host => host,
+    };
+    crate::errors::fit(format_args!("{}-setup", host))
+}
+
+/// Avvia l'access point di configurazione e ne serve i client; lo stack
+/// passa all'indirizzo statico 192.168.4.1
+///
+/// # Argomenti
+/// * `control` - Controller condiviso del CYW43
+/// * `stack` - Stack di rete
+///
+/// # Ritorna
+/// * ! - Non ritorna mai: si esce dalla modalità con il riavvio chiesto dal form
+pub async fn run(control: SharedControl, stack: Stack<'static>) -> ! {
+    let ssid = ap_ssid();
+    let password = get_provision_ap_password();
+    let password = password.trim();
+    {
+        let mut control = control.0.lock().await;
+        control.leave().await;
+        if password.len() >= WPA2_MIN_PASSWORD {
+            control.start_ap_wpa2(&ssid, password, AP_CHANNEL).await;
+        } else {
+            if !password.is_empty() {
+                log::warn!(
+                    "Configurazione WiFi: password sotto {} caratteri, access point aperto",
+                    WPA2_MIN_PASSWORD
+                );
+            }
+            control.start_ap_open(&ssid, AP_CHANNEL).await;
+        }
+    }
+    let [a, b, c, d] = AP_ADDRESS;
+    stack.set_config_v4(ConfigV4::Static(StaticConfigV4 {
+        address: Ipv4Cidr::new(Ipv4Address::new(a, b, c, d), AP_PREFIX),
+        gateway: None,
+        dns_servers: heapless::Vec::new(),
+    }));
+    ACTIVE.store(true, Ordering::Relaxed);
+    log::warn!(
+        "Configurazione WiFi: access point '{}' attivo, form su http://{}.{}.{}.{}{}",
+        ssid,
+        a,
+        b,
+        c,
+        d,
+        PROVISION_PATH
+    );
+    join(dhcp_server(stack), dns_server(stack)).await;
+    // I server non terminano: resta solo da attendere il riavvio
+    loop {
+        embassy_time::Timer::after_secs(3600).await;
+    }
+}
+
+/// Cerca un'opzione nelle opzioni di un pacchetto DHCP
+///
+/// # Argomenti
+/// * `packet` - Pacchetto ricevuto
+/// * `code` - Codice dell'opzione
+///
+/// # Ritorna
+/// * Option<&[u8]> - Valore dell'opzione, None se assente
+fn dhcp_option(packet: &[u8], code: u8) -> Option<&[u8]> {
+    let mut pos = DHCP_OPTIONS;
+    loop {
+        match *packet.get(pos)? {
+            // Riempimento
+            0 => pos += 1,
+            // Fine delle opzioni
+            255 => return None,
+            found => {
+                let len = *packet.get(pos + 1)? as usize;
+                let value = packet.get(pos + 2..pos + 2 + len)?;
+                if found == code {
+                    return Some(value);
+                }
+                pos += 2 + len;
+            }
+        }
+    }
+}
+
+/// Costruisce la risposta a un DISCOVER (OFFER) o a un REQUEST (ACK)
+///
+/// # Argomenti
+/// * `request` - Pacchetto del client
+/// * `kind` - Tipo della risposta (DHCP_OFFER o DHCP_ACK)
+/// * `address` - Indirizzo assegnato al client
+/// * `reply` - Buffer della risposta, azzerato
+fn dhcp_reply(request: &[u8], kind: u8, address: [u8; 4], reply: &mut [u8; DHCP_REPLY_SIZE]) {
+    // op, tipo di hardware (Ethernet), lunghezza del MAC, hop
+    reply[..4].copy_from_slice(&[2, 1, 6, 0]);
+    // ID della transazione
+    reply[4..8].copy_from_slice(&request[4..8]);
+    // Flag del client (es. risposta broadcast)
+    reply[10..12].copy_from_slice(&request[10..12]);
+    reply[16..20].copy_from_slice(&address);
+    reply[20..24].copy_from_slice(&AP_ADDRESS);
+    // Indirizzo hardware del client
+    reply[28..44].copy_from_slice(&request[28..44]);
+    reply[236..240].copy_from_slice(&DHCP_MAGIC);
+    let lease = LEASE_SECS.to_be_bytes();
+    let mask = u32::MAX
+        .checked_shl(32 - AP_PREFIX as u32)
+        .unwrap_or(0)
+        .to_be_bytes();
+    let [a, b, c, d] = AP_ADDRESS;
+    let options: [&[u8]; 7] = [
+        &[53, 1, kind],
+        &[54, 4, a, b, c, d],
+        &[51, 4, lease[0], lease[1], lease[2], lease[3]],
+        &[1, 4, mask[0], mask[1], mask[2], mask[3]],
+        &[3, 4, a, b, c, d],
+        &[6, 4, a, b, c, d],
+        &[255],
+    ];
+    let mut pos = DHCP_OPTIONS;
+    for option in options {
+        reply[pos..pos + option.len()].copy_from_slice(option);
+        pos += option.len();
+    }
+}
+
+/// Server DHCP dell'access point: un indirizzo fisso per ogni MAC, finché
+/// ci sono posti; oltre, i lease più vecchi vengono riassegnati
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+async fn dhcp_server(stack: Stack<'static>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; PACKET_CAPACITY];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0; PACKET_CAPACITY];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    if let Err(e) = socket.bind(DHCP_SERVER_PORT) {
+        log::error!("Configurazione WiFi: bind del DHCP fallito: {:?}", e);
+        return;
+    }
+    let broadcast = IpEndpoint::new(Ipv4Address::BROADCAST.into(), DHCP_CLIENT_PORT);
+    let mut leases: heapless::Vec<[u8; 6], LEASES> = heapless::Vec::new();
+    let mut next_reuse = 0;
+    let mut request = [0; PACKET_CAPACITY];
+    loop {
+        let Ok((len, _)) = socket.recv_from(&mut request).await else {
+            continue;
+        };
+        let request = &request[..len];
+        // Solo richieste BOOTP con le opzioni DHCP
+        if len < DHCP_OPTIONS || request[0] != 1 || request[236..240] != DHCP_MAGIC {
+            continue;
+        }
+        let kind = match dhcp_option(request, 53) {
+            Some([DHCP_DISCOVER]) => DHCP_OFFER,
+            Some([DHCP_REQUEST]) => DHCP_ACK,
+            _ => continue,
+        };
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&request[28..34]);
+        let index = match leases.iter().position(|lease| *lease == mac) {
+            Some(index) => index,
+            None => match leases.push(mac) {
+                Ok(()) => leases.len() - 1,
+                Err(mac) => {
+                    let index = next_reuse;
+                    leases[index] = mac;
+                    next_reuse = (next_reuse + 1) % LEASES;
+                    index
+                }
+            },
+        };
+        let [a, b, c, _] = AP_ADDRESS;
+        let address = [a, b, c, 2 + index as u8];
+        let mut reply = [0; DHCP_REPLY_SIZE];
+        dhcp_reply(request, kind, address, &mut reply);
+        if kind == DHCP_ACK {
+            log::info!(
+                "Configurazione WiFi: {}.{}.{}.{} assegnato a un client",
+                a,
+                b,
+                c,
+                address[3]
+            );
+        }
+        if let Err(e) = socket.send_to(&reply, broadcast).await {
+            log::warn!("Configurazione WiFi: risposta DHCP non inviata: {:?}", e);
+        }
+    }
+}
+
+/// Costruisce la risposta a una domanda DNS: l'indirizzo della pico per le
+/// domande A, nessun record per le altre
+///
+/// # Argomenti
+/// * `query` - Pacchetto ricevuto
+/// * `reply` - Buffer della risposta
+///
+/// # Ritorna
+/// * Option<usize> - Lunghezza della risposta, None se la query non è valida
+fn dns_reply(query: &[u8], reply: &mut [u8; PACKET_CAPACITY]) -> Option<usize> {
+    // Solo query standard (QR = 0, opcode 0) con almeno una domanda
+    let flags = u16::from_be_bytes([*query.get(2)?, *query.get(3)?]);
+    if flags & 0xF800 != 0 || query.get(4..6)? == [0, 0] {
+        return None;
+    }
+    // Fine della prima domanda: nome (senza compressione), tipo e classe
+    let mut end = 12;
+    loop {
+        match *query.get(end)? as usize {
+            0 => break,
+            len if len <= 63 => end += 1 + len,
+            _ => return None,
+        }
+    }
+    let kind = u16::from_be_bytes([*query.get(end + 1)?, *query.get(end + 2)?]);
+    end += 5;
+    let question = query.get(12..end)?;
+    let answers: u16 = if kind == 1 { 1 } else { 0 };
+
+    reply[..2].copy_from_slice(&query[..2]);
+    // Risposta autorevole, con il bit RD della domanda e la ricorsione disponibile
+    let flags = 0x8480 | (flags & 0x0100);
+    reply[2..4].copy_from_slice(&flags.to_be_bytes());
+    reply[4..6].copy_from_slice(&1u16.to_be_bytes());
+    reply[6..8].copy_from_slice(&answers.to_be_bytes());
+    reply[8..12].fill(0);
+    let mut len = 12;
+    reply
+        .get_mut(len..len + question.len())?
+        .copy_from_slice(question);
+    len += question.len();
+    if answers > 0 {
+        let ttl = DNS_TTL_SECS.to_be_bytes();
+        let [a, b, c, d] = AP_ADDRESS;
+        // Puntatore al nome della domanda, tipo A, classe IN, TTL, 4 byte di dati
+        let answer = [
+            0xC0, 12, 0, 1, 0, 1, ttl[0], ttl[1], ttl[2], ttl[3], 0, 4, a, b, c, d,
+        ];
+        reply
+            .get_mut(len..len + answer.len())?
+            .copy_from_slice(&answer);
+        len += answer.len();
+    }
+    Some(len)
+}
+
+/// Server DNS dell'access point: ogni nome risolve nell'indirizzo della pico
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+async fn dns_server(stack: Stack<'static>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; PACKET_CAPACITY];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0; PACKET_CAPACITY];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    if let Err(e) = socket.bind(DNS_PORT) {
+        log::error!("Configurazione WiFi: bind del DNS fallito: {:?}", e);
+        return;
+    }
+    let mut query = [0; PACKET_CAPACITY];
+    let mut reply = [0; PACKET_CAPACITY];
+    loop {
+        let Ok((len, meta)) = socket.recv_from(&mut query).await else {
+            continue;
+        };
+        let Some(reply_len) = dns_reply(&query[..len], &mut reply) else {
+            continue;
+        };
+        if let Err(e) = socket.send_to(&reply[..reply_len], meta.endpoint).await {
+            log::warn!("Configurazione WiFi: risposta DNS non inviata: {:?}", e);
+        }
+    }
+}
+
+/// Rete inviata dal form di `/provision`
+#[derive(serde::Deserialize)]
+pub struct ProvisionForm {
+    pub ssid: heapless::String<32>,
+    pub password: heapless::String<64>,
+}
+
+/// Salva la rete ricevuta dal form e chiede il riavvio
+///
+/// # Argomenti
+/// * `form` - Rete scelta
+/// * `lang` - Lingua della risposta
+///
+/// # Ritorna
+/// * (StatusCode, &'static str) - Esito per l'utente
+fn provision(form: &ProvisionForm, lang: Lang) -> (StatusCode, &'static str) {
+    let strings = lang.strings();
+    if !is_active() {
+        return (StatusCode::FORBIDDEN, strings.provision_inactive);
+    }
+    let ssid = form.ssid.trim();
+    if ssid.is_empty() {
+        return (StatusCode::BAD_REQUEST, strings.provision_failed);
+    }
+    let saved =
+        config_store::set_ssid(ssid).and_then(|()| config_store::set_wifi_password(&form.password));
+    if let Err(e) = saved {
+        log::error!("Configurazione WiFi: rete non salvata: {:?}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, strings.provision_failed);
+    }
+    log::warn!("Configurazione WiFi: rete '{}' salvata, riavvio", ssid);
+    drain::request(DrainAction::Reboot);
+    (StatusCode::OK, strings.provision_saved)
+}
+
+/// Rotte della configurazione del WiFi: `/provision` mostra il form (GET)
+/// e salva la rete scelta (POST)
+pub struct ProvisionRoutes;
+
+impl Routes for ProvisionRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router.route(
+            PROVISION_PATH,
+            get(|lang: Lang, accept: AcceptGzip| async move {
+                lang.strings().provision_page.html(accept)
+            })
+            .post(
+                |lang: Lang,
+                 picoserve::extract::Form(form): picoserve::extract::Form<ProvisionForm>| {
+                    async move { provision(&form, lang) }
+                },
+            ),
+        )
+    }
+}
+
+/// Layer che durante la configurazione reindirizza ogni richiesta al form,
+/// come si aspettano i controlli "captive portal" dei client
+pub struct ProvisionLayer;
+
+impl<State, PathParameters> Layer<State, PathParameters> for ProvisionLayer {
+    type NextState = State;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &State,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let path = request_path::decoded(request_parts.path().encoded());
+        // Il login serve se le pagine sono protette da password (vedi auth.rs)
+        let allowed = [
+            PROVISION_PATH,
+            wifi_scan::SCAN_PAGE_PATH,
+            wifi_scan::SCAN_API_PATH,
+            session::LOGIN_PATH,
+        ];
+        if is_active() && !allowed.iter().any(|allowed| path.is(allowed)) {
+            return Redirect::to(PROVISION_PATH)
+                .write_to(next.into_connection(), response_writer)
+                .await;
+        }
+        next.run(state, path_parameters, response_writer).await
+    }
+}
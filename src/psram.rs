@@ -0,0 +1,296 @@
+//! psram.rs - Supporto per una PSRAM QSPI esterna (es. APS6404L) collegata
+//! al secondo chip select (XIP_CS1) del controller QMI dell'RP2350.
+//!
+//! Una volta inizializzata, la PSRAM viene mappata in memoria all'indirizzo
+//! 0x11000000. La usa lo heap (feature `heap`, inclusa da `psram`, vedi
+//! heap.rs), che all'avvio riserva `PSRAM_HEAP_SIZE` byte (vedi
+//! configuration.rs) con [`alloc`]: le strutture allocate con `Box` e `Vec`
+//! (sessione TLS, buffer grandi) non consumano la SRAM interna, già
+//! condivisa dai worker del web server. Senza PSRAM lo heap resta in SRAM.
+//! Il ring dei log (log_buffer.rs) resta invece in SRAM: riceve i log già
+//! prima dell'inizializzazione della PSRAM e deve funzionare anche senza.
+//!
+//! [`alloc`] è un semplice allocatore a incremento: la memoria assegnata
+//! non viene mai rilasciata.
+//! Il modulo è compilato solo con la feature `psram`.
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// GPIO collegato al chip select della PSRAM (XIP_CS1 è disponibile su GPIO 0, 8, 19 e 47)
+pub const PSRAM_CS_PIN: usize = 19;
+
+/// Indirizzo base della finestra XIP associata a CS1
+const PSRAM_BASE: usize = 0x1100_0000;
+
+/// Frequenza massima supportata dalla PSRAM
+const MAX_PSRAM_FREQ_HZ: u32 = 133_000_000;
+
+// Registri QMI (RP2350 datasheet, cap. 12.14)
+const QMI_BASE: usize = 0x400d_0000;
+const QMI_DIRECT_CSR: usize = QMI_BASE;
+const QMI_DIRECT_TX: usize = QMI_BASE + 0x04;
+const QMI_DIRECT_RX: usize = QMI_BASE + 0x08;
+const QMI_M1_TIMING: usize = QMI_BASE + 0x20;
+const QMI_M1_RFMT: usize = QMI_BASE + 0x24;
+const QMI_M1_RCMD: usize = QMI_BASE + 0x28;
+const QMI_M1_WFMT: usize = QMI_BASE + 0x2c;
+const QMI_M1_WCMD: usize = QMI_BASE + 0x30;
+
+const XIP_CTRL: usize = 0x400c_8000;
+const XIP_CTRL_WRITABLE_M1: u32 = 1 << 11;
+
+const CSR_EN: u32 = 1 << 0;
+const CSR_BUSY: u32 = 1 << 1;
+const CSR_ASSERT_CS1N: u32 = 1 << 3;
+const CSR_AUTO_CS1N: u32 = 1 << 7;
+const CSR_TXEMPTY: u32 = 1 << 11;
+const CSR_CLKDIV_LSB: u32 = 22;
+
+const TX_OE: u32 = 1 << 19;
+const TX_IWIDTH_QUAD: u32 = 2 << 16;
+
+// Formato dei trasferimenti XIP: prefisso, indirizzo e dati in modalità quad
+const WIDTH_QUAD: u32 = 2;
+const FMT_PREFIX_LEN_8: u32 = 1 << 12;
+const FMT_DUMMY_LEN_24: u32 = 6 << 16;
+const FMT_QUAD_ALL: u32 =
+    WIDTH_QUAD | (WIDTH_QUAD << 2) | (WIDTH_QUAD << 4) | (WIDTH_QUAD << 6) | (WIDTH_QUAD << 8);
+
+// Comandi APS6404L
+const CMD_READ_ID: u32 = 0x9f;
+const CMD_RESET_ENABLE: u32 = 0x66;
+const CMD_RESET: u32 = 0x99;
+const CMD_ENTER_QUAD: u32 = 0x35;
+const CMD_EXIT_QUAD: u32 = 0xf5;
+const CMD_QUAD_READ: u32 = 0xeb;
+const CMD_QUAD_WRITE: u32 = 0x38;
+
+/// Valore "Known Good Die" restituito dalle PSRAM APS6404
+const KGD_PASS: u16 = 0x5d;
+
+/// Dimensione della PSRAM in base all'EID (maschera, valore, byte), in
+/// ordine: l'EID 0x26 dell'APS6404L ha una voce propria, per gli altri
+/// decidono i bit di densità (EID >> 5)
+const SIZE_BY_EID: [(u16, u16, usize); 4] = [
+    (0xffff, 0x26, 8 * 1024 * 1024),
+    (0xffe0, 2 << 5, 8 * 1024 * 1024),
+    (0xffe0, 1 << 5, 4 * 1024 * 1024),
+    (0xffe0, 0, 2 * 1024 * 1024),
+];
+
+/// Dimensione delle PSRAM con un EID non presente in [`SIZE_BY_EID`]
+const DEFAULT_SIZE: usize = 1024 * 1024;
+
+/// Dimensione rilevata della PSRAM (0 = non presente o non inizializzata)
+static PSRAM_SIZE: AtomicUsize = AtomicUsize::new(0);
+/// Prossimo offset libero per l'allocatore a incremento
+static NEXT_FREE: AtomicUsize = AtomicUsize::new(0);
+
+/// Errori possibili durante l'inizializzazione della PSRAM
+#[derive(Debug)]
+pub enum PsramError {
+    NotDetected,
+    AlreadyInitialized,
+}
+
+/// Inizializza la PSRAM su XIP_CS1 e la rende accessibile in lettura/scrittura.
+///
+/// # Ritorna
+/// * Result<usize, PsramError> - Dimensione della PSRAM in byte oppure errore
+pub fn init() -> Result<usize, PsramError> {
+    if PSRAM_SIZE.load(Ordering::Acquire) != 0 {
+        return Err(PsramError::AlreadyInitialized);
+    }
+
+    // Il pin di chip select va assegnato alla funzione XIP_CS1 (F9)
+    embassy_rp::pac::PADS_BANK0.gpio(PSRAM_CS_PIN).modify(|w| {
+        w.set_ie(true);
+        w.set_od(false);
+        w.set_iso(false);
+    });
+    embassy_rp::pac::IO_BANK0
+        .gpio(PSRAM_CS_PIN)
+        .ctrl()
+        .write(|w| w.set_funcsel(9));
+
+    let timing = m1_timing(embassy_rp::clocks::clk_sys_freq());
+    // La modalità diretta del QMI blocca l'accesso XIP alla flash:
+    // la configurazione avviene in RAM e senza interrupt.
+    let id = critical_section::with(|_| configure_qmi(timing));
+    let (kgd, eid) = ((id >> 16) as u16, id as u16);
+    if kgd != KGD_PASS {
+        return Err(PsramError::NotDetected);
+    }
+
+    let size = SIZE_BY_EID
+        .iter()
+        .find(|(mask, value, _)| eid & mask == *value)
+        .map_or(DEFAULT_SIZE, |&(_, _, size)| size);
+
+    PSRAM_SIZE.store(size, Ordering::Release);
+    Ok(size)
+}
+
+/// Ritorna la dimensione della PSRAM inizializzata (0 se assente)
+pub fn size() -> usize {
+    PSRAM_SIZE.load(Ordering::Acquire)
+}
+
+/// Ritorna i byte ancora disponibili per l'allocazione
+pub fn free() -> usize {
+    size().saturating_sub(NEXT_FREE.load(Ordering::Acquire))
+}
+
+/// Riserva un buffer statico in PSRAM, allineato a 4 byte.
+/// La memoria non viene mai rilasciata: pensato per buffer allocati all'avvio.
+///
+/// # Argomenti
+/// * `len` - Dimensione del buffer in byte
+///
+/// # Ritorna
+/// * Option<&'static mut [u8]> - Buffer azzerato, None se la PSRAM è assente o piena
+pub fn alloc(len: usize) -> Option<&'static mut [u8]> {
+    let total = size();
+    let aligned = (len + 3) & !3;
+    let offset = NEXT_FREE
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |next| {
+            let end = next.checked_add(aligned)?;
+            (end <= total).then_some(end)
+        })
+        .ok()?;
+
+    // SAFETY: la regione [offset, offset + len) è mappata (PSRAM inizializzata),
+    // scrivibile (WRITABLE_M1) e assegnata una sola volta dall'allocatore a incremento.
+    let buf = unsafe { core::slice::from_raw_parts_mut((PSRAM_BASE + offset) as *mut u8, len) };
+    buf.fill(0);
+    Some(buf)
+}
+
+#[inline(always)]
+fn reg_read(addr: usize) -> u32 {
+    // SAFETY: indirizzo di un registro MMIO valido del QMI/XIP_CTRL
+    unsafe { read_volatile(addr as *const u32) }
+}
+
+#[inline(always)]
+fn reg_write(addr: usize, value: u32) {
+    // SAFETY: indirizzo di un registro MMIO valido del QMI/XIP_CTRL
+    unsafe { write_volatile(addr as *mut u32, value) }
+}
+
+#[inline(always)]
+fn wait_not_busy() {
+    while reg_read(QMI_DIRECT_CSR) & CSR_BUSY != 0 {}
+}
+
+/// Invia un comando di un byte con CS1 gestito automaticamente
+#[inline(always)]
+fn send_command(cmd: u32, iwidth: u32) {
+    reg_write(QMI_DIRECT_TX, TX_OE | iwidth | cmd);
+    wait_not_busy();
+    let _ = reg_read(QMI_DIRECT_RX);
+}
+
+/// Calcola il valore del registro M1_TIMING per la frequenza di sistema corrente.
+/// Vincoli APS6404L: tCEM max 8us, tCPH min 18ns.
+///
+/// # Argomenti
+/// * `clock_hz` - Frequenza del clock di sistema
+///
+/// # Ritorna
+/// * u32 - Valore da scrivere in M1_TIMING
+fn m1_timing(clock_hz: u32) -> u32 {
+    let divisor = clock_hz.div_ceil(MAX_PSRAM_FREQ_HZ);
+    let period_fs: u64 = 1_000_000_000_000_000 / clock_hz as u64;
+    let max_select = ((8_000_000_000_u64 / period_fs) / 64).min(63) as u32;
+    let min_deselect = (18_000_000_u64.div_ceil(period_fs) as u32)
+        .saturating_sub(divisor.div_ceil(2))
+        .min(31);
+    let rxdelay = divisor.min(7);
+
+    (1 << 30) // cooldown
+        | (2 << 28) // pagebreak 1024 byte
+        | (3 << 23) // select hold
+        | (max_select << 17)
+        | (min_deselect << 12)
+        | (rxdelay << 8)
+        | divisor
+}
+
+/// Rileva la PSRAM e configura la finestra M1 del QMI.
+/// Eseguita dalla RAM: mentre il QMI è in modalità diretta la flash non è leggibile,
+/// quindi qui non si chiamano funzioni né si usano costanti in flash.
+///
+/// # Argomenti
+/// * `timing` - Valore precalcolato di M1_TIMING
+///
+/// # Ritorna
+/// * u32 - KGD nei 16 bit alti ed EID nei 16 bit bassi
+#[inline(never)]
+#[unsafe(link_section = ".data.ram_func")]
+fn configure_qmi(timing: u32) -> u32 {
+    // Modalità diretta, clock lento per la fase di rilevamento
+    reg_write(
+        QMI_DIRECT_CSR,
+        (30 << CSR_CLKDIV_LSB) | CSR_EN | CSR_AUTO_CS1N,
+    );
+    wait_not_busy();
+
+    // Se la PSRAM era già in modalità quad (reset a caldo) la riportiamo in SPI
+    send_command(CMD_EXIT_QUAD, TX_IWIDTH_QUAD);
+
+    // Lettura ID: comando + 3 byte di indirizzo, poi KGD ed EID
+    reg_write(
+        QMI_DIRECT_CSR,
+        (reg_read(QMI_DIRECT_CSR) & !CSR_AUTO_CS1N) | CSR_ASSERT_CS1N,
+    );
+    let mut kgd: u32 = 0;
+    let mut eid: u32 = 0;
+    let mut i = 0;
+    while i < 7 {
+        reg_write(QMI_DIRECT_TX, if i == 0 { CMD_READ_ID } else { 0xff });
+        while reg_read(QMI_DIRECT_CSR) & CSR_TXEMPTY == 0 {}
+        wait_not_busy();
+        let value = reg_read(QMI_DIRECT_RX) & 0xffff;
+        if i == 5 {
+            kgd = value;
+        } else if i == 6 {
+            eid = value;
+        }
+        i += 1;
+    }
+    reg_write(
+        QMI_DIRECT_CSR,
+        reg_read(QMI_DIRECT_CSR) & !(CSR_ASSERT_CS1N | CSR_EN),
+    );
+
+    if kgd != KGD_PASS as u32 {
+        return kgd << 16;
+    }
+
+    // Reset software e ingresso in modalità QPI
+    reg_write(
+        QMI_DIRECT_CSR,
+        (30 << CSR_CLKDIV_LSB) | CSR_EN | CSR_AUTO_CS1N,
+    );
+    wait_not_busy();
+    send_command(CMD_RESET_ENABLE, 0);
+    send_command(CMD_RESET, 0);
+    send_command(CMD_ENTER_QUAD, 0);
+
+    reg_write(QMI_M1_TIMING, timing);
+    reg_write(
+        QMI_M1_RFMT,
+        FMT_QUAD_ALL | FMT_PREFIX_LEN_8 | FMT_DUMMY_LEN_24,
+    );
+    reg_write(QMI_M1_RCMD, CMD_QUAD_READ);
+    reg_write(QMI_M1_WFMT, FMT_QUAD_ALL | FMT_PREFIX_LEN_8);
+    reg_write(QMI_M1_WCMD, CMD_QUAD_WRITE);
+
+    // Uscita dalla modalità diretta e abilitazione della scrittura su M1
+    reg_write(QMI_DIRECT_CSR, 0);
+    reg_write(XIP_CTRL, reg_read(XIP_CTRL) | XIP_CTRL_WRITABLE_M1);
+
+    (kgd << 16) | eid
+}
@@ -0,0 +1,237 @@
+//! pwm.rs - Uscite PWM comandate via HTTP, per servo e LED dimmerabili.
+//!
+//! Ogni canale usa l'uscita A di uno slice PWM diverso ([`PWM_PINS`]),
+//! così frequenza e duty cycle sono indipendenti. Le uscite partono spente
+//! (duty 0) a [`DEFAULT_FREQUENCY_HZ`].
+//! * `GET /api/pwm` - frequenza e duty cycle di ogni canale;
+//! * `POST /api/pwm/{channel}` - form con `frequency_hz` e `duty_permille`
+//!   (0-1000) oppure `pulse_us` (durata dell'impulso, comoda per i servo);
+//!   i campi assenti restano invariati.
+//!
+//! Il divisore del clock è il più piccolo che fa stare il periodo nel
+//! contatore a 16 bit, per avere la massima risoluzione del duty cycle.
+//! Sono rotte di amministrazione (vedi acl.rs).
+//!
+//! Esempio (servo a 50 Hz in posizione centrale):
+//! ```text
+//! curl -d 'frequency_hz=50&pulse_us=1500' http://<ip>/api/pwm/0
+//! ```
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::i18n::Lang;
+use core::cell::RefCell;
+use critical_section::Mutex as CsMutex;
+use embassy_rp::clocks::clk_sys_freq;
+use embassy_rp::pwm::{Config, Pwm};
+use picoserve::extract::Form;
+use picoserve::response::{Json, StatusCode};
+use picoserve::routing::{PathRouter, get, parse_path_segment, post};
+
+/// GPIO dei canali PWM: GP16 (slice 0, A) e GP18 (slice 1, A)
+pub const PWM_PINS: [u8; 2] = [16, 18];
+
+/// Frequenza all'avvio (quella dei servo)
+const DEFAULT_FREQUENCY_HZ: u32 = 50;
+
+/// Frequenza minima: con il divisore massimo il periodo deve stare in 16 bit
+const MIN_FREQUENCY_HZ: u32 = 10;
+
+/// Frequenza massima, con almeno 100 passi di duty cycle a 125 MHz
+const MAX_FREQUENCY_HZ: u32 = 1_000_000;
+
+/// Divisore intero massimo del clock di uno slice
+const MAX_DIVIDER: u32 = 255;
+
+/// Canale configurato da init
+struct PwmChannel {
+    pwm: Pwm<'static>,
+    config: Config,
+    frequency_hz: u32,
+    duty_permille: u16,
+}
+
+/// Canali configurati, nell'ordine di PWM_PINS
+static CHANNELS: CsMutex<RefCell<heapless::Vec<PwmChannel, { PWM_PINS.len() }>>> =
+    CsMutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Errori delle operazioni sui canali PWM
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PwmError {
+    /// Canale inesistente
+    UnknownChannel,
+    /// Frequenza o duty cycle fuori dai limiti
+    InvalidValue,
+}
+
+/// Stato di un canale restituito da /api/pwm
+#[derive(serde::Serialize)]
+pub struct ChannelState {
+    pub channel: u8,
+    pub pin: u8,
+    pub frequency_hz: u32,
+    pub duty_permille: u16,
+}
+
+/// Comando inviato a `POST /api/pwm/{channel}`
+#[derive(serde::Deserialize)]
+pub struct PwmForm {
+    #[serde(default)]
+    pub frequency_hz: Option<u32>,
+    #[serde(default)]
+    pub duty_permille: Option<u16>,
+    /// Durata dell'impulso in µs, alternativa a duty_permille
+    #[serde(default)]
+    pub pulse_us: Option<u32>,
+}
+
+/// Calcola divisore e TOP del contatore per una frequenza
+///
+/// # Argomenti
+/// * `frequency_hz` - Frequenza richiesta
+///
+/// # Ritorna
+/// * (u8, u16) - Divisore intero e valore di TOP
+fn timing(frequency_hz: u32) -> (u8, u16) {
+    let ticks = clk_sys_freq() / frequency_hz;
+    // TOP resta sotto u16::MAX, così il duty cycle del 100% ha un compare valido
+    let divider = ticks.div_ceil(u16::MAX as u32).clamp(1, MAX_DIVIDER);
+    let top = (ticks / divider).clamp(2, u16::MAX as u32) - 1;
+    (divider as u8, top as u16)
+}
+
+/// Applica frequenza e duty cycle alla configurazione di un canale
+fn configure(channel: &mut PwmChannel, frequency_hz: u32, duty_permille: u16) {
+    let (divider, top) = timing(frequency_hz);
+    channel.config.divider = divider.into();
+    channel.config.top = top;
+    channel.config.compare_a = ((top as u32 + 1) * duty_permille as u32 / 1000) as u16;
+    channel.pwm.set_config(&channel.config);
+    channel.frequency_hz = frequency_hz;
+    channel.duty_permille = duty_permille;
+}
+
+/// Registra i canali PWM e li spegne: da chiamare una sola volta all'avvio
+///
+/// # Argomenti
+/// * `channels` - Uscite degli slice, nell'ordine di PWM_PINS
+pub fn init(channels: [Pwm<'static>; PWM_PINS.len()]) {
+    critical_section::with(|cs| {
+        let mut configured = CHANNELS.borrow(cs).borrow_mut();
+        for pwm in channels {
+            let mut channel = PwmChannel {
+                pwm,
+                config: Config::default(),
+                frequency_hz: DEFAULT_FREQUENCY_HZ,
+                duty_permille: 0,
+            };
+            configure(&mut channel, DEFAULT_FREQUENCY_HZ, 0);
+            // PWM_PINS e CHANNELS hanno la stessa capacità
+            let _ = configured.push(channel);
+        }
+    });
+}
+
+/// Ritorna lo stato di un canale
+fn state(index: usize, channel: &PwmChannel) -> ChannelState {
+    ChannelState {
+        channel: index as u8,
+        pin: PWM_PINS[index],
+        frequency_hz: channel.frequency_hz,
+        duty_permille: channel.duty_permille,
+    }
+}
+
+/// Ritorna lo stato di tutti i canali
+///
+/// # Ritorna
+/// * heapless::Vec<ChannelState, N> - Un elemento per canale
+pub fn channels() -> heapless::Vec<ChannelState, { PWM_PINS.len() }> {
+    critical_section::with(|cs| {
+        let configured = CHANNELS.borrow(cs).borrow();
+        configured
+            .iter()
+            .enumerate()
+            .map(|(index, channel)| state(index, channel))
+            .collect()
+    })
+}
+
+/// Cambia frequenza e duty cycle di un canale
+///
+/// # Argomenti
+/// * `index` - Numero del canale
+/// * `form` - Valori richiesti, quelli assenti restano invariati
+///
+/// # Ritorna
+/// * Result<ChannelState, PwmError> - Nuovo stato, oppure errore senza modifiche
+pub fn set(index: u8, form: &PwmForm) -> Result<ChannelState, PwmError> {
+    critical_section::with(|cs| {
+        let mut configured = CHANNELS.borrow(cs).borrow_mut();
+        let channel = configured
+            .get_mut(index as usize)
+            .ok_or(PwmError::UnknownChannel)?;
+        let frequency_hz = form.frequency_hz.unwrap_or(channel.frequency_hz);
+        if !(MIN_FREQUENCY_HZ..=MAX_FREQUENCY_HZ).contains(&frequency_hz) {
+            return Err(PwmError::InvalidValue);
+        }
+        let duty_permille = match (form.pulse_us, form.duty_permille) {
+            // Periodo in µs = 1_000_000 / frequenza
+            (Some(pulse_us), _) => (pulse_us as u64 * frequency_hz as u64 / 1000) as u32,
+            (None, Some(duty)) => duty as u32,
+            (None, None) => channel.duty_permille as u32,
+        };
+        if duty_permille > 1000 {
+            return Err(PwmError::InvalidValue);
+        }
+        configure(channel, frequency_hz, duty_permille as u16);
+        Ok(state(index as usize, channel))
+    })
+}
+
+/// Applica un comando a un canale
+///
+/// # Argomenti
+/// * `index` - Numero del canale
+/// * `form` - Comando ricevuto
+/// * `lang` - Lingua della richiesta
+///
+/// # Ritorna
+/// * Result<ChannelState, (StatusCode, &str)> - Nuovo stato, oppure errore
+fn apply(
+    index: u8,
+    form: &PwmForm,
+    lang: Lang,
+) -> Result<ChannelState, (StatusCode, &'static str)> {
+    let state = set(index, form).map_err(|e| match e {
+        PwmError::UnknownChannel => (StatusCode::NOT_FOUND, lang.strings().pwm_unknown_channel),
+        PwmError::InvalidValue => (StatusCode::BAD_REQUEST, lang.strings().pwm_invalid),
+    })?;
+    log::info!(
+        "PWM: canale {} a {} Hz, duty {}‰ da HTTP",
+        index,
+        state.frequency_hz,
+        state.duty_permille
+    );
+    Ok(state)
+}
+
+/// Rotte dei canali PWM: `/api/pwm` e `/api/pwm/{channel}`
+pub struct PwmRoutes;
+
+impl Routes for PwmRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route("/api/pwm", get(|| async move { Json(channels()) }))
+            .route(
+                ("/api/pwm", parse_path_segment::<u8>()),
+                post(
+                    |index: u8, lang: Lang, Form(form): Form<PwmForm>| async move {
+                        apply(index, &form, lang).map(Json)
+                    },
+                ),
+            )
+    }
+}
@@ -0,0 +1,158 @@
+//! rate_limit.rs - Limiti per client del web server.
+//!
+//! I worker del web server sono pochi (vedi `ServerResources` in
+//! configuration.rs): un solo client aggressivo potrebbe occuparli tutti.
+//! Appena accettata una connessione, prima di passarla a picoserve, il
+//! worker chiama [`admit`], che applica due limiti all'IP del client:
+//! * `RATE_LIMIT_CONNECTIONS` - connessioni aperte contemporaneamente
+//! * `RATE_LIMIT_PER_MINUTE` - nuove connessioni al minuto, con un token
+//!   bucket che consente brevi raffiche fino al limite del minuto
+//!
+//! Oltre i limiti il client riceve subito `429 Too Many Requests` con
+//! `Retry-After` (vedi overload.rs) e il worker torna libero. I client sono
+//! seguiti in una tabella di [`CLIENT_SLOTS`] posti: a tabella piena un
+//! client nuovo prende il posto di quello visto meno di recente senza
+//! connessioni aperte. I rifiuti sono contati nel campo `rate_limited` di
+//! `/api/acl`.
+
+use crate::utility::get_rate_limit;
+use core::cell::RefCell;
+use critical_section::Mutex as CsMutex;
+use embassy_net::IpAddress;
+use embassy_time::Instant;
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Client seguiti contemporaneamente
+const CLIENT_SLOTS: usize = 8;
+
+/// Token di una connessione: i token sono in millesimi di secondo per
+/// connessione al minuto, così il bucket si ricarica con aritmetica intera
+const CONNECTION_COST: u64 = 60_000;
+
+/// Client seguito
+#[derive(Clone, Copy)]
+struct Slot {
+    addr: IpAddress,
+    /// Connessioni aperte
+    open: u32,
+    /// Token disponibili (vedi [`CONNECTION_COST`])
+    tokens: u64,
+    /// Ultimo accesso, per la ricarica del bucket e per scegliere il posto
+    /// da liberare
+    seen: Instant,
+}
+
+/// Tabella dei client
+static CLIENTS: CsMutex<RefCell<[Option<Slot>; CLIENT_SLOTS]>> =
+    CsMutex::new(RefCell::new([None; CLIENT_SLOTS]));
+
+/// Connessioni rifiutate con 429
+static LIMITED: AtomicU32 = AtomicU32::new(0);
+
+/// Connessione rifiutata perché il client ha superato un limite
+pub struct Limited {
+    /// Secondi suggeriti prima di riprovare
+    pub retry_after_secs: u32,
+}
+
+/// Connessione ammessa: conta tra quelle aperte del client finché esiste
+pub struct Admitted(Option<IpAddress>);
+
+impl Drop for Admitted {
+    fn drop(&mut self) {
+        let Some(addr) = self.0 else {
+            return;
+        };
+        critical_section::with(|cs| {
+            let mut clients = CLIENTS.borrow_ref_mut(cs);
+            if let Some(slot) = clients.iter_mut().flatten().find(|slot| slot.addr == addr) {
+                slot.open = slot.open.saturating_sub(1);
+            }
+        });
+    }
+}
+
+/// Controlla i limiti per una nuova connessione
+///
+/// # Argomenti
+/// * `addr` - IP del client, None se sconosciuto (nessun limite)
+///
+/// # Ritorna
+/// * Result<Admitted, Limited> - Connessione ammessa, oppure rifiutata con
+///   il tempo da attendere
+pub fn admit(addr: Option<IpAddress>) -> Result<Admitted, Limited> {
+    let (max_open, per_minute) = get_rate_limit();
+    let Some(addr) = addr else {
+        return Ok(Admitted(None));
+    };
+    if max_open == 0 && per_minute == 0 {
+        return Ok(Admitted(None));
+    }
+    let capacity = per_minute as u64 * CONNECTION_COST;
+    let now = Instant::now();
+    let admitted = critical_section::with(|cs| {
+        let mut clients = CLIENTS.borrow_ref_mut(cs);
+        let index = match clients
+            .iter()
+            .position(|slot| slot.is_some_and(|s| s.addr == addr))
+        {
+            Some(index) => index,
+            None => {
+                // Posto libero, oppure il client inattivo visto meno di recente
+                let free = clients
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, slot)| slot.is_none_or(|s| s.open == 0))
+                    .min_by_key(|(_, slot)| slot.map(|s| s.seen));
+                // Tutti i posti hanno connessioni aperte: il client non è seguito
+                let Some((index, _)) = free else {
+                    return Ok(None);
+                };
+                clients[index] = Some(Slot {
+                    addr,
+                    open: 0,
+                    tokens: capacity,
+                    seen: now,
+                });
+                index
+            }
+        };
+        let Some(slot) = clients[index].as_mut() else {
+            return Ok(None);
+        };
+        let elapsed_ms = now.saturating_duration_since(slot.seen).as_millis();
+        slot.tokens = (slot.tokens + elapsed_ms * per_minute as u64).min(capacity);
+        slot.seen = now;
+        if max_open > 0 && slot.open >= max_open {
+            return Err(Limited {
+                retry_after_secs: crate::overload::RETRY_AFTER_SECS,
+            });
+        }
+        if per_minute > 0 {
+            if slot.tokens < CONNECTION_COST {
+                let wait_ms = (CONNECTION_COST - slot.tokens).div_ceil(per_minute as u64);
+                return Err(Limited {
+                    retry_after_secs: wait_ms.div_ceil(1000) as u32,
+                });
+            }
+            slot.tokens -= CONNECTION_COST;
+        }
+        slot.open += 1;
+        Ok(Some(addr))
+    });
+    match admitted {
+        Ok(addr) => Ok(Admitted(addr)),
+        Err(limited) => {
+            LIMITED.fetch_add(1, Ordering::Relaxed);
+            Err(limited)
+        }
+    }
+}
+
+/// Ritorna il numero di connessioni rifiutate con 429
+///
+/// # Ritorna
+/// * u32 - Connessioni rifiutate dall'avvio
+pub fn limited() -> u32 {
+    LIMITED.load(Ordering::Relaxed)
+}
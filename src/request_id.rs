@@ -0,0 +1,114 @@
+//! request_id.rs - ID delle richieste HTTP e log di accesso.
+//!
+//! Il layer [`RequestIdLayer`] assegna a ogni richiesta un ID breve (6 cifre
+//! esadecimali, con un'origine casuale diversa a ogni avvio) e, al termine,
+//! scrive una riga nel log di accesso con metodo, percorso e durata.
+//!
+//! Mentre l'handler della richiesta è in esecuzione l'ID è disponibile con
+//! [`current`]: i worker del web server girano tutti sullo stesso executor,
+//! quindi l'ID viene impostato solo durante il poll della richiesta. Così
+//! ogni riga di log emessa dall'handler riporta l'ID (vedi logging.rs), come
+//! le pagine di errore mostrate all'utente (vedi utility.rs): un errore
+//! segnalato si ritrova nei log del dispositivo.
+
+use core::fmt;
+use core::future::poll_fn;
+use core::pin::pin;
+use embassy_time::Instant;
+use picoserve::ResponseSent;
+use picoserve::io::Read;
+use picoserve::request::RequestParts;
+use picoserve::response::ResponseWriter;
+use picoserve::routing::{Layer, Next};
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Bit degli ID mostrati
+const ID_MASK: u32 = 0x00FF_FFFF;
+
+/// Marcatore di un ID valido in CURRENT (0 = nessuna richiesta)
+const ID_VALID: u32 = 0x0100_0000;
+
+/// Prossimo ID da assegnare
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// ID della richiesta in esecuzione, 0 se nessuna
+static CURRENT: AtomicU32 = AtomicU32::new(0);
+
+/// ID di una richiesta HTTP
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RequestId(u32);
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:06x}", self.0 & ID_MASK)
+    }
+}
+
+/// Imposta l'origine degli ID, così gli ID di avvii diversi non si ripetono
+///
+/// # Argomenti
+/// * `seed` - Valore casuale
+pub fn init(seed: u32) {
+    NEXT_ID.store(seed & ID_MASK, Ordering::Relaxed);
+}
+
+/// Assegna un nuovo ID
+fn assign() -> RequestId {
+    RequestId(NEXT_ID.fetch_add(1, Ordering::Relaxed) & ID_MASK)
+}
+
+/// Ritorna l'ID della richiesta HTTP in esecuzione
+///
+/// # Ritorna
+/// * Option<RequestId> - ID, None fuori da un handler HTTP
+pub fn current() -> Option<RequestId> {
+    let id = CURRENT.load(Ordering::Relaxed);
+    (id & ID_VALID != 0).then_some(RequestId(id & ID_MASK))
+}
+
+/// Layer che assegna l'ID alla richiesta e scrive il log di accesso
+pub struct RequestIdLayer;
+
+impl<State, PathParameters> Layer<State, PathParameters> for RequestIdLayer {
+    type NextState = State;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &State,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let id = assign();
+        let start = Instant::now();
+        let mut response = pin!(next.run(state, path_parameters, response_writer));
+        // L'ID resta impostato solo durante il poll di questa richiesta
+        let result = poll_fn(|cx| {
+            let previous = CURRENT.swap(id.0 | ID_VALID, Ordering::Relaxed);
+            let poll = response.as_mut().poll(cx);
+            CURRENT.store(previous, Ordering::Relaxed);
+            poll
+        })
+        .await;
+        log::info!(
+            "[{}] {} {} {} ms{}",
+            id,
+            request_parts.method(),
+            request_parts.path().encoded(),
+            start.elapsed().as_millis(),
+            if result.is_err() {
+                " (connessione persa)"
+            } else {
+                ""
+            }
+        );
+        result
+    }
+}
@@ -0,0 +1,195 @@
+//! request_log.rs - Ultime richieste HTTP servite, per il debug dei client.
+//!
+//! Il layer [`RequestLogLayer`] registra di ogni richiesta metodo, percorso,
+//! codice di stato e durata in un buffer circolare delle ultime
+//! [`REQUEST_LOG_ENTRIES`] richieste, condiviso dai worker tramite
+//! [`RequestLog`] nello stato dell'applicazione. `GET /requests` restituisce
+//! in JSON le richieste (dalla più vecchia) e, per ogni percorso presente,
+//! numero di richieste, errori e durata media e massima: un client lento o
+//! che riceve errori si riconosce senza leggere il log di accesso.
+//!
+//! Una richiesta la cui connessione cade prima della risposta ha stato 0.
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::request_id;
+use core::cell::{Cell, RefCell};
+use critical_section::Mutex as CsMutex;
+use embassy_time::Instant;
+use picoserve::ResponseSent;
+use picoserve::extract::State;
+use picoserve::io::Read;
+use picoserve::request::RequestParts;
+use picoserve::response::{Body, Connection, HeadersIter, Json, Response, ResponseWriter};
+use picoserve::routing::{Layer, Next, PathRouter, get};
+
+/// Richieste conservate
+pub const REQUEST_LOG_ENTRIES: usize = 32;
+
+/// Richiesta servita
+#[derive(Clone, serde::Serialize)]
+pub struct Entry {
+    /// ID della richiesta, come nel log di accesso (vedi request_id.rs)
+    id: Option<heapless::String<8>>,
+    method: heapless::String<8>,
+    /// Percorso senza query string (troncato se troppo lungo)
+    path: heapless::String<64>,
+    /// Codice di stato, 0 se la connessione è caduta prima della risposta
+    status: u16,
+    duration_ms: u32,
+    /// Uptime alla fine della richiesta
+    uptime_ms: u32,
+}
+
+/// Statistiche delle richieste a un percorso
+#[derive(serde::Serialize)]
+pub struct RouteStats {
+    path: heapless::String<64>,
+    requests: u32,
+    /// Risposte con stato 4xx o 5xx e connessioni cadute
+    errors: u32,
+    avg_duration_ms: u32,
+    max_duration_ms: u32,
+}
+
+/// Contenuto di `/requests`
+#[derive(serde::Serialize)]
+pub struct Report {
+    requests: heapless::Vec<Entry, REQUEST_LOG_ENTRIES>,
+    routes: heapless::Vec<RouteStats, REQUEST_LOG_ENTRIES>,
+}
+
+/// Ultime richieste, dalla più vecchia
+static ENTRIES: CsMutex<RefCell<heapless::Deque<Entry, REQUEST_LOG_ENTRIES>>> =
+    CsMutex::new(RefCell::new(heapless::Deque::new()));
+
+/// Buffer delle ultime richieste, estraibile negli handler
+#[derive(Clone, Copy)]
+pub struct RequestLog;
+
+impl RequestLog {
+    /// Aggiunge una richiesta, scartando la più vecchia se il buffer è pieno
+    ///
+    /// # Argomenti
+    /// * `entry` - Richiesta servita
+    fn record(self, entry: Entry) {
+        critical_section::with(|cs| {
+            let mut entries = ENTRIES.borrow_ref_mut(cs);
+            if entries.is_full() {
+                entries.pop_front();
+            }
+            let _ = entries.push_back(entry);
+        });
+    }
+
+    /// Ritorna le richieste conservate e le statistiche per percorso
+    ///
+    /// # Ritorna
+    /// * Report - Richieste (dalla più vecchia) e statistiche
+    pub fn report(self) -> Report {
+        let requests: heapless::Vec<Entry, REQUEST_LOG_ENTRIES> =
+            critical_section::with(|cs| ENTRIES.borrow_ref(cs).iter().cloned().collect());
+        let mut routes = heapless::Vec::<RouteStats, REQUEST_LOG_ENTRIES>::new();
+        for entry in requests.iter() {
+            let failed = entry.status == 0 || entry.status >= 400;
+            match routes.iter_mut().find(|route| route.path == entry.path) {
+                Some(route) => {
+                    route.requests += 1;
+                    route.errors += failed as u32;
+                    route.avg_duration_ms += entry.duration_ms;
+                    route.max_duration_ms = route.max_duration_ms.max(entry.duration_ms);
+                }
+                // Le richieste sono al più quante le statistiche: c'è sempre posto
+                None => {
+                    let _ = routes.push(RouteStats {
+                        path: entry.path.clone(),
+                        requests: 1,
+                        errors: failed as u32,
+                        avg_duration_ms: entry.duration_ms,
+                        max_duration_ms: entry.duration_ms,
+                    });
+                }
+            }
+        }
+        // Finora avg_duration_ms conteneva la somma
+        for route in routes.iter_mut() {
+            route.avg_duration_ms /= route.requests;
+        }
+        Report { requests, routes }
+    }
+}
+
+/// Writer che annota il codice di stato della risposta
+struct StatusWriter<'s, W> {
+    inner: W,
+    status: &'s Cell<u16>,
+}
+
+impl<W: ResponseWriter> ResponseWriter for StatusWriter<'_, W> {
+    type Error = W::Error;
+
+    async fn write_response<R: Read<Error = Self::Error>, H: HeadersIter, B: Body>(
+        self,
+        connection: Connection<'_, R>,
+        response: Response<H, B>,
+    ) -> Result<ResponseSent, Self::Error> {
+        self.status.set(response.status_code().as_u16());
+        self.inner.write_response(connection, response).await
+    }
+}
+
+/// Layer che registra ogni richiesta nel buffer di [`RequestLog`]
+pub struct RequestLogLayer;
+
+impl<PathParameters> Layer<AppState, PathParameters> for RequestLogLayer {
+    type NextState = AppState;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &AppState,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        // Il layer gira dentro RequestIdLayer: l'ID è già assegnato
+        let id = request_id::current();
+        let start = Instant::now();
+        let status = Cell::new(0);
+        let writer = StatusWriter {
+            inner: response_writer,
+            status: &status,
+        };
+        let result = next.run(state, path_parameters, writer).await;
+        let status = if result.is_ok() { status.get() } else { 0 };
+        state.request_log.record(Entry {
+            id: id.map(|id| crate::errors::fit(format_args!("{}", id))),
+            method: crate::errors::fit(format_args!("{}", request_parts.method())),
+            path: crate::errors::fit(format_args!("{}", request_parts.path().encoded())),
+            status,
+            duration_ms: start.elapsed().as_millis() as u32,
+            uptime_ms: Instant::now().as_millis() as u32,
+        });
+        result
+    }
+}
+
+/// Rotta del buffer delle richieste: `/requests`
+pub struct RequestLogRoutes;
+
+impl Routes for RequestLogRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router.route(
+            "/requests",
+            get(|State(log): State<RequestLog>| async move { Json(log.report()) }),
+        )
+    }
+}
@@ -0,0 +1,93 @@
+//! request_path.rs - Percorso della richiesta come lo vede il router.
+//!
+//! Il router di picoserve confronta i segmenti del percorso dopo averli
+//! decodificati: `/%61pi/command` raggiunge la stessa rotta di
+//! `/api/command`. I layer che decidono in base al percorso (autenticazione,
+//! ACL, modalità provvisoria, configurazione, limite del corpo) non possono
+//! quindi confrontare il percorso codificato: usano [`decoded`], che toglie
+//! le sequenze `%XX` e riduce le barre ripetute, così ogni variante di una
+//! rotta viene trattata come la rotta stessa.
+
+/// Lunghezza massima del percorso decodificato: i prefissi confrontati
+/// sono molto più corti, un percorso più lungo viene troncato
+const PATH_CAPACITY: usize = 128;
+
+/// Percorso decodificato di una richiesta
+pub struct DecodedPath {
+    bytes: heapless::Vec<u8, PATH_CAPACITY>,
+    /// true se il percorso non è stato copiato per intero
+    truncated: bool,
+}
+
+impl DecodedPath {
+    /// Ritorna true se il percorso inizia con `prefix`
+    pub fn starts_with(&self, prefix: &str) -> bool {
+        self.bytes.starts_with(prefix.as_bytes())
+    }
+
+    /// Ritorna true se il percorso è esattamente `path`
+    pub fn is(&self, path: &str) -> bool {
+        !self.truncated && self.bytes == path.as_bytes()
+    }
+
+    /// Ritorna il resto del percorso dopo `prefix`, se inizia con `prefix`
+    ///
+    /// # Argomenti
+    /// * `prefix` - Prefisso da togliere
+    ///
+    /// # Ritorna
+    /// * Option<&[u8]> - Resto del percorso
+    pub fn strip_prefix(&self, prefix: &str) -> Option<&[u8]> {
+        self.bytes.strip_prefix(prefix.as_bytes())
+    }
+}
+
+/// Valore di una cifra esadecimale
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodifica il percorso di una richiesta
+///
+/// # Argomenti
+/// * `encoded` - Percorso come ricevuto (`RequestParts::path().encoded()`)
+///
+/// # Ritorna
+/// * DecodedPath - Percorso con le sequenze `%XX` decodificate e le barre
+///   ripetute ridotte a una
+pub fn decoded(encoded: &str) -> DecodedPath {
+    let mut path = DecodedPath {
+        bytes: heapless::Vec::new(),
+        truncated: false,
+    };
+    let mut rest = encoded.as_bytes();
+    while let Some((&c, tail)) = rest.split_first() {
+        let escaped = match tail {
+            [high, low, ..] if c == b'%' => hex_digit(*high).zip(hex_digit(*low)),
+            _ => None,
+        };
+        let byte = match escaped {
+            Some((high, low)) => {
+                rest = &tail[2..];
+                high << 4 | low
+            }
+            None => {
+                rest = tail;
+                c
+            }
+        };
+        if byte == b'/' && path.bytes.last() == Some(&b'/') {
+            continue;
+        }
+        if path.bytes.push(byte).is_err() {
+            path.truncated = true;
+            break;
+        }
+    }
+    path
+}
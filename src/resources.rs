@@ -0,0 +1,126 @@
+//! resources.rs - Registro delle risorse condivise tra task e handler.
+//!
+//! Le risorse create dal main (state machine sm2, stack di rete, controller
+//! del CYW43, UART1 in trasmissione) vengono registrate una sola volta con
+//! [`register`] e recuperate per tipo con [`get`], dai task oppure dagli
+//! handler HTTP estraendo un [`Registry`] dallo stato dell'applicazione:
+//! ```ignore
+//! |State(registry): State<Registry>| async move { registry.get::<SharedSm2>() }
+//! ```
+//! Una seconda registrazione della stessa risorsa viene loggata e ignorata
+//! invece di andare in panic. Per aggiungere una risorsa (es. un sensore o
+//! un display) basta un tipo `Copy` che la condivide e un'implementazione
+//! di [`Registered`] con la sua cella statica.
+
+use crate::app_state::AppState;
+use crate::{SharedControl, SharedSm2};
+use core::cell::Cell;
+use critical_section::Mutex as CsMutex;
+use embassy_rp::uart::BufferedUartTx;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+
+/// Cella di una risorsa, scritta una sola volta all'avvio
+pub struct Resource<T: Copy + 'static> {
+    name: &'static str,
+    cell: CsMutex<Cell<Option<T>>>,
+}
+
+impl<T: Copy + 'static> Resource<T> {
+    /// Crea una cella vuota
+    ///
+    /// # Argomenti
+    /// * `name` - Nome per i log
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            cell: CsMutex::new(Cell::new(None)),
+        }
+    }
+}
+
+/// Tipo registrabile: associa il tipo alla sua cella statica
+pub trait Registered: Copy + 'static {
+    fn resource() -> &'static Resource<Self>;
+}
+
+/// Registra una risorsa (da chiamare solo dal main una sola volta)
+///
+/// # Argomenti
+/// * `value` - Risorsa da condividere
+///
+/// # Ritorna
+/// * bool - false se la risorsa era già registrata (il nuovo valore viene ignorato)
+pub fn register<T: Registered>(value: T) -> bool {
+    let resource = T::resource();
+    let registered = critical_section::with(|cs| {
+        let cell = resource.cell.borrow(cs);
+        if cell.get().is_some() {
+            return false;
+        }
+        cell.set(Some(value));
+        true
+    });
+    if !registered {
+        log::error!("Risorsa {} già registrata, ignorata", resource.name);
+    }
+    registered
+}
+
+/// Ritorna una risorsa registrata
+///
+/// # Ritorna
+/// * Option<T> - Risorsa, None se non ancora registrata
+pub fn get<T: Registered>() -> Option<T> {
+    critical_section::with(|cs| T::resource().cell.borrow(cs).get())
+}
+
+/// Accesso al registro dagli handler HTTP (estratto con `State<Registry>`)
+#[derive(Clone, Copy)]
+pub struct Registry;
+
+impl Registry {
+    /// Ritorna una risorsa registrata (vedi [`get`])
+    pub fn get<T: Registered>(self) -> Option<T> {
+        get::<T>()
+    }
+}
+
+impl picoserve::extract::FromRef<AppState> for Registry {
+    fn from_ref(_state: &AppState) -> Self {
+        Registry
+    }
+}
+
+/// UART1 in trasmissione, condivisa tra il task di lettura e chi invia notifiche
+#[derive(Clone, Copy)]
+pub struct SharedUartTx(pub &'static Mutex<CriticalSectionRawMutex, BufferedUartTx>);
+
+static SM2: Resource<SharedSm2> = Resource::new("sm2");
+static NET_STACK: Resource<embassy_net::Stack<'static>> = Resource::new("stack di rete");
+static CONTROL: Resource<SharedControl> = Resource::new("controller CYW43");
+static UART_TX: Resource<SharedUartTx> = Resource::new("UART1 TX");
+
+impl Registered for SharedSm2 {
+    fn resource() -> &'static Resource<Self> {
+        &SM2
+    }
+}
+
+impl Registered for embassy_net::Stack<'static> {
+    fn resource() -> &'static Resource<Self> {
+        &NET_STACK
+    }
+}
+
+impl Registered for SharedControl {
+    fn resource() -> &'static Resource<Self> {
+        &CONTROL
+    }
+}
+
+impl Registered for SharedUartTx {
+    fn resource() -> &'static Resource<Self> {
+        &UART_TX
+    }
+}
@@ -0,0 +1,97 @@
+//! safe_mode.rs - Modalità provvisoria dopo panic ripetuti.
+//!
+//! Il numero di avvii consecutivi seguiti a un panic è conservato nel
+//! contesto in RAM `.uninit` (vedi crash_log.rs), che sopravvive al riavvio
+//! eseguito da panic_persist. Raggiunta la soglia `SAFE_MODE_PANIC_THRESHOLD`
+//! la pico si avvia in modalità provvisoria: rete, console e rotte di
+//! diagnostica restano attive, i sottosistemi opzionali (BLE, UART1, eventi
+//! GPIO, log TCP, syslog e MQTT) non vengono avviati e le altre rotte
+//! rispondono 503.
+//! Un riavvio senza panic (es. comando `reboot`) torna alla modalità normale.
+
+use crate::configuration::SAFE_MODE_PANIC_THRESHOLD;
+use crate::crash_log::{self, CrashContext};
+use crate::i18n::Lang;
+use crate::request_path;
+use picoserve::ResponseSent;
+use picoserve::io::Read;
+use picoserve::request::RequestParts;
+use picoserve::response::{IntoResponse, ResponseWriter, StatusCode};
+use picoserve::routing::{Layer, Next};
+use portable_atomic::{AtomicBool, Ordering};
+
+/// Rotte disponibili anche in modalità provvisoria
+const ALLOWED_PREFIXES: [&str; 5] = ["/api/", "/logs", "/requests", "/events", "/static/"];
+
+/// true se la pico è in modalità provvisoria
+static SAFE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Decide se avviare in modalità provvisoria: da chiamare una sola volta
+/// all'inizio del main, dopo crash_log::take_context
+///
+/// # Argomenti
+/// * `previous` - Contesto dell'esecuzione precedente
+/// * `panicked` - true se l'esecuzione precedente è terminata con un panic
+///
+/// # Ritorna
+/// * bool - true se la pico deve avviarsi in modalità provvisoria
+pub fn evaluate(previous: Option<&CrashContext>, panicked: bool) -> bool {
+    let panic_boots = if panicked {
+        previous.map_or(0, |c| c.panic_boots()).saturating_add(1)
+    } else {
+        0
+    };
+    crash_log::set_panic_boots(panic_boots);
+
+    let active = panic_boots >= SAFE_MODE_PANIC_THRESHOLD;
+    SAFE_MODE.store(active, Ordering::Relaxed);
+    if active {
+        log::error!(
+            "{} panic consecutivi: avvio in modalità provvisoria",
+            panic_boots
+        );
+    }
+    active
+}
+
+/// Ritorna true se la pico è in modalità provvisoria
+pub fn is_active() -> bool {
+    SAFE_MODE.load(Ordering::Relaxed)
+}
+
+/// Layer che in modalità provvisoria consente solo la pagina principale
+/// e le rotte di diagnostica
+pub struct SafeModeLayer;
+
+impl<State, PathParameters> Layer<State, PathParameters> for SafeModeLayer {
+    type NextState = State;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &State,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let path = request_path::decoded(request_parts.path().encoded());
+        let allowed = path.is("/") || ALLOWED_PREFIXES.iter().any(|p| path.starts_with(p));
+        if is_active() && !allowed {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Lang::negotiate(&request_parts)
+                    .strings()
+                    .safe_mode_unavailable,
+            )
+                .write_to(next.into_connection(), response_writer)
+                .await;
+        }
+        next.run(state, path_parameters, response_writer).await
+    }
+}
@@ -0,0 +1,145 @@
+//! sensors.rs - Sensore di temperatura interno e ADC condiviso.
+//!
+//! L'ADC è uno solo e serve sia al sensore di temperatura del chip sia al
+//! campionamento di VSYS (vedi brownout.rs): [`init`] lo registra qui e
+//! [`read`] esegue una conversione alla volta, in una sezione critica.
+//!
+//! * `GET /api/temperature` - temperatura del chip in JSON;
+//! * `GET /temperature` - pagina con un indicatore aggiornato ogni 2 s.
+//!
+//! La temperatura si ricava dalla tensione del sensore come indicato nel
+//! datasheet (RP2040 e RP2350): 0,706 V a 27 °C, con una pendenza di
+//! -1,721 mV/°C. Il valore dipende dalla tensione di riferimento (3,3 V
+//! nominali) e varia di qualche grado da un chip all'altro.
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::assets::AcceptGzip;
+use crate::i18n::Lang;
+use core::cell::RefCell;
+use critical_section::Mutex as CsMutex;
+use embassy_rp::adc::{Adc, Blocking, Channel};
+use picoserve::response::{Json, StatusCode};
+use picoserve::routing::{PathRouter, get};
+
+/// Tensione di riferimento dell'ADC (mV)
+const ADC_REF_MV: u32 = 3300;
+
+/// Tensione del sensore a 27 °C (µV)
+const SENSOR_UV_AT_27C: i32 = 706_000;
+
+/// Pendenza del sensore (µV per °C)
+const SENSOR_UV_PER_C: i32 = 1721;
+
+/// Campioni mediati per una lettura della temperatura
+const SAMPLES: u32 = 8;
+
+/// ADC condiviso, registrato all'avvio
+static ADC: CsMutex<RefCell<Option<Adc<'static, Blocking>>>> = CsMutex::new(RefCell::new(None));
+
+/// Canale del sensore di temperatura
+static TEMP_SENSOR: CsMutex<RefCell<Option<Channel<'static>>>> = CsMutex::new(RefCell::new(None));
+
+/// Registra l'ADC e il canale del sensore di temperatura: da chiamare una
+/// sola volta all'avvio
+///
+/// # Argomenti
+/// * `adc` - ADC in modalità bloccante
+/// * `temp_sensor` - Canale del sensore di temperatura
+pub fn init(adc: Adc<'static, Blocking>, temp_sensor: Channel<'static>) {
+    critical_section::with(|cs| {
+        ADC.borrow(cs).replace(Some(adc));
+        TEMP_SENSOR.borrow(cs).replace(Some(temp_sensor));
+    });
+}
+
+/// Esegue una conversione sull'ADC condiviso
+///
+/// # Argomenti
+/// * `channel` - Canale da leggere
+///
+/// # Ritorna
+/// * Option<u16> - Lettura a 12 bit, None se l'ADC non è registrato o la conversione fallisce
+pub fn read(channel: &mut Channel<'static>) -> Option<u16> {
+    critical_section::with(|cs| {
+        let mut adc = ADC.borrow(cs).borrow_mut();
+        adc.as_mut()?.blocking_read(channel).ok()
+    })
+}
+
+/// Temperatura restituita da /api/temperature
+#[derive(serde::Serialize)]
+pub struct Temperature {
+    /// Lettura dell'ADC (media di SAMPLES campioni)
+    raw: u16,
+    /// Tensione del sensore
+    millivolts: u32,
+    /// Temperatura in millesimi di grado
+    millicelsius: i32,
+    celsius: f32,
+}
+
+impl Temperature {
+    /// Ritorna la temperatura in millesimi di grado
+    pub fn millicelsius(&self) -> i32 {
+        self.millicelsius
+    }
+}
+
+/// Converte una lettura dell'ADC del sensore in millesimi di grado
+///
+/// # Argomenti
+/// * `raw` - Lettura a 12 bit
+///
+/// # Ritorna
+/// * i32 - Temperatura in m°C
+fn millicelsius(raw: u16) -> i32 {
+    let microvolts = (raw as u32 * ADC_REF_MV * 1000 / 4096) as i32;
+    27_000 - (microvolts - SENSOR_UV_AT_27C) * 1000 / SENSOR_UV_PER_C
+}
+
+/// Legge la temperatura del chip
+///
+/// # Ritorna
+/// * Option<Temperature> - Temperatura, None se il sensore non è disponibile
+pub fn temperature() -> Option<Temperature> {
+    let total = critical_section::with(|cs| {
+        let mut channel = TEMP_SENSOR.borrow(cs).borrow_mut();
+        let channel = channel.as_mut()?;
+        (0..SAMPLES).try_fold(0u32, |total, _| Some(total + read(channel)? as u32))
+    })?;
+    let raw = (total / SAMPLES) as u16;
+    let millicelsius = millicelsius(raw);
+    Some(Temperature {
+        raw,
+        millivolts: raw as u32 * ADC_REF_MV / 4096,
+        millicelsius,
+        celsius: millicelsius as f32 / 1000.0,
+    })
+}
+
+/// Rotte del sensore: `/temperature` (pagina) e `/api/temperature` (JSON)
+pub struct SensorRoutes;
+
+impl Routes for SensorRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                "/temperature",
+                get(|lang: Lang, accept: AcceptGzip| async move {
+                    lang.strings().temperature_page.html(accept)
+                }),
+            )
+            .route(
+                "/api/temperature",
+                get(|lang: Lang| async move {
+                    temperature().map(Json).ok_or((
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        lang.strings().sensor_unavailable,
+                    ))
+                }),
+            )
+    }
+}
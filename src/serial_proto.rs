@@ -0,0 +1,324 @@
+//! serial_proto.rs - Protocollo seriale in stile AT condiviso da UART0
+//! (console), UART1 (I/O applicativo), USB CDC e telnet. Le righe arrivano
+//! qui tramite `commands::handle_line`, che gestisce anche i comandi testuali.
+//!
+//! Comandi supportati (una riga per comando):
+//! * `AT` - verifica della connessione
+//! * `AT+STATUS?` - uptime, stato del link e indirizzo IP
+//! * `AT+SOLVE=<schema>` - risolve uno schema (81 caratteri compatti oppure
+//!   9 righe separate da spazi nel formato a righe)
+//! * `AT+WIFI?` - rete in uso (senza join quella configurata) e stato del link
+//! * `AT+WIFI=<ssid>,<password>` - salva la nuova rete in flash (vale anche
+//!   dopo il riavvio, vedi config_store.rs) e si ricollega; l'esito arriva
+//!   come notifica (`+WIFI: JOINED,<ssid>`, `+WIFI: UP`)
+//! * `AT+REBOOT` - riavvia la pico
+//! * `AT+BOOTSEL` - riavvia la pico in modalità BOOTSEL per l'aggiornamento
+//!
+//! Ogni comando termina con `OK` oppure `ERROR`, eventualmente preceduti da
+//! righe di risposta `+CMD: ...`. Le notifiche non richieste (URC) sono
+//! ricavate dagli eventi del bus (vedi bus.rs) con [`urc`] e inoltrate dai
+//! trasporti che le supportano.
+
+use crate::bus::{Event, SolveSource};
+use crate::commands;
+use crate::config_store;
+use crate::errors::{self, ErrorKind};
+use crate::line_framer::{Frame, LineFramer};
+use crate::resources;
+use crate::system;
+use crate::utility::get_ssid;
+use crate::wifi;
+use core::cell::RefCell;
+use critical_section::Mutex as CsMutex;
+use embassy_rp::uart::{Async, BufferedUartTx, UartTx};
+use embassy_time::Instant;
+use heapless::format;
+
+/// Lunghezza massima di una notifica URC
+pub const URC_CAPACITY: usize = 96;
+
+/// Notifica non richiesta
+pub type Urc = heapless::String<URC_CAPACITY>;
+
+/// Comando AT riconosciuto
+pub enum AtCommand<'a> {
+    Test,
+    StatusQuery,
+    Solve(&'a str),
+    WifiQuery,
+    SetWifi { ssid: &'a str, password: &'a str },
+    Reboot,
+    Bootsel,
+}
+
+/// Errori di parsing dei comandi AT
+#[derive(Debug)]
+pub enum AtError {
+    NotAt,
+    UnknownCommand,
+    InvalidArguments,
+}
+
+/// Destinazione delle righe di risposta di un trasporto
+pub trait LineSink {
+    /// Scrive una riga aggiungendo il terminatore "\r\n"
+    async fn write_line(&mut self, line: &str);
+}
+
+// Le righe sulle UART vengono ritrasmesse qualche volta e poi scartate
+// (vedi errors.rs): un errore di trasmissione non interrompe il task
+impl LineSink for BufferedUartTx {
+    async fn write_line(&mut self, line: &str) {
+        let result = errors::retry(ErrorKind::UartTx, async || {
+            embedded_io_async::Write::write_all(self, line.as_bytes()).await?;
+            embedded_io_async::Write::write_all(self, b"\r\n").await
+        });
+        if let Err(e) = result.await {
+            log::warn!(
+                "serial_proto: errore di trasmissione {:?}, riga scartata",
+                e
+            );
+        }
+    }
+}
+
+impl LineSink for UartTx<'static, Async> {
+    async fn write_line(&mut self, line: &str) {
+        let result = errors::retry(ErrorKind::UartTx, async || {
+            self.write(line.as_bytes()).await?;
+            self.write(b"\r\n").await
+        });
+        if let Err(e) = result.await {
+            log::warn!(
+                "serial_proto: errore di trasmissione {:?}, riga scartata",
+                e
+            );
+        }
+    }
+}
+
+/// Sink che inoltra le risposte al logger (usato per la USB CDC,
+/// dove il canale in uscita è quello del logger)
+pub struct LogSink;
+
+impl LineSink for LogSink {
+    async fn write_line(&mut self, line: &str) {
+        log::info!("{line}");
+    }
+}
+
+/// Verifica se una riga è un comando AT
+///
+/// # Argomenti
+/// * `line` - Riga ricevuta
+///
+/// # Ritorna
+/// * bool - true se la riga inizia con "AT"
+pub fn is_at_command(line: &str) -> bool {
+    line.get(..2)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("AT"))
+}
+
+/// Interpreta una riga come comando AT
+///
+/// # Argomenti
+/// * `line` - Riga ricevuta (senza terminatore)
+///
+/// # Ritorna
+/// * Result<AtCommand, AtError> - Comando riconosciuto oppure errore
+pub fn parse(line: &str) -> Result<AtCommand<'_>, AtError> {
+    let line = line.trim();
+    if !is_at_command(line) {
+        return Err(AtError::NotAt);
+    }
+    let rest = &line[2..];
+    if rest.is_empty() {
+        return Ok(AtCommand::Test);
+    }
+    let rest = rest.strip_prefix('+').ok_or(AtError::UnknownCommand)?;
+
+    let (name, args) = match rest.find(['=', '?']) {
+        Some(pos) => rest.split_at(pos),
+        None => (rest, ""),
+    };
+
+    if name.eq_ignore_ascii_case("STATUS") && args == "?" {
+        Ok(AtCommand::StatusQuery)
+    } else if name.eq_ignore_ascii_case("WIFI") && args == "?" {
+        Ok(AtCommand::WifiQuery)
+    } else if name.eq_ignore_ascii_case("REBOOT") && args.is_empty() {
+        Ok(AtCommand::Reboot)
+    } else if name.eq_ignore_ascii_case("BOOTSEL") && args.is_empty() {
+        Ok(AtCommand::Bootsel)
+    } else if name.eq_ignore_ascii_case("SOLVE") {
+        match args.strip_prefix('=') {
+            Some(schema) if !schema.trim().is_empty() => Ok(AtCommand::Solve(schema.trim())),
+            _ => Err(AtError::InvalidArguments),
+        }
+    } else if name.eq_ignore_ascii_case("WIFI") {
+        let (ssid, password) = args
+            .strip_prefix('=')
+            .and_then(|a| a.split_once(','))
+            .ok_or(AtError::InvalidArguments)?;
+        if ssid.is_empty() {
+            return Err(AtError::InvalidArguments);
+        }
+        Ok(AtCommand::SetWifi { ssid, password })
+    } else {
+        Err(AtError::UnknownCommand)
+    }
+}
+
+/// Interpreta ed esegue una riga, scrivendo la risposta sul sink
+///
+/// # Argomenti
+/// * `line` - Riga ricevuta
+/// * `sink` - Destinazione della risposta
+pub async fn handle_line<S: LineSink>(line: &str, sink: &mut S) {
+    match parse(line) {
+        Ok(cmd) => execute(cmd, sink).await,
+        Err(AtError::NotAt) => {}
+        Err(e) => {
+            let out: heapless::String<48> = errors::fit(format_args!("+ERROR: {:?}", e));
+            sink.write_line(&out).await;
+            sink.write_line("ERROR").await;
+        }
+    }
+}
+
+/// Esegue un comando AT
+///
+/// # Argomenti
+/// * `cmd` - Comando da eseguire
+/// * `sink` - Destinazione della risposta
+pub async fn execute<S: LineSink>(cmd: AtCommand<'_>, sink: &mut S) {
+    match cmd {
+        AtCommand::Test => sink.write_line("OK").await,
+        AtCommand::StatusQuery => {
+            let stack = resources::get::<embassy_net::Stack<'static>>();
+            let link = stack.is_some_and(|s| s.is_link_up());
+            let mut out: heapless::String<96> = errors::fit(format_args!(
+                "+STATUS: {},{}",
+                Instant::now().as_secs(),
+                if link { "UP" } else { "DOWN" }
+            ));
+            if let Some(config) = stack.and_then(|s| s.config_v4()) {
+                let ip: heapless::String<24> =
+                    errors::fit(format_args!(",{}", config.address.address()));
+                let _ = out.push_str(&ip);
+            }
+            sink.write_line(&out).await;
+            sink.write_line("OK").await;
+        }
+        AtCommand::Solve(schema) => match commands::solve(schema, SolveSource::Serial).await {
+            Ok(sudoku) => {
+                let mut out = heapless::String::<{ 8 + 81 }>::new();
+                let _ = out.push_str("+SOLVE: ");
+                for cell in sudoku.grid.iter().flatten() {
+                    let _ = out.push((b'0' + cell) as char);
+                }
+                sink.write_line(&out).await;
+                sink.write_line("OK").await;
+            }
+            Err(e) => {
+                let out: heapless::String<48> = errors::fit(format_args!("+ERROR: {:?}", e));
+                sink.write_line(&out).await;
+                sink.write_line("ERROR").await;
+            }
+        },
+        AtCommand::WifiQuery => {
+            let link =
+                resources::get::<embassy_net::Stack<'static>>().is_some_and(|s| s.is_link_up());
+            let out: heapless::String<64> = errors::fit(format_args!(
+                "+WIFI: {},{}",
+                wifi::joined_ssid().unwrap_or_else(get_ssid),
+                if link { "UP" } else { "DOWN" }
+            ));
+            sink.write_line(&out).await;
+            sink.write_line("OK").await;
+        }
+        AtCommand::Reboot => {
+            sink.write_line("OK").await;
+            system::reboot().await;
+        }
+        AtCommand::Bootsel => {
+            sink.write_line("OK").await;
+            system::enter_bootsel().await;
+        }
+        AtCommand::SetWifi { ssid, password } => {
+            let result = config_store::set_ssid(ssid)
+                .and_then(|()| config_store::set_wifi_password(password));
+            match result {
+                Ok(()) => {
+                    // L'esito del join arriva come URC (+WIFI: JOINED / +WIFI: UP)
+                    wifi::request_reconnect();
+                    sink.write_line("OK").await;
+                }
+                Err(e) => {
+                    let out: heapless::String<48> = errors::fit(format_args!("+ERROR: {:?}", e));
+                    sink.write_line(&out).await;
+                    sink.write_line("ERROR").await;
+                }
+            }
+        }
+    }
+}
+
+/// Traduce un evento del bus nella notifica non richiesta (URC) corrispondente
+///
+/// # Argomenti
+/// * `event` - Evento ricevuto dal bus
+///
+/// # Ritorna
+/// * Option<Urc> - Notifica, es. "+WIFI: JOINED,rete"; None se l'evento non ne prevede
+pub fn urc(event: &Event) -> Option<Urc> {
+    let urc: Urc = match event {
+        Event::WifiStateChanged { state } => {
+            let mut urc: Urc = format!("+WIFI: ").ok()?;
+            for c in state.as_str().chars() {
+                let _ = urc.push(c.to_ascii_uppercase());
+            }
+            Ok(urc)
+        }
+        Event::WifiJoined { ssid } => format!("+WIFI: JOINED,{}", ssid),
+        Event::PuzzleSolved { source } => format!("+SOLVED: {}", source.as_str()),
+        Event::ButtonPressed { pin } => format!("+BUTTON: {}", pin),
+        Event::SolveStarted | Event::SolveFailed { .. } | Event::LedModeChanged { .. } => {
+            return None;
+        }
+    }
+    .ok()?;
+    Some(urc)
+}
+
+/// Handler della USB CDC: suddivide i dati ricevuti in righe e le esegue
+/// come comandi (vedi commands.rs), rispondendo tramite il logger USB.
+pub struct UsbCommandHandler {
+    framer: CsMutex<RefCell<LineFramer<{ commands::LINE_CAPACITY }>>>,
+}
+
+impl embassy_usb_logger::ReceiverHandler for UsbCommandHandler {
+    fn new() -> Self {
+        Self {
+            framer: CsMutex::new(RefCell::new(LineFramer::new())),
+        }
+    }
+
+    async fn handle_data(&self, data: &[u8]) {
+        for &byte in data {
+            // La riga viene copiata fuori dalla sezione critica prima di eseguirla
+            let line = critical_section::with(|cs| {
+                let mut framer = self.framer.borrow(cs).borrow_mut();
+                match framer.push(byte) {
+                    Some(Frame::Line(line)) => core::str::from_utf8(line).ok().and_then(|l| {
+                        heapless::String::<{ commands::LINE_CAPACITY }>::try_from(l).ok()
+                    }),
+                    _ => None,
+                }
+            });
+            if let Some(line) = line {
+                commands::handle_line(&line, SolveSource::Serial, &mut LogSink).await;
+            }
+        }
+    }
+}
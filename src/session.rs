@@ -0,0 +1,293 @@
+//! session.rs - Sessioni di login per le pagine protette.
+//!
+//! Con le credenziali `HTTP_AUTH_USER`/`HTTP_AUTH_PASSWORD` configurate (vedi
+//! auth.rs), un browser senza sessione che apre una rotta protetta viene
+//! reindirizzato a [`LOGIN_PATH`] invece di ricevere la finestra del Basic
+//! Auth. Il form di login, se le credenziali sono giuste, apre una sessione:
+//! un token casuale di 128 bit (generato con `RoscRng`) inviato al browser
+//! nel cookie `session`, `HttpOnly` e `SameSite=Strict`.
+//!
+//! Le sessioni stanno in una tabella di [`SESSION_SLOTS`] posti: scadono
+//! dopo [`SESSION_TTL_SECS`] senza richieste (ogni richiesta le rinnova) e,
+//! a tabella piena, una nuova sessione prende il posto di quella più vicina
+//! alla scadenza. Un riavvio chiude tutte le sessioni. `POST /logout` chiude
+//! la sessione corrente.
+//!
+//! I client senza browser (es. curl) continuano a usare il Basic Auth.
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::assets::AcceptGzip;
+use crate::auth;
+use crate::i18n::Lang;
+use core::cell::RefCell;
+use core::convert::Infallible;
+use core::fmt;
+use critical_section::Mutex as CsMutex;
+use embassy_rp::clocks::RoscRng;
+use embassy_time::{Duration, Instant};
+use picoserve::extract::FromRequestParts;
+use picoserve::request::RequestParts;
+use picoserve::response::{IntoResponse, Response, StatusCode};
+use picoserve::routing::{PathRouter, get, post};
+
+/// Pagina di login
+pub const LOGIN_PATH: &str = "/login";
+
+/// Chiusura della sessione
+const LOGOUT_PATH: &str = "/logout";
+
+/// Nome del cookie con il token
+const COOKIE_NAME: &str = "session";
+
+/// Sessioni aperte contemporaneamente
+const SESSION_SLOTS: usize = 4;
+
+/// Durata di una sessione senza richieste
+const SESSION_TTL_SECS: u64 = 30 * 60;
+
+/// Token di sessione: 16 byte casuali scritti in esadecimale
+type Token = [u8; 32];
+
+/// Sessione aperta
+#[derive(Clone, Copy)]
+struct Session {
+    token: Token,
+    expires: Instant,
+}
+
+/// Tabella delle sessioni aperte
+static SESSIONS: CsMutex<RefCell<[Option<Session>; SESSION_SLOTS]>> =
+    CsMutex::new(RefCell::new([None; SESSION_SLOTS]));
+
+/// Genera un nuovo token casuale
+fn random_token() -> Token {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let mut bytes = [0u8; 16];
+    RoscRng.fill_bytes(&mut bytes);
+    let mut token = [0u8; 32];
+    for (i, byte) in bytes.iter().enumerate() {
+        token[2 * i] = HEX[(byte >> 4) as usize];
+        token[2 * i + 1] = HEX[(byte & 0x0f) as usize];
+    }
+    token
+}
+
+/// Apre una sessione, liberando se serve quella più vicina alla scadenza
+///
+/// # Ritorna
+/// * Token - Token della nuova sessione
+fn open() -> Token {
+    let token = random_token();
+    let session = Session {
+        token,
+        expires: Instant::now() + Duration::from_secs(SESSION_TTL_SECS),
+    };
+    critical_section::with(|cs| {
+        let mut sessions = SESSIONS.borrow_ref_mut(cs);
+        let now = Instant::now();
+        let slot = sessions.iter_mut().min_by_key(|slot| match slot {
+            Some(session) if session.expires > now => Some(session.expires),
+            _ => None,
+        });
+        if let Some(slot) = slot {
+            *slot = Some(session);
+        }
+    });
+    token
+}
+
+/// Chiude la sessione con un token
+fn close(token: &[u8]) {
+    critical_section::with(|cs| {
+        for slot in SESSIONS.borrow_ref_mut(cs).iter_mut() {
+            if slot.is_some_and(|session| auth::same_bytes(&session.token, token)) {
+                *slot = None;
+            }
+        }
+    });
+}
+
+/// Verifica il token di una richiesta e, se valido, rinnova la sessione
+///
+/// # Argomenti
+/// * `token` - Token letto dal cookie
+///
+/// # Ritorna
+/// * bool - true se il token appartiene a una sessione non scaduta
+pub fn is_valid(token: &[u8]) -> bool {
+    let now = Instant::now();
+    critical_section::with(|cs| {
+        let mut valid = false;
+        for slot in SESSIONS.borrow_ref_mut(cs).iter_mut() {
+            match slot {
+                Some(session) if session.expires <= now => *slot = None,
+                // Confronto su tutti i posti: la durata non rivela quale coincide
+                Some(session) if auth::same_bytes(&session.token, token) => {
+                    session.expires = now + Duration::from_secs(SESSION_TTL_SECS);
+                    valid = true;
+                }
+                _ => {}
+            }
+        }
+        valid
+    })
+}
+
+/// Estrae il token di sessione dall'intestazione `Cookie`
+///
+/// # Argomenti
+/// * `header` - Valore dell'intestazione, es. `lang=it; session=0f3a...`
+///
+/// # Ritorna
+/// * Option<&[u8]> - Token, None se il cookie manca
+pub fn cookie_token(header: &[u8]) -> Option<&[u8]> {
+    header
+        .split(|&b| b == b';')
+        .map(<[u8]>::trim_ascii)
+        .find_map(|cookie| {
+            cookie
+                .strip_prefix(COOKIE_NAME.as_bytes())?
+                .strip_prefix(b"=")
+        })
+}
+
+/// Token di sessione inviato dal browser, estraibile negli handler
+pub struct SessionToken(Option<heapless::Vec<u8, 32>>);
+
+impl<'r, State> FromRequestParts<'r, State> for SessionToken {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        _state: &'r State,
+        request_parts: &RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        let header = request_parts.headers().get("Cookie");
+        let token = header
+            .as_ref()
+            .and_then(|value| cookie_token(value.as_raw()))
+            .and_then(|token| heapless::Vec::from_slice(token).ok());
+        Ok(Self(token))
+    }
+}
+
+/// Intestazione `Set-Cookie` che apre (con un token) o chiude una sessione
+struct SetCookie(Option<Token>);
+
+impl fmt::Display for SetCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (token, max_age) = match &self.0 {
+            Some(token) => (
+                core::str::from_utf8(token).unwrap_or_default(),
+                SESSION_TTL_SECS,
+            ),
+            None => ("", 0),
+        };
+        write!(
+            f,
+            "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict",
+            COOKIE_NAME, token, max_age
+        )
+    }
+}
+
+/// Indirizzo a cui tornare dopo il login: solo percorsi locali, così il
+/// form non può reindirizzare a un altro sito
+///
+/// # Argomenti
+/// * `next` - Campo `next` del form
+///
+/// # Ritorna
+/// * &str - Percorso richiesto, oppure `/`
+fn local_target(next: Option<&str>) -> &str {
+    next.map(str::trim)
+        .filter(|next| next.starts_with('/') && !next.starts_with("//") && !next.contains('\\'))
+        .unwrap_or("/")
+}
+
+/// Risposta che reindirizza alla pagina di login, con il percorso a cui
+/// tornare
+///
+/// # Argomenti
+/// * `path` - Percorso richiesto
+///
+/// # Ritorna
+/// * impl IntoResponse - Risposta `303 See Other`
+pub fn redirect_to_login(path: &str) -> impl IntoResponse {
+    let location: heapless::String<96> =
+        crate::errors::fit(format_args!("{}?next={}", LOGIN_PATH, path));
+    Response::new(StatusCode::SEE_OTHER, "").with_header("Location", location)
+}
+
+/// Credenziali inviate a `POST /login`
+#[derive(serde::Deserialize)]
+pub struct LoginForm {
+    pub user: heapless::String<32>,
+    pub password: heapless::String<64>,
+    /// Percorso da aprire dopo il login
+    #[serde(default)]
+    pub next: Option<heapless::String<64>>,
+}
+
+/// Verifica le credenziali del form e apre una sessione
+///
+/// # Argomenti
+/// * `form` - Form ricevuto
+/// * `lang` - Lingua della risposta
+///
+/// # Ritorna
+/// * Result<impl IntoResponse, (StatusCode, &'static str)> - Redirect al
+///   percorso richiesto con il cookie, oppure 401 se le credenziali sono errate
+fn login(form: &LoginForm, lang: Lang) -> Result<impl IntoResponse, (StatusCode, &'static str)> {
+    let target = local_target(form.next.as_deref());
+    // Senza credenziali configurate non serve una sessione
+    let cookie = if auth::credentials_required() {
+        if !auth::check_credentials(form.user.as_bytes(), form.password.as_bytes()) {
+            log::warn!("Login: credenziali errate per '{}'", form.user);
+            return Err((StatusCode::UNAUTHORIZED, lang.strings().login_failed));
+        }
+        log::info!("Login: sessione aperta per '{}'", form.user);
+        SetCookie(Some(open()))
+    } else {
+        SetCookie(None)
+    };
+    let location: heapless::String<64> = crate::errors::fit(format_args!("{}", target));
+    Ok(Response::new(StatusCode::SEE_OTHER, "")
+        .with_header("Location", location)
+        .with_header("Set-Cookie", cookie))
+}
+
+/// Rotte delle sessioni: `/login` mostra (GET) e invia (POST) il form di
+/// login, `POST /logout` chiude la sessione
+pub struct SessionRoutes;
+
+impl Routes for SessionRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                LOGIN_PATH,
+                get(|lang: Lang, accept: AcceptGzip| async move {
+                    lang.strings().login_page.html(accept)
+                })
+                .post(
+                    |lang: Lang,
+                     picoserve::extract::Form(form): picoserve::extract::Form<LoginForm>| {
+                        async move { login(&form, lang) }
+                    },
+                ),
+            )
+            .route(
+                LOGOUT_PATH,
+                post(|SessionToken(token): SessionToken| async move {
+                    if let Some(token) = token {
+                        close(&token);
+                    }
+                    Response::new(StatusCode::SEE_OTHER, "")
+                        .with_header("Location", LOGIN_PATH)
+                        .with_header("Set-Cookie", SetCookie(None))
+                }),
+            )
+    }
+}
@@ -0,0 +1,247 @@
+//! settings.rs - Impostazioni di rete modificabili via HTTP.
+//!
+//! * `GET /settings` - pagina con le impostazioni correnti (lette da
+//!   `/api/settings`) e il form per cambiarle;
+//! * `POST /settings` - riceve i campi `ssid`, `password`, `ip`, `gateway`,
+//!   `mask` e `hostname` e li salva in flash con i setter di config_store.rs.
+//!
+//! I campi assenti o vuoti restano invariati; se un campo non è valido non
+//! viene salvato nulla. Le modifiche passano da [`Settings`], nello stato
+//! dell'applicazione: la rete e il nome vengono letti all'avvio, quindi i
+//! nuovi valori restano in attesa del riavvio (`reboot_required` su
+//! `/api/settings`). Sono rotte di amministrazione (vedi acl.rs).
+//!
+//! Esempio:
+//! ```text
+//! curl -d 'ip=192.168.1.50&gateway=192.168.1.1&mask=24' http://<ip>/settings
+//! ```
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::assets::AcceptGzip;
+use crate::config_store;
+use crate::i18n::Lang;
+use crate::utility::{
+    ConfigError, get_gateway_address, get_hostname, get_ip_address, get_ssid, get_subnet_mask,
+};
+use crate::wifi;
+use picoserve::extract::State;
+use picoserve::response::{Json, StatusCode};
+use picoserve::routing::{PathRouter, get};
+use portable_atomic::{AtomicBool, Ordering};
+
+/// true se ci sono impostazioni salvate che valgono solo dal riavvio
+static REBOOT_REQUIRED: AtomicBool = AtomicBool::new(false);
+
+/// Impostazioni inviate a `POST /settings`
+#[derive(serde::Deserialize)]
+pub struct SettingsForm {
+    #[serde(default)]
+    pub ssid: Option<heapless::String<32>>,
+    #[serde(default)]
+    pub password: Option<heapless::String<64>>,
+    #[serde(default)]
+    pub ip: Option<heapless::String<16>>,
+    #[serde(default)]
+    pub gateway: Option<heapless::String<16>>,
+    #[serde(default)]
+    pub mask: Option<heapless::String<4>>,
+    #[serde(default)]
+    pub hostname: Option<heapless::String<32>>,
+}
+
+/// Valore di un campo del form, None se assente o vuoto
+fn field<const N: usize>(value: &Option<heapless::String<N>>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Legge un indirizzo IPv4 in notazione puntata, es. `192.168.1.50`
+fn parse_address(text: &str) -> Result<[u8; 4], ConfigError> {
+    text.parse::<core::net::Ipv4Addr>()
+        .map(|address| address.octets())
+        .map_err(|_| ConfigError::InvalidValue)
+}
+
+/// Impostazioni convalidate, pronte per il salvataggio
+struct SettingsUpdate<'a> {
+    ssid: Option<&'a str>,
+    password: Option<&'a str>,
+    ip: Option<[u8; 4]>,
+    gateway: Option<[u8; 4]>,
+    mask: Option<u8>,
+    hostname: Option<&'a str>,
+}
+
+impl<'a> SettingsUpdate<'a> {
+    /// Convalida tutti i campi del form prima di salvarne qualcuno
+    ///
+    /// # Argomenti
+    /// * `form` - Form ricevuto
+    ///
+    /// # Ritorna
+    /// * Result<SettingsUpdate, ConfigError> - Impostazioni, InvalidValue se un campo non è valido
+    fn parse(form: &'a SettingsForm) -> Result<Self, ConfigError> {
+        let mask = match field(&form.mask) {
+            Some(mask) => Some(
+                mask.parse::<u8>()
+                    .ok()
+                    .filter(|&mask| mask <= 32)
+                    .ok_or(ConfigError::InvalidValue)?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            ssid: field(&form.ssid),
+            // Il form invia sempre la password: vuota = invariata
+            password: field(&form.password),
+            ip: field(&form.ip).map(parse_address).transpose()?,
+            gateway: field(&form.gateway).map(parse_address).transpose()?,
+            mask,
+            hostname: field(&form.hostname),
+        })
+    }
+
+    /// Salva in flash i campi presenti
+    fn save(&self) -> Result<(), ConfigError> {
+        if let Some(ssid) = self.ssid {
+            config_store::set_ssid(ssid)?;
+        }
+        if let Some(password) = self.password {
+            config_store::set_wifi_password(password)?;
+        }
+        if let Some(ip) = self.ip {
+            config_store::set_ip_address(ip)?;
+        }
+        if let Some(gateway) = self.gateway {
+            config_store::set_gateway_address(gateway)?;
+        }
+        if let Some(mask) = self.mask {
+            config_store::set_subnet_mask(mask)?;
+        }
+        if let Some(hostname) = self.hostname {
+            config_store::set_hostname(hostname)?;
+        }
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ssid.is_none()
+            && self.password.is_none()
+            && self.ip.is_none()
+            && self.gateway.is_none()
+            && self.mask.is_none()
+            && self.hostname.is_none()
+    }
+}
+
+/// Impostazioni correnti, restituite da /api/settings
+#[derive(serde::Serialize)]
+pub struct SettingsReport {
+    ssid: heapless::String<64>,
+    hostname: heapless::String<64>,
+    ip: heapless::String<16>,
+    gateway: heapless::String<16>,
+    mask: u8,
+    /// Modo di assegnazione dell'indirizzo (vedi wifi.rs)
+    addressing: &'static str,
+    reboot_required: bool,
+}
+
+/// Scrive un indirizzo in notazione puntata
+fn dotted(address: [u8; 4]) -> heapless::String<16> {
+    let [a, b, c, d] = address;
+    crate::errors::fit(format_args!("{}.{}.{}.{}", a, b, c, d))
+}
+
+/// Impostazioni di rete della pico, condivise dai worker del web server
+/// tramite lo stato dell'applicazione
+#[derive(Clone, Copy)]
+pub struct Settings;
+
+impl Settings {
+    /// Ritorna le impostazioni salvate (quelle in uso, se non c'è un riavvio
+    /// in attesa)
+    ///
+    /// # Ritorna
+    /// * SettingsReport - Impostazioni correnti
+    pub fn current(&self) -> SettingsReport {
+        SettingsReport {
+            ssid: get_ssid(),
+            hostname: get_hostname(),
+            ip: dotted(get_ip_address()),
+            gateway: dotted(get_gateway_address()),
+            mask: get_subnet_mask(),
+            addressing: wifi::addressing(),
+            reboot_required: REBOOT_REQUIRED.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Convalida e salva le impostazioni di un form; i nuovi valori restano
+    /// in attesa del riavvio
+    ///
+    /// # Argomenti
+    /// * `form` - Form ricevuto
+    ///
+    /// # Ritorna
+    /// * Result<(), ConfigError> - InvalidValue se un campo non è valido (nulla
+    ///   viene salvato), Storage se la scrittura in flash fallisce
+    pub fn apply(&self, form: &SettingsForm) -> Result<(), ConfigError> {
+        let update = SettingsUpdate::parse(form)?;
+        if update.is_empty() {
+            return Ok(());
+        }
+        // Anche un salvataggio a metà cambia i valori letti al prossimo avvio
+        REBOOT_REQUIRED.store(true, Ordering::Relaxed);
+        update.save()
+    }
+}
+
+/// Convalida e salva le impostazioni ricevute
+///
+/// # Argomenti
+/// * `settings` - Impostazioni dallo stato dell'applicazione
+/// * `form` - Form ricevuto
+/// * `lang` - Lingua della risposta
+///
+/// # Ritorna
+/// * (StatusCode, &'static str) - Esito per l'utente
+fn update(settings: Settings, form: &SettingsForm, lang: Lang) -> (StatusCode, &'static str) {
+    let strings = lang.strings();
+    match settings.apply(form) {
+        Ok(()) => (StatusCode::OK, strings.settings_saved),
+        Err(ConfigError::InvalidValue) => (StatusCode::BAD_REQUEST, strings.settings_invalid),
+        Err(e) => {
+            log::error!("Impostazioni: salvataggio fallito: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, strings.settings_failed)
+        }
+    }
+}
+
+/// Rotte delle impostazioni: `/settings` mostra (GET) e salva (POST) le
+/// impostazioni di rete, `/api/settings` le restituisce in JSON
+pub struct SettingsRoutes;
+
+impl Routes for SettingsRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                "/settings",
+                get(|lang: Lang, accept: AcceptGzip| async move {
+                    lang.strings().settings_page.html(accept)
+                })
+                .post(
+                    |State(settings): State<Settings>,
+                     lang: Lang,
+                     picoserve::extract::Form(form): picoserve::extract::Form<SettingsForm>| {
+                        async move { update(settings, &form, lang) }
+                    },
+                ),
+            )
+            .route(
+                "/api/settings",
+                get(|State(settings): State<Settings>| async move { Json(settings.current()) }),
+            )
+    }
+}
@@ -0,0 +1,297 @@
+//! smtp.rs - Avviso via email dopo un crash.
+//!
+//! Se la pico si avvia dopo un panic o un reset del watchdog (vedi
+//! crash_log.rs) e `SMTP_SERVER` e `SMTP_TO` sono configurati, il task
+//! [`smtp_alert_task`] invia il record del crash all'indirizzo indicato con
+//! un client SMTP minimo. Con `SMTP_USER` viene usato `AUTH PLAIN`.
+//!
+//! Con `SMTP_STARTTLS=true` la sessione passa a TLS dopo il comando
+//! `STARTTLS` (feature `smtp-tls`, basata su embedded-tls). Il certificato
+//! del server non viene verificato: la cifratura protegge la password dalla
+//! rete, non dall'impersonificazione del server.
+
+use crate::crash_log;
+use crate::errors;
+use crate::fetch;
+use crate::i18n::Lang;
+use crate::utility::{ConfigValue, base64, get_smtp_config};
+use embassy_net::tcp::TcpSocket;
+use embassy_net::{IpEndpoint, Stack};
+use embassy_time::{Duration, Timer};
+use embedded_io_async::{Read, Write};
+
+/// Tentativi di invio prima di rinunciare
+const ATTEMPTS: u32 = 5;
+
+/// Pausa tra due tentativi
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Timeout delle operazioni sul socket
+const IO_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Nome con cui la pico si presenta al server (EHLO)
+const CLIENT_NAME: &str = "picoweb";
+
+/// Configurazione del server e dei destinatari
+pub struct SmtpConfig {
+    pub server: ConfigValue,
+    pub port: u16,
+    pub starttls: bool,
+    pub from: ConfigValue,
+    pub to: ConfigValue,
+    /// Utente per AUTH PLAIN, vuoto per non autenticarsi
+    pub user: ConfigValue,
+    pub password: ConfigValue,
+}
+
+/// Errori di un invio
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SmtpError {
+    /// Nome del server non risolto
+    Dns,
+    /// Connessione rifiutata o scaduta
+    Connect,
+    /// Errore di invio o ricezione
+    Io,
+    /// Risposta diversa da quella attesa (codice ricevuto)
+    Reply(u16),
+    /// Risposta non SMTP
+    Malformed,
+    /// Handshake TLS fallito
+    Tls,
+    /// STARTTLS richiesto senza la feature smtp-tls
+    TlsUnavailable,
+}
+
+impl core::fmt::Display for SmtpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SmtpError::Dns => f.write_str("dns"),
+            SmtpError::Connect => f.write_str("connect"),
+            SmtpError::Io => f.write_str("io"),
+            SmtpError::Reply(code) => write!(f, "risposta {}", code),
+            SmtpError::Malformed => f.write_str("malformed"),
+            SmtpError::Tls => f.write_str("tls"),
+            SmtpError::TlsUnavailable => f.write_str("STARTTLS senza la feature smtp-tls"),
+        }
+    }
+}
+
+/// Legge una risposta del server, anche su più righe ("250-...")
+///
+/// # Argomenti
+/// * `io` - Connessione con il server
+///
+/// # Ritorna
+/// * Result<u16, SmtpError> - Codice della risposta
+async fn reply<T: Read>(io: &mut T) -> Result<u16, SmtpError> {
+    let mut line: heapless::Vec<u8, 8> = heapless::Vec::new();
+    loop {
+        let mut byte = [0];
+        if io.read(&mut byte).await.map_err(|_| SmtpError::Io)? == 0 {
+            return Err(SmtpError::Io);
+        }
+        match byte[0] {
+            b'\n' => {
+                let code = core::str::from_utf8(line.get(..3).ok_or(SmtpError::Malformed)?)
+                    .ok()
+                    .and_then(|code| code.parse::<u16>().ok())
+                    .ok_or(SmtpError::Malformed)?;
+                // "250-" annuncia altre righe, "250 " chiude la risposta
+                if line.get(3) != Some(&b'-') {
+                    return Ok(code);
+                }
+                line.clear();
+            }
+            // Del resto della riga interessano solo codice e separatore
+            byte => {
+                let _ = line.push(byte);
+            }
+        }
+    }
+}
+
+/// Invia un comando e controlla il codice della risposta
+///
+/// # Argomenti
+/// * `io` - Connessione con il server
+/// * `parts` - Parti del comando, senza il "\r\n" finale
+/// * `expected` - Codice atteso
+async fn command<T: Read + Write>(
+    io: &mut T,
+    parts: &[&str],
+    expected: u16,
+) -> Result<(), SmtpError> {
+    for part in parts.iter().chain(&["\r\n"]) {
+        io.write_all(part.as_bytes())
+            .await
+            .map_err(|_| SmtpError::Io)?;
+    }
+    io.flush().await.map_err(|_| SmtpError::Io)?;
+    match reply(io).await? {
+        code if code == expected => Ok(()),
+        code => Err(SmtpError::Reply(code)),
+    }
+}
+
+/// Invia il messaggio su una connessione già salutata dal server
+///
+/// # Argomenti
+/// * `io` - Connessione con il server (in chiaro o TLS)
+/// * `config` - Configurazione SMTP
+/// * `body` - Testo del messaggio
+async fn session<T: Read + Write>(
+    io: &mut T,
+    config: &SmtpConfig,
+    body: &str,
+) -> Result<(), SmtpError> {
+    command(io, &["EHLO ", CLIENT_NAME], 250).await?;
+    if !config.user.is_empty() {
+        let mut credentials: heapless::Vec<u8, 130> = heapless::Vec::new();
+        let _ = credentials.push(0);
+        let _ = credentials.extend_from_slice(config.user.as_bytes());
+        let _ = credentials.push(0);
+        let _ = credentials.extend_from_slice(config.password.as_bytes());
+        let encoded: heapless::String<176> = base64(&credentials);
+        command(io, &["AUTH PLAIN ", encoded.as_str()], 235).await?;
+    }
+    command(io, &["MAIL FROM:<", config.from.as_str(), ">"], 250).await?;
+    command(io, &["RCPT TO:<", config.to.as_str(), ">"], 250).await?;
+    command(io, &["DATA"], 354).await?;
+
+    let subject = Lang::configured().strings().crash_alert_subject;
+    let headers = [
+        "From: <",
+        config.from.as_str(),
+        ">\r\nTo: <",
+        config.to.as_str(),
+        ">\r\nSubject: ",
+        subject,
+        "\r\n\r\n",
+    ];
+    for part in headers {
+        io.write_all(part.as_bytes())
+            .await
+            .map_err(|_| SmtpError::Io)?;
+    }
+    for line in body.lines() {
+        // Una riga che inizia con '.' va raddoppiata (RFC 5321, 4.5.2)
+        let stuffing = if line.starts_with('.') { "." } else { "" };
+        for part in [stuffing, line, "\r\n"] {
+            io.write_all(part.as_bytes())
+                .await
+                .map_err(|_| SmtpError::Io)?;
+        }
+    }
+    command(io, &["."], 250).await?;
+    command(io, &["QUIT"], 221).await
+}
+
+/// Passa a TLS e invia il messaggio
+///
+/// # Argomenti
+/// * `socket` - Connessione con il server, dopo la risposta a STARTTLS
+/// * `config` - Configurazione SMTP
+/// * `body` - Testo del messaggio
+#[cfg(feature = "smtp-tls")]
+async fn tls_session(
+    socket: TcpSocket<'_>,
+    config: &SmtpConfig,
+    body: &str,
+) -> Result<(), SmtpError> {
+    use embassy_rp::clocks::RoscRng;
+    use embedded_tls::{Aes128GcmSha256, TlsConfig, TlsConnection, TlsContext, UnsecureProvider};
+
+    // Un record TLS può arrivare a 16 KB più l'intestazione
+    let mut read_record = [0; 16_640];
+    let mut write_record = [0; 4096];
+    let tls_config = TlsConfig::new().with_server_name(&config.server);
+    let mut tls: TlsConnection<'_, _, Aes128GcmSha256> =
+        TlsConnection::new(socket, &mut read_record, &mut write_record);
+    tls.open(TlsContext::new(
+        &tls_config,
+        UnsecureProvider::new::<Aes128GcmSha256>(RoscRng),
+    ))
+    .await
+    .map_err(|_| SmtpError::Tls)?;
+    session(&mut tls, config, body).await
+}
+
+/// Senza la feature smtp-tls STARTTLS non è disponibile
+#[cfg(not(feature = "smtp-tls"))]
+async fn tls_session(
+    _socket: TcpSocket<'_>,
+    _config: &SmtpConfig,
+    _body: &str,
+) -> Result<(), SmtpError> {
+    Err(SmtpError::TlsUnavailable)
+}
+
+/// Invia un messaggio
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+/// * `config` - Configurazione SMTP
+/// * `body` - Testo del messaggio
+async fn send(stack: Stack<'static>, config: &SmtpConfig, body: &str) -> Result<(), SmtpError> {
+    let address = fetch::resolve(stack, &config.server)
+        .await
+        .ok_or(SmtpError::Dns)?;
+
+    let mut rx_buffer = [0; 512];
+    let mut tx_buffer = [0; 1024];
+    let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+    socket.set_timeout(Some(IO_TIMEOUT));
+    socket
+        .connect(IpEndpoint::new(address, config.port))
+        .await
+        .map_err(|_| SmtpError::Connect)?;
+
+    match reply(&mut socket).await? {
+        220 => {}
+        code => return Err(SmtpError::Reply(code)),
+    }
+    if config.starttls {
+        command(&mut socket, &["EHLO ", CLIENT_NAME], 250).await?;
+        command(&mut socket, &["STARTTLS"], 220).await?;
+        return tls_session(socket, config, body).await;
+    }
+    let result = session(&mut socket, config, body).await;
+    socket.close();
+    let _ = socket.flush().await;
+    result
+}
+
+#[embassy_executor::task]
+/// Task che invia via email l'ultimo record dello storico dei crash
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+pub async fn smtp_alert_task(stack: Stack<'static>) {
+    let Some(record) = crash_log::latest() else {
+        return;
+    };
+    let body: heapless::String<512> = errors::fit(format_args!("{}", record));
+    for attempt in 1..=ATTEMPTS {
+        crate::tasks::touch("smtp");
+        // La configurazione è riletta a ogni tentativo: modificabile a runtime
+        let Some(config) = get_smtp_config() else {
+            return;
+        };
+        stack.wait_config_up().await;
+        match send(stack, &config, &body).await {
+            Ok(()) => {
+                log::info!("smtp: avviso del crash inviato a {}", config.to);
+                return;
+            }
+            Err(e) => log::warn!(
+                "smtp: invio fallito ({}), tentativo {}/{}",
+                e,
+                attempt,
+                ATTEMPTS
+            ),
+        }
+        Timer::after(RETRY_DELAY).await;
+    }
+    log::error!("smtp: avviso del crash non inviato");
+}
@@ -0,0 +1,163 @@
+//! sntp.rs - Sincronizzazione dell'orologio con un server NTP (SNTPv4, RFC 4330).
+//!
+//! Il task interroga il server `NTP_SERVER` (nome o indirizzo IPv4) ogni
+//! `NTP_INTERVAL_MINUTES` minuti e imposta l'orologio di clock.rs, che
+//! conserva lo scostamento tra l'ora reale e `embassy_time::Instant`: da lì
+//! l'ora arriva ai timestamp dei log, all'intestazione `Date` delle risposte
+//! HTTP (vedi `clock::DateLayer`), a `/api/time` e a `/api/status`.
+//! L'ora ricevuta è corretta di metà del tempo di andata e ritorno. Se la
+//! richiesta fallisce il task riprova dopo [`RETRY_SECS`].
+
+use crate::clock::{self, SyncSource};
+use crate::fetch;
+use crate::utility::ConfigValue;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{IpEndpoint, Stack};
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+
+/// Porta UDP dei server NTP
+const NTP_PORT: u16 = 123;
+
+/// Lunghezza di un pacchetto NTP senza estensioni
+const PACKET_LEN: usize = 48;
+
+/// Secondi tra il 1900-01-01 (epoca NTP) e il 1970-01-01 (epoca Unix)
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// Attesa massima della risposta
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Ritardo prima di un nuovo tentativo dopo un errore
+const RETRY_SECS: u64 = 60;
+
+/// Errori di una sincronizzazione
+#[derive(Debug)]
+enum SntpError {
+    /// Nome del server non risolto
+    Dns,
+    /// Invio della richiesta fallito
+    Send,
+    /// Nessuna risposta entro REPLY_TIMEOUT
+    Timeout,
+    /// Risposta non valida o rifiutata dal server (es. kiss-o'-death)
+    InvalidReply,
+}
+
+/// Estrae l'ora di trasmissione da una risposta del server
+///
+/// # Argomenti
+/// * `reply` - Pacchetto ricevuto
+///
+/// # Ritorna
+/// * Option<u64> - Millisecondi Unix all'invio della risposta, None se il
+///   pacchetto non è una risposta valida di un server sincronizzato
+fn transmit_time_ms(reply: &[u8]) -> Option<u64> {
+    let header = *reply.first()?;
+    let leap = header >> 6;
+    let mode = header & 0x07;
+    let stratum = *reply.get(1)?;
+    // Modo 4 = server; stratum 0 = kiss-o'-death; leap 3 = non sincronizzato
+    if reply.len() < PACKET_LEN || mode != 4 || stratum == 0 || leap == 3 {
+        return None;
+    }
+    let secs = u32::from_be_bytes(reply[40..44].try_into().ok()?) as u64;
+    let fraction = u32::from_be_bytes(reply[44..48].try_into().ok()?) as u64;
+    let unix_secs = secs.checked_sub(NTP_UNIX_OFFSET_SECS)?;
+    Some(unix_secs * 1000 + ((fraction * 1000) >> 32))
+}
+
+/// Interroga il server una volta
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+/// * `socket` - Socket UDP già associato a una porta
+/// * `server` - Nome o indirizzo IPv4 del server
+///
+/// # Ritorna
+/// * Result<u64, SntpError> - Ora corrente in millisecondi Unix
+async fn query(
+    stack: Stack<'static>,
+    socket: &mut UdpSocket<'_>,
+    server: &str,
+) -> Result<u64, SntpError> {
+    let address = fetch::resolve(stack, server).await.ok_or(SntpError::Dns)?;
+    let endpoint = IpEndpoint::new(address, NTP_PORT);
+
+    // LI = 0, versione 4, modo 3 (client)
+    let mut request = [0u8; PACKET_LEN];
+    request[0] = 0x23;
+    let sent = Instant::now();
+    socket
+        .send_to(&request, endpoint)
+        .await
+        .map_err(|_| SntpError::Send)?;
+
+    let mut reply = [0u8; PACKET_LEN];
+    with_timeout(REPLY_TIMEOUT, async {
+        loop {
+            let Ok((len, meta)) = socket.recv_from(&mut reply).await else {
+                continue;
+            };
+            // Scarta le risposte di altri host o a richieste precedenti
+            if meta.endpoint != endpoint {
+                continue;
+            }
+            let round_trip = sent.elapsed().as_millis();
+            return transmit_time_ms(&reply[..len])
+                .map(|ms| ms + round_trip / 2)
+                .ok_or(SntpError::InvalidReply);
+        }
+    })
+    .await
+    .map_err(|_| SntpError::Timeout)?
+}
+
+#[embassy_executor::task]
+/// Task che sincronizza periodicamente l'orologio con il server NTP
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+/// * `server` - Nome o indirizzo IPv4 del server
+/// * `interval_secs` - Secondi tra due sincronizzazioni riuscite
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn sntp_task(stack: Stack<'static>, server: ConfigValue, interval_secs: u64) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 2];
+    let mut rx_buffer = [0; 2 * PACKET_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0; PACKET_LEN];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    // Porta locale scelta dallo stack
+    if let Err(e) = socket.bind(0) {
+        log::error!("SNTP: bind fallito: {:?}", e);
+        return;
+    }
+
+    let server = server.trim();
+    loop {
+        stack.wait_config_up().await;
+        crate::tasks::touch("sntp");
+        let delay = match query(stack, &mut socket, server).await {
+            Ok(unix_ms) => {
+                let drift = clock::now_unix_ms().map(|now| unix_ms as i64 - now as i64);
+                clock::set_unix_time_ms(unix_ms, SyncSource::Sntp);
+                if let Some(drift) = drift {
+                    log::debug!("SNTP: orologio corretto di {} ms", drift);
+                }
+                interval_secs
+            }
+            Err(e) => {
+                log::warn!("SNTP: sincronizzazione con {} fallita: {:?}", server, e);
+                RETRY_SECS
+            }
+        };
+        Timer::after_secs(delay).await;
+    }
+}
@@ -0,0 +1,416 @@
+//! solve.rs - Risoluzione degli schemi sul secondo core.
+//!
+//! Tutti i worker del web server, la console e il Bluetooth girano sullo
+//! stesso executor del core 0: un backtracking lungo su uno schema difficile
+//! lo terrebbe occupato fino alla fine, bloccando anche la rete. [`start`]
+//! avvia quindi sul core 1 un executor con il solo [`solver_task`]: [`solve`]
+//! gli passa lo schema su un canale e attende la soluzione senza bloccare,
+//! mentre il core 0 continua a servire le altre richieste. Il core 1 risolve
+//! uno schema alla volta; gli altri attendono in coda.
+//!
+//! Se il core 1 non è avviato o tutti i posti in coda sono occupati, [`solve`]
+//! ricade sulla risoluzione cooperativa: usa il risolutore a passi del crate
+//! `sudoku` e cede il controllo all'executor ogni [`ROUND_BUDGET`] iterazioni.
+//!
+//! Il budget è per tornata e viene diviso tra i solve in corso: con più
+//! schemi difficili in parallelo ogni solve fa meno iterazioni prima di
+//! cedere, così il tempo tra due occasioni di servire le altre richieste
+//! resta lo stesso di un solve singolo (con un minimo di [`MIN_SLICE`]
+//! iterazioni per non moltiplicare i cambi di contesto).
+//!
+//! Su entrambi i core uno schema ostile terrebbe occupato un worker (e il
+//! core 1, o una parte dell'executor) per un tempo illimitato: dopo
+//! [`MAX_ITERATIONS`] la ricerca si ferma con `SudokuError::Timeout`.
+//!
+//! [`solve_with_progress`] risolve invece sul core 1 con il backtracking di
+//! `Sudoku::solve_fast_with` e riceve ogni [`PROGRESS_PERIOD`] lo stato della
+//! ricerca (celle riempite, backtrack), es. per mostrarla in tempo reale
+//! (vedi solve_ws.rs). Un solo solve alla volta riporta l'avanzamento; gli
+//! altri ricadono su [`solve`].
+//!
+//! Le scritture in flash del core 0 restano possibili: il core 1 avviato con
+//! `spawn_core1` viene sospeso da embassy-rp per la durata della scrittura.
+
+use crate::bus::{self, Event};
+use crate::status_led::Solving;
+use core::ops::ControlFlow;
+use embassy_executor::Executor;
+use embassy_futures::select::{Either, select};
+use embassy_futures::yield_now;
+use embassy_rp::Peri;
+use embassy_rp::multicore::{Stack, spawn_core1};
+use embassy_rp::peripherals::CORE1;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant};
+use portable_atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering};
+use static_cell::{ConstStaticCell, StaticCell};
+use sudoku::{Progress, Solver, Step, Sudoku, SudokuError};
+
+/// Iterazioni per tornata, divise tra i solve in corso (qualche ms sulla pico)
+pub const ROUND_BUDGET: u32 = 2000;
+
+/// Iterazioni minime di un solve prima di cedere il controllo
+pub const MIN_SLICE: u32 = 200;
+
+/// Iterazioni massime di un solve (qualche secondo sulla pico); gli schemi
+/// difficili ma leciti ne richiedono molte meno
+pub const MAX_ITERATIONS: u32 = 1_000_000;
+
+/// Intervallo minimo tra due aggiornamenti di [`solve_with_progress`]
+pub const PROGRESS_PERIOD: Duration = Duration::from_millis(100);
+
+/// Schemi affidati al core 1 nello stesso momento (in risoluzione o in coda)
+const SLOTS: usize = 4;
+
+/// Passi tra due controlli dell'orologio durante un solve con avanzamento
+const PROGRESS_CHECK_STEPS: u32 = 256;
+
+/// Dimensione dello stack del core 1
+const CORE1_STACK_SIZE: usize = 8192;
+
+/// Griglia 9x9 passata tra i core
+type Grid = [[u8; 9]; 9];
+
+/// Schema da risolvere sul core 1
+struct Job {
+    /// Numero della richiesta, per scartare le soluzioni di richieste abbandonate
+    id: u32,
+    /// Posto a cui consegnare la soluzione
+    slot: usize,
+    grid: Grid,
+    /// true per riportare l'avanzamento su PROGRESS
+    progress: bool,
+}
+
+/// Stato della ricerca inviato da [`solve_with_progress`]
+#[derive(Clone, Copy)]
+pub struct ProgressUpdate {
+    /// Numero della richiesta, per scartare gli aggiornamenti di richieste abbandonate
+    id: u32,
+    /// Schema con i numeri provati finora
+    pub grid: Grid,
+    /// Numeri piazzati, compresi quelli poi tolti
+    pub placed: u32,
+    /// Numeri tolti perché portavano a un vicolo cieco
+    pub backtracks: u32,
+}
+
+/// Soluzione di uno schema, con il numero della richiesta
+type Outcome = (u32, Result<Grid, SudokuError>);
+
+static CORE1_STACK: ConstStaticCell<Stack<CORE1_STACK_SIZE>> = ConstStaticCell::new(Stack::new());
+static CORE1_EXECUTOR: StaticCell<Executor> = StaticCell::new();
+
+/// true quando il solver_task è avviato sul core 1
+static CORE1_READY: AtomicBool = AtomicBool::new(false);
+
+/// Schemi in attesa del core 1
+static JOBS: Channel<CriticalSectionRawMutex, Job, SLOTS> = Channel::new();
+
+/// Soluzioni del core 1, una per posto
+static OUTCOMES: [Signal<CriticalSectionRawMutex, Outcome>; SLOTS] =
+    [const { Signal::new() }; SLOTS];
+
+/// Aggiornamenti del solve con avanzamento; se il core 0 resta indietro
+/// quelli nuovi vengono scartati
+static PROGRESS: Channel<CriticalSectionRawMutex, ProgressUpdate, 2> = Channel::new();
+
+/// true mentre un solve riporta l'avanzamento
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+/// Posti occupati (un bit per posto)
+static BUSY: AtomicU8 = AtomicU8::new(0);
+
+/// Numero dell'ultima richiesta al core 1
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Posto occupato da una richiesta al core 1, liberato quando la richiesta
+/// termina o viene abbandonata (es. client disconnesso)
+struct Slot(usize);
+
+impl Slot {
+    fn acquire() -> Option<Self> {
+        BUSY.fetch_update(Ordering::Acquire, Ordering::Relaxed, |busy| {
+            (busy != (1 << SLOTS) - 1).then(|| busy | (1 << busy.trailing_ones()))
+        })
+        .ok()
+        .map(|busy| Slot(busy.trailing_ones() as usize))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        BUSY.fetch_and(!(1 << self.0), Ordering::Release);
+    }
+}
+
+/// Diritto di riportare l'avanzamento, rilasciato quando il solve termina
+struct Reporting;
+
+impl Reporting {
+    fn acquire() -> Option<Self> {
+        (!REPORTING.swap(true, Ordering::Acquire)).then_some(Reporting)
+    }
+}
+
+impl Drop for Reporting {
+    fn drop(&mut self) {
+        REPORTING.store(false, Ordering::Release);
+    }
+}
+
+/// Avvia l'executor del core 1 con il risolutore: da chiamare una sola volta
+/// all'avvio, prima del web server
+///
+/// # Argomenti
+/// * `core1` - Secondo core del microcontrollore
+pub fn start(core1: Peri<'static, CORE1>) {
+    spawn_core1(core1, CORE1_STACK.take(), move || {
+        let executor = CORE1_EXECUTOR.init(Executor::new());
+        executor.run(|spawner| match spawner.spawn(solver_task()) {
+            Ok(()) => CORE1_READY.store(true, Ordering::Release),
+            Err(e) => log::error!("Core 1: avvio del risolutore fallito: {:?}", e),
+        })
+    });
+}
+
+#[embassy_executor::task]
+/// Task del core 1 che risolve gli schemi in coda, uno alla volta
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+async fn solver_task() -> ! {
+    loop {
+        let job = JOBS.receive().await;
+        // Il core 1 esegue solo questo task: la ricerca può bloccarlo
+        let mut sudoku = Sudoku { grid: job.grid };
+        let result = if job.progress {
+            solve_reporting(job.id, &mut sudoku)
+        } else {
+            sudoku.solve_with_budget(MAX_ITERATIONS)
+        };
+        OUTCOMES[job.slot].signal((job.id, result.map(|()| sudoku.grid)));
+    }
+}
+
+/// Risolve lo schema sul core 1 inviando lo stato della ricerca su PROGRESS
+/// al massimo ogni [`PROGRESS_PERIOD`]
+///
+/// # Argomenti
+/// * `id` - Numero della richiesta
+/// * `sudoku` - Schema da risolvere, sostituito dalla soluzione
+///
+/// # Ritorna
+/// * Result<(), SudokuError> - Esito come `solve_fast`, `Timeout` dopo
+///   [`MAX_ITERATIONS`] passi
+fn solve_reporting(id: u32, sudoku: &mut Sudoku) -> Result<(), SudokuError> {
+    let mut update = ProgressUpdate {
+        id,
+        grid: sudoku.grid,
+        placed: 0,
+        backtracks: 0,
+    };
+    let mut last = Instant::now();
+    sudoku.solve_fast_with(|step| {
+        match step {
+            Progress::Placed { row, col, num } => {
+                update.grid[row as usize][col as usize] = num;
+                update.placed += 1;
+            }
+            Progress::Backtracked { row, col } => {
+                update.grid[row as usize][col as usize] = 0;
+                update.backtracks += 1;
+            }
+        }
+        let steps = update.placed + update.backtracks;
+        if steps >= MAX_ITERATIONS {
+            return ControlFlow::Break(());
+        }
+        if steps % PROGRESS_CHECK_STEPS == 0 && last.elapsed() >= PROGRESS_PERIOD {
+            last = Instant::now();
+            let _ = PROGRESS.try_send(update);
+        }
+        ControlFlow::Continue(())
+    })
+}
+
+/// Affida lo schema al core 1, se è avviato e c'è un posto libero
+///
+/// # Argomenti
+/// * `grid` - Schema da risolvere
+/// * `progress` - true per riportare l'avanzamento su PROGRESS
+///
+/// # Ritorna
+/// * Option<(Slot, u32)> - Posto occupato e numero della richiesta
+async fn submit(grid: Grid, progress: bool) -> Option<(Slot, u32)> {
+    if !CORE1_READY.load(Ordering::Acquire) {
+        return None;
+    }
+    let slot = Slot::acquire()?;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    // Una soluzione rimasta da una richiesta abbandonata su questo posto
+    OUTCOMES[slot.0].reset();
+    JOBS.send(Job {
+        id,
+        slot: slot.0,
+        grid,
+        progress,
+    })
+    .await;
+    Some((slot, id))
+}
+
+/// Applica la soluzione del core 1 allo schema
+///
+/// # Argomenti
+/// * `sudoku` - Schema inviato, sostituito dalla soluzione
+/// * `result` - Soluzione ricevuta dal core 1
+///
+/// # Ritorna
+/// * Result<(), SudokuError> - Esito come [`solve`]
+fn finish(sudoku: &mut Sudoku, result: Result<Grid, SudokuError>) -> Result<(), SudokuError> {
+    if let Err(SudokuError::Timeout) = result {
+        log::warn!(
+            "Solve sul core 1 interrotto dopo {} iterazioni",
+            MAX_ITERATIONS
+        );
+    }
+    result.map(|grid| sudoku.grid = grid)
+}
+
+/// Risolve lo schema sul core 1, se c'è un posto libero
+///
+/// # Argomenti
+/// * `sudoku` - Schema da risolvere, sostituito dalla soluzione
+///
+/// # Ritorna
+/// * Option<Result<(), SudokuError>> - Esito come [`solve`], None se il core 1
+///   non è disponibile
+async fn solve_on_core1(sudoku: &mut Sudoku) -> Option<Result<(), SudokuError>> {
+    let (slot, id) = submit(sudoku.grid, false).await?;
+    loop {
+        let (done, result) = OUTCOMES[slot.0].wait().await;
+        if done == id {
+            return Some(finish(sudoku, result));
+        }
+    }
+}
+
+/// Solve cooperativi in corso
+static ACTIVE: AtomicU32 = AtomicU32::new(0);
+
+/// Solve in corso: conta per la divisione del budget finché esiste
+struct Active;
+
+impl Active {
+    fn enter() -> Self {
+        ACTIVE.fetch_add(1, Ordering::Relaxed);
+        Active
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Risolve lo schema sul core 1 senza bloccare l'executor, oppure cedendo
+/// periodicamente il controllo se il core 1 non è disponibile.
+/// Pubblica sul bus `SolveStarted` e, in caso di errore, `SolveFailed`; il
+/// successo lo pubblica il chiamante con `PuzzleSolved`, che ne indica l'origine.
+///
+/// # Argomenti
+/// * `sudoku` - Schema da risolvere, sostituito dalla soluzione
+///
+/// # Ritorna
+/// * Result<(), SudokuError> - Ok se risolto, altrimenti errore come `solve_fast`,
+///   oppure `Timeout` dopo [`MAX_ITERATIONS`]
+pub async fn solve(sudoku: &mut Sudoku) -> Result<(), SudokuError> {
+    let _solving = Solving::enter();
+    bus::publish(Event::SolveStarted);
+    let result = match solve_on_core1(sudoku).await {
+        Some(result) => result,
+        None => solve_cooperative(sudoku).await,
+    };
+    publish_failure(&result);
+    result
+}
+
+/// Pubblica `SolveFailed` sul bus se il solve è fallito
+fn publish_failure(result: &Result<(), SudokuError>) {
+    if let Err(e) = result {
+        bus::publish(Event::SolveFailed {
+            timeout: matches!(e, SudokuError::Timeout),
+        });
+    }
+}
+
+/// Risolve lo schema sul core 1 passando lo stato della ricerca a
+/// `on_progress` mentre il core 0 continua a servire le altre richieste.
+/// Se il core 1 non è disponibile o un altro solve sta già riportando
+/// l'avanzamento, risolve come [`solve`] senza aggiornamenti.
+///
+/// # Argomenti
+/// * `sudoku` - Schema da risolvere, sostituito dalla soluzione
+/// * `on_progress` - Chiamata a ogni aggiornamento; un errore interrompe
+///   l'attesa (il core 1 finisce comunque lo schema)
+///
+/// # Ritorna
+/// * Result<Result<(), SudokuError>, E> - Esito come [`solve`], oppure
+///   l'errore di `on_progress`
+pub async fn solve_with_progress<E>(
+    sudoku: &mut Sudoku,
+    mut on_progress: impl AsyncFnMut(&ProgressUpdate) -> Result<(), E>,
+) -> Result<Result<(), SudokuError>, E> {
+    let Some(_reporting) = Reporting::acquire() else {
+        return Ok(solve(sudoku).await);
+    };
+    let Some((slot, id)) = submit(sudoku.grid, true).await else {
+        return Ok(solve(sudoku).await);
+    };
+    let _solving = Solving::enter();
+    bus::publish(Event::SolveStarted);
+    loop {
+        match select(OUTCOMES[slot.0].wait(), PROGRESS.receive()).await {
+            Either::First((done, result)) if done == id => {
+                let result = finish(sudoku, result);
+                publish_failure(&result);
+                return Ok(result);
+            }
+            // Aggiornamenti di una richiesta abbandonata
+            Either::First(_) => {}
+            Either::Second(update) if update.id == id => on_progress(&update).await?,
+            Either::Second(_) => {}
+        }
+    }
+}
+
+/// Risolve lo schema sul core 0 cedendo periodicamente il controllo all'executor
+///
+/// # Argomenti
+/// * `sudoku` - Schema da risolvere, sostituito dalla soluzione
+///
+/// # Ritorna
+/// * Result<(), SudokuError> - Esito come [`solve`]
+async fn solve_cooperative(sudoku: &mut Sudoku) -> Result<(), SudokuError> {
+    let mut solver = Solver::new(sudoku)?;
+    let _active = Active::enter();
+    loop {
+        let slice = (ROUND_BUDGET / ACTIVE.load(Ordering::Relaxed).max(1)).max(MIN_SLICE);
+        match solver.step(slice) {
+            Step::Pending if solver.iterations() >= MAX_ITERATIONS => {
+                log::warn!("Solve interrotto dopo {} iterazioni", solver.iterations());
+                return Err(SudokuError::Timeout);
+            }
+            Step::Pending => yield_now().await,
+            Step::Solved => {
+                sudoku.grid = *solver.grid();
+                return Ok(());
+            }
+            Step::NoSolution => return Err(SudokuError::NoSolution),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! solve_ws.rs - Risoluzione interattiva degli schemi via WebSocket.
+//!
+//! Su `GET /api/solve/ws` il client apre un WebSocket e invia uno schema per
+//! messaggio di testo, in uno dei formati del comando `solve` (vedi
+//! `commands::parse_schema`). Mentre il core 1 risolve (vedi
+//! `solve::solve_with_progress`) la pico risponde con messaggi JSON:
+//! * `{"type":"progress","placed":..,"backtracks":..,"grid":"..."}` - stato
+//!   della ricerca, al massimo ogni `solve::PROGRESS_PERIOD`;
+//! * `{"type":"solved","grid":"...","elapsed_us":..}` - soluzione;
+//! * `{"type":"error","error":"..."}` - schema non valido o senza soluzione.
+//!
+//! Le griglie sono 81 cifre riga per riga, con 0 per le celle vuote. Sugli
+//! schemi facili la soluzione arriva prima di qualunque aggiornamento.
+//!
+//! Esempio (con websocat):
+//! ```text
+//! echo '53..7....6..195....98....6.8...6...34..8.3..17...2...6.6....28....419..5....8..79' \
+//!     | websocat ws://<ip>/api/solve/ws
+//! ```
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::bus::{self, Event, SolveSource};
+use crate::commands;
+use crate::solve::{self, ProgressUpdate};
+use crate::timing;
+use core::fmt::Write;
+use picoserve::response::WebSocketUpgrade;
+use picoserve::response::ws::{self, Message, ReadMessageError, SocketRx, SocketTx};
+use picoserve::routing::{PathRouter, get};
+
+/// Dimensione massima di un messaggio ricevuto (lo schema a righe è il più lungo)
+const MESSAGE_CAPACITY: usize = 256;
+
+/// Messaggio JSON inviato al client
+type Reply = heapless::String<160>;
+
+/// Scrive la griglia come 81 cifre riga per riga
+///
+/// # Argomenti
+/// * `reply` - Messaggio in costruzione
+/// * `grid` - Griglia da scrivere
+fn write_grid(reply: &mut Reply, grid: &[[u8; 9]; 9]) {
+    for &num in grid.iter().flatten() {
+        let _ = reply.push((b'0' + num) as char);
+    }
+}
+
+/// Costruisce il messaggio con lo stato della ricerca
+fn progress_reply(update: &ProgressUpdate) -> Reply {
+    let mut reply = Reply::new();
+    let _ = write!(
+        reply,
+        "{{\"type\":\"progress\",\"placed\":{},\"backtracks\":{},\"grid\":\"",
+        update.placed, update.backtracks
+    );
+    write_grid(&mut reply, &update.grid);
+    let _ = reply.push_str("\"}");
+    reply
+}
+
+/// Costruisce il messaggio di errore
+fn error_reply(error: sudoku::SudokuError) -> Reply {
+    crate::errors::fit(format_args!(
+        "{{\"type\":\"error\",\"error\":\"{:?}\"}}",
+        error
+    ))
+}
+
+/// Risolve uno schema ricevuto, inviando avanzamento ed esito
+///
+/// # Argomenti
+/// * `schema` - Testo del messaggio ricevuto
+/// * `tx` - Lato di invio del WebSocket
+///
+/// # Ritorna
+/// * Result<(), W::Error> - Errore di scrittura (client disconnesso)
+async fn solve_schema<W: picoserve::io::Write>(
+    schema: &str,
+    tx: &mut SocketTx<W>,
+) -> Result<(), W::Error> {
+    let mut sudoku = match commands::parse_schema(schema.trim()) {
+        Ok(sudoku) => sudoku,
+        Err(e) => return tx.send_text(&error_reply(e)).await,
+    };
+    let solving = solve::solve_with_progress(&mut sudoku, async |update| {
+        tx.send_text(&progress_reply(update)).await
+    });
+    let (result, elapsed) = timing::measure(solving).await;
+    match result? {
+        Ok(()) => {
+            bus::publish(Event::PuzzleSolved {
+                source: SolveSource::Web,
+            });
+            let mut reply = Reply::new();
+            let _ = reply.push_str("{\"type\":\"solved\",\"grid\":\"");
+            write_grid(&mut reply, &sudoku.grid);
+            let _ = write!(reply, "\",\"elapsed_us\":{}}}", elapsed.as_micros());
+            tx.send_text(&reply).await
+        }
+        Err(e) => tx.send_text(&error_reply(e)).await,
+    }
+}
+
+/// Sessione WebSocket di risoluzione interattiva
+pub struct SolveSocket;
+
+impl ws::WebSocketCallback for SolveSocket {
+    /// Risolve gli schemi ricevuti finché il client resta connesso
+    ///
+    /// # Argomenti
+    /// * `rx` - Lato di ricezione del WebSocket
+    /// * `tx` - Lato di invio del WebSocket
+    ///
+    /// # Ritorna
+    /// * Result<(), W::Error> - Errore di lettura o scrittura
+    async fn run<R: picoserve::io::Read, W: picoserve::io::Write<Error = R::Error>>(
+        self,
+        mut rx: SocketRx<R>,
+        mut tx: SocketTx<W>,
+    ) -> Result<(), W::Error> {
+        let mut buffer = [0; MESSAGE_CAPACITY];
+        let close_reason = loop {
+            match rx
+                .next_message(&mut buffer, core::future::pending())
+                .await?
+                .ignore_never_b()
+            {
+                Ok(Message::Text(schema)) => solve_schema(schema, &mut tx).await?,
+                Ok(Message::Binary(_)) => break Some((1003, "solo messaggi di testo")),
+                Ok(Message::Ping(data)) => tx.send_pong(data).await?,
+                Ok(Message::Pong(_)) => {}
+                Ok(Message::Close(_)) => break None,
+                Err(ReadMessageError::Io(e)) => return Err(e),
+                Err(ReadMessageError::TextIsNotUtf8) => break Some((1007, "testo non UTF-8")),
+                Err(e) => {
+                    log::warn!("WebSocket di risoluzione: {:?}", e);
+                    break Some((1002, "errore di protocollo"));
+                }
+            }
+        };
+        tx.close(close_reason).await
+    }
+}
+
+/// Rotte della risoluzione interattiva: `/api/solve/ws`
+pub struct SolveSocketRoutes;
+
+impl Routes for SolveSocketRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router.route(
+            "/api/solve/ws",
+            get(|upgrade: WebSocketUpgrade| async move { upgrade.on_upgrade(SolveSocket) }),
+        )
+    }
+}
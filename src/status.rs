@@ -0,0 +1,132 @@
+//! status.rs - Stato sintetico della pico per gli strumenti di monitoraggio.
+//!
+//! `GET /api/status` riassume in un solo JSON quello che un monitor
+//! interroga periodicamente: versione del firmware (la stessa di
+//! `rp_cargo_version` nei metadati per picotool), scheda (vedi board.rs),
+//! uptime, ora corrente (se l'orologio è sincronizzato, vedi sntp.rs),
+//! configurazione IP, potenza del segnale WiFi letta dal CYW43 e messaggio
+//! del panic conservato da panic_persist, se l'avvio corrente ne segue uno.
+//! I dettagli restano nelle rotte di metrics.rs.
+
+use crate::SharedControl;
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::board;
+use crate::clock;
+use crate::resources;
+use crate::wifi;
+use core::cell::Cell;
+use critical_section::Mutex as CsMutex;
+use embassy_time::{Duration, Instant, with_timeout};
+use picoserve::extract::State;
+use picoserve::response::Json;
+use picoserve::routing::{PathRouter, get};
+
+/// Attesa massima del controller, occupato ad esempio da una scansione
+const RSSI_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Messaggio del panic che ha concluso l'esecuzione precedente
+static STORED_PANIC: CsMutex<Cell<Option<&'static str>>> = CsMutex::new(Cell::new(None));
+
+/// Registra il messaggio letto da panic_persist all'avvio
+///
+/// # Argomenti
+/// * `message` - Messaggio del panic, None se l'avvio non segue un panic
+pub fn set_stored_panic(message: Option<&'static str>) {
+    critical_section::with(|cs| STORED_PANIC.borrow(cs).set(message));
+}
+
+/// Configurazione IP corrente
+#[derive(serde::Serialize)]
+pub struct IpStatus {
+    /// Indirizzo con il prefisso, es. `192.168.1.115/24`
+    address: heapless::String<20>,
+    gateway: Option<heapless::String<16>>,
+    /// Modo di assegnazione dell'indirizzo (vedi wifi.rs)
+    addressing: &'static str,
+}
+
+/// Stato restituito da /api/status
+#[derive(serde::Serialize)]
+pub struct StatusReport {
+    version: &'static str,
+    /// Scheda per cui è compilato il firmware (vedi board.rs)
+    board: &'static str,
+    uptime_secs: u64,
+    /// Ora corrente (ISO 8601 UTC), None se l'orologio non è sincronizzato
+    time: Option<heapless::String<32>>,
+    /// Stato della connessione (vedi wifi.rs)
+    wifi: &'static str,
+    /// None se la rete non è configurata
+    ip: Option<IpStatus>,
+    /// Potenza del segnale in dBm, None senza link o con il controller occupato
+    rssi_dbm: Option<i32>,
+    /// Messaggio del panic conservato da panic_persist
+    stored_panic: Option<&'static str>,
+}
+
+/// Legge la configurazione IP dallo stack di rete
+fn ip_status() -> Option<IpStatus> {
+    let config = resources::get::<embassy_net::Stack<'static>>()?.config_v4()?;
+    Some(IpStatus {
+        address: crate::errors::fit(format_args!("{}", config.address)),
+        gateway: config
+            .gateway
+            .map(|gateway| crate::errors::fit(format_args!("{}", gateway))),
+        addressing: wifi::addressing(),
+    })
+}
+
+/// Legge la potenza del segnale della rete a cui la pico è associata
+///
+/// # Argomenti
+/// * `control` - Controller condiviso del CYW43
+///
+/// # Ritorna
+/// * Option<i32> - RSSI in dBm, None senza link o se il controller non si libera in tempo
+async fn rssi(control: SharedControl) -> Option<i32> {
+    let link_up = resources::get::<embassy_net::Stack<'static>>().is_some_and(|s| s.is_link_up());
+    if !link_up {
+        return None;
+    }
+    with_timeout(RSSI_TIMEOUT, async {
+        control.0.lock().await.get_rssi().await
+    })
+    .await
+    .ok()
+}
+
+/// Raccoglie lo stato della pico
+///
+/// # Argomenti
+/// * `control` - Controller condiviso del CYW43
+///
+/// # Ritorna
+/// * StatusReport - Stato corrente
+pub async fn report(control: SharedControl) -> StatusReport {
+    StatusReport {
+        version: env!("CARGO_PKG_VERSION"),
+        board: board::NAME,
+        uptime_secs: Instant::now().as_secs(),
+        time: clock::is_synced()
+            .then(|| crate::errors::fit(format_args!("{}", clock::Timestamp::now()))),
+        wifi: wifi::state().as_str(),
+        ip: ip_status(),
+        rssi_dbm: rssi(control).await,
+        stored_panic: critical_section::with(|cs| STORED_PANIC.borrow(cs).get()),
+    }
+}
+
+/// Rotta dello stato: `/api/status`
+pub struct StatusRoutes;
+
+impl Routes for StatusRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router.route(
+            "/api/status",
+            get(|State(control): State<SharedControl>| async move { Json(report(control).await) }),
+        )
+    }
+}
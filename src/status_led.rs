@@ -0,0 +1,118 @@
+//! status_led.rs - Stato del sistema mostrato dal LED del CYW43.
+//!
+//! [`system_state`] riassume in un [`SystemState`] i guasti attivi
+//! (segnalati da main.rs e dagli altri moduli con fault.rs), lo stato della
+//! connessione (aggiornato dal supervisore del WiFi, vedi wifi.rs) e le
+//! risoluzioni in corso (aggiornate da solve.rs con [`Solving`]). Il blink
+//! task di main.rs ripete il pattern dello stato corrente, una sequenza di
+//! passi acceso/spento, e ricomincia appena lo stato cambia:
+//! * connessione in corso - lampeggio veloce
+//! * rete attiva - acceso fisso
+//! * connessione degradata o access point di configurazione - lampeggio lento
+//! * risoluzione in corso - doppio lampeggio breve
+//! * guasto - codice del guasto (vedi [`fault::blink_code`])
+
+use crate::fault::{self, Fault};
+use crate::wifi::{self, WifiState};
+use embassy_time::Duration;
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Semiperiodo del lampeggio veloce (connessione in corso)
+const FAST_BLINK: Duration = Duration::from_millis(100);
+
+/// Durata di un lampo del doppio lampeggio (risoluzione in corso)
+const FLASH: Duration = Duration::from_millis(60);
+
+/// Sequenza di passi: stato del LED e durata
+pub type Pattern = &'static [(bool, Duration)];
+
+/// Lampeggio veloce
+const CONNECTING: Pattern = &[(true, FAST_BLINK), (false, FAST_BLINK)];
+
+/// Acceso fisso
+const CONNECTED: Pattern = &[(true, fault::SLOW_BLINK)];
+
+/// Lampeggio lento
+const DEGRADED: Pattern = &[(true, fault::SLOW_BLINK), (false, fault::SLOW_BLINK)];
+
+/// Doppio lampeggio breve seguito da una pausa
+const SOLVING: Pattern = &[
+    (true, FLASH),
+    (false, FLASH),
+    (true, FLASH),
+    (false, Duration::from_millis(320)),
+];
+
+/// Risoluzioni in corso
+static SOLVES: AtomicU32 = AtomicU32::new(0);
+
+/// Stato del sistema mostrato dal LED, in ordine di priorità
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SystemState {
+    /// Guasto attivo con priorità più alta
+    Error(Fault),
+    /// Almeno una risoluzione in corso
+    Solving,
+    /// Scansione, join, attesa del DHCP o riconnessione
+    Connecting,
+    /// Rete attiva
+    Connected,
+    /// Connessione degradata o access point di configurazione
+    Degraded,
+}
+
+impl SystemState {
+    /// Pattern del LED per lo stato
+    ///
+    /// # Ritorna
+    /// * Pattern - Passi da ripetere; per un guasto il LED resta spento,
+    ///   perché il codice è mostrato da [`fault::blink_code`]
+    pub fn pattern(self) -> Pattern {
+        match self {
+            SystemState::Error(_) => &[(false, fault::SLOW_BLINK)],
+            SystemState::Solving => SOLVING,
+            SystemState::Connecting => CONNECTING,
+            SystemState::Connected => CONNECTED,
+            SystemState::Degraded => DEGRADED,
+        }
+    }
+}
+
+/// Ritorna lo stato corrente del sistema
+///
+/// # Ritorna
+/// * SystemState - Guasto, risoluzione in corso o stato della connessione
+pub fn system_state() -> SystemState {
+    if let Some(fault) = fault::current() {
+        return SystemState::Error(fault);
+    }
+    if SOLVES.load(Ordering::Relaxed) > 0 {
+        return SystemState::Solving;
+    }
+    match wifi::state() {
+        WifiState::Up => SystemState::Connected,
+        WifiState::Degraded | WifiState::Provisioning => SystemState::Degraded,
+        WifiState::Init
+        | WifiState::Scanning
+        | WifiState::Joining
+        | WifiState::DhcpWait
+        | WifiState::Reconnecting => SystemState::Connecting,
+    }
+}
+
+/// Risoluzione in corso: il LED mostra [`SystemState::Solving`] finché
+/// esiste
+pub struct Solving;
+
+impl Solving {
+    pub fn enter() -> Self {
+        SOLVES.fetch_add(1, Ordering::Relaxed);
+        Solving
+    }
+}
+
+impl Drop for Solving {
+    fn drop(&mut self) {
+        SOLVES.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,197 @@
+//! supervisor.rs - Supervisore dei task di lunga durata.
+//!
+//! Ogni task supervisionato chiama periodicamente [`check_in`]; il task
+//! [`supervisor_task`] controlla ogni secondo che nessun task abbia superato
+//! la propria scadenza. Una scadenza mancata viene loggata con il nome del
+//! task, è visibile su `/api/supervisor` (e sulla dashboard) e, se
+//! `WATCHDOG_ENABLED=true`, smette di alimentare il watchdog hardware, che
+//! riavvia la pico.
+//!
+//! I runner `cyw43` e `net` e i task web non ritornano mai il controllo:
+//! per loro il check-in avviene da un ciclo in `join` e verifica che
+//! l'executor non sia bloccato. Il blink del LED passa dal chip CYW43 e
+//! verifica anche che il runner `cyw43` risponda (con una scadenza più
+//! lunga, perché condivide il controller con il join WiFi). `net_io` fa
+//! check-in solo quando il gateway risponde a un ping (vedi net_watch.rs):
+//! verifica che lo stack di rete faccia davvero progressi.
+
+use embassy_rp::watchdog::Watchdog;
+use embassy_time::{Duration, Instant, Ticker, Timer};
+use portable_atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Periodo del controllo delle scadenze
+const CHECK_PERIOD_MS: u64 = 1000;
+
+/// Timeout del watchdog hardware (deve superare CHECK_PERIOD_MS)
+const WATCHDOG_TIMEOUT_MS: u64 = 5000;
+
+/// Task supervisionati
+#[derive(Clone, Copy)]
+pub enum Task {
+    Cyw43,
+    Net,
+    Web,
+    Blink,
+    Uart,
+    NetIo,
+}
+
+/// Numero di task supervisionati
+pub const TASK_COUNT: usize = 6;
+
+/// Nome e scadenza (ms) di ogni task, nello stesso ordine di [`Task`]
+pub const TASKS: [(&str, u32); TASK_COUNT] = [
+    ("cyw43", 3000),
+    ("net", 3000),
+    ("web", 3000),
+    // Il join WiFi occupa il controller CYW43 anche per diversi secondi
+    ("blink", 15000),
+    ("uart", 3000),
+    // Un echo al gateway ogni 10 s: diverse risposte perse di fila
+    ("net_io", 60000),
+];
+
+/// Periodo con cui i task in attesa di eventi esterni fanno check-in
+pub const HEARTBEAT_PERIOD: Duration = Duration::from_millis(1000);
+
+/// Ultimo check-in di ogni task (ms dall'avvio, 0 = mai)
+static LAST_CHECK_IN: [AtomicU32; TASK_COUNT] = [const { AtomicU32::new(0) }; TASK_COUNT];
+
+/// Scadenza mancata per ogni task (per loggare una sola volta)
+static MISSED: [AtomicBool; TASK_COUNT] = [const { AtomicBool::new(false) }; TASK_COUNT];
+
+/// Millisecondi dall'avvio, troncati a 32 bit (circa 49 giorni, confronti con wrapping)
+fn now_ms() -> u32 {
+    Instant::now().as_millis() as u32
+}
+
+/// Registra il check-in di un task
+///
+/// # Argomenti
+/// * `task` - Task che fa check-in
+pub fn check_in(task: Task) {
+    // 0 è riservato a "mai", quindi il primo millisecondo vale 1
+    LAST_CHECK_IN[task as usize].store(now_ms().max(1), Ordering::Relaxed);
+}
+
+/// Fa check-in periodicamente: da usare in `join` con i task che non
+/// ritornano mai il controllo (runner cyw43 e net, web server)
+///
+/// # Argomenti
+/// * `task` - Task per cui fare check-in
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn heartbeat(task: Task) -> ! {
+    loop {
+        check_in(task);
+        Timer::after(HEARTBEAT_PERIOD).await;
+    }
+}
+
+/// Stato di un task supervisionato
+#[derive(serde::Serialize)]
+pub struct TaskHealth {
+    name: &'static str,
+    deadline_ms: u32,
+    /// Millisecondi dall'ultimo check-in (None se non ha mai fatto check-in)
+    since_check_in_ms: Option<u32>,
+    healthy: bool,
+}
+
+/// Stato complessivo restituito da /api/supervisor
+#[derive(serde::Serialize)]
+pub struct SupervisorReport {
+    healthy: bool,
+    watchdog: bool,
+    safe_mode: bool,
+    tasks: heapless::Vec<TaskHealth, TASK_COUNT>,
+}
+
+/// Calcola lo stato di un task
+///
+/// # Argomenti
+/// * `index` - Indice del task in TASKS
+/// * `now` - Istante attuale (ms)
+///
+/// # Ritorna
+/// * TaskHealth - Stato del task
+fn task_health(index: usize, now: u32) -> TaskHealth {
+    let (name, deadline_ms) = TASKS[index];
+    let last = LAST_CHECK_IN[index].load(Ordering::Relaxed);
+    let since_check_in_ms = (last != 0).then(|| now.wrapping_sub(last));
+    TaskHealth {
+        name,
+        deadline_ms,
+        since_check_in_ms,
+        healthy: since_check_in_ms.is_some_and(|ms| ms <= deadline_ms),
+    }
+}
+
+/// Ritorna true se nessun task supervisionato ha mancato la scadenza
+pub fn is_healthy() -> bool {
+    let now = now_ms();
+    (0..TASK_COUNT).all(|i| task_health(i, now).healthy)
+}
+
+/// Ritorna lo stato di tutti i task supervisionati
+///
+/// # Ritorna
+/// * SupervisorReport - Stato complessivo
+pub fn report() -> SupervisorReport {
+    let now = now_ms();
+    let tasks: heapless::Vec<TaskHealth, TASK_COUNT> =
+        (0..TASK_COUNT).map(|i| task_health(i, now)).collect();
+    SupervisorReport {
+        healthy: tasks.iter().all(|t| t.healthy),
+        watchdog: crate::utility::get_watchdog_enabled(),
+        safe_mode: crate::safe_mode::is_active(),
+        tasks,
+    }
+}
+
+#[embassy_executor::task]
+/// Task del supervisore: controlla le scadenze e alimenta il watchdog
+/// hardware solo se tutti i task sono in salute
+///
+/// # Argomenti
+/// * `watchdog` - Watchdog hardware, se abilitato da configurazione
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn supervisor_task(mut watchdog: Option<Watchdog>) {
+    if let Some(watchdog) = watchdog.as_mut() {
+        watchdog.start(Duration::from_millis(WATCHDOG_TIMEOUT_MS));
+        log::info!("Supervisore: watchdog hardware attivo");
+    }
+
+    let mut ticker = Ticker::every(Duration::from_millis(CHECK_PERIOD_MS));
+    loop {
+        ticker.next().await;
+        crate::tasks::touch("supervisor");
+        crate::crash_log::update_uptime();
+        let now = now_ms();
+        let mut healthy = true;
+        for (index, missed) in MISSED.iter().enumerate() {
+            let health = task_health(index, now);
+            // Un task mai partito non è ancora in ritardo: il main potrebbe
+            // essere ancora in attesa del WiFi
+            let late = health.since_check_in_ms.is_some() && !health.healthy;
+            if late && !missed.swap(true, Ordering::Relaxed) {
+                log::error!(
+                    "Supervisore: il task '{}' non fa check-in da {} ms (scadenza {} ms)",
+                    health.name,
+                    health.since_check_in_ms.unwrap_or_default(),
+                    health.deadline_ms
+                );
+            } else if !late && missed.swap(false, Ordering::Relaxed) {
+                log::info!("Supervisore: il task '{}' è di nuovo attivo", health.name);
+            }
+            healthy &= !late;
+        }
+
+        if let Some(watchdog) = watchdog.as_mut().filter(|_| healthy) {
+            watchdog.feed();
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! syslog.rs - Invio dei log a un server syslog via UDP (RFC 5424).
+//!
+//! Il task invia un datagramma per record, partendo da quelli ancora
+//! presenti nel ring buffer, al server configurato con `SYSLOG_ADDRESS` e
+//! `SYSLOG_PORT`. Ogni messaggio ha la forma
+//! `<PRI>1 TIMESTAMP HOSTNAME picoweb - TARGET - MESSAGGIO`, con facility
+//! local0 e severità derivata dal livello del record; il timestamp è `-`
+//! finché l'orologio non è sincronizzato (vedi clock.rs). L'UDP non ha
+//! conferme: i datagrammi persi non vengono ritrasmessi.
+
+use crate::clock::{self, Timestamp};
+use crate::log_buffer::{self, LogRecord};
+use crate::utility::ConfigValue;
+use core::fmt::Write;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{IpEndpoint, Ipv4Address, Stack};
+
+/// Facility local0 (RFC 5424, sezione 6.2.1)
+const FACILITY_LOCAL0: u8 = 16;
+
+/// Nome dell'applicazione nel campo APP-NAME
+const APP_NAME: &str = "picoweb";
+
+/// Lunghezza massima del campo MSGID (RFC 5424)
+const MSGID_CAPACITY: usize = 32;
+
+/// Dimensione massima di un datagramma
+const PACKET_CAPACITY: usize = 256;
+
+/// Severità syslog di un livello di log
+///
+/// # Argomenti
+/// * `level` - Livello del record
+///
+/// # Ritorna
+/// * u8 - Severità (3 = error ... 7 = debug)
+fn severity(level: log::Level) -> u8 {
+    match level {
+        log::Level::Error => 3,
+        log::Level::Warn => 4,
+        log::Level::Info => 6,
+        log::Level::Debug | log::Level::Trace => 7,
+    }
+}
+
+/// Formatta un record come messaggio RFC 5424
+///
+/// # Argomenti
+/// * `record` - Record da inviare
+/// * `hostname` - Nome della pico nel campo HOSTNAME
+///
+/// # Ritorna
+/// * heapless::String<PACKET_CAPACITY> - Messaggio, troncato se troppo lungo
+fn render(record: &LogRecord, hostname: &str) -> heapless::String<PACKET_CAPACITY> {
+    let mut packet = heapless::String::new();
+    let pri = FACILITY_LOCAL0 * 8 + severity(record.level);
+    let _ = write!(packet, "<{}>1 ", pri);
+    let _ = if clock::is_synced() {
+        write!(packet, "{} ", Timestamp(record.timestamp_ms))
+    } else {
+        packet.write_str("- ")
+    };
+    let hostname = if hostname.is_empty() { "-" } else { hostname };
+    let target = record
+        .target
+        .get(..MSGID_CAPACITY)
+        .unwrap_or(&record.target);
+    let _ = write!(packet, "{} {} - {} - ", hostname, APP_NAME, target);
+    for c in record.message.chars() {
+        if packet.push(c).is_err() {
+            break;
+        }
+    }
+    packet
+}
+
+#[embassy_executor::task]
+/// Task che invia i log al server syslog configurato
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+/// * `address` - Indirizzo IPv4 del server
+/// * `port` - Porta UDP del server
+/// * `hostname` - Nome della pico nei messaggi
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn syslog_task(
+    stack: Stack<'static>,
+    address: [u8; 4],
+    port: u16,
+    hostname: ConfigValue,
+) {
+    let Some(mut records) = log_buffer::watch() else {
+        log::error!("syslog: troppi consumatori del ring buffer");
+        return;
+    };
+    let endpoint = IpEndpoint::new(
+        Ipv4Address::new(address[0], address[1], address[2], address[3]).into(),
+        port,
+    );
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; 16];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0; 4 * PACKET_CAPACITY];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    // Porta locale scelta dallo stack
+    if let Err(e) = socket.bind(0) {
+        log::error!("syslog: bind fallito: {:?}", e);
+        return;
+    }
+    stack.wait_config_up().await;
+    log::info!("syslog: invio dei log a {}", endpoint);
+
+    let hostname = hostname.trim();
+    let mut next_seq: Option<u32> = None;
+    loop {
+        let (first, count) = log_buffer::window();
+        let end = first.wrapping_add(count as u32);
+        // Se i record da inviare sono già stati scartati si riparte dal più vecchio
+        let mut seq = match next_seq {
+            Some(seq) if seq.wrapping_sub(first) <= count as u32 => seq,
+            _ => first,
+        };
+
+        crate::tasks::touch("syslog");
+        while seq != end {
+            if let Some(record) = log_buffer::get(seq) {
+                let packet = render(&record, hostname);
+                // Un datagramma non inviato (es. rete giù) è perso, come in UDP
+                let _ = socket.send_to(packet.as_bytes(), endpoint).await;
+            }
+            seq = seq.wrapping_add(1);
+        }
+        next_seq = Some(seq);
+
+        records.changed().await;
+    }
+}
@@ -0,0 +1,121 @@
+//! system.rs - Operazioni di sistema: riavvio (anche di manutenzione) e
+//! ingresso in modalità BOOTSEL.
+
+use embassy_time::Timer;
+
+/// Flag della ROM RP2350: riavvio in modalità BOOTSEL (USB mass storage + PICOBOOT)
+#[cfg(feature = "rp235x")]
+const REBOOT_TYPE_BOOTSEL: u32 = 0x0002;
+/// Flag della ROM RP2350: riavvio dopo l'aggiornamento di una partizione,
+/// che viene avviata come prova se l'immagine ha il flag TBYB
+#[cfg(feature = "rp235x")]
+const REBOOT_TYPE_FLASH_UPDATE: u32 = 0x0004;
+/// Flag della ROM RP2350: la chiamata non ritorna in caso di successo
+#[cfg(feature = "rp235x")]
+const REBOOT_NO_RETURN_ON_SUCCESS: u32 = 0x0100;
+
+/// Ritardo prima del riavvio, per permettere lo svuotamento delle seriali
+const REBOOT_DELAY_MS: u64 = 100;
+
+/// Attesa prima del riavvio di manutenzione, per inviare i log a tutte le
+/// destinazioni (USB, UART, TCP)
+const LOG_DRAIN_MS: u64 = 1000;
+
+/// Azione da eseguire alla ricezione di un break sulla UART1
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakAction {
+    None,
+    Reboot,
+    Bootsel,
+}
+
+/// Riavvia la pico dopo un breve ritardo
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn reboot() -> ! {
+    log::warn!("riavvio richiesto");
+    Timer::after_millis(REBOOT_DELAY_MS).await;
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Riavvio di manutenzione (vedi maintenance.rs): salva i contatori
+/// persistenti, lascia il tempo di inviare i log e forza il reset tramite
+/// il watchdog
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn maintenance_reboot() -> ! {
+    log::warn!("riavvio di manutenzione: salvataggio dei contatori");
+    crate::counters::flush();
+    log::warn!("riavvio di manutenzione tra {} ms", LOG_DRAIN_MS);
+    Timer::after_millis(LOG_DRAIN_MS).await;
+    // Il reset forzato (causa "forced") non viene registrato come crash
+    embassy_rp::pac::WATCHDOG
+        .ctrl()
+        .modify(|w| w.set_trigger(true));
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Riavvia la pico in modalità BOOTSEL, pronta per ricevere un nuovo firmware
+/// via USB (picotool o copia del file .uf2), senza premere il pulsante.
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn enter_bootsel() -> ! {
+    log::warn!("ingresso in BOOTSEL richiesto");
+    Timer::after_millis(REBOOT_DELAY_MS).await;
+    // SAFETY: funzione della bootrom RP2350; con NO_RETURN_ON_SUCCESS non ritorna
+    // se il riavvio va a buon fine. p0 = 0 (nessun pin di attività), p1 = 0
+    // (entrambe le interfacce USB abilitate).
+    #[cfg(feature = "rp235x")]
+    unsafe {
+        embassy_rp::rom_data::reboot(REBOOT_TYPE_BOOTSEL | REBOOT_NO_RETURN_ON_SUCCESS, 10, 0, 0);
+    }
+    // SAFETY: funzione della bootrom RP2040, non ritorna. Nessun pin di
+    // attività, entrambe le interfacce USB abilitate.
+    #[cfg(feature = "rp2040")]
+    #[allow(unused_unsafe)]
+    unsafe {
+        embassy_rp::rom_data::reset_to_usb_boot(0, 0);
+    }
+    // La ROM ha rifiutato la richiesta: riavvio normale
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Riavvia la pico nella partizione appena aggiornata (vedi ota.rs), solo RP2350
+///
+/// # Argomenti
+/// * `offset` - Offset in flash dell'inizio della partizione aggiornata
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+#[cfg(feature = "rp235x")]
+pub async fn reboot_flash_update(offset: u32) -> ! {
+    log::warn!("riavvio nella partizione aggiornata");
+    Timer::after_millis(REBOOT_DELAY_MS).await;
+    // SAFETY: funzione della bootrom RP2350; con NO_RETURN_ON_SUCCESS non ritorna
+    // se il riavvio va a buon fine. p0 = indirizzo XIP della partizione aggiornata.
+    unsafe {
+        embassy_rp::rom_data::reboot(
+            REBOOT_TYPE_FLASH_UPDATE | REBOOT_NO_RETURN_ON_SUCCESS,
+            10,
+            0x1000_0000 + offset,
+            0,
+        );
+    }
+    // La ROM ha rifiutato la richiesta: riavvio normale
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Esegue l'azione configurata per un break sulla seriale
+///
+/// # Argomenti
+/// * `action` - Azione configurata
+pub async fn on_break(action: BreakAction) {
+    match action {
+        BreakAction::None => {}
+        BreakAction::Reboot => reboot().await,
+        BreakAction::Bootsel => enter_bootsel().await,
+    }
+}
@@ -0,0 +1,86 @@
+//! tasks.rs - Registro dei task embassy avviati.
+//!
+//! L'executor di embassy non offre un elenco dei task in esecuzione:
+//! ogni task viene quindi avviato con [`spawn`], che ne registra nome e
+//! istante di avvio, e segnala la propria attività con [`touch`] (i task
+//! supervisionati lo fanno a ogni poll, vedi memory.rs). L'elenco è
+//! disponibile su `/api/tasks` e con il comando `ps` della console.
+//! I task di un pool condividono la stessa voce, con il numero di istanze.
+
+use core::cell::RefCell;
+use critical_section::Mutex as CsMutex;
+use embassy_executor::{SpawnToken, Spawner};
+use embassy_time::Instant;
+
+/// Numero massimo di task distinti registrati
+const TASK_SLOTS: usize = 30;
+
+/// Voce del registro dei task
+#[derive(Clone, Copy, serde::Serialize)]
+pub struct TaskEntry {
+    pub name: &'static str,
+    pub instances: u8,
+    /// Istante di avvio (ms dall'avvio)
+    pub spawned_at_ms: u32,
+    /// Ultimo segnale di attività (ms dall'avvio)
+    pub last_activity_ms: u32,
+}
+
+type Registry = CsMutex<RefCell<heapless::Vec<TaskEntry, TASK_SLOTS>>>;
+static REGISTRY: Registry = CsMutex::new(RefCell::new(heapless::Vec::new()));
+
+fn now_ms() -> u32 {
+    Instant::now().as_millis() as u32
+}
+
+/// Avvia un task registrandolo con il nome indicato
+///
+/// # Argomenti
+/// * `spawner` - Spawner dell'executor
+/// * `name` - Nome del task
+/// * `token` - Task da avviare
+pub fn spawn<S>(spawner: &Spawner, name: &'static str, token: SpawnToken<S>) {
+    spawner.must_spawn(token);
+    let now = now_ms();
+    critical_section::with(|cs| {
+        let mut registry = REGISTRY.borrow(cs).borrow_mut();
+        match registry.iter_mut().find(|entry| entry.name == name) {
+            Some(entry) => entry.instances = entry.instances.saturating_add(1),
+            None => {
+                if registry
+                    .push(TaskEntry {
+                        name,
+                        instances: 1,
+                        spawned_at_ms: now,
+                        last_activity_ms: now,
+                    })
+                    .is_err()
+                {
+                    log::warn!("Registro dei task pieno: '{}' non registrato", name);
+                }
+            }
+        }
+    });
+}
+
+/// Segnala l'attività di un task
+///
+/// # Argomenti
+/// * `name` - Nome con cui il task è stato avviato
+pub fn touch(name: &'static str) {
+    let now = now_ms();
+    critical_section::with(|cs| {
+        let mut registry = REGISTRY.borrow(cs).borrow_mut();
+        if let Some(entry) = registry.iter_mut().find(|entry| entry.name == name) {
+            entry.last_activity_ms = now;
+        }
+    });
+}
+
+/// Ritorna una copia del registro dei task
+///
+/// # Ritorna
+/// * heapless::Vec<TaskEntry, TASK_SLOTS> - Task registrati, in ordine di avvio
+pub fn list() -> heapless::Vec<TaskEntry, TASK_SLOTS> {
+    critical_section::with(|cs| REGISTRY.borrow(cs).borrow().clone())
+}
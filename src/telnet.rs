@@ -0,0 +1,262 @@
+//! telnet.rs - Shell di gestione via telnet (porta 23).
+//!
+//! Accetta una connessione alla volta ed esegue ogni riga ricevuta come
+//! comando di gestione (vedi commands.rs), come la console su UART0.
+//! Le sequenze di negoziazione telnet (IAC) vengono ignorate: il client
+//! resta nella modalità a righe di default, con l'eco locale.
+//! La shell è disattivata di default e si abilita con `TELNET_ENABLED=true`.
+//! Permette riavvio e configurazione, quindi una sessione viene aperta solo
+//! se l'accesso è limitato: con `HTTP_AUTH_USER` configurato la shell chiede
+//! all'inizio le stesse credenziali del web server (vedi auth.rs), e con
+//! `ACL_ADMIN_ALLOW` accetta solo le reti di amministrazione (vedi acl.rs).
+//! Senza nessuna delle due la sessione viene rifiutata. Telnet non cifra le
+//! credenziali: va usato solo su reti fidate.
+
+use crate::acl::{self, Access};
+use crate::auth;
+use crate::bus::SolveSource;
+use crate::commands::{self, LINE_CAPACITY};
+use crate::errors;
+use crate::line_framer::{Frame, LineFramer};
+use crate::serial_proto::LineSink;
+use crate::utility::get_acl_admin_allow;
+use embassy_net::tcp::TcpSocket;
+use embassy_time::Duration;
+
+/// Porta TCP della shell
+const TELNET_PORT: u16 = 23;
+
+/// Una sessione inattiva oltre questo tempo viene chiusa
+const IDLE_TIMEOUT_SECS: u64 = 300;
+
+/// Byte che introduce un comando telnet
+const IAC: u8 = 255;
+
+const PROMPT: &str = "picoweb> ";
+
+/// Tentativi di login concessi a una sessione
+const LOGIN_ATTEMPTS: u8 = 3;
+
+/// Lunghezza massima di utente e password inseriti al login
+const CREDENTIAL_CAPACITY: usize = 64;
+
+/// Stato del filtro delle sequenze IAC
+#[derive(Clone, Copy)]
+enum Telnet {
+    /// Dati normali
+    Data,
+    /// Ricevuto IAC: segue il comando
+    Command,
+    /// Ricevuto WILL/WONT/DO/DONT: segue l'opzione
+    Option,
+}
+
+impl Telnet {
+    /// Filtra un byte ricevuto
+    ///
+    /// # Argomenti
+    /// * `byte` - Byte ricevuto
+    ///
+    /// # Ritorna
+    /// * Option<u8> - Il byte se fa parte dei dati, None se va scartato
+    fn filter(&mut self, byte: u8) -> Option<u8> {
+        match (*self, byte) {
+            (Telnet::Data, IAC) => {
+                *self = Telnet::Command;
+                None
+            }
+            // Molti client inviano "\r\0" per un ritorno a capo senza newline
+            (Telnet::Data, 0) => None,
+            (Telnet::Data, _) => Some(byte),
+            (Telnet::Command, 251..=254) => {
+                *self = Telnet::Option;
+                None
+            }
+            (Telnet::Command, _) | (Telnet::Option, _) => {
+                *self = Telnet::Data;
+                None
+            }
+        }
+    }
+}
+
+impl LineSink for TcpSocket<'_> {
+    async fn write_line(&mut self, line: &str) {
+        let result = async {
+            embedded_io_async::Write::write_all(self, line.as_bytes()).await?;
+            embedded_io_async::Write::write_all(self, b"\r\n").await
+        };
+        if let Err(e) = result.await {
+            log::debug!("telnet: errore di trasmissione {:?}", e);
+        }
+    }
+}
+
+#[embassy_executor::task]
+/// Task della shell telnet: una sessione alla volta
+///
+/// # Argomenti
+/// * `stack` - Stack di rete
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn telnet_task(stack: embassy_net::Stack<'static>) {
+    let mut rx_buffer = [0; 256];
+    let mut tx_buffer = [0; 1024];
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(IDLE_TIMEOUT_SECS)));
+
+        if let Err(e) = socket.accept(TELNET_PORT).await {
+            log::warn!("telnet: accept fallita {:?}", e);
+            continue;
+        }
+        // La shell permette riavvio e configurazione: solo dalle reti di
+        // amministrazione (vedi acl.rs)
+        if acl::classify(socket.remote_endpoint().map(|e| e.addr)) != Access::Admin {
+            log::warn!("telnet: {:?} rifiutato (ACL)", socket.remote_endpoint());
+            socket.abort();
+            let _ = socket.flush().await;
+            continue;
+        }
+        // Senza lista di amministrazione né credenziali chiunque sulla rete
+        // avrebbe la shell completa
+        let credentials = auth::credentials_required();
+        if !credentials && get_acl_admin_allow().trim().is_empty() {
+            log::warn!(
+                "telnet: {:?} rifiutato, né ACL_ADMIN_ALLOW né HTTP_AUTH_USER configurati",
+                socket.remote_endpoint()
+            );
+            socket
+                .write_line("errore: configurare ACL_ADMIN_ALLOW o HTTP_AUTH_USER")
+                .await;
+        } else {
+            log::info!("telnet: sessione aperta da {:?}", socket.remote_endpoint());
+            let mut telnet = Telnet::Data;
+            let logged_in = !credentials || login(&mut socket, &mut telnet).await;
+            if logged_in {
+                session(&mut socket, telnet).await;
+            } else {
+                log::warn!(
+                    "telnet: credenziali errate da {:?}",
+                    socket.remote_endpoint()
+                );
+            }
+            log::info!("telnet: sessione chiusa");
+        }
+
+        socket.close();
+        let _ = socket.flush().await;
+        socket.abort();
+    }
+}
+
+/// Legge una riga di credenziali, un byte alla volta
+///
+/// # Argomenti
+/// * `socket` - Socket connesso
+/// * `telnet` - Stato del filtro delle sequenze IAC
+///
+/// # Ritorna
+/// * Option<heapless::Vec<u8, CREDENTIAL_CAPACITY>> - Riga letta (vuota se
+///   troppo lunga), None se il client si è disconnesso
+async fn read_credential(
+    socket: &mut TcpSocket<'_>,
+    telnet: &mut Telnet,
+) -> Option<heapless::Vec<u8, CREDENTIAL_CAPACITY>> {
+    let mut framer = LineFramer::<CREDENTIAL_CAPACITY>::new();
+    loop {
+        let mut byte = [0; 1];
+        match socket.read(&mut byte).await {
+            Ok(0) | Err(_) => return None,
+            Ok(_) => {}
+        }
+        let Some(byte) = telnet.filter(byte[0]) else {
+            continue;
+        };
+        match framer.push(byte) {
+            Some(Frame::Line(line)) => return heapless::Vec::from_slice(line).ok(),
+            Some(Frame::Overflow(_)) => return Some(heapless::Vec::new()),
+            None => {}
+        }
+    }
+}
+
+/// Chiede utente e password, al massimo LOGIN_ATTEMPTS volte, e li verifica
+/// con lo stesso controllo del web server (vedi auth.rs)
+///
+/// # Argomenti
+/// * `socket` - Socket connesso
+/// * `telnet` - Stato del filtro delle sequenze IAC
+///
+/// # Ritorna
+/// * bool - true se le credenziali sono corrette
+async fn login(socket: &mut TcpSocket<'_>, telnet: &mut Telnet) -> bool {
+    for _ in 0..LOGIN_ATTEMPTS {
+        write_raw(socket, "utente: ").await;
+        let Some(user) = read_credential(socket, telnet).await else {
+            return false;
+        };
+        write_raw(socket, "password: ").await;
+        let Some(password) = read_credential(socket, telnet).await else {
+            return false;
+        };
+        if auth::check_credentials(&user, &password) {
+            return true;
+        }
+        socket.write_line("credenziali errate").await;
+    }
+    false
+}
+
+/// Gestisce una sessione finché il client non si disconnette
+///
+/// # Argomenti
+/// * `socket` - Socket connesso
+/// * `telnet` - Stato del filtro delle sequenze IAC, dopo il login
+async fn session(socket: &mut TcpSocket<'_>, mut telnet: Telnet) {
+    let mut framer = LineFramer::<LINE_CAPACITY>::new();
+    socket.write_line("picoweb telnet - digitare 'help'").await;
+    write_prompt(socket).await;
+
+    loop {
+        let mut buf = [0; 64];
+        let n = match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        crate::tasks::touch("telnet");
+
+        for byte in buf[..n].iter().filter_map(|&b| telnet.filter(b)) {
+            match framer.push(byte) {
+                Some(Frame::Line(line)) => {
+                    match core::str::from_utf8(line) {
+                        Ok(text) => commands::handle_line(text, SolveSource::Telnet, socket).await,
+                        Err(_) => socket.write_line("errore: riga non valida").await,
+                    }
+                    write_prompt(socket).await;
+                }
+                Some(Frame::Overflow(len)) => {
+                    let out: heapless::String<48> =
+                        errors::fit(format_args!("errore: riga di {} byte scartata", len));
+                    socket.write_line(&out).await;
+                    write_prompt(socket).await;
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+/// Scrive il prompt senza andare a capo
+async fn write_prompt(socket: &mut TcpSocket<'_>) {
+    write_raw(socket, PROMPT).await;
+}
+
+/// Scrive un testo senza andare a capo
+async fn write_raw(socket: &mut TcpSocket<'_>, text: &str) {
+    if let Err(e) = embedded_io_async::Write::write_all(socket, text.as_bytes()).await {
+        log::debug!("telnet: errore di trasmissione {:?}", e);
+    }
+}
@@ -0,0 +1,112 @@
+//! timing.rs - Servizio di misura dei tempi di esecuzione.
+//!
+//! [`measure`] esegue un'operazione asincrona e ne ritorna il risultato
+//! insieme al tempo impiegato. La misura usa il timer sulla state machine
+//! sm2 del PIO1 (vedi pio.rs, risoluzione 1 µs) se è registrato tra le
+//! risorse e libero; altrimenti (es. misure annidate) ricade sul timer di
+//! embassy. È usabile da qualunque handler o task, così la generazione delle
+//! risposte HTTP non ha effetti collaterali sull'hardware.
+//!
+//! [`measure_solve`] è la variante per i solve mostrati all'utente: ritorna
+//! un [`SolveTiming`] che dice anche quale timer ha fatto la misura. Il
+//! numero di misure fatte con ciascun timer è in `/api/timing`.
+
+use crate::SharedSm2;
+use crate::resources;
+use embassy_time::{Duration, Instant};
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Misure fatte con il timer PIO
+static PIO_MEASUREMENTS: AtomicU32 = AtomicU32::new(0);
+
+/// Misure ricadute sul timer di embassy
+static FALLBACK_MEASUREMENTS: AtomicU32 = AtomicU32::new(0);
+
+/// Tempo impiegato da un solve, mostrato nella pagina del risultato
+#[derive(Clone, Copy)]
+pub struct SolveTiming {
+    /// Microsecondi trascorsi
+    pub micros: u64,
+    /// true se misurato con il timer PIO, false con il timer di embassy
+    pub pio: bool,
+}
+
+/// Misure fatte da ciascun timer, restituite da /api/timing
+#[derive(serde::Serialize)]
+pub struct TimingReport {
+    pio: u32,
+    fallback: u32,
+}
+
+/// Ritorna il numero di misure fatte da ciascun timer
+///
+/// # Ritorna
+/// * TimingReport - Misure con il timer PIO e con il timer di embassy
+pub fn report() -> TimingReport {
+    TimingReport {
+        pio: PIO_MEASUREMENTS.load(Ordering::Relaxed),
+        fallback: FALLBACK_MEASUREMENTS.load(Ordering::Relaxed),
+    }
+}
+
+/// Misura il tempo di esecuzione di un'operazione
+///
+/// # Argomenti
+/// * `op` - Operazione da misurare
+///
+/// # Ritorna
+/// * (T, Duration) - Risultato dell'operazione e tempo impiegato
+pub async fn measure<T>(op: impl Future<Output = T>) -> (T, Duration) {
+    let (result, elapsed, _) = measure_with_source(op).await;
+    (result, elapsed)
+}
+
+/// Misura il tempo di esecuzione di un solve
+///
+/// # Argomenti
+/// * `op` - Solve da misurare
+///
+/// # Ritorna
+/// * (T, SolveTiming) - Risultato del solve e tempo impiegato
+pub async fn measure_solve<T>(op: impl Future<Output = T>) -> (T, SolveTiming) {
+    let (result, elapsed, pio) = measure_with_source(op).await;
+    let timing = SolveTiming {
+        micros: elapsed.as_micros(),
+        pio,
+    };
+    (result, timing)
+}
+
+/// Misura il tempo di esecuzione di un'operazione e conta la misura
+///
+/// # Argomenti
+/// * `op` - Operazione da misurare
+///
+/// # Ritorna
+/// * (T, Duration, bool) - Risultato, tempo impiegato e true se misurato
+///   con il timer PIO
+async fn measure_with_source<T>(op: impl Future<Output = T>) -> (T, Duration, bool) {
+    let start = Instant::now();
+    // Il timer resta occupato per tutta la misura: un'eventuale misura
+    // annidata trova il mutex bloccato e usa il timer di embassy
+    let timer = resources::get::<SharedSm2>().and_then(|sm2| sm2.0.try_lock().ok());
+    let Some(mut timer) = timer else {
+        let result = op.await;
+        FALLBACK_MEASUREMENTS.fetch_add(1, Ordering::Relaxed);
+        return (result, start.elapsed(), false);
+    };
+
+    timer.start();
+    let result = op.await;
+    match timer.stop().await {
+        Some(us) => {
+            PIO_MEASUREMENTS.fetch_add(1, Ordering::Relaxed);
+            (result, Duration::from_micros(us as u64), true)
+        }
+        None => {
+            log::warn!("timer PIO: conteggio non disponibile, uso il timer di embassy");
+            FALLBACK_MEASUREMENTS.fetch_add(1, Ordering::Relaxed);
+            (result, start.elapsed(), false)
+        }
+    }
+}
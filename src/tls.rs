@@ -0,0 +1,447 @@
+//! tls.rs - HTTPS: TLS 1.3 lato server davanti a picoserve.
+//!
+//! Con la feature `https` un worker del web server (`ServerResources::HTTPS`
+//! in configuration.rs) accetta le connessioni sulla porta [`HTTPS_PORT`] e
+//! le passa a picoserve attraverso un [`TlsSocket`], che esegue l'handshake
+//! e cifra e decifra i record con l'API "unbuffered" di rustls: le rotte, i
+//! layer e i limiti (ACL, limiti per client, svuotamento) sono gli stessi
+//! della porta 80.
+//!
+//! Certificato e chiave privata (PEM) sono letti a compile time da build.rs
+//! dai file indicati da `HTTPS_CERT` e `HTTPS_KEY`, nell'ambiente o in
+//! `.env`, come gli altri segreti. I numeri casuali (chiavi effimere,
+//! nonce) vengono dal generatore hardware TRNG dell'RP2350, ceduto dal main
+//! con [`init`].
+//!
+//! La crittografia è quella di `rustls-rustcrypto`, una versione alpha che
+//! non ha avuto una revisione di sicurezza indipendente: la feature `https`
+//! è sperimentale e la versione è fissata in Cargo.toml, così un
+//! aggiornamento va scelto e verificato a mano.
+//!
+//! Una sessione TLS occupa lo heap (feature `heap`, inclusa da `https`): il
+//! buffer di ricezione deve contenere un record intero, fino a 16 KB, più lo
+//! stato della connessione. Per questo il worker HTTPS è uno solo.
+
+use crate::clock;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::ops::Range;
+use critical_section::Mutex as CsMutex;
+use embassy_net::tcp::Error as TcpError;
+use embassy_rp::peripherals::TRNG;
+use embassy_rp::trng::Trng;
+use embassy_time::Duration;
+use picoserve::io::{ErrorType, Read, Socket, Write};
+use rustls::ServerConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
+use rustls::server::UnbufferedServerConnection;
+use rustls::time_provider::TimeProvider;
+use rustls::unbuffered::{AppDataRecord, ConnectionState, EncodeError, UnbufferedStatus};
+
+/// Porta del server HTTPS
+pub const HTTPS_PORT: u16 = 443;
+
+/// Tempo massimo per l'handshake: un client che non lo completa non tiene
+/// occupato il worker
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Buffer dei record ricevuti: un record TLS intero (16 KB di dati più
+/// intestazione e autenticazione)
+const INCOMING_CAPACITY: usize = 16 * 1024 + 512;
+
+/// Buffer dei record da inviare: contiene i messaggi dell'handshake (catena
+/// dei certificati compresa) e un record di dati
+const OUTGOING_CAPACITY: usize = 4096;
+
+/// Dati in chiaro cifrati in un solo record: lasciano nel buffer di invio
+/// lo spazio per intestazione e autenticazione
+const PLAINTEXT_CHUNK: usize = 2048;
+
+/// Certificato del server (catena PEM), copiato da build.rs
+const CERT_PEM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/https_cert.pem"));
+
+/// Chiave privata del server (PEM), copiata da build.rs
+const KEY_PEM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/https_key.pem"));
+
+/// Configurazione TLS, creata da [`init`]
+static CONFIG: CsMutex<RefCell<Option<Arc<ServerConfig>>>> = CsMutex::new(RefCell::new(None));
+
+/// Generatore hardware di numeri casuali, ceduto con [`init`]
+static RNG: CsMutex<RefCell<Option<Trng<'static, TRNG>>>> = CsMutex::new(RefCell::new(None));
+
+/// Errore dell'handshake
+#[derive(Debug)]
+pub enum TlsError {
+    /// [`init`] non è stata chiamata o è fallita
+    NotConfigured,
+    /// Errore del socket TCP
+    Io(TcpError),
+    /// Connessione chiusa dal client durante l'handshake
+    Closed,
+    /// Messaggio dell'handshake più grande del buffer di ricezione o di invio
+    BufferFull,
+    /// Errore del protocollo (certificato rifiutato, cifrario non comune, ...)
+    Tls(rustls::Error),
+}
+
+impl From<rustls::Error> for TlsError {
+    fn from(e: rustls::Error) -> Self {
+        TlsError::Tls(e)
+    }
+}
+
+/// Numeri casuali per rustls-rustcrypto (vedi il crate getrandom), dal TRNG
+///
+/// # Argomenti
+/// * `dest` - Buffer da riempire
+///
+/// # Ritorna
+/// * Result<(), getrandom::Error> - UNSUPPORTED se il TRNG non è stato ceduto
+fn fill_random(dest: &mut [u8]) -> Result<(), getrandom::Error> {
+    critical_section::with(|cs| match RNG.borrow_ref_mut(cs).as_mut() {
+        Some(trng) => {
+            trng.blocking_fill_bytes(dest);
+            Ok(())
+        }
+        None => Err(getrandom::Error::UNSUPPORTED),
+    })
+}
+
+getrandom::register_custom_getrandom!(fill_random);
+
+/// Ora corrente per rustls, dall'orologio sincronizzato (vedi clock.rs)
+#[derive(Debug)]
+struct ClockTime;
+
+impl TimeProvider for ClockTime {
+    fn current_time(&self) -> Option<UnixTime> {
+        let unix_ms = clock::now_unix_ms()?;
+        Some(UnixTime::since_unix_epoch(
+            core::time::Duration::from_millis(unix_ms),
+        ))
+    }
+}
+
+/// Carica certificato e chiave e crea la configurazione TLS: da chiamare
+/// una sola volta, prima di avviare il worker HTTPS
+///
+/// # Argomenti
+/// * `trng` - Generatore hardware dei numeri casuali dell'handshake
+///
+/// # Ritorna
+/// * Result<(), rustls::Error> - Errore se il certificato o la chiave non
+///   sono validi
+pub fn init(trng: Trng<'static, TRNG>) -> Result<(), rustls::Error> {
+    critical_section::with(|cs| RNG.borrow_ref_mut(cs).replace(trng));
+    let certs = CertificateDer::pem_slice_iter(CERT_PEM)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| rustls::Error::General("certificato PEM non valido".into()))?;
+    let key = PrivateKeyDer::from_pem_slice(KEY_PEM)
+        .map_err(|_| rustls::Error::General("chiave PEM non valida".into()))?;
+    let mut config = ServerConfig::builder_with_details(
+        Arc::new(rustls_rustcrypto::provider()),
+        Arc::new(ClockTime),
+    )
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)?;
+    // Niente ticket di sessione: dopo l'handshake il server non invia
+    // messaggi propri, quindi lettura e scrittura restano indipendenti
+    config.send_tls13_tickets = 0;
+    config.max_fragment_size = Some(PLAINTEXT_CHUNK);
+    let config = Arc::new(config);
+    critical_section::with(|cs| CONFIG.borrow_ref_mut(cs).replace(config));
+    Ok(())
+}
+
+/// Record ricevuti e dati in chiaro non ancora letti
+struct Incoming {
+    buf: Box<[u8]>,
+    /// Byte ricevuti in `buf`
+    used: usize,
+    /// Dati in chiaro del record decifrato, non ancora letti da picoserve
+    plain: Range<usize>,
+    /// Byte da scartare dall'inizio di `buf` quando `plain` è vuoto
+    discard: usize,
+}
+
+impl Incoming {
+    /// Scarta i primi `n` byte del buffer
+    fn consume(&mut self, n: usize) {
+        self.buf.copy_within(n..self.used, 0);
+        self.used -= n;
+    }
+}
+
+/// Socket TLS sopra un socket TCP (di solito un `CountingSocket`, vedi
+/// http_stats.rs, che conta così i byte cifrati)
+pub struct TlsSocket<S> {
+    inner: S,
+    conn: RefCell<UnbufferedServerConnection>,
+    incoming: Incoming,
+    outgoing: Box<[u8]>,
+}
+
+impl<S: Socket<Error = TcpError>> TlsSocket<S> {
+    /// Esegue l'handshake TLS su una connessione accettata
+    ///
+    /// # Argomenti
+    /// * `inner` - Socket della connessione
+    ///
+    /// # Ritorna
+    /// * Result<Self, TlsError> - Socket pronto per picoserve, oppure errore
+    ///   dell'handshake
+    pub async fn accept(mut inner: S) -> Result<Self, TlsError> {
+        let config = critical_section::with(|cs| CONFIG.borrow_ref(cs).clone())
+            .ok_or(TlsError::NotConfigured)?;
+        let mut conn = UnbufferedServerConnection::new(config)?;
+        let mut incoming = Incoming {
+            buf: vec![0; INCOMING_CAPACITY].into_boxed_slice(),
+            used: 0,
+            plain: 0..0,
+            discard: 0,
+        };
+        let mut outgoing = vec![0; OUTGOING_CAPACITY].into_boxed_slice();
+        let mut pending = 0;
+        let (mut reader, mut writer) = inner.split();
+        loop {
+            let UnbufferedStatus { discard, state } =
+                conn.process_tls_records(&mut incoming.buf[..incoming.used]);
+            let (mut read_more, mut done) = (false, false);
+            match state? {
+                ConnectionState::EncodeTlsData(mut state) => {
+                    match state.encode(&mut outgoing[pending..]) {
+                        Ok(n) => pending += n,
+                        Err(EncodeError::InsufficientSize(_)) => return Err(TlsError::BufferFull),
+                        Err(EncodeError::AlreadyEncoded) => {}
+                    }
+                }
+                ConnectionState::TransmitTlsData(state) => {
+                    writer
+                        .write_all(&outgoing[..pending])
+                        .await
+                        .map_err(TlsError::Io)?;
+                    writer.flush().await.map_err(TlsError::Io)?;
+                    pending = 0;
+                    state.done();
+                }
+                ConnectionState::BlockedHandshake => read_more = true,
+                // Handshake concluso; i dati già ricevuti restano nel buffer
+                ConnectionState::WriteTraffic(_) | ConnectionState::ReadTraffic(_) => done = true,
+                _ => return Err(TlsError::Closed),
+            }
+            incoming.consume(discard);
+            if done {
+                break;
+            }
+            if read_more {
+                let free = &mut incoming.buf[incoming.used..];
+                if free.is_empty() {
+                    return Err(TlsError::BufferFull);
+                }
+                match reader.read(free).await.map_err(TlsError::Io)? {
+                    0 => return Err(TlsError::Closed),
+                    n => incoming.used += n,
+                }
+            }
+        }
+        drop((reader, writer));
+        Ok(Self {
+            inner,
+            conn: RefCell::new(conn),
+            incoming,
+            outgoing,
+        })
+    }
+}
+
+/// Registra un errore TLS e lo riporta a picoserve come connessione chiusa
+fn tls_failed(e: rustls::Error) -> TcpError {
+    log::warn!("TLS: {:?}", e);
+    TcpError::ConnectionReset
+}
+
+/// Metà in lettura: decifra un record alla volta
+pub struct TlsReader<'a, R> {
+    io: R,
+    conn: &'a RefCell<UnbufferedServerConnection>,
+    incoming: &'a mut Incoming,
+}
+
+/// Esito dell'elaborazione dei record ricevuti
+enum Received {
+    /// Record decifrato, dati in `Incoming::plain`
+    Data,
+    /// Servono altri byte dal socket
+    More,
+    /// Il client ha chiuso la sessione
+    Closed,
+}
+
+impl<R> ErrorType for TlsReader<'_, R> {
+    type Error = TcpError;
+}
+
+impl<R: Read<Error = TcpError>> TlsReader<'_, R> {
+    /// Elabora i record ricevuti fino al primo con dati in chiaro
+    fn process(&mut self) -> Result<Received, TcpError> {
+        let incoming = &mut *self.incoming;
+        let mut conn = self.conn.borrow_mut();
+        let base = incoming.buf.as_ptr() as usize;
+        let UnbufferedStatus { discard, state } =
+            conn.process_tls_records(&mut incoming.buf[..incoming.used]);
+        let (received, discard) = match state.map_err(tls_failed)? {
+            ConnectionState::ReadTraffic(mut traffic) => match traffic.next_record() {
+                Some(record) => {
+                    let AppDataRecord {
+                        discard: record_discard,
+                        payload,
+                    } = record.map_err(tls_failed)?;
+                    // Il record è decifrato sul posto, dentro il buffer
+                    let start = payload.as_ptr() as usize - base;
+                    incoming.plain = start..start + payload.len();
+                    (Received::Data, discard + record_discard)
+                }
+                None => (Received::More, discard),
+            },
+            ConnectionState::WriteTraffic(_) | ConnectionState::BlockedHandshake => {
+                (Received::More, discard)
+            }
+            ConnectionState::PeerClosed | ConnectionState::Closed => (Received::Closed, discard),
+            // Messaggi del server dopo l'handshake (es. KeyUpdate) non previsti
+            _ => return Err(tls_failed(rustls::Error::General("stato inatteso".into()))),
+        };
+        drop(conn);
+        match received {
+            // I dati in chiaro stanno nella parte da scartare: si scarta dopo averli letti
+            Received::Data => incoming.discard = discard,
+            _ => incoming.consume(discard),
+        }
+        Ok(received)
+    }
+}
+
+impl<R: Read<Error = TcpError>> Read for TlsReader<'_, R> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        loop {
+            let incoming = &mut *self.incoming;
+            if !incoming.plain.is_empty() {
+                let n = buf.len().min(incoming.plain.len());
+                let start = incoming.plain.start;
+                buf[..n].copy_from_slice(&incoming.buf[start..start + n]);
+                incoming.plain.start += n;
+                if incoming.plain.is_empty() {
+                    let discard = core::mem::take(&mut incoming.discard);
+                    incoming.consume(discard);
+                }
+                return Ok(n);
+            }
+            match self.process()? {
+                Received::Data => continue,
+                Received::Closed => return Ok(0),
+                Received::More => {}
+            }
+            let incoming = &mut *self.incoming;
+            let free = &mut incoming.buf[incoming.used..];
+            if free.is_empty() {
+                return Err(tls_failed(rustls::Error::General(
+                    "record troppo grande".into(),
+                )));
+            }
+            match self.io.read(free).await? {
+                0 => return Ok(0),
+                n => incoming.used += n,
+            }
+        }
+    }
+}
+
+/// Metà in scrittura: cifra i dati in record da al più [`PLAINTEXT_CHUNK`] byte
+pub struct TlsWriter<'a, W> {
+    io: W,
+    conn: &'a RefCell<UnbufferedServerConnection>,
+    outgoing: &'a mut [u8],
+}
+
+impl<W> ErrorType for TlsWriter<'_, W> {
+    type Error = TcpError;
+}
+
+impl<W: Write<Error = TcpError>> Write for TlsWriter<'_, W> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let chunk = &buf[..buf.len().min(PLAINTEXT_CHUNK)];
+        let encrypted = {
+            let mut conn = self.conn.borrow_mut();
+            // Nessun record da elaborare: i record ricevuti sono di TlsReader
+            let status = conn.process_tls_records(&mut []);
+            match status.state.map_err(tls_failed)? {
+                ConnectionState::WriteTraffic(mut traffic) => traffic
+                    .encrypt(chunk, self.outgoing)
+                    .map_err(|e| tls_failed(rustls::Error::General(alloc::format!("{e:?}"))))?,
+                _ => return Err(TcpError::ConnectionReset),
+            }
+        };
+        self.io.write_all(&self.outgoing[..encrypted]).await?;
+        Ok(chunk.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.io.flush().await
+    }
+}
+
+impl<S: Socket<Error = TcpError>> Socket for TlsSocket<S> {
+    type Error = TcpError;
+    type ReadHalf<'a>
+        = TlsReader<'a, S::ReadHalf<'a>>
+    where
+        Self: 'a;
+    type WriteHalf<'a>
+        = TlsWriter<'a, S::WriteHalf<'a>>
+    where
+        Self: 'a;
+
+    fn split(&mut self) -> (Self::ReadHalf<'_>, Self::WriteHalf<'_>) {
+        let (reader, writer) = self.inner.split();
+        (
+            TlsReader {
+                io: reader,
+                conn: &self.conn,
+                incoming: &mut self.incoming,
+            },
+            TlsWriter {
+                io: writer,
+                conn: &self.conn,
+                outgoing: &mut self.outgoing,
+            },
+        )
+    }
+
+    async fn shutdown<T: picoserve::Timer>(
+        mut self,
+        timeouts: &picoserve::Timeouts<T::Duration>,
+        timer: &mut T,
+    ) -> Result<(), picoserve::Error<Self::Error>> {
+        // close_notify, poi chiusura del socket TCP; un errore nell'invio
+        // dell'avviso non impedisce la chiusura
+        let notify = {
+            let mut conn = self.conn.borrow_mut();
+            match conn.process_tls_records(&mut []).state {
+                Ok(ConnectionState::WriteTraffic(mut traffic)) => {
+                    traffic.queue_close_notify(&mut self.outgoing).ok()
+                }
+                _ => None,
+            }
+        };
+        if let Some(n) = notify {
+            let (_, mut writer) = self.inner.split();
+            let _ = writer.write_all(&self.outgoing[..n]).await;
+            let _ = writer.flush().await;
+        }
+        Socket::shutdown(self.inner, timeouts, timer).await
+    }
+}
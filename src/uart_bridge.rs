@@ -0,0 +1,261 @@
+//! uart_bridge.rs - Terminale seriale via web sulla UART1.
+//!
+//! Il task `reader` (main.rs) copia qui ogni byte ricevuto, oltre a
+//! interpretare le righe come comandi: gli ultimi [`BRIDGE_CAPACITY`] byte
+//! restano in un ring buffer numerato, così ogni client sa da dove
+//! riprendere e si accorge dei byte persi.
+//! * `GET /uart` - pagina del terminale;
+//! * `GET /api/uart/events` - byte ricevuti via Server-Sent Events (evento
+//!   `rx` con `seq` e `text`, evento `gap` se il client è rimasto indietro);
+//! * `POST /uart` - form con `data`, inviato così com'è sulla linea TX.
+//!
+//! Il terminale passa da [`UartBridge`], nello stato dell'applicazione. Sono
+//! rotte di amministrazione (vedi acl.rs).
+//!
+//! Esempio:
+//! ```text
+//! curl -d 'data=AT%0D%0A' http://<ip>/uart
+//! ```
+
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::assets::AcceptGzip;
+use crate::errors::{self, ErrorKind};
+use crate::i18n::Lang;
+use crate::resources::{self, SharedUartTx};
+use core::cell::RefCell;
+use core::fmt::Write;
+use critical_section::Mutex as CsMutex;
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::watch::Watch;
+use embassy_time::Timer;
+use heapless::Deque;
+use picoserve::extract::{Form, State};
+use picoserve::response::sse::{EventSource, EventWriter};
+use picoserve::response::{EventStream, StatusCode};
+use picoserve::routing::{PathRouter, get};
+
+/// Byte ricevuti conservati per i client
+const BRIDGE_CAPACITY: usize = 1024;
+
+/// Numero massimo di terminali contemporanei
+const BRIDGE_CLIENTS: usize = 2;
+
+/// Byte inviati al massimo in un evento `rx`
+const EVENT_BYTES: usize = 32;
+
+/// Intervallo dei keep-alive SSE in assenza di dati
+const KEEPALIVE_SECS: u64 = 15;
+
+/// Byte ricevuti e numero di sequenza del prossimo byte
+struct BridgeRing {
+    bytes: Deque<u8, BRIDGE_CAPACITY>,
+    next_seq: u32,
+}
+
+static RING: CsMutex<RefCell<BridgeRing>> = CsMutex::new(RefCell::new(BridgeRing {
+    bytes: Deque::new(),
+    next_seq: 0,
+}));
+
+/// Sequenza del prossimo byte, pubblicata a ogni ricezione
+static RECEIVED: Watch<CriticalSectionRawMutex, u32, BRIDGE_CLIENTS> = Watch::new();
+
+/// Comando inviato a `POST /uart`
+#[derive(serde::Deserialize)]
+pub struct UartForm {
+    pub data: heapless::String<128>,
+}
+
+/// Aggiunge i byte ricevuti dalla UART1, scartando i più vecchi se il
+/// buffer è pieno
+///
+/// # Argomenti
+/// * `bytes` - Byte appena letti
+pub fn record(bytes: &[u8]) {
+    let next_seq = critical_section::with(|cs| {
+        let mut ring = RING.borrow(cs).borrow_mut();
+        for &byte in bytes {
+            if ring.bytes.is_full() {
+                ring.bytes.pop_front();
+            }
+            let _ = ring.bytes.push_back(byte);
+        }
+        ring.next_seq = ring.next_seq.wrapping_add(bytes.len() as u32);
+        ring.next_seq
+    });
+    RECEIVED.sender().send(next_seq);
+}
+
+/// Copia i byte a partire da una sequenza
+///
+/// # Argomenti
+/// * `seq` - Sequenza del primo byte richiesto
+/// * `out` - Buffer di destinazione
+///
+/// # Ritorna
+/// * (u32, usize) - Sequenza del primo byte copiato (più avanti di `seq` se
+///   quelli richiesti sono già stati scartati) e numero di byte copiati
+fn read_from(seq: u32, out: &mut [u8]) -> (u32, usize) {
+    critical_section::with(|cs| {
+        let ring = RING.borrow(cs).borrow();
+        let first = ring.next_seq.wrapping_sub(ring.bytes.len() as u32);
+        let behind = ring.next_seq.wrapping_sub(seq) as usize;
+        let (start, skip) = if behind > ring.bytes.len() {
+            (first, 0)
+        } else {
+            (seq, ring.bytes.len() - behind)
+        };
+        let mut n = 0;
+        for (slot, &byte) in out.iter_mut().zip(ring.bytes.iter().skip(skip)) {
+            *slot = byte;
+            n += 1;
+        }
+        (start, n)
+    })
+}
+
+/// Scrive i byte ricevuti come stringa JSON, con i caratteri di controllo
+/// e i byte non ASCII in forma `\u00XX`
+pub fn escape<const N: usize>(bytes: &[u8], out: &mut heapless::String<N>) -> core::fmt::Result {
+    out.push('"').map_err(|_| core::fmt::Error)?;
+    for &byte in bytes {
+        match byte {
+            b'"' => out.write_str("\\\"")?,
+            b'\\' => out.write_str("\\\\")?,
+            b'\n' => out.write_str("\\n")?,
+            b'\r' => out.write_str("\\r")?,
+            b'\t' => out.write_str("\\t")?,
+            0x20..=0x7E => out.push(byte as char).map_err(|_| core::fmt::Error)?,
+            _ => write!(out, "\\u{:04x}", byte)?,
+        }
+    }
+    out.push('"').map_err(|_| core::fmt::Error)
+}
+
+/// Terminale sulla UART1, condiviso dai worker del web server tramite lo
+/// stato dell'applicazione
+#[derive(Clone, Copy)]
+pub struct UartBridge;
+
+impl UartBridge {
+    /// Invia dei byte sulla linea TX della UART1
+    ///
+    /// # Argomenti
+    /// * `data` - Byte da inviare
+    ///
+    /// # Ritorna
+    /// * bool - false se la UART1 non è disponibile o la trasmissione fallisce
+    pub async fn send(&self, data: &[u8]) -> bool {
+        let Some(tx) = resources::get::<SharedUartTx>() else {
+            return false;
+        };
+        let mut tx = tx.0.lock().await;
+        let result = errors::retry(ErrorKind::UartTx, async || {
+            embedded_io_async::Write::write_all(&mut *tx, data).await
+        });
+        match result.await {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("UART1: invio dal terminale web fallito: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Sorgente SSE dei byte ricevuti dalla UART1
+pub struct UartEventStream;
+
+impl EventSource for UartEventStream {
+    /// Invia i byte ricevuti al client finché la connessione resta aperta,
+    /// partendo da quelli ancora nel buffer
+    ///
+    /// # Argomenti
+    /// * `writer` - Writer degli eventi SSE
+    ///
+    /// # Ritorna
+    /// * Result<(), W::Error> - Errore di scrittura (client disconnesso)
+    async fn write_events<W: picoserve::io::Write>(
+        self,
+        mut writer: EventWriter<W>,
+    ) -> Result<(), W::Error> {
+        let Some(mut received) = RECEIVED.receiver() else {
+            return writer.write_event("error", "troppi client connessi").await;
+        };
+
+        let mut seq = critical_section::with(|cs| {
+            let ring = RING.borrow(cs).borrow();
+            ring.next_seq.wrapping_sub(ring.bytes.len() as u32)
+        });
+        loop {
+            let mut chunk = [0u8; EVENT_BYTES];
+            let (start, n) = read_from(seq, &mut chunk);
+            if start != seq {
+                let lost: heapless::String<12> =
+                    errors::fit(format_args!("{}", start.wrapping_sub(seq)));
+                writer.write_event("gap", lost.as_str()).await?;
+            }
+            if n > 0 {
+                let mut data = heapless::String::<{ 6 * EVENT_BYTES + 32 }>::new();
+                let _ = write!(data, "{{\"seq\":{},\"text\":", start);
+                let _ = escape(&chunk[..n], &mut data);
+                let _ = data.push('}');
+                writer.write_event("rx", data.as_str()).await?;
+                seq = start.wrapping_add(n as u32);
+                continue;
+            }
+            seq = start;
+            match select(received.changed(), Timer::after_secs(KEEPALIVE_SECS)).await {
+                Either::First(_) => {}
+                Either::Second(()) => writer.write_keepalive().await?,
+            }
+        }
+    }
+}
+
+/// Invia sulla UART1 i dati di un form
+///
+/// # Argomenti
+/// * `bridge` - Terminale dallo stato dell'applicazione
+/// * `form` - Form ricevuto
+/// * `lang` - Lingua della risposta
+///
+/// # Ritorna
+/// * (StatusCode, &'static str) - Esito per l'utente
+async fn send(bridge: UartBridge, form: &UartForm, lang: Lang) -> (StatusCode, &'static str) {
+    if bridge.send(form.data.as_bytes()).await {
+        (StatusCode::OK, lang.strings().uart_sent)
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            lang.strings().uart_unavailable,
+        )
+    }
+}
+
+/// Rotte del terminale: `/uart` (pagina e invio) e `/api/uart/events` (SSE)
+pub struct UartRoutes;
+
+impl Routes for UartRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                "/uart",
+                get(|lang: Lang, accept: AcceptGzip| async move { lang.strings().uart_page.html(accept) }).post(
+                    |State(bridge): State<UartBridge>,
+                     lang: Lang,
+                     Form(form): Form<UartForm>| async move {
+                        send(bridge, &form, lang).await
+                    },
+                ),
+            )
+            .route(
+                "/api/uart/events",
+                get(|| async move { EventStream(UartEventStream) }),
+            )
+    }
+}
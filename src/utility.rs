@@ -1,105 +1,456 @@
 //! Funzioni di utility per la generazione di pagine HTML
 use crate::configuration::CONFIG;
+use crate::errors::{self, ErrorKind};
 use crate::form_value::FormValue;
-use crate::sudoku::Sudoku;
+use crate::i18n::Lang;
+use crate::maintenance::RebootSchedule;
+use crate::mqtt::MqttConfig;
+use crate::request_id;
+use crate::smtp::SmtpConfig;
+use crate::system::BreakAction;
+use crate::timing::{self, SolveTiming};
+use crate::wifi::{WIFI_PROFILES, WifiProfile};
+use core::cell::RefCell;
+use core::fmt::Write;
+use critical_section::Mutex as CsMutex;
 use heapless::Vec;
-use heapless::format;
+use sudoku::{Sudoku, SudokuError};
 
-const HTML_HEADER: &str =
-    "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Sudoku Result</title></head><body>";
+const HTML_HEAD: &str =
+    "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Sudoku Result</title>";
 const HTML_FOOTER: &str = "</body></html>";
 
-#[macro_export]
-macro_rules! panic_led_loop {
-    ($control:expr) => {
-        if let Some(panic_message) = panic_persist::get_panic_message_utf8() {
-            let _ = $control.gpio_set(0, true).await;
-            log::error!("{panic_message}");
-            loop {
-                embassy_time::Timer::after_secs(5).await;
-            }
+/// Dimensione massima di un pezzo della pagina (intestazione, riga della
+/// tabella, ...): la pagina intera non ha limiti
+const CHUNK_CAPACITY: usize = 256;
+
+/// Stile della tabella della soluzione: bordi spessi tra i riquadri 3x3 e
+/// numeri dello schema inviato (classe `clue`) in grassetto e colorati
+const SOLVED_STYLE: &str = "table{border-collapse:collapse;border:3px solid}\
+    td{width:2em;height:2em;text-align:center;border:1px solid #999}\
+    td.clue{font-weight:bold;color:#06c}\
+    td:nth-child(3n){border-right:3px solid #000}\
+    tr:nth-child(3n) td{border-bottom:3px solid #000}";
+
+/// Celle provate al massimo nel controllo dell'unicità della soluzione:
+/// il conteggio gira sull'executor del core 0, insieme al web server, e oltre
+/// questo limite la pagina dice che l'unicità non è stata verificata
+const UNIQUENESS_STEPS: u32 = 20_000;
+
+/// Lunghezza massima della descrizione di un errore
+const ERROR_CAPACITY: usize = 64;
+
+/// Pagina HTML di risposta al form. Non viene mai scritta per intero in un
+/// buffer: [`write_content`](picoserve::response::Content::write_content)
+/// la genera un pezzo alla volta direttamente sul socket, così la
+/// dimensione della pagina non è limitata dalla RAM.
+pub enum HtmlPage {
+    /// Schema risolto
+    Solved {
+        /// Schema inviato, 0 per le celle vuote
+        puzzle: [[u8; 9]; 9],
+        solution: [[u8; 9]; 9],
+        /// Titolo, nella lingua della richiesta
+        title: &'static str,
+        /// Tempo impiegato dal solve (vedi timing.rs)
+        timing: SolveTiming,
+        /// Etichetta del tempo, nella lingua della richiesta
+        timing_label: &'static str,
+        /// Nota sotto il tempo (es. unicità non verificata), se presente
+        note: Option<&'static str>,
+    },
+    /// Schema non valido o senza soluzione
+    Error {
+        msg: &'static str,
+        err: heapless::String<ERROR_CAPACITY>,
+        /// ID della richiesta HTTP, per ritrovarla nei log del dispositivo
+        request_id: Option<request_id::RequestId>,
+        lang: Lang,
+    },
+}
+
+impl HtmlPage {
+    /// Genera una pagina di errore, con l'ID della richiesta HTTP in corso.
+    ///
+    /// # Argomenti
+    /// * `msg` - Messaggio di errore
+    /// * `err` - Errore da mostrare
+    /// * `lang` - Lingua della richiesta
+    ///
+    /// # Ritorna
+    /// * HtmlPage - Pagina di errore
+    pub fn error<T: core::fmt::Debug>(msg: &'static str, err: &T, lang: Lang) -> Self {
+        HtmlPage::Error {
+            msg,
+            err: errors::fit(format_args!("{err:?}")),
+            request_id: request_id::current(),
+            lang,
         }
-    };
+    }
+
+    /// Scrive un pezzo della pagina
+    ///
+    /// # Argomenti
+    /// * `index` - Indice del pezzo, da 0
+    /// * `out` - Buffer del pezzo, svuotato prima di scrivere
+    ///
+    /// # Ritorna
+    /// * Option<core::fmt::Result> - None se la pagina è già finita, errore se il
+    ///   pezzo è stato troncato
+    fn chunk(
+        &self,
+        index: usize,
+        out: &mut heapless::String<CHUNK_CAPACITY>,
+    ) -> Option<core::fmt::Result> {
+        out.clear();
+        let written = match self {
+            HtmlPage::Solved {
+                puzzle,
+                solution,
+                title,
+                timing,
+                timing_label,
+                note,
+            } => match index {
+                0 => write!(out, "{HTML_HEAD}<style>"),
+                1 => out.write_str(SOLVED_STYLE),
+                2 => write!(out, "</style></head><body><h1>{title}</h1><table>"),
+                3..=11 => {
+                    let (given, row) = (&puzzle[index - 3], &solution[index - 3]);
+                    out.write_str("<tr>")
+                        .and_then(|_| {
+                            given.iter().zip(row).try_for_each(|(&g, c)| match g {
+                                0 => write!(out, "<td>{c}</td>"),
+                                _ => write!(out, "<td class=\"clue\">{c}</td>"),
+                            })
+                        })
+                        .and_then(|_| out.write_str("</tr>"))
+                }
+                12 => write!(out, "</table><p>{timing_label}: {} µs</p>", timing.micros)
+                    .and_then(|_| match note {
+                        Some(note) => write!(out, "<p>{note}</p>"),
+                        None => Ok(()),
+                    })
+                    .and_then(|_| out.write_str(HTML_FOOTER)),
+                _ => return None,
+            },
+            HtmlPage::Error {
+                msg,
+                err,
+                request_id,
+                lang,
+            } => match (index, request_id) {
+                (0, _) => write!(out, "{HTML_HEAD}</head><body><h1>{msg}: {err}</h1>"),
+                (1, Some(id)) => write!(out, "<p>{}: {id}</p>", lang.strings().request_id_label),
+                (1, None) => Ok(()),
+                (2, _) => out.write_str(HTML_FOOTER),
+                _ => return None,
+            },
+        };
+        Some(written)
+    }
 }
 
-#[macro_export]
-macro_rules! panic_led_loop_shared {
-    ($shared_control:expr) => {
-        if let Some(panic_message) = panic_persist::get_panic_message_utf8() {
-            let mut control = $shared_control.0.lock().await;
-            let _ = control.gpio_set(0, true).await;
-            log::error!("{panic_message}");
-            loop {
-                embassy_time::Timer::after_secs(5).await;
+impl picoserve::response::Content for HtmlPage {
+    fn content_type(&self) -> &'static str {
+        "text/html"
+    }
+
+    fn content_length(&self) -> usize {
+        // I pezzi vengono generati due volte: qui per la Content-Length e poi
+        // per l'invio, sempre uguali perché la pagina non cambia
+        let mut out = heapless::String::new();
+        let mut length = 0;
+        let mut index = 0;
+        while self.chunk(index, &mut out).is_some() {
+            length += out.len();
+            index += 1;
+        }
+        length
+    }
+
+    async fn write_content<W: picoserve::io::Write>(self, mut writer: W) -> Result<(), W::Error> {
+        let mut out = heapless::String::new();
+        let mut index = 0;
+        while let Some(written) = self.chunk(index, &mut out) {
+            if written.is_err() {
+                // Un pezzo troncato ha la stessa lunghezza in content_length:
+                // la risposta resta coerente, ma va segnalato
+                errors::report(ErrorKind::Truncated);
+                log::error!(
+                    "HTML: pezzo {} della pagina oltre {} byte",
+                    index,
+                    CHUNK_CAPACITY
+                );
             }
+            writer.write_all(out.as_bytes()).await?;
+            index += 1;
         }
-    };
+        Ok(())
+    }
 }
 
-// Genera una pagina HTML di errore.
+/// Lunghezza massima di un valore di configurazione
+pub const CONFIG_VALUE_CAPACITY: usize = 64;
+
+/// Lunghezza massima del nome di una chiave di configurazione
+const CONFIG_KEY_CAPACITY: usize = 24;
+
+/// Numero massimo di valori modificati a runtime o caricati dalla flash
+const CONFIG_OVERRIDES: usize = 16;
+
+/// Valore di configurazione
+pub type ConfigValue = heapless::String<CONFIG_VALUE_CAPACITY>;
+
+type ConfigOverride = (heapless::String<CONFIG_KEY_CAPACITY>, ConfigValue);
+
+/// Valori modificati a runtime o caricati dalla flash (vedi config_store.rs):
+/// hanno la precedenza sui default di CONFIG
+static OVERRIDES: CsMutex<RefCell<Vec<ConfigOverride, CONFIG_OVERRIDES>>> =
+    CsMutex::new(RefCell::new(Vec::new()));
+
+/// Errori nella modifica della configurazione
+#[derive(Debug)]
+pub enum ConfigError {
+    UnknownKey,
+    ValueTooLong,
+    TooManyOverrides,
+    /// Valore non accettato dal setter tipizzato (vedi config_store.rs)
+    InvalidValue,
+    /// Scrittura in flash fallita (vedi config_store.rs)
+    Storage,
+    /// Archivio in flash pieno: chiave nuova oltre `PERSISTED_KEYS` (vedi config_store.rs)
+    StoreFull,
+}
+
+/// Chiavi presenti nella configurazione compilata
+///
+/// # Ritorna
+/// * impl Iterator<Item = &str> - Nomi delle chiavi, nell'ordine di CONFIG
+pub fn config_keys() -> impl Iterator<Item = &'static str> {
+    CONFIG
+        .lines()
+        .filter_map(|line| line.split_once('=').map(|(key, _)| key))
+}
+
+/// Estrae un valore dalla configurazione, considerando le modifiche a runtime
 ///
 /// # Argomenti
-/// * `msg` - Messaggio di errore
+/// * `key` - Nome della chiave, es. "WIFI_NETWORK"
 ///
 /// # Ritorna
-/// * heapless::String<1024> - Pagina HTML generata
-pub fn error_html<T: core::fmt::Debug>(msg: &str, err: &T) -> heapless::String<1024> {
-    format!(
-        "{header}<h1>{msg}: {:?}</h1>{footer}",
-        err,
-        header = HTML_HEADER,
-        footer = HTML_FOOTER
-    )
-    .unwrap_or_default()
+/// * Option<ConfigValue> - Valore corrente, None se la chiave non esiste
+pub fn get_config(key: &str) -> Option<ConfigValue> {
+    let modified = critical_section::with(|cs| {
+        OVERRIDES
+            .borrow(cs)
+            .borrow()
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.clone())
+    });
+    modified.or_else(|| {
+        CONFIG
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .and_then(|value| ConfigValue::try_from(value).ok())
+    })
 }
 
-/// Genera una tabella HTML dal risultato del Sudoku risolto.
+/// Modifica un valore della configurazione fino al riavvio. Il nuovo valore
+/// viene usato alla lettura successiva (es. al prossimo join WiFi).
 ///
 /// # Argomenti
-/// * `grid` - Riferimento alla matrice 9x9 del Sudoku risolto
-///
-/// # Ritorna
-/// * heapless::String<1024> - Tabella HTML generata
-pub fn html_table(grid: &[[u8; 9]; 9]) -> heapless::String<1024> {
-    let mut html = heapless::String::<1024>::new();
-    html.push_str(HTML_HEADER).unwrap();
-    html.push_str("<h1>Solved Sudoku</h1><table border=\"1\">")
-        .unwrap();
-    for row in grid.iter() {
-        html.push_str("<tr>").unwrap();
-        for cell in row.iter() {
-            let s: heapless::String<24> = format!("<td>{cell}</td>").unwrap_or_default();
-            html.push_str(s.as_str()).unwrap_or_default();
+/// * `key` - Nome di una chiave presente in CONFIG
+/// * `value` - Nuovo valore
+///
+/// # Ritorna
+/// * Result<(), ConfigError> - Errore se la chiave non esiste o il valore è troppo lungo
+pub fn set_config(key: &str, value: &str) -> Result<(), ConfigError> {
+    if !config_keys().any(|k| k == key) {
+        return Err(ConfigError::UnknownKey);
+    }
+    let value = ConfigValue::try_from(value).map_err(|_| ConfigError::ValueTooLong)?;
+    critical_section::with(|cs| {
+        let mut overrides = OVERRIDES.borrow(cs).borrow_mut();
+        if let Some((_, current)) = overrides.iter_mut().find(|(k, _)| k == key) {
+            *current = value;
+            return Ok(());
+        }
+        let key = heapless::String::try_from(key).map_err(|_| ConfigError::UnknownKey)?;
+        overrides
+            .push((key, value))
+            .map_err(|_| ConfigError::TooManyOverrides)
+    })?;
+    log::info!("Configurazione: {} modificato", key);
+    Ok(())
+}
+
+/// Codifica in base64 (per AUTH PLAIN di smtp.rs)
+///
+/// # Argomenti
+/// * `input` - Byte da codificare
+///
+/// # Ritorna
+/// * heapless::String<N> - Testo codificato, troncato se più lungo di N
+pub fn base64<const N: usize>(input: &[u8]) -> heapless::String<N> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = heapless::String::new();
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            let c = if i <= chunk.len() {
+                ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char
+            } else {
+                '='
+            };
+            let _ = out.push(c);
         }
-        html.push_str("</tr>").unwrap_or_default();
     }
-    html.push_str("</table>").unwrap_or_default();
-    html.push_str(HTML_FOOTER).unwrap_or_default();
-    html
+    out
+}
+
+/// Interpreta un indirizzo IPv4 scritto come "192, 168, 1, 1"
+///
+/// # Argomenti
+/// * `value` - Valore di configurazione
+///
+/// # Ritorna
+/// * Option<[u8; 4]> - Indirizzo, None se non valido
+fn parse_ipv4(value: &str) -> Option<[u8; 4]> {
+    let octets: Vec<u8, 4> = value
+        .split(',')
+        .filter_map(|s| s.trim().parse::<u8>().ok())
+        .collect();
+    (octets.len() == 4).then(|| [octets[0], octets[1], octets[2], octets[3]])
+}
+
+/// Estrae l'indirizzo del server DNS dalla configurazione.
+/// Se l'indirizzo è vuoto lo stack non risolve i nomi.
+///
+/// # Ritorna
+/// * Option<[u8; 4]> - Indirizzo IP del server DNS
+pub fn get_dns_server() -> Option<[u8; 4]> {
+    get_config("DNS_SERVER").and_then(|value| parse_ipv4(&value))
 }
 
 /// Estrae l'SSID dalla configurazione.
 ///
 /// # Ritorna
-/// * &str - SSID della rete WiFi
-pub fn get_ssid() -> &'static str {
-    CONFIG
-        .lines()
-        .find(|line| line.starts_with("WIFI_NETWORK="))
-        .map(|line| &line["WIFI_NETWORK=".len()..])
-        .unwrap_or("")
+/// * ConfigValue - SSID della rete WiFi
+pub fn get_ssid() -> ConfigValue {
+    get_config("WIFI_NETWORK").unwrap_or_default()
+}
+
+/// Estrae dalla configurazione le reti i cui client vengono respinti
+/// (vedi acl.rs). Di default nessuna.
+///
+/// # Ritorna
+/// * ConfigValue - Reti CIDR separate da virgole
+pub fn get_acl_deny() -> ConfigValue {
+    get_config("ACL_DENY").unwrap_or_default()
+}
+
+/// Estrae dalla configurazione le reti da cui sono consentite le rotte di
+/// amministrazione (vedi acl.rs). Vuota = tutte.
+///
+/// # Ritorna
+/// * ConfigValue - Reti CIDR separate da virgole
+pub fn get_acl_admin_allow() -> ConfigValue {
+    get_config("ACL_ADMIN_ALLOW").unwrap_or_default()
+}
+
+/// Estrae dalla configurazione i limiti per client del web server (vedi
+/// rate_limit.rs): connessioni aperte contemporaneamente (default 4) e
+/// nuove connessioni al minuto (default 120). 0 = nessun limite.
+///
+/// # Ritorna
+/// * (u32, u32) - Connessioni aperte e connessioni al minuto
+pub fn get_rate_limit() -> (u32, u32) {
+    let limit = |key, default| {
+        get_config(key)
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .unwrap_or(default)
+    };
+    (
+        limit("RATE_LIMIT_CONNECTIONS", 4),
+        limit("RATE_LIMIT_PER_MINUTE", 120),
+    )
+}
+
+/// Estrae dalla configurazione le credenziali HTTP (vedi auth.rs). Se
+/// l'utente è vuoto l'autenticazione è disabilitata.
+///
+/// # Ritorna
+/// * Option<(ConfigValue, ConfigValue)> - Utente e password
+pub fn get_http_auth() -> Option<(ConfigValue, ConfigValue)> {
+    let user = get_config("HTTP_AUTH_USER").filter(|value| !value.trim().is_empty())?;
+    let password = get_config("HTTP_AUTH_PASSWORD").unwrap_or_default();
+    Some((user, password))
+}
+
+/// Estrae il nome annunciato con mDNS (vedi mdns.rs), es. `picoweb` per
+/// `picoweb.local`. Vuoto = mDNS disabilitato.
+///
+/// # Ritorna
+/// * ConfigValue - Nome della pico, senza `.local`
+pub fn get_hostname() -> ConfigValue {
+    get_config("HOSTNAME").unwrap_or_default()
+}
+
+/// Estrae la password dell'access point di configurazione (vedi
+/// provisioning.rs); vuota = access point aperto
+///
+/// # Ritorna
+/// * ConfigValue - Password WPA2, almeno 8 caratteri
+pub fn get_provision_ap_password() -> ConfigValue {
+    get_config("PROVISION_AP_PASSWORD").unwrap_or_default()
+}
+
+/// Estrae il numero di join falliti di fila dopo cui la pico avvia
+/// l'access point di configurazione, di default 5 (0 = mai)
+///
+/// # Ritorna
+/// * u32 - Soglia dei join falliti
+pub fn get_provision_after_failures() -> u32 {
+    get_config("PROVISION_AFTER_FAILURES")
+        .and_then(|value| value.trim().parse::<u32>().ok())
+        .unwrap_or(5)
 }
 
 /// Estrae la password di rete dalla configurazione.
 ///
 /// # Ritorna
-/// * &str - Password della rete WiFi
-pub fn get_wifi_password() -> &'static str {
-    CONFIG
-        .lines()
-        .find(|line| line.starts_with("WIFI_PASSWORD="))
-        .map(|line| &line["WIFI_PASSWORD=".len()..])
-        .unwrap_or("")
+/// * ConfigValue - Password della rete WiFi
+pub fn get_wifi_password() -> ConfigValue {
+    get_config("WIFI_PASSWORD").unwrap_or_default()
+}
+
+/// Estrae le reti WiFi configurate (vedi wifi.rs), in ordine di priorità:
+/// `WIFI_NETWORK`, poi `WIFI_NETWORK_2` e `WIFI_NETWORK_3`, ognuna con la
+/// sua password. Le reti con SSID vuoto sono saltate.
+///
+/// # Ritorna
+/// * Vec<WifiProfile, WIFI_PROFILES> - Reti, la prima è la preferita
+pub fn get_wifi_profiles() -> Vec<WifiProfile, WIFI_PROFILES> {
+    const KEYS: [(&str, &str); WIFI_PROFILES] = [
+        ("WIFI_NETWORK", "WIFI_PASSWORD"),
+        ("WIFI_NETWORK_2", "WIFI_PASSWORD_2"),
+        ("WIFI_NETWORK_3", "WIFI_PASSWORD_3"),
+    ];
+    KEYS.iter()
+        .filter_map(|(ssid, password)| {
+            Some(WifiProfile {
+                ssid: get_config(ssid).filter(|value| !value.trim().is_empty())?,
+                password: get_config(password).unwrap_or_default(),
+            })
+        })
+        .collect()
 }
 
 /// Estrae l'indirizzo IP dalla configurazione, di default 192.168.1.115
@@ -107,33 +458,27 @@ pub fn get_wifi_password() -> &'static str {
 /// # Ritorna
 /// * [u8; 4] - Indirizzo IP
 pub fn get_ip_address() -> [u8; 4] {
-    CONFIG
-        .lines()
-        .find(|line| line.starts_with("IP_ADDRESS="))
-        .map(|line| {
-            let ip_str = &line["IP_ADDRESS=".len()..];
-            let octets: Vec<u8, 4> = ip_str
-                .split(',')
-                .filter_map(|s| s.trim().parse::<u8>().ok())
-                .collect();
-            if octets.len() == 4 {
-                [octets[0], octets[1], octets[2], octets[3]]
-            } else {
-                [192, 168, 1, 115] // Default IP
-            }
-        })
+    get_config("IP_ADDRESS")
+        .and_then(|value| parse_ipv4(&value))
         .unwrap_or([192, 168, 1, 115]) // Default IP
 }
 
+/// Estrae dalla configurazione se l'indirizzo va chiesto al DHCP
+/// (vedi wifi.rs). Di default no: indirizzo statico.
+///
+/// # Ritorna
+/// * bool - true se l'indirizzo viene assegnato dal DHCP
+pub fn get_use_dhcp() -> bool {
+    get_config("USE_DHCP").is_some_and(|value| value.trim() == "true")
+}
+
 /// Estrae la submask dalla configurazione, di default 24.
 ///
 /// # Ritorna
 /// * u8 - Subnet mask
 pub fn get_subnet_mask() -> u8 {
-    CONFIG
-        .lines()
-        .find(|line| line.starts_with("SUBNET_MASK="))
-        .and_then(|line| line["SUBNET_MASK=".len()..].trim().parse::<u8>().ok())
+    get_config("SUBNET_MASK")
+        .and_then(|value| value.trim().parse::<u8>().ok())
         .unwrap_or(24) // Default subnet mask
 }
 
@@ -142,59 +487,271 @@ pub fn get_subnet_mask() -> u8 {
 /// # Ritorna
 /// * [u8; 4] - Indirizzo IP del gateway
 pub fn get_gateway_address() -> [u8; 4] {
-    CONFIG
-        .lines()
-        .find(|line| line.starts_with("GATEWAY_ADDRESS="))
-        .map(|line| {
-            let ip_str = &line["GATEWAY_ADDRESS=".len()..];
-            let octets: Vec<u8, 4> = ip_str
+    get_config("GATEWAY_ADDRESS")
+        .and_then(|value| parse_ipv4(&value))
+        .unwrap_or([192, 168, 1, 1]) // Default Gateway
+}
+
+/// Estrae l'azione da eseguire su un break della UART1 (none, reboot, bootsel).
+/// Di default reboot.
+///
+/// # Ritorna
+/// * BreakAction - Azione configurata
+pub fn get_uart_break_action() -> BreakAction {
+    match get_config("UART_BREAK_ACTION").as_deref().map(str::trim) {
+        Some("none") => BreakAction::None,
+        Some("bootsel") => BreakAction::Bootsel,
+        _ => BreakAction::Reboot,
+    }
+}
+
+/// Estrae l'indirizzo del collector TCP dei log dalla configurazione.
+/// Se l'indirizzo è vuoto l'invio dei log via TCP è disabilitato.
+/// La porta di default è 5140.
+///
+/// # Ritorna
+/// * Option<([u8; 4], u16)> - Indirizzo IP e porta del collector
+#[cfg_attr(not(feature = "log-net"), allow(dead_code))]
+pub fn get_log_collector() -> Option<([u8; 4], u16)> {
+    let address = get_config("LOG_COLLECTOR_ADDRESS").and_then(|value| parse_ipv4(&value))?;
+    let port = get_config("LOG_COLLECTOR_PORT")
+        .and_then(|value| value.trim().parse::<u16>().ok())
+        .unwrap_or(5140);
+    Some((address, port))
+}
+
+/// Estrae l'indirizzo del server syslog dalla configurazione.
+/// Se l'indirizzo è vuoto l'invio dei log via syslog è disabilitato.
+/// La porta di default è 514.
+///
+/// # Ritorna
+/// * Option<([u8; 4], u16)> - Indirizzo IP e porta del server
+#[cfg_attr(not(feature = "log-net"), allow(dead_code))]
+pub fn get_syslog_server() -> Option<([u8; 4], u16)> {
+    let address = get_config("SYSLOG_ADDRESS").and_then(|value| parse_ipv4(&value))?;
+    let port = get_config("SYSLOG_PORT")
+        .and_then(|value| value.trim().parse::<u16>().ok())
+        .unwrap_or(514);
+    Some((address, port))
+}
+
+/// Estrae dalla configurazione se il watchdog hardware è abilitato
+/// (vedi supervisor.rs). Di default disabilitato.
+///
+/// # Ritorna
+/// * bool - true se il watchdog è abilitato
+pub fn get_watchdog_enabled() -> bool {
+    get_config("WATCHDOG_ENABLED").is_some_and(|value| value.trim() == "true")
+}
+
+/// Estrae l'orario del riavvio di manutenzione dalla configurazione
+/// (vedi maintenance.rs). Se il valore è vuoto il riavvio è disabilitato.
+///
+/// # Ritorna
+/// * Option<RebootSchedule> - Orario del riavvio
+pub fn get_maintenance_reboot() -> Option<RebootSchedule> {
+    get_config("MAINTENANCE_REBOOT").and_then(|value| RebootSchedule::parse(&value))
+}
+
+/// Estrae lo scostamento dell'ora locale da UTC, in minuti. Di default 0.
+///
+/// # Ritorna
+/// * i32 - Minuti da sommare all'ora UTC
+pub fn get_utc_offset_minutes() -> i32 {
+    get_config("UTC_OFFSET_MINUTES")
+        .and_then(|value| value.trim().parse::<i32>().ok())
+        .unwrap_or(0)
+}
+
+/// Estrae dalla configurazione il server NTP e l'intervallo tra due
+/// sincronizzazioni (vedi sntp.rs). Se il server è vuoto la sincronizzazione
+/// è disabilitata. L'intervallo di default è 60 minuti.
+///
+/// # Ritorna
+/// * Option<(ConfigValue, u64)> - Nome o indirizzo del server e intervallo in secondi
+pub fn get_ntp_server() -> Option<(ConfigValue, u64)> {
+    let server = get_config("NTP_SERVER").filter(|value| !value.trim().is_empty())?;
+    let minutes = get_config("NTP_INTERVAL_MINUTES")
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .filter(|&minutes| minutes > 0)
+        .unwrap_or(60);
+    Some((server, minutes * 60))
+}
+
+/// Estrae l'indirizzo del documento JSON letto periodicamente (vedi fetch.rs).
+/// Se l'indirizzo è vuoto la lettura è disabilitata.
+///
+/// # Ritorna
+/// * Option<ConfigValue> - Indirizzo `http://` del documento
+pub fn get_fetch_url() -> Option<ConfigValue> {
+    get_config("FETCH_URL").filter(|value| !value.trim().is_empty())
+}
+
+/// Estrae i nomi dei campi del documento da conservare, separati da virgole
+///
+/// # Ritorna
+/// * ConfigValue - Elenco dei campi
+pub fn get_fetch_keys() -> ConfigValue {
+    get_config("FETCH_KEYS").unwrap_or_default()
+}
+
+/// Estrae il periodo di lettura del documento, in secondi. Di default 300,
+/// mai meno di 10.
+///
+/// # Ritorna
+/// * u64 - Secondi tra due letture
+pub fn get_fetch_interval_secs() -> u64 {
+    get_config("FETCH_INTERVAL_SECS")
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .unwrap_or(300)
+        .max(10)
+}
+
+/// Estrae la configurazione dell'avviso via email dopo un crash (vedi smtp.rs).
+/// Se il server o il destinatario sono vuoti l'avviso è disabilitato.
+/// La porta di default è 25.
+///
+/// # Ritorna
+/// * Option<SmtpConfig> - Server, mittente, destinatario e credenziali
+pub fn get_smtp_config() -> Option<SmtpConfig> {
+    let non_empty = |key| get_config(key).filter(|value| !value.trim().is_empty());
+    Some(SmtpConfig {
+        server: non_empty("SMTP_SERVER")?,
+        port: get_config("SMTP_PORT")
+            .and_then(|value| value.trim().parse::<u16>().ok())
+            .unwrap_or(25),
+        starttls: get_config("SMTP_STARTTLS").is_some_and(|value| value.trim() == "true"),
+        from: non_empty("SMTP_FROM")
+            .unwrap_or_else(|| ConfigValue::try_from("picoweb@localhost").unwrap_or_default()),
+        to: non_empty("SMTP_TO")?,
+        user: get_config("SMTP_USER").unwrap_or_default(),
+        password: get_config("SMTP_PASSWORD").unwrap_or_default(),
+    })
+}
+
+/// Estrae la configurazione del client MQTT (vedi mqtt.rs). Se il broker è
+/// vuoto il client è disabilitato. Di default porta 1883, prefisso dei topic
+/// `picoweb` e telemetria ogni 60 secondi (mai meno di 5).
+///
+/// # Ritorna
+/// * Option<MqttConfig> - Broker, prefisso dei topic e credenziali
+pub fn get_mqtt_config() -> Option<MqttConfig> {
+    let broker = get_config("MQTT_BROKER").filter(|value| !value.trim().is_empty())?;
+    Some(MqttConfig {
+        broker,
+        port: get_config("MQTT_PORT")
+            .and_then(|value| value.trim().parse::<u16>().ok())
+            .unwrap_or(1883),
+        topic: get_config("MQTT_TOPIC")
+            .filter(|value| !value.trim().is_empty())
+            .unwrap_or_else(|| ConfigValue::try_from("picoweb").unwrap_or_default()),
+        user: get_config("MQTT_USER").unwrap_or_default(),
+        password: get_config("MQTT_PASSWORD").unwrap_or_default(),
+        interval_secs: get_config("MQTT_INTERVAL_SECS")
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .unwrap_or(60)
+            .max(5),
+    })
+}
+
+/// Estrae dalla configurazione se la shell telnet è abilitata
+/// (vedi telnet.rs). Di default disabilitata.
+///
+/// # Ritorna
+/// * bool - true se la shell telnet è abilitata
+pub fn get_telnet_enabled() -> bool {
+    get_config("TELNET_ENABLED").is_some_and(|value| value.trim() == "true")
+}
+
+/// Estrae la soglia di VSYS sotto la quale i log vengono salvati in flash
+/// (vedi brownout.rs). Di default 0, disabilitato.
+///
+/// # Ritorna
+/// * u32 - Soglia in mV, 0 se disabilitato
+pub fn get_brownout_vsys_mv() -> u32 {
+    get_config("BROWNOUT_VSYS_MV")
+        .and_then(|value| value.trim().parse::<u32>().ok())
+        .unwrap_or(0)
+}
+
+/// Estrae un elenco di GPIO separati da virgole (vedi gpio.rs), es.
+/// `GPIO_OUTPUTS=6,7`. I valori non numerici sono ignorati.
+///
+/// # Argomenti
+/// * `key` - Chiave della configurazione
+///
+/// # Ritorna
+/// * Vec<u8, 8> - Numeri dei GPIO, al massimo 8
+pub fn get_gpio_pins(key: &str) -> Vec<u8, 8> {
+    get_config(key)
+        .map(|value| {
+            value
                 .split(',')
-                .filter_map(|s| s.trim().parse::<u8>().ok())
-                .collect();
-            if octets.len() == 4 {
-                [octets[0], octets[1], octets[2], octets[3]]
-            } else {
-                [192, 168, 1, 1] // Default Gateway
-            }
+                .filter_map(|pin| pin.trim().parse::<u8>().ok())
+                .take(8)
+                .collect()
         })
-        .unwrap_or([192, 168, 1, 1]) // Default Gateway
+        .unwrap_or_default()
+}
+
+/// Estrae i pin del bus I2C del sensore ambientale (vedi i2c.rs), come
+/// `SDA,SCL`. Se il valore è vuoto il bus è disabilitato.
+///
+/// # Ritorna
+/// * Option<(u8, u8)> - GPIO di SDA e SCL
+pub fn get_i2c_pins() -> Option<(u8, u8)> {
+    let value = get_config("I2C_PINS")?;
+    let (sda, scl) = value.split_once(',')?;
+    Some((sda.trim().parse().ok()?, scl.trim().parse().ok()?))
 }
 
 /// Genera una pagina HTML di risposta al form inviato.
 ///
 /// # Argomenti
 /// * `form` - Riferimento alla struttura FormValue con i dati del form
+/// * `lang` - Lingua della richiesta
 ///
 /// # Ritorna
-/// * heapless::String<1024> - Pagina HTML generata
-pub fn generate_html(form: &FormValue) -> heapless::String<1024> {
-    let schema: heapless::String<1024> = format!(
-        "{} {} {} {} {} {} {} {} {}",
-        form.row_1,
-        form.row_2,
-        form.row_3,
-        form.row_4,
-        form.row_5,
-        form.row_6,
-        form.row_7,
-        form.row_8,
-        form.row_9
-    )
-    .unwrap_or_default();
-
+/// * HtmlPage - Pagina con la soluzione o con l'errore (anche se lo schema ha
+///   più soluzioni), generata durante l'invio
+pub async fn generate_html(form: &FormValue, lang: Lang) -> HtmlPage {
+    let strings = lang.strings();
     let mut sudoku = Sudoku::default();
-    let processing = match sudoku.parse(&schema) {
-        Ok(_) => match sudoku.solve_fast() {
-            Ok(_) => html_table(&sudoku.grid),
-            Err(e) => error_html("Error solving schema", &e),
-        },
-        Err(e) => error_html("Error parsing schema", &e),
+    if let Err(e) = sudoku.parse_grid81(&form.grid) {
+        return HtmlPage::error(strings.parse_error, &e, lang);
+    }
+    let mut solved = sudoku.clone();
+    let (result, timing) = timing::measure_solve(crate::solve::solve(&mut solved)).await;
+    log::info!(
+        "Schema elaborato in {} us (timer {})",
+        timing.micros,
+        if timing.pio { "PIO" } else { "embassy" }
+    );
+    // Uno schema sotto-vincolato non ha una risposta sola: meglio dirlo
+    // che mostrare la prima soluzione trovata. Il conteggio ha un limite di
+    // passi perché gira sul core 0 (None: unicità non verificata)
+    let solutions = match result {
+        Ok(_) => sudoku.count_solutions_within(2, UNIQUENESS_STEPS),
+        Err(_) => Some(0),
     };
-
-    form.message.borrow_mut().clear();
-    form.message
-        .borrow_mut()
-        .push_str(&processing)
-        .unwrap_or_default();
-    processing
+    match result {
+        Ok(_) if solutions.is_some_and(|n| n > 1) => {
+            HtmlPage::error(strings.solve_error, &SudokuError::MultipleSolutions, lang)
+        }
+        Ok(_) => {
+            crate::bus::publish(crate::bus::Event::PuzzleSolved {
+                source: crate::bus::SolveSource::Web,
+            });
+            HtmlPage::Solved {
+                puzzle: sudoku.grid,
+                solution: solved.grid,
+                title: strings.solved_title,
+                timing,
+                timing_label: strings.solve_time_label,
+                note: solutions.is_none().then_some(strings.uniqueness_unknown),
+            }
+        }
+        Err(e @ SudokuError::Timeout) => HtmlPage::error(strings.solve_timeout, &e, lang),
+        Err(e) => HtmlPage::error(strings.solve_error, &e, lang),
+    }
 }
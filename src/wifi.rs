@@ -0,0 +1,450 @@
+//! wifi.rs - Ciclo di vita della connessione WiFi.
+//!
+//! La connessione è gestita da [`wifi_task`] come una macchina a stati:
+//! * `Init` → `Scanning`: all'avvio;
+//! * `Scanning` → `Joining`: cerca le reti configurate (`WIFI_NETWORK` e le
+//!   reti di riserva `WIFI_NETWORK_2` e `WIFI_NETWORK_3`, vedi
+//!   [`WifiProfile`]), ne logga la potenza e le ordina per il join: prima le
+//!   reti visibili con un segnale buono, in ordine di priorità, poi quelle
+//!   con un segnale sotto [`WEAK_RSSI_DBM`], dalla più forte, infine quelle
+//!   non visibili, che potrebbero essere nascoste;
+//! * `Joining` → `DhcpWait` se il join a una delle reti riesce (in
+//!   quell'ordine, passando alla successiva a ogni fallimento), altrimenti
+//!   `Reconnecting`;
+//!   se la rete non è mai stata raggiunta dall'avvio, dopo
+//!   `PROVISION_AFTER_FAILURES` join falliti di fila `Provisioning`: la pico
+//!   diventa un access point di configurazione (vedi provisioning.rs) e ne
+//!   esce solo con un riavvio. Una rete già raggiunta che scompare (es.
+//!   router riavviato) viene invece ritentata senza limiti;
+//! * `DhcpWait` → `Up` quando lo stack ha una configurazione IPv4,
+//!   `Reconnecting` se non arriva entro [`STACK_CONFIG_TIMEOUT`]; con
+//!   `USE_DHCP=true`, se nessun server DHCP risponde entro [`DHCP_TIMEOUT`]
+//!   lo stack passa all'indirizzo statico della configurazione (fallback),
+//!   e il DHCP viene ritentato al join successivo;
+//! * `Up` → `Degraded` se il link cade;
+//! * `Degraded` → `Up` se il link torna entro [`DEGRADED_GRACE`],
+//!   altrimenti `Reconnecting`;
+//! * `Reconnecting` → `Scanning`: lascia la rete e attende un ritardo
+//!   che raddoppia a ogni ciclo, fino a [`RETRY_MAX_SECS`], e torna al
+//!   minimo quando la connessione è di nuovo `Up`.
+//!
+//! Ogni transizione è pubblicata sul bus (`Event::WifiStateChanged`).
+//! [`request_reconnect`] (es. dopo `AT+WIFI=`) forza un nuovo ciclo dagli
+//! stati di attesa. Lo stato corrente è letto dagli handler con [`state`]
+//! o [`is_up`], guida il LED (vedi fault.rs) ed è visibile su `/api/wifi`
+//! (con il ritardo del prossimo ciclo) e con il comando `status`.
+
+use crate::SharedControl;
+use crate::bus::{self, Event};
+use crate::fault::{self, Fault};
+use crate::provisioning;
+use crate::utility::{
+    ConfigValue, get_dns_server, get_gateway_address, get_ip_address, get_provision_after_failures,
+    get_subnet_mask, get_use_dhcp, get_wifi_profiles,
+};
+use crate::wifi_stats;
+use core::cell::RefCell;
+use critical_section::Mutex as CsMutex;
+use cyw43::{JoinOptions, ScanOptions};
+use embassy_futures::select::{Either, select};
+use embassy_net::{ConfigV4, DhcpConfig, Ipv4Address, Ipv4Cidr, StaticConfigV4};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+use portable_atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// Tempo massimo di attesa della configurazione dello stack dopo il join
+pub const STACK_CONFIG_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Attesa di un'offerta DHCP prima di passare all'indirizzo statico
+pub const DHCP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Tempo concesso al link per tornare da solo prima di ricollegarsi
+pub const DEGRADED_GRACE: Duration = Duration::from_secs(10);
+
+/// Ritardo iniziale e massimo tra due cicli di connessione
+const RETRY_MIN_SECS: u64 = 1;
+const RETRY_MAX_SECS: u64 = 30;
+
+/// Numero massimo di reti configurate
+pub const WIFI_PROFILES: usize = 3;
+
+/// Potenza sotto la quale una rete visibile viene tentata dopo quelle con
+/// un segnale buono, anche se ha una priorità più alta
+pub const WEAK_RSSI_DBM: i16 = -80;
+
+/// Rete WiFi configurata (vedi `get_wifi_profiles`)
+pub struct WifiProfile {
+    pub ssid: ConfigValue,
+    pub password: ConfigValue,
+}
+
+/// Reti da tentare in un ciclo di connessione, nell'ordine del join
+type Candidates = heapless::Vec<WifiProfile, WIFI_PROFILES>;
+
+/// Stato della connessione WiFi
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WifiState {
+    Init = 0,
+    Scanning = 1,
+    Joining = 2,
+    DhcpWait = 3,
+    Up = 4,
+    Degraded = 5,
+    Reconnecting = 6,
+    Provisioning = 7,
+}
+
+impl WifiState {
+    /// Nome dello stato, es. per /api/wifi e per le notifiche URC
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WifiState::Init => "init",
+            WifiState::Scanning => "scanning",
+            WifiState::Joining => "joining",
+            WifiState::DhcpWait => "dhcp_wait",
+            WifiState::Up => "up",
+            WifiState::Degraded => "degraded",
+            WifiState::Reconnecting => "reconnecting",
+            WifiState::Provisioning => "provisioning",
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => WifiState::Scanning,
+            2 => WifiState::Joining,
+            3 => WifiState::DhcpWait,
+            4 => WifiState::Up,
+            5 => WifiState::Degraded,
+            6 => WifiState::Reconnecting,
+            7 => WifiState::Provisioning,
+            _ => WifiState::Init,
+        }
+    }
+}
+
+/// Stato corrente
+static STATE: AtomicU8 = AtomicU8::new(WifiState::Init as u8);
+
+/// Richiesta di un nuovo ciclo di connessione
+static RECONNECT: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// true se il DHCP non ha risposto e lo stack usa l'indirizzo statico
+static STATIC_FALLBACK: AtomicBool = AtomicBool::new(false);
+
+/// Rete a cui la pico è associata
+static JOINED_SSID: CsMutex<RefCell<Option<ConfigValue>>> = CsMutex::new(RefCell::new(None));
+
+/// Configurazione IPv4 statica, da `IP_ADDRESS`, `SUBNET_MASK`,
+/// `GATEWAY_ADDRESS` e `DNS_SERVER`
+///
+/// # Ritorna
+/// * StaticConfigV4 - Indirizzo, gateway e server DNS
+pub fn static_config() -> StaticConfigV4 {
+    let ip = get_ip_address();
+    let gateway = get_gateway_address();
+    StaticConfigV4 {
+        address: Ipv4Cidr::new(
+            Ipv4Address::new(ip[0], ip[1], ip[2], ip[3]),
+            get_subnet_mask(),
+        ),
+        gateway: Some(Ipv4Address::new(
+            gateway[0], gateway[1], gateway[2], gateway[3],
+        )),
+        dns_servers: get_dns_server()
+            .into_iter()
+            .map(|dns| Ipv4Address::new(dns[0], dns[1], dns[2], dns[3]))
+            .collect(),
+    }
+}
+
+/// Modo di assegnazione dell'indirizzo, es. per il comando `config`
+///
+/// # Ritorna
+/// * &'static str - "dhcp", "static" oppure "static-fallback" se il DHCP non ha risposto
+pub fn addressing() -> &'static str {
+    if !get_use_dhcp() {
+        "static"
+    } else if STATIC_FALLBACK.load(Ordering::Relaxed) {
+        "static-fallback"
+    } else {
+        "dhcp"
+    }
+}
+
+/// Ritorna lo stato corrente della connessione
+///
+/// # Ritorna
+/// * WifiState - Stato corrente
+pub fn state() -> WifiState {
+    WifiState::from_u8(STATE.load(Ordering::Relaxed))
+}
+
+/// Ritorna la rete a cui la pico è associata
+///
+/// # Ritorna
+/// * Option<ConfigValue> - SSID, None fuori da un join riuscito
+pub fn joined_ssid() -> Option<ConfigValue> {
+    critical_section::with(|cs| JOINED_SSID.borrow_ref(cs).clone())
+}
+
+/// Ritorna true se la pico è collegata alla rete e ha un indirizzo
+pub fn is_up() -> bool {
+    state() == WifiState::Up
+}
+
+/// Chiede di lasciare la rete e ricollegarsi, es. dopo aver cambiato
+/// `WIFI_NETWORK` o `WIFI_PASSWORD`
+pub fn request_reconnect() {
+    RECONNECT.signal(());
+}
+
+/// Aggiorna lo stato e pubblica la transizione sul bus
+///
+/// # Argomenti
+/// * `to` - Nuovo stato
+fn transition(to: WifiState) {
+    let from = WifiState::from_u8(STATE.swap(to as u8, Ordering::Relaxed));
+    if from != to {
+        log::info!("WiFi: {} -> {}", from.as_str(), to.as_str());
+        crate::tasks::touch("wifi");
+        bus::publish(Event::WifiStateChanged { state: to });
+    }
+}
+
+#[embassy_executor::task]
+/// Task che gestisce il ciclo di vita della connessione WiFi
+///
+/// # Argomenti
+/// * `control` - Controller condiviso del CYW43
+/// * `stack` - Stack di rete
+///
+/// # Ritorna
+/// * ! - Non ritorna mai
+pub async fn wifi_task(control: SharedControl, stack: embassy_net::Stack<'static>) {
+    let mut retry_secs = RETRY_MIN_SECS;
+    // Primo tentativo del ciclo in corso, per il tempo di associazione
+    let mut cycle_started: Option<Instant> = None;
+    // Join falliti di fila, per passare all'access point di configurazione
+    let mut failed_joins: u32 = 0;
+    // true dopo il primo join riuscito: la rete configurata esiste
+    let mut ever_joined = false;
+    let mut candidates = Candidates::new();
+    let mut state = WifiState::Init;
+
+    loop {
+        state = match state {
+            WifiState::Init => WifiState::Scanning,
+            WifiState::Scanning => {
+                candidates = scan(control).await;
+                WifiState::Joining
+            }
+            WifiState::Joining => {
+                let started = *cycle_started.get_or_insert_with(Instant::now);
+                if join_any(control, &candidates).await {
+                    wifi_stats::joined(started);
+                    cycle_started = None;
+                    failed_joins = 0;
+                    ever_joined = true;
+                    // Dopo un fallback il DHCP viene ritentato a ogni nuovo join
+                    if get_use_dhcp() && STATIC_FALLBACK.swap(false, Ordering::Relaxed) {
+                        stack.set_config_v4(ConfigV4::Dhcp(DhcpConfig::default()));
+                    }
+                    WifiState::DhcpWait
+                } else {
+                    failed_joins += 1;
+                    let threshold = get_provision_after_failures();
+                    if !ever_joined && threshold > 0 && failed_joins >= threshold {
+                        log::warn!(
+                            "WiFi: {} join falliti di fila, avvio della configurazione",
+                            failed_joins
+                        );
+                        WifiState::Provisioning
+                    } else {
+                        WifiState::Reconnecting
+                    }
+                }
+            }
+            WifiState::DhcpWait => {
+                let dhcp = get_use_dhcp() && !STATIC_FALLBACK.load(Ordering::Relaxed);
+                let timeout = if dhcp {
+                    DHCP_TIMEOUT
+                } else {
+                    STACK_CONFIG_TIMEOUT
+                };
+                let config = with_timeout(timeout, stack.wait_config_up());
+                match select(config, RECONNECT.wait()).await {
+                    Either::First(Ok(())) => {
+                        fault::clear(Fault::StackConfigTimeout);
+                        WifiState::Up
+                    }
+                    Either::First(Err(_)) if dhcp => {
+                        // Nessuna offerta: l'indirizzo statico rende subito la pico raggiungibile
+                        log::warn!(
+                            "WiFi: nessuna risposta DHCP in {} s, uso l'indirizzo statico",
+                            DHCP_TIMEOUT.as_secs()
+                        );
+                        STATIC_FALLBACK.store(true, Ordering::Relaxed);
+                        stack.set_config_v4(ConfigV4::Static(static_config()));
+                        WifiState::DhcpWait
+                    }
+                    Either::First(Err(_)) => {
+                        log::warn!("WiFi: configurazione di rete non arrivata in tempo");
+                        fault::raise(Fault::StackConfigTimeout);
+                        WifiState::Reconnecting
+                    }
+                    Either::Second(()) => WifiState::Reconnecting,
+                }
+            }
+            WifiState::Up => {
+                retry_secs = RETRY_MIN_SECS;
+                wifi_stats::retry_scheduled(None);
+                match select(stack.wait_link_down(), RECONNECT.wait()).await {
+                    Either::First(()) => {
+                        wifi_stats::disconnected();
+                        WifiState::Degraded
+                    }
+                    Either::Second(()) => WifiState::Reconnecting,
+                }
+            }
+            WifiState::Degraded => {
+                let link_up = with_timeout(DEGRADED_GRACE, stack.wait_link_up());
+                match select(link_up, RECONNECT.wait()).await {
+                    Either::First(Ok(())) => {
+                        wifi_stats::reassociated();
+                        WifiState::Up
+                    }
+                    _ => WifiState::Reconnecting,
+                }
+            }
+            WifiState::Reconnecting => {
+                control.0.lock().await.leave().await;
+                critical_section::with(|cs| JOINED_SSID.replace(cs, None));
+                // Una richiesta arrivata durante il ciclo è già soddisfatta da questo
+                RECONNECT.reset();
+                wifi_stats::retry_scheduled(Some(retry_secs as u32));
+                Timer::after_secs(retry_secs).await;
+                retry_secs = (retry_secs * 2).min(RETRY_MAX_SECS);
+                WifiState::Scanning
+            }
+            WifiState::Provisioning => provisioning::run(control, stack).await,
+        };
+        transition(state);
+    }
+}
+
+/// Posizione di una rete nell'ordine del join: segnale buono, poi segnale
+/// debole dal più forte, poi non visibile; a parità conta la priorità
+///
+/// # Argomenti
+/// * `priority` - Posizione nella configurazione, 0 = preferita
+/// * `rssi` - Potenza del segnale in dBm, None se la rete non è visibile
+///
+/// # Ritorna
+/// * (u8, i16, usize) - Chiave di ordinamento crescente
+fn join_rank(priority: usize, rssi: Option<i16>) -> (u8, i16, usize) {
+    match rssi {
+        Some(rssi) if rssi >= WEAK_RSSI_DBM => (0, 0, priority),
+        Some(rssi) => (1, rssi.saturating_neg(), priority),
+        None => (2, 0, priority),
+    }
+}
+
+/// Cerca le reti configurate, ne logga la potenza del segnale e le ordina
+/// per il join
+///
+/// # Argomenti
+/// * `control` - Controller condiviso del CYW43
+///
+/// # Ritorna
+/// * Candidates - Reti configurate nell'ordine in cui tentare il join
+async fn scan(control: SharedControl) -> Candidates {
+    let profiles = get_wifi_profiles();
+    let mut rssi: [Option<i16>; WIFI_PROFILES] = [None; WIFI_PROFILES];
+    {
+        let mut control = control.0.lock().await;
+        let mut scanner = control.scan(ScanOptions::default()).await;
+        // La scansione va consumata fino in fondo per liberare il controller
+        while let Some(bss) = scanner.next().await {
+            let ssid = bss.ssid.get(..bss.ssid_len as usize);
+            for (profile, best) in profiles.iter().zip(rssi.iter_mut()) {
+                if ssid == Some(profile.ssid.as_bytes()) {
+                    *best = (*best).max(Some(bss.rssi));
+                }
+            }
+        }
+    }
+
+    let mut ranked: heapless::Vec<((u8, i16, usize), WifiProfile), WIFI_PROFILES> =
+        heapless::Vec::new();
+    for (priority, (profile, rssi)) in profiles.into_iter().zip(rssi).enumerate() {
+        match rssi {
+            Some(rssi) => log::info!("WiFi: rete '{}' trovata, rssi {} dBm", profile.ssid, rssi),
+            None => log::warn!(
+                "WiFi: rete '{}' non visibile (nascosta o fuori portata)",
+                profile.ssid
+            ),
+        }
+        let _ = ranked.push((join_rank(priority, rssi), profile));
+    }
+    ranked.sort_unstable_by_key(|(rank, _)| *rank);
+    ranked.into_iter().map(|(_, profile)| profile).collect()
+}
+
+/// Tenta il join alle reti nell'ordine dato, fino al primo che riesce
+///
+/// # Argomenti
+/// * `control` - Controller condiviso del CYW43
+/// * `candidates` - Reti ordinate da [`scan`]
+///
+/// # Ritorna
+/// * bool - true se il join a una delle reti è riuscito
+async fn join_any(control: SharedControl, candidates: &Candidates) -> bool {
+    if candidates.is_empty() {
+        log::warn!("WiFi: nessuna rete configurata (WIFI_NETWORK vuoto)");
+        return false;
+    }
+    for profile in candidates {
+        if join(control, profile).await {
+            return true;
+        }
+    }
+    false
+}
+
+/// Tenta il join a una rete
+///
+/// # Argomenti
+/// * `control` - Controller condiviso del CYW43
+/// * `profile` - Rete e password
+///
+/// # Ritorna
+/// * bool - true se il join è riuscito
+async fn join(control: SharedControl, profile: &WifiProfile) -> bool {
+    wifi_stats::join_attempt();
+    let result = control
+        .0
+        .lock()
+        .await
+        .join(&profile.ssid, JoinOptions::new(profile.password.as_bytes()))
+        .await;
+    match result {
+        Ok(()) => {
+            log::info!("WiFi: associato alla rete '{}'", profile.ssid);
+            fault::clear(Fault::WifiJoinFailed);
+            critical_section::with(|cs| JOINED_SSID.replace(cs, Some(profile.ssid.clone())));
+            bus::publish(Event::WifiJoined {
+                ssid: heapless::String::try_from(profile.ssid.as_str()).unwrap_or_default(),
+            });
+            true
+        }
+        Err(err) => {
+            log::warn!("WiFi: join alla rete '{}' fallito", profile.ssid);
+            wifi_stats::join_failed(err.status);
+            fault::raise(Fault::WifiJoinFailed);
+            false
+        }
+    }
+}
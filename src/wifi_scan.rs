@@ -0,0 +1,222 @@
+//! wifi_scan.rs - Elenco delle reti WiFi visibili.
+//!
+//! `GET /wifi/scan` avvia una scansione del CYW43 e mostra le reti trovate
+//! in una tabella HTML (SSID, canale, potenza del segnale e sicurezza);
+//! `GET /api/wifi/scan` restituisce lo stesso elenco in JSON. Le reti con
+//! lo stesso SSID (più access point) compaiono una volta sola, con il
+//! segnale più forte, e l'elenco è ordinato dal segnale più forte; le reti
+//! nascoste sono escluse. Le due rotte restano disponibili sull'access
+//! point di configurazione (vedi provisioning.rs), per scegliere la rete
+//! da inserire nel form di `/provision`.
+//!
+//! La scansione dura qualche secondo e tiene occupato il controller: nel
+//! frattempo il traffico della pico rallenta.
+
+use crate::SharedControl;
+use crate::app_props::Routes;
+use crate::app_state::AppState;
+use crate::i18n::Lang;
+use core::fmt::{self, Write};
+use cyw43::ScanOptions;
+use picoserve::extract::State;
+use picoserve::response::Json;
+use picoserve::routing::{PathRouter, get};
+
+/// Pagina HTML della scansione
+pub const SCAN_PAGE_PATH: &str = "/wifi/scan";
+
+/// Scansione in JSON
+pub const SCAN_API_PATH: &str = "/api/wifi/scan";
+
+/// Numero massimo di reti riportate
+const SCAN_RESULTS: usize = 16;
+
+/// Bit "Privacy" del campo capability di un beacon: rete protetta
+const CAPABILITY_PRIVACY: u16 = 0x0010;
+
+/// Dimensione massima di una riga della tabella (SSID con l'escape HTML)
+const ROW_CAPACITY: usize = 320;
+
+/// Rete trovata dalla scansione
+#[derive(serde::Serialize)]
+pub struct Network {
+    ssid: heapless::String<32>,
+    channel: u8,
+    rssi_dbm: i16,
+    /// true se la rete richiede una password (WEP o WPA)
+    protected: bool,
+}
+
+/// Reti trovate, dal segnale più forte
+pub type Networks = heapless::Vec<Network, SCAN_RESULTS>;
+
+/// Esegue una scansione
+///
+/// # Argomenti
+/// * `control` - Controller condiviso del CYW43
+///
+/// # Ritorna
+/// * Networks - Reti visibili, al massimo SCAN_RESULTS
+pub async fn scan(control: SharedControl) -> Networks {
+    let mut networks = Networks::new();
+    let mut control = control.0.lock().await;
+    let mut scanner = control.scan(ScanOptions::default()).await;
+    // La scansione va consumata fino in fondo per liberare il controller
+    while let Some(bss) = scanner.next().await {
+        let ssid = bss
+            .ssid
+            .get(..bss.ssid_len as usize)
+            .and_then(|ssid| core::str::from_utf8(ssid).ok())
+            .filter(|ssid| !ssid.is_empty());
+        let Some(ssid) = ssid else {
+            continue;
+        };
+        let rssi = bss.rssi;
+        match networks.iter_mut().find(|network| network.ssid == ssid) {
+            Some(network) if network.rssi_dbm >= rssi => {}
+            Some(network) => {
+                network.rssi_dbm = rssi;
+                network.channel = (bss.chanspec & 0xff) as u8;
+            }
+            None => {
+                // Oltre SCAN_RESULTS reti le altre vengono ignorate
+                let _ = networks.push(Network {
+                    ssid: heapless::String::try_from(ssid).unwrap_or_default(),
+                    channel: (bss.chanspec & 0xff) as u8,
+                    rssi_dbm: rssi,
+                    protected: bss.capability & CAPABILITY_PRIVACY != 0,
+                });
+            }
+        }
+    }
+    networks.sort_unstable_by_key(|network| core::cmp::Reverse(network.rssi_dbm));
+    networks
+}
+
+/// Testo con i caratteri speciali dell'HTML sostituiti dalle entità
+struct HtmlEscaped<'a>(&'a str);
+
+impl fmt::Display for HtmlEscaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                _ => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pagina HTML con le reti trovate, scritta una riga alla volta
+pub struct ScanPage {
+    networks: Networks,
+    lang: Lang,
+}
+
+impl ScanPage {
+    /// Scrive un pezzo della pagina
+    ///
+    /// # Argomenti
+    /// * `index` - Indice del pezzo: intestazione, una riga per rete, chiusura
+    /// * `out` - Buffer del pezzo, svuotato prima di scrivere
+    ///
+    /// # Ritorna
+    /// * Option<fmt::Result> - None se la pagina è già finita
+    fn chunk(&self, index: usize, out: &mut heapless::String<ROW_CAPACITY>) -> Option<fmt::Result> {
+        let strings = self.lang.strings();
+        let [network, channel, signal, security] = strings.wifi_scan_columns;
+        out.clear();
+        let written = match index.checked_sub(1) {
+            None => write!(
+                out,
+                "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{0}</title></head>\
+                 <body><h1>{0}</h1><table border=\"1\"><tr><th>{1}</th><th>{2}</th>\
+                 <th>{3}</th><th>{4}</th></tr>",
+                strings.wifi_scan_title, network, channel, signal, security
+            ),
+            Some(row) => match self.networks.get(row) {
+                Some(found) => write!(
+                    out,
+                    "<tr><td>{}</td><td>{}</td><td>{} dBm</td><td>{}</td></tr>",
+                    HtmlEscaped(&found.ssid),
+                    found.channel,
+                    found.rssi_dbm,
+                    if found.protected {
+                        strings.wifi_protected
+                    } else {
+                        strings.wifi_open
+                    }
+                ),
+                None if row == self.networks.len() => {
+                    let empty = if self.networks.is_empty() {
+                        strings.wifi_scan_empty
+                    } else {
+                        ""
+                    };
+                    write!(out, "</table><p>{}</p></body></html>", empty)
+                }
+                None => return None,
+            },
+        };
+        Some(written)
+    }
+}
+
+impl picoserve::response::Content for ScanPage {
+    fn content_type(&self) -> &'static str {
+        "text/html; charset=utf-8"
+    }
+
+    fn content_length(&self) -> usize {
+        let mut out = heapless::String::new();
+        let mut length = 0;
+        let mut index = 0;
+        while self.chunk(index, &mut out).is_some() {
+            length += out.len();
+            index += 1;
+        }
+        length
+    }
+
+    async fn write_content<W: picoserve::io::Write>(self, mut writer: W) -> Result<(), W::Error> {
+        let mut out = heapless::String::new();
+        let mut index = 0;
+        while self.chunk(index, &mut out).is_some() {
+            writer.write_all(out.as_bytes()).await?;
+            index += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Rotte della scansione: `/wifi/scan` (HTML) e `/api/wifi/scan` (JSON)
+pub struct WifiScanRoutes;
+
+impl Routes for WifiScanRoutes {
+    fn register(
+        router: picoserve::Router<impl PathRouter<AppState>, AppState>,
+    ) -> picoserve::Router<impl PathRouter<AppState>, AppState> {
+        router
+            .route(
+                SCAN_PAGE_PATH,
+                get(
+                    |State(control): State<SharedControl>, lang: Lang| async move {
+                        ScanPage {
+                            networks: scan(control).await,
+                            lang,
+                        }
+                    },
+                ),
+            )
+            .route(
+                SCAN_API_PATH,
+                get(
+                    |State(control): State<SharedControl>| async move { Json(scan(control).await) },
+                ),
+            )
+    }
+}
@@ -0,0 +1,150 @@
+//! wifi_stats.rs - Telemetria della connessione WiFi.
+//!
+//! Conta i tentativi di join, i fallimenti (per codice di stato del
+//! CYW43), le disconnessioni e misura il tempo impiegato per associarsi
+//! all'access point, così che i problemi di un AP instabile siano
+//! quantificabili. I contatori sono aggiornati dalla macchina a stati
+//! della connessione (vedi wifi.rs). I dati sono disponibili su `/api/wifi`, sulla pagina
+//! principale e con il comando `status` della console.
+
+use crate::utility::ConfigValue;
+use core::cell::RefCell;
+use critical_section::Mutex as CsMutex;
+use embassy_time::Instant;
+use portable_atomic::{AtomicU32, Ordering};
+
+/// Numero massimo di codici di stato distinti conteggiati
+const STATUS_SLOTS: usize = 8;
+
+static JOIN_ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+static JOIN_FAILURES: AtomicU32 = AtomicU32::new(0);
+static DISCONNECTS: AtomicU32 = AtomicU32::new(0);
+/// Tempo impiegato dall'ultima associazione riuscita (ms, 0 = mai associato)
+static TIME_TO_ASSOCIATE_MS: AtomicU32 = AtomicU32::new(0);
+/// Istante dell'ultima associazione (ms dall'avvio, 0 = non associato)
+static ASSOCIATED_AT_MS: AtomicU32 = AtomicU32::new(0);
+/// Attesa prima del prossimo ciclo di connessione (s, 0 = nessuna)
+static RETRY_DELAY_SECS: AtomicU32 = AtomicU32::new(0);
+
+/// Conteggio dei fallimenti per codice di stato
+type StatusCounts = CsMutex<RefCell<heapless::Vec<FailureCount, STATUS_SLOTS>>>;
+static FAILURE_STATUSES: StatusCounts = CsMutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Fallimenti del join con un dato codice di stato
+#[derive(Clone, Copy, serde::Serialize)]
+pub struct FailureCount {
+    status: u32,
+    count: u32,
+}
+
+/// Telemetria restituita da /api/wifi
+#[derive(serde::Serialize)]
+pub struct WifiStats {
+    /// Stato della connessione (vedi wifi.rs)
+    pub state: &'static str,
+    /// Rete a cui la pico è associata, tra quelle configurate
+    pub ssid: Option<ConfigValue>,
+    pub join_attempts: u32,
+    pub join_failures: u32,
+    pub failure_statuses: heapless::Vec<FailureCount, STATUS_SLOTS>,
+    pub disconnects: u32,
+    pub time_to_associate_ms: Option<u32>,
+    /// Secondi dall'ultima associazione (None se non associato)
+    pub associated_for_secs: Option<u32>,
+    /// Attesa dell'ultimo ciclo di riconnessione (None se connesso)
+    pub retry_delay_secs: Option<u32>,
+}
+
+/// Registra un tentativo di join
+pub fn join_attempt() {
+    JOIN_ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Registra un join fallito
+///
+/// # Argomenti
+/// * `status` - Codice di stato restituito dal CYW43
+pub fn join_failed(status: u32) {
+    let failures = JOIN_FAILURES.fetch_add(1, Ordering::Relaxed) + 1;
+    critical_section::with(|cs| {
+        let mut statuses = FAILURE_STATUSES.borrow(cs).borrow_mut();
+        match statuses.iter_mut().find(|f| f.status == status) {
+            Some(entry) => entry.count += 1,
+            // Oltre STATUS_SLOTS codici distinti conta solo il totale
+            None => {
+                let _ = statuses.push(FailureCount { status, count: 1 });
+            }
+        }
+    });
+    log::warn!(
+        "WiFi: join fallito con status={} ({} fallimenti)",
+        status,
+        failures
+    );
+}
+
+/// Registra un join riuscito
+///
+/// # Argomenti
+/// * `started` - Istante del primo tentativo
+pub fn joined(started: Instant) {
+    let elapsed = started.elapsed().as_millis() as u32;
+    TIME_TO_ASSOCIATE_MS.store(elapsed.max(1), Ordering::Relaxed);
+    ASSOCIATED_AT_MS.store(
+        (Instant::now().as_millis() as u32).max(1),
+        Ordering::Relaxed,
+    );
+    log::info!(
+        "WiFi: associato in {} ms dopo {} tentativi",
+        elapsed,
+        JOIN_ATTEMPTS.load(Ordering::Relaxed)
+    );
+}
+
+/// Registra la perdita del link
+pub fn disconnected() {
+    let disconnects = DISCONNECTS.fetch_add(1, Ordering::Relaxed) + 1;
+    ASSOCIATED_AT_MS.store(0, Ordering::Relaxed);
+    log::warn!("WiFi: link perso ({} disconnessioni)", disconnects);
+}
+
+/// Registra il ritorno del link senza un nuovo join
+pub fn reassociated() {
+    ASSOCIATED_AT_MS.store(
+        (Instant::now().as_millis() as u32).max(1),
+        Ordering::Relaxed,
+    );
+    log::info!("WiFi: link ripristinato");
+}
+
+/// Registra l'attesa prima di un nuovo ciclo di connessione
+///
+/// # Argomenti
+/// * `secs` - Secondi di attesa, None quando la connessione è tornata
+pub fn retry_scheduled(secs: Option<u32>) {
+    RETRY_DELAY_SECS.store(secs.unwrap_or(0), Ordering::Relaxed);
+    if let Some(secs) = secs {
+        log::info!("WiFi: nuovo tentativo tra {} s", secs);
+    }
+}
+
+/// Ritorna la telemetria WiFi
+///
+/// # Ritorna
+/// * WifiStats - Contatori e tempi
+pub fn report() -> WifiStats {
+    let associated_at = ASSOCIATED_AT_MS.load(Ordering::Relaxed);
+    let time_to_associate = TIME_TO_ASSOCIATE_MS.load(Ordering::Relaxed);
+    let now = Instant::now().as_millis() as u32;
+    WifiStats {
+        state: crate::wifi::state().as_str(),
+        ssid: crate::wifi::joined_ssid(),
+        join_attempts: JOIN_ATTEMPTS.load(Ordering::Relaxed),
+        join_failures: JOIN_FAILURES.load(Ordering::Relaxed),
+        failure_statuses: critical_section::with(|cs| FAILURE_STATUSES.borrow(cs).borrow().clone()),
+        disconnects: DISCONNECTS.load(Ordering::Relaxed),
+        time_to_associate_ms: (time_to_associate != 0).then_some(time_to_associate),
+        associated_for_secs: (associated_at != 0).then(|| now.wrapping_sub(associated_at) / 1000),
+        retry_delay_secs: Some(RETRY_DELAY_SECS.load(Ordering::Relaxed)).filter(|&secs| secs != 0),
+    }
+}
@@ -0,0 +1,266 @@
+//! dlx.rs - Risoluzione come problema di copertura esatta con l'algoritmo X
+//! di Knuth e i dancing links (DLX).
+//!
+//! Le 324 colonne sono i vincoli del Sudoku (ogni cella ha un numero, ogni
+//! riga, colonna e riquadro contiene ogni numero una volta); ogni riga della
+//! matrice è un candidato (cella, numero) e copre 4 colonne. I vincoli già
+//! soddisfatti dai numeri dati non entrano nella matrice, né i candidati che
+//! li violano.
+//!
+//! I nodi stanno in array a dimensione fissa con indici a 16 bit: i 4 nodi
+//! di un candidato sono consecutivi, quindi i collegamenti orizzontali sono
+//! calcolati e in memoria restano solo quelli verticali. L'intera struttura
+//! occupa circa 23 KB di stack.
+
+use crate::SudokuError;
+
+/// Colonne (vincoli) della matrice
+const COLUMNS: usize = 324;
+
+/// Nodi di intestazione: la radice (0) e una per colonna
+const HEADERS: usize = COLUMNS + 1;
+
+/// Candidati possibili (81 celle x 9 numeri)
+const CANDIDATES: usize = 729;
+
+/// Nodi totali: intestazioni più 4 nodi per candidato
+const NODES: usize = HEADERS + 4 * CANDIDATES;
+
+/// Radice della lista delle intestazioni
+const ROOT: usize = 0;
+
+struct Dlx {
+    /// Collegamenti orizzontali, solo per le intestazioni
+    left: [u16; HEADERS],
+    right: [u16; HEADERS],
+    /// Collegamenti verticali e colonna di ogni nodo
+    up: [u16; NODES],
+    down: [u16; NODES],
+    col: [u16; NODES],
+    /// Nodi presenti in ogni colonna
+    size: [u16; HEADERS],
+    /// Candidato (riga * 81 + colonna * 9 + numero - 1) di ogni riga della matrice
+    candidate: [u16; CANDIDATES],
+    rows: usize,
+}
+
+/// Colonne coperte da un candidato
+fn columns_of(row: usize, col: usize, num: usize) -> [usize; 4] {
+    let b = (row / 3) * 3 + col / 3;
+    [
+        1 + row * 9 + col,
+        1 + 81 + row * 9 + num - 1,
+        1 + 162 + col * 9 + num - 1,
+        1 + 243 + b * 9 + num - 1,
+    ]
+}
+
+impl Dlx {
+    fn new() -> Self {
+        let mut dlx = Self {
+            left: [0; HEADERS],
+            right: [0; HEADERS],
+            up: [0; NODES],
+            down: [0; NODES],
+            col: [0; NODES],
+            size: [0; HEADERS],
+            candidate: [0; CANDIDATES],
+            rows: 0,
+        };
+        for c in 0..HEADERS {
+            dlx.up[c] = c as u16;
+            dlx.down[c] = c as u16;
+            dlx.col[c] = c as u16;
+        }
+        dlx.left[ROOT] = ROOT as u16;
+        dlx.right[ROOT] = ROOT as u16;
+        dlx
+    }
+
+    /// Aggiunge una colonna in coda alla lista delle intestazioni
+    fn link_column(&mut self, c: usize) {
+        let last = self.left[ROOT];
+        self.left[c] = last;
+        self.right[c] = ROOT as u16;
+        self.right[last as usize] = c as u16;
+        self.left[ROOT] = c as u16;
+    }
+
+    /// Aggiunge un candidato come riga della matrice
+    fn add_row(&mut self, candidate: usize, columns: [usize; 4]) {
+        let base = HEADERS + 4 * self.rows;
+        for (i, &c) in columns.iter().enumerate() {
+            let node = base + i;
+            self.col[node] = c as u16;
+            self.up[node] = self.up[c];
+            self.down[node] = c as u16;
+            self.down[self.up[c] as usize] = node as u16;
+            self.up[c] = node as u16;
+            self.size[c] += 1;
+        }
+        self.candidate[self.rows] = candidate as u16;
+        self.rows += 1;
+    }
+
+    /// Nodo successivo nella stessa riga (i 4 nodi sono consecutivi)
+    fn next(node: usize) -> usize {
+        let offset = (node - HEADERS) % 4;
+        node - offset + (offset + 1) % 4
+    }
+
+    /// Nodo precedente nella stessa riga
+    fn prev(node: usize) -> usize {
+        let offset = (node - HEADERS) % 4;
+        node - offset + (offset + 3) % 4
+    }
+
+    fn cover(&mut self, c: usize) {
+        let (l, r) = (self.left[c], self.right[c]);
+        self.right[l as usize] = r;
+        self.left[r as usize] = l;
+        let mut i = self.down[c] as usize;
+        while i != c {
+            let mut j = Self::next(i);
+            while j != i {
+                let (u, d) = (self.up[j], self.down[j]);
+                self.down[u as usize] = d;
+                self.up[d as usize] = u;
+                self.size[self.col[j] as usize] -= 1;
+                j = Self::next(j);
+            }
+            i = self.down[i] as usize;
+        }
+    }
+
+    fn uncover(&mut self, c: usize) {
+        let mut i = self.up[c] as usize;
+        while i != c {
+            let mut j = Self::prev(i);
+            while j != i {
+                self.size[self.col[j] as usize] += 1;
+                self.down[self.up[j] as usize] = j as u16;
+                self.up[self.down[j] as usize] = j as u16;
+                j = Self::prev(j);
+            }
+            i = self.up[i] as usize;
+        }
+        let (l, r) = (self.left[c], self.right[c]);
+        self.right[l as usize] = c as u16;
+        self.left[r as usize] = c as u16;
+    }
+
+    /// Algoritmo X: sceglie sempre la colonna con meno nodi
+    ///
+    /// # Argomenti
+    /// * `solution` - Righe scelte finora
+    /// * `depth` - Numero di righe scelte
+    /// * `steps` - Righe che restano da provare
+    ///
+    /// # Ritorna
+    /// * Result<Option<usize>, SudokuError> - Numero di righe della soluzione,
+    ///   se esiste, oppure `Timeout` se i passi sono finiti prima
+    fn search(
+        &mut self,
+        solution: &mut [u16; 81],
+        depth: usize,
+        steps: &mut u32,
+    ) -> Result<Option<usize>, SudokuError> {
+        if self.right[ROOT] as usize == ROOT {
+            return Ok(Some(depth));
+        }
+        let mut c = self.right[ROOT] as usize;
+        let mut best = c;
+        while c != ROOT {
+            if self.size[c] < self.size[best] {
+                best = c;
+            }
+            c = self.right[c] as usize;
+        }
+        if self.size[best] == 0 {
+            return Ok(None);
+        }
+
+        self.cover(best);
+        let mut r = self.down[best] as usize;
+        while r != best {
+            *steps = steps.checked_sub(1).ok_or(SudokuError::Timeout)?;
+            solution[depth] = ((r - HEADERS) / 4) as u16;
+            let mut j = Self::next(r);
+            while j != r {
+                self.cover(self.col[j] as usize);
+                j = Self::next(j);
+            }
+            if let Some(len) = self.search(solution, depth + 1, steps)? {
+                return Ok(Some(len));
+            }
+            let mut j = Self::prev(r);
+            while j != r {
+                self.uncover(self.col[j] as usize);
+                j = Self::prev(j);
+            }
+            r = self.down[r] as usize;
+        }
+        self.uncover(best);
+        Ok(None)
+    }
+}
+
+/// Risolve lo schema sul posto, provando al massimo `max_steps` righe
+///
+/// # Argomenti
+/// * `grid` - Griglia con 0 nelle celle vuote
+/// * `max_steps` - Numero massimo di righe provate
+///
+/// # Ritorna
+/// * Result<bool, SudokuError> - true se lo schema è stato risolto, false se
+///   i numeri dati sono in conflitto o non c'è soluzione, `Timeout` se i passi
+///   sono finiti prima (la griglia resta invariata)
+pub(crate) fn solve(grid: &mut [[u8; 9]; 9], max_steps: u32) -> Result<bool, SudokuError> {
+    // Vincoli già soddisfatti dai numeri dati
+    let mut satisfied = [false; HEADERS];
+    for (row, cells) in grid.iter().enumerate() {
+        for (col, &num) in cells.iter().enumerate() {
+            let num = num as usize;
+            if num == 0 {
+                continue;
+            }
+            if num > 9 {
+                return Ok(false);
+            }
+            for c in columns_of(row, col, num) {
+                if core::mem::replace(&mut satisfied[c], true) {
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    let mut dlx = Dlx::new();
+    for (c, _) in satisfied.iter().enumerate().skip(1).filter(|(_, s)| !**s) {
+        dlx.link_column(c);
+    }
+    for (row, cells) in grid.iter().enumerate() {
+        for (col, &given) in cells.iter().enumerate() {
+            if given != 0 {
+                continue;
+            }
+            for num in 1..=9 {
+                let columns = columns_of(row, col, num);
+                if columns.iter().all(|&c| !satisfied[c]) {
+                    dlx.add_row(row * 81 + col * 9 + num - 1, columns);
+                }
+            }
+        }
+    }
+
+    let mut solution = [0u16; 81];
+    let mut steps = max_steps;
+    let Some(len) = dlx.search(&mut solution, 0, &mut steps)? else {
+        return Ok(false);
+    };
+    for &row in &solution[..len] {
+        let candidate = dlx.candidate[row as usize] as usize;
+        grid[candidate / 81][candidate / 9 % 9] = (candidate % 9 + 1) as u8;
+    }
+    Ok(true)
+}
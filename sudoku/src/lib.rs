@@ -0,0 +1,447 @@
+//! sudoku - Questo crate modella la struct Sudoku che
+//! contiene la logica per risolvere il gioco
+//! mediante backtracking e vincoli.
+//!
+//! Oltre al backtracking di [`Sudoku::solve_fast`] (che con
+//! [`Sudoku::solve_fast_with`] riporta ogni passo a una callback, es. per
+//! mostrare l'avanzamento) sono disponibili altri
+//! algoritmi (vedi [`Backend`]), selezionabili con [`Sudoku::solve_with`].
+//! [`Sudoku::count_solutions`] dice se lo schema ha una sola soluzione e
+//! [`Sudoku::solve_with_budget`] e [`Sudoku::solve_within`] limitano le
+//! iterazioni su schemi ostili.
+//! [`Solver`] risolve invece a passi, con un budget di iterazioni per
+//! passo, per non monopolizzare un executor cooperativo.
+//!
+//! Il crate è `no_std` e non dipende dall'hardware: i test girano sul
+//! computer di sviluppo con `cargo test` dalla cartella `sudoku/`.
+
+#![no_std]
+
+use core::ops::ControlFlow;
+
+mod dlx;
+mod mrv;
+mod stepper;
+
+pub use stepper::{Solver, Step};
+
+/// Errori possibili durante il parsing o la risoluzione del Sudoku
+pub enum SudokuError {
+    InvalidFormat,
+    InvalidNumber,
+    NotEnoughArguments,
+    NoSolution,
+    /// Lo schema ha più di una soluzione (vedi [`Sudoku::count_solutions`])
+    MultipleSolutions,
+    /// Budget di iterazioni esaurito prima della soluzione (vedi
+    /// [`Sudoku::solve_with_budget`] e [`Sudoku::solve_within`]) o ricerca
+    /// interrotta dalla callback di [`Sudoku::solve_fast_with`]
+    Timeout,
+}
+
+impl core::fmt::Debug for SudokuError {
+    /// Formatta l'errore per essere stampato
+    ///
+    /// # Argomenti
+    /// * `f` - Formatter
+    ///
+    /// # Ritorna
+    /// core::fmt::Result
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SudokuError::InvalidFormat => write!(f, "Invalid format"),
+            SudokuError::InvalidNumber => write!(f, "Invalid number"),
+            SudokuError::NotEnoughArguments => write!(f, "Not enough arguments"),
+            SudokuError::NoSolution => write!(f, "No solution found"),
+            SudokuError::MultipleSolutions => write!(f, "Puzzle has multiple solutions"),
+            SudokuError::Timeout => write!(f, "Step budget exhausted"),
+        }
+    }
+}
+
+/// Algoritmi di risoluzione disponibili
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Backtracking cella per cella (vedi [`Sudoku::solve_fast`])
+    Backtracking,
+    /// Backtracking con maschere di bit sulla cella più vincolata
+    BitmaskMrv,
+    /// Copertura esatta con l'algoritmo X e i dancing links
+    Dlx,
+}
+
+impl Backend {
+    /// Tutti gli algoritmi, dal più semplice
+    pub const ALL: [Backend; 3] = [Backend::Backtracking, Backend::BitmaskMrv, Backend::Dlx];
+
+    /// Nome dell'algoritmo
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Backtracking => "backtracking",
+            Backend::BitmaskMrv => "bitmask-mrv",
+            Backend::Dlx => "dlx",
+        }
+    }
+}
+
+/// Schemi difficili in formato compatto ('.' per le celle vuote), con il
+/// loro nome: gli stessi per il benchmark del firmware e della simulazione
+pub const BENCH_SCHEMAS: [(&str, &str); 3] = [
+    (
+        "ai-escargot",
+        "1....7.9..3..2...8..96..5....53..9...1..8...26....4...3......1..4......7..7...3..",
+    ),
+    (
+        "inkala-2012",
+        "8..........36......7..9.2...5...7.......457.....1...3...1....68..85...1..9....4..",
+    ),
+    (
+        "easter-monster",
+        "1.......2.9.4...5...6...7...5.9.3.......7.......85..4.7.....6...3...9.8...2.....1",
+    ),
+];
+
+/// Passo del backtracking riportato da [`Sudoku::solve_fast_with`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// Numero provato in una cella vuota
+    Placed { row: u8, col: u8, num: u8 },
+    /// Numero tolto dalla cella perché porta a un vicolo cieco
+    Backtracked { row: u8, col: u8 },
+}
+
+#[derive(Clone, Default)]
+pub struct Sudoku {
+    pub grid: [[u8; 9]; 9], // matrice 9x9
+}
+
+impl Sudoku {
+    /// Carica lo schema nel formato a righe: 9 righe separate da spazi,
+    /// ognuna con 9 numeri separati da virgole ('_' per le celle vuote)
+    ///
+    /// # Argomenti
+    /// * `schema` - Schema da leggere
+    ///
+    /// # Ritorna
+    ///
+    /// Result oppure errore
+    pub fn parse(&mut self, schema: &str) -> Result<(), SudokuError> {
+        let mut lines = schema.split(' ');
+        for row in self.grid.iter_mut() {
+            let line = lines.next().ok_or(SudokuError::NotEnoughArguments)?;
+            let mut cells = line.split(',');
+            for cell in row.iter_mut() {
+                let value = cells.next().ok_or(SudokuError::InvalidNumber)?.trim();
+                *cell = match value {
+                    "_" => 0,
+                    value => match value.parse::<u8>() {
+                        Ok(n) if n <= 9 => n,
+                        _ => return Err(SudokuError::InvalidNumber),
+                    },
+                };
+            }
+            if cells.next().is_some() {
+                return Err(SudokuError::InvalidNumber);
+            }
+        }
+        if lines.next().is_some() {
+            return Err(SudokuError::NotEnoughArguments);
+        }
+
+        Ok(())
+    }
+
+    /// Carica lo schema nel formato compatto: 81 caratteri riga per riga,
+    /// con '0', '.' o '_' per le celle vuote
+    ///
+    /// # Argomenti
+    /// * `schema` - Schema da leggere
+    ///
+    /// # Ritorna
+    ///
+    /// Result oppure errore
+    pub fn parse_compact(&mut self, schema: &str) -> Result<(), SudokuError> {
+        if schema.len() != 81 {
+            return Err(SudokuError::InvalidFormat);
+        }
+        for (i, c) in schema.bytes().enumerate() {
+            self.grid[i / 9][i % 9] = match c {
+                b'1'..=b'9' => c - b'0',
+                b'0' | b'.' | b'_' => 0,
+                _ => return Err(SudokuError::InvalidNumber),
+            };
+        }
+        Ok(())
+    }
+
+    /// Carica lo schema della griglia 9x9 del form: 81 celle riga per riga
+    /// come nel formato compatto, ignorando spazi, a capo e i separatori
+    /// '|', '-' e '+' (es. uno schema copiato da un'altra pagina)
+    ///
+    /// # Argomenti
+    /// * `schema` - Schema da leggere
+    ///
+    /// # Ritorna
+    ///
+    /// Result oppure errore; lo schema resta invariato in caso di errore
+    pub fn parse_grid81(&mut self, schema: &str) -> Result<(), SudokuError> {
+        let mut grid = [[0u8; 9]; 9];
+        let mut cells = 0;
+        let significant = schema
+            .bytes()
+            .filter(|c| !c.is_ascii_whitespace() && !matches!(c, b'|' | b'-' | b'+'));
+        for c in significant {
+            if cells == 81 {
+                return Err(SudokuError::InvalidFormat);
+            }
+            grid[cells / 9][cells % 9] = match c {
+                b'1'..=b'9' => c - b'0',
+                b'0' | b'.' | b'_' => 0,
+                _ => return Err(SudokuError::InvalidNumber),
+            };
+            cells += 1;
+        }
+        if cells < 81 {
+            return Err(SudokuError::NotEnoughArguments);
+        }
+        self.grid = grid;
+        Ok(())
+    }
+
+    /// Controlla i numeri dati prima della risoluzione
+    ///
+    /// # Ritorna
+    ///
+    /// Errore se una cella è fuori intervallo o se un numero si ripete in
+    /// una riga, colonna o riquadro
+    fn check_givens(&self) -> Result<(), SudokuError> {
+        let mut rows = [0u16; 9];
+        let mut cols = [0u16; 9];
+        let mut boxes = [0u16; 9];
+        for (row, cells) in self.grid.iter().enumerate() {
+            for (col, &num) in cells.iter().enumerate() {
+                if num > 9 {
+                    return Err(SudokuError::InvalidNumber);
+                }
+                if num == 0 {
+                    continue;
+                }
+                let bit = 1 << num;
+                let b = (row / 3) * 3 + (col / 3);
+                if (rows[row] | cols[col] | boxes[b]) & bit != 0 {
+                    return Err(SudokuError::NoSolution);
+                }
+                rows[row] |= bit;
+                cols[col] |= bit;
+                boxes[b] |= bit;
+            }
+        }
+        Ok(())
+    }
+
+    /// Risolve in modo più veloce lo schema
+    ///
+    /// # Ritorna
+    ///
+    /// Result oppure errore
+    pub fn solve_fast(&mut self) -> Result<(), SudokuError> {
+        self.solve_fast_with(|_| ControlFlow::Continue(()))
+    }
+
+    /// Risolve lo schema come [`Sudoku::solve_fast`], chiamando `on_step` a
+    /// ogni numero provato e a ogni numero tolto: applicando i passi a una
+    /// copia dello schema si segue la ricerca in tempo reale
+    ///
+    /// # Argomenti
+    /// * `on_step` - Callback dei passi; `ControlFlow::Break` ferma la ricerca
+    ///
+    /// # Ritorna
+    ///
+    /// Result oppure errore, `Timeout` se la callback ha fermato la ricerca
+    /// (lo schema resta invariato)
+    pub fn solve_fast_with(
+        &mut self,
+        mut on_step: impl FnMut(Progress) -> ControlFlow<()>,
+    ) -> Result<(), SudokuError> {
+        self.check_givens()?;
+        let mut rows = [[false; 10]; 9];
+        let mut cols = [[false; 10]; 9];
+        let mut boxes = [[false; 10]; 9];
+
+        // Inizializza i vincoli
+        for (row, grid_row) in self.grid.iter().enumerate() {
+            for (col, _) in grid_row.iter().enumerate() {
+                let num = self.grid[row][col];
+                if num != 0 {
+                    let b = (row / 3) * 3 + (col / 3);
+                    rows[row][num as usize] = true;
+                    cols[col][num as usize] = true;
+                    boxes[b][num as usize] = true;
+                }
+            }
+        }
+
+        fn solve_rec(
+            grid: &mut [[u8; 9]; 9],
+            rows: &mut [[bool; 10]; 9],
+            cols: &mut [[bool; 10]; 9],
+            boxes: &mut [[bool; 10]; 9],
+            on_step: &mut impl FnMut(Progress) -> ControlFlow<()>,
+        ) -> ControlFlow<(), bool> {
+            for row in 0..9 {
+                for col in 0..9 {
+                    if grid[row][col] == 0 {
+                        let b = (row / 3) * 3 + (col / 3);
+                        let (r, c) = (row as u8, col as u8);
+                        for num in 1..=9 {
+                            if !rows[row][num] && !cols[col][num] && !boxes[b][num] {
+                                grid[row][col] = num as u8;
+                                rows[row][num] = true;
+                                cols[col][num] = true;
+                                boxes[b][num] = true;
+                                let placed = Progress::Placed {
+                                    row: r,
+                                    col: c,
+                                    num: num as u8,
+                                };
+                                let flow = match on_step(placed) {
+                                    ControlFlow::Continue(()) => {
+                                        solve_rec(grid, rows, cols, boxes, on_step)
+                                    }
+                                    ControlFlow::Break(()) => ControlFlow::Break(()),
+                                };
+                                if flow == ControlFlow::Continue(true) {
+                                    return flow;
+                                }
+                                grid[row][col] = 0;
+                                rows[row][num] = false;
+                                cols[col][num] = false;
+                                boxes[b][num] = false;
+                                flow?;
+                                on_step(Progress::Backtracked { row: r, col: c })?;
+                            }
+                        }
+                        return ControlFlow::Continue(false);
+                    }
+                }
+            }
+            ControlFlow::Continue(true)
+        }
+
+        match solve_rec(
+            &mut self.grid,
+            &mut rows,
+            &mut cols,
+            &mut boxes,
+            &mut on_step,
+        ) {
+            ControlFlow::Continue(true) => Ok(()),
+            ControlFlow::Continue(false) => Err(SudokuError::NoSolution),
+            ControlFlow::Break(()) => Err(SudokuError::Timeout),
+        }
+    }
+
+    /// Risolve lo schema con al massimo `max_steps` iterazioni (celle scelte
+    /// o numeri provati, vedi [`Solver::step`]): su uno schema ostile la
+    /// ricerca si ferma invece di durare senza limiti
+    ///
+    /// # Argomenti
+    /// * `max_steps` - Iterazioni concesse
+    ///
+    /// # Ritorna
+    ///
+    /// Result oppure errore, `Timeout` se il budget è finito prima della
+    /// conclusione (lo schema resta invariato)
+    pub fn solve_with_budget(&mut self, max_steps: u32) -> Result<(), SudokuError> {
+        let mut solver = Solver::new(self)?;
+        match solver.step(max_steps) {
+            Step::Solved => {
+                self.grid = *solver.grid();
+                Ok(())
+            }
+            Step::NoSolution => Err(SudokuError::NoSolution),
+            Step::Pending => Err(SudokuError::Timeout),
+        }
+    }
+
+    /// Conta le soluzioni dello schema, senza modificarlo: con `limit` 2
+    /// distingue gli schemi con una sola soluzione da quelli sotto-vincolati
+    ///
+    /// # Argomenti
+    /// * `limit` - Numero di soluzioni oltre il quale la ricerca si ferma
+    ///
+    /// # Ritorna
+    /// * u8 - Soluzioni trovate, al massimo `limit` (0 se lo schema non è valido
+    ///   o non ha soluzione)
+    pub fn count_solutions(&self, limit: u8) -> u8 {
+        // u32::MAX celle provate non vengono raggiunte in pratica
+        self.count_solutions_within(limit, u32::MAX)
+            .unwrap_or(limit)
+    }
+
+    /// Come [`Sudoku::count_solutions`], ma si ferma dopo `max_steps` celle
+    /// provate: per contare le soluzioni dove una ricerca lunga non è
+    /// ammessa (es. sull'executor che serve le richieste)
+    ///
+    /// # Argomenti
+    /// * `limit` - Numero di soluzioni oltre il quale la ricerca si ferma
+    /// * `max_steps` - Numero massimo di celle provate
+    ///
+    /// # Ritorna
+    /// * Option<u8> - Soluzioni trovate, al massimo `limit` (0 se lo schema non
+    ///   è valido o non ha soluzione), None se i passi sono finiti prima
+    pub fn count_solutions_within(&self, limit: u8, max_steps: u32) -> Option<u8> {
+        if self.check_givens().is_err() {
+            return Some(0);
+        }
+        mrv::count(&self.grid, limit, max_steps)
+    }
+
+    /// Risolve lo schema con l'algoritmo indicato
+    ///
+    /// # Argomenti
+    /// * `backend` - Algoritmo da usare
+    ///
+    /// # Ritorna
+    ///
+    /// Result oppure errore
+    pub fn solve_with(&mut self, backend: Backend) -> Result<(), SudokuError> {
+        // u32::MAX passi non vengono raggiunti in pratica
+        self.solve_within(backend, u32::MAX)
+    }
+
+    /// Come [`Sudoku::solve_with`], ma si ferma dopo `max_steps` numeri
+    /// provati: su uno schema ostile la ricerca ha una durata limitata con
+    /// qualunque algoritmo
+    ///
+    /// # Argomenti
+    /// * `backend` - Algoritmo da usare
+    /// * `max_steps` - Numero massimo di numeri provati
+    ///
+    /// # Ritorna
+    ///
+    /// Result oppure errore, `Timeout` se i passi sono finiti prima della
+    /// conclusione (lo schema resta invariato)
+    pub fn solve_within(&mut self, backend: Backend, max_steps: u32) -> Result<(), SudokuError> {
+        self.check_givens()?;
+        let solved = match backend {
+            Backend::Backtracking => {
+                let mut steps = max_steps;
+                return self.solve_fast_with(|_| match steps.checked_sub(1) {
+                    Some(left) => {
+                        steps = left;
+                        ControlFlow::Continue(())
+                    }
+                    None => ControlFlow::Break(()),
+                });
+            }
+            Backend::BitmaskMrv => mrv::solve(&mut self.grid, max_steps)?,
+            Backend::Dlx => dlx::solve(&mut self.grid, max_steps)?,
+        };
+        if solved {
+            Ok(())
+        } else {
+            Err(SudokuError::NoSolution)
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! mrv.rs - Backtracking con maschere di bit e scelta della cella più
+//! vincolata (minimum remaining values).
+//!
+//! I numeri già usati in righe, colonne e riquadri sono tenuti in maschere
+//! a 16 bit (bit n = numero n): i candidati di una cella si ottengono con
+//! un OR e a ogni passo si prova la cella con meno candidati, il che taglia
+//! drasticamente l'albero di ricerca sugli schemi difficili.
+
+use crate::SudokuError;
+
+/// Bit dei numeri 1-9
+const ALL: u16 = 0b11_1111_1110;
+
+/// Vincoli dei numeri già piazzati
+struct Masks {
+    rows: [u16; 9],
+    cols: [u16; 9],
+    boxes: [u16; 9],
+}
+
+impl Masks {
+    /// Vincoli dei numeri dati, None se due numeri sono in conflitto
+    fn from_grid(grid: &[[u8; 9]; 9]) -> Option<Self> {
+        let mut masks = Masks {
+            rows: [0; 9],
+            cols: [0; 9],
+            boxes: [0; 9],
+        };
+        for (row, cells) in grid.iter().enumerate() {
+            for (col, &num) in cells.iter().enumerate() {
+                if num == 0 {
+                    continue;
+                }
+                if num > 9 || masks.candidates(row, col) & (1 << num) == 0 {
+                    return None;
+                }
+                masks.toggle(row, col, 1 << num);
+            }
+        }
+        Some(masks)
+    }
+
+    fn candidates(&self, row: usize, col: usize) -> u16 {
+        !(self.rows[row] | self.cols[col] | self.boxes[box_of(row, col)]) & ALL
+    }
+
+    fn toggle(&mut self, row: usize, col: usize, bit: u16) {
+        self.rows[row] ^= bit;
+        self.cols[col] ^= bit;
+        self.boxes[box_of(row, col)] ^= bit;
+    }
+}
+
+fn box_of(row: usize, col: usize) -> usize {
+    (row / 3) * 3 + col / 3
+}
+
+/// Risolve lo schema sul posto, provando al massimo `max_steps` celle
+///
+/// # Argomenti
+/// * `grid` - Griglia con 0 nelle celle vuote
+/// * `max_steps` - Numero massimo di celle provate
+///
+/// # Ritorna
+/// * Result<bool, SudokuError> - true se lo schema è stato risolto, false se
+///   i numeri dati sono in conflitto o non c'è soluzione, `Timeout` se i passi
+///   sono finiti prima (la griglia resta invariata)
+pub(crate) fn solve(grid: &mut [[u8; 9]; 9], max_steps: u32) -> Result<bool, SudokuError> {
+    let mut work = *grid;
+    let Some(mut masks) = Masks::from_grid(&work) else {
+        return Ok(false);
+    };
+    let mut steps = max_steps;
+    let solved = solve_rec(&mut work, &mut masks, &mut steps)?;
+    if solved {
+        *grid = work;
+    }
+    Ok(solved)
+}
+
+/// Conta le soluzioni dello schema, fermandosi a `limit` o dopo `max_steps`
+/// celle provate
+///
+/// # Argomenti
+/// * `grid` - Griglia con 0 nelle celle vuote
+/// * `limit` - Numero di soluzioni oltre il quale la ricerca si ferma
+/// * `max_steps` - Numero massimo di celle provate
+///
+/// # Ritorna
+/// * Option<u8> - Soluzioni trovate, al massimo `limit` (0 se i numeri dati
+///   sono in conflitto), None se la ricerca ha esaurito i passi prima
+pub(crate) fn count(grid: &[[u8; 9]; 9], limit: u8, max_steps: u32) -> Option<u8> {
+    let mut grid = *grid;
+    let Some(mut masks) = Masks::from_grid(&grid) else {
+        return Some(0);
+    };
+    let mut found = 0;
+    let mut steps = max_steps;
+    count_rec(&mut grid, &mut masks, limit, &mut found, &mut steps)?;
+    Some(found)
+}
+
+/// Esito della scelta della cella da provare
+enum Pick {
+    /// Nessuna cella vuota: lo schema è completo
+    Complete,
+    /// Una cella vuota senza candidati: vicolo cieco
+    DeadEnd,
+    /// Cella vuota con meno candidati e i suoi candidati
+    Cell(usize, usize, u16),
+}
+
+/// Sceglie la cella vuota con meno candidati
+fn pick(grid: &[[u8; 9]; 9], masks: &Masks) -> Pick {
+    let mut best: Option<(usize, usize, u16)> = None;
+    'search: for (row, cells) in grid.iter().enumerate() {
+        for (col, &num) in cells.iter().enumerate() {
+            if num != 0 {
+                continue;
+            }
+            let candidates = masks.candidates(row, col);
+            match candidates.count_ones() {
+                0 => return Pick::DeadEnd,
+                count if best.is_none_or(|(_, _, b)| count < b.count_ones()) => {
+                    best = Some((row, col, candidates));
+                    if count == 1 {
+                        break 'search;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    match best {
+        Some((row, col, candidates)) => Pick::Cell(row, col, candidates),
+        None => Pick::Complete,
+    }
+}
+
+fn solve_rec(
+    grid: &mut [[u8; 9]; 9],
+    masks: &mut Masks,
+    steps: &mut u32,
+) -> Result<bool, SudokuError> {
+    let (row, col, mut candidates) = match pick(grid, masks) {
+        Pick::Complete => return Ok(true),
+        Pick::DeadEnd => return Ok(false),
+        Pick::Cell(row, col, candidates) => (row, col, candidates),
+    };
+
+    while candidates != 0 {
+        *steps = steps.checked_sub(1).ok_or(SudokuError::Timeout)?;
+        let bit = candidates & candidates.wrapping_neg();
+        candidates ^= bit;
+        grid[row][col] = bit.trailing_zeros() as u8;
+        masks.toggle(row, col, bit);
+        if solve_rec(grid, masks, steps)? {
+            return Ok(true);
+        }
+        masks.toggle(row, col, bit);
+    }
+    grid[row][col] = 0;
+    Ok(false)
+}
+
+fn count_rec(
+    grid: &mut [[u8; 9]; 9],
+    masks: &mut Masks,
+    limit: u8,
+    found: &mut u8,
+    steps: &mut u32,
+) -> Option<()> {
+    let (row, col, mut candidates) = match pick(grid, masks) {
+        Pick::Complete => {
+            *found += 1;
+            return Some(());
+        }
+        Pick::DeadEnd => return Some(()),
+        Pick::Cell(row, col, candidates) => (row, col, candidates),
+    };
+    while candidates != 0 && *found < limit {
+        *steps = steps.checked_sub(1)?;
+        let bit = candidates & candidates.wrapping_neg();
+        candidates ^= bit;
+        grid[row][col] = bit.trailing_zeros() as u8;
+        masks.toggle(row, col, bit);
+        let counted = count_rec(grid, masks, limit, found, steps);
+        masks.toggle(row, col, bit);
+        counted?;
+    }
+    grid[row][col] = 0;
+    Some(())
+}
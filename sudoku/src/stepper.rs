@@ -0,0 +1,197 @@
+//! stepper.rs - Risoluzione a passi, interrompibile e ripresa a piacere.
+//!
+//! Gli altri algoritmi risolvono lo schema in un'unica chiamata, che sugli
+//! schemi difficili può durare a lungo. [`Solver`] esegue lo stesso
+//! backtracking con maschere di bit e cella più vincolata di mrv.rs, ma con
+//! uno stack esplicito al posto della ricorsione: [`Solver::step`] avanza al
+//! massimo di un numero dato di iterazioni e ritorna, così chi lo usa (es.
+//! un executor cooperativo) può cedere il controllo tra un passo e l'altro.
+
+use crate::{Sudoku, SudokuError};
+
+/// Bit dei numeri 1-9
+const ALL: u16 = 0b11_1111_1110;
+
+/// Cella in cui si stanno provando i candidati
+#[derive(Clone, Copy)]
+struct Frame {
+    row: u8,
+    col: u8,
+    /// Candidati non ancora provati
+    remaining: u16,
+    /// Bit del numero piazzato nella cella, 0 se nessuno
+    placed: u16,
+}
+
+/// Stato di avanzamento di [`Solver::step`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Budget esaurito: la ricerca riprende alla prossima chiamata
+    Pending,
+    /// Schema risolto (vedi [`Solver::grid`])
+    Solved,
+    /// Lo schema non ha soluzione
+    NoSolution,
+}
+
+/// Risolutore a passi
+pub struct Solver {
+    grid: [[u8; 9]; 9],
+    rows: [u16; 9],
+    cols: [u16; 9],
+    boxes: [u16; 9],
+    stack: heapless::Vec<Frame, 81>,
+    /// true se il prossimo passo deve scegliere una nuova cella, false se
+    /// deve provare il candidato successivo della cella in cima allo stack
+    descend: bool,
+    /// Esito, una volta concluso
+    done: Option<Step>,
+    iterations: u32,
+}
+
+fn box_of(row: usize, col: usize) -> usize {
+    (row / 3) * 3 + col / 3
+}
+
+impl Solver {
+    /// Prepara la risoluzione di uno schema
+    ///
+    /// # Argomenti
+    /// * `sudoku` - Schema da risolvere
+    ///
+    /// # Ritorna
+    ///
+    /// Result con il risolutore oppure errore se i numeri dati sono fuori
+    /// intervallo o in conflitto
+    pub fn new(sudoku: &Sudoku) -> Result<Self, SudokuError> {
+        sudoku.check_givens()?;
+        let mut solver = Solver {
+            grid: sudoku.grid,
+            rows: [0; 9],
+            cols: [0; 9],
+            boxes: [0; 9],
+            stack: heapless::Vec::new(),
+            descend: true,
+            done: None,
+            iterations: 0,
+        };
+        for (row, cells) in sudoku.grid.iter().enumerate() {
+            for (col, &num) in cells.iter().enumerate() {
+                if num != 0 {
+                    solver.toggle(row, col, 1 << num);
+                }
+            }
+        }
+        Ok(solver)
+    }
+
+    fn candidates(&self, row: usize, col: usize) -> u16 {
+        !(self.rows[row] | self.cols[col] | self.boxes[box_of(row, col)]) & ALL
+    }
+
+    fn toggle(&mut self, row: usize, col: usize, bit: u16) {
+        self.rows[row] ^= bit;
+        self.cols[col] ^= bit;
+        self.boxes[box_of(row, col)] ^= bit;
+    }
+
+    /// Cella vuota con meno candidati
+    ///
+    /// # Ritorna
+    /// * Option<Option<Frame>> - None se lo schema è completo, Some(None) se
+    ///   una cella vuota non ha candidati
+    fn best_cell(&self) -> Option<Option<Frame>> {
+        let mut best: Option<Frame> = None;
+        for (row, cells) in self.grid.iter().enumerate() {
+            for (col, &num) in cells.iter().enumerate() {
+                if num != 0 {
+                    continue;
+                }
+                let candidates = self.candidates(row, col);
+                match candidates.count_ones() {
+                    0 => return Some(None),
+                    count if best.is_none_or(|b| count < b.remaining.count_ones()) => {
+                        best = Some(Frame {
+                            row: row as u8,
+                            col: col as u8,
+                            remaining: candidates,
+                            placed: 0,
+                        });
+                        if count == 1 {
+                            return Some(best);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        best.map(Some)
+    }
+
+    /// Avanza nella ricerca
+    ///
+    /// # Argomenti
+    /// * `budget` - Numero massimo di iterazioni (celle scelte o numeri provati)
+    ///
+    /// # Ritorna
+    /// * Step - Pending se il budget è finito prima della conclusione
+    pub fn step(&mut self, budget: u32) -> Step {
+        if let Some(done) = self.done {
+            return done;
+        }
+        for _ in 0..budget {
+            self.iterations = self.iterations.saturating_add(1);
+            if self.descend {
+                match self.best_cell() {
+                    None => return *self.done.insert(Step::Solved),
+                    // Nessun candidato: si torna alla cella precedente
+                    Some(None) => self.descend = false,
+                    Some(Some(frame)) => {
+                        // Al massimo una cella per ogni cella vuota: non può fallire
+                        let _ = self.stack.push(frame);
+                        self.descend = false;
+                    }
+                }
+                if self.stack.is_empty() {
+                    return *self.done.insert(Step::NoSolution);
+                }
+                continue;
+            }
+
+            let Some(mut frame) = self.stack.pop() else {
+                return *self.done.insert(Step::NoSolution);
+            };
+            let (row, col) = (frame.row as usize, frame.col as usize);
+            if frame.placed != 0 {
+                self.toggle(row, col, frame.placed);
+                self.grid[row][col] = 0;
+                frame.placed = 0;
+            }
+            if frame.remaining == 0 {
+                // Candidati esauriti: si torna alla cella precedente
+                if self.stack.is_empty() {
+                    return *self.done.insert(Step::NoSolution);
+                }
+                continue;
+            }
+            let bit = frame.remaining & frame.remaining.wrapping_neg();
+            frame.remaining ^= bit;
+            frame.placed = bit;
+            self.grid[row][col] = bit.trailing_zeros() as u8;
+            self.toggle(row, col, bit);
+            let _ = self.stack.push(frame);
+            self.descend = true;
+        }
+        Step::Pending
+    }
+
+    /// Griglia corrente: la soluzione dopo [`Step::Solved`]
+    pub fn grid(&self) -> &[[u8; 9]; 9] {
+        &self.grid
+    }
+
+    /// Iterazioni eseguite dall'inizio
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+}
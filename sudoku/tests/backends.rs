@@ -0,0 +1,142 @@
+//! Test degli algoritmi di risoluzione: tutti devono trovare la stessa
+//! soluzione sugli schemi con soluzione unica.
+
+use sudoku::{BENCH_SCHEMAS, Backend, Sudoku, SudokuError};
+
+/// Schemi difficili in formato compatto ('.' per le celle vuote)
+const HARD: [&str; 3] = [
+    // AI Escargot
+    "1....7.9..3..2...8..96..5....53..9...1..8...26....4...3......1..4......7..7...3..",
+    // Arto Inkala, 2012
+    "8..........36......7..9.2...5...7.......457.....1...3...1....68..85...1..9....4..",
+    // Easter Monster
+    "1.......2.9.4...5...6...7...5.9.3.......7.......85..4.7.....6...3...9.8...2.....1",
+];
+
+fn grid81(schema: &str) -> Sudoku {
+    let mut sudoku = Sudoku::default();
+    for (i, c) in schema.bytes().enumerate() {
+        sudoku.grid[i / 9][i % 9] = if c == b'.' { 0 } else { c - b'0' };
+    }
+    sudoku
+}
+
+/// Controlla che la soluzione sia completa, valida e rispetti i numeri dati
+fn assert_solves(schema: &Sudoku, solved: &Sudoku) {
+    for i in 0..9 {
+        let (top, left) = (i / 3 * 3, i % 3 * 3);
+        let mut row = [false; 10];
+        let mut col = [false; 10];
+        let mut block = [false; 10];
+        for k in 0..9 {
+            for (seen, n) in [
+                (&mut row, solved.grid[i][k]),
+                (&mut col, solved.grid[k][i]),
+                (&mut block, solved.grid[top + k / 3][left + k % 3]),
+            ] {
+                assert!((1..=9).contains(&n));
+                assert!(!std::mem::replace(&mut seen[n as usize], true));
+            }
+        }
+    }
+    for (given, cell) in schema
+        .grid
+        .iter()
+        .flatten()
+        .zip(solved.grid.iter().flatten())
+    {
+        assert!(*given == 0 || given == cell);
+    }
+}
+
+#[test]
+fn backends_agree_on_hard_schemas() {
+    for schema in HARD {
+        let schema = grid81(schema);
+        let mut reference: Option<[[u8; 9]; 9]> = None;
+        for backend in Backend::ALL {
+            let mut sudoku = schema.clone();
+            sudoku.solve_with(backend).unwrap();
+            assert_solves(&schema, &sudoku);
+            match reference {
+                Some(grid) => assert_eq!(grid, sudoku.grid, "{}", backend.name()),
+                None => reference = Some(sudoku.grid),
+            }
+        }
+    }
+}
+
+#[test]
+fn backends_fill_empty_grid() {
+    for backend in Backend::ALL {
+        let mut sudoku = Sudoku::default();
+        sudoku.solve_with(backend).unwrap();
+        assert_solves(&Sudoku::default(), &sudoku);
+    }
+}
+
+#[test]
+fn backends_reject_conflicting_givens() {
+    for backend in Backend::ALL {
+        let mut sudoku = Sudoku::default();
+        sudoku.grid[0][0] = 4;
+        sudoku.grid[8][0] = 4;
+        assert!(matches!(
+            sudoku.solve_with(backend),
+            Err(SudokuError::NoSolution)
+        ));
+    }
+}
+
+#[test]
+fn backends_reject_out_of_range_numbers() {
+    for backend in Backend::ALL {
+        let mut sudoku = Sudoku::default();
+        sudoku.grid[4][4] = 10;
+        assert!(matches!(
+            sudoku.solve_with(backend),
+            Err(SudokuError::InvalidNumber)
+        ));
+    }
+}
+
+#[test]
+fn backends_report_unsolvable_schema() {
+    // Riga 0 senza posto per il 9: il 9 è escluso da tutte le celle libere
+    let mut sudoku = Sudoku::default();
+    sudoku.grid[0] = [1, 2, 3, 4, 5, 6, 7, 8, 0];
+    sudoku.grid[1][8] = 9;
+    for backend in Backend::ALL {
+        let mut attempt = sudoku.clone();
+        assert!(matches!(
+            attempt.solve_with(backend),
+            Err(SudokuError::NoSolution)
+        ));
+    }
+}
+
+#[test]
+fn bench_schemas_parse_and_solve_within_budget() {
+    for (name, schema) in BENCH_SCHEMAS {
+        let mut parsed = Sudoku::default();
+        assert!(parsed.parse_compact(schema).is_ok(), "{name}");
+        for backend in Backend::ALL {
+            let mut sudoku = parsed.clone();
+            assert!(sudoku.solve_within(backend, 1_000_000).is_ok(), "{name}");
+            assert_solves(&parsed, &sudoku);
+        }
+    }
+}
+
+#[test]
+fn backends_stop_when_budget_is_exhausted() {
+    let schema = grid81(HARD[0]);
+    for backend in Backend::ALL {
+        let mut sudoku = schema.clone();
+        assert!(matches!(
+            sudoku.solve_within(backend, 3),
+            Err(SudokuError::Timeout)
+        ));
+        assert_eq!(sudoku.grid, schema.grid, "{}", backend.name());
+    }
+}
@@ -0,0 +1,148 @@
+//! Test di robustezza dei parser e dei risolutori su input arbitrari:
+//! nessun input (testo o griglia) deve mandare in panic il crate, che sul
+//! dispositivo riceve schemi dal web, dalla console e dal Bluetooth.
+//! Quando l'input è accettato, il risultato deve essere coerente: i parser
+//! producono solo numeri 0-9 e rileggono quello che rappresentano, i
+//! risolutori restituiscono solo soluzioni valide che rispettano i numeri dati.
+
+use proptest::prelude::*;
+use sudoku::{Backend, Solver, Step, Sudoku, SudokuError};
+
+/// Controlla che un gruppo di nove celle contenga le cifre 1-9
+fn is_complete(mut cells: impl Iterator<Item = u8>) -> bool {
+    let mut seen = [false; 10];
+    cells.all(|n| (1..=9).contains(&n) && !std::mem::replace(&mut seen[n as usize], true))
+}
+
+/// Controlla che righe, colonne e riquadri contengano ciascuno le cifre 1-9
+fn is_valid_solution(grid: &[[u8; 9]; 9]) -> bool {
+    (0..9).all(|i| {
+        let (top, left) = (i / 3 * 3, i % 3 * 3);
+        is_complete((0..9).map(|c| grid[i][c]))
+            && is_complete((0..9).map(|r| grid[r][i]))
+            && is_complete((0..9).map(|k| grid[top + k / 3][left + k % 3]))
+    })
+}
+
+/// Rappresenta una griglia nel formato compatto ('.' per le celle vuote)
+fn to_compact(grid: &[[u8; 9]; 9]) -> String {
+    grid.iter()
+        .flatten()
+        .map(|&n| match n {
+            0 => '.',
+            n => (b'0' + n) as char,
+        })
+        .collect()
+}
+
+/// Celle con prevalenza di vuote, qualche numero e, di rado, valori fuori
+/// intervallo (la griglia è un campo pubblico: può contenere di tutto)
+fn cell() -> impl Strategy<Value = u8> {
+    prop_oneof![
+        12 => Just(0u8),
+        4 => 1..=9u8,
+        1 => 10..=u8::MAX,
+    ]
+}
+
+fn grid_of(cell: impl Strategy<Value = u8>) -> impl Strategy<Value = [[u8; 9]; 9]> {
+    prop::collection::vec(cell, 81).prop_map(|cells| {
+        let mut grid = [[0; 9]; 9];
+        for (i, n) in cells.into_iter().enumerate() {
+            grid[i / 9][i % 9] = n;
+        }
+        grid
+    })
+}
+
+/// Testo che somiglia a uno schema: cifre, separatori e qualche intruso
+fn schema_like() -> impl Strategy<Value = String> {
+    "[0-9_., x-]{0,200}"
+}
+
+proptest! {
+    #[test]
+    fn parse_never_panics(schema in any::<String>()) {
+        let _ = Sudoku::default().parse(&schema);
+    }
+
+    #[test]
+    fn parse_schema_like_never_panics(schema in schema_like()) {
+        let mut sudoku = Sudoku::default();
+        if sudoku.parse(&schema).is_ok() {
+            prop_assert!(sudoku.grid.iter().flatten().all(|&n| n <= 9));
+        }
+    }
+
+    #[test]
+    fn parse_compact_never_panics(schema in any::<String>()) {
+        let _ = Sudoku::default().parse_compact(&schema);
+    }
+
+    #[test]
+    fn parse_compact_schema_like_never_panics(schema in "[0-9._x]{75,85}") {
+        let mut sudoku = Sudoku::default();
+        if sudoku.parse_compact(&schema).is_ok() {
+            prop_assert_eq!(schema.len(), 81);
+            prop_assert!(sudoku.grid.iter().flatten().all(|&n| n <= 9));
+        }
+    }
+
+    #[test]
+    fn parse_grid81_schema_like_never_panics(schema in "[0-9._x |+\n-]{70,200}") {
+        let mut sudoku = Sudoku::default();
+        if sudoku.parse_grid81(&schema).is_ok() {
+            prop_assert!(sudoku.grid.iter().flatten().all(|&n| n <= 9));
+        }
+    }
+
+    #[test]
+    fn parse_compact_round_trips(grid in grid_of(0..=9u8)) {
+        let mut sudoku = Sudoku::default();
+        prop_assert!(sudoku.parse_compact(&to_compact(&grid)).is_ok());
+        prop_assert_eq!(sudoku.grid, grid);
+    }
+
+    #[test]
+    fn solvers_never_panic_and_only_return_valid_solutions(grid in grid_of(cell())) {
+        for backend in Backend::ALL {
+            let mut sudoku = Sudoku { grid };
+            match sudoku.solve_with(backend) {
+                Ok(()) => {
+                    prop_assert!(is_valid_solution(&sudoku.grid), "{}", backend.name());
+                    for (given, cell) in grid.iter().flatten().zip(sudoku.grid.iter().flatten()) {
+                        prop_assert!(*given == 0 || given == cell, "{}", backend.name());
+                    }
+                }
+                Err(SudokuError::InvalidNumber) => {
+                    prop_assert!(grid.iter().flatten().any(|&n| n > 9));
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn stepper_never_panics_and_agrees_with_mrv(grid in grid_of(cell()), budget in 1..500u32) {
+        let mut reference = Sudoku { grid };
+        let expected = reference.solve_with(Backend::BitmaskMrv);
+        match (Solver::new(&Sudoku { grid }), expected) {
+            (Ok(mut solver), expected) => {
+                let step = loop {
+                    match solver.step(budget) {
+                        Step::Pending => {}
+                        done => break done,
+                    }
+                };
+                match expected {
+                    Ok(()) => {
+                        prop_assert_eq!(step, Step::Solved);
+                        prop_assert_eq!(*solver.grid(), reference.grid);
+                    }
+                    Err(_) => prop_assert_eq!(step, Step::NoSolution),
+                }
+            }
+            (Err(_), expected) => prop_assert!(expected.is_err()),
+        }
+    }
+}
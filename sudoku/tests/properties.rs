@@ -0,0 +1,98 @@
+//! Test di proprietà del risolutore: schemi ottenuti svuotando celle a
+//! caso di una griglia valida devono avere sempre una soluzione valida
+//! che rispetta i numeri dati.
+
+use proptest::prelude::*;
+use sudoku::Sudoku;
+
+/// Griglia completa valida ottenuta permutando le cifre dello schema
+/// canonico `(3 * riga + riga / 3 + colonna) % 9`
+fn solved_grid(digits: &[u8]) -> [[u8; 9]; 9] {
+    let mut grid = [[0; 9]; 9];
+    for (row, cells) in grid.iter_mut().enumerate() {
+        for (col, cell) in cells.iter_mut().enumerate() {
+            *cell = digits[(3 * row + row / 3 + col) % 9];
+        }
+    }
+    grid
+}
+
+/// Controlla che un gruppo di nove celle contenga le cifre 1-9
+fn is_complete(mut cells: impl Iterator<Item = u8>) -> bool {
+    let mut seen = [false; 10];
+    cells.all(|n| (1..=9).contains(&n) && !std::mem::replace(&mut seen[n as usize], true))
+}
+
+/// Controlla che righe, colonne e riquadri contengano ciascuno le cifre 1-9
+fn is_valid_solution(grid: &[[u8; 9]; 9]) -> bool {
+    (0..9).all(|i| {
+        let (top, left) = (i / 3 * 3, i % 3 * 3);
+        is_complete((0..9).map(|c| grid[i][c]))
+            && is_complete((0..9).map(|r| grid[r][i]))
+            && is_complete((0..9).map(|k| grid[top + k / 3][left + k % 3]))
+    })
+}
+
+/// Rappresenta una griglia nel formato a righe (`_` per le celle vuote)
+fn to_schema(grid: &[[u8; 9]; 9]) -> String {
+    grid.iter()
+        .map(|row| {
+            row.iter()
+                .map(|&n| match n {
+                    0 => "_".to_string(),
+                    n => n.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn digits() -> impl Strategy<Value = Vec<u8>> {
+    Just((1..=9).collect::<Vec<u8>>()).prop_shuffle()
+}
+
+proptest! {
+    #[test]
+    fn canonical_grids_are_valid(digits in digits()) {
+        prop_assert!(is_valid_solution(&solved_grid(&digits)));
+    }
+
+    #[test]
+    fn solution_is_valid_and_keeps_givens(
+        digits in digits(),
+        blanks in prop::collection::vec(any::<bool>(), 81),
+    ) {
+        let mut puzzle = solved_grid(&digits);
+        for (i, blank) in blanks.iter().enumerate() {
+            if *blank {
+                puzzle[i / 9][i % 9] = 0;
+            }
+        }
+        let mut sudoku = Sudoku { grid: puzzle };
+        prop_assert!(sudoku.solve_fast().is_ok());
+        prop_assert!(is_valid_solution(&sudoku.grid));
+        for (row, cells) in puzzle.iter().enumerate() {
+            for (col, &given) in cells.iter().enumerate() {
+                prop_assert!(given == 0 || sudoku.grid[row][col] == given);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_round_trips_form_schema(
+        digits in digits(),
+        blanks in prop::collection::vec(any::<bool>(), 81),
+    ) {
+        let mut grid = solved_grid(&digits);
+        for (i, blank) in blanks.iter().enumerate() {
+            if *blank {
+                grid[i / 9][i % 9] = 0;
+            }
+        }
+        let mut sudoku = Sudoku::default();
+        prop_assert!(sudoku.parse(&to_schema(&grid)).is_ok());
+        prop_assert_eq!(sudoku.grid, grid);
+    }
+}
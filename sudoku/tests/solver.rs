@@ -0,0 +1,243 @@
+//! Test del parser e del risolutore su schemi noti.
+
+use core::ops::ControlFlow;
+use sudoku::{Progress, Sudoku, SudokuError};
+
+/// Schema di esempio nel formato a righe (righe separate da spazi)
+const SCHEMA: &str = "5,3,_,_,7,_,_,_,_ 6,_,_,1,9,5,_,_,_ _,9,8,_,_,_,_,6,_ \
+                      8,_,_,_,6,_,_,_,3 4,_,_,8,_,3,_,_,1 7,_,_,_,2,_,_,_,6 \
+                      _,6,_,_,_,_,2,8,_ _,_,_,4,1,9,_,_,5 _,_,_,_,8,_,_,7,9";
+
+const SOLUTION: [[u8; 9]; 9] = [
+    [5, 3, 4, 6, 7, 8, 9, 1, 2],
+    [6, 7, 2, 1, 9, 5, 3, 4, 8],
+    [1, 9, 8, 3, 4, 2, 5, 6, 7],
+    [8, 5, 9, 7, 6, 1, 4, 2, 3],
+    [4, 2, 6, 8, 5, 3, 7, 9, 1],
+    [7, 1, 3, 9, 2, 4, 8, 5, 6],
+    [9, 6, 1, 5, 3, 7, 2, 8, 4],
+    [2, 8, 7, 4, 1, 9, 6, 3, 5],
+    [3, 4, 5, 2, 8, 6, 1, 7, 9],
+];
+
+#[test]
+fn parse_reads_givens_and_blanks() {
+    let mut sudoku = Sudoku::default();
+    sudoku.parse(SCHEMA).unwrap();
+    assert_eq!(sudoku.grid[0], [5, 3, 0, 0, 7, 0, 0, 0, 0]);
+    assert_eq!(sudoku.grid[8], [0, 0, 0, 0, 8, 0, 0, 7, 9]);
+}
+
+#[test]
+fn solve_fast_finds_known_solution() {
+    let mut sudoku = Sudoku::default();
+    sudoku.parse(SCHEMA).unwrap();
+    sudoku.solve_fast().unwrap();
+    assert_eq!(sudoku.grid, SOLUTION);
+}
+
+#[test]
+fn solve_fast_fills_empty_grid() {
+    let mut sudoku = Sudoku::default();
+    sudoku.solve_fast().unwrap();
+    assert!(sudoku.grid.iter().flatten().all(|&n| (1..=9).contains(&n)));
+}
+
+#[test]
+fn solve_fast_reports_unsolvable_schema() {
+    let mut sudoku = Sudoku::default();
+    // Il primo riquadro non può contenere un 9: riga 0 e colonne 1 e 2 lo
+    // escludono dalle celle libere, le altre sono occupate
+    sudoku.grid[0] = [0, 0, 0, 9, 0, 0, 0, 0, 0];
+    sudoku.grid[1] = [1, 0, 0, 0, 0, 0, 0, 0, 0];
+    sudoku.grid[2] = [2, 3, 4, 0, 0, 0, 0, 0, 0];
+    sudoku.grid[3][1] = 9;
+    sudoku.grid[4][2] = 9;
+    sudoku.grid[5][0] = 5;
+    sudoku.grid[1][1] = 6;
+    sudoku.grid[1][2] = 7;
+    assert!(matches!(sudoku.solve_fast(), Err(SudokuError::NoSolution)));
+}
+
+#[test]
+fn solve_fast_with_reports_replayable_steps() {
+    let mut sudoku = Sudoku::default();
+    sudoku.parse(SCHEMA).unwrap();
+    // Applicando i passi a una copia dello schema si ottiene la soluzione
+    let mut replay = sudoku.grid;
+    let mut backtracks = 0;
+    sudoku
+        .solve_fast_with(|step| {
+            match step {
+                Progress::Placed { row, col, num } => {
+                    assert_eq!(replay[row as usize][col as usize], 0);
+                    replay[row as usize][col as usize] = num;
+                }
+                Progress::Backtracked { row, col } => {
+                    assert_ne!(replay[row as usize][col as usize], 0);
+                    replay[row as usize][col as usize] = 0;
+                    backtracks += 1;
+                }
+            }
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+    assert_eq!(sudoku.grid, SOLUTION);
+    assert_eq!(replay, SOLUTION);
+    assert!(backtracks > 0);
+}
+
+#[test]
+fn solve_fast_with_stops_on_break() {
+    let mut sudoku = Sudoku::default();
+    sudoku.parse(SCHEMA).unwrap();
+    let puzzle = sudoku.grid;
+    let mut steps = 0;
+    let result = sudoku.solve_fast_with(|_| {
+        steps += 1;
+        if steps == 100 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    assert!(matches!(result, Err(SudokuError::Timeout)));
+    assert_eq!(steps, 100);
+    // La ricerca interrotta lascia lo schema invariato
+    assert_eq!(sudoku.grid, puzzle);
+}
+
+#[test]
+fn parse_rejects_wrong_row_count() {
+    let mut sudoku = Sudoku::default();
+    assert!(matches!(
+        sudoku.parse("1,2,3,4,5,6,7,8,9"),
+        Err(SudokuError::NotEnoughArguments)
+    ));
+}
+
+#[test]
+fn parse_rejects_short_row() {
+    let schema = SCHEMA.replacen("5,3,_,", "5,3,", 1);
+    let mut sudoku = Sudoku::default();
+    assert!(matches!(
+        sudoku.parse(&schema),
+        Err(SudokuError::InvalidNumber)
+    ));
+}
+
+#[test]
+fn parse_rejects_non_numeric_cell() {
+    let schema = SCHEMA.replacen('5', "x", 1);
+    let mut sudoku = Sudoku::default();
+    assert!(matches!(
+        sudoku.parse(&schema),
+        Err(SudokuError::InvalidNumber)
+    ));
+}
+
+#[test]
+fn parse_rejects_out_of_range_number() {
+    let schema = SCHEMA.replacen('5', "10", 1);
+    let mut sudoku = Sudoku::default();
+    assert!(matches!(
+        sudoku.parse(&schema),
+        Err(SudokuError::InvalidNumber)
+    ));
+}
+
+#[test]
+fn parse_rejects_extra_rows_and_cells() {
+    let mut sudoku = Sudoku::default();
+    assert!(matches!(
+        sudoku.parse(&format!("{SCHEMA} 1,2,3")),
+        Err(SudokuError::NotEnoughArguments)
+    ));
+    let schema = SCHEMA.replacen("5,3,", "5,3,3,3,3,3,3,3,3,3,3,3,3,3,3,3,3,3,3,3,", 1);
+    assert!(matches!(
+        sudoku.parse(&schema),
+        Err(SudokuError::InvalidNumber)
+    ));
+}
+
+#[test]
+fn parse_compact_reads_givens_and_blanks() {
+    let mut sudoku = Sudoku::default();
+    let schema = format!("53..7.._0{}", "0".repeat(72));
+    sudoku.parse_compact(&schema).unwrap();
+    assert_eq!(sudoku.grid[0], [5, 3, 0, 0, 7, 0, 0, 0, 0]);
+    assert!(matches!(
+        sudoku.parse_compact(&schema[1..]),
+        Err(SudokuError::InvalidFormat)
+    ));
+}
+
+#[test]
+fn parse_grid81_ignores_separators() {
+    let mut sudoku = Sudoku::default();
+    let schema = format!("53. .7. ...|\n6.. 195 ...\n---+---\n{}", "0".repeat(63));
+    sudoku.parse_grid81(&schema).unwrap();
+    assert_eq!(sudoku.grid[0], [5, 3, 0, 0, 7, 0, 0, 0, 0]);
+    assert_eq!(sudoku.grid[1], [6, 0, 0, 1, 9, 5, 0, 0, 0]);
+    // Celle mancanti, in più o non valide: lo schema resta invariato
+    let short = &schema[..schema.len() - 1];
+    assert!(matches!(
+        sudoku.parse_grid81(short),
+        Err(SudokuError::NotEnoughArguments)
+    ));
+    assert!(matches!(
+        sudoku.parse_grid81(&format!("{schema}0")),
+        Err(SudokuError::InvalidFormat)
+    ));
+    assert!(matches!(
+        sudoku.parse_grid81(&schema.replacen('5', "x", 1)),
+        Err(SudokuError::InvalidNumber)
+    ));
+    assert_eq!(sudoku.grid[0], [5, 3, 0, 0, 7, 0, 0, 0, 0]);
+}
+
+#[test]
+fn count_solutions_finds_unique_solution() {
+    let mut sudoku = Sudoku::default();
+    sudoku.parse(SCHEMA).unwrap();
+    let puzzle = sudoku.grid;
+    assert_eq!(sudoku.count_solutions(2), 1);
+    // Lo schema non viene modificato
+    assert_eq!(sudoku.grid, puzzle);
+}
+
+#[test]
+fn count_solutions_stops_at_limit() {
+    let sudoku = Sudoku::default();
+    assert_eq!(sudoku.count_solutions(1), 1);
+    assert_eq!(sudoku.count_solutions(2), 2);
+    assert_eq!(sudoku.count_solutions(5), 5);
+}
+
+#[test]
+fn count_solutions_detects_under_constrained_schema() {
+    // Solo la prima riga: molte soluzioni, la ricerca si ferma alla seconda
+    let mut grid = [[0; 9]; 9];
+    grid[0] = SOLUTION[0];
+    let sudoku = Sudoku { grid };
+    assert_eq!(sudoku.count_solutions(2), 2);
+}
+
+#[test]
+fn count_solutions_within_reports_exhausted_budget() {
+    let sudoku = Sudoku::default();
+    assert_eq!(sudoku.count_solutions_within(2, 10), None);
+    assert_eq!(sudoku.count_solutions_within(2, 1000), Some(2));
+
+    let mut sudoku = Sudoku::default();
+    sudoku.parse(SCHEMA).unwrap();
+    assert_eq!(sudoku.count_solutions_within(2, 10_000), Some(1));
+}
+
+#[test]
+fn count_solutions_is_zero_for_conflicting_givens() {
+    let mut grid = [[0; 9]; 9];
+    grid[0][0] = 5;
+    grid[0][8] = 5;
+    assert_eq!(Sudoku { grid }.count_solutions(2), 0);
+}
@@ -0,0 +1,102 @@
+//! Test della risoluzione a passi: qualunque budget deve portare alla
+//! stessa soluzione degli altri algoritmi.
+
+use sudoku::{Backend, Solver, Step, Sudoku, SudokuError};
+
+/// AI Escargot in formato compatto
+const ESCARGOT: &str =
+    "1....7.9..3..2...8..96..5....53..9...1..8...26....4...3......1..4......7..7...3..";
+
+fn escargot() -> Sudoku {
+    let mut sudoku = Sudoku::default();
+    sudoku.parse_compact(ESCARGOT).unwrap();
+    sudoku
+}
+
+/// Risolve a passi con il budget dato, contando i passi
+fn run(solver: &mut Solver, budget: u32) -> (Step, u32) {
+    let mut steps = 1;
+    loop {
+        match solver.step(budget) {
+            Step::Pending => steps += 1,
+            done => return (done, steps),
+        }
+    }
+}
+
+#[test]
+fn stepper_matches_other_backends_for_any_budget() {
+    let mut reference = escargot();
+    reference.solve_with(Backend::BitmaskMrv).unwrap();
+    for budget in [1, 7, 100, u32::MAX] {
+        let mut solver = Solver::new(&escargot()).unwrap();
+        assert_eq!(run(&mut solver, budget).0, Step::Solved, "budget {budget}");
+        assert_eq!(*solver.grid(), reference.grid, "budget {budget}");
+    }
+}
+
+#[test]
+fn stepper_yields_when_budget_runs_out() {
+    let mut solver = Solver::new(&escargot()).unwrap();
+    assert_eq!(solver.step(10), Step::Pending);
+    assert_eq!(solver.iterations(), 10);
+    let (step, steps) = run(&mut solver, 10);
+    assert_eq!(step, Step::Solved);
+    assert!(steps > 1);
+    // Una volta concluso l'esito non cambia
+    assert_eq!(solver.step(10), Step::Solved);
+}
+
+#[test]
+fn stepper_solves_complete_and_empty_grids() {
+    let mut solver = Solver::new(&Sudoku::default()).unwrap();
+    assert_eq!(run(&mut solver, 50).0, Step::Solved);
+    let solved = Sudoku {
+        grid: *solver.grid(),
+    };
+    let mut again = Solver::new(&solved).unwrap();
+    assert_eq!(again.step(1), Step::Solved);
+    assert_eq!(*again.grid(), solved.grid);
+}
+
+#[test]
+fn stepper_reports_unsolvable_schema() {
+    let mut sudoku = Sudoku::default();
+    sudoku.grid[0] = [1, 2, 3, 4, 5, 6, 7, 8, 0];
+    sudoku.grid[1][8] = 9;
+    let mut solver = Solver::new(&sudoku).unwrap();
+    assert_eq!(run(&mut solver, 3).0, Step::NoSolution);
+}
+
+#[test]
+fn stepper_rejects_invalid_givens() {
+    let mut sudoku = Sudoku::default();
+    sudoku.grid[0][0] = 4;
+    sudoku.grid[0][8] = 4;
+    assert!(matches!(Solver::new(&sudoku), Err(SudokuError::NoSolution)));
+    sudoku.grid[0][8] = 10;
+    assert!(matches!(
+        Solver::new(&sudoku),
+        Err(SudokuError::InvalidNumber)
+    ));
+}
+
+#[test]
+fn solve_with_budget_times_out_on_small_budget() {
+    let mut sudoku = escargot();
+    let puzzle = sudoku.grid;
+    assert!(matches!(
+        sudoku.solve_with_budget(10),
+        Err(SudokuError::Timeout)
+    ));
+    assert_eq!(sudoku.grid, puzzle);
+}
+
+#[test]
+fn solve_with_budget_solves_within_budget() {
+    let mut reference = escargot();
+    reference.solve_with(Backend::BitmaskMrv).unwrap();
+    let mut sudoku = escargot();
+    sudoku.solve_with_budget(u32::MAX).unwrap();
+    assert_eq!(sudoku.grid, reference.grid);
+}